@@ -0,0 +1,191 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! What the flush and compaction threads do when a task fails: halt writes until the error is cleared, retry with
+//! backoff, or let a user-supplied [`BackgroundErrorHandler`] decide.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, bail};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::lsm_error::{self, Error};
+use crate::lsm_storage::{LsmStorageInner, MiniLsm};
+
+/// A task run by the background threads.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BackgroundTask {
+    Flush,
+    Compaction,
+    ManifestRotation,
+}
+
+impl fmt::Display for BackgroundTask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackgroundTask::Flush => write!(f, "flush"),
+            BackgroundTask::Compaction => write!(f, "compaction"),
+            BackgroundTask::ManifestRotation => write!(f, "manifest rotation"),
+        }
+    }
+}
+
+/// What to do about a failed background task.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BackgroundErrorAction {
+    /// Reject writes with `Error::Poisoned`, and stop running background tasks, until
+    /// `MiniLsm::clear_background_error` is called.
+    Halt,
+    /// Run the task again once this long has passed.
+    RetryAfter(Duration),
+}
+
+/// The action taken for a failed background task when no [`BackgroundErrorHandler`] is set.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BackgroundErrorPolicy {
+    #[default]
+    HaltWrites,
+    /// Retry after `initial_backoff`, doubling the wait after each failure in a row up to `max_backoff`, and halt
+    /// writes once the task has failed `max_retries` more times.
+    Retry {
+        max_retries: u32,
+        initial_backoff: Duration,
+        max_backoff: Duration,
+    },
+}
+
+impl BackgroundErrorPolicy {
+    /// The action for the `failures`-th failure in a row of a task, counting from 1.
+    pub fn action(&self, failures: u32) -> BackgroundErrorAction {
+        match *self {
+            BackgroundErrorPolicy::HaltWrites => BackgroundErrorAction::Halt,
+            BackgroundErrorPolicy::Retry { max_retries, .. } if failures > max_retries => {
+                BackgroundErrorAction::Halt
+            }
+            BackgroundErrorPolicy::Retry {
+                initial_backoff,
+                max_backoff,
+                ..
+            } => BackgroundErrorAction::RetryAfter(
+                initial_backoff
+                    .saturating_mul(1 << (failures - 1).min(31))
+                    .min(max_backoff),
+            ),
+        }
+    }
+}
+
+/// Decides what to do about a failed background task in place of `LsmStorageOptions::background_error_policy`, e.g.,
+/// to alert an operator, or to retry errors known to be transient and halt on the others.
+pub trait BackgroundErrorHandler: Send + Sync {
+    /// `failures` counts the failures in a row of `task`, including this one. Called on the background thread, so it
+    /// must not wait for background work such as a flush.
+    fn on_background_error(
+        &self,
+        task: BackgroundTask,
+        error: &Error,
+        failures: u32,
+    ) -> BackgroundErrorAction;
+}
+
+#[derive(Default)]
+pub(crate) struct BackgroundErrors {
+    handler: Mutex<Option<Arc<dyn BackgroundErrorHandler>>>,
+    /// The error that halted writes.
+    halted: Mutex<Option<String>>,
+    /// The failures in a row of each task, and when it may run again.
+    failures: Mutex<HashMap<BackgroundTask, (u32, Instant)>>,
+}
+
+impl LsmStorageInner {
+    /// Fails with `Error::Poisoned` if a background error has halted writes.
+    pub(crate) fn check_background_error(&self) -> Result<()> {
+        if let Some(msg) = self.background_errors.halted.lock().as_ref() {
+            bail!(Error::Poisoned(msg.clone()));
+        }
+        Ok(())
+    }
+
+    /// Run `task` from a background thread, unless writes are halted or the task is backing off after a failure, and
+    /// handle its error according to the handler or the policy.
+    pub(crate) fn run_background_task(
+        &self,
+        task: BackgroundTask,
+        run: impl FnOnce() -> Result<()>,
+    ) {
+        let errors = &self.background_errors;
+        if errors.halted.lock().is_some() {
+            return;
+        }
+        if let Some((_, retry_at)) = errors.failures.lock().get(&task)
+            && Instant::now() < *retry_at
+        {
+            return;
+        }
+        let error = match run() {
+            Ok(()) => {
+                errors.failures.lock().remove(&task);
+                return;
+            }
+            Err(e) => lsm_error::Error::from(e),
+        };
+        let failures = errors
+            .failures
+            .lock()
+            .get(&task)
+            .map_or(1, |(failures, _)| failures + 1);
+        let handler = errors.handler.lock().clone();
+        let action = match handler {
+            Some(handler) => handler.on_background_error(task, &error, failures),
+            None => self.options().background_error_policy.action(failures),
+        };
+        match action {
+            BackgroundErrorAction::Halt => {
+                eprintln!("{task} failed, halting writes: {error}");
+                *errors.halted.lock() = Some(format!("background {task} failed: {error}"));
+                errors.failures.lock().remove(&task);
+            }
+            BackgroundErrorAction::RetryAfter(backoff) => {
+                eprintln!("{task} failed, retrying in {backoff:?}: {error}");
+                errors
+                    .failures
+                    .lock()
+                    .insert(task, (failures, Instant::now() + backoff));
+            }
+        }
+    }
+}
+
+impl MiniLsm {
+    /// Let `handler` decide what to do about failed background tasks from now on, instead of
+    /// `LsmStorageOptions::background_error_policy`.
+    pub fn set_background_error_handler(&self, handler: Arc<dyn BackgroundErrorHandler>) {
+        *self.inner.background_errors.handler.lock() = Some(handler);
+    }
+
+    /// The error that halted writes, if any.
+    pub fn background_error(&self) -> Option<String> {
+        self.inner.background_errors.halted.lock().clone()
+    }
+
+    /// Accept writes and run background tasks again after a background error halted them, e.g., once the disk has
+    /// been freed up. Returns the error that was cleared.
+    pub fn clear_background_error(&self) -> Option<String> {
+        self.inner.background_errors.halted.lock().take()
+    }
+}
@@ -0,0 +1,444 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A YCSB-style workload driver for the storage engine.
+//!
+//! The benchmark runs in two phases: a load phase that inserts `record_count` keys, and a run phase where each
+//! thread issues a mix of reads and updates over the loaded key space following the configured key distribution.
+//!
+//! With `--read-scaling`, the run phase instead measures read-only throughput with 1, 2, 4, ... up to `threads` reader
+//! threads, while a writer keeps updating the key space as fast as it can, to check that readers scale with cores.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use clap::{Parser, ValueEnum};
+use mini_lsm_mvcc::background_error::BackgroundErrorPolicy;
+use mini_lsm_mvcc::block::DEFAULT_RESTART_INTERVAL;
+use mini_lsm_mvcc::compact::{
+    CompactionOptions, LazyLeveledCompactionOptions, LeveledCompactionOptions,
+    SimpleLeveledCompactionOptions, TieredCompactionOptions,
+};
+use mini_lsm_mvcc::file_system::FileSystemOptions;
+use mini_lsm_mvcc::lsm_storage::{LsmStorageOptions, MAX_KEY_VALUE_SIZE, MiniLsm};
+use mini_lsm_mvcc::mvcc::ts_provider::TsProviderOptions;
+use rand::distributions::Alphanumeric;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+#[derive(Debug, Clone, ValueEnum)]
+enum CompactionStrategy {
+    Simple,
+    Leveled,
+    Tiered,
+    LazyLeveled,
+    None,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum KeyDistribution {
+    Uniform,
+    Zipfian,
+}
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[arg(long, default_value = "bench.db")]
+    path: PathBuf,
+    #[arg(long, default_value = "leveled")]
+    compaction: CompactionStrategy,
+    #[arg(long)]
+    enable_wal: bool,
+    /// Number of keys inserted in the load phase.
+    #[arg(long, default_value = "100000")]
+    record_count: u64,
+    /// Total number of operations issued in the run phase, split across all threads.
+    #[arg(long, default_value = "100000")]
+    operation_count: u64,
+    /// Percentage of operations in the run phase that are reads; the rest are updates.
+    #[arg(long, default_value = "50")]
+    read_percent: u32,
+    #[arg(long, default_value = "uniform")]
+    distribution: KeyDistribution,
+    /// The skew of the zipfian distribution. Larger values make the hot keys hotter.
+    #[arg(long, default_value = "0.99")]
+    zipfian_theta: f64,
+    #[arg(long, default_value = "100")]
+    value_size: usize,
+    #[arg(long, default_value = "4")]
+    threads: usize,
+    /// Skip the load phase and run against an existing database.
+    #[arg(long)]
+    skip_load: bool,
+    #[arg(long, default_value = "0")]
+    seed: u64,
+    /// Measure how read throughput scales with the number of readers under a concurrent writer.
+    #[arg(long)]
+    read_scaling: bool,
+}
+
+/// Generates zipfian-distributed item numbers in `[0, items)`, following the algorithm used by YCSB (Gray et al.,
+/// "Quickly Generating Billion-Record Synthetic Databases"). The output is scrambled so that the popular items are
+/// spread across the key space instead of clustering at the beginning.
+struct ZipfianGenerator {
+    items: u64,
+    theta: f64,
+    alpha: f64,
+    zetan: f64,
+    eta: f64,
+}
+
+impl ZipfianGenerator {
+    fn zeta(n: u64, theta: f64) -> f64 {
+        (1..=n).map(|i| 1.0 / (i as f64).powf(theta)).sum()
+    }
+
+    fn new(items: u64, theta: f64) -> Self {
+        assert!(items > 0, "zipfian generator needs at least one item");
+        assert!(
+            theta > 0.0 && theta < 1.0,
+            "zipfian theta must be in (0, 1)"
+        );
+        let zetan = Self::zeta(items, theta);
+        let zeta2theta = Self::zeta(2, theta);
+        let alpha = 1.0 / (1.0 - theta);
+        let eta = (1.0 - (2.0 / items as f64).powf(1.0 - theta)) / (1.0 - zeta2theta / zetan);
+        Self {
+            items,
+            theta,
+            alpha,
+            zetan,
+            eta,
+        }
+    }
+
+    fn next(&self, rng: &mut impl Rng) -> u64 {
+        let u: f64 = rng.r#gen();
+        let uz = u * self.zetan;
+        let rank = if uz < 1.0 {
+            0
+        } else if uz < 1.0 + 0.5f64.powf(self.theta) {
+            1
+        } else {
+            ((self.items as f64) * (self.eta * u - self.eta + 1.0).powf(self.alpha)) as u64
+        };
+        farmhash::hash64(&rank.min(self.items - 1).to_le_bytes()) % self.items
+    }
+}
+
+enum KeyChooser {
+    Uniform(u64),
+    Zipfian(ZipfianGenerator),
+}
+
+impl KeyChooser {
+    fn next(&self, rng: &mut impl Rng) -> u64 {
+        match self {
+            KeyChooser::Uniform(items) => rng.gen_range(0..*items),
+            KeyChooser::Zipfian(generator) => generator.next(rng),
+        }
+    }
+}
+
+fn key_of(id: u64) -> Vec<u8> {
+    format!("user{:012}", id).into_bytes()
+}
+
+fn random_value(rng: &mut impl Rng, size: usize) -> Vec<u8> {
+    rng.sample_iter(&Alphanumeric).take(size.max(1)).collect()
+}
+
+/// Latencies (in nanoseconds) collected by a single worker.
+#[derive(Default)]
+struct Latencies {
+    reads: Vec<u64>,
+    writes: Vec<u64>,
+}
+
+impl Latencies {
+    fn merge(&mut self, other: Latencies) {
+        self.reads.extend(other.reads);
+        self.writes.extend(other.writes);
+    }
+}
+
+fn percentile(sorted: &[u64], p: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let idx = ((p / 100.0) * (sorted.len() - 1) as f64).round() as usize;
+    sorted[idx]
+}
+
+fn report(name: &str, latencies: &mut [u64], elapsed: Duration) {
+    if latencies.is_empty() {
+        return;
+    }
+    latencies.sort_unstable();
+    let avg = latencies.iter().sum::<u64>() / latencies.len() as u64;
+    println!(
+        "[{name}] ops={} throughput={:.0} ops/s avg={:.1}us p50={:.1}us p95={:.1}us p99={:.1}us p999={:.1}us max={:.1}us",
+        latencies.len(),
+        latencies.len() as f64 / elapsed.as_secs_f64(),
+        avg as f64 / 1000.0,
+        percentile(latencies, 50.0) as f64 / 1000.0,
+        percentile(latencies, 95.0) as f64 / 1000.0,
+        percentile(latencies, 99.0) as f64 / 1000.0,
+        percentile(latencies, 99.9) as f64 / 1000.0,
+        *latencies.last().unwrap() as f64 / 1000.0,
+    );
+}
+
+/// Split `total` operations across `threads` workers, running `f(thread_id, num_ops)` on each of them.
+fn run_parallel(
+    threads: usize,
+    total: u64,
+    f: impl Fn(usize, u64) -> Result<Latencies> + Sync,
+) -> Result<(Latencies, Duration)> {
+    let threads = threads.max(1);
+    let start = Instant::now();
+    let results = std::thread::scope(|s| {
+        let handles = (0..threads)
+            .map(|thread_id| {
+                let f = &f;
+                let num_ops =
+                    total / threads as u64 + u64::from((thread_id as u64) < total % threads as u64);
+                s.spawn(move || f(thread_id, num_ops))
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|h| h.join().expect("benchmark worker panicked"))
+            .collect::<Vec<_>>()
+    });
+    let elapsed = start.elapsed();
+    let mut latencies = Latencies::default();
+    for result in results {
+        latencies.merge(result?);
+    }
+    Ok((latencies, elapsed))
+}
+
+fn load(lsm: &Arc<MiniLsm>, args: &Args) -> Result<()> {
+    let threads = args.threads.max(1) as u64;
+    let (mut latencies, elapsed) =
+        run_parallel(args.threads, args.record_count, |thread_id, _| {
+            let mut rng = StdRng::seed_from_u64(args.seed.wrapping_add(thread_id as u64));
+            let mut latencies = Latencies::default();
+            // Interleave the key space across threads so that every key is loaded exactly once.
+            for id in (thread_id as u64..args.record_count).step_by(threads as usize) {
+                let value = random_value(&mut rng, args.value_size);
+                let start = Instant::now();
+                lsm.put(&key_of(id), &value)?;
+                latencies.writes.push(start.elapsed().as_nanos() as u64);
+            }
+            Ok(latencies)
+        })?;
+    println!("load phase finished in {:.3}s", elapsed.as_secs_f64());
+    report("LOAD", &mut latencies.writes, elapsed);
+    Ok(())
+}
+
+fn run(lsm: &Arc<MiniLsm>, args: &Args) -> Result<()> {
+    let chooser = match args.distribution {
+        KeyDistribution::Uniform => KeyChooser::Uniform(args.record_count.max(1)),
+        KeyDistribution::Zipfian => KeyChooser::Zipfian(ZipfianGenerator::new(
+            args.record_count.max(1),
+            args.zipfian_theta,
+        )),
+    };
+    let (mut latencies, elapsed) =
+        run_parallel(args.threads, args.operation_count, |thread_id, num_ops| {
+            let mut rng = StdRng::seed_from_u64(
+                args.seed
+                    .wrapping_add(args.threads as u64)
+                    .wrapping_add(thread_id as u64),
+            );
+            let mut latencies = Latencies::default();
+            for _ in 0..num_ops {
+                let key = key_of(chooser.next(&mut rng));
+                if rng.gen_range(0..100) < args.read_percent {
+                    let start = Instant::now();
+                    lsm.get(&key)?;
+                    latencies.reads.push(start.elapsed().as_nanos() as u64);
+                } else {
+                    let value = random_value(&mut rng, args.value_size);
+                    let start = Instant::now();
+                    lsm.put(&key, &value)?;
+                    latencies.writes.push(start.elapsed().as_nanos() as u64);
+                }
+            }
+            Ok(latencies)
+        })?;
+    println!("run phase finished in {:.3}s", elapsed.as_secs_f64());
+    println!(
+        "[OVERALL] ops={} throughput={:.0} ops/s",
+        latencies.reads.len() + latencies.writes.len(),
+        (latencies.reads.len() + latencies.writes.len()) as f64 / elapsed.as_secs_f64()
+    );
+    report("READ", &mut latencies.reads, elapsed);
+    report("UPDATE", &mut latencies.writes, elapsed);
+    Ok(())
+}
+
+fn read_scaling(lsm: &Arc<MiniLsm>, args: &Args) -> Result<()> {
+    let items = args.record_count.max(1);
+    let stop = AtomicBool::new(false);
+    let writes = AtomicU64::new(0);
+    std::thread::scope(|s| {
+        let writer = s.spawn(|| -> Result<()> {
+            let mut rng = StdRng::seed_from_u64(args.seed.wrapping_sub(1));
+            while !stop.load(Ordering::Relaxed) {
+                let value = random_value(&mut rng, args.value_size);
+                lsm.put(&key_of(rng.gen_range(0..items)), &value)?;
+                writes.fetch_add(1, Ordering::Relaxed);
+            }
+            Ok(())
+        });
+        let result = (|| {
+            let mut baseline = None;
+            let mut readers = 1;
+            while readers <= args.threads.max(1) {
+                let writes_before = writes.load(Ordering::Relaxed);
+                // Every reader issues `operation_count` reads, so that ideal scaling keeps the elapsed time constant.
+                let (latencies, elapsed) = run_parallel(
+                    readers,
+                    args.operation_count * readers as u64,
+                    |thread_id, num_ops| {
+                        let mut rng =
+                            StdRng::seed_from_u64(args.seed.wrapping_add(thread_id as u64));
+                        let mut latencies = Latencies::default();
+                        for _ in 0..num_ops {
+                            let key = key_of(rng.gen_range(0..items));
+                            let start = Instant::now();
+                            lsm.get(&key)?;
+                            latencies.reads.push(start.elapsed().as_nanos() as u64);
+                        }
+                        Ok(latencies)
+                    },
+                )?;
+                let throughput = latencies.reads.len() as f64 / elapsed.as_secs_f64();
+                let baseline = *baseline.get_or_insert(throughput);
+                let speedup = throughput / baseline;
+                println!(
+                    "[READ-SCALING] readers={readers} throughput={throughput:.0} ops/s speedup={speedup:.2}x efficiency={:.0}% writer={:.0} ops/s",
+                    speedup / readers as f64 * 100.0,
+                    (writes.load(Ordering::Relaxed) - writes_before) as f64 / elapsed.as_secs_f64(),
+                );
+                readers *= 2;
+            }
+            Ok(())
+        })();
+        stop.store(true, Ordering::Relaxed);
+        writer.join().expect("benchmark writer panicked")?;
+        result
+    })
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    assert!(args.read_percent <= 100, "read percent must be <= 100");
+    let lsm = MiniLsm::open(
+        &args.path,
+        LsmStorageOptions {
+            block_size: 4096,
+            target_sst_size: 2 << 20, // 2MB
+            num_memtable_limit: 3,
+            compaction_options: match args.compaction {
+                CompactionStrategy::None => CompactionOptions::NoCompaction,
+                CompactionStrategy::Simple => {
+                    CompactionOptions::Simple(SimpleLeveledCompactionOptions {
+                        size_ratio_percent: 200,
+                        level0_file_num_compaction_trigger: 2,
+                        max_levels: 4,
+                    })
+                }
+                CompactionStrategy::Tiered => CompactionOptions::Tiered(TieredCompactionOptions {
+                    num_tiers: 3,
+                    max_size_amplification_percent: 200,
+                    size_ratio: 1,
+                    min_merge_width: 2,
+                    max_merge_width: None,
+                }),
+                CompactionStrategy::Leveled => {
+                    CompactionOptions::Leveled(LeveledCompactionOptions {
+                        level0_file_num_compaction_trigger: 2,
+                        max_levels: 4,
+                        base_level_size_mb: 128,
+                        level_size_multiplier: 2,
+                    })
+                }
+                CompactionStrategy::LazyLeveled => {
+                    CompactionOptions::LazyLeveled(LazyLeveledCompactionOptions {
+                        size_ratio: 4,
+                        level0_file_num_compaction_trigger: 2,
+                        max_levels: 4,
+                    })
+                }
+            },
+            enable_wal: args.enable_wal,
+            serializable: false,
+            max_key_size: MAX_KEY_VALUE_SIZE,
+            max_value_size: MAX_KEY_VALUE_SIZE,
+            periodic_compaction_ttl: None,
+            level0_file_num_compaction_trigger: None,
+            create_if_missing: true,
+            error_if_exists: false,
+            read_only: false,
+            scrub_bytes_per_sec: None,
+            wal_segment_size: None,
+            manifest_rotation_size: None,
+            verify_compaction: false,
+            check_sst_key_order: false,
+            scan_page_ttl: Duration::from_secs(60),
+            tombstone_compaction_ratio: None,
+            delete_compaction_ratio: None,
+            block_filters: false,
+            value_schema: None,
+            value_compression_threshold: None,
+            preload_bloom_filters: true,
+            open_timeout: None,
+            recovery_threads: 4,
+            deterministic_seed: None,
+            background_error_policy: BackgroundErrorPolicy::HaltWrites,
+            io_retry_policy: None,
+            block_cache_shards: 16,
+            bloom_bits_per_key: Vec::new(),
+            lock_timeout: Duration::from_secs(1),
+            block_restart_interval: DEFAULT_RESTART_INTERVAL,
+            value_checksums: false,
+            persistent_cache: None,
+            warm_cache_after_compaction: false,
+            flush_threads: 1,
+            prefix_extractor: None,
+            ts_provider: TsProviderOptions::Logical,
+            file_system: FileSystemOptions::Std,
+        },
+    )?;
+
+    if !args.skip_load {
+        load(&lsm, &args)?;
+    }
+    if args.read_scaling {
+        read_scaling(&lsm, &args)?;
+    } else {
+        run(&lsm, &args)?;
+    }
+    lsm.close()?;
+    Ok(())
+}
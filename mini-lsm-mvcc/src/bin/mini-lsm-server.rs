@@ -0,0 +1,112 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Serve a mini-lsm database over HTTP, and optionally the Redis protocol. See `mini_lsm_mvcc::http_server` and
+//! `mini_lsm_mvcc::resp_server` for the supported requests.
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::{Parser, ValueEnum};
+use mini_lsm_mvcc::compact::{
+    CompactionOptions, LazyLeveledCompactionOptions, LeveledCompactionOptions,
+    SimpleLeveledCompactionOptions, TieredCompactionOptions,
+};
+use mini_lsm_mvcc::lsm_storage::{LsmStorageOptions, MiniLsm};
+use mini_lsm_mvcc::{http_server, resp_server};
+
+#[derive(Debug, Clone, ValueEnum)]
+enum CompactionStrategy {
+    Simple,
+    Leveled,
+    Tiered,
+    LazyLeveled,
+    None,
+}
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[arg(long, default_value = "lsm.db")]
+    path: PathBuf,
+    #[arg(long, default_value = "leveled")]
+    compaction: CompactionStrategy,
+    #[arg(long)]
+    enable_wal: bool,
+    #[arg(long)]
+    serializable: bool,
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    addr: String,
+    #[arg(long, default_value = "4")]
+    threads: usize,
+    /// Also accept Redis protocol clients on this address, e.g. `127.0.0.1:6379`.
+    #[arg(long)]
+    resp_addr: Option<String>,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let lsm = MiniLsm::open(
+        &args.path,
+        LsmStorageOptions {
+            block_size: 4096,
+            target_sst_size: 2 << 20, // 2MB
+            num_memtable_limit: 3,
+            compaction_options: match args.compaction {
+                CompactionStrategy::None => CompactionOptions::NoCompaction,
+                CompactionStrategy::Simple => {
+                    CompactionOptions::Simple(SimpleLeveledCompactionOptions {
+                        size_ratio_percent: 200,
+                        level0_file_num_compaction_trigger: 2,
+                        max_levels: 4,
+                    })
+                }
+                CompactionStrategy::Tiered => CompactionOptions::Tiered(TieredCompactionOptions {
+                    num_tiers: 3,
+                    max_size_amplification_percent: 200,
+                    size_ratio: 1,
+                    min_merge_width: 2,
+                    max_merge_width: None,
+                }),
+                CompactionStrategy::Leveled => {
+                    CompactionOptions::Leveled(LeveledCompactionOptions {
+                        level0_file_num_compaction_trigger: 2,
+                        max_levels: 4,
+                        base_level_size_mb: 128,
+                        level_size_multiplier: 2,
+                    })
+                }
+                CompactionStrategy::LazyLeveled => {
+                    CompactionOptions::LazyLeveled(LazyLeveledCompactionOptions {
+                        size_ratio: 4,
+                        level0_file_num_compaction_trigger: 2,
+                        max_levels: 4,
+                    })
+                }
+            },
+            enable_wal: args.enable_wal,
+            serializable: args.serializable,
+            ..LsmStorageOptions::default_for_week1_test()
+        },
+    )?;
+    if let Some(resp_addr) = args.resp_addr {
+        let lsm = lsm.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = resp_server::serve(lsm, &resp_addr) {
+                println!("RESP listener failed: {}", e);
+            }
+        });
+    }
+    http_server::serve(lsm, &args.addr, args.threads)
+}
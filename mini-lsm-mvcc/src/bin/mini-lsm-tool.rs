@@ -0,0 +1,376 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Offline tools for a mini-lsm database, complementing the interactive `mini-lsm-cli`.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use anyhow::Result;
+use bytes::Bytes;
+use clap::{Parser, Subcommand, ValueEnum};
+use mini_lsm_mvcc::compact::{
+    CompactionOptions, LazyLeveledCompactionOptions, LeveledCompactionOptions,
+    SimpleLeveledCompactionOptions, TieredCompactionOptions,
+};
+use mini_lsm_mvcc::import::{DataFormat, ImportOptions, ImportProgress};
+use mini_lsm_mvcc::iterators::StorageIterator;
+use mini_lsm_mvcc::lsm_storage::{LsmStorageOptions, MiniLsm, ReadOptions};
+use mini_lsm_mvcc::table::{FileObject, SsTable};
+use mini_lsm_mvcc::trace::TraceReader;
+
+#[derive(Debug, Clone, ValueEnum)]
+enum CompactionStrategy {
+    Simple,
+    Leveled,
+    Tiered,
+    LazyLeveled,
+    None,
+}
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[arg(long, default_value = "lsm.db")]
+    path: PathBuf,
+    #[arg(long, default_value = "leveled")]
+    compaction: CompactionStrategy,
+    #[arg(long)]
+    enable_wal: bool,
+    #[arg(long)]
+    serializable: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Bulk import key-value pairs from a CSV or JSON lines file.
+    Import {
+        input: PathBuf,
+        #[arg(long, default_value = "csv")]
+        format: DataFormat,
+        /// The input is already sorted by key without duplicates.
+        #[arg(long)]
+        sorted: bool,
+        /// Memory used to sort the input before spilling to disk, in MB.
+        #[arg(long, default_value = "64")]
+        sort_buffer_mb: usize,
+        /// Directory for temporary files of the external sort.
+        #[arg(long)]
+        tmp_dir: Option<PathBuf>,
+    },
+    /// Export a range of keys into a CSV or JSON lines file.
+    Export {
+        output: PathBuf,
+        #[arg(long, default_value = "csv")]
+        format: DataFormat,
+        /// Inclusive lower bound of the keys to export.
+        #[arg(long)]
+        begin: Option<String>,
+        /// Exclusive upper bound of the keys to export.
+        #[arg(long)]
+        end: Option<String>,
+        /// Add the commit timestamp of each key.
+        #[arg(long)]
+        with_ts: bool,
+    },
+    /// Print the keys in a range, as they were at an earlier commit timestamp if `--ts` is given.
+    Scan {
+        /// Inclusive lower bound of the keys to scan.
+        #[arg(long)]
+        begin: Option<String>,
+        /// Exclusive upper bound of the keys to scan.
+        #[arg(long)]
+        end: Option<String>,
+        /// Read at this commit timestamp, which cannot be below the GC watermark.
+        #[arg(long)]
+        ts: Option<u64>,
+    },
+    /// Show the LSM structure and what would be compacted next, without compacting.
+    Plan,
+    /// Verify the LSM structure and the checksums of all SSTs, failing if any invariant is violated.
+    Check,
+    /// Print the properties and the meta of an SST file, which need not belong to the database at `--path`.
+    DumpSst { file: PathBuf },
+    /// Merge all data of the database at `dir` into the bottom level and write a fresh manifest, without running the
+    /// engine in the background.
+    CompactOffline { dir: PathBuf },
+    /// Replay a trace recorded with `MiniLsm::start_trace` against a new database at `--path`.
+    ReplayTrace {
+        trace: PathBuf,
+        /// Wait between the operations as long as they were apart when recorded.
+        #[arg(long)]
+        preserve_timing: bool,
+    },
+}
+
+fn options(args: &Args) -> LsmStorageOptions {
+    LsmStorageOptions {
+        block_size: 4096,
+        target_sst_size: 2 << 20, // 2MB
+        num_memtable_limit: 3,
+        compaction_options: match args.compaction {
+            CompactionStrategy::None => CompactionOptions::NoCompaction,
+            CompactionStrategy::Simple => {
+                CompactionOptions::Simple(SimpleLeveledCompactionOptions {
+                    size_ratio_percent: 200,
+                    level0_file_num_compaction_trigger: 2,
+                    max_levels: 4,
+                })
+            }
+            CompactionStrategy::Tiered => CompactionOptions::Tiered(TieredCompactionOptions {
+                num_tiers: 3,
+                max_size_amplification_percent: 200,
+                size_ratio: 1,
+                min_merge_width: 2,
+                max_merge_width: None,
+            }),
+            CompactionStrategy::Leveled => CompactionOptions::Leveled(LeveledCompactionOptions {
+                level0_file_num_compaction_trigger: 2,
+                max_levels: 4,
+                base_level_size_mb: 128,
+                level_size_multiplier: 2,
+            }),
+            CompactionStrategy::LazyLeveled => {
+                CompactionOptions::LazyLeveled(LazyLeveledCompactionOptions {
+                    size_ratio: 4,
+                    level0_file_num_compaction_trigger: 2,
+                    max_levels: 4,
+                })
+            }
+        },
+        enable_wal: args.enable_wal,
+        serializable: args.serializable,
+        ..LsmStorageOptions::default_for_week1_test()
+    }
+}
+
+fn open(args: &Args) -> Result<std::sync::Arc<MiniLsm>> {
+    Ok(MiniLsm::open(&args.path, options(args))?)
+}
+
+fn dump_sst(file: &Path) -> Result<()> {
+    let sst = SsTable::open(0, None, FileObject::open(file)?)?;
+    let properties = sst.properties();
+    let time_range = sst.time_range();
+    let entry_counts = sst.entry_counts();
+    println!("unique id: {}", properties.uuid());
+    println!(
+        "created: {} ms since the UNIX epoch",
+        properties.creation_time
+    );
+    println!("created by: {}", properties.origin);
+    println!("engine version: {}", properties.engine_version);
+    println!(
+        "size: {} bytes, {} blocks{}",
+        sst.table_size(),
+        sst.num_of_blocks(),
+        if sst.has_block_filters() {
+            " with filters"
+        } else {
+            ""
+        }
+    );
+    if sst.has_compressed_values() {
+        println!("values: compressed individually above the threshold");
+    }
+    if sst.has_value_checksums() {
+        println!("values: followed by entry checksums");
+    }
+    println!(
+        "entries: {} ({} deletes)",
+        entry_counts.num_entries, entry_counts.num_deletes
+    );
+    println!("ts: {}..={}", time_range.min_ts, time_range.max_ts);
+    println!(
+        "keys: {}@{}..={}@{}",
+        String::from_utf8_lossy(sst.first_key().key_ref()),
+        sst.first_key().ts(),
+        String::from_utf8_lossy(sst.last_key().key_ref()),
+        sst.last_key().ts()
+    );
+    for (name, value) in &properties.user_collected {
+        println!("{}: {}", name, String::from_utf8_lossy(value));
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    match &args.command {
+        Command::DumpSst { file } => return dump_sst(file),
+        Command::CompactOffline { dir } => {
+            let start = Instant::now();
+            let ids = MiniLsm::compact_offline(
+                dir,
+                LsmStorageOptions {
+                    create_if_missing: false,
+                    ..options(&args)
+                },
+            )?;
+            println!(
+                "compacted {} into {} SSTs in {:.3}s",
+                dir.display(),
+                ids.len(),
+                start.elapsed().as_secs_f64()
+            );
+            return Ok(());
+        }
+        Command::ReplayTrace {
+            trace,
+            preserve_timing,
+        } => {
+            let lsm = MiniLsm::open(
+                &args.path,
+                LsmStorageOptions {
+                    error_if_exists: true,
+                    ..options(&args)
+                },
+            )?;
+            let start = Instant::now();
+            let summary = lsm.replay_trace(TraceReader::open(trace)?, *preserve_timing)?;
+            println!(
+                "replayed {} batches ({} puts, {} deletes), {} gets ({} found) and {} scans ({} entries) in {:.3}s",
+                summary.batches,
+                summary.puts,
+                summary.deletes,
+                summary.gets,
+                summary.gets_found,
+                summary.scans,
+                summary.scanned_entries,
+                start.elapsed().as_secs_f64()
+            );
+            lsm.close()?;
+            return Ok(());
+        }
+        _ => {}
+    }
+    let lsm = open(&args)?;
+    match &args.command {
+        Command::Scan { begin, end, ts } => {
+            let lower = begin
+                .as_ref()
+                .map_or(Bound::Unbounded, |x| Bound::Included(x.as_bytes()));
+            let upper = end
+                .as_ref()
+                .map_or(Bound::Unbounded, |x| Bound::Excluded(x.as_bytes()));
+            let options = ReadOptions {
+                snapshot: *ts,
+                ..Default::default()
+            };
+            // Fails with the range of timestamps that can be read if `ts` is out of it
+            let mut iter = lsm.scan_with_options(lower, upper, &options)?;
+            let mut cnt = 0;
+            while iter.is_valid() {
+                println!(
+                    "{:?}={:?}",
+                    Bytes::copy_from_slice(iter.key()),
+                    Bytes::copy_from_slice(iter.value()),
+                );
+                iter.next()?;
+                cnt += 1;
+            }
+            println!();
+            match ts {
+                Some(ts) => println!("{} keys scanned at ts={}", cnt, ts),
+                None => println!("{} keys scanned", cnt),
+            }
+        }
+        Command::Plan => {
+            lsm.dump_structure();
+            print!("{}", lsm.compaction_plan());
+        }
+        Command::Check => {
+            let report = lsm.check_integrity()?;
+            print!("{}", report);
+            if !report.is_ok() {
+                lsm.close()?;
+                anyhow::bail!("integrity check failed");
+            }
+        }
+        Command::Import {
+            input,
+            format,
+            sorted,
+            sort_buffer_mb,
+            tmp_dir,
+        } => {
+            let options = ImportOptions {
+                format: *format,
+                sorted: *sorted,
+                sort_buffer_size: sort_buffer_mb << 20,
+                tmp_dir: tmp_dir.clone(),
+            };
+            let start = Instant::now();
+            let summary =
+                lsm.import(
+                    BufReader::new(File::open(input)?),
+                    &options,
+                    |progress| match progress {
+                        ImportProgress::Read(records) => println!("{} records read", records),
+                        ImportProgress::Sorted { runs } => {
+                            println!("input sorted into {} runs", runs)
+                        }
+                        ImportProgress::Ingested(records) => {
+                            println!("{} records ingested", records)
+                        }
+                    },
+                )?;
+            println!(
+                "imported {} records into {} SSTs at ts={} in {:.3}s",
+                summary.num_entries,
+                summary.sst_ids.len(),
+                summary.commit_ts,
+                start.elapsed().as_secs_f64()
+            );
+        }
+        Command::DumpSst { .. } | Command::CompactOffline { .. } | Command::ReplayTrace { .. } => {
+            unreachable!()
+        }
+        Command::Export {
+            output,
+            format,
+            begin,
+            end,
+            with_ts,
+        } => {
+            let lower = begin
+                .as_ref()
+                .map_or(Bound::Unbounded, |x| Bound::Included(x.as_bytes()));
+            let upper = end
+                .as_ref()
+                .map_or(Bound::Unbounded, |x| Bound::Excluded(x.as_bytes()));
+            let start = Instant::now();
+            let cnt = lsm.export(
+                lower,
+                upper,
+                *format,
+                *with_ts,
+                BufWriter::new(File::create(output)?),
+            )?;
+            println!(
+                "exported {} records to {} in {:.3}s",
+                cnt,
+                output.display(),
+                start.elapsed().as_secs_f64()
+            );
+        }
+    }
+    lsm.close()?;
+    Ok(())
+}
@@ -15,26 +15,51 @@
 mod builder;
 mod iterator;
 
+use alloc::vec::Vec;
 pub use builder::BlockBuilder;
 use bytes::{Buf, BufMut, Bytes};
 pub use iterator::BlockIterator;
 
-pub(crate) const SIZEOF_U16: usize = std::mem::size_of::<u16>();
+pub(crate) const SIZEOF_U16: usize = core::mem::size_of::<u16>();
+/// The size of the fixed-size header of each entry: key overlap, rest key length and value length.
+pub(crate) const ENTRY_HEADER_SIZE: usize = SIZEOF_U16 * 3;
+/// The number of entries sharing a restart point unless configured otherwise.
+pub const DEFAULT_RESTART_INTERVAL: usize = 16;
 
 /// A block is the smallest unit of read and caching in LSM tree. It is a collection of sorted
 /// key-value pairs.
+///
+/// Every `restart_interval`-th entry is a restart point, which stores its key in full. The other entries store their
+/// keys prefix-compressed against the key of the restart point before them.
 pub struct Block {
     pub(crate) data: Vec<u8>,
     pub(crate) offsets: Vec<u16>,
+    pub(crate) restart_interval: usize,
 }
 
 impl Block {
+    /// A block without entries, which iterators over it find exhausted right away.
+    #[cfg(feature = "std")]
+    pub(crate) fn empty() -> Self {
+        Self {
+            data: Vec::new(),
+            offsets: Vec::new(),
+            restart_interval: 1,
+        }
+    }
+
+    /// The memory held by the entries and offsets of the block.
+    pub fn size(&self) -> usize {
+        self.data.len() + self.offsets.len() * SIZEOF_U16
+    }
+
     pub fn encode(&self) -> Bytes {
         let mut buf = self.data.clone();
         let offsets_len = self.offsets.len();
         for offset in &self.offsets {
             buf.put_u16(*offset);
         }
+        buf.put_u16(self.restart_interval as u16);
         // Adds number of elements at the end of the block
         buf.put_u16(offsets_len as u16);
         buf.into()
@@ -43,8 +68,10 @@ impl Block {
     pub fn decode(data: &[u8]) -> Self {
         // get number of elements in the block
         let entry_offsets_len = (&data[data.len() - SIZEOF_U16..]).get_u16() as usize;
-        let data_end = data.len() - SIZEOF_U16 - entry_offsets_len * SIZEOF_U16;
-        let offsets_raw = &data[data_end..data.len() - SIZEOF_U16];
+        let restart_interval = (&data[data.len() - SIZEOF_U16 * 2..]).get_u16() as usize;
+        let offsets_end = data.len() - SIZEOF_U16 * 2;
+        let data_end = offsets_end - entry_offsets_len * SIZEOF_U16;
+        let offsets_raw = &data[data_end..offsets_end];
         // get offset array
         let offsets = offsets_raw
             .chunks(SIZEOF_U16)
@@ -52,6 +79,10 @@ impl Block {
             .collect();
         // retrieve data
         let data = data[0..data_end].to_vec();
-        Self { data, offsets }
+        Self {
+            data,
+            offsets,
+            restart_interval,
+        }
     }
 }
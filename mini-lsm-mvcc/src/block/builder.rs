@@ -12,11 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use alloc::vec::Vec;
 use bytes::BufMut;
 
+use crate::checksum::common_prefix_len;
 use crate::key::{KeySlice, KeyVec};
 
-use super::{Block, SIZEOF_U16};
+use super::{Block, DEFAULT_RESTART_INTERVAL, ENTRY_HEADER_SIZE, SIZEOF_U16};
 
 /// Builds a block.
 pub struct BlockBuilder {
@@ -26,37 +28,41 @@ pub struct BlockBuilder {
     data: Vec<u8>,
     /// The expected block size.
     block_size: usize,
-    /// The first key in the block
-    first_key: KeyVec,
+    /// The number of entries from one restart point to the next.
+    restart_interval: usize,
+    /// The key of the last restart point, which the keys after it are encoded against.
+    restart_key: KeyVec,
 }
 
-fn compute_overlap(first_key: KeySlice, key: KeySlice) -> usize {
-    let mut i = 0;
-    loop {
-        if i >= first_key.key_len() || i >= key.key_len() {
-            break;
-        }
-        if first_key.key_ref()[i] != key.key_ref()[i] {
-            break;
-        }
-        i += 1;
-    }
-    i
+fn compute_overlap(restart_key: KeySlice, key: KeySlice) -> usize {
+    common_prefix_len(restart_key.key_ref(), key.key_ref())
 }
 
 impl BlockBuilder {
     /// Creates a new block builder.
     pub fn new(block_size: usize) -> Self {
+        Self::with_restart_interval(block_size, DEFAULT_RESTART_INTERVAL)
+    }
+
+    /// Creates a new block builder storing a full key every `restart_interval` entries. Larger intervals compress the
+    /// keys better, but a seek decodes more entries past the restart point it finds.
+    pub fn with_restart_interval(block_size: usize, restart_interval: usize) -> Self {
+        assert!(
+            (1..=u16::MAX as usize).contains(&restart_interval),
+            "restart interval must be in [1, {}]",
+            u16::MAX
+        );
         Self {
             offsets: Vec::new(),
             data: Vec::new(),
             block_size,
-            first_key: KeyVec::new(),
+            restart_interval,
+            restart_key: KeyVec::new(),
         }
     }
 
     fn estimated_size(&self) -> usize {
-        SIZEOF_U16 /* number of key-value pairs in the block */ +  self.offsets.len() * SIZEOF_U16 /* offsets */ + self.data.len()
+        SIZEOF_U16 * 2 /* number of key-value pairs in the block and restart interval */ +  self.offsets.len() * SIZEOF_U16 /* offsets */ + self.data.len()
         // key-value pairs
     }
 
@@ -64,29 +70,38 @@ impl BlockBuilder {
     #[must_use]
     pub fn add(&mut self, key: KeySlice, value: &[u8]) -> bool {
         assert!(!key.is_empty(), "key must not be empty");
-        if self.estimated_size() + key.raw_len() + value.len() + SIZEOF_U16 * 3 /* key_len, value_len and offset */ > self.block_size
+        // A restart point stores its key in full.
+        let restart = self.offsets.len().is_multiple_of(self.restart_interval);
+        let overlap = if restart {
+            0
+        } else {
+            compute_overlap(self.restart_key.as_key_slice(), key)
+        };
+        if self.estimated_size() + ENTRY_HEADER_SIZE + SIZEOF_U16 /* offset */ + key.raw_len()
+            - overlap
+            + value.len()
+            > self.block_size
             && !self.is_empty()
         {
             return false;
         }
         // Add the offset of the data into the offset array.
         self.offsets.push(self.data.len() as u16);
-        let overlap = compute_overlap(self.first_key.as_key_slice(), key);
         // Encode key overlap.
         self.data.put_u16(overlap as u16);
         // Encode key length.
         self.data.put_u16((key.key_len() - overlap) as u16);
+        // Encode value length, next to the key length so that the entry header is decoded in one go.
+        self.data.put_u16(value.len() as u16);
         // Encode key content.
         self.data.put(&key.key_ref()[overlap..]);
         // Encode key ts
         self.data.put_u64(key.ts());
-        // Encode value length.
-        self.data.put_u16(value.len() as u16);
         // Encode value content.
         self.data.put(value);
 
-        if self.first_key.is_empty() {
-            self.first_key = key.to_key_vec();
+        if restart {
+            self.restart_key.set_from_slice(key);
         }
 
         true
@@ -105,6 +120,7 @@ impl BlockBuilder {
         Block {
             data: self.data,
             offsets: self.offsets,
+            restart_interval: self.restart_interval,
         }
     }
 }
@@ -12,12 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::sync::Arc;
+use alloc::sync::Arc;
 
 use bytes::Buf;
 
 use crate::{
-    block::SIZEOF_U16,
+    block::{ENTRY_HEADER_SIZE, SIZEOF_U16},
+    checksum::common_prefix_len,
     key::{KeySlice, KeyVec},
 };
 
@@ -29,36 +30,42 @@ pub struct BlockIterator {
     block: Arc<Block>,
     /// the current key at the iterator position
     key: KeyVec,
-    /// the current value range in the block.data, corresponds to the current key
-    value_range: (usize, usize),
+    /// the offset of the current value in the block.data and its length, corresponds to the current key. The value
+    /// bytes are only touched by `value()`.
+    value_pos: (usize, usize),
     /// the current index at the iterator position
     idx: usize,
-    /// the first key in the block
-    first_key: KeyVec,
 }
 
 impl Block {
-    fn get_first_key(&self) -> KeyVec {
-        let mut buf = &self.data[..];
-        buf.get_u16();
-        let key_len = buf.get_u16() as usize;
-        let key = &buf[..key_len];
-        buf.advance(key_len);
-        KeyVec::from_vec_with_ts(key.to_vec(), buf.get_u64())
+    /// The key of the restart point that the idx-th entry is encoded against, without its timestamp. Restart points
+    /// store their key in full right after the entry header.
+    fn restart_key(&self, idx: usize) -> &[u8] {
+        let offset = self.offsets[idx - idx % self.restart_interval] as usize;
+        let key_len = (&self.data[offset + SIZEOF_U16..]).get_u16() as usize;
+        &self.data[offset + ENTRY_HEADER_SIZE..offset + ENTRY_HEADER_SIZE + key_len]
     }
 }
 
 impl BlockIterator {
     fn new(block: Arc<Block>) -> Self {
         Self {
-            first_key: block.get_first_key(),
             block,
             key: KeyVec::new(),
-            value_range: (0, 0),
+            value_pos: (0, 0),
             idx: 0,
         }
     }
 
+    /// Move the iterator to another block, keeping the key buffer so that iterating over consecutive blocks does not
+    /// allocate. The iterator must be positioned with one of the seek methods afterwards.
+    pub fn reset(&mut self, block: Arc<Block>) {
+        self.block = block;
+        self.key.clear();
+        self.value_pos = (0, 0);
+        self.idx = 0;
+    }
+
     /// Creates a block iterator and seek to the first entry.
     pub fn create_and_seek_to_first(block: Arc<Block>) -> Self {
         let mut iter = Self::new(block);
@@ -82,7 +89,8 @@ impl BlockIterator {
     /// Returns the value of the current entry.
     pub fn value(&self) -> &[u8] {
         debug_assert!(!self.key.is_empty(), "invalid iterator");
-        &self.block.data[self.value_range.0..self.value_range.1]
+        let (offset, len) = self.value_pos;
+        &self.block.data[offset..offset + len]
     }
 
     /// Returns true if the iterator is valid.
@@ -99,11 +107,11 @@ impl BlockIterator {
     fn seek_to(&mut self, idx: usize) {
         if idx >= self.block.offsets.len() {
             self.key.clear();
-            self.value_range = (0, 0);
+            self.value_pos = (0, 0);
             return;
         }
         let offset = self.block.offsets[idx] as usize;
-        self.seek_to_offset(offset);
+        self.seek_to_offset(idx, offset);
         self.idx = idx;
     }
 
@@ -113,28 +121,48 @@ impl BlockIterator {
         self.seek_to(self.idx);
     }
 
-    /// Seek to the specified position and update the current `key` and `value`
+    /// Seek to the specified position of the idx-th entry and update the current `key` and `value`
     /// Index update will be handled by caller
-    fn seek_to_offset(&mut self, offset: usize) {
+    fn seek_to_offset(&mut self, idx: usize, offset: usize) {
         let mut entry = &self.block.data[offset..];
         // Since `get_u16()` will automatically move the ptr 2 bytes ahead here,
         // we don't need to manually advance it
         let overlap_len = entry.get_u16() as usize;
         let key_len = entry.get_u16() as usize;
+        let value_len = entry.get_u16() as usize;
         let key = &entry[..key_len];
         self.key.clear();
-        self.key.append(&self.first_key.key_ref()[..overlap_len]);
+        self.key.append(&self.block.restart_key(idx)[..overlap_len]);
         self.key.append(key);
         entry.advance(key_len);
         let ts = entry.get_u64();
         self.key.set_ts(ts);
-        let value_len = entry.get_u16() as usize;
-        // REMEMBER TO CHANGE THIS every time you change the encoding!
-        let value_offset_begin =
-            offset + SIZEOF_U16 + SIZEOF_U16 + std::mem::size_of::<u64>() + key_len + SIZEOF_U16;
-        let value_offset_end = value_offset_begin + value_len;
-        self.value_range = (value_offset_begin, value_offset_end);
-        entry.advance(value_len);
+        let value_offset = offset + ENTRY_HEADER_SIZE + key_len + core::mem::size_of::<u64>();
+        self.value_pos = (value_offset, value_len);
+    }
+
+    /// Compare the key of the idx-th entry with `key`, without copying the entry key out of the block. Only the bytes
+    /// after the prefix shared with the key of the restart point, which the entry key is encoded against, are compared.
+    fn compare_key_at(&self, idx: usize, key: KeySlice) -> core::cmp::Ordering {
+        let mut entry = &self.block.data[self.block.offsets[idx] as usize..];
+        let overlap_len = entry.get_u16() as usize;
+        let key_len = entry.get_u16() as usize;
+        entry.get_u16();
+        let target = key.key_ref();
+        let restart_key = self.block.restart_key(idx);
+        let common = common_prefix_len(restart_key, target);
+        if overlap_len > common {
+            // The entry key and `key` differ within the prefix shared with the restart key.
+            return match target.get(common) {
+                Some(byte) => restart_key[common].cmp(byte),
+                None => core::cmp::Ordering::Greater,
+            };
+        }
+        let rest = &entry[..key_len];
+        entry.advance(key_len);
+        let ts = entry.get_u64();
+        rest.cmp(&target[overlap_len..])
+            .then_with(|| key.ts().cmp(&ts))
     }
 
     /// Seek to the first key that is >= `key`.
@@ -143,12 +171,13 @@ impl BlockIterator {
         let mut high = self.block.offsets.len();
         while low < high {
             let mid = low + (high - low) / 2;
-            self.seek_to(mid);
-            assert!(self.is_valid());
-            match self.key().cmp(&key) {
-                std::cmp::Ordering::Less => low = mid + 1,
-                std::cmp::Ordering::Greater => high = mid,
-                std::cmp::Ordering::Equal => return,
+            match self.compare_key_at(mid, key) {
+                core::cmp::Ordering::Less => low = mid + 1,
+                core::cmp::Ordering::Greater => high = mid,
+                core::cmp::Ordering::Equal => {
+                    low = mid;
+                    break;
+                }
             }
         }
         self.seek_to(low);
@@ -0,0 +1,121 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The block cache, split into shards by the hash of `(sst_id, block_idx)`, each with its own lock and an equal part of
+//! the capacity, so that threads serving point reads from the cache do not contend on a single cache. It may be backed
+//! by a [`PersistentBlockCache`] on local disk, which blocks missing from memory are looked up in before reading them
+//! from their SST.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::block::Block;
+use crate::lsm_storage::THREADS_SUPPORTED;
+use crate::persistent_cache::PersistentBlockCache;
+
+type Shard = moka::sync::Cache<(usize, usize), Arc<Block>>;
+
+pub struct BlockCache {
+    shards: Vec<Shard>,
+    persistent: Option<PersistentBlockCache>,
+    /// The total size of the blocks held by SST iterators reading through the cache.
+    pinned: AtomicUsize,
+}
+
+impl BlockCache {
+    /// Create a cache of `capacity` blocks in total, split into `num_shards` shards (at least one).
+    pub fn new(capacity: u64, num_shards: usize) -> Self {
+        let num_shards = num_shards.max(1);
+        let shard_capacity = capacity.div_ceil(num_shards as u64);
+        Self {
+            shards: (0..num_shards)
+                .map(|_| {
+                    Shard::builder()
+                        .max_capacity(shard_capacity)
+                        .thread_pool_enabled(THREADS_SUPPORTED)
+                        .build()
+                })
+                .collect(),
+            persistent: None,
+            pinned: AtomicUsize::new(0),
+        }
+    }
+
+    /// Back the cache with `persistent` as a second tier.
+    pub fn with_persistent_tier(mut self, persistent: PersistentBlockCache) -> Self {
+        self.persistent = Some(persistent);
+        self
+    }
+
+    /// The second tier of the cache, if any.
+    pub fn persistent_tier(&self) -> Option<&PersistentBlockCache> {
+        self.persistent.as_ref()
+    }
+
+    pub fn num_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// The index of the shard holding block `block_idx` of SST `sst_id`.
+    pub fn shard_of(&self, sst_id: usize, block_idx: usize) -> usize {
+        let mut buf = [0; 16];
+        buf[..8].copy_from_slice(&(sst_id as u64).to_le_bytes());
+        buf[8..].copy_from_slice(&(block_idx as u64).to_le_bytes());
+        farmhash::hash64(&buf) as usize % self.shards.len()
+    }
+
+    fn shard(&self, key: &(usize, usize)) -> &Shard {
+        &self.shards[self.shard_of(key.0, key.1)]
+    }
+
+    pub fn get(&self, key: &(usize, usize)) -> Option<Arc<Block>> {
+        self.shard(key).get(key)
+    }
+
+    pub fn insert(&self, key: (usize, usize), block: Arc<Block>) {
+        self.shard(&key).insert(key, block)
+    }
+
+    /// Get the block, or load it with `init` on a miss. Concurrent misses on the same block only load it once.
+    pub fn try_get_with<E: Send + Sync + 'static>(
+        &self,
+        key: (usize, usize),
+        init: impl FnOnce() -> Result<Arc<Block>, E>,
+    ) -> Result<Arc<Block>, Arc<E>> {
+        self.shard(&key).try_get_with(key, init)
+    }
+
+    /// The total size of the cached blocks.
+    pub fn size(&self) -> usize {
+        self.iter().map(|(_, block)| block.size()).sum()
+    }
+
+    /// The total size of the blocks held by SST iterators, which may also be in the cache.
+    pub fn pinned_size(&self) -> usize {
+        self.pinned.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn pin(&self, size: usize) {
+        self.pinned.fetch_add(size, Ordering::Relaxed);
+    }
+
+    pub(crate) fn unpin(&self, size: usize) {
+        self.pinned.fetch_sub(size, Ordering::Relaxed);
+    }
+
+    /// All cached blocks, shard by shard.
+    pub fn iter(&self) -> impl Iterator<Item = (Arc<(usize, usize)>, Arc<Block>)> + '_ {
+        self.shards.iter().flat_map(|shard| shard.iter())
+    }
+}
@@ -0,0 +1,202 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Change data capture: scan the keys changed after a point in time.
+//!
+//! Each memtable and SST records the range of commit timestamps and write times of its entries, so that a change scan
+//! over time-ordered data only reads the few recent files instead of the whole LSM tree.
+
+use std::cell::Cell;
+use std::ops::Bound;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use anyhow::Result;
+use bytes::Bytes;
+
+use crate::iterators::StorageIterator;
+use crate::lsm_error;
+use crate::lsm_iterator::LsmIteratorInner;
+use crate::lsm_storage::{MiniLsm, ReadOptions};
+use crate::mem_table::map_bound;
+use crate::mvcc::txn::Transaction;
+use crate::table::unix_millis;
+
+/// The point in time after which changes are returned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangesSince {
+    /// Changes committed after the timestamp.
+    Ts(u64),
+    /// Changes written after the wall-clock time. As the write time of each entry is not recorded, changes written
+    /// before it may also be returned if they are in the same memtable or SST as a later change.
+    WriteTime(SystemTime),
+}
+
+/// Iterates over the latest version of each key changed after a point in time. A deleted key has an empty value.
+pub struct ChangeIterator {
+    inner: LsmIteratorInner,
+    end_bound: Bound<Bytes>,
+    read_ts: u64,
+    since_ts: u64,
+    is_valid: bool,
+    prev_key: Vec<u8>,
+    // Keep the read timestamp from being garbage collected
+    _txn: Arc<Transaction>,
+}
+
+impl ChangeIterator {
+    /// The commit timestamp of the current change.
+    pub fn ts(&self) -> u64 {
+        self.inner.key().ts()
+    }
+
+    pub fn is_delete(&self) -> bool {
+        self.inner.value().is_empty()
+    }
+
+    fn next_inner(&mut self) -> Result<()> {
+        self.inner.next()?;
+        self.check_end_bound();
+        Ok(())
+    }
+
+    fn check_end_bound(&mut self) {
+        self.is_valid = self.inner.is_valid()
+            && match self.end_bound.as_ref() {
+                Bound::Unbounded => true,
+                Bound::Included(key) => self.inner.key().key_ref() <= key.as_ref(),
+                Bound::Excluded(key) => self.inner.key().key_ref() < key.as_ref(),
+            };
+    }
+
+    /// Move to the latest visible version of the next changed key.
+    fn move_to_change(&mut self) -> Result<()> {
+        loop {
+            while self.is_valid && self.inner.key().key_ref() == self.prev_key {
+                self.next_inner()?;
+            }
+            if !self.is_valid {
+                break;
+            }
+            self.prev_key.clear();
+            self.prev_key.extend(self.inner.key().key_ref());
+            while self.is_valid
+                && self.inner.key().key_ref() == self.prev_key
+                && self.inner.key().ts() > self.read_ts
+            {
+                self.next_inner()?;
+            }
+            if !self.is_valid {
+                break;
+            }
+            if self.inner.key().key_ref() == self.prev_key && self.inner.key().ts() > self.since_ts
+            {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl StorageIterator for ChangeIterator {
+    type KeyType<'a> = &'a [u8];
+
+    fn is_valid(&self) -> bool {
+        self.is_valid
+    }
+
+    fn key(&self) -> &[u8] {
+        self.inner.key().key_ref()
+    }
+
+    fn value(&self) -> &[u8] {
+        self.inner.value()
+    }
+
+    fn next(&mut self) -> Result<()> {
+        self.next_inner()?;
+        self.move_to_change()
+    }
+
+    fn num_active_iterators(&self) -> usize {
+        self.inner.num_active_iterators()
+    }
+}
+
+impl MiniLsm {
+    /// Scan the keys in the range changed after `since`, including deleted ones. Memtables and SSTs without changes
+    /// after `since` are skipped as a whole.
+    pub fn scan_changes(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        since: ChangesSince,
+    ) -> lsm_error::Result<ChangeIterator> {
+        let txn = self.inner.new_txn()?;
+        let read_ts = txn.read_ts();
+        let (inner, since_ts) = match since {
+            ChangesSince::Ts(since_ts) => {
+                let inner = self.inner.create_merge_iterator(
+                    lower,
+                    upper,
+                    read_ts,
+                    &ReadOptions::default(),
+                    |_| true,
+                    |table| {
+                        let range = table.time_range();
+                        range.max_ts > since_ts && range.min_ts <= read_ts
+                    },
+                )?;
+                (inner, since_ts)
+            }
+            ChangesSince::WriteTime(since) => {
+                let since = unix_millis(since);
+                // Everything in the skipped memtables and SSTs was committed before the changes, so a version not newer
+                // than all of them is either not a change or shadowed by a newer version in a skipped one.
+                let since_ts = Cell::new(0);
+                let skip = |max_ts: u64| {
+                    since_ts.set(since_ts.get().max(max_ts));
+                    false
+                };
+                let inner = self.inner.create_merge_iterator(
+                    lower,
+                    upper,
+                    read_ts,
+                    &ReadOptions::default(),
+                    |memtable| match memtable.write_time_range() {
+                        Some((_, max)) if max >= since => true,
+                        _ => skip(memtable.max_ts()),
+                    },
+                    |table| {
+                        let range = table.time_range();
+                        range.min_ts <= read_ts
+                            && (range.max_write_time >= since || skip(range.max_ts))
+                    },
+                )?;
+                (inner, since_ts.get())
+            }
+        };
+        let mut iter = ChangeIterator {
+            inner,
+            end_bound: map_bound(upper),
+            read_ts,
+            since_ts,
+            is_valid: false,
+            prev_key: Vec::new(),
+            _txn: txn,
+        };
+        iter.check_end_bound();
+        iter.move_to_change()?;
+        Ok(iter)
+    }
+}
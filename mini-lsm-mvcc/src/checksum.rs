@@ -0,0 +1,188 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Checksums of SST blocks, SST entries and external tables.
+//!
+//! With the `simd` feature, CRC32C is computed with the CPU's CRC instructions when they are available. The feature
+//! only speeds up the checksums, so files written by a build with it can be read by a build without it and vice versa.
+
+/// The name of the algorithm used by [`block_checksum`], as recorded in the manifest.
+pub const BLOCK_CHECKSUM: &str = "crc32";
+
+/// The checksum stored after each block of an SST.
+pub fn block_checksum(data: &[u8]) -> u32 {
+    crc32fast::hash(data)
+}
+
+/// Whether the CPU has `feature`, detected at runtime with std or fixed by the target features otherwise.
+#[cfg(feature = "simd")]
+macro_rules! cpu_has {
+    ($feature:tt, $($detect:ident)::+) => {{
+        #[cfg(feature = "std")]
+        let has = std::$($detect)::+!($feature);
+        #[cfg(not(feature = "std"))]
+        let has = cfg!(target_feature = $feature);
+        has
+    }};
+}
+
+/// CRC32C (Castagnoli), as used by LevelDB and RocksDB.
+pub fn crc32c(data: &[u8]) -> u32 {
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    if cpu_has!("sse4.2", is_x86_feature_detected) {
+        // SAFETY: the CPU supports SSE4.2
+        return unsafe { crc32c_sse42(data) };
+    }
+    #[cfg(all(feature = "simd", target_arch = "aarch64"))]
+    if cpu_has!("crc", arch::is_aarch64_feature_detected) {
+        // SAFETY: the CPU supports the CRC extension
+        return unsafe { crc32c_arm(data) };
+    }
+    crc32c_software(data)
+}
+
+pub(crate) fn crc32c_software(data: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0u32; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut crc = i as u32;
+            let mut j = 0;
+            while j < 8 {
+                crc = if crc & 1 != 0 {
+                    (crc >> 1) ^ 0x82f63b78
+                } else {
+                    crc >> 1
+                };
+                j += 1;
+            }
+            table[i] = crc;
+            i += 1;
+        }
+        table
+    };
+    !data.iter().fold(!0u32, |crc, byte| {
+        TABLE[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+#[target_feature(enable = "sse4.2")]
+unsafe fn crc32c_sse42(data: &[u8]) -> u32 {
+    use core::arch::x86_64::{_mm_crc32_u8, _mm_crc32_u64};
+    let mut crc = !0u64;
+    let mut chunks = data.chunks_exact(8);
+    for chunk in &mut chunks {
+        crc = _mm_crc32_u64(crc, u64::from_le_bytes(chunk.try_into().unwrap()));
+    }
+    let mut crc = crc as u32;
+    for byte in chunks.remainder() {
+        crc = _mm_crc32_u8(crc, *byte);
+    }
+    !crc
+}
+
+#[cfg(all(feature = "simd", target_arch = "aarch64"))]
+#[target_feature(enable = "crc")]
+unsafe fn crc32c_arm(data: &[u8]) -> u32 {
+    use core::arch::aarch64::{__crc32cb, __crc32cd};
+    let mut crc = !0u32;
+    let mut chunks = data.chunks_exact(8);
+    for chunk in &mut chunks {
+        crc = __crc32cd(crc, u64::from_le_bytes(chunk.try_into().unwrap()));
+    }
+    for byte in chunks.remainder() {
+        crc = __crc32cb(crc, *byte);
+    }
+    !crc
+}
+
+/// XXH32, the 32-bit xxHash of `data` with `seed`. Much faster than CRC32 without CPU support, for checksumming many
+/// small pieces of data such as the entries of an SST.
+pub fn xxhash32(data: &[u8], seed: u32) -> u32 {
+    const PRIME1: u32 = 0x9e3779b1;
+    const PRIME2: u32 = 0x85ebca77;
+    const PRIME3: u32 = 0xc2b2ae3d;
+    const PRIME4: u32 = 0x27d4eb2f;
+    const PRIME5: u32 = 0x165667b1;
+    let read_u32 = |bytes: &[u8]| u32::from_le_bytes(bytes.try_into().unwrap());
+    let round = |acc: u32, lane: u32| {
+        acc.wrapping_add(lane.wrapping_mul(PRIME2))
+            .rotate_left(13)
+            .wrapping_mul(PRIME1)
+    };
+
+    let mut stripes = data.chunks_exact(16);
+    let mut hash = if data.len() >= 16 {
+        let mut acc = [
+            seed.wrapping_add(PRIME1).wrapping_add(PRIME2),
+            seed.wrapping_add(PRIME2),
+            seed,
+            seed.wrapping_sub(PRIME1),
+        ];
+        for stripe in &mut stripes {
+            for (i, acc) in acc.iter_mut().enumerate() {
+                *acc = round(*acc, read_u32(&stripe[i * 4..i * 4 + 4]));
+            }
+        }
+        acc[0]
+            .rotate_left(1)
+            .wrapping_add(acc[1].rotate_left(7))
+            .wrapping_add(acc[2].rotate_left(12))
+            .wrapping_add(acc[3].rotate_left(18))
+    } else {
+        seed.wrapping_add(PRIME5)
+    };
+    hash = hash.wrapping_add(data.len() as u32);
+
+    let mut words = stripes.remainder().chunks_exact(4);
+    for word in &mut words {
+        hash = hash
+            .wrapping_add(read_u32(word).wrapping_mul(PRIME3))
+            .rotate_left(17)
+            .wrapping_mul(PRIME4);
+    }
+    for byte in words.remainder() {
+        hash = hash
+            .wrapping_add((*byte as u32).wrapping_mul(PRIME5))
+            .rotate_left(11)
+            .wrapping_mul(PRIME1);
+    }
+
+    hash ^= hash >> 15;
+    hash = hash.wrapping_mul(PRIME2);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(PRIME3);
+    hash ^ (hash >> 16)
+}
+
+/// The length of the longest common prefix of `a` and `b`. With the `simd` feature, 16 bytes are compared at a time.
+pub(crate) fn common_prefix_len(a: &[u8], b: &[u8]) -> usize {
+    let len = a.len().min(b.len());
+    let mut i = 0;
+    #[cfg(feature = "simd")]
+    while i + 16 <= len {
+        let x = u128::from_le_bytes(a[i..i + 16].try_into().unwrap());
+        let y = u128::from_le_bytes(b[i..i + 16].try_into().unwrap());
+        let diff = x ^ y;
+        if diff != 0 {
+            return i + (diff.trailing_zeros() / 8) as usize;
+        }
+        i += 16;
+    }
+    while i < len && a[i] == b[i] {
+        i += 1;
+    }
+    i
+}
@@ -12,36 +12,48 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod lazy_leveled;
 mod leveled;
 mod simple_leveled;
 mod tiered;
+mod verify;
 
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashSet};
 use std::sync::Arc;
-use std::time::Duration;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, SystemTime};
 
-use anyhow::Result;
+use anyhow::{Result, bail};
+pub use lazy_leveled::{
+    LazyLeveledCompactionController, LazyLeveledCompactionOptions, LazyLeveledCompactionTask,
+};
 pub use leveled::{LeveledCompactionController, LeveledCompactionOptions, LeveledCompactionTask};
+use parking_lot::RwLockReadGuard;
 use serde::{Deserialize, Serialize};
 pub use simple_leveled::{
     SimpleLeveledCompactionController, SimpleLeveledCompactionOptions, SimpleLeveledCompactionTask,
 };
 pub use tiered::{TieredCompactionController, TieredCompactionOptions, TieredCompactionTask};
 
+use crate::background_error::BackgroundTask;
+use crate::file_system::FileSystem;
 use crate::iterators::StorageIterator;
 use crate::iterators::concat_iterator::SstConcatIterator;
 use crate::iterators::merge_iterator::MergeIterator;
 use crate::iterators::two_merge_iterator::TwoMergeIterator;
-use crate::key::KeySlice;
-use crate::lsm_storage::{CompactionFilter, LsmStorageInner, LsmStorageState};
+use crate::key::{KeyBytes, KeySlice};
+use crate::lsm_error::Error;
+use crate::lsm_storage::{CompactionFilter, LsmStorageInner, LsmStorageState, THREADS_SUPPORTED};
 use crate::manifest::ManifestRecord;
-use crate::table::{SsTable, SsTableBuilder, SsTableIterator};
+use crate::stats::EntryStatsCollector;
+use crate::table::{SsTable, SsTableIterator, SstOrigin};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum CompactionTask {
     Leveled(LeveledCompactionTask),
     Tiered(TieredCompactionTask),
     Simple(SimpleLeveledCompactionTask),
+    LazyLeveled(LazyLeveledCompactionTask),
     ForceFullCompaction {
         l0_sstables: Vec<usize>,
         l1_sstables: Vec<usize>,
@@ -55,14 +67,140 @@ impl CompactionTask {
             CompactionTask::Leveled(task) => task.is_lower_level_bottom_level,
             CompactionTask::Simple(task) => task.is_lower_level_bottom_level,
             CompactionTask::Tiered(task) => task.bottom_tier_included,
+            CompactionTask::LazyLeveled(task) => task.is_lower_level_bottom_level,
+        }
+    }
+
+    /// The level the output SSTs go to, which sizes their bloom filters. Tiers have no level number, so the output of a
+    /// tiered compaction counts as L1, or as the bottom level if it includes the bottom tier.
+    fn output_level(&self) -> usize {
+        match self {
+            CompactionTask::ForceFullCompaction { .. } => 1,
+            CompactionTask::Leveled(task) => task.lower_level,
+            CompactionTask::Simple(task) => task.lower_level,
+            CompactionTask::Tiered(task) if task.bottom_tier_included => usize::MAX,
+            CompactionTask::Tiered(_) => 1,
+            CompactionTask::LazyLeveled(task) => task.lower_level,
+        }
+    }
+
+    /// The ids of all SSTs compacted by this task.
+    fn input_sst_ids(&self) -> Vec<usize> {
+        match self {
+            CompactionTask::ForceFullCompaction {
+                l0_sstables,
+                l1_sstables,
+            } => l0_sstables.iter().chain(l1_sstables).copied().collect(),
+            CompactionTask::Leveled(LeveledCompactionTask {
+                upper_level_sst_ids,
+                lower_level_sst_ids,
+                ..
+            })
+            | CompactionTask::Simple(SimpleLeveledCompactionTask {
+                upper_level_sst_ids,
+                lower_level_sst_ids,
+                ..
+            }) => upper_level_sst_ids
+                .iter()
+                .chain(lower_level_sst_ids)
+                .copied()
+                .collect(),
+            CompactionTask::Tiered(task) => task
+                .tiers
+                .iter()
+                .flat_map(|(_, ssts)| ssts)
+                .copied()
+                .collect(),
+            CompactionTask::LazyLeveled(task) => task
+                .upper_runs
+                .iter()
+                .flatten()
+                .chain(&task.lower_level_sst_ids)
+                .copied()
+                .collect(),
+        }
+    }
+}
+
+/// One of the measures a compaction controller checks to decide what to compact. Compaction is needed when a score
+/// reaches 1.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompactionScore {
+    /// The level, tier, or property the score is about.
+    pub name: String,
+    pub score: f64,
+    pub detail: String,
+}
+
+/// The result of a compaction dry run.
+#[derive(Debug)]
+pub struct CompactionPlan {
+    /// The task that would run next, or `None` if nothing needs to be compacted.
+    pub task: Option<CompactionTask>,
+    /// The score that triggered the task.
+    pub reason: Option<String>,
+    pub input_sst_ids: Vec<usize>,
+    /// The total size of the input SSTs in bytes.
+    pub input_size: u64,
+    /// The estimated total size of the output SSTs in bytes. It is the input size, as overwritten and deleted keys
+    /// are only known after reading the inputs.
+    pub estimated_output_size: u64,
+    pub scores: Vec<CompactionScore>,
+}
+
+impl std::fmt::Display for CompactionPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.task, &self.reason) {
+            (Some(task), reason) => {
+                writeln!(
+                    f,
+                    "next compaction: {}",
+                    reason.as_deref().unwrap_or("unknown reason")
+                )?;
+                writeln!(f, "  task: {:?}", task)?;
+                writeln!(
+                    f,
+                    "  inputs: {} SSTs, {:.3}MB, estimated output {:.3}MB",
+                    self.input_sst_ids.len(),
+                    self.input_size as f64 / 1024.0 / 1024.0,
+                    self.estimated_output_size as f64 / 1024.0 / 1024.0
+                )?;
+            }
+            (None, _) => writeln!(f, "no compaction needed")?,
         }
+        for score in &self.scores {
+            writeln!(f, "  {}: {:.3} ({})", score.name, score.score, score.detail)?;
+        }
+        Ok(())
     }
 }
 
-pub(crate) enum CompactionController {
+/// Find the SST outside of L0 with the highest ratio of deletes above `ratio`, among those with all entries at or below
+/// `max_ts`, so that the deletes can be dropped once rewritten into the bottom level instead of being rewritten over and
+/// over. Returns the ratio and the SST ID.
+pub(crate) fn find_tombstone_heavy_sst(
+    snapshot: &LsmStorageState,
+    ratio: f64,
+    max_ts: u64,
+) -> Option<(f64, usize)> {
+    snapshot
+        .levels
+        .iter()
+        .flat_map(|(_, ssts)| ssts)
+        .filter_map(|sst_id| {
+            let sst = &snapshot.sstables[sst_id];
+            let tombstone_ratio = sst.entry_counts().tombstone_ratio();
+            (tombstone_ratio > ratio && sst.max_ts() <= max_ts)
+                .then_some((tombstone_ratio, *sst_id))
+        })
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+}
+
+pub enum CompactionController {
     Leveled(LeveledCompactionController),
     Tiered(TieredCompactionController),
     Simple(SimpleLeveledCompactionController),
+    LazyLeveled(LazyLeveledCompactionController),
     NoCompaction,
 }
 
@@ -78,10 +216,91 @@ impl CompactionController {
             CompactionController::Tiered(ctrl) => ctrl
                 .generate_compaction_task(snapshot)
                 .map(CompactionTask::Tiered),
+            CompactionController::LazyLeveled(ctrl) => ctrl
+                .generate_compaction_task(snapshot)
+                .map(CompactionTask::LazyLeveled),
             CompactionController::NoCompaction => unreachable!(),
         }
     }
 
+    /// Explain what would be compacted next and why, without running the compaction.
+    pub fn plan(&self, snapshot: &LsmStorageState) -> CompactionPlan {
+        let scores = match self {
+            CompactionController::Leveled(ctrl) => ctrl.compaction_scores(snapshot),
+            CompactionController::Simple(ctrl) => ctrl.compaction_scores(snapshot),
+            CompactionController::Tiered(ctrl) => ctrl.compaction_scores(snapshot),
+            CompactionController::LazyLeveled(ctrl) => ctrl.compaction_scores(snapshot),
+            CompactionController::NoCompaction => Vec::new(),
+        };
+        let task = match self {
+            CompactionController::NoCompaction => None,
+            _ => self.generate_compaction_task(snapshot),
+        };
+        let input_sst_ids = task
+            .as_ref()
+            .map(CompactionTask::input_sst_ids)
+            .unwrap_or_default();
+        let input_size = input_sst_ids
+            .iter()
+            .map(|id| snapshot.sstables[id].table_size())
+            .sum();
+        // The scores are ordered by how the controller checks them, so the first one over the limit triggered the task
+        let reason = task.as_ref().and_then(|_| {
+            scores
+                .iter()
+                .find(|score| score.score >= 1.0)
+                .map(|score| format!("{} ({})", score.name, score.detail))
+        });
+        CompactionPlan {
+            task,
+            reason,
+            input_sst_ids,
+            input_size,
+            estimated_output_size: input_size,
+            scores,
+        }
+    }
+
+    /// Generate a task that rewrites `sst_id` because it has not been compacted for longer than the periodic
+    /// compaction TTL. Returns `None` if `sst_id` is not in the LSM tree or compaction is disabled.
+    pub fn generate_periodic_compaction_task(
+        &self,
+        snapshot: &LsmStorageState,
+        sst_id: usize,
+    ) -> Option<CompactionTask> {
+        match self {
+            CompactionController::Leveled(ctrl) => ctrl
+                .generate_periodic_compaction_task(snapshot, sst_id)
+                .map(CompactionTask::Leveled),
+            CompactionController::Simple(ctrl) => ctrl
+                .generate_periodic_compaction_task(snapshot, sst_id)
+                .map(CompactionTask::Simple),
+            CompactionController::Tiered(ctrl) => ctrl
+                .generate_periodic_compaction_task(snapshot, sst_id)
+                .map(CompactionTask::Tiered),
+            CompactionController::LazyLeveled(ctrl) => ctrl
+                .generate_periodic_compaction_task(snapshot, sst_id)
+                .map(CompactionTask::LazyLeveled),
+            CompactionController::NoCompaction => None,
+        }
+    }
+
+    /// Generate a task that rewrites an SST dominated by deletes, see
+    /// `LeveledCompactionController::generate_tombstone_compaction_task`. Only leveled compaction supports this.
+    pub fn generate_tombstone_compaction_task(
+        &self,
+        snapshot: &LsmStorageState,
+        ratio: f64,
+        max_ts: u64,
+    ) -> Option<CompactionTask> {
+        match self {
+            CompactionController::Leveled(ctrl) => ctrl
+                .generate_tombstone_compaction_task(snapshot, ratio, max_ts)
+                .map(CompactionTask::Leveled),
+            _ => None,
+        }
+    }
+
     pub fn apply_compaction_result(
         &self,
         snapshot: &LsmStorageState,
@@ -99,21 +318,147 @@ impl CompactionController {
             (CompactionController::Tiered(ctrl), CompactionTask::Tiered(task)) => {
                 ctrl.apply_compaction_result(snapshot, task, output)
             }
+            (CompactionController::LazyLeveled(ctrl), CompactionTask::LazyLeveled(task)) => {
+                ctrl.apply_compaction_result(snapshot, task, output)
+            }
+            (
+                CompactionController::NoCompaction,
+                CompactionTask::ForceFullCompaction {
+                    l0_sstables,
+                    l1_sstables,
+                },
+            ) => {
+                let mut snapshot = snapshot.clone();
+                snapshot
+                    .l0_sstables
+                    .retain(|sst_id| !l0_sstables.contains(sst_id));
+                snapshot.levels[0].1 = output.to_vec();
+                let files_to_remove = l0_sstables.iter().chain(l1_sstables).copied().collect();
+                (snapshot, files_to_remove)
+            }
             _ => unreachable!(),
         }
     }
 }
 
+impl CompactionOptions {
+    /// The name of the strategy, as recorded in the manifest.
+    pub fn name(&self) -> &'static str {
+        match self {
+            CompactionOptions::Leveled(_) => "leveled",
+            CompactionOptions::Tiered(_) => "tiered",
+            CompactionOptions::Simple(_) => "simple",
+            CompactionOptions::LazyLeveled(_) => "lazy_leveled",
+            CompactionOptions::NoCompaction => "none",
+        }
+    }
+
+    /// Check that the knobs of the compaction strategy are in range.
+    pub(crate) fn validate(&self) -> Result<()> {
+        fn check(ok: bool, msg: &str) -> Result<()> {
+            if !ok {
+                bail!(Error::InvalidArgument(msg.to_string()));
+            }
+            Ok(())
+        }
+        match self {
+            CompactionOptions::Leveled(options) => {
+                check(
+                    options.level0_file_num_compaction_trigger >= 1,
+                    "level0_file_num_compaction_trigger must be at least 1",
+                )?;
+                check(options.max_levels >= 1, "max_levels must be at least 1")?;
+                check(
+                    options.level_size_multiplier >= 2,
+                    "level_size_multiplier must be at least 2",
+                )?;
+                check(
+                    options.base_level_size_mb >= 1,
+                    "base_level_size_mb must be at least 1",
+                )
+            }
+            CompactionOptions::Tiered(options) => {
+                check(options.num_tiers >= 2, "num_tiers must be at least 2")?;
+                check(
+                    options.min_merge_width >= 2,
+                    "min_merge_width must be at least 2",
+                )?;
+                check(
+                    options
+                        .max_merge_width
+                        .is_none_or(|width| width >= options.min_merge_width),
+                    "max_merge_width must not be smaller than min_merge_width",
+                )
+            }
+            CompactionOptions::Simple(options) => {
+                check(
+                    options.level0_file_num_compaction_trigger >= 1,
+                    "level0_file_num_compaction_trigger must be at least 1",
+                )?;
+                check(options.max_levels >= 1, "max_levels must be at least 1")?;
+                check(
+                    options.size_ratio_percent >= 1,
+                    "size_ratio_percent must be at least 1",
+                )
+            }
+            CompactionOptions::LazyLeveled(options) => {
+                check(
+                    options.level0_file_num_compaction_trigger >= 1,
+                    "level0_file_num_compaction_trigger must be at least 1",
+                )?;
+                check(options.max_levels >= 1, "max_levels must be at least 1")?;
+                check(options.size_ratio >= 2, "size_ratio must be at least 2")
+            }
+            CompactionOptions::NoCompaction => Ok(()),
+        }
+    }
+
+    /// Set the number of L0 SSTs that triggers compacting L0. Tiered compaction does not keep SSTs in L0, so it is not
+    /// affected.
+    pub fn set_level0_file_num_compaction_trigger(&mut self, trigger: usize) {
+        match self {
+            CompactionOptions::Leveled(options) => {
+                options.level0_file_num_compaction_trigger = trigger
+            }
+            CompactionOptions::Simple(options) => {
+                options.level0_file_num_compaction_trigger = trigger
+            }
+            CompactionOptions::LazyLeveled(options) => {
+                options.level0_file_num_compaction_trigger = trigger
+            }
+            CompactionOptions::Tiered(_) | CompactionOptions::NoCompaction => {}
+        }
+    }
+}
+
 impl CompactionController {
+    pub fn new(options: &CompactionOptions) -> Self {
+        match options {
+            CompactionOptions::Leveled(options) => {
+                CompactionController::Leveled(LeveledCompactionController::new(options.clone()))
+            }
+            CompactionOptions::Tiered(options) => {
+                CompactionController::Tiered(TieredCompactionController::new(options.clone()))
+            }
+            CompactionOptions::Simple(options) => CompactionController::Simple(
+                SimpleLeveledCompactionController::new(options.clone()),
+            ),
+            CompactionOptions::LazyLeveled(options) => CompactionController::LazyLeveled(
+                LazyLeveledCompactionController::new(options.clone()),
+            ),
+            CompactionOptions::NoCompaction => CompactionController::NoCompaction,
+        }
+    }
+
     pub fn flush_to_l0(&self) -> bool {
         matches!(
             self,
-            Self::Leveled(_) | Self::Simple(_) | Self::NoCompaction
+            Self::Leveled(_) | Self::Simple(_) | Self::LazyLeveled(_) | Self::NoCompaction
         )
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum CompactionOptions {
     /// Leveled compaction with partial compaction + dynamic level support (= RocksDB's Leveled
     /// Compaction)
@@ -122,6 +467,8 @@ pub enum CompactionOptions {
     Tiered(TieredCompactionOptions),
     /// Simple leveled compaction
     Simple(SimpleLeveledCompactionOptions),
+    /// Tiered levels above a leveled last level (= Dostoevsky's lazy leveling)
+    LazyLeveled(LazyLeveledCompactionOptions),
     /// In no compaction mode (week 1), always flush to L0
     NoCompaction,
 }
@@ -131,16 +478,25 @@ impl LsmStorageInner {
         &self,
         mut iter: impl for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>,
         compact_to_bottom_level: bool,
+        output_level: usize,
+        write_time: (u64, u64),
+        watermark: u64,
+        origin: SstOrigin,
     ) -> Result<Vec<Arc<SsTable>>> {
         let mut builder = None;
+        let mut stats = EntryStatsCollector::new();
         let mut new_sst = Vec::new();
-        let watermark = self.mvcc().watermark();
         let mut last_key = Vec::<u8>::new();
         let mut first_key_below_watermark = false;
         let compaction_filters = self.compaction_filters.lock().clone();
         'outer: while iter.is_valid() {
             if builder.is_none() {
-                builder = Some(SsTableBuilder::new(self.options.block_size));
+                let mut new_builder = self.new_sst_builder(origin);
+                if let Some(bits) = self.options().bloom_bits_per_key_of_level(output_level) {
+                    new_builder.set_bloom_bits_per_key(bits);
+                }
+                new_builder.add_write_time_range(write_time.0, write_time.1);
+                builder = Some(new_builder);
             }
 
             let same_as_last_key = iter.key().key_ref() == last_key;
@@ -184,7 +540,8 @@ impl LsmStorageInner {
 
             let builder_inner = builder.as_mut().unwrap();
 
-            if builder_inner.estimated_size() >= self.options.target_sst_size && !same_as_last_key {
+            if builder_inner.estimated_size() >= self.options().target_sst_size && !same_as_last_key
+            {
                 let sst_id = self.next_sst_id();
                 let old_builder = builder.take().unwrap();
                 let sst = Arc::new(old_builder.build(
@@ -193,11 +550,19 @@ impl LsmStorageInner {
                     self.path_of_sst(sst_id),
                 )?);
                 new_sst.push(sst);
-                builder = Some(SsTableBuilder::new(self.options.block_size));
+                let old_stats = std::mem::replace(&mut stats, EntryStatsCollector::new());
+                self.sst_entry_stats.insert(sst_id, old_stats.finish());
+                let mut new_builder = self.new_sst_builder(origin);
+                if let Some(bits) = self.options().bloom_bits_per_key_of_level(output_level) {
+                    new_builder.set_bloom_bits_per_key(bits);
+                }
+                new_builder.add_write_time_range(write_time.0, write_time.1);
+                builder = Some(new_builder);
             }
 
             let builder_inner = builder.as_mut().unwrap();
             builder_inner.add(iter.key(), iter.value());
+            stats.add(iter.key(), iter.value());
 
             if !same_as_last_key {
                 last_key.clear();
@@ -206,7 +571,10 @@ impl LsmStorageInner {
 
             iter.next()?;
         }
-        if let Some(builder) = builder {
+        // Nothing is left to build if all entries were dropped
+        if let Some(builder) = builder
+            && !builder.is_empty()
+        {
             let sst_id = self.next_sst_id(); // lock dropped here
             let sst = Arc::new(builder.build(
                 sst_id,
@@ -214,14 +582,47 @@ impl LsmStorageInner {
                 self.path_of_sst(sst_id),
             )?);
             new_sst.push(sst);
+            self.sst_entry_stats.insert(sst_id, stats.finish());
         }
         Ok(new_sst)
     }
 
     fn compact(&self, task: &CompactionTask) -> Result<Vec<Arc<SsTable>>> {
-        let snapshot = {
-            let state = self.state.read();
-            state.clone()
+        let snapshot = self.snapshot();
+        // Verification must judge the output by the watermark it was produced with
+        let watermark = self.mvcc().watermark_for_gc();
+        let output = self.compact_inputs(task, &snapshot, watermark)?;
+        if self.options().verify_compaction
+            && let Err(e) = self.verify_compaction(task, &snapshot, &output, watermark)
+        {
+            for sst in &output {
+                self.options()
+                    .file_system
+                    .remove_file(&self.path_of_sst(sst.sst_id()))?;
+            }
+            return Err(e);
+        }
+        Ok(output)
+    }
+
+    fn compact_inputs(
+        &self,
+        task: &CompactionTask,
+        snapshot: &LsmStorageState,
+        watermark: u64,
+    ) -> Result<Vec<Arc<SsTable>>> {
+        // The outputs cover the write times of all inputs, as the write time of each entry is not recorded
+        let write_time = task
+            .input_sst_ids()
+            .iter()
+            .map(|id| {
+                let range = snapshot.sstables[id].time_range();
+                (range.min_write_time, range.max_write_time)
+            })
+            .reduce(|(min1, max1), (min2, max2)| (min1.min(min2), max1.max(max2)))
+            .unwrap_or_default();
+        let origin = SstOrigin::Compaction {
+            job_id: self.next_sst_id() as u64,
         };
         match task {
             CompactionTask::ForceFullCompaction {
@@ -242,7 +643,14 @@ impl LsmStorageInner {
                     MergeIterator::create(l0_iters),
                     SstConcatIterator::create_and_seek_to_first(l1_iters)?,
                 )?;
-                self.compact_generate_sst_from_iter(iter, task.compact_to_bottom_level())
+                self.compact_generate_sst_from_iter(
+                    iter,
+                    task.compact_to_bottom_level(),
+                    task.output_level(),
+                    write_time,
+                    watermark,
+                    origin,
+                )
             }
             CompactionTask::Simple(SimpleLeveledCompactionTask {
                 upper_level,
@@ -272,6 +680,10 @@ impl LsmStorageInner {
                     self.compact_generate_sst_from_iter(
                         TwoMergeIterator::create(upper_iter, lower_iter)?,
                         task.compact_to_bottom_level(),
+                        task.output_level(),
+                        write_time,
+                        watermark,
+                        origin,
                     )
                 }
                 None => {
@@ -290,9 +702,38 @@ impl LsmStorageInner {
                     self.compact_generate_sst_from_iter(
                         TwoMergeIterator::create(upper_iter, lower_iter)?,
                         task.compact_to_bottom_level(),
+                        task.output_level(),
+                        write_time,
+                        watermark,
+                        origin,
                     )
                 }
             },
+            CompactionTask::LazyLeveled(LazyLeveledCompactionTask {
+                upper_runs,
+                lower_level_sst_ids,
+                ..
+            }) => {
+                let mut iters = Vec::with_capacity(upper_runs.len() + 1);
+                for run in upper_runs
+                    .iter()
+                    .chain(std::iter::once(lower_level_sst_ids))
+                {
+                    let ssts = run
+                        .iter()
+                        .map(|id| snapshot.sstables[id].clone())
+                        .collect::<Vec<_>>();
+                    iters.push(Box::new(SstConcatIterator::create_and_seek_to_first(ssts)?));
+                }
+                self.compact_generate_sst_from_iter(
+                    MergeIterator::create(iters),
+                    task.compact_to_bottom_level(),
+                    task.output_level(),
+                    write_time,
+                    watermark,
+                    origin,
+                )
+            }
             CompactionTask::Tiered(TieredCompactionTask { tiers, .. }) => {
                 let mut iters = Vec::with_capacity(tiers.len());
                 for (_, tier_sst_ids) in tiers {
@@ -305,20 +746,22 @@ impl LsmStorageInner {
                 self.compact_generate_sst_from_iter(
                     MergeIterator::create(iters),
                     task.compact_to_bottom_level(),
+                    task.output_level(),
+                    write_time,
+                    watermark,
+                    origin,
                 )
             }
         }
     }
 
     pub fn force_full_compaction(&self) -> Result<()> {
-        let CompactionOptions::NoCompaction = self.options.compaction_options else {
+        self.check_writable()?;
+        let CompactionOptions::NoCompaction = self.options().compaction_options else {
             panic!("full compaction can only be called with compaction is not enabled")
         };
 
-        let snapshot = {
-            let state = self.state.read();
-            state.clone()
-        };
+        let snapshot = self.snapshot();
 
         let l0_sstables = snapshot.l0_sstables.clone();
         let l1_sstables = snapshot.levels[0].1.clone();
@@ -329,6 +772,8 @@ impl LsmStorageInner {
 
         println!("force full compaction: {:?}", compaction_task);
 
+        self.notify_event_listeners(|listener| listener.on_compaction_begin(&compaction_task));
+        let hot_ranges = self.sample_hot_ranges(&snapshot, &compaction_task);
         let sstables = self.compact(&compaction_task)?;
         let mut ids = Vec::with_capacity(sstables.len());
 
@@ -339,9 +784,9 @@ impl LsmStorageInner {
                 let result = state.sstables.remove(sst);
                 assert!(result.is_some());
             }
-            for new_sst in sstables {
+            for new_sst in &sstables {
                 ids.push(new_sst.sst_id());
-                let result = state.sstables.insert(new_sst.sst_id(), new_sst);
+                let result = state.sstables.insert(new_sst.sst_id(), new_sst.clone());
                 assert!(result.is_none());
             }
             assert_eq!(l1_sstables, state.levels[0].1);
@@ -354,15 +799,22 @@ impl LsmStorageInner {
                 .copied()
                 .collect::<Vec<_>>();
             assert!(l0_sstables_map.is_empty());
-            *self.state.write() = Arc::new(state);
             self.sync_dir()?;
-            self.manifest.as_ref().unwrap().add_record(
+            self.manifest.as_ref().unwrap().add_records(
                 &state_lock,
-                ManifestRecord::Compaction(compaction_task, ids.clone()),
+                &[
+                    ManifestRecord::Compaction(compaction_task.clone(), ids.clone()),
+                    ManifestRecord::GcWatermark(self.mvcc().gc_watermark()),
+                ],
             )?;
+            *self.state.write() = Arc::new(state);
         }
+        self.notify_event_listeners(|listener| {
+            listener.on_compaction_completed(&compaction_task, &ids)
+        });
+        self.warm_block_cache(&hot_ranges, &sstables);
         for sst in l0_sstables.iter().chain(l1_sstables.iter()) {
-            std::fs::remove_file(self.path_of_sst(*sst))?;
+            self.delete_sst(*sst)?;
         }
 
         println!("force full compaction done, new SSTs: {:?}", ids);
@@ -370,32 +822,304 @@ impl LsmStorageInner {
         Ok(())
     }
 
+    /// Flush all memtables and merge every SST into a single sorted run on the bottom level, whatever the compaction
+    /// strategy, then replace the manifest with a snapshot of the result. Meant for a storage without background
+    /// threads, e.g., to shrink an archived database. Returns the ids of the new SSTs.
+    pub fn compact_all_to_bottom_level(&self) -> Result<Vec<usize>> {
+        self.check_writable()?;
+        if !self.state.read().memtable.is_empty() {
+            self.force_freeze_memtable(&self.state_lock.lock())?;
+        }
+        while !self.state.read().imm_memtables.is_empty() {
+            self.force_flush_imm_memtables()?;
+        }
+
+        let snapshot = self.snapshot();
+        let input_ids = snapshot
+            .l0_sstables
+            .iter()
+            .chain(snapshot.levels.iter().flat_map(|(_, ssts)| ssts))
+            .copied()
+            .collect::<Vec<_>>();
+        let write_time = input_ids
+            .iter()
+            .map(|id| {
+                let range = snapshot.sstables[id].time_range();
+                (range.min_write_time, range.max_write_time)
+            })
+            .reduce(|(min1, max1), (min2, max2)| (min1.min(min2), max1.max(max2)))
+            .unwrap_or_default();
+        let mut l0_iters = Vec::with_capacity(snapshot.l0_sstables.len());
+        for id in &snapshot.l0_sstables {
+            l0_iters.push(Box::new(SsTableIterator::create_and_seek_to_first(
+                snapshot.sstables[id].clone(),
+            )?));
+        }
+        let mut level_iters = Vec::with_capacity(snapshot.levels.len());
+        for (_, ssts) in &snapshot.levels {
+            level_iters.push(Box::new(SstConcatIterator::create_and_seek_to_first(
+                ssts.iter()
+                    .map(|id| snapshot.sstables[id].clone())
+                    .collect(),
+            )?));
+        }
+        let iter = TwoMergeIterator::create(
+            MergeIterator::create(l0_iters),
+            MergeIterator::create(level_iters),
+        )?;
+        let origin = SstOrigin::Compaction {
+            job_id: self.next_sst_id() as u64,
+        };
+        let sstables = self.compact_generate_sst_from_iter(
+            iter,
+            true,
+            usize::MAX,
+            write_time,
+            self.mvcc().watermark_for_gc(),
+            origin,
+        )?;
+        let ids = sstables.iter().map(|sst| sst.sst_id()).collect::<Vec<_>>();
+
+        {
+            let state_lock = self.state_lock.lock();
+            let mut state = self.state.read().as_ref().clone();
+            for sst in &input_ids {
+                state.sstables.remove(sst);
+            }
+            for sst in sstables {
+                state.sstables.insert(sst.sst_id(), sst);
+            }
+            state.l0_sstables.clear();
+            match &self.options().compaction_options {
+                // A tier is named after its first SST
+                CompactionOptions::Tiered(_) => {
+                    state.levels = ids
+                        .first()
+                        .map(|&id| (id, ids.clone()))
+                        .into_iter()
+                        .collect()
+                }
+                CompactionOptions::LazyLeveled(options) => {
+                    state.levels = if ids.is_empty() {
+                        Vec::new()
+                    } else {
+                        vec![(options.max_levels, ids.clone())]
+                    }
+                }
+                CompactionOptions::Leveled(_)
+                | CompactionOptions::Simple(_)
+                | CompactionOptions::NoCompaction => {
+                    for (_, ssts) in &mut state.levels {
+                        ssts.clear();
+                    }
+                    state.levels.last_mut().unwrap().1.clone_from(&ids);
+                }
+            }
+            *self.state.write() = Arc::new(state);
+            self.sync_dir()?;
+            self.rotate_manifest(&state_lock)?;
+        }
+        for sst in &input_ids {
+            self.delete_sst(*sst)?;
+        }
+        self.sync_dir()?;
+
+        Ok(ids)
+    }
+
+    /// Purge the versions below `before_ts` from all levels, e.g., to expire overwritten and deleted data for
+    /// compliance: flush the memtables, then compact every SST holding versions below it until they reach the bottom
+    /// level, where only the latest of them is kept for each key, or none if it is a delete. `before_ts` cannot be above
+    /// the watermark, as snapshots may still read the versions below it.
+    pub fn gc(&self, before_ts: u64) -> Result<()> {
+        self.check_writable()?;
+        self.mvcc().raise_gc_watermark(before_ts)?;
+        if !self.state.read().memtable.is_empty() {
+            self.force_freeze_memtable(&self.state_lock.lock())?;
+        }
+        while !self.state.read().imm_memtables.is_empty() {
+            self.force_flush_imm_memtables()?;
+        }
+        let holds_versions_below = |sst: &SsTable| sst.time_range().min_ts < before_ts;
+
+        if let CompactionOptions::NoCompaction = self.options().compaction_options {
+            if self
+                .snapshot()
+                .sstables
+                .values()
+                .any(|sst| holds_versions_below(sst))
+            {
+                self.force_full_compaction()?;
+            }
+            return Ok(());
+        }
+        // The SSTs compacted into the bottom level since, which hold nothing left to purge
+        let mut purged = HashSet::<usize>::new();
+        loop {
+            // Keep the compaction thread from changing the levels between the tasks and their results
+            let _guard = self.background_lock.write();
+            let snapshot = self.snapshot();
+            // Upper levels first, so that their versions are purged along with the ones below
+            let Some(sst_id) = snapshot
+                .l0_sstables
+                .iter()
+                .chain(snapshot.levels.iter().flat_map(|(_, ssts)| ssts))
+                .copied()
+                .find(|id| !purged.contains(id) && holds_versions_below(&snapshot.sstables[id]))
+            else {
+                return Ok(());
+            };
+            let task = self
+                .compaction_controller()
+                .generate_periodic_compaction_task(&snapshot, sst_id)
+                .unwrap();
+            if let Some(sst_id) = task
+                .input_sst_ids()
+                .into_iter()
+                .find(|sst_id| self.is_quarantined(*sst_id))
+            {
+                bail!(Error::Corruption(format!("SST {sst_id} is quarantined")));
+            }
+            let compact_to_bottom_level = task.compact_to_bottom_level();
+            self.run_compaction_task(task)?;
+            if compact_to_bottom_level {
+                let state = self.snapshot();
+                purged.extend(state.sstables.keys().filter(|id| {
+                    !snapshot.sstables.contains_key(id) && !state.l0_sstables.contains(id)
+                }));
+            }
+        }
+    }
+
+    /// Find the SST that has gone without compaction for the longest time beyond the periodic compaction TTL, and
+    /// generate a task to rewrite it.
+    fn generate_periodic_compaction_task(
+        &self,
+        snapshot: &LsmStorageState,
+    ) -> Option<CompactionTask> {
+        let ttl = self.options().periodic_compaction_ttl?;
+        let now = SystemTime::now();
+        let (_, sst_id) = snapshot
+            .l0_sstables
+            .iter()
+            .chain(snapshot.levels.iter().flat_map(|(_, ssts)| ssts))
+            .filter_map(|sst_id| {
+                let created = snapshot.sstables[sst_id].creation_time()?;
+                let age = now.duration_since(created).unwrap_or_default();
+                (age > ttl).then_some((created, *sst_id))
+            })
+            .min()?;
+        println!("periodic compaction triggered by {}.sst", sst_id);
+        self.compaction_controller()
+            .generate_periodic_compaction_task(snapshot, sst_id)
+    }
+
+    fn generate_tombstone_compaction_task(
+        &self,
+        snapshot: &LsmStorageState,
+    ) -> Option<CompactionTask> {
+        let ratio = self.options().tombstone_compaction_ratio?;
+        self.compaction_controller()
+            .generate_tombstone_compaction_task(snapshot, ratio, self.mvcc().watermark())
+    }
+
+    /// Find the SST with the highest ratio of deletes above `delete_compaction_ratio` outside of L0, and generate a task
+    /// that rewrites it alone. This works with every compaction strategy, after the tasks the strategy triggers.
+    fn generate_delete_compaction_task(
+        &self,
+        snapshot: &LsmStorageState,
+    ) -> Option<CompactionTask> {
+        let ratio = self.options().delete_compaction_ratio?;
+        let (_, sst_id) = find_tombstone_heavy_sst(snapshot, ratio, self.mvcc().watermark())?;
+        println!("delete compaction triggered by {}.sst", sst_id);
+        self.compaction_controller()
+            .generate_periodic_compaction_task(snapshot, sst_id)
+    }
+
+    pub fn compaction_plan(&self) -> CompactionPlan {
+        self.compaction_controller().plan(&self.snapshot())
+    }
+
     fn trigger_compaction(&self) -> Result<()> {
-        let snapshot = {
-            let state = self.state.read();
-            state.clone()
+        let Some(_guard) = self.background_task_guard() else {
+            return Ok(());
         };
+        let snapshot = self.snapshot();
         let task = self
-            .compaction_controller
-            .generate_compaction_task(&snapshot);
+            .generate_tombstone_compaction_task(&snapshot)
+            .or_else(|| {
+                self.compaction_controller()
+                    .generate_compaction_task(&snapshot)
+            })
+            .or_else(|| self.generate_delete_compaction_task(&snapshot))
+            .or_else(|| self.generate_periodic_compaction_task(&snapshot));
         let Some(task) = task else {
             return Ok(());
         };
+        // Rewriting a quarantined SST would spread its damage into new SSTs, so leave it to repair. Until then, the
+        // strategy keeps picking the same task, so report that compaction is stuck instead of silently skipping it.
+        if let Some(sst_id) = task
+            .input_sst_ids()
+            .into_iter()
+            .find(|sst_id| self.is_quarantined(*sst_id))
+        {
+            bail!(Error::Corruption(format!(
+                "compaction is blocked by quarantined SST {sst_id} until it is repaired"
+            )));
+        }
+        self.run_compaction_task(task)
+    }
+
+    /// Rewrite the single SST `sst_id` into the next level, or in place if it is in the bottom level, dropping the
+    /// deletes and old versions that are no longer visible. Compacting an L0 SST compacts all of L0.
+    pub fn compact_file(&self, sst_id: usize) -> Result<()> {
+        self.check_writable()?;
+        // Keep the compaction thread from picking the same SST meanwhile
+        let _guard = self.background_lock.write();
+        let snapshot = self.snapshot();
+        if !snapshot.sstables.contains_key(&sst_id) {
+            bail!(Error::InvalidArgument(format!(
+                "SST {sst_id} is not in the LSM tree"
+            )));
+        }
+        let Some(task) = self
+            .compaction_controller()
+            .generate_periodic_compaction_task(&snapshot, sst_id)
+        else {
+            bail!(Error::InvalidArgument(
+                "compact_file requires a compaction strategy".to_string()
+            ));
+        };
+        if let Some(sst_id) = task
+            .input_sst_ids()
+            .into_iter()
+            .find(|sst_id| self.is_quarantined(*sst_id))
+        {
+            bail!(Error::Corruption(format!("SST {sst_id} is quarantined")));
+        }
+        self.run_compaction_task(task)
+    }
+
+    fn run_compaction_task(&self, task: CompactionTask) -> Result<()> {
         self.dump_structure();
         println!("running compaction task: {:?}", task);
+        self.notify_event_listeners(|listener| listener.on_compaction_begin(&task));
+        let hot_ranges = self.sample_hot_ranges(&self.snapshot(), &task);
         let sstables = self.compact(&task)?;
         let output = sstables.iter().map(|x| x.sst_id()).collect::<Vec<_>>();
         let ssts_to_remove = {
             let state_lock = self.state_lock.lock();
             let mut snapshot = self.state.read().as_ref().clone();
             let mut new_sst_ids = Vec::new();
-            for file_to_add in sstables {
+            for file_to_add in &sstables {
                 new_sst_ids.push(file_to_add.sst_id());
-                let result = snapshot.sstables.insert(file_to_add.sst_id(), file_to_add);
+                let result = snapshot
+                    .sstables
+                    .insert(file_to_add.sst_id(), file_to_add.clone());
                 assert!(result.is_none());
             }
             let (mut snapshot, files_to_remove) = self
-                .compaction_controller
+                .compaction_controller()
                 .apply_compaction_result(&snapshot, &task, &output, false);
 
             let mut ssts_to_remove = Vec::with_capacity(files_to_remove.len());
@@ -404,12 +1128,16 @@ impl LsmStorageInner {
                 assert!(result.is_some(), "cannot remove {}.sst", file_to_remove);
                 ssts_to_remove.push(result.unwrap());
             }
-            let mut state = self.state.write();
-            *state = Arc::new(snapshot);
-            drop(state);
+            // Record the compaction before readers can see it, and swap all inputs for all outputs at once
             self.sync_dir()?;
-            self.manifest()
-                .add_record(&state_lock, ManifestRecord::Compaction(task, new_sst_ids))?;
+            self.manifest().add_records(
+                &state_lock,
+                &[
+                    ManifestRecord::Compaction(task.clone(), new_sst_ids),
+                    ManifestRecord::GcWatermark(self.mvcc().gc_watermark()),
+                ],
+            )?;
+            *self.state.write() = Arc::new(snapshot);
             ssts_to_remove
         };
         println!(
@@ -418,30 +1146,90 @@ impl LsmStorageInner {
             output.len(),
             output
         );
+        self.notify_event_listeners(|listener| listener.on_compaction_completed(&task, &output));
+        self.warm_block_cache(&hot_ranges, &sstables);
         for sst in ssts_to_remove {
-            std::fs::remove_file(self.path_of_sst(sst.sst_id()))?;
+            self.delete_sst(sst.sst_id())?;
         }
         self.sync_dir()?;
 
         Ok(())
     }
 
+    /// The key ranges of the input blocks of `task` in the block cache, which stand for the keys recently read from
+    /// the inputs. Sampled before the compaction, as reading the inputs caches all their blocks. Empty unless
+    /// `warm_cache_after_compaction` is set.
+    fn sample_hot_ranges(
+        &self,
+        snapshot: &LsmStorageState,
+        task: &CompactionTask,
+    ) -> Vec<(KeyBytes, KeyBytes)> {
+        if !self.options().warm_cache_after_compaction {
+            return Vec::new();
+        }
+        let mut hot_ranges = Vec::new();
+        for sst_id in task.input_sst_ids() {
+            let input = &snapshot.sstables[&sst_id];
+            for (block_idx, meta) in input.block_meta.iter().enumerate() {
+                if input.cached_block(block_idx).is_some() {
+                    hot_ranges.push((meta.first_key.clone(), meta.last_key.clone()));
+                }
+            }
+        }
+        hot_ranges
+    }
+
+    /// Load into the block cache the blocks of `outputs` overlapping `hot_ranges`, so that reads of hot keys do not
+    /// all miss the cache once the compaction replaces the inputs.
+    fn warm_block_cache(&self, hot_ranges: &[(KeyBytes, KeyBytes)], outputs: &[Arc<SsTable>]) {
+        if hot_ranges.is_empty() {
+            return;
+        }
+        let mut warmed = 0;
+        for output in outputs {
+            let mut blocks = BTreeSet::new();
+            for (first_key, last_key) in hot_ranges {
+                let start = output
+                    .block_meta
+                    .partition_point(|meta| meta.last_key.key_ref() < first_key.key_ref());
+                blocks.extend((start..output.block_meta.len()).take_while(|&idx| {
+                    output.block_meta[idx].first_key.key_ref() <= last_key.key_ref()
+                }));
+            }
+            for block_idx in blocks {
+                // Warming only saves a later miss, so a block failing to load is left to the reader that needs it
+                if output.read_block_cached(block_idx).is_ok() {
+                    warmed += 1;
+                }
+            }
+        }
+        println!(
+            "warmed {} blocks of the compaction output from {} cached input blocks",
+            warmed,
+            hot_ranges.len()
+        );
+    }
+
     pub(crate) fn spawn_compaction_thread(
         self: &Arc<Self>,
         rx: crossbeam_channel::Receiver<()>,
     ) -> Result<Option<std::thread::JoinHandle<()>>> {
+        if !self.spawns_background_threads() {
+            return Ok(None);
+        }
         if let CompactionOptions::Leveled(_)
         | CompactionOptions::Simple(_)
-        | CompactionOptions::Tiered(_) = self.options.compaction_options
+        | CompactionOptions::Tiered(_)
+        | CompactionOptions::LazyLeveled(_) = self.options().compaction_options
         {
             let this = self.clone();
             let handle = std::thread::spawn(move || {
                 let ticker = crossbeam_channel::tick(Duration::from_millis(50));
                 loop {
                     crossbeam_channel::select! {
-                        recv(ticker) -> _ => if let Err(e) = this.trigger_compaction() {
-                            eprintln!("compaction failed: {}", e);
-                        },
+                        recv(ticker) -> _ => this.run_background_task(BackgroundTask::Compaction, || {
+                            this.trigger_compaction()
+                        }),
                         recv(rx) -> _ => return
                     }
                 }
@@ -451,29 +1239,66 @@ impl LsmStorageInner {
         Ok(None)
     }
 
+    pub(crate) fn pause_background(&self) {
+        self.background_paused.store(true, Ordering::SeqCst);
+        // Wait for the in-flight tasks, which hold the read lock
+        drop(self.background_lock.write());
+    }
+
+    pub(crate) fn resume_background(&self) {
+        self.background_paused.store(false, Ordering::SeqCst);
+    }
+
+    /// Called by the flush and compaction threads before starting a task. Returns `None` if background work is
+    /// paused, or a guard to hold until the task finishes otherwise.
+    fn background_task_guard(&self) -> Option<RwLockReadGuard<'_, ()>> {
+        let guard = self.background_lock.read();
+        if self.background_paused.load(Ordering::SeqCst) || self.options().read_only {
+            return None;
+        }
+        Some(guard)
+    }
+
     fn trigger_flush(&self) -> Result<()> {
+        let Some(_guard) = self.background_task_guard() else {
+            return Ok(());
+        };
         let res = {
             let state = self.state.read();
-            state.imm_memtables.len() >= self.options.num_memtable_limit
+            state.imm_memtables.len() >= self.options().num_memtable_limit
         };
         if res {
-            self.force_flush_next_imm_memtable()?;
+            self.force_flush_imm_memtables()?;
         }
 
         Ok(())
     }
 
+    fn trigger_manifest_rotation(&self) -> Result<()> {
+        let Some(_guard) = self.background_task_guard() else {
+            return Ok(());
+        };
+        self.maybe_rotate_manifest()
+    }
+
     pub(crate) fn spawn_flush_thread(
         self: &Arc<Self>,
         rx: crossbeam_channel::Receiver<()>,
     ) -> Result<Option<std::thread::JoinHandle<()>>> {
+        if !self.spawns_background_threads() {
+            return Ok(None);
+        }
         let this = self.clone();
         let handle = std::thread::spawn(move || {
             let ticker = crossbeam_channel::tick(Duration::from_millis(50));
             loop {
                 crossbeam_channel::select! {
-                    recv(ticker) -> _ => if let Err(e) = this.trigger_flush() {
-                        eprintln!("flush failed: {}", e);
+                    recv(ticker) -> _ => {
+                        this.run_background_task(BackgroundTask::Flush, || this.trigger_flush());
+                        this.run_background_task(BackgroundTask::ManifestRotation, || {
+                            this.trigger_manifest_rotation()
+                        });
+                        this.expire_page_leases();
                     },
                     recv(rx) -> _ => return
                 }
@@ -481,4 +1306,40 @@ impl LsmStorageInner {
         });
         Ok(Some(handle))
     }
+
+    /// Whether the flush, compaction and scrub threads run, or the work is left to `tick`.
+    pub(crate) fn spawns_background_threads(&self) -> bool {
+        THREADS_SUPPORTED && self.options.deterministic_seed.is_none()
+    }
+
+    /// One round of the work of the flush, compaction and scrub threads, run on the calling thread.
+    pub(crate) fn tick(&self) -> Result<()> {
+        self.trigger_flush()?;
+        self.trigger_manifest_rotation()?;
+        self.expire_page_leases();
+        if !matches!(
+            self.options().compaction_options,
+            CompactionOptions::NoCompaction
+        ) {
+            self.trigger_compaction()?;
+        }
+        if self.options().scrub_bytes_per_sec.is_some()
+            && !self.background_paused.load(Ordering::SeqCst)
+        {
+            self.scrub_next_block()?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn run_pending_tasks(&self) -> Result<usize> {
+        let mut ticks = 0;
+        loop {
+            let before = self.snapshot();
+            self.tick()?;
+            if Arc::ptr_eq(&before, &self.snapshot()) {
+                return Ok(ticks);
+            }
+            ticks += 1;
+        }
+    }
 }
@@ -0,0 +1,202 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Lazy leveling (Dostoevsky): the levels above the last one are tiered, and the last level is a single sorted run.
+//!
+//! Each entry of `levels` is a sorted run tagged with its level. A tiered level collects up to `size_ratio` runs
+//! (newest first) before they are merged into one run of the next level, and runs reaching the last level are merged
+//! into its single run. Most data lives in the last level, so reads and space amplification are close to leveled
+//! compaction, while the upper levels only rewrite data once per level as in tiered compaction.
+
+use serde::{Deserialize, Serialize};
+
+use super::CompactionScore;
+use crate::lsm_storage::LsmStorageState;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LazyLeveledCompactionTask {
+    /// The level of the runs to merge, where 0 is L0.
+    pub upper_level: usize,
+    /// The runs to merge. Each L0 SST is a run of its own.
+    pub upper_runs: Vec<Vec<usize>>,
+    pub lower_level: usize,
+    /// The run of the last level if the upper runs are merged into it, or empty otherwise.
+    pub lower_level_sst_ids: Vec<usize>,
+    pub is_lower_level_bottom_level: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LazyLeveledCompactionOptions {
+    /// The number of runs a tiered level holds before merging them into the next level.
+    pub size_ratio: usize,
+    pub level0_file_num_compaction_trigger: usize,
+    pub max_levels: usize,
+}
+
+pub struct LazyLeveledCompactionController {
+    options: LazyLeveledCompactionOptions,
+}
+
+impl LazyLeveledCompactionController {
+    pub fn new(options: LazyLeveledCompactionOptions) -> Self {
+        Self { options }
+    }
+
+    fn runs_at(snapshot: &LsmStorageState, level: usize) -> impl Iterator<Item = &Vec<usize>> {
+        snapshot
+            .levels
+            .iter()
+            .filter(move |(run_level, _)| *run_level == level)
+            .map(|(_, ssts)| ssts)
+    }
+
+    /// Merge all runs of `level` into the next level, or rewrite the last level if `level` is the last level.
+    fn merge_level(&self, snapshot: &LsmStorageState, level: usize) -> LazyLeveledCompactionTask {
+        let max_levels = self.options.max_levels;
+        let upper_runs = if level == 0 {
+            snapshot.l0_sstables.iter().map(|id| vec![*id]).collect()
+        } else if level < max_levels {
+            Self::runs_at(snapshot, level).cloned().collect()
+        } else {
+            Vec::new()
+        };
+        let lower_level = (level + 1).min(max_levels);
+        LazyLeveledCompactionTask {
+            upper_level: level,
+            upper_runs,
+            lower_level,
+            lower_level_sst_ids: if lower_level == max_levels {
+                Self::runs_at(snapshot, max_levels)
+                    .flatten()
+                    .copied()
+                    .collect()
+            } else {
+                Vec::new()
+            },
+            is_lower_level_bottom_level: lower_level == max_levels,
+        }
+    }
+
+    pub fn generate_compaction_task(
+        &self,
+        snapshot: &LsmStorageState,
+    ) -> Option<LazyLeveledCompactionTask> {
+        if snapshot.l0_sstables.len() >= self.options.level0_file_num_compaction_trigger {
+            println!(
+                "compaction triggered at L0 with {} runs",
+                snapshot.l0_sstables.len()
+            );
+            return Some(self.merge_level(snapshot, 0));
+        }
+        for level in 1..self.options.max_levels {
+            let num_runs = Self::runs_at(snapshot, level).count();
+            if num_runs >= self.options.size_ratio {
+                println!("compaction triggered at L{level} with {num_runs} runs");
+                return Some(self.merge_level(snapshot, level));
+            }
+        }
+        None
+    }
+
+    /// The score of each level above the last one is its number of runs over the limit.
+    pub fn compaction_scores(&self, snapshot: &LsmStorageState) -> Vec<CompactionScore> {
+        let mut scores = vec![CompactionScore {
+            name: "L0".to_string(),
+            score: snapshot.l0_sstables.len() as f64
+                / self.options.level0_file_num_compaction_trigger as f64,
+            detail: format!(
+                "{} runs, trigger {}",
+                snapshot.l0_sstables.len(),
+                self.options.level0_file_num_compaction_trigger
+            ),
+        }];
+        for level in 1..self.options.max_levels {
+            let num_runs = Self::runs_at(snapshot, level).count();
+            scores.push(CompactionScore {
+                name: format!("L{level}"),
+                score: num_runs as f64 / self.options.size_ratio as f64,
+                detail: format!("{num_runs} runs, limit {}", self.options.size_ratio),
+            });
+        }
+        scores
+    }
+
+    /// Generates a task that merges the level holding `sst_id` into the next level, or rewrites the last level if
+    /// `sst_id` is in it.
+    pub fn generate_periodic_compaction_task(
+        &self,
+        snapshot: &LsmStorageState,
+        sst_id: usize,
+    ) -> Option<LazyLeveledCompactionTask> {
+        let level = if snapshot.l0_sstables.contains(&sst_id) {
+            0
+        } else {
+            snapshot
+                .levels
+                .iter()
+                .find(|(_, ssts)| ssts.contains(&sst_id))?
+                .0
+        };
+        Some(self.merge_level(snapshot, level))
+    }
+
+    pub fn apply_compaction_result(
+        &self,
+        snapshot: &LsmStorageState,
+        task: &LazyLeveledCompactionTask,
+        output: &[usize],
+    ) -> (LsmStorageState, Vec<usize>) {
+        let mut snapshot = snapshot.clone();
+        let mut files_to_remove = Vec::new();
+        if task.upper_level == 0 {
+            let compacted = task.upper_runs.iter().flatten().collect::<Vec<_>>();
+            snapshot.l0_sstables.retain(|id| !compacted.contains(&id));
+        } else {
+            let num_levels = snapshot.levels.len();
+            snapshot.levels.retain(|(level, ssts)| {
+                *level != task.upper_level || !task.upper_runs.contains(ssts)
+            });
+            assert_eq!(
+                num_levels - snapshot.levels.len(),
+                task.upper_runs.len(),
+                "run mismatched"
+            );
+        }
+        files_to_remove.extend(task.upper_runs.iter().flatten());
+        if task.is_lower_level_bottom_level {
+            let bottom_run = snapshot
+                .levels
+                .iter()
+                .position(|(level, _)| *level == task.lower_level);
+            if let Some(idx) = bottom_run {
+                let (_, ssts) = snapshot.levels.remove(idx);
+                assert_eq!(ssts, task.lower_level_sst_ids, "sst mismatched");
+            } else {
+                assert!(task.lower_level_sst_ids.is_empty(), "sst mismatched");
+            }
+            files_to_remove.extend(&task.lower_level_sst_ids);
+        }
+        if !output.is_empty() {
+            // The new run is the newest one of its level.
+            let idx = snapshot
+                .levels
+                .iter()
+                .position(|(level, _)| *level >= task.lower_level)
+                .unwrap_or(snapshot.levels.len());
+            snapshot
+                .levels
+                .insert(idx, (task.lower_level, output.to_vec()));
+        }
+        (snapshot, files_to_remove)
+    }
+}
@@ -16,9 +16,10 @@ use std::collections::HashSet;
 
 use serde::{Deserialize, Serialize};
 
+use super::{CompactionScore, find_tombstone_heavy_sst};
 use crate::lsm_storage::LsmStorageState;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct LeveledCompactionTask {
     // if upper_level is `None`, then it is L0 compaction
     pub upper_level: Option<usize>,
@@ -28,7 +29,7 @@ pub struct LeveledCompactionTask {
     pub is_lower_level_bottom_level: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LeveledCompactionOptions {
     pub level_size_multiplier: usize,
     pub level0_file_num_compaction_trigger: usize,
@@ -75,11 +76,8 @@ impl LeveledCompactionController {
         overlap_ssts
     }
 
-    pub fn generate_compaction_task(
-        &self,
-        snapshot: &LsmStorageState,
-    ) -> Option<LeveledCompactionTask> {
-        // step 1: compute target level size
+    /// Compute the target size and real size of each level in bytes, and the base level that L0 is compacted into.
+    fn level_sizes(&self, snapshot: &LsmStorageState) -> (Vec<usize>, Vec<usize>, usize) {
         let mut target_level_size = (0..self.options.max_levels).map(|_| 0).collect::<Vec<_>>(); // exclude level 0
         let mut real_level_size = Vec::with_capacity(self.options.max_levels);
         let mut base_level = self.options.max_levels;
@@ -107,6 +105,15 @@ impl LeveledCompactionController {
                 base_level = i + 1;
             }
         }
+        (target_level_size, real_level_size, base_level)
+    }
+
+    pub fn generate_compaction_task(
+        &self,
+        snapshot: &LsmStorageState,
+    ) -> Option<LeveledCompactionTask> {
+        // step 1: compute target level size
+        let (target_level_size, real_level_size, base_level) = self.level_sizes(snapshot);
 
         // Flush L0 SST is the top priority
         if snapshot.l0_sstables.len() >= self.options.level0_file_num_compaction_trigger {
@@ -169,6 +176,100 @@ impl LeveledCompactionController {
         None
     }
 
+    /// The L0 score is the number of L0 SSTs over the trigger, and the score of the other levels is their size over
+    /// the target size. Levels after L0 are ordered by score, highest first.
+    pub fn compaction_scores(&self, snapshot: &LsmStorageState) -> Vec<CompactionScore> {
+        let (target_level_size, real_level_size, base_level) = self.level_sizes(snapshot);
+        let mut level_scores = (0..self.options.max_levels)
+            .map(|level| CompactionScore {
+                name: format!("L{}", level + 1),
+                score: real_level_size[level] as f64 / target_level_size[level] as f64,
+                detail: format!(
+                    "size {:.3}MB, target {:.3}MB",
+                    real_level_size[level] as f64 / 1024.0 / 1024.0,
+                    target_level_size[level] as f64 / 1024.0 / 1024.0
+                ),
+            })
+            .collect::<Vec<_>>();
+        // A level without target size is empty or only receives L0 SSTs through the base level
+        level_scores.retain(|score| !score.score.is_nan());
+        level_scores.sort_by(|a, b| b.score.total_cmp(&a.score));
+        let mut scores = vec![CompactionScore {
+            name: "L0".to_string(),
+            score: snapshot.l0_sstables.len() as f64
+                / self.options.level0_file_num_compaction_trigger as f64,
+            detail: format!(
+                "{} SSTs, trigger {}, base level L{}",
+                snapshot.l0_sstables.len(),
+                self.options.level0_file_num_compaction_trigger,
+                base_level
+            ),
+        }];
+        scores.extend(level_scores);
+        scores
+    }
+
+    /// Generates a task that rewrites `sst_id` into the next level, or into the same level if it is in the bottom
+    /// level. An L0 SST is compacted along with all other L0 SSTs into the first non-empty level.
+    pub fn generate_periodic_compaction_task(
+        &self,
+        snapshot: &LsmStorageState,
+        sst_id: usize,
+    ) -> Option<LeveledCompactionTask> {
+        if snapshot.l0_sstables.contains(&sst_id) {
+            let base_level = (1..self.options.max_levels)
+                .find(|level| !snapshot.levels[level - 1].1.is_empty())
+                .unwrap_or(self.options.max_levels);
+            return Some(LeveledCompactionTask {
+                upper_level: None,
+                upper_level_sst_ids: snapshot.l0_sstables.clone(),
+                lower_level: base_level,
+                lower_level_sst_ids: self.find_overlapping_ssts(
+                    snapshot,
+                    &snapshot.l0_sstables,
+                    base_level,
+                ),
+                is_lower_level_bottom_level: base_level == self.options.max_levels,
+            });
+        }
+        let level = snapshot
+            .levels
+            .iter()
+            .position(|(_, ssts)| ssts.contains(&sst_id))?
+            + 1;
+        let lower_level = (level + 1).min(self.options.max_levels);
+        Some(LeveledCompactionTask {
+            upper_level: Some(level),
+            upper_level_sst_ids: vec![sst_id],
+            lower_level,
+            lower_level_sst_ids: if lower_level == level {
+                Vec::new()
+            } else {
+                self.find_overlapping_ssts(snapshot, &[sst_id], lower_level)
+            },
+            is_lower_level_bottom_level: lower_level == self.options.max_levels,
+        })
+    }
+
+    /// Generates a task that rewrites the SST with the highest tombstone ratio above `ratio` into the next level,
+    /// unless L0 needs compacting first. See `find_tombstone_heavy_sst` for how it is picked.
+    pub fn generate_tombstone_compaction_task(
+        &self,
+        snapshot: &LsmStorageState,
+        ratio: f64,
+        max_ts: u64,
+    ) -> Option<LeveledCompactionTask> {
+        if snapshot.l0_sstables.len() >= self.options.level0_file_num_compaction_trigger {
+            return None;
+        }
+        let (tombstone_ratio, sst_id) = find_tombstone_heavy_sst(snapshot, ratio, max_ts)?;
+        println!(
+            "tombstone compaction triggered by {sst_id}.sst with {:.1}% deletes",
+            tombstone_ratio * 100.0
+        );
+        self.generate_periodic_compaction_task(snapshot, sst_id)
+    }
+
     pub fn apply_compaction_result(
         &self,
         snapshot: &LsmStorageState,
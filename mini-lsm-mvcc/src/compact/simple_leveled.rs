@@ -16,16 +16,17 @@ use std::collections::HashSet;
 
 use serde::{Deserialize, Serialize};
 
+use super::CompactionScore;
 use crate::lsm_storage::LsmStorageState;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SimpleLeveledCompactionOptions {
     pub size_ratio_percent: usize,
     pub level0_file_num_compaction_trigger: usize,
     pub max_levels: usize,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SimpleLeveledCompactionTask {
     // if upper_level is `None`, then it is L0 compaction
     pub upper_level: Option<usize>,
@@ -103,6 +104,72 @@ impl SimpleLeveledCompactionController {
         None
     }
 
+    /// The L0 score is the number of L0 SSTs over the trigger, and the score of the other levels is the configured size
+    /// ratio over the ratio of the number of SSTs in the next level to this level.
+    pub fn compaction_scores(&self, snapshot: &LsmStorageState) -> Vec<CompactionScore> {
+        let mut scores = vec![CompactionScore {
+            name: "L0".to_string(),
+            score: snapshot.l0_sstables.len() as f64
+                / self.options.level0_file_num_compaction_trigger as f64,
+            detail: format!(
+                "{} SSTs, trigger {}",
+                snapshot.l0_sstables.len(),
+                self.options.level0_file_num_compaction_trigger
+            ),
+        }];
+        for level in 1..self.options.max_levels {
+            let upper = snapshot.levels[level - 1].1.len();
+            let lower = snapshot.levels[level].1.len();
+            let size_ratio = lower as f64 / upper as f64;
+            scores.push(CompactionScore {
+                name: format!("L{level}"),
+                score: if upper == 0 {
+                    0.0
+                } else {
+                    self.options.size_ratio_percent as f64 / 100.0 / size_ratio
+                },
+                detail: format!(
+                    "{upper} SSTs, L{} has {lower} SSTs, size ratio target {}%",
+                    level + 1,
+                    self.options.size_ratio_percent
+                ),
+            });
+        }
+        scores
+    }
+
+    /// Generates a task that compacts the level holding `sst_id` into the next level, or rewrites the bottom level if
+    /// `sst_id` is in it.
+    pub fn generate_periodic_compaction_task(
+        &self,
+        snapshot: &LsmStorageState,
+        sst_id: usize,
+    ) -> Option<SimpleLeveledCompactionTask> {
+        let (upper_level, upper_level_sst_ids) = if snapshot.l0_sstables.contains(&sst_id) {
+            (None, snapshot.l0_sstables.clone())
+        } else {
+            let idx = snapshot
+                .levels
+                .iter()
+                .position(|(_, ssts)| ssts.contains(&sst_id))?;
+            (Some(idx + 1), snapshot.levels[idx].1.clone())
+        };
+        let level = upper_level.unwrap_or(0);
+        let lower_level = (level + 1).min(self.options.max_levels);
+        Some(SimpleLeveledCompactionTask {
+            upper_level,
+            upper_level_sst_ids,
+            lower_level,
+            // The bottom level is cleared before its output is installed when compacted into itself.
+            lower_level_sst_ids: if lower_level == level {
+                Vec::new()
+            } else {
+                snapshot.levels[lower_level - 1].1.clone()
+            },
+            is_lower_level_bottom_level: lower_level == self.options.max_levels,
+        })
+    }
+
     /// Apply the compaction result.
     ///
     /// The compactor will call this function with the compaction task and the list of SST ids generated. This function applies the
@@ -16,15 +16,16 @@ use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
 
+use super::CompactionScore;
 use crate::lsm_storage::LsmStorageState;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TieredCompactionTask {
     pub tiers: Vec<(usize, Vec<usize>)>,
     pub bottom_tier_included: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TieredCompactionOptions {
     pub num_tiers: usize,
     pub max_size_amplification_percent: usize,
@@ -112,6 +113,73 @@ impl TieredCompactionController {
         })
     }
 
+    /// Scores in the order the triggers are checked: the space amplification over its limit, the size ratio of each
+    /// tier to the tiers above it over the trigger, and the number of tiers over the limit. No compaction happens until
+    /// the number of tiers reaches the limit.
+    pub fn compaction_scores(&self, snapshot: &LsmStorageState) -> Vec<CompactionScore> {
+        let mut scores = Vec::new();
+        if let Some((_, last_tier)) = snapshot.levels.last() {
+            let upper_size = snapshot.levels[..snapshot.levels.len() - 1]
+                .iter()
+                .map(|(_, tier)| tier.len())
+                .sum::<usize>();
+            let space_amp_ratio = upper_size as f64 / last_tier.len() as f64 * 100.0;
+            scores.push(CompactionScore {
+                name: "space amplification".to_string(),
+                score: space_amp_ratio / self.options.max_size_amplification_percent as f64,
+                detail: format!(
+                    "{:.1}%, limit {}%",
+                    space_amp_ratio, self.options.max_size_amplification_percent
+                ),
+            });
+        }
+        let size_ratio_trigger = (100.0 + self.options.size_ratio as f64) / 100.0;
+        let mut size = 0;
+        for id in 0..snapshot.levels.len().saturating_sub(1) {
+            size += snapshot.levels[id].1.len();
+            if id + 1 < self.options.min_merge_width {
+                continue;
+            }
+            let next_level_size = snapshot.levels[id + 1].1.len();
+            let current_size_ratio = next_level_size as f64 / size as f64;
+            scores.push(CompactionScore {
+                name: format!("size ratio of tier {}", snapshot.levels[id + 1].0),
+                score: current_size_ratio / size_ratio_trigger,
+                detail: format!(
+                    "{:.1}%, trigger {:.1}%",
+                    current_size_ratio * 100.0,
+                    size_ratio_trigger * 100.0
+                ),
+            });
+        }
+        scores.push(CompactionScore {
+            name: "tiers".to_string(),
+            score: snapshot.levels.len() as f64 / self.options.num_tiers as f64,
+            detail: format!(
+                "{} tiers, limit {}",
+                snapshot.levels.len(),
+                self.options.num_tiers
+            ),
+        });
+        scores
+    }
+
+    /// Generates a task that compacts the tier holding `sst_id` with all tiers below it.
+    pub fn generate_periodic_compaction_task(
+        &self,
+        snapshot: &LsmStorageState,
+        sst_id: usize,
+    ) -> Option<TieredCompactionTask> {
+        let idx = snapshot
+            .levels
+            .iter()
+            .position(|(_, ssts)| ssts.contains(&sst_id))?;
+        Some(TieredCompactionTask {
+            tiers: snapshot.levels[idx..].to_vec(),
+            bottom_tier_included: true,
+        })
+    }
+
     pub fn apply_compaction_result(
         &self,
         snapshot: &LsmStorageState,
@@ -141,9 +209,11 @@ impl TieredCompactionController {
                 levels.push((*tier_id, files.clone()));
             }
             if tier_to_remove.is_empty() && !new_tier_added {
-                // add the compacted tier to the LSM tree
+                // add the compacted tier to the LSM tree, unless everything in it has been removed
                 new_tier_added = true;
-                levels.push((output[0], output.to_vec()));
+                if !output.is_empty() {
+                    levels.push((output[0], output.to_vec()));
+                }
             }
         }
         if !tier_to_remove.is_empty() {
@@ -0,0 +1,136 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Verification of compaction results, enabled by `LsmStorageOptions::verify_compaction`.
+//!
+//! Compaction may drop versions that no read can see anymore, so the inputs and the outputs are compared by what is
+//! visible at or above the watermark: every version newer than the watermark, and the newest version at or below it,
+//! unless compaction is allowed to drop that one.
+
+use std::sync::Arc;
+
+use anyhow::{Result, bail};
+use bytes::Bytes;
+
+use super::CompactionTask;
+use crate::iterators::StorageIterator;
+use crate::iterators::merge_iterator::MergeIterator;
+use crate::lsm_error::Error;
+use crate::lsm_storage::{CompactionFilter, LsmStorageInner, LsmStorageState};
+use crate::table::{SsTable, SsTableIterator};
+
+/// The versions of a key visible at or above the watermark, newest first.
+type VisibleVersions = (Vec<u8>, Vec<(u64, Bytes)>);
+
+struct VisibleIterator<'a> {
+    iter: MergeIterator<SsTableIterator>,
+    watermark: u64,
+    compact_to_bottom_level: bool,
+    compaction_filters: &'a [CompactionFilter],
+}
+
+impl VisibleIterator<'_> {
+    /// Return the next key that has a visible version.
+    fn next_key(&mut self) -> Result<Option<VisibleVersions>> {
+        while self.iter.is_valid() {
+            let key = self.iter.key().key_ref().to_vec();
+            let mut versions = Vec::new();
+            let mut below_watermark_seen = false;
+            while self.iter.is_valid() && self.iter.key().key_ref() == key {
+                let ts = self.iter.key().ts();
+                if ts > self.watermark {
+                    versions.push((ts, Bytes::copy_from_slice(self.iter.value())));
+                } else if !below_watermark_seen {
+                    below_watermark_seen = true;
+                    // A tombstone hiding nothing newer can be removed at the bottom level
+                    let dropped = (self.compact_to_bottom_level
+                        && versions.is_empty()
+                        && self.iter.value().is_empty())
+                        || self.compaction_filters.iter().any(|filter| match filter {
+                            CompactionFilter::Prefix(prefix) => key.starts_with(prefix),
+                        });
+                    if !dropped {
+                        versions.push((ts, Bytes::copy_from_slice(self.iter.value())));
+                    }
+                }
+                self.iter.next()?;
+            }
+            if !versions.is_empty() {
+                return Ok(Some((key, versions)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn merge_ssts(
+    ssts: impl IntoIterator<Item = Arc<SsTable>>,
+) -> Result<MergeIterator<SsTableIterator>> {
+    let mut iters = Vec::new();
+    for sst in ssts {
+        iters.push(Box::new(SsTableIterator::create_and_seek_to_first(sst)?));
+    }
+    Ok(MergeIterator::create(iters))
+}
+
+impl LsmStorageInner {
+    /// Check that the outputs of `task`, produced at `watermark`, hold the same visible versions as its inputs.
+    pub(crate) fn verify_compaction(
+        &self,
+        task: &CompactionTask,
+        snapshot: &LsmStorageState,
+        output: &[Arc<SsTable>],
+        watermark: u64,
+    ) -> Result<()> {
+        let compaction_filters = self.compaction_filters.lock().clone();
+        let compact_to_bottom_level = task.compact_to_bottom_level();
+        // Versions of a key carry distinct timestamps, so the order of the inputs does not matter when merging
+        let mut inputs = VisibleIterator {
+            iter: merge_ssts(
+                task.input_sst_ids()
+                    .iter()
+                    .map(|id| snapshot.sstables[id].clone()),
+            )?,
+            watermark,
+            compact_to_bottom_level,
+            compaction_filters: &compaction_filters,
+        };
+        let mut outputs = VisibleIterator {
+            iter: merge_ssts(output.iter().cloned())?,
+            watermark,
+            compact_to_bottom_level,
+            compaction_filters: &compaction_filters,
+        };
+        loop {
+            let (input, output) = (inputs.next_key()?, outputs.next_key()?);
+            if input != output {
+                let key = |versions: &Option<VisibleVersions>| {
+                    versions
+                        .as_ref()
+                        .map(|(key, _)| Bytes::copy_from_slice(key))
+                };
+                bail!(Error::Corruption(format!(
+                    "compaction {:?} changed the visible versions at watermark {}: input key {:?}, output key {:?}",
+                    task,
+                    watermark,
+                    key(&input),
+                    key(&output)
+                )));
+            }
+            if input.is_none() {
+                return Ok(());
+            }
+        }
+    }
+}
@@ -0,0 +1,46 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Hooks on the background work of the storage engine, e.g., to log it, alert on it, or coordinate external systems
+//! such as a backup tool copying the SSTs.
+
+use crate::compact::CompactionTask;
+
+/// Notified of flushes, compactions, SST deletions and write stalls. All hooks do nothing by default.
+///
+/// Hooks run synchronously on the thread doing the work, usually a background thread, and some of them with the state
+/// lock held, so they should return quickly and must not call back into the storage engine.
+pub trait EventListener: Send + Sync {
+    /// Before the immutable memtables with `memtable_ids` are flushed, the earliest-created first.
+    fn on_flush_begin(&self, _memtable_ids: &[usize]) {}
+
+    /// After the immutable memtables with `memtable_ids` are flushed and the flush is recorded. `sst_ids` holds the
+    /// SSTs each memtable was flushed to in the same order, the first of which has the id of the memtable.
+    fn on_flush_completed(&self, _memtable_ids: &[usize], _sst_ids: &[Vec<usize>]) {}
+
+    /// Before `task` is compacted.
+    fn on_compaction_begin(&self, _task: &CompactionTask) {}
+
+    /// After the output of `task` is installed and the compaction is recorded, but before its inputs are deleted.
+    /// `output` holds the ids of the new SSTs.
+    fn on_compaction_completed(&self, _task: &CompactionTask, _output: &[usize]) {}
+
+    /// After the file of an SST replaced by a compaction is deleted.
+    fn on_table_deleted(&self, _sst_id: usize) {}
+
+    /// When the immutable memtables waiting for a flush come to outnumber `LsmStorageOptions::num_memtable_limit`,
+    /// i.e., the flushes fall behind the writes, with `stalled` set, and once they catch up again with it cleared. The
+    /// writes are not throttled meanwhile, so the memtables keep piling up in memory.
+    fn on_write_stall(&self, _stalled: bool, _num_imm_memtables: usize) {}
+}
@@ -0,0 +1,275 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Export a point-in-time view of the storage engine, either as a standalone set of SSTs, as a new database holding a
+//! range of keys, or as CSV or JSON lines.
+
+use std::io::Write;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result, bail};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+use crate::import::DataFormat;
+use crate::iterators::StorageIterator;
+use crate::key::KeySlice;
+use crate::lsm_error::{self, Error};
+use crate::lsm_storage::{LsmStorageInner, LsmStorageOptions, MiniLsm};
+use crate::platform;
+use crate::table::SstOrigin;
+
+/// The name of the manifest file written into an export directory.
+pub const EXPORT_MANIFEST_NAME: &str = "EXPORT";
+
+/// Describes an exported snapshot. The SSTs are sorted and non-overlapping, contain a single version of each key
+/// (with the export timestamp), no tombstones, and can be opened with `SsTable::open` without a block cache.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportManifest {
+    /// The timestamp of the exported view.
+    pub ts: u64,
+    /// The SST ids in key order. See `path_of_sst` for the file names.
+    pub sst_ids: Vec<usize>,
+}
+
+impl ExportManifest {
+    pub fn read(dir: impl AsRef<Path>) -> Result<Self> {
+        let path = dir.as_ref().join(EXPORT_MANIFEST_NAME);
+        let data = std::fs::read(&path).context("failed to read export manifest")?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    pub fn path_of_sst(dir: impl AsRef<Path>, id: usize) -> PathBuf {
+        LsmStorageInner::path_of_sst_static(dir, id)
+    }
+}
+
+impl LsmStorageInner {
+    pub fn export_snapshot(&self, dir: impl AsRef<Path>, ts: u64) -> Result<ExportManifest> {
+        let dir = dir.as_ref();
+        if dir.exists() && std::fs::read_dir(dir)?.next().is_some() {
+            bail!(Error::InvalidArgument(format!(
+                "export directory {} is not empty",
+                dir.display()
+            )));
+        }
+        {
+            // Register the snapshot as a reader so that compactions keep the versions visible at `ts`.
+            let mut mvcc_ts = self.mvcc().ts.lock();
+            let (latest_commit_ts, watermark) = &mut *mvcc_ts;
+            let lowest_retained_ts = self.mvcc().gc_watermark();
+            if ts > *latest_commit_ts || ts < lowest_retained_ts {
+                bail!(Error::InvalidArgument(format!(
+                    "cannot export at ts={}, only [{}, {}] is retained",
+                    ts, lowest_retained_ts, latest_commit_ts
+                )));
+            }
+            watermark.add_reader(ts);
+        }
+        let result = self.export_snapshot_inner(dir, ts);
+        self.mvcc().ts.lock().1.remove_reader(ts);
+        result
+    }
+
+    fn export_snapshot_inner(&self, dir: &Path, ts: u64) -> Result<ExportManifest> {
+        std::fs::create_dir_all(dir)?;
+        let mut iter = self.scan_with_ts(Bound::Unbounded, Bound::Unbounded, ts)?;
+        let mut sst_ids = Vec::new();
+        let mut builder = None;
+        while iter.is_valid() {
+            let builder_inner =
+                builder.get_or_insert_with(|| self.new_sst_builder(SstOrigin::Export));
+            builder_inner.add(KeySlice::from_slice(iter.key(), ts), iter.value());
+            iter.next()?;
+            if builder_inner.estimated_size() >= self.options().target_sst_size || !iter.is_valid()
+            {
+                let sst_id = sst_ids.len();
+                builder.take().unwrap().build(
+                    sst_id,
+                    None,
+                    Self::path_of_sst_static(dir, sst_id),
+                )?;
+                sst_ids.push(sst_id);
+            }
+        }
+        let manifest = ExportManifest { ts, sst_ids };
+        let manifest_path = dir.join(EXPORT_MANIFEST_NAME);
+        platform::write_synced(&manifest_path, &serde_json::to_vec(&manifest)?)?;
+        platform::sync_dir(dir)?;
+        Ok(manifest)
+    }
+}
+
+impl LsmStorageInner {
+    /// Create a database in `dir` holding the latest version of each key in the range, compacted into its bottom level,
+    /// with the options of this one. `dir` must not exist or be empty. Returns the number of keys copied.
+    pub fn export_range_as_db(
+        self: &Arc<Self>,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        dir: impl AsRef<Path>,
+    ) -> Result<usize> {
+        let dir = dir.as_ref();
+        if dir.exists() && std::fs::read_dir(dir)?.next().is_some() {
+            bail!(Error::InvalidArgument(format!(
+                "export directory {} is not empty",
+                dir.display()
+            )));
+        }
+        let target = LsmStorageInner::open(
+            dir,
+            LsmStorageOptions {
+                create_if_missing: true,
+                error_if_exists: false,
+                read_only: false,
+                ..self.options().as_ref().clone()
+            },
+        )?;
+        // the transaction pins the snapshot being copied
+        let txn = self.new_txn()?;
+        let mut iter = txn.scan(lower, upper)?;
+        let mut started = false;
+        let entries = std::iter::from_fn(|| {
+            if started && let Err(e) = iter.next() {
+                return Some(Err(e));
+            }
+            started = true;
+            iter.is_valid().then(|| {
+                Ok((
+                    Bytes::copy_from_slice(iter.key()),
+                    Bytes::copy_from_slice(iter.value()),
+                ))
+            })
+        });
+        let summary = target.ingest_sorted(entries)?;
+        target.compact_all_to_bottom_level()?;
+        if target.options().enable_wal {
+            target.sync()?;
+        }
+        Ok(summary.num_entries)
+    }
+}
+
+impl MiniLsm {
+    /// Create a self-contained database in `dir` holding only the keys in the range as of now, compacted, e.g., to
+    /// move a tenant elsewhere. It can be opened with `MiniLsm::open` and the options of this one. `dir` must not exist
+    /// or be empty. Returns the number of keys copied.
+    pub fn export_range_as_db(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        dir: impl AsRef<Path>,
+    ) -> lsm_error::Result<usize> {
+        Ok(self.inner.export_range_as_db(lower, upper, dir)?)
+    }
+}
+
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    key: &'a str,
+    value: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ts: Option<u64>,
+}
+
+/// Write a CSV field, quoting it if needed.
+fn write_csv_field(writer: &mut impl Write, field: &[u8]) -> Result<()> {
+    if field
+        .iter()
+        .any(|c| matches!(c, b',' | b'"' | b'\n' | b'\r'))
+    {
+        writer.write_all(b"\"")?;
+        for chunk in field.split_inclusive(|c| *c == b'"') {
+            writer.write_all(chunk)?;
+            if chunk.ends_with(b"\"") {
+                writer.write_all(b"\"")?;
+            }
+        }
+        writer.write_all(b"\"")?;
+    } else {
+        writer.write_all(field)?;
+    }
+    Ok(())
+}
+
+fn to_json_str<'a>(data: &'a [u8], what: &str, key: &[u8]) -> Result<&'a str> {
+    std::str::from_utf8(data).map_err(|_| {
+        Error::InvalidArgument(format!(
+            "{} of key {:?} is not valid UTF-8 and cannot be exported as JSON",
+            what,
+            bytes::Bytes::copy_from_slice(key)
+        ))
+        .into()
+    })
+}
+
+impl LsmStorageInner {
+    pub fn export(
+        self: &Arc<Self>,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        format: DataFormat,
+        with_ts: bool,
+        mut writer: impl Write,
+    ) -> Result<usize> {
+        // Keep a transaction open so that the versions being exported are not garbage-collected.
+        let txn = self.new_txn()?;
+        let mut iter = self.scan_with_ts(lower, upper, txn.read_ts())?;
+        let mut cnt = 0;
+        while iter.is_valid() {
+            let ts = with_ts.then(|| iter.ts());
+            match format {
+                DataFormat::Csv => {
+                    write_csv_field(&mut writer, iter.key())?;
+                    writer.write_all(b",")?;
+                    write_csv_field(&mut writer, iter.value())?;
+                    if let Some(ts) = ts {
+                        write!(writer, ",{}", ts)?;
+                    }
+                    writer.write_all(b"\n")?;
+                }
+                DataFormat::Jsonl => {
+                    let record = JsonRecord {
+                        key: to_json_str(iter.key(), "key", iter.key())?,
+                        value: to_json_str(iter.value(), "value", iter.key())?,
+                        ts,
+                    };
+                    serde_json::to_writer(&mut writer, &record)?;
+                    writer.write_all(b"\n")?;
+                }
+            }
+            cnt += 1;
+            iter.next()?;
+        }
+        writer.flush()?;
+        Ok(cnt)
+    }
+}
+
+impl MiniLsm {
+    /// Write the keys in the range as CSV (`key,value[,ts]`) or JSON lines (`{"key", "value"[, "ts"]}`), which can be
+    /// loaded back with `MiniLsm::import`. Returns the number of records written.
+    pub fn export(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        format: DataFormat,
+        with_ts: bool,
+        writer: impl Write,
+    ) -> lsm_error::Result<usize> {
+        Ok(self.inner.export(lower, upper, format, with_ts, writer)?)
+    }
+}
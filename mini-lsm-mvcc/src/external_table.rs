@@ -0,0 +1,374 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A reader for LevelDB and RocksDB table files, used to migrate data into the engine.
+//!
+//! Supported are LevelDB tables and RocksDB block-based tables up to `format_version` 5 with the bytewise
+//! comparator, a binary search or hash index, no compression or Snappy compression, and CRC32C (or no) checksums.
+//! Merge operands, range deletions and blob references are rejected.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+
+use crate::checksum::crc32c;
+use crate::ingest::IngestSummary;
+use crate::lsm_error::{self, Error};
+use crate::lsm_storage::MiniLsm;
+
+const LEGACY_MAGIC: u64 = 0xdb4775248b80fb57;
+const BLOCK_BASED_MAGIC: u64 = 0x88e241b785f4cff7;
+const LEGACY_FOOTER_LEN: usize = 48;
+const FOOTER_LEN: usize = 53;
+const MAX_FORMAT_VERSION: u32 = 5;
+const BLOCK_TRAILER_LEN: usize = 5;
+
+const NO_CHECKSUM: u8 = 0;
+const CRC32C_CHECKSUM: u8 = 1;
+const NO_COMPRESSION: u8 = 0;
+const SNAPPY_COMPRESSION: u8 = 1;
+
+const TYPE_DELETION: u8 = 0x0;
+const TYPE_VALUE: u8 = 0x1;
+const TYPE_SINGLE_DELETION: u8 = 0x7;
+
+const PROPERTIES_BLOCK: &[u8] = b"rocksdb.properties";
+const RANGE_DEL_BLOCK: &[u8] = b"rocksdb.range_del";
+const PROP_COMPARATOR: &[u8] = b"rocksdb.comparator";
+const PROP_INDEX_TYPE: &[u8] = b"rocksdb.block.based.table.index.type";
+const PROP_INDEX_VALUE_DELTA_ENCODED: &[u8] = b"rocksdb.index.value.is.delta.encoded";
+const PROP_NUM_RANGE_DELETIONS: &[u8] = b"rocksdb.num.range-deletions";
+const BYTEWISE_COMPARATOR: &[u8] = b"leveldb.BytewiseComparator";
+/// Index types that keep a single binary-searchable index block.
+const INDEX_TYPE_BINARY_SEARCH: u32 = 0;
+const INDEX_TYPE_HASH_SEARCH: u32 = 1;
+
+fn corruption(msg: &str) -> anyhow::Error {
+    Error::Corruption(format!("external table: {}", msg)).into()
+}
+
+fn unsupported(msg: String) -> anyhow::Error {
+    Error::InvalidArgument(format!("unsupported external table: {}", msg)).into()
+}
+
+/// LevelDB and RocksDB store checksums masked, as computing the CRC of a string containing embedded CRCs is
+/// problematic.
+pub(crate) fn mask_crc32c(crc: u32) -> u32 {
+    crc.rotate_right(15).wrapping_add(0xa282ead8)
+}
+
+fn get_varint(buf: &mut &[u8]) -> Result<u64> {
+    let mut result = 0u64;
+    for shift in (0..64).step_by(7) {
+        let Some((&byte, rest)) = buf.split_first() else {
+            return Err(corruption("truncated varint"));
+        };
+        *buf = rest;
+        result |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(result);
+        }
+    }
+    Err(corruption("varint too long"))
+}
+
+fn get_signed_varint(buf: &mut &[u8]) -> Result<i64> {
+    let n = get_varint(buf)?;
+    Ok(((n >> 1) as i64) ^ -((n & 1) as i64))
+}
+
+fn get_slice<'a>(buf: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if buf.len() < len {
+        return Err(corruption("truncated entry"));
+    }
+    let (slice, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(slice)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct BlockHandle {
+    offset: u64,
+    size: u64,
+}
+
+impl BlockHandle {
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        Ok(Self {
+            offset: get_varint(buf)?,
+            size: get_varint(buf)?,
+        })
+    }
+}
+
+/// Decode the key-value entries of a block. If `delta_handles` is set, the block is an index block whose entries
+/// have no value length and whose handles are delta-encoded within a restart interval.
+fn decode_block(block: &[u8], delta_handles: bool) -> Result<Vec<(Vec<u8>, Bytes)>> {
+    if block.len() < 4 {
+        return Err(corruption("block too small"));
+    }
+    let footer = u32::from_le_bytes(block[block.len() - 4..].try_into().unwrap());
+    // The highest bit marks a data block hash index after the restart array, see `DataBlockHashIndex` in RocksDB.
+    let num_restarts = (footer & 0x7fff_ffff) as usize;
+    let mut end = block.len() - 4;
+    if footer & 0x8000_0000 != 0 {
+        if end < 2 {
+            return Err(corruption("block too small"));
+        }
+        let num_buckets = u16::from_le_bytes([block[end - 2], block[end - 1]]) as usize;
+        end = end
+            .checked_sub(2 + num_buckets)
+            .ok_or_else(|| corruption("bad data block hash index"))?;
+    }
+    let end = end
+        .checked_sub(num_restarts * 4)
+        .ok_or_else(|| corruption("bad restart array"))?;
+
+    let mut buf = &block[..end];
+    let mut entries = Vec::new();
+    let mut key = Vec::new();
+    let mut last_handle: Option<BlockHandle> = None;
+    while !buf.is_empty() {
+        let shared = get_varint(&mut buf)? as usize;
+        let non_shared = get_varint(&mut buf)? as usize;
+        if shared > key.len() {
+            return Err(corruption("bad shared key length"));
+        }
+        if delta_handles {
+            key.truncate(shared);
+            key.extend_from_slice(get_slice(&mut buf, non_shared)?);
+            let handle = match last_handle {
+                Some(last) if shared > 0 => {
+                    let delta = get_signed_varint(&mut buf)?;
+                    BlockHandle {
+                        offset: last.offset + last.size + BLOCK_TRAILER_LEN as u64,
+                        size: last.size.wrapping_add_signed(delta),
+                    }
+                }
+                _ => BlockHandle::decode(&mut buf)?,
+            };
+            last_handle = Some(handle);
+            let mut value = Vec::new();
+            put_varint(&mut value, handle.offset);
+            put_varint(&mut value, handle.size);
+            entries.push((key.clone(), Bytes::from(value)));
+        } else {
+            let value_len = get_varint(&mut buf)? as usize;
+            key.truncate(shared);
+            key.extend_from_slice(get_slice(&mut buf, non_shared)?);
+            let value = get_slice(&mut buf, value_len)?;
+            entries.push((key.clone(), Bytes::copy_from_slice(value)));
+        }
+    }
+    Ok(entries)
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        buf.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+/// A LevelDB or RocksDB table file loaded into memory.
+pub struct ExternalTable {
+    data: Vec<u8>,
+    checksum_type: u8,
+    /// Handles of the data blocks, in key order.
+    data_blocks: Vec<BlockHandle>,
+}
+
+impl ExternalTable {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let data = std::fs::read(path.as_ref()).context("failed to read external table")?;
+        Self::decode(data)
+    }
+
+    pub fn decode(data: Vec<u8>) -> Result<Self> {
+        if data.len() < LEGACY_FOOTER_LEN {
+            return Err(corruption("file too small"));
+        }
+        let magic = u64::from_le_bytes(data[data.len() - 8..].try_into().unwrap());
+        let (checksum_type, mut footer) = match magic {
+            LEGACY_MAGIC => (CRC32C_CHECKSUM, &data[data.len() - LEGACY_FOOTER_LEN..]),
+            BLOCK_BASED_MAGIC => {
+                if data.len() < FOOTER_LEN {
+                    return Err(corruption("file too small"));
+                }
+                let footer = &data[data.len() - FOOTER_LEN..];
+                let format_version =
+                    u32::from_le_bytes(footer[FOOTER_LEN - 12..FOOTER_LEN - 8].try_into().unwrap());
+                if format_version > MAX_FORMAT_VERSION {
+                    return Err(unsupported(format!("format_version {}", format_version)));
+                }
+                (footer[0], &footer[1..])
+            }
+            _ => return Err(unsupported(format!("magic number {:#x}", magic))),
+        };
+        if checksum_type != NO_CHECKSUM && checksum_type != CRC32C_CHECKSUM {
+            return Err(unsupported(format!("checksum type {}", checksum_type)));
+        }
+        let metaindex_handle = BlockHandle::decode(&mut footer)?;
+        let index_handle = BlockHandle::decode(&mut footer)?;
+
+        let mut table = Self {
+            data,
+            checksum_type,
+            data_blocks: Vec::new(),
+        };
+        let mut delta_handles = false;
+        for (name, value) in decode_block(&table.read_block(metaindex_handle)?, false)? {
+            if name == PROPERTIES_BLOCK {
+                let properties = table.read_block(BlockHandle::decode(&mut &value[..])?)?;
+                delta_handles = Self::check_properties(&decode_block(&properties, false)?)?;
+            } else if name == RANGE_DEL_BLOCK {
+                let range_dels = table.read_block(BlockHandle::decode(&mut &value[..])?)?;
+                if !decode_block(&range_dels, false)?.is_empty() {
+                    return Err(unsupported("range deletions".to_string()));
+                }
+            }
+        }
+        table.data_blocks = decode_block(&table.read_block(index_handle)?, delta_handles)?
+            .into_iter()
+            .map(|(_, handle)| BlockHandle::decode(&mut &handle[..]))
+            .collect::<Result<_>>()?;
+        Ok(table)
+    }
+
+    /// Validate the table properties, and return whether the index block uses delta-encoded block handles.
+    fn check_properties(properties: &[(Vec<u8>, Bytes)]) -> Result<bool> {
+        let mut delta_handles = false;
+        for (name, value) in properties {
+            let name = name.as_slice();
+            if name == PROP_COMPARATOR && value.as_ref() != BYTEWISE_COMPARATOR {
+                return Err(unsupported(format!(
+                    "comparator {}",
+                    String::from_utf8_lossy(value)
+                )));
+            } else if name == PROP_INDEX_TYPE {
+                let index_type = u32::from_le_bytes(
+                    value
+                        .as_ref()
+                        .try_into()
+                        .map_err(|_| corruption("bad index type"))?,
+                );
+                if index_type != INDEX_TYPE_BINARY_SEARCH && index_type != INDEX_TYPE_HASH_SEARCH {
+                    return Err(unsupported(format!("index type {}", index_type)));
+                }
+            } else if name == PROP_INDEX_VALUE_DELTA_ENCODED {
+                delta_handles = get_varint(&mut &value[..])? != 0;
+            } else if name == PROP_NUM_RANGE_DELETIONS && get_varint(&mut &value[..])? != 0 {
+                return Err(unsupported("range deletions".to_string()));
+            }
+        }
+        Ok(delta_handles)
+    }
+
+    /// Read a block, verify its checksum and decompress it.
+    fn read_block(&self, handle: BlockHandle) -> Result<Vec<u8>> {
+        let start = handle.offset as usize;
+        let end = start
+            .checked_add(handle.size as usize + BLOCK_TRAILER_LEN)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| corruption("block handle out of range"))?;
+        let block = &self.data[start..end - BLOCK_TRAILER_LEN];
+        let compression_type = self.data[end - BLOCK_TRAILER_LEN];
+        if self.checksum_type == CRC32C_CHECKSUM {
+            let expected = u32::from_le_bytes(self.data[end - 4..end].try_into().unwrap());
+            if mask_crc32c(crc32c(&self.data[start..end - 4])) != expected {
+                return Err(corruption("block checksum mismatched"));
+            }
+        }
+        match compression_type {
+            NO_COMPRESSION => Ok(block.to_vec()),
+            SNAPPY_COMPRESSION => snap::raw::Decoder::new()
+                .decompress_vec(block)
+                .map_err(|e| corruption(&format!("bad snappy block: {}", e))),
+            _ => Err(unsupported(format!(
+                "compression type {}",
+                compression_type
+            ))),
+        }
+    }
+
+    /// Iterate over the newest version of each key in key order. Deleted keys have an empty value.
+    pub fn iter(&self) -> ExternalTableIterator<'_> {
+        ExternalTableIterator {
+            table: self,
+            next_block: 0,
+            entries: Vec::new().into_iter(),
+            last_user_key: None,
+        }
+    }
+}
+
+pub struct ExternalTableIterator<'a> {
+    table: &'a ExternalTable,
+    next_block: usize,
+    entries: std::vec::IntoIter<(Vec<u8>, Bytes)>,
+    last_user_key: Option<Vec<u8>>,
+}
+
+impl ExternalTableIterator<'_> {
+    fn next_entry(&mut self) -> Result<Option<(Bytes, Bytes)>> {
+        loop {
+            let Some((key, value)) = self.entries.next() else {
+                let Some(handle) = self.table.data_blocks.get(self.next_block) else {
+                    return Ok(None);
+                };
+                self.next_block += 1;
+                self.entries = decode_block(&self.table.read_block(*handle)?, false)?.into_iter();
+                continue;
+            };
+            // Internal keys are the user key followed by `(sequence << 8) | type`, sorted by user key and then by
+            // sequence in descending order, so the first entry of a user key is its newest version.
+            if key.len() < 8 {
+                return Err(corruption("internal key too short"));
+            }
+            let (user_key, tag) = key.split_at(key.len() - 8);
+            if self.last_user_key.as_deref() == Some(user_key) {
+                continue;
+            }
+            self.last_user_key = Some(user_key.to_vec());
+            let value = match tag[0] {
+                TYPE_VALUE => value,
+                TYPE_DELETION | TYPE_SINGLE_DELETION => Bytes::new(),
+                value_type => return Err(unsupported(format!("value type {:#x}", value_type))),
+            };
+            return Ok(Some((Bytes::copy_from_slice(user_key), value)));
+        }
+    }
+}
+
+impl Iterator for ExternalTableIterator<'_> {
+    type Item = Result<(Bytes, Bytes)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_entry().transpose()
+    }
+}
+
+impl MiniLsm {
+    /// Ingest a LevelDB or RocksDB table file. When importing several files of the same database, import older files
+    /// (lower levels first, and older files within L0) before newer ones, so that newer versions win.
+    pub fn import_external_table(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> lsm_error::Result<IngestSummary> {
+        let table = ExternalTable::open(path)?;
+        self.ingest_sorted(table.iter())
+    }
+}
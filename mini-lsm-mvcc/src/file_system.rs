@@ -0,0 +1,229 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The storage under the SSTs and WALs of the engine. Files are only read positionally, written to by appending or
+//! overwriting from the start, and never mapped into memory, so that a sandbox without `std::fs`, e.g., a WASM runtime
+//! embedding the engine, can provide them. The manifest and the other metadata files, and the files copied by
+//! replication and exports, still go through `std::fs`.
+
+use std::fmt::Debug;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::platform;
+
+pub trait FileSystem: Send + Sync + Debug {
+    /// Open the file at `path` for positional reads.
+    fn open_read(&self, path: &Path) -> io::Result<Box<dyn ReadFile>>;
+
+    /// Write `data` to a new file at `path`, replacing any existing one, and make it durable.
+    fn write_synced(&self, path: &Path, data: &[u8]) -> io::Result<()>;
+
+    /// Create a file at `path` for writing, failing if it exists.
+    fn create_new(&self, path: &Path) -> io::Result<Box<dyn WriteFile>>;
+
+    /// Open the existing file at `path` for writing, at its beginning.
+    fn open_write(&self, path: &Path) -> io::Result<Box<dyn WriteFile>>;
+
+    /// Read the whole file at `path`.
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    /// Rename the file at `from` to `to`, replacing any file at `to`.
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+
+    /// The paths of the files in `dir`.
+    fn list_dir(&self, dir: &Path) -> io::Result<Vec<PathBuf>>;
+}
+
+/// A file opened by [`FileSystem::open_read`], shared by concurrent readers.
+pub trait ReadFile: Send + Sync {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()>;
+
+    fn size(&self) -> io::Result<u64>;
+}
+
+/// A file opened by [`FileSystem::create_new`] or [`FileSystem::open_write`]. It is synced while another thread
+/// writes to it, so it takes `&self` throughout.
+pub trait WriteFile: Send + Sync {
+    /// Write at the position of the file, and advance it.
+    fn write(&self, buf: &[u8]) -> io::Result<usize>;
+
+    fn write_all(&self, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.write(buf) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => buf = &buf[n..],
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn seek(&self, offset: u64) -> io::Result<()>;
+
+    fn set_len(&self, len: u64) -> io::Result<()>;
+
+    /// Make the bytes written so far durable.
+    fn sync(&self) -> io::Result<()>;
+}
+
+/// The file system of the storage. Files go through `std::fs` by default, which is the host file system, or the
+/// preopened directories on wasm32-wasip1, while an external one cannot be serialized.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub enum FileSystemOptions {
+    #[default]
+    Std,
+    #[serde(skip)]
+    External(Arc<dyn FileSystem>),
+}
+
+impl FileSystemOptions {
+    fn get(&self) -> &dyn FileSystem {
+        match self {
+            FileSystemOptions::Std => &StdFileSystem,
+            FileSystemOptions::External(file_system) => file_system.as_ref(),
+        }
+    }
+}
+
+impl FileSystem for FileSystemOptions {
+    fn open_read(&self, path: &Path) -> io::Result<Box<dyn ReadFile>> {
+        self.get().open_read(path)
+    }
+
+    fn write_synced(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        self.get().write_synced(path, data)
+    }
+
+    fn create_new(&self, path: &Path) -> io::Result<Box<dyn WriteFile>> {
+        self.get().create_new(path)
+    }
+
+    fn open_write(&self, path: &Path) -> io::Result<Box<dyn WriteFile>> {
+        self.get().open_write(path)
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.get().read(path)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.get().rename(from, to)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.get().remove_file(path)
+    }
+
+    fn list_dir(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        self.get().list_dir(dir)
+    }
+}
+
+/// The files of `std::fs`, with the platform-specific behavior of [`platform`].
+#[derive(Debug)]
+struct StdFileSystem;
+
+impl FileSystem for StdFileSystem {
+    fn open_read(&self, path: &Path) -> io::Result<Box<dyn ReadFile>> {
+        Ok(Box::new(platform::open_read_only(path)?))
+    }
+
+    fn write_synced(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        platform::write_synced(path, data)
+    }
+
+    fn create_new(&self, path: &Path) -> io::Result<Box<dyn WriteFile>> {
+        let file = OpenOptions::new()
+            .read(true)
+            .create_new(true)
+            .write(true)
+            .open(path)?;
+        Ok(Box::new(file))
+    }
+
+    fn open_write(&self, path: &Path) -> io::Result<Box<dyn WriteFile>> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        Ok(Box::new(file))
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        File::open(path)?.read_to_end(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        std::fs::rename(from, to)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        std::fs::remove_file(path)
+    }
+
+    fn list_dir(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        std::fs::read_dir(dir)?
+            .map(|entry| Ok(entry?.path()))
+            .collect()
+    }
+}
+
+impl ReadFile for File {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        platform::read_exact_at(self, buf, offset)
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+}
+
+impl WriteFile for File {
+    fn write(&self, buf: &[u8]) -> io::Result<usize> {
+        Write::write(&mut &*self, buf)
+    }
+
+    fn seek(&self, offset: u64) -> io::Result<()> {
+        Seek::seek(&mut &*self, SeekFrom::Start(offset))?;
+        Ok(())
+    }
+
+    fn set_len(&self, len: u64) -> io::Result<()> {
+        File::set_len(self, len)
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        self.sync_all()
+    }
+}
+
+/// Adapts a [`WriteFile`] to `Write`, e.g., to buffer the writes with a `BufWriter`.
+pub(crate) struct Writer(pub(crate) Arc<dyn WriteFile>);
+
+impl Write for Writer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The history of a key: its versions still kept in the storage, for debugging MVCC and showing how a key changed.
+
+use std::ops::Bound;
+
+use anyhow::Result;
+use bytes::Bytes;
+
+use crate::iterators::StorageIterator;
+use crate::iterators::concat_iterator::SstConcatIterator;
+use crate::iterators::merge_iterator::MergeIterator;
+use crate::iterators::two_merge_iterator::TwoMergeIterator;
+use crate::key::{self, KeySlice};
+use crate::lsm_error;
+use crate::lsm_storage::{LsmStorageInner, MiniLsm, key_within};
+use crate::table::{SsTable, SsTableIterator};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VersionOp {
+    Put,
+    Delete,
+}
+
+/// A version of a key, returned by `MiniLsm::get_versions`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyVersion {
+    /// The value written, empty for a delete.
+    pub value: Bytes,
+    /// The commit timestamp of the version.
+    pub ts: u64,
+    pub op: VersionOp,
+}
+
+impl LsmStorageInner {
+    /// The versions of `key` committed at or before `read_ts`, newest first, at most `limit` of them. The SSTs with
+    /// all entries committed after `read_ts` are skipped, and the others are probed at `read_ts` rather than at the
+    /// newest version of the key.
+    pub(crate) fn get_versions(
+        &self,
+        key: &[u8],
+        read_ts: u64,
+        limit: usize,
+    ) -> Result<Vec<KeyVersion>> {
+        let snapshot = self.snapshot();
+        let begin = KeySlice::from_slice(key, read_ts);
+        let end = KeySlice::from_slice(key, key::TS_RANGE_END);
+        let memtable_iters = std::iter::once(&snapshot.memtable)
+            .chain(snapshot.imm_memtables.iter())
+            .map(|memtable| Box::new(memtable.scan(Bound::Included(begin), Bound::Included(end))))
+            .collect();
+
+        let keep_table = |table: &SsTable| {
+            table.time_range().min_ts <= read_ts
+                && key_within(
+                    key,
+                    table.first_key().as_key_slice(),
+                    table.last_key().as_key_slice(),
+                )
+                && table.may_contain_key(key)
+        };
+        let mut l0_iters = Vec::with_capacity(snapshot.l0_sstables.len());
+        for table in snapshot.l0_sstables.iter() {
+            let table = snapshot.sstables[table].clone();
+            if keep_table(&table) {
+                l0_iters.push(Box::new(SsTableIterator::create_and_seek_to_key(
+                    table, begin,
+                )?));
+            }
+        }
+        let mut level_iters = Vec::with_capacity(snapshot.levels.len());
+        for (_, level_sst_ids) in &snapshot.levels {
+            let level_ssts = level_sst_ids
+                .iter()
+                .map(|table| snapshot.sstables[table].clone())
+                .filter(|table| keep_table(table))
+                .collect();
+            level_iters.push(Box::new(SstConcatIterator::create_and_seek_to_key(
+                level_ssts, begin,
+            )?));
+        }
+        let mut iter = TwoMergeIterator::create(
+            TwoMergeIterator::create(
+                MergeIterator::create(memtable_iters),
+                MergeIterator::create(l0_iters),
+            )?,
+            MergeIterator::create(level_iters),
+        )?;
+
+        let mut versions = Vec::new();
+        while iter.is_valid() && iter.key().key_ref() == key && versions.len() < limit {
+            versions.push(KeyVersion {
+                value: Bytes::copy_from_slice(iter.value()),
+                ts: iter.key().ts(),
+                op: if iter.value().is_empty() {
+                    VersionOp::Delete
+                } else {
+                    VersionOp::Put
+                },
+            });
+            iter.next()?;
+        }
+        Ok(versions)
+    }
+}
+
+impl MiniLsm {
+    /// The versions of `key` visible at the latest commit, newest first, at most `limit` of them. The versions below
+    /// the watermark may have been compacted away, so the history is only complete above it.
+    pub fn get_versions(&self, key: &[u8], limit: usize) -> lsm_error::Result<Vec<KeyVersion>> {
+        // Keep the versions from being garbage collected while reading them
+        let snapshot = self.snapshot();
+        Ok(self.inner.get_versions(key, snapshot.read_ts(), limit)?)
+    }
+}
@@ -0,0 +1,279 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Sampling-based hot key detection.
+//!
+//! A sample of the reads and writes is counted in a count-min sketch, which estimates the access count of any key in
+//! constant space, and the keys with the highest estimates are kept as candidates for reporting. Counts are kept for
+//! two consecutive windows; the previous window is weighted by how much of it still overlaps the sliding window ending
+//! now.
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use parking_lot::Mutex;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::prefix::PrefixExtractor;
+
+const SKETCH_DEPTH: usize = 4;
+const SKETCH_WIDTH: usize = 1024;
+const MAX_CANDIDATES: usize = 256;
+
+/// The estimated accesses of a key or prefix in the sliding window.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HotKey {
+    pub key: Bytes,
+    pub reads: u64,
+    pub writes: u64,
+}
+
+impl HotKey {
+    pub fn accesses(&self) -> u64 {
+        self.reads + self.writes
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Access {
+    Read = 0,
+    Write = 1,
+}
+
+/// A count-min sketch holding separate read and write counts.
+struct CountMinSketch {
+    counts: Vec<[u64; 2]>,
+}
+
+impl CountMinSketch {
+    fn new() -> Self {
+        Self {
+            counts: vec![[0; 2]; SKETCH_DEPTH * SKETCH_WIDTH],
+        }
+    }
+
+    fn cells(key: &[u8]) -> impl Iterator<Item = usize> {
+        let hash = farmhash::fingerprint64(key);
+        let (h1, h2) = (hash as u32 as usize, (hash >> 32) as usize);
+        (0..SKETCH_DEPTH).map(move |row| {
+            row * SKETCH_WIDTH + h1.wrapping_add(row.wrapping_mul(h2)) % SKETCH_WIDTH
+        })
+    }
+
+    fn add(&mut self, key: &[u8], access: Access) {
+        for cell in Self::cells(key) {
+            self.counts[cell][access as usize] += 1;
+        }
+    }
+
+    fn estimate(&self, key: &[u8]) -> [u64; 2] {
+        Self::cells(key).fold([u64::MAX; 2], |[reads, writes], cell| {
+            let [r, w] = self.counts[cell];
+            [reads.min(r), writes.min(w)]
+        })
+    }
+}
+
+struct Window {
+    sketch: CountMinSketch,
+    /// The keys with the highest estimated accesses in this window.
+    candidates: HashMap<Bytes, u64>,
+}
+
+impl Window {
+    fn new() -> Self {
+        Self {
+            sketch: CountMinSketch::new(),
+            candidates: HashMap::new(),
+        }
+    }
+
+    fn add(&mut self, key: &[u8], access: Access) {
+        self.sketch.add(key, access);
+        let [reads, writes] = self.sketch.estimate(key);
+        let estimate = reads + writes;
+        if let Some(count) = self.candidates.get_mut(key) {
+            *count = estimate;
+            return;
+        }
+        if self.candidates.len() >= MAX_CANDIDATES {
+            let (coldest, count) = self
+                .candidates
+                .iter()
+                .min_by_key(|(_, count)| **count)
+                .map(|(key, count)| (key.clone(), *count))
+                .unwrap();
+            if count >= estimate {
+                return;
+            }
+            self.candidates.remove(&coldest);
+        }
+        self.candidates
+            .insert(Bytes::copy_from_slice(key), estimate);
+    }
+}
+
+struct TrackerState {
+    window: Duration,
+    window_start: Instant,
+    current: Window,
+    previous: Window,
+}
+
+impl TrackerState {
+    fn new(window: Duration) -> Self {
+        Self {
+            window,
+            window_start: Instant::now(),
+            current: Window::new(),
+            previous: Window::new(),
+        }
+    }
+
+    fn rotate(&mut self) {
+        let elapsed = self.window_start.elapsed();
+        if elapsed < self.window {
+            return;
+        }
+        if elapsed < self.window * 2 {
+            self.previous = std::mem::replace(&mut self.current, Window::new());
+            self.window_start += self.window;
+        } else {
+            self.previous = Window::new();
+            self.current = Window::new();
+            self.window_start = Instant::now();
+        }
+    }
+}
+
+/// Tracks the hot keys in the read and write paths. Tracking is disabled until enabled with a sample rate.
+#[derive(Default)]
+pub(crate) struct HotKeyTracker {
+    /// Count one in `sample_rate` accesses, or none if 0.
+    sample_rate: AtomicU32,
+    state: Mutex<Option<TrackerState>>,
+    /// Samples with this instead of the thread-local generator if seeded.
+    seeded_rng: Mutex<Option<StdRng>>,
+}
+
+impl HotKeyTracker {
+    pub fn enable(&self, sample_rate: u32, window: Duration) {
+        let mut state = self.state.lock();
+        *state = Some(TrackerState::new(window));
+        self.sample_rate
+            .store(sample_rate.max(1), Ordering::Relaxed);
+    }
+
+    pub fn disable(&self) {
+        let mut state = self.state.lock();
+        self.sample_rate.store(0, Ordering::Relaxed);
+        *state = None;
+    }
+
+    pub fn set_seed(&self, seed: u64) {
+        *self.seeded_rng.lock() = Some(StdRng::seed_from_u64(seed));
+    }
+
+    pub fn record(&self, key: &[u8], access: Access) {
+        let sample_rate = self.sample_rate.load(Ordering::Relaxed);
+        if sample_rate == 0 {
+            return;
+        }
+        if sample_rate > 1 {
+            let draw = match self.seeded_rng.lock().as_mut() {
+                Some(rng) => rng.gen_range(0..sample_rate),
+                None => rand::thread_rng().gen_range(0..sample_rate),
+            };
+            if draw != 0 {
+                return;
+            }
+        }
+        if let Some(state) = self.state.lock().as_mut() {
+            state.rotate();
+            state.current.add(key, access);
+        }
+    }
+
+    /// Estimate the accesses of all candidates in the sliding window, scaled up by the sample rate.
+    fn estimates(&self) -> Vec<HotKey> {
+        let sample_rate = self.sample_rate.load(Ordering::Relaxed) as u64;
+        let mut state = self.state.lock();
+        let Some(state) = state.as_mut() else {
+            return Vec::new();
+        };
+        state.rotate();
+        let overlap = 1.0
+            - (state.window_start.elapsed().as_secs_f64() / state.window.as_secs_f64()).min(1.0);
+        let keys = state
+            .current
+            .candidates
+            .keys()
+            .chain(state.previous.candidates.keys())
+            .collect::<std::collections::HashSet<_>>();
+        keys.into_iter()
+            .map(|key| {
+                let [reads, writes] = state.current.sketch.estimate(key);
+                let [prev_reads, prev_writes] = state.previous.sketch.estimate(key);
+                let scale = |count: u64, prev_count: u64| {
+                    (count as f64 + prev_count as f64 * overlap).round() as u64 * sample_rate
+                };
+                HotKey {
+                    key: key.clone(),
+                    reads: scale(reads, prev_reads),
+                    writes: scale(writes, prev_writes),
+                }
+            })
+            .filter(|hot_key| hot_key.accesses() > 0)
+            .collect()
+    }
+
+    pub fn hot_keys(&self, top_n: usize) -> Vec<HotKey> {
+        top(self.estimates(), top_n)
+    }
+
+    /// Keys without a prefix under `extractor` are counted on their own.
+    pub fn hot_prefixes(&self, extractor: &dyn PrefixExtractor, top_n: usize) -> Vec<HotKey> {
+        let mut prefixes = HashMap::<Bytes, HotKey>::new();
+        for hot_key in self.estimates() {
+            let prefix = match extractor.prefix(&hot_key.key) {
+                Some(prefix) => hot_key.key.slice_ref(prefix),
+                None => hot_key.key.clone(),
+            };
+            match prefixes.entry(prefix) {
+                Entry::Occupied(mut entry) => {
+                    entry.get_mut().reads += hot_key.reads;
+                    entry.get_mut().writes += hot_key.writes;
+                }
+                Entry::Vacant(entry) => {
+                    let key = entry.key().clone();
+                    entry.insert(HotKey { key, ..hot_key });
+                }
+            }
+        }
+        top(prefixes.into_values().collect(), top_n)
+    }
+}
+
+fn top(mut hot_keys: Vec<HotKey>, top_n: usize) -> Vec<HotKey> {
+    hot_keys.sort_by(|a, b| {
+        b.accesses()
+            .cmp(&a.accesses())
+            .then_with(|| a.key.cmp(&b.key))
+    });
+    hot_keys.truncate(top_n);
+    hot_keys
+}
@@ -0,0 +1,243 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A small HTTP service over the storage engine, so that non-Rust clients and integration tests can use it over the
+//! network.
+//!
+//! | Request                                     | Response                                                |
+//! | ------------------------------------------- | ------------------------------------------------------- |
+//! | `GET /kv/{key}`                             | `200` with the value as body, or `404`                  |
+//! | `PUT /kv/{key}` with the value as body      | `204`                                                   |
+//! | `DELETE /kv/{key}`                          | `204`                                                   |
+//! | `GET /scan?begin={key}&end={key}&limit={n}` | `200` with a JSON array of `{"key", "value"}` objects   |
+//! | `POST /batch` with a JSON array of ops      | `204`; ops are `{"op": "put", "key", "value"}` or `{"op": "delete", "key"}` |
+//!
+//! Keys in paths and query strings are percent-encoded. `begin` is inclusive and `end` is exclusive. Keys and values
+//! in JSON must be UTF-8 strings.
+
+use std::ops::Bound;
+use std::sync::Arc;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::iterators::StorageIterator;
+use crate::lsm_error::Error;
+use crate::lsm_storage::{MiniLsm, WriteBatchRecord};
+
+/// The maximum number of keys returned by a scan without a `limit`.
+const DEFAULT_SCAN_LIMIT: usize = 1000;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    fn new(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status,
+            body: body.into(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ScanEntry<'a> {
+    key: &'a str,
+    value: &'a str,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum BatchOp {
+    Put { key: String, value: String },
+    Delete { key: String },
+}
+
+fn from_hex(c: u8) -> Option<u8> {
+    (c as char).to_digit(16).map(|x| x as u8)
+}
+
+fn percent_decode(input: &str) -> Option<Vec<u8>> {
+    let input = input.as_bytes();
+    let mut output = Vec::with_capacity(input.len());
+    let mut idx = 0;
+    while idx < input.len() {
+        match input[idx] {
+            b'%' => {
+                let hi = from_hex(*input.get(idx + 1)?)?;
+                let lo = from_hex(*input.get(idx + 2)?)?;
+                output.push(hi << 4 | lo);
+                idx += 3;
+            }
+            b'+' => {
+                output.push(b' ');
+                idx += 1;
+            }
+            c => {
+                output.push(c);
+                idx += 1;
+            }
+        }
+    }
+    Some(output)
+}
+
+fn bad_request(msg: impl Into<String>) -> HttpResponse {
+    HttpResponse::new(400, msg.into())
+}
+
+/// Map an engine error to a response.
+fn error_response(e: Error) -> HttpResponse {
+    let status = match e {
+        Error::InvalidArgument(_) | Error::KeyTooLarge { .. } | Error::ValueTooLarge { .. } => 400,
+        Error::ReadOnly => 403,
+        Error::Busy(_) => 409,
+        Error::QuotaExceeded { .. } => 429,
+        Error::Poisoned(_) | Error::Cancelled => 503,
+        Error::Io(_) | Error::Corruption(_) | Error::Other(_) => 500,
+    };
+    HttpResponse::new(status, e.to_string())
+}
+
+fn scan(lsm: &MiniLsm, query: &str) -> Result<HttpResponse, Error> {
+    let mut begin = None;
+    let mut end = None;
+    let mut limit = DEFAULT_SCAN_LIMIT;
+    for param in query.split('&').filter(|param| !param.is_empty()) {
+        let (name, value) = param.split_once('=').unwrap_or((param, ""));
+        let Some(value) = percent_decode(value) else {
+            return Ok(bad_request("bad percent-encoding"));
+        };
+        match name {
+            "begin" => begin = Some(value),
+            "end" => end = Some(value),
+            "limit" => {
+                let Some(n) = std::str::from_utf8(&value)
+                    .ok()
+                    .and_then(|x| x.parse().ok())
+                else {
+                    return Ok(bad_request("bad limit"));
+                };
+                limit = n;
+            }
+            _ => return Ok(bad_request(format!("unknown parameter {}", name))),
+        }
+    }
+    let mut iter = lsm.scan(
+        begin.as_deref().map_or(Bound::Unbounded, Bound::Included),
+        end.as_deref().map_or(Bound::Unbounded, Bound::Excluded),
+    )?;
+    let mut body = b"[".to_vec();
+    let mut cnt = 0;
+    while iter.is_valid() && cnt < limit {
+        let (Ok(key), Ok(value)) = (
+            std::str::from_utf8(iter.key()),
+            std::str::from_utf8(iter.value()),
+        ) else {
+            return Ok(HttpResponse::new(
+                500,
+                "cannot encode a non-UTF-8 key or value as JSON",
+            ));
+        };
+        if cnt > 0 {
+            body.push(b',');
+        }
+        serde_json::to_writer(&mut body, &ScanEntry { key, value })
+            .map_err(|e| Error::Other(e.into()))?;
+        cnt += 1;
+        iter.next()?;
+    }
+    body.push(b']');
+    Ok(HttpResponse::new(200, body))
+}
+
+fn batch(lsm: &MiniLsm, body: &[u8]) -> Result<HttpResponse, Error> {
+    let ops: Vec<BatchOp> = match serde_json::from_slice(body) {
+        Ok(ops) => ops,
+        Err(e) => return Ok(bad_request(format!("bad batch: {}", e))),
+    };
+    let records = ops
+        .iter()
+        .map(|op| match op {
+            BatchOp::Put { key, value } => WriteBatchRecord::Put(key.as_bytes(), value.as_bytes()),
+            BatchOp::Delete { key } => WriteBatchRecord::Del(key.as_bytes()),
+        })
+        .collect::<Vec<_>>();
+    lsm.write_batch(&records)?;
+    Ok(HttpResponse::new(204, ""))
+}
+
+/// Serve a single request.
+pub fn handle_request(lsm: &MiniLsm, method: &str, url: &str, body: &[u8]) -> HttpResponse {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let result = if let Some(key) = path.strip_prefix("/kv/") {
+        let Some(key) = percent_decode(key) else {
+            return bad_request("bad percent-encoding");
+        };
+        match method {
+            "GET" => lsm.get(&key).map(|value| match value {
+                Some(value) => HttpResponse::new(200, value.to_vec()),
+                None => HttpResponse::new(404, ""),
+            }),
+            "PUT" => lsm.put(&key, body).map(|_| HttpResponse::new(204, "")),
+            "DELETE" => lsm.delete(&key).map(|_| HttpResponse::new(204, "")),
+            _ => return HttpResponse::new(405, ""),
+        }
+    } else {
+        match (method, path) {
+            ("GET", "/scan") => scan(lsm, query),
+            ("POST", "/batch") => batch(lsm, body),
+            (_, "/scan" | "/batch") => return HttpResponse::new(405, ""),
+            _ => return HttpResponse::new(404, ""),
+        }
+    };
+    result.unwrap_or_else(error_response)
+}
+
+/// Serve requests on `addr` with `threads` worker threads until the process exits.
+pub fn serve(lsm: Arc<MiniLsm>, addr: &str, threads: usize) -> Result<()> {
+    let server = Arc::new(tiny_http::Server::http(addr).map_err(|e| anyhow::anyhow!(e))?);
+    println!("listening on {}", server.server_addr());
+    let workers = (0..threads.max(1))
+        .map(|_| {
+            let server = server.clone();
+            let lsm = lsm.clone();
+            std::thread::spawn(move || {
+                for mut request in server.incoming_requests() {
+                    let mut body = Vec::new();
+                    let response = match request.as_reader().read_to_end(&mut body) {
+                        Ok(_) => {
+                            handle_request(&lsm, request.method().as_str(), request.url(), &body)
+                        }
+                        Err(e) => bad_request(format!("failed to read body: {}", e)),
+                    };
+                    let response = tiny_http::Response::from_data(response.body)
+                        .with_status_code(response.status);
+                    if let Err(e) = request.respond(response) {
+                        println!("failed to respond: {}", e);
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for worker in workers {
+        worker
+            .join()
+            .map_err(|e| anyhow::anyhow!("server thread panicked: {:?}", e))?;
+    }
+    Ok(())
+}
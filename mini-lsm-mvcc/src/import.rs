@@ -0,0 +1,411 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bulk import of key-value pairs from CSV or JSON lines.
+//!
+//! CSV records are `key,value` lines, where fields may be quoted with `"` (and `""` inside a quoted field). JSON
+//! lines are objects with a `key` string and an optional `value` string. Any further columns or fields, such as the
+//! timestamp written by `MiniLsm::export`, are ignored. An empty or missing value deletes the key, and later records
+//! win over earlier ones with the same key.
+//!
+//! Unsorted input is sorted externally: records are buffered up to `ImportOptions::sort_buffer_size` bytes, sorted
+//! and spilled into temporary run files, which are then merged while building the SSTs.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::ingest::IngestSummary;
+use crate::lsm_error::{self, Error};
+use crate::lsm_storage::MiniLsm;
+
+type Record = (Vec<u8>, Vec<u8>);
+
+/// Report progress every this many records.
+const PROGRESS_INTERVAL: usize = 100_000;
+/// The estimated memory overhead of a buffered record besides its key and value.
+const RECORD_OVERHEAD: usize = 48;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum DataFormat {
+    Csv,
+    Jsonl,
+}
+
+#[derive(Clone, Debug)]
+pub struct ImportOptions {
+    pub format: DataFormat,
+    /// Whether the input is already sorted by key without duplicates, which skips the external sort.
+    pub sorted: bool,
+    /// The memory used to sort the input before spilling a run to disk.
+    pub sort_buffer_size: usize,
+    /// Where to put the run files of the external sort. Defaults to the system temporary directory.
+    pub tmp_dir: Option<PathBuf>,
+}
+
+impl ImportOptions {
+    pub fn new(format: DataFormat) -> Self {
+        Self {
+            format,
+            sorted: false,
+            sort_buffer_size: 64 << 20, // 64MB
+            tmp_dir: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportProgress {
+    /// Records read and sorted so far.
+    Read(usize),
+    /// The input was split into this many sorted runs.
+    Sorted { runs: usize },
+    /// Records written into SSTs so far.
+    Ingested(usize),
+}
+
+#[derive(Deserialize)]
+struct JsonRecord {
+    key: String,
+    value: Option<String>,
+}
+
+/// Split a CSV line into fields.
+fn parse_csv_line(line: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let mut fields = Vec::new();
+    let mut field = Vec::new();
+    let mut in_quotes = false;
+    let mut idx = 0;
+    while idx < line.len() {
+        let c = line[idx];
+        if in_quotes {
+            if c == b'"' {
+                if line.get(idx + 1) == Some(&b'"') {
+                    field.push(b'"');
+                    idx += 1;
+                } else {
+                    in_quotes = false;
+                }
+            } else {
+                field.push(c);
+            }
+        } else if c == b'"' && field.is_empty() {
+            in_quotes = true;
+        } else if c == b',' {
+            fields.push(std::mem::take(&mut field));
+        } else {
+            field.push(c);
+        }
+        idx += 1;
+    }
+    if in_quotes {
+        bail!("unterminated quoted field");
+    }
+    fields.push(field);
+    Ok(fields)
+}
+
+/// Reads key-value records from CSV or JSON lines.
+pub struct RecordReader<R: BufRead> {
+    reader: R,
+    format: DataFormat,
+    line: Vec<u8>,
+    line_no: usize,
+}
+
+impl<R: BufRead> RecordReader<R> {
+    pub fn new(reader: R, format: DataFormat) -> Self {
+        Self {
+            reader,
+            format,
+            line: Vec::new(),
+            line_no: 0,
+        }
+    }
+
+    fn parse_line(&self, line: &[u8]) -> Result<Record> {
+        match self.format {
+            DataFormat::Csv => {
+                let mut fields = parse_csv_line(line)?.into_iter();
+                let key = fields.next().unwrap();
+                let Some(value) = fields.next() else {
+                    bail!("expected at least 2 columns");
+                };
+                Ok((key, value))
+            }
+            DataFormat::Jsonl => {
+                let record: JsonRecord = serde_json::from_slice(line)?;
+                Ok((
+                    record.key.into_bytes(),
+                    record.value.unwrap_or_default().into_bytes(),
+                ))
+            }
+        }
+    }
+
+    fn next_record(&mut self) -> Result<Option<Record>> {
+        loop {
+            self.line.clear();
+            if self.reader.read_until(b'\n', &mut self.line)? == 0 {
+                return Ok(None);
+            }
+            self.line_no += 1;
+            let line = self.line.strip_suffix(b"\n").unwrap_or(&self.line);
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            if line.is_empty() {
+                continue;
+            }
+            return match self.parse_line(line) {
+                Ok(record) => Ok(Some(record)),
+                Err(e) => {
+                    Err(Error::InvalidArgument(format!("line {}: {}", self.line_no, e)).into())
+                }
+            };
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for RecordReader<R> {
+    type Item = Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record().transpose()
+    }
+}
+
+/// A sorted run of records spilled to a temporary file, which is removed on drop.
+struct SortedRun {
+    path: PathBuf,
+}
+
+impl SortedRun {
+    fn write(path: PathBuf, records: &[Record]) -> Result<Self> {
+        let mut writer =
+            BufWriter::new(File::create(&path).context("failed to create sort run file")?);
+        let run = Self { path };
+        for (key, value) in records {
+            writer.write_all(&(key.len() as u32).to_le_bytes())?;
+            writer.write_all(key)?;
+            writer.write_all(&(value.len() as u32).to_le_bytes())?;
+            writer.write_all(value)?;
+        }
+        writer.flush()?;
+        Ok(run)
+    }
+
+    fn reader(&self) -> Result<SortedRunReader> {
+        Ok(SortedRunReader(BufReader::new(File::open(&self.path)?)))
+    }
+}
+
+impl Drop for SortedRun {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.path).ok();
+    }
+}
+
+struct SortedRunReader(BufReader<File>);
+
+impl SortedRunReader {
+    fn read_bytes(&mut self) -> Result<Option<Vec<u8>>> {
+        let mut len = [0; 4];
+        match self.0.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let mut buf = vec![0; u32::from_le_bytes(len) as usize];
+        self.0.read_exact(&mut buf)?;
+        Ok(Some(buf))
+    }
+
+    fn next_record(&mut self) -> Result<Option<Record>> {
+        let Some(key) = self.read_bytes()? else {
+            return Ok(None);
+        };
+        let Some(value) = self.read_bytes()? else {
+            bail!(Error::Corruption("truncated sort run file".to_string()));
+        };
+        Ok(Some((key, value)))
+    }
+}
+
+struct HeapEntry {
+    key: Vec<u8>,
+    value: Vec<u8>,
+    run: usize,
+}
+
+impl PartialEq for HeapEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for HeapEntry {}
+
+impl PartialOrd for HeapEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for HeapEntry {
+    /// `BinaryHeap` is a max-heap: pop the smallest key first, and the latest run among equal keys.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .key
+            .cmp(&self.key)
+            .then_with(|| self.run.cmp(&other.run))
+    }
+}
+
+/// Merges sorted runs, keeping the record of the latest run for duplicated keys.
+struct MergedRuns {
+    readers: Vec<SortedRunReader>,
+    heap: BinaryHeap<HeapEntry>,
+    last_key: Option<Vec<u8>>,
+    _runs: Vec<SortedRun>,
+}
+
+impl MergedRuns {
+    fn new(runs: Vec<SortedRun>) -> Result<Self> {
+        let mut readers = runs
+            .iter()
+            .map(SortedRun::reader)
+            .collect::<Result<Vec<_>>>()?;
+        let mut heap = BinaryHeap::new();
+        for (run, reader) in readers.iter_mut().enumerate() {
+            if let Some((key, value)) = reader.next_record()? {
+                heap.push(HeapEntry { key, value, run });
+            }
+        }
+        Ok(Self {
+            readers,
+            heap,
+            last_key: None,
+            _runs: runs,
+        })
+    }
+
+    fn next_record(&mut self) -> Result<Option<Record>> {
+        while let Some(entry) = self.heap.pop() {
+            if let Some((key, value)) = self.readers[entry.run].next_record()? {
+                self.heap.push(HeapEntry {
+                    key,
+                    value,
+                    run: entry.run,
+                });
+            }
+            if self.last_key.as_ref() == Some(&entry.key) {
+                continue;
+            }
+            self.last_key = Some(entry.key.clone());
+            return Ok(Some((entry.key, entry.value)));
+        }
+        Ok(None)
+    }
+}
+
+impl Iterator for MergedRuns {
+    type Item = Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_record().transpose()
+    }
+}
+
+/// Sort the buffered records by key, keeping the last record of duplicated keys.
+fn sort_buffer(buffer: &mut Vec<Record>) {
+    buffer.sort_by(|a, b| a.0.cmp(&b.0));
+    buffer.reverse();
+    buffer.dedup_by(|a, b| a.0 == b.0);
+    buffer.reverse();
+}
+
+fn run_path(tmp_dir: &Path, run: usize) -> PathBuf {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    tmp_dir.join(format!(
+        "mini-lsm-import-{}-{}-{}.run",
+        std::process::id(),
+        nanos,
+        run
+    ))
+}
+
+impl MiniLsm {
+    /// Import key-value records from CSV or JSON lines by building SSTs directly, which is much faster than `put`
+    /// for large inputs. All records are committed atomically with a single timestamp.
+    pub fn import(
+        &self,
+        reader: impl BufRead,
+        options: &ImportOptions,
+        mut progress: impl FnMut(ImportProgress),
+    ) -> lsm_error::Result<IngestSummary> {
+        let records = RecordReader::new(reader, options.format);
+        let sorted: Box<dyn Iterator<Item = Result<Record>>> = if options.sorted {
+            Box::new(records)
+        } else {
+            let tmp_dir = options.tmp_dir.clone().unwrap_or_else(std::env::temp_dir);
+            let mut runs = Vec::new();
+            let mut buffer = Vec::new();
+            let mut buffer_size = 0;
+            for (idx, record) in records.enumerate() {
+                let (key, value) = record?;
+                buffer_size += key.len() + value.len() + RECORD_OVERHEAD;
+                buffer.push((key, value));
+                if (idx + 1) % PROGRESS_INTERVAL == 0 {
+                    progress(ImportProgress::Read(idx + 1));
+                }
+                if buffer_size >= options.sort_buffer_size {
+                    sort_buffer(&mut buffer);
+                    runs.push(SortedRun::write(run_path(&tmp_dir, runs.len()), &buffer)?);
+                    buffer.clear();
+                    buffer_size = 0;
+                }
+            }
+            sort_buffer(&mut buffer);
+            if runs.is_empty() {
+                progress(ImportProgress::Sorted { runs: 1 });
+                Box::new(buffer.into_iter().map(Ok))
+            } else {
+                if !buffer.is_empty() {
+                    runs.push(SortedRun::write(run_path(&tmp_dir, runs.len()), &buffer)?);
+                }
+                progress(ImportProgress::Sorted { runs: runs.len() });
+                Box::new(MergedRuns::new(runs)?)
+            }
+        };
+        let mut ingested = 0;
+        let summary = self.ingest_sorted(sorted.inspect(|record| {
+            if record.is_ok() {
+                ingested += 1;
+                if ingested % PROGRESS_INTERVAL == 0 {
+                    progress(ImportProgress::Ingested(ingested));
+                }
+            }
+        }))?;
+        progress(ImportProgress::Ingested(summary.num_entries));
+        Ok(summary)
+    }
+}
@@ -0,0 +1,165 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bulk ingestion of sorted key-value pairs as SSTs, bypassing the memtable and the WAL.
+
+use std::sync::Arc;
+
+use anyhow::{Result, bail};
+
+use crate::key::KeySlice;
+use crate::lsm_error::Error;
+use crate::lsm_storage::LsmStorageInner;
+use crate::manifest::ManifestRecord;
+use crate::table::{SsTable, SsTableBuilder, SstOrigin};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IngestSummary {
+    /// The commit timestamp of all ingested entries.
+    pub commit_ts: u64,
+    /// The SSTs added to the engine.
+    pub sst_ids: Vec<usize>,
+    pub num_entries: usize,
+}
+
+impl LsmStorageInner {
+    /// Build SSTs from `iter`, which must yield strictly increasing keys, and add all of them to the engine at once
+    /// with a single commit timestamp. An empty value deletes the key. Writers are blocked while the SSTs are built,
+    /// and write callbacks and prefix quotas do not apply to ingested entries.
+    pub fn ingest_sorted<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &self,
+        iter: impl Iterator<Item = Result<(K, V)>>,
+    ) -> Result<IngestSummary> {
+        self.check_writable()?;
+        let _lck = self.mvcc().write_lock.lock();
+        let ts = self.mvcc().allocate_commit_ts()?;
+        let _published = self.mvcc().publish_on_drop(ts);
+        self.ingest_sorted_at(iter, ts)
+    }
+
+    fn ingest_sorted_at<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &self,
+        iter: impl Iterator<Item = Result<(K, V)>>,
+        ts: u64,
+    ) -> Result<IngestSummary> {
+        let mut ssts = Vec::new();
+        let result = self.build_ingested_ssts(iter, ts, &mut ssts);
+        let num_entries = match result {
+            Ok(num_entries) => num_entries,
+            Err(e) => {
+                for sst in ssts {
+                    std::fs::remove_file(self.path_of_sst(sst.sst_id())).ok();
+                }
+                return Err(e);
+            }
+        };
+        if ssts.is_empty() {
+            return Ok(IngestSummary::default());
+        }
+        let sst_ids = ssts.iter().map(|sst| sst.sst_id()).collect::<Vec<_>>();
+        {
+            let state_lock = self.state_lock.lock();
+            let mut guard = self.state.write();
+            let mut snapshot = guard.as_ref().clone();
+            if self.compaction_controller().flush_to_l0() {
+                for sst_id in sst_ids.iter().rev() {
+                    snapshot.l0_sstables.insert(0, *sst_id);
+                }
+            } else {
+                snapshot.levels.insert(0, (sst_ids[0], sst_ids.clone()));
+            }
+            for sst in ssts {
+                snapshot.sstables.insert(sst.sst_id(), sst);
+            }
+            *guard = Arc::new(snapshot);
+            drop(guard);
+            self.manifest().add_records(
+                &state_lock,
+                &[
+                    ManifestRecord::CommitTs(ts),
+                    ManifestRecord::Ingest(sst_ids.clone()),
+                ],
+            )?;
+            self.sync_dir()?;
+        }
+        println!(
+            "ingested {} entries into {} SSTs at ts={}",
+            num_entries,
+            sst_ids.len(),
+            ts
+        );
+        Ok(IngestSummary {
+            commit_ts: ts,
+            sst_ids,
+            num_entries,
+        })
+    }
+
+    fn build_ingested_ssts<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &self,
+        iter: impl Iterator<Item = Result<(K, V)>>,
+        ts: u64,
+        ssts: &mut Vec<Arc<SsTable>>,
+    ) -> Result<usize> {
+        let mut builder: Option<SsTableBuilder> = None;
+        let mut last_key = Vec::new();
+        let mut num_entries = 0;
+        for entry in iter {
+            let (key, value) = entry?;
+            let (key, value) = (key.as_ref(), value.as_ref());
+            if key.is_empty() {
+                bail!(Error::InvalidArgument("key cannot be empty".to_string()));
+            }
+            if num_entries > 0 && key <= last_key.as_slice() {
+                bail!(Error::InvalidArgument(
+                    "ingested keys must be strictly increasing".to_string()
+                ));
+            }
+            if key.len() > self.options().max_key_size {
+                bail!(Error::KeyTooLarge {
+                    size: key.len(),
+                    limit: self.options().max_key_size,
+                });
+            }
+            if value.len() > self.options().max_value_size {
+                bail!(Error::ValueTooLarge {
+                    size: value.len(),
+                    limit: self.options().max_value_size,
+                });
+            }
+            let builder_inner =
+                builder.get_or_insert_with(|| self.new_sst_builder(SstOrigin::Ingest));
+            builder_inner.add(KeySlice::from_slice(key, ts), value);
+            last_key.clear();
+            last_key.extend(key);
+            num_entries += 1;
+            if builder_inner.estimated_size() >= self.options().target_sst_size {
+                ssts.push(self.build_ingested_sst(builder.take().unwrap())?);
+            }
+        }
+        if let Some(builder) = builder {
+            ssts.push(self.build_ingested_sst(builder)?);
+        }
+        Ok(num_entries)
+    }
+
+    fn build_ingested_sst(&self, builder: SsTableBuilder) -> Result<Arc<SsTable>> {
+        let sst_id = self.next_sst_id();
+        Ok(Arc::new(builder.build(
+            sst_id,
+            Some(self.block_cache.clone()),
+            self.path_of_sst(sst_id),
+        )?))
+    }
+}
@@ -0,0 +1,337 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A consistency checker that verifies the invariants of the LSM structure and the data it references.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use crate::iterators::{StorageIterator, ToKeyBytes};
+use crate::key::KeyBytes;
+use crate::lsm_error;
+use crate::lsm_storage::{LsmStorageInner, MiniLsm};
+use crate::table::{SsTable, SsTableIterator};
+
+/// An invariant of the storage that does not hold.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Violation {
+    /// The SST is referenced by the LSM structure but its file does not exist.
+    MissingFile { sst_id: usize },
+    /// The SST is referenced by the LSM structure but not loaded.
+    MissingTable { sst_id: usize },
+    /// The SST is referenced more than once by the LSM structure.
+    DuplicateTable { sst_id: usize },
+    /// The L0 SSTs are not ordered from the newest to the oldest.
+    L0OutOfOrder { sst_id: usize, next_sst_id: usize },
+    /// Two SSTs in the same sorted run are out of order or their key ranges overlap.
+    RunOverlap {
+        level: usize,
+        sst_id: usize,
+        next_sst_id: usize,
+    },
+    /// The SST cannot be read, e.g., a block fails its checksum.
+    Unreadable { sst_id: usize, error: String },
+    /// The keys in the SST are not strictly increasing.
+    KeysOutOfOrder { sst_id: usize, key: KeyBytes },
+    /// The first or last key recorded in the SST metadata does not match its entries.
+    KeyRangeMismatch { sst_id: usize },
+    /// The SST has an entry whose timestamp is outside of the recorded time range.
+    TsOutOfRange { sst_id: usize, ts: u64 },
+    /// The SST has an entry committed after the latest commit timestamp.
+    SstTsAfterLatestCommit {
+        sst_id: usize,
+        ts: u64,
+        latest_commit_ts: u64,
+    },
+    /// The memtable has an entry committed after the latest commit timestamp.
+    MemtableTsAfterLatestCommit {
+        memtable_id: usize,
+        ts: u64,
+        latest_commit_ts: u64,
+    },
+    /// The watermark is ahead of the latest commit timestamp.
+    WatermarkAfterLatestCommit {
+        watermark: u64,
+        latest_commit_ts: u64,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingFile { sst_id } => write!(f, "SST {sst_id}: file does not exist"),
+            Self::MissingTable { sst_id } => write!(f, "SST {sst_id}: not loaded"),
+            Self::DuplicateTable { sst_id } => {
+                write!(f, "SST {sst_id}: referenced more than once")
+            }
+            Self::L0OutOfOrder {
+                sst_id,
+                next_sst_id,
+            } => write!(
+                f,
+                "L0: SST {sst_id} is placed before older SST {next_sst_id}"
+            ),
+            Self::RunOverlap {
+                level,
+                sst_id,
+                next_sst_id,
+            } => write!(
+                f,
+                "L{level}: SST {sst_id} overlaps or is out of order with SST {next_sst_id}"
+            ),
+            Self::Unreadable { sst_id, error } => write!(f, "SST {sst_id}: {error}"),
+            Self::KeysOutOfOrder { sst_id, key } => {
+                write!(f, "SST {sst_id}: key {:?} is out of order", key)
+            }
+            Self::KeyRangeMismatch { sst_id } => {
+                write!(
+                    f,
+                    "SST {sst_id}: first or last key does not match the entries"
+                )
+            }
+            Self::TsOutOfRange { sst_id, ts } => {
+                write!(
+                    f,
+                    "SST {sst_id}: ts={ts} is outside of the recorded time range"
+                )
+            }
+            Self::SstTsAfterLatestCommit {
+                sst_id,
+                ts,
+                latest_commit_ts,
+            } => write!(
+                f,
+                "SST {sst_id}: ts={ts} is after the latest commit ts={latest_commit_ts}"
+            ),
+            Self::MemtableTsAfterLatestCommit {
+                memtable_id,
+                ts,
+                latest_commit_ts,
+            } => write!(
+                f,
+                "memtable {memtable_id}: ts={ts} is after the latest commit ts={latest_commit_ts}"
+            ),
+            Self::WatermarkAfterLatestCommit {
+                watermark,
+                latest_commit_ts,
+            } => write!(
+                f,
+                "watermark={watermark} is after the latest commit ts={latest_commit_ts}"
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IntegrityReport {
+    /// The number of SSTs checked.
+    pub num_sstables: usize,
+    /// The number of SST entries checked.
+    pub num_entries: usize,
+    pub violations: Vec<Violation>,
+}
+
+impl IntegrityReport {
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+impl fmt::Display for IntegrityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "checked {} SSTs with {} entries, {} violations",
+            self.num_sstables,
+            self.num_entries,
+            self.violations.len()
+        )?;
+        for violation in &self.violations {
+            writeln!(f, "  {violation}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Read every entry of the SST, checking the key order, the recorded key range and time range, and the checksums of
+/// all blocks.
+fn check_sst(
+    table: &Arc<SsTable>,
+    latest_commit_ts: u64,
+    report: &mut IntegrityReport,
+) -> Result<()> {
+    let sst_id = table.sst_id();
+    let time_range = *table.time_range();
+    let mut iter = SsTableIterator::create_and_seek_to_first(table.clone())?;
+    let mut first_key = None;
+    let mut last_key: Option<KeyBytes> = None;
+    let mut max_ts = 0;
+    while iter.is_valid() {
+        let key = iter.key();
+        report.num_entries += 1;
+        if last_key
+            .as_ref()
+            .is_some_and(|last| last.as_key_slice() >= key)
+        {
+            report.violations.push(Violation::KeysOutOfOrder {
+                sst_id,
+                key: key.to_key_bytes(),
+            });
+        }
+        if key.ts() < time_range.min_ts || key.ts() > time_range.max_ts {
+            report.violations.push(Violation::TsOutOfRange {
+                sst_id,
+                ts: key.ts(),
+            });
+        }
+        max_ts = max_ts.max(key.ts());
+        if first_key.is_none() {
+            first_key = Some(key.to_key_bytes());
+        }
+        last_key = Some(key.to_key_bytes());
+        iter.next()?;
+    }
+    if first_key
+        .as_ref()
+        .is_some_and(|key| key != table.first_key())
+        || last_key.as_ref().is_some_and(|key| key != table.last_key())
+    {
+        report
+            .violations
+            .push(Violation::KeyRangeMismatch { sst_id });
+    }
+    if max_ts > latest_commit_ts {
+        report.violations.push(Violation::SstTsAfterLatestCommit {
+            sst_id,
+            ts: max_ts,
+            latest_commit_ts,
+        });
+    }
+    Ok(())
+}
+
+impl LsmStorageInner {
+    pub(crate) fn check_integrity(&self) -> Result<IntegrityReport> {
+        let mut report = IntegrityReport::default();
+        // Files of SSTs removed from the structure are deleted after the state lock is released, so every SST in a
+        // snapshot taken under the lock has its file.
+        let snapshot = {
+            let _state_lock = self.state_lock.lock();
+            let snapshot = self.snapshot();
+            for sst_id in snapshot
+                .l0_sstables
+                .iter()
+                .chain(snapshot.levels.iter().flat_map(|(_, ssts)| ssts))
+            {
+                if !self.path_of_sst(*sst_id).exists() {
+                    report
+                        .violations
+                        .push(Violation::MissingFile { sst_id: *sst_id });
+                }
+            }
+            snapshot
+        };
+        let (latest_commit_ts, watermark) = {
+            let mvcc = self.mvcc();
+            (mvcc.latest_commit_ts(), mvcc.watermark())
+        };
+        if watermark > latest_commit_ts {
+            report
+                .violations
+                .push(Violation::WatermarkAfterLatestCommit {
+                    watermark,
+                    latest_commit_ts,
+                });
+        }
+        for memtable in std::iter::once(&snapshot.memtable).chain(&snapshot.imm_memtables) {
+            if memtable.max_ts() > latest_commit_ts {
+                report
+                    .violations
+                    .push(Violation::MemtableTsAfterLatestCommit {
+                        memtable_id: memtable.id(),
+                        ts: memtable.max_ts(),
+                        latest_commit_ts,
+                    });
+            }
+        }
+
+        for pair in snapshot.l0_sstables.windows(2) {
+            if pair[0] <= pair[1] {
+                report.violations.push(Violation::L0OutOfOrder {
+                    sst_id: pair[0],
+                    next_sst_id: pair[1],
+                });
+            }
+        }
+        for (level, ssts) in &snapshot.levels {
+            for pair in ssts.windows(2) {
+                let (Some(sst), Some(next_sst)) = (
+                    snapshot.sstables.get(&pair[0]),
+                    snapshot.sstables.get(&pair[1]),
+                ) else {
+                    continue;
+                };
+                // Versions of a key are never split across SSTs, so the user keys of a run are disjoint.
+                if sst.last_key().key_ref() >= next_sst.first_key().key_ref() {
+                    report.violations.push(Violation::RunOverlap {
+                        level: *level,
+                        sst_id: pair[0],
+                        next_sst_id: pair[1],
+                    });
+                }
+            }
+        }
+
+        let mut seen = HashSet::new();
+        for sst_id in snapshot
+            .l0_sstables
+            .iter()
+            .chain(snapshot.levels.iter().flat_map(|(_, ssts)| ssts))
+        {
+            if !seen.insert(*sst_id) {
+                report
+                    .violations
+                    .push(Violation::DuplicateTable { sst_id: *sst_id });
+                continue;
+            }
+            let Some(table) = snapshot.sstables.get(sst_id) else {
+                report
+                    .violations
+                    .push(Violation::MissingTable { sst_id: *sst_id });
+                continue;
+            };
+            report.num_sstables += 1;
+            if let Err(e) = check_sst(table, latest_commit_ts, &mut report) {
+                report.violations.push(Violation::Unreadable {
+                    sst_id: *sst_id,
+                    error: format!("{e:#}"),
+                });
+            }
+        }
+        Ok(report)
+    }
+}
+
+impl MiniLsm {
+    /// Verify the invariants of the LSM structure: the L0 SSTs are ordered from the newest to the oldest, the SSTs of
+    /// each sorted run are ordered and do not overlap, every SST exists and passes its checksums with its keys in
+    /// order, and no entry or watermark is ahead of the latest commit. Violations are collected into the report
+    /// instead of failing the check.
+    pub fn check_integrity(&self) -> lsm_error::Result<IntegrityReport> {
+        Ok(self.inner.check_integrity()?)
+    }
+}
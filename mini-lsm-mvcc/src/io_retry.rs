@@ -0,0 +1,153 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Retrying SST reads and WAL and manifest writes that fail with a transient I/O error, e.g., an interrupted system
+//! call or a busy device, so that a blip does not fail a user query or poison the engine.
+
+use std::io::{self, ErrorKind, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::lsm_storage::MiniLsm;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IoRetryPolicy {
+    /// Give up and return the error after this many retries of an operation.
+    pub max_retries: u32,
+    /// The wait before the first retry, doubled before each next one up to `max_backoff`.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl IoRetryPolicy {
+    fn backoff(&self, retries: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(1 << retries.min(31))
+            .min(self.max_backoff)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IoRetryStats {
+    pub sst_read_retries: u64,
+    pub wal_write_retries: u64,
+    pub manifest_write_retries: u64,
+    /// The operations that still failed after `IoRetryPolicy::max_retries` retries.
+    pub exhausted: u64,
+}
+
+/// What an I/O operation does, to count its retries.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum IoTarget {
+    SstRead,
+    WalWrite,
+    ManifestWrite,
+}
+
+/// Errors that may go away by trying again.
+fn is_transient(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        ErrorKind::Interrupted
+            | ErrorKind::WouldBlock
+            | ErrorKind::TimedOut
+            | ErrorKind::ResourceBusy
+    )
+}
+
+/// Shared by the SSTs, WALs and manifest of an engine, which count their retries here.
+#[derive(Default)]
+pub(crate) struct IoRetry {
+    policy: Option<IoRetryPolicy>,
+    sst_read_retries: AtomicU64,
+    wal_write_retries: AtomicU64,
+    manifest_write_retries: AtomicU64,
+    exhausted: AtomicU64,
+}
+
+impl IoRetry {
+    pub(crate) fn new(policy: Option<IoRetryPolicy>) -> Self {
+        Self {
+            policy,
+            ..Default::default()
+        }
+    }
+
+    /// Run `op`, and run it again after each transient error until the policy gives up. `op` must be safe to repeat,
+    /// e.g., a positional read or a sync.
+    pub(crate) fn run<T>(
+        &self,
+        target: IoTarget,
+        mut op: impl FnMut() -> io::Result<T>,
+    ) -> io::Result<T> {
+        let mut retries = 0;
+        loop {
+            match op() {
+                Err(e) if is_transient(&e) => {
+                    let Some(policy) = self.policy else {
+                        return Err(e);
+                    };
+                    if retries >= policy.max_retries {
+                        self.exhausted.fetch_add(1, Ordering::Relaxed);
+                        return Err(e);
+                    }
+                    let counter = match target {
+                        IoTarget::SstRead => &self.sst_read_retries,
+                        IoTarget::WalWrite => &self.wal_write_retries,
+                        IoTarget::ManifestWrite => &self.manifest_write_retries,
+                    };
+                    counter.fetch_add(1, Ordering::Relaxed);
+                    std::thread::sleep(policy.backoff(retries));
+                    retries += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Like `Write::write_all`, except that a transient error retries the write of only the bytes not written yet, so
+    /// that an append is never duplicated.
+    pub(crate) fn write_all(
+        &self,
+        target: IoTarget,
+        writer: &mut impl Write,
+        mut buf: &[u8],
+    ) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.run(target, || writer.write(buf))? {
+                0 => return Err(ErrorKind::WriteZero.into()),
+                n => buf = &buf[n..],
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn stats(&self) -> IoRetryStats {
+        IoRetryStats {
+            sst_read_retries: self.sst_read_retries.load(Ordering::Relaxed),
+            wal_write_retries: self.wal_write_retries.load(Ordering::Relaxed),
+            manifest_write_retries: self.manifest_write_retries.load(Ordering::Relaxed),
+            exhausted: self.exhausted.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl MiniLsm {
+    /// The I/O operations retried under `LsmStorageOptions::io_retry_policy` so far.
+    pub fn io_retry_stats(&self) -> IoRetryStats {
+        self.inner.io_retry.stats()
+    }
+}
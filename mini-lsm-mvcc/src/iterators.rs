@@ -12,10 +12,34 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+#[cfg(feature = "std")]
 pub mod concat_iterator;
 pub mod merge_iterator;
 pub mod two_merge_iterator;
 
+use alloc::vec::Vec;
+use bytes::Bytes;
+
+use crate::key::{KeyBytes, KeySlice, TS_DEFAULT};
+
+/// Converts the key of an iterator into an owned key for [`StorageIterator::next_batch`].
+pub trait ToKeyBytes {
+    fn to_key_bytes(&self) -> KeyBytes;
+}
+
+impl ToKeyBytes for KeySlice<'_> {
+    fn to_key_bytes(&self) -> KeyBytes {
+        KeyBytes::from_bytes_with_ts(Bytes::copy_from_slice(self.key_ref()), self.ts())
+    }
+}
+
+/// User keys carry no timestamp, so they get the default one.
+impl ToKeyBytes for &[u8] {
+    fn to_key_bytes(&self) -> KeyBytes {
+        KeyBytes::from_bytes_with_ts(Bytes::copy_from_slice(self), TS_DEFAULT)
+    }
+}
+
 pub trait StorageIterator {
     type KeyType<'a>: PartialEq + Eq + PartialOrd + Ord
     where
@@ -37,4 +61,29 @@ pub trait StorageIterator {
     fn num_active_iterators(&self) -> usize {
         1
     }
+
+    /// Append the current entry and the ones after it to `out`, up to `n` entries, and move past them. Returns the
+    /// number of entries appended, which is less than `n` only if the iterator is exhausted.
+    fn next_batch(&mut self, out: &mut Vec<(KeyBytes, Bytes)>, n: usize) -> anyhow::Result<usize>
+    where
+        for<'a> Self::KeyType<'a>: ToKeyBytes,
+    {
+        let mut cnt = 0;
+        while cnt < n && self.is_valid() {
+            out.push((
+                self.key().to_key_bytes(),
+                Bytes::copy_from_slice(self.value()),
+            ));
+            self.next()?;
+            cnt += 1;
+        }
+        Ok(cnt)
+    }
+}
+
+/// An iterator that can jump forward to a key without being recreated, e.g., for cursors that skip over a range.
+pub trait SeekableIterator: StorageIterator {
+    /// Move to the first entry at or after `key`. Iterators only move forward: if the iterator is exhausted or
+    /// already at or after `key`, it stays where it is.
+    fn seek(&mut self, key: Self::KeyType<'_>) -> anyhow::Result<()>;
 }
@@ -12,16 +12,19 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::ops::Bound;
 use std::sync::Arc;
 
 use anyhow::Result;
+use bytes::Bytes;
 
 use crate::{
     key::KeySlice,
-    table::{SsTable, SsTableIterator},
+    lsm_storage::ReadOptions,
+    table::{CorruptionReport, SsTable, SsTableIterator, is_past_upper},
 };
 
-use super::StorageIterator;
+use super::{SeekableIterator, StorageIterator};
 
 /// Concat multiple iterators ordered in key order and their key ranges do not overlap. We do not want to create the
 /// iterators when initializing this iterator to reduce the overhead of seeking.
@@ -29,6 +32,8 @@ pub struct SstConcatIterator {
     current: Option<SsTableIterator>,
     next_sst_idx: usize,
     sstables: Vec<Arc<SsTable>>,
+    /// The iterator ends before the first key past this bound, without opening the SSTs after it.
+    upper: Bound<Bytes>,
 }
 
 impl SstConcatIterator {
@@ -44,26 +49,67 @@ impl SstConcatIterator {
     }
 
     pub fn create_and_seek_to_first(sstables: Vec<Arc<SsTable>>) -> Result<Self> {
+        Self::create_and_seek_to_first_with_options(sstables, ReadOptions::default())
+    }
+
+    /// Create an iterator that reads blocks according to `options` and seek to the first key-value pair.
+    pub fn create_and_seek_to_first_with_options(
+        sstables: Vec<Arc<SsTable>>,
+        options: ReadOptions,
+    ) -> Result<Self> {
+        Self::create_and_seek_to_first_with_report(sstables, options, None)
+    }
+
+    /// Like `create_and_seek_to_first_with_options`, adding the blocks skipped as corrupted to `report`.
+    pub(crate) fn create_and_seek_to_first_with_report(
+        sstables: Vec<Arc<SsTable>>,
+        options: ReadOptions,
+        report: Option<CorruptionReport>,
+    ) -> Result<Self> {
         Self::check_sst_valid(&sstables);
         if sstables.is_empty() {
             return Ok(Self {
                 current: None,
                 next_sst_idx: 0,
                 sstables,
+                upper: Bound::Unbounded,
             });
         }
         let mut iter = Self {
-            current: Some(SsTableIterator::create_and_seek_to_first(
+            current: Some(SsTableIterator::create_and_seek_to_first_with_report(
                 sstables[0].clone(),
+                options,
+                report,
             )?),
             next_sst_idx: 1,
             sstables,
+            upper: Bound::Unbounded,
         };
         iter.move_until_valid()?;
         Ok(iter)
     }
 
     pub fn create_and_seek_to_key(sstables: Vec<Arc<SsTable>>, key: KeySlice) -> Result<Self> {
+        Self::create_and_seek_to_key_with_options(sstables, key, ReadOptions::default())
+    }
+
+    /// Create an iterator that reads blocks according to `options` and seek to the first key-value pair which >=
+    /// `key`.
+    pub fn create_and_seek_to_key_with_options(
+        sstables: Vec<Arc<SsTable>>,
+        key: KeySlice,
+        options: ReadOptions,
+    ) -> Result<Self> {
+        Self::create_and_seek_to_key_with_report(sstables, key, options, None)
+    }
+
+    /// Like `create_and_seek_to_key_with_options`, adding the blocks skipped as corrupted to `report`.
+    pub(crate) fn create_and_seek_to_key_with_report(
+        sstables: Vec<Arc<SsTable>>,
+        key: KeySlice,
+        options: ReadOptions,
+        report: Option<CorruptionReport>,
+    ) -> Result<Self> {
         Self::check_sst_valid(&sstables);
         let idx: usize = sstables
             .partition_point(|table| table.first_key().as_key_slice() <= key)
@@ -73,31 +119,47 @@ impl SstConcatIterator {
                 current: None,
                 next_sst_idx: sstables.len(),
                 sstables,
+                upper: Bound::Unbounded,
             });
         }
         let mut iter = Self {
-            current: Some(SsTableIterator::create_and_seek_to_key(
+            current: Some(SsTableIterator::create_and_seek_to_key_with_report(
                 sstables[idx].clone(),
                 key,
+                options,
+                report,
             )?),
             next_sst_idx: idx + 1,
             sstables,
+            upper: Bound::Unbounded,
         };
         iter.move_until_valid()?;
         Ok(iter)
     }
 
+    /// End the iterator before the first key past `upper`, which applies to the current key and to all later seeks.
+    pub fn set_upper_bound(&mut self, upper: Bound<Bytes>) -> Result<()> {
+        if let Some(current) = self.current.as_mut() {
+            current.set_upper_bound(upper.clone())?;
+        }
+        self.upper = upper;
+        self.move_until_valid()
+    }
+
     fn move_until_valid(&mut self) -> Result<()> {
         while let Some(iter) = self.current.as_mut() {
             if iter.is_valid() {
                 break;
             }
-            if self.next_sst_idx >= self.sstables.len() {
+            if self.next_sst_idx >= self.sstables.len()
+                || is_past_upper(
+                    self.sstables[self.next_sst_idx].first_key().key_ref(),
+                    &self.upper,
+                )
+            {
                 self.current = None;
             } else {
-                self.current = Some(SsTableIterator::create_and_seek_to_first(
-                    self.sstables[self.next_sst_idx].clone(),
-                )?);
+                iter.reset_and_seek_to_first(self.sstables[self.next_sst_idx].clone())?;
                 self.next_sst_idx += 1;
             }
         }
@@ -135,3 +197,25 @@ impl StorageIterator for SstConcatIterator {
         1
     }
 }
+
+impl SeekableIterator for SstConcatIterator {
+    fn seek(&mut self, key: KeySlice) -> Result<()> {
+        let Some(current) = self.current.as_mut() else {
+            return Ok(());
+        };
+        if current.key() >= key {
+            return Ok(());
+        }
+        // The current SST is the one before `next_sst_idx`, so `key` is in it or a later one
+        let skipped = self.sstables[self.next_sst_idx..]
+            .partition_point(|table| table.first_key().as_key_slice() <= key);
+        if skipped == 0 {
+            current.seek_to_key(key)?;
+        } else {
+            let idx = self.next_sst_idx + skipped - 1;
+            current.reset_and_seek_to_key(self.sstables[idx].clone(), key)?;
+            self.next_sst_idx = idx + 1;
+        }
+        self.move_until_valid()
+    }
+}
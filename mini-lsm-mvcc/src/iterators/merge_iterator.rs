@@ -12,15 +12,17 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::cmp::{self};
-use std::collections::BinaryHeap;
-use std::collections::binary_heap::PeekMut;
+use alloc::boxed::Box;
+use alloc::collections::BinaryHeap;
+use alloc::collections::binary_heap::PeekMut;
+use alloc::vec::Vec;
+use core::cmp::{self};
 
 use anyhow::Result;
 
 use crate::key::KeySlice;
 
-use super::StorageIterator;
+use super::{SeekableIterator, StorageIterator};
 
 struct HeapWrapper<I: StorageIterator>(pub usize, pub Box<I>);
 
@@ -146,7 +148,7 @@ impl<I: 'static + for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>> StorageIt
         // Otherwise, compare with heap top and swap if necessary.
         if let Some(mut inner_iter) = self.iters.peek_mut() {
             if *current < *inner_iter {
-                std::mem::swap(&mut *inner_iter, current);
+                core::mem::swap(&mut *inner_iter, current);
             }
         }
 
@@ -165,3 +167,21 @@ impl<I: 'static + for<'a> StorageIterator<KeyType<'a> = KeySlice<'a>>> StorageIt
                 .unwrap_or(0)
     }
 }
+
+impl<I: 'static + for<'a> SeekableIterator<KeyType<'a> = KeySlice<'a>>> SeekableIterator
+    for MergeIterator<I>
+{
+    fn seek(&mut self, key: KeySlice) -> Result<()> {
+        if !self.is_valid() || self.key() >= key {
+            return Ok(());
+        }
+        let mut iters = core::mem::take(&mut self.iters).into_vec();
+        iters.extend(self.current.take());
+        for iter in &mut iters {
+            iter.1.seek(key)?;
+        }
+        iters.sort_by_key(|iter| iter.0);
+        *self = Self::create(iters.into_iter().map(|iter| iter.1).collect());
+        Ok(())
+    }
+}
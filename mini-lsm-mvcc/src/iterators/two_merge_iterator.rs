@@ -14,7 +14,7 @@
 
 use anyhow::Result;
 
-use super::StorageIterator;
+use super::{SeekableIterator, StorageIterator};
 
 /// Merges two iterators of different types into one. If the two iterators have the same key, only
 /// produce the key once and prefer the entry from A.
@@ -106,3 +106,19 @@ impl<
         self.a.num_active_iterators() + self.b.num_active_iterators()
     }
 }
+
+impl<
+    A: 'static + SeekableIterator,
+    B: 'static + for<'a> SeekableIterator<KeyType<'a> = A::KeyType<'a>>,
+> SeekableIterator for TwoMergeIterator<A, B>
+where
+    for<'a> A::KeyType<'a>: Copy,
+{
+    fn seek(&mut self, key: A::KeyType<'_>) -> Result<()> {
+        self.a.seek(key)?;
+        self.b.seek(key)?;
+        self.skip_b()?;
+        self.choose_a = Self::choose_a(&self.a, &self.b);
+        Ok(())
+    }
+}
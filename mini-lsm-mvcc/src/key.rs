@@ -12,7 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{cmp::Reverse, fmt::Debug};
+use alloc::vec::Vec;
+use core::{cmp::Reverse, fmt::Debug};
 
 use bytes::Bytes;
 
@@ -43,7 +44,7 @@ impl<T: AsRef<[u8]>> Key<T> {
     }
 
     pub fn raw_len(&self) -> usize {
-        self.0.as_ref().len() + std::mem::size_of::<u64>()
+        self.0.as_ref().len() + core::mem::size_of::<u64>()
     }
 
     pub fn is_empty(&self) -> bool {
@@ -174,7 +175,7 @@ impl<'a> Key<&'a [u8]> {
 }
 
 impl<T: AsRef<[u8]> + Debug> Debug for Key<T> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         self.0.fmt(f)
     }
 }
@@ -202,13 +203,13 @@ impl<T: AsRef<[u8]> + Clone> Clone for Key<T> {
 impl<T: AsRef<[u8]> + Copy> Copy for Key<T> {}
 
 impl<T: AsRef<[u8]> + PartialOrd> PartialOrd for Key<T> {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
         (self.0.as_ref(), Reverse(self.1)).partial_cmp(&(other.0.as_ref(), Reverse(other.1)))
     }
 }
 
 impl<T: AsRef<[u8]> + Ord> Ord for Key<T> {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
         (self.0.as_ref(), Reverse(self.1)).cmp(&(other.0.as_ref(), Reverse(other.1)))
     }
 }
@@ -12,18 +12,95 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+//! Without the default `std` feature, only the on-disk block format, keys, checksums and the iterator
+//! combinators are built, with `alloc` only, e.g., to read blocks on embedded targets.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod background_error;
 pub mod block;
+#[cfg(feature = "std")]
+pub mod block_cache;
+#[cfg(feature = "std")]
+pub mod cdc;
+pub mod checksum;
+#[cfg(feature = "std")]
 pub mod compact;
+#[cfg(feature = "std")]
 pub mod debug;
+#[cfg(feature = "std")]
+pub mod event_listener;
+#[cfg(feature = "std")]
+pub mod export;
+#[cfg(feature = "rocksdb-import")]
+pub mod external_table;
+#[cfg(feature = "std")]
+pub mod file_system;
+#[cfg(feature = "std")]
+pub mod history;
+#[cfg(feature = "std")]
+pub mod hot_keys;
+#[cfg(feature = "server")]
+pub mod http_server;
+#[cfg(feature = "std")]
+pub mod import;
+#[cfg(feature = "std")]
+pub mod ingest;
+#[cfg(feature = "std")]
+pub mod integrity;
+#[cfg(feature = "std")]
+pub mod io_retry;
 pub mod iterators;
 pub mod key;
+#[cfg(feature = "std")]
+pub mod lsm_error;
+#[cfg(feature = "std")]
 pub mod lsm_iterator;
+#[cfg(feature = "std")]
 pub mod lsm_storage;
+#[cfg(feature = "std")]
 pub mod manifest;
+#[cfg(feature = "std")]
 pub mod mem_table;
+#[cfg(feature = "std")]
 pub mod mvcc;
+#[cfg(feature = "std")]
+pub mod pagination;
+#[cfg(feature = "std")]
+pub mod persistent_cache;
+#[cfg(feature = "std")]
+pub(crate) mod platform;
+#[cfg(feature = "std")]
+pub mod prefix;
+#[cfg(feature = "std")]
+pub mod quota;
+#[cfg(feature = "std")]
+pub mod repair;
+#[cfg(feature = "std")]
+pub mod replication;
+#[cfg(feature = "server")]
+pub mod resp_server;
+#[cfg(feature = "std")]
+pub mod scrub;
+#[cfg(feature = "std")]
+pub mod split_points;
+#[cfg(feature = "std")]
+pub mod state_machine;
+#[cfg(feature = "std")]
+pub mod stats;
+#[cfg(feature = "std")]
 pub mod table;
+#[cfg(feature = "std")]
+pub mod trace;
+#[cfg(feature = "std")]
+pub mod value_stats;
+#[cfg(feature = "std")]
 pub mod wal;
+#[cfg(feature = "std")]
+pub mod write_batch;
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests;
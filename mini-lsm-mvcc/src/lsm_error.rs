@@ -0,0 +1,117 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The error type returned by the public API of the storage engine.
+//!
+//! Internally, the engine uses `anyhow` for convenience. Errors that callers may want to handle are raised as
+//! [`Error`] values wrapped in `anyhow::Error`, and recovered again at the public API boundary by the `From`
+//! conversion below, so that embedders can match on the error kind.
+
+use std::fmt;
+
+use bytes::Bytes;
+
+/// The error type of the storage engine.
+#[derive(Debug)]
+pub enum Error {
+    /// An I/O error from the underlying file system.
+    Io(std::io::Error),
+    /// On-disk data failed validation, e.g., a checksum mismatch or a truncated file.
+    Corruption(String),
+    /// The caller passed an argument or an option the engine cannot accept.
+    InvalidArgument(String),
+    /// The key is larger than `LsmStorageOptions::max_key_size`.
+    KeyTooLarge { size: usize, limit: usize },
+    /// The value is larger than `LsmStorageOptions::max_value_size`.
+    ValueTooLarge { size: usize, limit: usize },
+    /// The write would exceed the quota of the key prefix.
+    QuotaExceeded {
+        prefix: Bytes,
+        usage: u64,
+        limit: u64,
+    },
+    /// The operation conflicted with a concurrent one and may succeed if retried, e.g., a serializable transaction
+    /// failed validation.
+    Busy(String),
+    /// The engine stopped accepting requests after an unrecoverable error.
+    Poisoned(String),
+    /// The engine was opened with `LsmStorageOptions::read_only` and cannot be modified.
+    ReadOnly,
+    /// The operation was aborted through its `CancelHandle`.
+    Cancelled,
+    /// Any other error.
+    Other(anyhow::Error),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "I/O error: {e}"),
+            Error::Corruption(msg) => write!(f, "corruption: {msg}"),
+            Error::InvalidArgument(msg) => write!(f, "invalid argument: {msg}"),
+            Error::KeyTooLarge { size, limit } => {
+                write!(f, "key of {size} bytes exceeds the limit of {limit} bytes")
+            }
+            Error::ValueTooLarge { size, limit } => {
+                write!(
+                    f,
+                    "value of {size} bytes exceeds the limit of {limit} bytes"
+                )
+            }
+            Error::QuotaExceeded {
+                prefix,
+                usage,
+                limit,
+            } => write!(
+                f,
+                "quota of prefix {prefix:?} exceeded: {usage} of {limit} bytes used"
+            ),
+            Error::Busy(msg) => write!(f, "busy: {msg}"),
+            Error::Poisoned(msg) => write!(f, "poisoned: {msg}"),
+            Error::ReadOnly => write!(f, "the storage is opened read-only"),
+            Error::Cancelled => write!(f, "the operation was cancelled"),
+            Error::Other(e) => write!(f, "{e:#}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Other(e) => e.source(),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(e: anyhow::Error) -> Self {
+        match e.downcast::<Error>() {
+            Ok(e) => e,
+            Err(e) => match e.downcast::<std::io::Error>() {
+                Ok(e) => Error::Io(e),
+                Err(e) => Error::Other(e),
+            },
+        }
+    }
+}
@@ -13,29 +13,57 @@
 // limitations under the License.
 
 use std::ops::Bound;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use anyhow::{Result, bail};
 use bytes::Bytes;
 
-use crate::iterators::StorageIterator;
 use crate::iterators::concat_iterator::SstConcatIterator;
 use crate::iterators::merge_iterator::MergeIterator;
 use crate::iterators::two_merge_iterator::TwoMergeIterator;
+use crate::iterators::{SeekableIterator, StorageIterator, ToKeyBytes};
+use crate::key::{self, KeyBytes, KeySlice};
+use crate::lsm_error::Error;
 use crate::mem_table::MemTableIterator;
-use crate::table::SsTableIterator;
+use crate::table::{CorruptedBlock, CorruptionReport, SsTableIterator};
 
 /// Represents the internal type for an LSM iterator. This type will be changed across the course for multiple times.
-type LsmIteratorInner = TwoMergeIterator<
+pub(crate) type LsmIteratorInner = TwoMergeIterator<
     TwoMergeIterator<MergeIterator<MemTableIterator>, MergeIterator<SsTableIterator>>,
     MergeIterator<SstConcatIterator>,
 >;
 
+/// Recreates the inner iterator of a scan positioned at the given key.
+pub(crate) type ReopenFn = Box<dyn Fn(&[u8]) -> Result<LsmIteratorInner> + Send + Sync>;
+
+/// Aborts an iterator from another thread, e.g., when the client of a server abandons a query. The next call to
+/// `next` on the iterator fails with `Error::Cancelled`.
+#[derive(Clone, Debug, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
 pub struct LsmIterator {
     inner: LsmIteratorInner,
     end_bound: Bound<Bytes>,
     is_valid: bool,
     read_ts: u64,
     prev_key: Vec<u8>,
+    cancel: CancelHandle,
+    /// Recreates `inner` every this many keys, see `ReadOptions::max_keys_per_poll`.
+    reopen: Option<(usize, ReopenFn)>,
+    keys_since_poll: usize,
+    /// The blocks the SST iterators skipped as corrupted, see `ReadOptions::skip_corrupted_blocks`.
+    corrupted: Option<CorruptionReport>,
 }
 
 impl LsmIterator {
@@ -43,30 +71,96 @@ impl LsmIterator {
         iter: LsmIteratorInner,
         end_bound: Bound<Bytes>,
         read_ts: u64,
+    ) -> Result<Self> {
+        Self::with_report(iter, end_bound, read_ts, None)
+    }
+
+    /// Create an iterator hiding the versions that may be older than one in a block added to `corrupted` by the SST
+    /// iterators of `iter`.
+    pub(crate) fn with_report(
+        iter: LsmIteratorInner,
+        end_bound: Bound<Bytes>,
+        read_ts: u64,
+        corrupted: Option<CorruptionReport>,
     ) -> Result<Self> {
         let mut iter = Self {
-            is_valid: iter.is_valid(),
+            is_valid: true,
             inner: iter,
             end_bound,
             read_ts,
             prev_key: Vec::new(),
+            cancel: CancelHandle::default(),
+            reopen: None,
+            keys_since_poll: 0,
+            corrupted,
         };
+        iter.check_end_bound();
         iter.move_to_key()?;
         Ok(iter)
     }
 
+    /// The commit timestamp of the current version.
+    pub fn ts(&self) -> u64 {
+        self.inner.key().ts()
+    }
+
+    /// A handle to abort the iterator from another thread.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    /// The blocks skipped so far as corrupted, in the order they were found.
+    pub fn corrupted_blocks(&self) -> Vec<CorruptedBlock> {
+        self.corrupted
+            .as_ref()
+            .map(CorruptionReport::blocks)
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn corruption_report(&self) -> Option<CorruptionReport> {
+        self.corrupted.clone()
+    }
+
+    /// Recreate the inner iterator with `reopen` every `max_keys` keys, releasing what the old one pinned.
+    pub(crate) fn set_reopen(&mut self, max_keys: usize, reopen: ReopenFn) {
+        self.reopen = Some((max_keys.max(1), reopen));
+    }
+
+    /// Called before moving past the current key. Fails if the iterator was cancelled, and recreates the inner
+    /// iterator at the current key once `max_keys_per_poll` keys were returned since the last time.
+    fn poll(&mut self) -> Result<()> {
+        if self.cancel.is_cancelled() {
+            bail!(Error::Cancelled);
+        }
+        if let Some((max_keys, reopen)) = &self.reopen
+            && self.is_valid
+        {
+            self.keys_since_poll += 1;
+            if self.keys_since_poll >= *max_keys {
+                self.keys_since_poll = 0;
+                // The new iterator starts at the version of the current key visible at `read_ts`, like the old one.
+                self.inner = reopen(&self.prev_key)?;
+            }
+        }
+        Ok(())
+    }
+
     fn next_inner(&mut self) -> Result<()> {
         self.inner.next()?;
+        self.check_end_bound();
+        Ok(())
+    }
+
+    fn check_end_bound(&mut self) {
         if !self.inner.is_valid() {
             self.is_valid = false;
-            return Ok(());
+            return;
         }
         match self.end_bound.as_ref() {
             Bound::Unbounded => {}
             Bound::Included(key) => self.is_valid = self.inner.key().key_ref() <= key.as_ref(),
             Bound::Excluded(key) => self.is_valid = self.inner.key().key_ref() < key.as_ref(),
         }
-        Ok(())
     }
 
     fn move_to_key(&mut self) -> Result<()> {
@@ -91,7 +185,11 @@ impl LsmIterator {
             if self.inner.key().key_ref() != self.prev_key {
                 continue;
             }
-            if !self.inner.value().is_empty() {
+            // A skipped block may have held a newer version, or a delete, of the key
+            let masked = self.corrupted.as_ref().is_some_and(|corrupted| {
+                corrupted.masks(self.inner.key().key_ref(), self.inner.key().ts())
+            });
+            if !self.inner.value().is_empty() && !masked {
                 break;
             }
         }
@@ -115,6 +213,7 @@ impl StorageIterator for LsmIterator {
     }
 
     fn next(&mut self) -> Result<()> {
+        self.poll()?;
         self.next_inner()?;
         self.move_to_key()?;
         Ok(())
@@ -123,6 +222,36 @@ impl StorageIterator for LsmIterator {
     fn num_active_iterators(&self) -> usize {
         self.inner.num_active_iterators()
     }
+
+    /// Unlike the default implementation, the collected keys keep the commit timestamp of their version.
+    fn next_batch(&mut self, out: &mut Vec<(KeyBytes, Bytes)>, n: usize) -> Result<usize> {
+        let mut cnt = 0;
+        while cnt < n && self.is_valid {
+            out.push((
+                self.inner.key().to_key_bytes(),
+                Bytes::copy_from_slice(self.inner.value()),
+            ));
+            self.poll()?;
+            self.next_inner()?;
+            self.move_to_key()?;
+            cnt += 1;
+        }
+        Ok(cnt)
+    }
+}
+
+impl SeekableIterator for LsmIterator {
+    /// Repositions the memtable and SST iterators in place instead of creating new ones.
+    fn seek(&mut self, key: &[u8]) -> Result<()> {
+        if !self.is_valid || self.key() >= key {
+            return Ok(());
+        }
+        self.inner
+            .seek(KeySlice::from_slice(key, key::TS_RANGE_BEGIN))?;
+        self.check_end_bound();
+        self.prev_key.clear();
+        self.move_to_key()
+    }
 }
 
 /// A wrapper around existing iterator, will prevent users from calling `next` when the iterator is
@@ -142,7 +271,28 @@ impl<I: StorageIterator> FusedIterator<I> {
     }
 }
 
-impl<I: StorageIterator> StorageIterator for FusedIterator<I> {
+impl FusedIterator<LsmIterator> {
+    pub fn ts(&self) -> u64 {
+        if !self.is_valid() {
+            panic!("invalid access to the underlying iterator");
+        }
+        self.iter.ts()
+    }
+
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.iter.cancel_handle()
+    }
+
+    pub fn corrupted_blocks(&self) -> Vec<CorruptedBlock> {
+        self.iter.corrupted_blocks()
+    }
+
+    pub(crate) fn corruption_report(&self) -> Option<CorruptionReport> {
+        self.iter.corruption_report()
+    }
+}
+
+impl<I: StorageIterator + 'static> StorageIterator for FusedIterator<I> {
     type KeyType<'a>
         = I::KeyType<'a>
     where
@@ -183,4 +333,25 @@ impl<I: StorageIterator> StorageIterator for FusedIterator<I> {
     fn num_active_iterators(&self) -> usize {
         self.iter.num_active_iterators()
     }
+
+    fn next_batch(&mut self, out: &mut Vec<(KeyBytes, Bytes)>, n: usize) -> Result<usize>
+    where
+        for<'a> Self::KeyType<'a>: ToKeyBytes,
+    {
+        if self.has_errored {
+            bail!("the iterator is tainted");
+        }
+        self.iter
+            .next_batch(out, n)
+            .inspect_err(|_| self.has_errored = true)
+    }
+}
+
+impl<I: SeekableIterator + 'static> SeekableIterator for FusedIterator<I> {
+    fn seek(&mut self, key: Self::KeyType<'_>) -> Result<()> {
+        if self.has_errored {
+            bail!("the iterator is tainted");
+        }
+        self.iter.seek(key).inspect_err(|_| self.has_errored = true)
+    }
 }
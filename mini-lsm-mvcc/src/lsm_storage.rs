@@ -12,35 +12,66 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::{BTreeSet, HashMap};
-use std::fs::File;
-use std::ops::Bound;
+use std::collections::HashMap;
+use std::ops::{Bound, Deref, DerefMut};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::{AtomicBool, AtomicUsize};
+use std::time::{Duration, Instant};
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
+use arc_swap::ArcSwap;
 use bytes::Bytes;
-use parking_lot::{Mutex, MutexGuard, RwLock};
-
-use crate::block::Block;
+use parking_lot::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::background_error::{BackgroundErrorPolicy, BackgroundErrors};
+use crate::block::DEFAULT_RESTART_INTERVAL;
+use crate::checksum::BLOCK_CHECKSUM;
 use crate::compact::{
-    CompactionController, CompactionOptions, LeveledCompactionController, LeveledCompactionOptions,
-    SimpleLeveledCompactionController, SimpleLeveledCompactionOptions, TieredCompactionController,
+    CompactionController, CompactionOptions, CompactionPlan, LeveledCompactionOptions,
+    SimpleLeveledCompactionOptions,
 };
-use crate::iterators::StorageIterator;
+use crate::event_listener::EventListener;
+use crate::export::ExportManifest;
+use crate::file_system::{FileSystem, FileSystemOptions};
+use crate::hot_keys::{Access, HotKey, HotKeyTracker};
+use crate::ingest::IngestSummary;
+use crate::io_retry::{IoRetry, IoRetryPolicy};
 use crate::iterators::concat_iterator::SstConcatIterator;
 use crate::iterators::merge_iterator::MergeIterator;
 use crate::iterators::two_merge_iterator::TwoMergeIterator;
+use crate::iterators::{SeekableIterator, StorageIterator};
 use crate::key::{self, KeySlice};
-use crate::lsm_iterator::{FusedIterator, LsmIterator};
-use crate::manifest::{Manifest, ManifestRecord};
-use crate::mem_table::{MemTable, map_bound, map_key_bound_plus_ts};
-use crate::mvcc::LsmMvccInner;
+use crate::lsm_error::{self, Error};
+use crate::lsm_iterator::{FusedIterator, LsmIterator, LsmIteratorInner};
+use crate::manifest::{FormatOptions, Manifest, ManifestRecord, ManifestReplay};
+use crate::mem_table::{MemTable, MemTableStats, map_bound, map_key_bound_plus_ts};
+use crate::mvcc::snapshot::Snapshot;
+use crate::mvcc::ts_provider::TsProviderOptions;
 use crate::mvcc::txn::{Transaction, TxnIterator};
-use crate::table::{FileObject, SsTable, SsTableBuilder, SsTableIterator};
+use crate::mvcc::{LsmMvccInner, prepared};
+use crate::pagination::PageLeases;
+use crate::persistent_cache::{PersistentBlockCache, PersistentCacheOptions};
+use crate::platform;
+use crate::prefix::{PrefixExtractor, PrefixExtractorOptions, prefix_successor};
+use crate::quota::{PrefixQuotas, QuotaUsage};
+use crate::scrub::Scrubber;
+use crate::stats::SstEntryStats;
+use crate::table::{
+    CorruptionReport, FileObject, SsTable, SsTableBuilder, SsTableIterator, SstOrigin,
+    TablePropertiesCollectorFactory, VALUE_CHECKSUM_SIZE, ValueSchema,
+};
+use crate::trace::Tracer;
+use crate::wal::WalPool;
 
-pub type BlockCache = moka::sync::Cache<(usize, usize), Arc<Block>>;
+pub use crate::block_cache::BlockCache;
+
+/// Whether the target can spawn threads. On wasm32 the engine runs on the calling thread only: recovery is sequential,
+/// the block cache does its housekeeping inline, and background work only runs through `MiniLsm::tick`.
+pub(crate) const THREADS_SUPPORTED: bool = cfg!(not(target_family = "wasm"));
 
 /// Represents the state of the storage engine.
 #[derive(Clone)]
@@ -58,20 +89,34 @@ pub struct LsmStorageState {
     pub sstables: HashMap<usize, Arc<SsTable>>,
 }
 
+/// A step of recovery reported by `MiniLsm::open_with_progress`, counting what is done out of the total.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecoveryProgress {
+    ManifestRecordsApplied { applied: usize, total: usize },
+    SstsOpened { opened: usize, total: usize },
+    WalsReplayed { replayed: usize, total: usize },
+}
+
 pub enum WriteBatchRecord<T: AsRef<[u8]>> {
     Put(T, T),
     Del(T),
 }
 
+/// The largest key or value the block, SST and WAL formats can encode, as lengths are stored as `u16`.
+pub const MAX_KEY_VALUE_SIZE: usize = u16::MAX as usize;
+
+/// The number of latches serializing `increment`, each guarding the keys hashed to it.
+const NUM_INCREMENT_LATCHES: usize = 64;
+
 impl LsmStorageState {
-    fn create(options: &LsmStorageOptions) -> Self {
+    pub(crate) fn create(options: &LsmStorageOptions) -> Self {
         let levels = match &options.compaction_options {
             CompactionOptions::Leveled(LeveledCompactionOptions { max_levels, .. })
             | CompactionOptions::Simple(SimpleLeveledCompactionOptions { max_levels, .. }) => (1
                 ..=*max_levels)
                 .map(|level| (level, Vec::new()))
                 .collect::<Vec<_>>(),
-            CompactionOptions::Tiered(_) => Vec::new(),
+            CompactionOptions::Tiered(_) | CompactionOptions::LazyLeveled(_) => Vec::new(),
             CompactionOptions::NoCompaction => vec![(1, Vec::new())],
         };
         Self {
@@ -82,9 +127,93 @@ impl LsmStorageState {
             sstables: Default::default(),
         }
     }
+
+    /// Sort the SSTs on each level by their first keys, as the manifest does not record the order.
+    pub(crate) fn sort_levels_by_first_key(&mut self) {
+        for (_id, ssts) in &mut self.levels {
+            ssts.sort_by(|x, y| {
+                self.sstables
+                    .get(x)
+                    .unwrap()
+                    .first_key()
+                    .cmp(self.sstables.get(y).unwrap().first_key())
+            })
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
+/// Holds the current `LsmStorageState`. State changes are serialized by the lock, and each new state is published to
+/// a lock-free cell when the write guard is dropped, so that `load` never waits for the lock, not even behind a writer
+/// queued on it.
+pub(crate) struct StateCell {
+    lock: RwLock<Arc<LsmStorageState>>,
+    published: ArcSwap<LsmStorageState>,
+}
+
+impl StateCell {
+    fn new(state: LsmStorageState) -> Self {
+        let state = Arc::new(state);
+        Self {
+            published: ArcSwap::new(state.clone()),
+            lock: RwLock::new(state),
+        }
+    }
+
+    /// Load the latest published state without locking.
+    pub(crate) fn load(&self) -> Arc<LsmStorageState> {
+        self.published.load_full()
+    }
+
+    /// Lock the state for reading, which keeps it from being replaced, e.g., to write to the memtable without racing
+    /// with a freeze.
+    pub(crate) fn read(&self) -> RwLockReadGuard<'_, Arc<LsmStorageState>> {
+        self.lock.read()
+    }
+
+    /// Lock the state for replacing it. The new state is published when the guard is dropped.
+    pub(crate) fn write(&self) -> StateWriteGuard<'_> {
+        StateWriteGuard {
+            guard: self.lock.write(),
+            published: &self.published,
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn try_write(&self) -> Option<StateWriteGuard<'_>> {
+        Some(StateWriteGuard {
+            guard: self.lock.try_write()?,
+            published: &self.published,
+        })
+    }
+}
+
+pub(crate) struct StateWriteGuard<'a> {
+    guard: RwLockWriteGuard<'a, Arc<LsmStorageState>>,
+    published: &'a ArcSwap<LsmStorageState>,
+}
+
+impl Deref for StateWriteGuard<'_> {
+    type Target = Arc<LsmStorageState>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl DerefMut for StateWriteGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+impl Drop for StateWriteGuard<'_> {
+    fn drop(&mut self) {
+        // Publish while still holding the lock, so that states are published in the order they are written.
+        self.published.store(self.guard.clone());
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LsmStorageOptions {
     // Block size in bytes
     pub block_size: usize,
@@ -95,9 +224,151 @@ pub struct LsmStorageOptions {
     pub compaction_options: CompactionOptions,
     pub enable_wal: bool,
     pub serializable: bool,
+    // Maximum key size in bytes, must not exceed `MAX_KEY_VALUE_SIZE`
+    pub max_key_size: usize,
+    // Maximum value size in bytes, must not exceed `MAX_KEY_VALUE_SIZE`
+    pub max_value_size: usize,
+    // Rewrite SSTs that have not been compacted for this long, so that deletes and old versions in cold key ranges are
+    // reclaimed
+    pub periodic_compaction_ttl: Option<Duration>,
+    // Number of L0 SSTs that triggers compacting L0, overriding the trigger in `compaction_options`. Flushes are
+    // triggered separately by `num_memtable_limit`
+    pub level0_file_num_compaction_trigger: Option<usize>,
+    // Create the storage if the directory does not hold one yet
+    pub create_if_missing: bool,
+    // Refuse to open a directory that already holds a storage
+    pub error_if_exists: bool,
+    // Reject all writes and leave the files in the directory untouched, including WALs and the manifest
+    pub read_only: bool,
+    // Scrub SSTs in the background at this many bytes per second, validating block checksums and quarantining
+    // corrupt SSTs before a read runs into them
+    pub scrub_bytes_per_sec: Option<u64>,
+    // Preallocate each WAL to this many bytes, and keep the WALs of flushed memtables for reuse instead of deleting
+    // them, so that appends do not change the file size
+    pub wal_segment_size: Option<usize>,
+    // Replace the manifest with a snapshot of the LSM structure once it grows beyond this many bytes, so that recovery
+    // does not replay every record ever written
+    pub manifest_rotation_size: Option<u64>,
+    // Check after each compaction that the outputs hold the same versions visible at or above the watermark as the
+    // inputs, before the inputs are removed. Useful while developing compaction strategies
+    pub verify_compaction: bool,
+    // Fail to build SSTs whose keys are not added in order, which is always checked in debug builds
+    pub check_sst_key_order: bool,
+    // Keep the snapshot of a paginated scan readable for this long after each page, so that the next page is consistent
+    // with it
+    pub scan_page_ttl: Duration,
+    // With leveled compaction, rewrite SSTs in which more than this fraction of the entries are deletes ahead of
+    // size-triggered compactions, so that delete-heavy workloads reclaim space promptly
+    pub tombstone_compaction_ratio: Option<f64>,
+    // With any compaction strategy, rewrite a single SST in which more than this fraction of the entries are deletes
+    // when the strategy has nothing else to compact, without compacting the whole level
+    pub delete_compaction_ratio: Option<f64>,
+    // Store a bloom filter for each data block in the SSTs, so that point lookups skip SSTs whose blocks covering the
+    // key do not hold it. Helps with large blocks and poor key locality, at the cost of a larger meta section
+    pub block_filters: bool,
+    // Interpret the values as 8-byte big-endian numbers and record their range in each data block of the SSTs, so
+    // that `scan_where_value_between` skips the blocks out of its range
+    pub value_schema: Option<ValueSchema>,
+    // Compress each SST value longer than this many bytes on its own with Snappy, so that large values shrink while
+    // small ones skip the compression cost. The flag stored with each value takes a byte of max_value_size, which
+    // must therefore be below `MAX_KEY_VALUE_SIZE`
+    pub value_compression_threshold: Option<usize>,
+    // Read the bloom filter of every SST when opening the storage. When unset, each filter is read by the first
+    // lookup that needs it instead, so that large databases open faster
+    pub preload_bloom_filters: bool,
+    // Fail opening the storage once recovery has taken longer than this
+    pub open_timeout: Option<Duration>,
+    // The number of threads opening SSTs and replaying WALs in parallel when opening the storage
+    pub recovery_threads: usize,
+    // Run flushes, compactions and scrubbing only when driven by MiniLsm::tick or run_pending_tasks instead of
+    // background threads, and derive all randomness from this seed, so that a test runs the same way every time
+    pub deterministic_seed: Option<u64>,
+    // What the flush and compaction threads do when a task fails, unless MiniLsm::set_background_error_handler is used
+    pub background_error_policy: BackgroundErrorPolicy,
+    // Retry SST reads and WAL and manifest writes that fail with a transient I/O error, e.g., EINTR or EAGAIN
+    pub io_retry_policy: Option<IoRetryPolicy>,
+    // The number of independently locked shards of the block cache, which blocks are spread across by hashing their
+    // SST id and index
+    pub block_cache_shards: usize,
+    // The bits per key of the bloom filters of the SSTs in each level, starting from L0, which flushes write to. Levels
+    // past the end use the last entry, and all use the bits for a 1% false positive rate if empty
+    pub bloom_bits_per_key: Vec<usize>,
+    // How long Transaction::get_for_update waits for the lock of a key held by another transaction before failing
+    pub lock_timeout: Duration,
+    // Store a full key every this many entries of a block, prefix-compressing the others against it. Larger intervals
+    // compress the keys better, but a seek decodes more entries
+    pub block_restart_interval: usize,
+    // Store the checksum of each entry after its value in the SSTs, so that reads verifying checksums detect damage
+    // within a block down to the entry. Takes 4 bytes of max_value_size
+    pub value_checksums: bool,
+    // Back the in-memory block cache with a cache of blocks in files on a local disk, which survives restarts. Saves
+    // reading the SSTs when they are slow or costly to read, e.g., on network storage
+    pub persistent_cache: Option<PersistentCacheOptions>,
+    // After a compaction, load into the block cache the output blocks covering the keys of the input blocks that were
+    // cached, so that reads of hot keys do not all miss the cache once the inputs are replaced
+    pub warm_cache_after_compaction: bool,
+    // The number of immutable memtables the flush thread writes to SSTs in parallel, so that it keeps up with bursts
+    // of writes freezing memtables faster than flushing them one at a time drains them
+    pub flush_threads: usize,
+    // Map keys to the prefixes grouping them, e.g., a tenant or a table. The prefixes are added to the bloom filters of
+    // the SSTs, so that scans within a prefix skip the SSTs without it, and group the hot keys into hot prefixes
+    pub prefix_extractor: Option<PrefixExtractorOptions>,
+    // Where commit timestamps come from: a counter, a hybrid logical clock tracking wall time, or an external source
+    // shared by several nodes
+    pub ts_provider: TsProviderOptions,
+    // Where the SSTs and WALs are stored: `std::fs` by default, or a file system provided by the host, e.g., a WASM
+    // runtime embedding the engine
+    pub file_system: FileSystemOptions,
 }
 
 impl LsmStorageOptions {
+    /// The defaults of the builder, on which the test presets below override the options of their week.
+    fn base() -> Self {
+        Self {
+            block_size: 4096,
+            target_sst_size: 2 << 20, // 2MB
+            compaction_options: CompactionOptions::NoCompaction,
+            enable_wal: false,
+            num_memtable_limit: 3,
+            serializable: false,
+            max_key_size: MAX_KEY_VALUE_SIZE,
+            max_value_size: MAX_KEY_VALUE_SIZE,
+            periodic_compaction_ttl: None,
+            level0_file_num_compaction_trigger: None,
+            create_if_missing: true,
+            error_if_exists: false,
+            read_only: false,
+            scrub_bytes_per_sec: None,
+            wal_segment_size: None,
+            manifest_rotation_size: None,
+            verify_compaction: false,
+            check_sst_key_order: false,
+            scan_page_ttl: Duration::from_secs(60),
+            tombstone_compaction_ratio: None,
+            delete_compaction_ratio: None,
+            block_filters: false,
+            value_schema: None,
+            value_compression_threshold: None,
+            preload_bloom_filters: true,
+            open_timeout: None,
+            recovery_threads: 4,
+            deterministic_seed: None,
+            background_error_policy: BackgroundErrorPolicy::HaltWrites,
+            io_retry_policy: None,
+            block_cache_shards: 16,
+            bloom_bits_per_key: Vec::new(),
+            lock_timeout: Duration::from_secs(1),
+            block_restart_interval: DEFAULT_RESTART_INTERVAL,
+            value_checksums: false,
+            persistent_cache: None,
+            warm_cache_after_compaction: false,
+            flush_threads: 1,
+            prefix_extractor: None,
+            ts_provider: TsProviderOptions::Logical,
+            file_system: FileSystemOptions::Std,
+        }
+    }
+
     pub fn default_for_week1_test() -> Self {
         Self {
             block_size: 4096,
@@ -106,6 +377,7 @@ impl LsmStorageOptions {
             enable_wal: false,
             num_memtable_limit: 50,
             serializable: false,
+            ..Self::base()
         }
     }
 
@@ -117,6 +389,7 @@ impl LsmStorageOptions {
             enable_wal: false,
             num_memtable_limit: 2,
             serializable: false,
+            ..Self::base()
         }
     }
 
@@ -128,11 +401,433 @@ impl LsmStorageOptions {
             enable_wal: false,
             num_memtable_limit: 2,
             serializable: false,
+            ..Self::base()
+        }
+    }
+
+    /// The options recorded in the manifest that a reopened engine must agree on.
+    pub fn format_options(&self) -> FormatOptions {
+        FormatOptions {
+            format_version: FormatOptions::FORMAT_VERSION,
+            comparator: "bytewise".to_string(),
+            mvcc: true,
+            compression: "none".to_string(),
+            checksum: BLOCK_CHECKSUM.to_string(),
+            value_compression: match self.value_compression_threshold {
+                Some(_) => "snappy".to_string(),
+                None => "none".to_string(),
+            },
+            compaction: self.compaction_options.name().to_string(),
+        }
+    }
+
+    /// The bits per key of the bloom filters of SSTs in `level`, if configured.
+    pub fn bloom_bits_per_key_of_level(&self, level: usize) -> Option<usize> {
+        self.bloom_bits_per_key
+            .get(level)
+            .or(self.bloom_bits_per_key.last())
+            .copied()
+    }
+
+    pub fn builder() -> LsmStorageOptionsBuilder {
+        LsmStorageOptionsBuilder::default()
+    }
+
+    /// Load options from a JSON file, e.g., one written by serializing `LsmStorageOptions` with `serde_json`.
+    pub fn load(path: impl AsRef<Path>) -> lsm_error::Result<Self> {
+        let data = std::fs::read(path)?;
+        let options: Self = serde_json::from_slice(&data)
+            .map_err(|e| Error::InvalidArgument(format!("failed to parse options: {e}")))?;
+        options.validate()?;
+        Ok(options)
+    }
+
+    /// Check that the options are consistent with each other and within the limits of the storage format.
+    pub fn validate(&self) -> lsm_error::Result<()> {
+        fn check(ok: bool, msg: &str) -> lsm_error::Result<()> {
+            if !ok {
+                return Err(Error::InvalidArgument(msg.to_string()));
+            }
+            Ok(())
+        }
+        // Offsets inside a block are encoded as u16
+        check(
+            self.block_size >= 1 && self.block_size <= u16::MAX as usize,
+            "block_size must be between 1 and 65535 bytes",
+        )?;
+        check(
+            self.target_sst_size >= 1,
+            "target_sst_size must be at least 1",
+        )?;
+        check(
+            self.num_memtable_limit >= 1,
+            "num_memtable_limit must be at least 1",
+        )?;
+        check(
+            self.max_key_size <= MAX_KEY_VALUE_SIZE && self.max_value_size <= MAX_KEY_VALUE_SIZE,
+            &format!("max_key_size and max_value_size must not exceed {MAX_KEY_VALUE_SIZE} bytes"),
+        )?;
+        check(
+            self.level0_file_num_compaction_trigger != Some(0),
+            "level0_file_num_compaction_trigger must be at least 1",
+        )?;
+        check(
+            self.periodic_compaction_ttl != Some(Duration::ZERO),
+            "periodic_compaction_ttl must not be zero",
+        )?;
+        check(
+            self.scrub_bytes_per_sec != Some(0),
+            "scrub_bytes_per_sec must be at least 1",
+        )?;
+        check(
+            self.wal_segment_size != Some(0),
+            "wal_segment_size must be at least 1",
+        )?;
+        check(
+            self.manifest_rotation_size != Some(0),
+            "manifest_rotation_size must be at least 1",
+        )?;
+        check(
+            self.tombstone_compaction_ratio
+                .is_none_or(|ratio| ratio > 0.0 && ratio <= 1.0),
+            "tombstone_compaction_ratio must be in (0, 1]",
+        )?;
+        check(
+            self.delete_compaction_ratio
+                .is_none_or(|ratio| ratio > 0.0 && ratio <= 1.0),
+            "delete_compaction_ratio must be in (0, 1]",
+        )?;
+        check(
+            self.value_compression_threshold.is_none() || self.max_value_size < MAX_KEY_VALUE_SIZE,
+            &format!(
+                "max_value_size must be below {MAX_KEY_VALUE_SIZE} bytes to fit the value compression flag"
+            ),
+        )?;
+        check(
+            self.recovery_threads >= 1,
+            "recovery_threads must be at least 1",
+        )?;
+        check(self.flush_threads >= 1, "flush_threads must be at least 1")?;
+        check(
+            self.block_cache_shards >= 1,
+            "block_cache_shards must be at least 1",
+        )?;
+        check(
+            self.bloom_bits_per_key.iter().all(|&bits| bits >= 1),
+            "bloom_bits_per_key must be at least 1",
+        )?;
+        check(
+            (1..=u16::MAX as usize).contains(&self.block_restart_interval),
+            &format!("block_restart_interval must be in [1, {}]", u16::MAX),
+        )?;
+        let value_flag_size = self.value_compression_threshold.is_some() as usize;
+        check(
+            !self.value_checksums
+                || self.max_value_size + value_flag_size + VALUE_CHECKSUM_SIZE
+                    <= MAX_KEY_VALUE_SIZE,
+            &format!(
+                "max_value_size must leave {VALUE_CHECKSUM_SIZE} of {MAX_KEY_VALUE_SIZE} bytes to fit the value checksum"
+            ),
+        )?;
+        Ok(self.compaction_options.validate()?)
+    }
+}
+
+/// Builds [`LsmStorageOptions`], checking them with [`LsmStorageOptions::validate`] before handing them out.
+#[derive(Debug, Clone)]
+pub struct LsmStorageOptionsBuilder {
+    options: LsmStorageOptions,
+}
+
+impl Default for LsmStorageOptionsBuilder {
+    fn default() -> Self {
+        Self {
+            options: LsmStorageOptions::base(),
+        }
+    }
+}
+
+impl LsmStorageOptionsBuilder {
+    pub fn block_size(mut self, block_size: usize) -> Self {
+        self.options.block_size = block_size;
+        self
+    }
+
+    pub fn target_sst_size(mut self, target_sst_size: usize) -> Self {
+        self.options.target_sst_size = target_sst_size;
+        self
+    }
+
+    pub fn num_memtable_limit(mut self, num_memtable_limit: usize) -> Self {
+        self.options.num_memtable_limit = num_memtable_limit;
+        self
+    }
+
+    pub fn compaction_options(mut self, compaction_options: CompactionOptions) -> Self {
+        self.options.compaction_options = compaction_options;
+        self
+    }
+
+    pub fn enable_wal(mut self, enable_wal: bool) -> Self {
+        self.options.enable_wal = enable_wal;
+        self
+    }
+
+    pub fn serializable(mut self, serializable: bool) -> Self {
+        self.options.serializable = serializable;
+        self
+    }
+
+    pub fn max_key_size(mut self, max_key_size: usize) -> Self {
+        self.options.max_key_size = max_key_size;
+        self
+    }
+
+    pub fn max_value_size(mut self, max_value_size: usize) -> Self {
+        self.options.max_value_size = max_value_size;
+        self
+    }
+
+    pub fn periodic_compaction_ttl(mut self, ttl: Duration) -> Self {
+        self.options.periodic_compaction_ttl = Some(ttl);
+        self
+    }
+
+    pub fn level0_file_num_compaction_trigger(mut self, trigger: usize) -> Self {
+        self.options.level0_file_num_compaction_trigger = Some(trigger);
+        self
+    }
+
+    pub fn create_if_missing(mut self, create_if_missing: bool) -> Self {
+        self.options.create_if_missing = create_if_missing;
+        self
+    }
+
+    pub fn error_if_exists(mut self, error_if_exists: bool) -> Self {
+        self.options.error_if_exists = error_if_exists;
+        self
+    }
+
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.options.read_only = read_only;
+        self
+    }
+
+    pub fn scrub_bytes_per_sec(mut self, bytes_per_sec: u64) -> Self {
+        self.options.scrub_bytes_per_sec = Some(bytes_per_sec);
+        self
+    }
+
+    pub fn wal_segment_size(mut self, segment_size: usize) -> Self {
+        self.options.wal_segment_size = Some(segment_size);
+        self
+    }
+
+    pub fn manifest_rotation_size(mut self, rotation_size: u64) -> Self {
+        self.options.manifest_rotation_size = Some(rotation_size);
+        self
+    }
+
+    pub fn verify_compaction(mut self, verify_compaction: bool) -> Self {
+        self.options.verify_compaction = verify_compaction;
+        self
+    }
+
+    pub fn check_sst_key_order(mut self, check_sst_key_order: bool) -> Self {
+        self.options.check_sst_key_order = check_sst_key_order;
+        self
+    }
+
+    pub fn scan_page_ttl(mut self, scan_page_ttl: Duration) -> Self {
+        self.options.scan_page_ttl = scan_page_ttl;
+        self
+    }
+
+    pub fn tombstone_compaction_ratio(mut self, ratio: f64) -> Self {
+        self.options.tombstone_compaction_ratio = Some(ratio);
+        self
+    }
+
+    pub fn delete_compaction_ratio(mut self, ratio: f64) -> Self {
+        self.options.delete_compaction_ratio = Some(ratio);
+        self
+    }
+
+    pub fn block_filters(mut self, block_filters: bool) -> Self {
+        self.options.block_filters = block_filters;
+        self
+    }
+
+    pub fn value_schema(mut self, schema: ValueSchema) -> Self {
+        self.options.value_schema = Some(schema);
+        self
+    }
+
+    /// Also lowers `max_value_size` to `MAX_KEY_VALUE_SIZE - 1` if it is higher, leaving room for the compression flag
+    /// of each value. Setting a higher `max_value_size` afterwards makes `build` fail.
+    pub fn value_compression_threshold(mut self, threshold: usize) -> Self {
+        self.options.value_compression_threshold = Some(threshold);
+        self.options.max_value_size = self.options.max_value_size.min(MAX_KEY_VALUE_SIZE - 1);
+        self
+    }
+
+    pub fn preload_bloom_filters(mut self, preload: bool) -> Self {
+        self.options.preload_bloom_filters = preload;
+        self
+    }
+
+    pub fn open_timeout(mut self, timeout: Duration) -> Self {
+        self.options.open_timeout = Some(timeout);
+        self
+    }
+
+    pub fn recovery_threads(mut self, threads: usize) -> Self {
+        self.options.recovery_threads = threads;
+        self
+    }
+
+    pub fn deterministic_seed(mut self, seed: u64) -> Self {
+        self.options.deterministic_seed = Some(seed);
+        self
+    }
+
+    pub fn background_error_policy(mut self, policy: BackgroundErrorPolicy) -> Self {
+        self.options.background_error_policy = policy;
+        self
+    }
+
+    pub fn io_retry_policy(mut self, policy: IoRetryPolicy) -> Self {
+        self.options.io_retry_policy = Some(policy);
+        self
+    }
+
+    pub fn block_cache_shards(mut self, shards: usize) -> Self {
+        self.options.block_cache_shards = shards;
+        self
+    }
+
+    pub fn bloom_bits_per_key(mut self, bits_per_level: Vec<usize>) -> Self {
+        self.options.bloom_bits_per_key = bits_per_level;
+        self
+    }
+
+    pub fn lock_timeout(mut self, lock_timeout: Duration) -> Self {
+        self.options.lock_timeout = lock_timeout;
+        self
+    }
+
+    pub fn block_restart_interval(mut self, interval: usize) -> Self {
+        self.options.block_restart_interval = interval;
+        self
+    }
+
+    /// Also lowers `max_value_size` to leave room for the checksum and the compression flag of each value.
+    pub fn value_checksums(mut self, value_checksums: bool) -> Self {
+        self.options.value_checksums = value_checksums;
+        if value_checksums {
+            self.options.max_value_size = self
+                .options
+                .max_value_size
+                .min(MAX_KEY_VALUE_SIZE - VALUE_CHECKSUM_SIZE - 1);
+        }
+        self
+    }
+
+    pub fn persistent_cache(mut self, options: PersistentCacheOptions) -> Self {
+        self.options.persistent_cache = Some(options);
+        self
+    }
+
+    pub fn warm_cache_after_compaction(mut self, warm_cache_after_compaction: bool) -> Self {
+        self.options.warm_cache_after_compaction = warm_cache_after_compaction;
+        self
+    }
+
+    pub fn flush_threads(mut self, threads: usize) -> Self {
+        self.options.flush_threads = threads;
+        self
+    }
+
+    pub fn prefix_extractor(mut self, extractor: PrefixExtractorOptions) -> Self {
+        self.options.prefix_extractor = Some(extractor);
+        self
+    }
+
+    pub fn ts_provider(mut self, provider: TsProviderOptions) -> Self {
+        self.options.ts_provider = provider;
+        self
+    }
+
+    pub fn file_system(mut self, file_system: FileSystemOptions) -> Self {
+        self.options.file_system = file_system;
+        self
+    }
+
+    /// Besides [`LsmStorageOptions::validate`], this also rejects SSTs smaller than a block. Tests open the storage
+    /// with tiny memtables on purpose, so that is not checked when opening.
+    pub fn build(self) -> lsm_error::Result<LsmStorageOptions> {
+        self.options.validate()?;
+        if self.options.target_sst_size < self.options.block_size {
+            return Err(Error::InvalidArgument(
+                "target_sst_size must not be smaller than block_size".to_string(),
+            ));
         }
+        Ok(self.options)
     }
 }
 
-fn range_overlap(
+/// Runtime knobs changed by `MiniLsm::set_options`. Each field that is set replaces the option of the same name in
+/// `LsmStorageOptions`; the others are kept. The block cache is sized when opening and cannot be changed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OptionsDelta {
+    pub num_memtable_limit: Option<usize>,
+    pub level0_file_num_compaction_trigger: Option<usize>,
+    pub periodic_compaction_ttl: Option<Option<Duration>>,
+    pub tombstone_compaction_ratio: Option<Option<f64>>,
+    pub delete_compaction_ratio: Option<Option<f64>>,
+    /// Scrubbing can be slowed down, sped up or stopped, but only if it was enabled when opening.
+    pub scrub_bytes_per_sec: Option<Option<u64>>,
+}
+
+/// Open (or flush) `items` with up to `threads` threads, passing each result to `on_opened` on the calling thread
+/// along with the index of the item, in the order they finish. Stops at the first error.
+fn open_in_parallel<T: Sync, R: Send>(
+    items: &[T],
+    threads: usize,
+    open: impl Fn(&T) -> Result<R> + Sync,
+    mut on_opened: impl FnMut(usize, R) -> Result<()>,
+) -> Result<()> {
+    if threads <= 1 || !THREADS_SUPPORTED {
+        for (idx, item) in items.iter().enumerate() {
+            on_opened(idx, open(item)?)?;
+        }
+        return Ok(());
+    }
+    let next = AtomicUsize::new(0);
+    std::thread::scope(|s| {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        for _ in 0..threads.clamp(1, items.len().max(1)) {
+            let tx = tx.clone();
+            let (next, open) = (&next, &open);
+            s.spawn(move || {
+                loop {
+                    let idx = next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    // stop once everything is claimed or the receiver gave up after an error
+                    if idx >= items.len() || tx.send((idx, open(&items[idx]))).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(tx);
+        for (idx, result) in rx {
+            on_opened(idx, result?)?;
+        }
+        Ok(())
+    })
+}
+
+pub(crate) fn range_overlap(
     user_begin: Bound<&[u8]>,
     user_end: Bound<&[u8]>,
     table_begin: KeySlice,
@@ -159,7 +854,27 @@ fn range_overlap(
     true
 }
 
-fn key_within(user_key: &[u8], table_begin: KeySlice, table_end: KeySlice) -> bool {
+/// The prefix under `extractor` of all keys in the range, if they share one.
+fn range_prefix<'a>(
+    extractor: &dyn PrefixExtractor,
+    lower: Bound<&'a [u8]>,
+    upper: Bound<&[u8]>,
+) -> Option<&'a [u8]> {
+    let (Bound::Included(lower) | Bound::Excluded(lower)) = lower else {
+        return None;
+    };
+    let prefix = extractor.prefix(lower)?;
+    // The keys between the lower bound and the successor all start with the prefix
+    let successor = prefix_successor(prefix);
+    let within = match upper {
+        Bound::Included(upper) => successor.is_none_or(|successor| upper < &successor[..]),
+        Bound::Excluded(upper) => successor.is_none_or(|successor| upper <= &successor[..]),
+        Bound::Unbounded => successor.is_none(),
+    };
+    within.then_some(prefix)
+}
+
+pub(crate) fn key_within(user_key: &[u8], table_begin: KeySlice, table_end: KeySlice) -> bool {
     table_begin.key_ref() <= user_key && user_key <= table_end.key_ref()
 }
 
@@ -168,18 +883,115 @@ pub enum CompactionFilter {
     Prefix(Bytes),
 }
 
+/// Options of a single read, passed to `MiniLsm::get_with_options` and `MiniLsm::scan_with_options`.
+#[derive(Clone, Copy, Debug)]
+pub struct ReadOptions {
+    /// Insert the blocks read from disk into the block cache. Turn off for one-off scans that would otherwise evict
+    /// the working set.
+    pub fill_cache: bool,
+    /// Verify the checksum of every block read from disk. Blocks already in the cache were verified when inserted.
+    pub verify_checksums: bool,
+    /// Read at least this many bytes of consecutive blocks in one I/O when an iterator moves to a block that is not
+    /// cached. 0 reads one block at a time.
+    pub readahead_size: usize,
+    /// Read at this timestamp instead of the latest committed one, which cannot be below `MiniLsm::gc_watermark`.
+    pub snapshot: Option<u64>,
+    /// Have scans recreate their memtable and SST iterators after this many keys, releasing the memtables, SSTs and
+    /// blocks they pinned, so that long scans do not keep flushed memtables and compacted SSTs alive.
+    pub max_keys_per_poll: Option<usize>,
+    /// Have scans skip the SST blocks that fail checksum verification instead of failing, so that the rest of a
+    /// damaged SST can be salvaged, and list them in `TxnIterator::corrupted_blocks`. All keys in the key range of a
+    /// skipped block are missing from the scan, unless written after the damaged SST, as the versions left in other
+    /// SSTs may have been overwritten or deleted in the block. Point lookups ignore it and fail.
+    pub skip_corrupted_blocks: bool,
+    /// Read the latest committed data without pinning a snapshot in the watermark, for reads that need not be
+    /// repeatable. A scan sees the data committed when it is created. `snapshot` is ignored, and it cannot be combined
+    /// with `max_keys_per_poll`, as a recreated iterator may miss the versions compacted away meanwhile.
+    pub read_committed: bool,
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        Self {
+            fill_cache: true,
+            verify_checksums: true,
+            readahead_size: 0,
+            snapshot: None,
+            max_keys_per_poll: None,
+            skip_corrupted_blocks: false,
+            read_committed: false,
+        }
+    }
+}
+
+/// Options of a single write, passed to `MiniLsm::write_batch_with_options` and friends.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WriteOptions {
+    /// Sync the WAL before the write returns, so that it survives a machine crash.
+    pub sync: bool,
+    /// Skip the WAL. The write is lost on a crash before its memtable is flushed, e.g., for bulk loads that can be
+    /// redone.
+    pub disable_wal: bool,
+}
+
+/// A hook invoked on every committed write batch, e.g., to maintain secondary indexes or derived keyspaces.
+///
+/// Callbacks run synchronously on the write path, once the batch has passed its checks and got its commit timestamp,
+/// and before it is appended to the WAL. The records they return are appended to the batch, so that they are logged,
+/// applied and made visible to readers atomically with it. Batches that fail before, e.g., over a quota, are not
+/// delivered, while a batch that fails afterwards, e.g., on a returned record that is too large or writing the WAL,
+/// is. Batches are delivered one at a time in commit timestamp order. A callback must not write to the same storage
+/// engine other than through the records it returns, as the write lock is held while it runs.
+pub trait WriteCallback: Send + Sync {
+    /// `batch` contains the changes in the order they were written. The returned records, e.g., the entries of a
+    /// secondary index, are committed with them at `commit_ts`. They are not seen by the other callbacks, and skip
+    /// the lock and quota checks of the batch, but fail it if a key or value is empty or too large.
+    fn on_write(
+        &self,
+        commit_ts: u64,
+        batch: &[WriteBatchRecord<&[u8]>],
+    ) -> Vec<WriteBatchRecord<Bytes>>;
+}
+
 /// The storage interface of the LSM tree.
 pub(crate) struct LsmStorageInner {
-    pub(crate) state: Arc<RwLock<Arc<LsmStorageState>>>,
+    pub(crate) state: StateCell,
     pub(crate) state_lock: Mutex<()>,
-    path: PathBuf,
+    pub(crate) path: PathBuf,
     pub(crate) block_cache: Arc<BlockCache>,
     next_sst_id: AtomicUsize,
+    /// The options the storage was opened with. Runtime knobs may have changed since, see `options()`.
     pub(crate) options: Arc<LsmStorageOptions>,
-    pub(crate) compaction_controller: CompactionController,
+    /// Swapped as a whole by `set_options`, along with the compaction controller built from it.
+    live_options: ArcSwap<LsmStorageOptions>,
+    compaction_controller: ArcSwap<CompactionController>,
     pub(crate) manifest: Option<Manifest>,
     pub(crate) mvcc: Option<LsmMvccInner>,
     pub(crate) compaction_filters: Arc<Mutex<Vec<CompactionFilter>>>,
+    pub(crate) quotas: PrefixQuotas,
+    pub(crate) hot_keys: HotKeyTracker,
+    pub(crate) write_callbacks: Arc<Mutex<Vec<Arc<dyn WriteCallback>>>>,
+    pub(crate) event_listeners: Arc<Mutex<Vec<Arc<dyn EventListener>>>>,
+    /// Set while the immutable memtables outnumber `LsmStorageOptions::num_memtable_limit`.
+    write_stalled: AtomicBool,
+    pub(crate) properties_collectors: Arc<Mutex<Vec<Arc<dyn TablePropertiesCollectorFactory>>>>,
+    increment_latches: Vec<Mutex<()>>,
+    pub(crate) tracer: Tracer,
+    /// Draws the random parts of new SSTs when `LsmStorageOptions::deterministic_seed` is set.
+    seeded_rng: Option<Mutex<StdRng>>,
+    /// The index of the last log entry applied through `LsmStateMachine`. The lock serializes applies.
+    pub(crate) applied_index: Mutex<u64>,
+    /// Set while background flushes and compactions are paused.
+    pub(crate) background_paused: AtomicBool,
+    /// Held by the flush and compaction threads while they work, so that pausing can wait for in-flight tasks.
+    pub(crate) background_lock: RwLock<()>,
+    pub(crate) scrubber: Scrubber,
+    pub(crate) background_errors: BackgroundErrors,
+    pub(crate) io_retry: Arc<IoRetry>,
+    /// WALs of flushed memtables kept for reuse when `LsmStorageOptions::wal_segment_size` is set.
+    pub(crate) wal_pool: WalPool,
+    pub(crate) page_leases: PageLeases,
+    pub(crate) sst_entry_stats: SstEntryStats,
 }
 
 /// A thin wrapper for `LsmStorageInner` and the user interface for MiniLSM.
@@ -193,35 +1005,51 @@ pub struct MiniLsm {
     compaction_notifier: crossbeam_channel::Sender<()>,
     /// The handle for the compaction thread. (In week 2)
     compaction_thread: Mutex<Option<std::thread::JoinHandle<()>>>,
+    /// Notifies the scrub thread to stop working.
+    scrub_notifier: crossbeam_channel::Sender<()>,
+    /// The handle for the scrub thread, if scrubbing is enabled.
+    scrub_thread: Mutex<Option<std::thread::JoinHandle<()>>>,
 }
 
 impl Drop for MiniLsm {
     fn drop(&mut self) {
         self.compaction_notifier.send(()).ok();
         self.flush_notifier.send(()).ok();
+        self.scrub_notifier.send(()).ok();
     }
 }
 
 impl MiniLsm {
-    pub fn close(&self) -> Result<()> {
+    pub fn close(&self) -> lsm_error::Result<()> {
         self.inner.sync_dir()?;
         self.compaction_notifier.send(()).ok();
         self.flush_notifier.send(()).ok();
+        self.scrub_notifier.send(()).ok();
 
         let mut compaction_thread = self.compaction_thread.lock();
         if let Some(compaction_thread) = compaction_thread.take() {
             compaction_thread
                 .join()
-                .map_err(|e| anyhow::anyhow!("{:?}", e))?;
+                .map_err(|e| Error::Poisoned(format!("compaction thread panicked: {:?}", e)))?;
         }
         let mut flush_thread = self.flush_thread.lock();
         if let Some(flush_thread) = flush_thread.take() {
             flush_thread
                 .join()
-                .map_err(|e| anyhow::anyhow!("{:?}", e))?;
+                .map_err(|e| Error::Poisoned(format!("flush thread panicked: {:?}", e)))?;
+        }
+        let mut scrub_thread = self.scrub_thread.lock();
+        if let Some(scrub_thread) = scrub_thread.take() {
+            scrub_thread
+                .join()
+                .map_err(|e| Error::Poisoned(format!("scrub thread panicked: {:?}", e)))?;
+        }
+
+        if self.inner.options().read_only {
+            return Ok(());
         }
 
-        if self.inner.options.enable_wal {
+        if self.inner.options().enable_wal {
             self.inner.sync()?;
             self.inner.sync_dir()?;
             return Ok(());
@@ -239,7 +1067,7 @@ impl MiniLsm {
             let snapshot = self.inner.state.read();
             !snapshot.imm_memtables.is_empty()
         } {
-            self.inner.force_flush_next_imm_memtable()?;
+            self.inner.force_flush_imm_memtables()?;
         }
         self.inner.sync_dir()?;
 
@@ -247,19 +1075,71 @@ impl MiniLsm {
     }
 
     /// Start the storage engine by either loading an existing directory or creating a new one if the directory does
-    /// not exist.
-    pub fn open(path: impl AsRef<Path>, options: LsmStorageOptions) -> Result<Arc<Self>> {
-        let inner = Arc::new(LsmStorageInner::open(path, options)?);
+    /// not exist, as allowed by `create_if_missing`, `error_if_exists` and `read_only` in `options`.
+    pub fn open(
+        path: impl AsRef<Path>,
+        options: LsmStorageOptions,
+    ) -> lsm_error::Result<Arc<Self>> {
+        Self::start(LsmStorageInner::open(path, options)?)
+    }
+
+    /// Open the storage like `open`, calling `progress` as the manifest is replayed, the SSTs are opened and the WALs
+    /// are replayed, e.g., to report the progress of a long startup. Fails with `Error::Busy` once recovery has taken
+    /// longer than `LsmStorageOptions::open_timeout`.
+    pub fn open_with_progress(
+        path: impl AsRef<Path>,
+        options: LsmStorageOptions,
+        mut progress: impl FnMut(RecoveryProgress),
+    ) -> lsm_error::Result<Arc<Self>> {
+        Self::start(LsmStorageInner::open_with_progress(
+            path,
+            options,
+            &mut progress,
+        )?)
+    }
+
+    /// Open the storage at `path` without starting background threads, merge all of its data into the bottom level
+    /// and write a fresh manifest, e.g., to shrink an archived database. Returns the ids of the new SSTs.
+    pub fn compact_offline(
+        path: impl AsRef<Path>,
+        options: LsmStorageOptions,
+    ) -> lsm_error::Result<Vec<usize>> {
+        let inner = LsmStorageInner::open(path, options)?;
+        let ids = inner.compact_all_to_bottom_level()?;
+        if inner.options().enable_wal {
+            inner.sync()?;
+        }
+        Ok(ids)
+    }
+
+    /// Change runtime knobs without reopening. The changes are not persisted, and must be applied again after
+    /// reopening. Fails with `Error::InvalidArgument` and leaves the options unchanged if the result is not valid.
+    pub fn set_options(&self, delta: &OptionsDelta) -> lsm_error::Result<()> {
+        Ok(self.inner.set_options(delta)?)
+    }
+
+    /// The options in effect, including the changes made by `set_options`.
+    pub fn options(&self) -> Arc<LsmStorageOptions> {
+        self.inner.options()
+    }
+
+    /// Spawn the background threads of the opened storage.
+    fn start(inner: LsmStorageInner) -> lsm_error::Result<Arc<Self>> {
+        let inner = Arc::new(inner);
         let (tx1, rx) = crossbeam_channel::unbounded();
         let compaction_thread = inner.spawn_compaction_thread(rx)?;
         let (tx2, rx) = crossbeam_channel::unbounded();
         let flush_thread = inner.spawn_flush_thread(rx)?;
+        let (tx3, rx) = crossbeam_channel::unbounded();
+        let scrub_thread = inner.spawn_scrub_thread(rx)?;
         Ok(Arc::new(Self {
             inner,
             flush_notifier: tx2,
             flush_thread: Mutex::new(flush_thread),
             compaction_notifier: tx1,
             compaction_thread: Mutex::new(compaction_thread),
+            scrub_notifier: tx3,
+            scrub_thread: Mutex::new(scrub_thread),
         }))
     }
 
@@ -267,39 +1147,238 @@ impl MiniLsm {
         self.inner.add_compaction_filter(compaction_filter)
     }
 
-    pub fn get(&self, key: &[u8]) -> Result<Option<Bytes>> {
-        self.inner.get(key)
+    pub fn add_write_callback(&self, write_callback: Arc<dyn WriteCallback>) {
+        self.inner.add_write_callback(write_callback)
     }
 
-    pub fn write_batch<T: AsRef<[u8]>>(&self, batch: &[WriteBatchRecord<T>]) -> Result<()> {
-        self.inner.write_batch(batch)
+    pub fn add_event_listener(&self, event_listener: Arc<dyn EventListener>) {
+        self.inner.add_event_listener(event_listener)
     }
 
-    pub fn put(&self, key: &[u8], value: &[u8]) -> Result<()> {
-        self.inner.put(key, value)
+    /// Collect properties with collectors created by `factory` in the SSTs written from now on.
+    pub fn add_table_properties_collector(
+        &self,
+        factory: Arc<dyn TablePropertiesCollectorFactory>,
+    ) {
+        self.inner.add_table_properties_collector(factory)
     }
 
-    pub fn delete(&self, key: &[u8]) -> Result<()> {
-        self.inner.delete(key)
+    /// Track the bytes written to keys under `prefix`, and reject writes beyond `limit` bytes if set. A key is
+    /// charged to the quota with the longest matching prefix.
+    pub fn set_prefix_quota(&self, prefix: &[u8], limit: Option<u64>) {
+        self.inner.quotas.set_quota(prefix, limit)
     }
 
-    pub fn sync(&self) -> Result<()> {
-        self.inner.sync()
+    pub fn remove_prefix_quota(&self, prefix: &[u8]) -> Option<QuotaUsage> {
+        self.inner.quotas.remove_quota(prefix)
+    }
+
+    pub fn prefix_quota_usage(&self, prefix: &[u8]) -> Option<QuotaUsage> {
+        self.inner.quotas.usage(prefix)
+    }
+
+    pub fn all_prefix_quota_usage(&self) -> Vec<QuotaUsage> {
+        self.inner.quotas.all_usage()
     }
 
-    pub fn new_txn(&self) -> Result<Arc<Transaction>> {
-        self.inner.new_txn()
+    /// Count one in `sample_rate` reads and writes of each key to find the hot keys in a sliding window of length
+    /// `window`. Enabling it again resets the counts.
+    pub fn enable_hot_key_tracking(&self, sample_rate: u32, window: Duration) {
+        self.inner.hot_keys.enable(sample_rate, window)
     }
 
-    pub fn scan(&self, lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> Result<TxnIterator> {
-        self.inner.scan(lower, upper)
+    pub fn disable_hot_key_tracking(&self) {
+        self.inner.hot_keys.disable()
+    }
+
+    /// The `top_n` keys with the most estimated reads and writes in the sliding window, hottest first.
+    pub fn hot_keys(&self, top_n: usize) -> Vec<HotKey> {
+        self.inner.hot_keys.hot_keys(top_n)
+    }
+
+    /// The `top_n` key prefixes of `prefix_len` bytes with the most estimated reads and writes in the sliding window,
+    /// hottest first. Only the accesses of the hottest keys are aggregated.
+    pub fn hot_prefixes(&self, prefix_len: usize, top_n: usize) -> Vec<HotKey> {
+        self.inner
+            .hot_keys
+            .hot_prefixes(&PrefixExtractorOptions::Fixed(prefix_len), top_n)
+    }
+
+    /// Like `hot_prefixes`, with the prefixes of `LsmStorageOptions::prefix_extractor`. Without an extractor, each
+    /// key is its own prefix.
+    pub fn hot_extracted_prefixes(&self, top_n: usize) -> Vec<HotKey> {
+        match &self.inner.options().prefix_extractor {
+            Some(extractor) => self.inner.hot_keys.hot_prefixes(extractor, top_n),
+            None => self.inner.hot_keys.hot_keys(top_n),
+        }
+    }
+
+    pub fn get(&self, key: &[u8]) -> lsm_error::Result<Option<Bytes>> {
+        Ok(self.inner.get(key)?)
+    }
+
+    /// Check whether a key exists, without reading its value out of the blocks.
+    pub fn contains(&self, key: &[u8]) -> lsm_error::Result<bool> {
+        self.inner.new_txn()?.contains(key)
+    }
+
+    /// Whether a key may exist, checking only the memtables, and the key ranges and bloom filters of the SSTs without
+    /// reading any block. May report deleted keys or false positives of the filters, but never misses a key.
+    pub fn may_contain(&self, key: &[u8]) -> bool {
+        self.inner.may_contain(key)
+    }
+
+    /// Get a key with per-read options, e.g., to read an older snapshot or to keep the blocks out of the cache.
+    pub fn get_with_options(
+        &self,
+        key: &[u8],
+        options: &ReadOptions,
+    ) -> lsm_error::Result<Option<Bytes>> {
+        if options.read_committed {
+            return Ok(self.inner.get_read_committed(key, options)?);
+        }
+        self.inner
+            .txn_for_read(options)?
+            .get_with_options(key, options)
+    }
+
+    pub fn write_batch<T: AsRef<[u8]>>(
+        &self,
+        batch: &[WriteBatchRecord<T>],
+    ) -> lsm_error::Result<()> {
+        Ok(self.inner.write_batch(batch)?)
+    }
+
+    pub fn put(&self, key: &[u8], value: &[u8]) -> lsm_error::Result<()> {
+        Ok(self.inner.put(key, value)?)
+    }
+
+    pub fn delete(&self, key: &[u8]) -> lsm_error::Result<()> {
+        Ok(self.inner.delete(key)?)
+    }
+
+    /// Add `delta` to the counter stored at a key as an 8-byte big-endian `i64`, starting from 0 if the key is missing,
+    /// and return the new value. Increments of the same key are atomic with respect to each other, but not to other
+    /// writes of the key. Fails with `Error::InvalidArgument` if the value is not a counter or the result overflows.
+    pub fn increment(&self, key: &[u8], delta: i64) -> lsm_error::Result<i64> {
+        Ok(self.inner.increment(key, delta)?)
+    }
+
+    /// Put a key-value pair unless the key exists. Returns whether the value was written.
+    pub fn put_if_absent(&self, key: &[u8], value: &[u8]) -> lsm_error::Result<bool> {
+        Ok(self.inner.compare_and_swap(key, None, Some(value))?)
+    }
+
+    /// Replace the value of a key with `new` if it is currently `expected`, where `None` stands for a missing key and
+    /// deletes it for `new`. Returns whether the value was replaced. The check and the write are atomic with respect to
+    /// transactions and other conditional writes, and, when `serializable` is set, to all writes.
+    pub fn compare_and_swap(
+        &self,
+        key: &[u8],
+        expected: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> lsm_error::Result<bool> {
+        Ok(self.inner.compare_and_swap(key, expected, new)?)
+    }
+
+    /// Write a batch with per-write options, e.g., to skip the WAL during a bulk load or to sync a critical write.
+    pub fn write_batch_with_options<T: AsRef<[u8]>>(
+        &self,
+        batch: &[WriteBatchRecord<T>],
+        options: &WriteOptions,
+    ) -> lsm_error::Result<()> {
+        Ok(self.inner.write_batch_with_options(batch, options)?)
+    }
+
+    pub fn put_with_options(
+        &self,
+        key: &[u8],
+        value: &[u8],
+        options: &WriteOptions,
+    ) -> lsm_error::Result<()> {
+        self.write_batch_with_options(&[WriteBatchRecord::Put(key, value)], options)
+    }
+
+    pub fn delete_with_options(&self, key: &[u8], options: &WriteOptions) -> lsm_error::Result<()> {
+        self.write_batch_with_options(&[WriteBatchRecord::Del(key)], options)
+    }
+
+    pub fn sync(&self) -> lsm_error::Result<()> {
+        Ok(self.inner.sync()?)
+    }
+
+    pub fn new_txn(&self) -> lsm_error::Result<Arc<Transaction>> {
+        Ok(self.inner.new_txn()?)
+    }
+
+    /// The transactions that were prepared but not decided when the storage was last closed, or that were dropped
+    /// undecided since, so that the coordinator can commit or roll them back. Their keys stay locked until then. Each
+    /// is returned only once.
+    pub fn recovered_prepared_txns(&self) -> Vec<Arc<Transaction>> {
+        self.inner.mvcc().take_recovered_prepared(&self.inner)
+    }
+
+    /// Take a read-only snapshot of the latest committed data, which keeps it readable until dropped. Cheaper than a
+    /// transaction for consumers that only need several reads to be consistent with each other.
+    pub fn snapshot(&self) -> Snapshot {
+        self.inner.mvcc().new_snapshot(self.inner.clone())
+    }
+
+    /// Make later commits take timestamps above `ts`, e.g., the commit timestamp of a write on another node that the
+    /// next writes here depend on, so that they are ordered after it.
+    pub fn observe_ts(&self, ts: u64) -> lsm_error::Result<()> {
+        Ok(self.inner.observe_ts(ts)?)
+    }
+
+    /// The lowest timestamp that `ReadOptions::snapshot` can read at, below which compactions may have dropped the
+    /// visible versions. It survives restarts, so that the history above it stays readable.
+    pub fn gc_watermark(&self) -> u64 {
+        self.inner.mvcc().gc_watermark()
+    }
+
+    pub fn scan(&self, lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> lsm_error::Result<TxnIterator> {
+        Ok(self.inner.scan(lower, upper)?)
+    }
+
+    /// Scan the keys starting with `prefix`. With a prefix extractor under which `prefix` is a prefix, the SSTs whose
+    /// bloom filters rule it out are skipped.
+    pub fn scan_prefix(&self, prefix: &[u8]) -> lsm_error::Result<TxnIterator> {
+        let successor = prefix_successor(prefix);
+        let upper = match &successor {
+            Some(successor) => Bound::Excluded(&successor[..]),
+            None => Bound::Unbounded,
+        };
+        self.scan(Bound::Included(prefix), upper)
+    }
+
+    /// Scan a range with per-read options, e.g., to read an older snapshot or to keep the blocks out of the cache.
+    pub fn scan_with_options(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        options: &ReadOptions,
+    ) -> lsm_error::Result<TxnIterator> {
+        if options.read_committed {
+            let (read_ts, iter) = self.inner.scan_read_committed(lower, upper, options)?;
+            return self
+                .inner
+                .mvcc()
+                .read_committed_txn(self.inner.clone(), read_ts)
+                .scan_storage(lower, upper, iter);
+        }
+        self.inner
+            .txn_for_read(options)?
+            .scan_with_options(lower, upper, options)
     }
 
     /// Only call this in test cases due to race conditions
-    pub fn force_flush(&self) -> Result<()> {
-        if !self.inner.state.read().memtable.is_empty() {
-            self.inner
-                .force_freeze_memtable(&self.inner.state_lock.lock())?;
+    pub fn force_flush(&self) -> lsm_error::Result<()> {
+        {
+            let state_lock = self.inner.state_lock.lock();
+            // check under the state lock, as a write may have frozen the memtable meanwhile, leaving an empty one
+            if !self.inner.state.read().memtable.is_empty() {
+                self.inner.force_freeze_memtable(&state_lock)?;
+            }
         }
         if !self.inner.state.read().imm_memtables.is_empty() {
             self.inner.force_flush_next_imm_memtable()?;
@@ -307,8 +1386,81 @@ impl MiniLsm {
         Ok(())
     }
 
-    pub fn force_full_compaction(&self) -> Result<()> {
-        self.inner.force_full_compaction()
+    /// Write the view at `ts` into a set of non-overlapping SSTs in `dir`, along with an `ExportManifest`. `ts` must
+    /// not be older than the oldest active transaction (or the latest commit if there is none), as older versions may
+    /// have been garbage-collected.
+    pub fn export_snapshot(
+        &self,
+        dir: impl AsRef<Path>,
+        ts: u64,
+    ) -> lsm_error::Result<ExportManifest> {
+        Ok(self.inner.export_snapshot(dir, ts)?)
+    }
+
+    /// Add the key-value pairs yielded by `iter`, which must be sorted by key without duplicates, by writing SSTs
+    /// directly. An empty value deletes the key.
+    pub fn ingest_sorted<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &self,
+        iter: impl Iterator<Item = anyhow::Result<(K, V)>>,
+    ) -> lsm_error::Result<IngestSummary> {
+        Ok(self.inner.ingest_sorted(iter)?)
+    }
+
+    pub fn force_full_compaction(&self) -> lsm_error::Result<()> {
+        Ok(self.inner.force_full_compaction()?)
+    }
+
+    /// Purge the versions committed below `before_ts` that are overwritten or deleted, along with the deletes, from all
+    /// levels, e.g., to expire data for compliance. Compacts every SST holding such versions, whether or not the
+    /// compaction strategy would pick it.
+    pub fn gc(&self, before_ts: u64) -> lsm_error::Result<()> {
+        Ok(self.inner.gc(before_ts)?)
+    }
+
+    /// Rewrite a single SST down a level, e.g., after deleting most of its keys, without compacting the whole level.
+    pub fn compact_file(&self, sst_id: usize) -> lsm_error::Result<()> {
+        Ok(self.inner.compact_file(sst_id)?)
+    }
+
+    /// Stop the flush and compaction threads from starting new work, and wait for the in-flight flush and compaction
+    /// to finish. Writes are still accepted; frozen memtables pile up in memory until background work is resumed, so
+    /// keep the pause short under heavy writes.
+    pub fn pause_background(&self) {
+        self.inner.pause_background()
+    }
+
+    pub fn resume_background(&self) {
+        self.inner.resume_background()
+    }
+
+    /// Run one round of the background work that `LsmStorageOptions::deterministic_seed` keeps off threads: flush a
+    /// memtable if too many are frozen, rotate the manifest, expire page leases, run one compaction task and scrub
+    /// one block. Can also be called without the seed, alongside the background threads. On wasm32 targets, which
+    /// cannot spawn threads, this is the only way background work runs.
+    pub fn tick(&self) -> lsm_error::Result<()> {
+        Ok(self.inner.tick()?)
+    }
+
+    /// Tick until the LSM structure stops changing, returning the number of ticks that changed it.
+    pub fn run_pending_tasks(&self) -> lsm_error::Result<usize> {
+        Ok(self.inner.run_pending_tasks()?)
+    }
+
+    pub fn is_background_paused(&self) -> bool {
+        self.inner
+            .background_paused
+            .load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    /// Explain what the compaction thread would compact next and why, without compacting anything.
+    pub fn compaction_plan(&self) -> CompactionPlan {
+        self.inner.compaction_plan()
+    }
+
+    /// The size of the current memtable followed by the immutable ones from the newest to the oldest, e.g., to check
+    /// that memtables are frozen and flushed as configured.
+    pub fn memtable_stats(&self) -> Vec<MemTableStats> {
+        self.inner.memtable_stats()
     }
 }
 
@@ -318,6 +1470,14 @@ impl LsmStorageInner {
             .fetch_add(1, std::sync::atomic::Ordering::SeqCst)
     }
 
+    pub(crate) fn options(&self) -> Arc<LsmStorageOptions> {
+        self.live_options.load_full()
+    }
+
+    pub(crate) fn compaction_controller(&self) -> Arc<CompactionController> {
+        self.compaction_controller.load_full()
+    }
+
     pub(crate) fn mvcc(&self) -> &LsmMvccInner {
         self.mvcc.as_ref().unwrap()
     }
@@ -329,111 +1489,198 @@ impl LsmStorageInner {
     /// Start the storage engine by either loading an existing directory or creating a new one if the directory does
     /// not exist.
     pub(crate) fn open(path: impl AsRef<Path>, options: LsmStorageOptions) -> Result<Self> {
+        Self::open_with_progress(path, options, &mut |_| {})
+    }
+
+    /// Like `open`, calling `progress` after each step of recovery and failing once `open_timeout` has passed.
+    pub(crate) fn open_with_progress(
+        path: impl AsRef<Path>,
+        mut options: LsmStorageOptions,
+        progress: &mut dyn FnMut(RecoveryProgress),
+    ) -> Result<Self> {
+        options.validate()?;
+        let started = Instant::now();
+        let open_timeout = options.open_timeout;
+        let mut report = |step: RecoveryProgress| {
+            progress(step);
+            if open_timeout.is_some_and(|timeout| started.elapsed() > timeout) {
+                bail!(Error::Busy(format!(
+                    "opening timed out after {:?} at {step:?}",
+                    started.elapsed()
+                )));
+            }
+            Ok(())
+        };
+        if let Some(trigger) = options.level0_file_num_compaction_trigger {
+            options
+                .compaction_options
+                .set_level0_file_num_compaction_trigger(trigger);
+        }
         let mut state = LsmStorageState::create(&options);
         let path = path.as_ref();
         let mut next_sst_id = 1;
-        let block_cache = Arc::new(BlockCache::new(1 << 20)); // 4GB block cache,
-        let manifest;
+        let io_retry = Arc::new(IoRetry::new(options.io_retry_policy));
+        let mut manifest;
 
-        let compaction_controller = match &options.compaction_options {
-            CompactionOptions::Leveled(options) => {
-                CompactionController::Leveled(LeveledCompactionController::new(options.clone()))
-            }
-            CompactionOptions::Tiered(options) => {
-                CompactionController::Tiered(TieredCompactionController::new(options.clone()))
-            }
-            CompactionOptions::Simple(options) => CompactionController::Simple(
-                SimpleLeveledCompactionController::new(options.clone()),
-            ),
-            CompactionOptions::NoCompaction => CompactionController::NoCompaction,
-        };
+        let compaction_controller = CompactionController::new(&options.compaction_options);
 
+        let manifest_path = path.join("MANIFEST");
+        if manifest_path.exists() {
+            if options.error_if_exists {
+                bail!(Error::InvalidArgument(format!(
+                    "{} already exists",
+                    path.display()
+                )));
+            }
+        } else if !options.create_if_missing || options.read_only {
+            bail!(Error::InvalidArgument(format!(
+                "{} does not exist",
+                path.display()
+            )));
+        }
         if !path.exists() {
             std::fs::create_dir_all(path).context("failed to create DB dir")?;
         }
-        let manifest_path = path.join("MANIFEST");
+        let block_cache = Arc::new(Self::open_block_cache(path, &options)?);
+        let wal_pool = WalPool::open(options.file_system.clone(), path)?;
         let mut last_commit_ts = 0;
+        let mut gc_watermark = 0;
+        let mut applied_index = 0;
         if !manifest_path.exists() {
             if options.enable_wal {
-                state.memtable = Arc::new(MemTable::create_with_wal(
+                state.memtable = Arc::new(Self::create_memtable_with_wal(
+                    path,
                     state.memtable.id(),
-                    Self::path_of_wal_static(path, state.memtable.id()),
+                    &options,
+                    &wal_pool,
+                    &io_retry,
                 )?);
             }
             manifest = Manifest::create(&manifest_path).context("failed to create manifest")?;
-            manifest.add_record_when_init(ManifestRecord::NewMemtable(state.memtable.id()))?;
+            manifest.set_io_retry(io_retry.clone());
+            manifest.add_records_when_init(&[
+                ManifestRecord::Options(options.format_options()),
+                ManifestRecord::GcWatermark(0),
+                ManifestRecord::NewMemtable(state.memtable.id()),
+            ])?;
         } else {
-            let (m, records) = Manifest::recover(&manifest_path)?;
-            let mut memtables = BTreeSet::new();
-            for record in records {
-                match record {
-                    ManifestRecord::Flush(sst_id) => {
-                        let res = memtables.remove(&sst_id);
-                        assert!(res, "memtable not exist?");
-                        if compaction_controller.flush_to_l0() {
-                            state.l0_sstables.insert(0, sst_id);
-                        } else {
-                            state.levels.insert(0, (sst_id, vec![sst_id]));
-                        }
-                        next_sst_id = next_sst_id.max(sst_id);
-                    }
-                    ManifestRecord::NewMemtable(x) => {
-                        next_sst_id = next_sst_id.max(x);
-                        memtables.insert(x);
-                    }
-                    ManifestRecord::Compaction(task, output) => {
-                        let (new_state, _) = compaction_controller
-                            .apply_compaction_result(&state, &task, &output, true);
-                        // TODO: apply remove again
-                        state = new_state;
-                        next_sst_id =
-                            next_sst_id.max(output.iter().max().copied().unwrap_or_default());
-                    }
-                }
+            let (mut m, records) = Manifest::recover(&manifest_path)?;
+            m.set_io_retry(io_retry.clone());
+            let format_options = options.format_options();
+            // Manifests without an options record were written before the format was versioned
+            let legacy = FormatOptions {
+                format_version: 1,
+                ..format_options.clone()
+            };
+            let created = records
+                .iter()
+                .find_map(|record| match record {
+                    ManifestRecord::Options(created) => Some(created),
+                    _ => None,
+                })
+                .unwrap_or(&legacy);
+            if let Some(mismatch) = created.mismatch(&format_options) {
+                bail!(Error::InvalidArgument(format!(
+                    "incompatible options for {}: {mismatch}",
+                    path.display()
+                )));
             }
+            let mut replay = ManifestReplay::new(state, next_sst_id);
+            let total = records.len();
+            for (idx, record) in records.into_iter().enumerate() {
+                replay.apply(&compaction_controller, record);
+                report(RecoveryProgress::ManifestRecordsApplied {
+                    applied: idx + 1,
+                    total,
+                })?;
+            }
+            let memtables = replay.memtables;
+            state = replay.state;
+            next_sst_id = replay.next_sst_id;
+            applied_index = replay.applied_index;
+            last_commit_ts = replay.commit_ts;
+            let replay_gc_watermark = replay.gc_watermark;
 
             let mut sst_cnt = 0;
             // recover SSTs
-            for table_id in state
+            let table_ids = state
                 .l0_sstables
                 .iter()
                 .chain(state.levels.iter().flat_map(|(_, files)| files))
-            {
-                let table_id = *table_id;
-                let sst = SsTable::open(
-                    table_id,
-                    Some(block_cache.clone()),
-                    FileObject::open(&Self::path_of_sst_static(path, table_id))
-                        .context("failed to open SST")?,
-                )?;
+                .copied()
+                .collect::<Vec<_>>();
+            let open_sst = |&table_id: &usize| {
+                let mut file = FileObject::open_in(
+                    &options.file_system,
+                    &Self::path_of_sst_static(path, table_id),
+                )
+                .context("failed to open SST")?;
+                file.set_io_retry(io_retry.clone());
+                if options.preload_bloom_filters {
+                    SsTable::open(table_id, Some(block_cache.clone()), file)
+                } else {
+                    SsTable::open_with_lazy_bloom(table_id, Some(block_cache.clone()), file)
+                }
+            };
+            open_in_parallel(&table_ids, options.recovery_threads, open_sst, |_, sst| {
                 last_commit_ts = last_commit_ts.max(sst.max_ts());
-                state.sstables.insert(table_id, Arc::new(sst));
+                state.sstables.insert(sst.sst_id(), Arc::new(sst));
                 sst_cnt += 1;
-            }
+                report(RecoveryProgress::SstsOpened {
+                    opened: sst_cnt,
+                    total: table_ids.len(),
+                })
+            })?;
             println!("{} SSTs opened", sst_cnt);
 
             next_sst_id += 1;
 
             // Sort SSTs on each level (only for leveled compaction)
             if let CompactionController::Leveled(_) = &compaction_controller {
-                for (_id, ssts) in &mut state.levels {
-                    ssts.sort_by(|x, y| {
-                        state
-                            .sstables
-                            .get(x)
-                            .unwrap()
-                            .first_key()
-                            .cmp(state.sstables.get(y).unwrap().first_key())
-                    })
-                }
+                state.sort_levels_by_first_key();
             }
 
-            // recover memtables
-            if options.enable_wal {
+            // recover memtables, replaying their WALs in parallel and installing them in order
+            let mut recovered = Vec::new();
+            if options.read_only || options.enable_wal {
+                let memtables = memtables.iter().copied().collect::<Vec<_>>();
+                recovered.resize_with(memtables.len(), || None);
+                let mut wal_cnt = 0;
+                let replay_wal = |&id: &usize| {
+                    let mut memtable = MemTable::recover_from_wal_in(
+                        id,
+                        &options.file_system,
+                        Self::path_of_wal_static(path, id),
+                    )?;
+                    memtable.set_io_retry(io_retry.clone());
+                    Ok(memtable)
+                };
+                open_in_parallel(
+                    &memtables,
+                    options.recovery_threads,
+                    replay_wal,
+                    |idx, memtable| {
+                        recovered[idx] = Some(memtable);
+                        wal_cnt += 1;
+                        report(RecoveryProgress::WalsReplayed {
+                            replayed: wal_cnt,
+                            total: memtables.len(),
+                        })
+                    },
+                )?;
+            }
+            if options.read_only {
+                // Keep the WALs as they are and serve the memtables they hold from memory
+                for memtable in recovered.into_iter().flatten() {
+                    last_commit_ts = last_commit_ts.max(memtable.max_ts());
+                    if !memtable.is_empty() {
+                        state.imm_memtables.insert(0, Arc::new(memtable));
+                    }
+                }
+                state.memtable = Arc::new(MemTable::create(next_sst_id));
+            } else if options.enable_wal {
                 let mut wal_cnt = 0;
-                for id in memtables.iter() {
-                    let memtable =
-                        MemTable::recover_from_wal(*id, Self::path_of_wal_static(path, *id))?;
+                for memtable in recovered.into_iter().flatten() {
                     let max_ts = memtable
                         .map
                         .iter()
@@ -447,55 +1694,396 @@ impl LsmStorageInner {
                     }
                 }
                 println!("{} WALs recovered", wal_cnt);
-                state.memtable = Arc::new(MemTable::create_with_wal(
+                state.memtable = Arc::new(Self::create_memtable_with_wal(
+                    path,
                     next_sst_id,
-                    Self::path_of_wal_static(path, next_sst_id),
+                    &options,
+                    &wal_pool,
+                    &io_retry,
                 )?);
             } else {
                 state.memtable = Arc::new(MemTable::create(next_sst_id));
             }
-            m.add_record_when_init(ManifestRecord::NewMemtable(state.memtable.id()))?;
+            // Without a recorded GC watermark, any version may have been dropped
+            gc_watermark = replay_gc_watermark.unwrap_or(last_commit_ts);
+            if !options.read_only {
+                m.add_records_when_init(&[
+                    ManifestRecord::CommitTs(last_commit_ts),
+                    ManifestRecord::GcWatermark(gc_watermark),
+                    ManifestRecord::NewMemtable(state.memtable.id()),
+                ])?;
+            }
             next_sst_id += 1;
             manifest = m;
         };
 
-        let storage = Self {
-            state: Arc::new(RwLock::new(Arc::new(state))),
-            state_lock: Mutex::new(()),
-            path: path.to_path_buf(),
-            block_cache,
-            next_sst_id: AtomicUsize::new(next_sst_id),
-            compaction_controller,
-            manifest: Some(manifest),
-            options: options.into(),
-            mvcc: Some(LsmMvccInner::new(last_commit_ts)),
-            compaction_filters: Arc::new(Mutex::new(Vec::new())),
+        let seeded_rng = options
+            .deterministic_seed
+            .map(|seed| Mutex::new(StdRng::seed_from_u64(seed)));
+        let mvcc = LsmMvccInner::new(last_commit_ts, gc_watermark, options.ts_provider.clone());
+        let storage = Self {
+            state: StateCell::new(state),
+            state_lock: Mutex::new(()),
+            path: path.to_path_buf(),
+            block_cache,
+            next_sst_id: AtomicUsize::new(next_sst_id),
+            compaction_controller: ArcSwap::from_pointee(compaction_controller),
+            manifest: Some(manifest),
+            live_options: ArcSwap::from_pointee(options.clone()),
+            options: Arc::new(options),
+            mvcc: Some(mvcc),
+            compaction_filters: Arc::new(Mutex::new(Vec::new())),
+            quotas: PrefixQuotas::default(),
+            hot_keys: HotKeyTracker::default(),
+            write_callbacks: Arc::new(Mutex::new(Vec::new())),
+            event_listeners: Arc::new(Mutex::new(Vec::new())),
+            write_stalled: AtomicBool::new(false),
+            properties_collectors: Arc::new(Mutex::new(Vec::new())),
+            increment_latches: (0..NUM_INCREMENT_LATCHES).map(|_| Mutex::new(())).collect(),
+            tracer: Tracer::default(),
+            seeded_rng,
+            applied_index: Mutex::new(applied_index),
+            background_paused: AtomicBool::new(false),
+            background_lock: RwLock::new(()),
+            scrubber: Scrubber::default(),
+            background_errors: BackgroundErrors::default(),
+            io_retry,
+            wal_pool,
+            page_leases: PageLeases::default(),
+            sst_entry_stats: SstEntryStats::default(),
+        };
+        if let Some(seed) = storage.options.deterministic_seed {
+            storage.hot_keys.set_seed(seed);
+        }
+        storage
+            .mvcc()
+            .set_recovered_prepared(prepared::recover(path)?)?;
+        storage.sync_dir()?;
+
+        Ok(storage)
+    }
+
+    /// The block cache, backed by a persistent tier if configured.
+    fn open_block_cache(path: &Path, options: &LsmStorageOptions) -> Result<BlockCache> {
+        let block_cache = BlockCache::new(1 << 20, options.block_cache_shards); // 4GB block cache
+        Ok(match &options.persistent_cache {
+            Some(persistent) => {
+                block_cache.with_persistent_tier(PersistentBlockCache::open(persistent, path)?)
+            }
+            None => block_cache,
+        })
+    }
+
+    /// Create an engine at `path` with an empty state and no manifest or MVCC state of its own, for a replica whose
+    /// state is installed by replication. It only serves reads at a given timestamp.
+    pub(crate) fn new_replica(path: impl AsRef<Path>, options: LsmStorageOptions) -> Result<Self> {
+        let path = path.as_ref();
+        std::fs::create_dir_all(path).context("failed to create DB dir")?;
+        let seeded_rng = options
+            .deterministic_seed
+            .map(|seed| Mutex::new(StdRng::seed_from_u64(seed)));
+        let io_retry = Arc::new(IoRetry::new(options.io_retry_policy));
+        Ok(Self {
+            state: StateCell::new(LsmStorageState::create(&options)),
+            state_lock: Mutex::new(()),
+            path: path.to_path_buf(),
+            block_cache: Arc::new(Self::open_block_cache(path, &options)?),
+            next_sst_id: AtomicUsize::new(1),
+            compaction_controller: ArcSwap::from_pointee(CompactionController::new(
+                &options.compaction_options,
+            )),
+            manifest: None,
+            live_options: ArcSwap::from_pointee(options.clone()),
+            options: Arc::new(options),
+            mvcc: None,
+            compaction_filters: Arc::new(Mutex::new(Vec::new())),
+            quotas: PrefixQuotas::default(),
+            hot_keys: HotKeyTracker::default(),
+            write_callbacks: Arc::new(Mutex::new(Vec::new())),
+            event_listeners: Arc::new(Mutex::new(Vec::new())),
+            write_stalled: AtomicBool::new(false),
+            properties_collectors: Arc::new(Mutex::new(Vec::new())),
+            increment_latches: (0..NUM_INCREMENT_LATCHES).map(|_| Mutex::new(())).collect(),
+            tracer: Tracer::default(),
+            seeded_rng,
+            applied_index: Mutex::new(0),
+            background_paused: AtomicBool::new(false),
+            background_lock: RwLock::new(()),
+            scrubber: Scrubber::default(),
+            background_errors: BackgroundErrors::default(),
+            io_retry,
+            wal_pool: WalPool::default(),
+            page_leases: PageLeases::default(),
+            sst_entry_stats: SstEntryStats::default(),
+        })
+    }
+
+    pub fn add_compaction_filter(&self, compaction_filter: CompactionFilter) {
+        let mut compaction_filters = self.compaction_filters.lock();
+        compaction_filters.push(compaction_filter);
+    }
+
+    pub fn add_write_callback(&self, write_callback: Arc<dyn WriteCallback>) {
+        let mut write_callbacks = self.write_callbacks.lock();
+        write_callbacks.push(write_callback);
+    }
+
+    pub fn add_event_listener(&self, event_listener: Arc<dyn EventListener>) {
+        self.event_listeners.lock().push(event_listener);
+    }
+
+    /// Call `f` on each event listener. The listeners are called outside the lock, so that they may be added meanwhile.
+    pub(crate) fn notify_event_listeners(&self, f: impl Fn(&dyn EventListener)) {
+        let event_listeners = self.event_listeners.lock().clone();
+        for event_listener in &event_listeners {
+            f(event_listener.as_ref());
+        }
+    }
+
+    /// Notify the event listeners if the immutable memtables have just come to outnumber `num_memtable_limit` or
+    /// stopped doing so. Called with the state lock held whenever the immutable memtables change.
+    fn update_write_stall(&self, _state_lock_observer: &MutexGuard<'_, ()>) {
+        let num_imm_memtables = self.state.read().imm_memtables.len();
+        let stalled = num_imm_memtables > self.options().num_memtable_limit;
+        if self
+            .write_stalled
+            .swap(stalled, std::sync::atomic::Ordering::SeqCst)
+            != stalled
+        {
+            self.notify_event_listeners(|listener| {
+                listener.on_write_stall(stalled, num_imm_memtables)
+            });
+        }
+    }
+
+    pub fn add_table_properties_collector(
+        &self,
+        factory: Arc<dyn TablePropertiesCollectorFactory>,
+    ) {
+        self.properties_collectors.lock().push(factory);
+    }
+
+    /// Deliver a batch to the write callbacks, returning the records they add to it.
+    fn notify_write_callbacks<T: AsRef<[u8]>>(
+        &self,
+        ts: u64,
+        batch: &[WriteBatchRecord<T>],
+    ) -> Vec<WriteBatchRecord<Bytes>> {
+        let write_callbacks = self.write_callbacks.lock();
+        if write_callbacks.is_empty() {
+            return Vec::new();
+        }
+        let batch = batch
+            .iter()
+            .map(|record| match record {
+                WriteBatchRecord::Put(key, value) => {
+                    WriteBatchRecord::Put(key.as_ref(), value.as_ref())
+                }
+                WriteBatchRecord::Del(key) => WriteBatchRecord::Del(key.as_ref()),
+            })
+            .collect::<Vec<_>>();
+        write_callbacks
+            .iter()
+            .flat_map(|write_callback| write_callback.on_write(ts, &batch))
+            .collect()
+    }
+
+    /// Take a snapshot of the current state. The state lock is not taken at all, so that readers are never blocked
+    /// behind memtable freezes, flushes and compactions, nor behind writers waiting to install them.
+    pub(crate) fn snapshot(&self) -> Arc<LsmStorageState> {
+        self.state.load()
+    }
+
+    pub fn memtable_stats(&self) -> Vec<MemTableStats> {
+        let snapshot = self.snapshot();
+        std::iter::once(&snapshot.memtable)
+            .chain(&snapshot.imm_memtables)
+            .enumerate()
+            .map(|(idx, memtable)| MemTableStats {
+                id: memtable.id(),
+                approximate_size: memtable.approximate_size(),
+                is_empty: memtable.is_empty(),
+                immutable: idx > 0,
+            })
+            .collect()
+    }
+
+    /// Record `ts` in the manifest before later commits take timestamps above it, so that a reopened storage keeps
+    /// handing out greater ones.
+    pub fn observe_ts(&self, ts: u64) -> Result<()> {
+        self.check_writable()?;
+        let mvcc = self.mvcc();
+        let _lck = mvcc.write_lock.lock();
+        let state_lock = self.state_lock.lock();
+        self.manifest()
+            .add_record(&state_lock, ManifestRecord::CommitTs(ts))?;
+        mvcc.observe_ts(ts);
+        Ok(())
+    }
+
+    pub fn sync(&self) -> Result<()> {
+        let memtable = self.state.read().memtable.clone();
+        memtable.sync_wal()
+    }
+
+    /// Get a key from the storage. In day 7, this can be further optimized by using a bloom filter.
+    pub fn get(self: &Arc<Self>, key: &[u8]) -> Result<Option<Bytes>> {
+        let txn = self
+            .mvcc()
+            .new_txn(self.clone(), self.options().serializable);
+        Ok(txn.get(key)?)
+    }
+
+    pub(crate) fn get_with_ts(&self, key: &[u8], read_ts: u64) -> Result<Option<Bytes>> {
+        self.get_with_options(key, read_ts, &ReadOptions::default())
+    }
+
+    /// Get a key at `read_ts`, reading SST blocks according to `options`. `options.snapshot` is ignored.
+    pub(crate) fn get_with_options(
+        &self,
+        key: &[u8],
+        read_ts: u64,
+        options: &ReadOptions,
+    ) -> Result<Option<Bytes>> {
+        let iter = self.point_lookup(key, read_ts, options)?;
+        if iter.is_valid() && iter.key() == key && !iter.value().is_empty() {
+            return Ok(Some(Bytes::copy_from_slice(iter.value())));
+        }
+        Ok(None)
+    }
+
+    /// Get a key at the latest commit timestamp without pinning it in the watermark. The state is taken before the
+    /// timestamp, so that it holds no SST compacted with a watermark above the timestamp, which may lack the versions
+    /// visible at it.
+    pub(crate) fn get_read_committed(
+        &self,
+        key: &[u8],
+        options: &ReadOptions,
+    ) -> Result<Option<Bytes>> {
+        let snapshot = self.snapshot();
+        let read_ts = self.mvcc().latest_commit_ts();
+        let iter = self.point_lookup_in(&snapshot, key, read_ts, options)?;
+        if iter.is_valid() && iter.key() == key && !iter.value().is_empty() {
+            return Ok(Some(Bytes::copy_from_slice(iter.value())));
+        }
+        Ok(None)
+    }
+
+    /// Get several keys at `read_ts` from the same state of the storage, returning the values in the order of `keys`.
+    /// The keys are sorted and looked up in one pass, seeking a single iterator over the memtables and the SSTs that
+    /// may contain any of them.
+    pub(crate) fn get_multi_with_options<T: AsRef<[u8]>>(
+        &self,
+        keys: &[T],
+        read_ts: u64,
+        options: &ReadOptions,
+    ) -> Result<Vec<Option<Bytes>>> {
+        let mut sorted = keys.iter().map(|key| key.as_ref()).collect::<Vec<_>>();
+        sorted.sort_unstable();
+        sorted.dedup();
+        let (Some(&first), Some(&last)) = (sorted.first(), sorted.last()) else {
+            return Ok(Vec::new());
         };
-        storage.sync_dir()?;
+        for key in &sorted {
+            self.hot_keys.record(key, Access::Read);
+            self.tracer.record_get(read_ts, key);
+        }
 
-        Ok(storage)
+        let iter = self.create_merge_iterator_in(
+            &self.snapshot(),
+            Bound::Included(first),
+            Bound::Included(last),
+            read_ts,
+            options,
+            None,
+            |_| true,
+            |table| {
+                let lower = sorted.partition_point(|key| *key < table.first_key().key_ref());
+                let upper = sorted.partition_point(|key| *key <= table.last_key().key_ref());
+                table.time_range().min_ts <= read_ts
+                    && sorted[lower..upper]
+                        .iter()
+                        .any(|key| table.may_contain_key(key))
+            },
+        )?;
+        let mut iter =
+            LsmIterator::new(iter, Bound::Included(Bytes::copy_from_slice(last)), read_ts)?;
+        let mut values = Vec::with_capacity(sorted.len());
+        for key in &sorted {
+            iter.seek(key)?;
+            values.push(
+                (iter.is_valid() && iter.key() == *key)
+                    .then(|| Bytes::copy_from_slice(iter.value())),
+            );
+        }
+        Ok(keys
+            .iter()
+            .map(|key| values[sorted.binary_search(&key.as_ref()).unwrap()].clone())
+            .collect())
     }
 
-    pub fn add_compaction_filter(&self, compaction_filter: CompactionFilter) {
-        let mut compaction_filters = self.compaction_filters.lock();
-        compaction_filters.push(compaction_filter);
+    /// Check whether a key exists at `read_ts` like `get_with_options`, without copying its value.
+    pub(crate) fn contains_with_options(
+        &self,
+        key: &[u8],
+        read_ts: u64,
+        options: &ReadOptions,
+    ) -> Result<bool> {
+        let iter = self.point_lookup(key, read_ts, options)?;
+        Ok(iter.is_valid() && iter.key() == key && !iter.value().is_empty())
     }
 
-    pub fn sync(&self) -> Result<()> {
-        self.state.read().memtable.sync_wal()
+    /// Whether a version of `key` was committed after `ts`, including deletes.
+    pub(crate) fn modified_after(&self, key: &[u8], ts: u64) -> Result<bool> {
+        let iter = self.create_merge_iterator(
+            Bound::Included(key),
+            Bound::Included(key),
+            key::TS_RANGE_BEGIN,
+            &ReadOptions::default(),
+            |memtable| memtable.max_ts() > ts,
+            |table| table.time_range().max_ts > ts,
+        )?;
+        Ok(iter.is_valid() && iter.key().key_ref() == key && iter.key().ts() > ts)
     }
 
-    /// Get a key from the storage. In day 7, this can be further optimized by using a bloom filter.
-    pub fn get(self: &Arc<Self>, key: &[u8]) -> Result<Option<Bytes>> {
-        let txn = self.mvcc().new_txn(self.clone(), self.options.serializable);
-        txn.get(key)
+    /// Whether a key may exist, judging by the memtables and the key ranges and bloom filters of the SSTs only. Deleted
+    /// keys and bloom filter false positives may be reported, but `false` means that the key does not exist.
+    pub fn may_contain(&self, key: &[u8]) -> bool {
+        let snapshot = self.snapshot();
+        let in_memtable = std::iter::once(&snapshot.memtable)
+            .chain(&snapshot.imm_memtables)
+            .any(|memtable| {
+                memtable
+                    .scan(
+                        Bound::Included(KeySlice::from_slice(key, key::TS_RANGE_BEGIN)),
+                        Bound::Included(KeySlice::from_slice(key, key::TS_RANGE_END)),
+                    )
+                    .is_valid()
+            });
+        in_memtable
+            || snapshot.sstables.values().any(|table| {
+                key_within(
+                    key,
+                    table.first_key().as_key_slice(),
+                    table.last_key().as_key_slice(),
+                ) && table.may_contain_key(key)
+            })
     }
 
-    pub(crate) fn get_with_ts(&self, key: &[u8], read_ts: u64) -> Result<Option<Bytes>> {
-        let snapshot = {
-            let guard = self.state.read();
-            Arc::clone(&guard)
-        }; // drop global lock here
+    /// Position an iterator over the storage at the latest version of `key` visible at `read_ts`, or the key after it.
+    fn point_lookup(&self, key: &[u8], read_ts: u64, options: &ReadOptions) -> Result<LsmIterator> {
+        self.point_lookup_in(&self.snapshot(), key, read_ts, options)
+    }
+
+    fn point_lookup_in(
+        &self,
+        snapshot: &LsmStorageState,
+        key: &[u8],
+        read_ts: u64,
+        options: &ReadOptions,
+    ) -> Result<LsmIterator> {
+        self.hot_keys.record(key, Access::Read);
+        self.tracer.record_get(read_ts, key);
 
         let mut memtable_iters = Vec::with_capacity(snapshot.imm_memtables.len() + 1);
         memtable_iters.push(Box::new(snapshot.memtable.scan(
@@ -513,29 +2101,23 @@ impl LsmStorageInner {
         let mut l0_iters = Vec::with_capacity(snapshot.l0_sstables.len());
 
         let keep_table = |key: &[u8], table: &SsTable| {
-            if key_within(
+            key_within(
                 key,
                 table.first_key().as_key_slice(),
                 table.last_key().as_key_slice(),
-            ) {
-                if let Some(bloom) = &table.bloom {
-                    if bloom.may_contain(farmhash::fingerprint32(key)) {
-                        return true;
-                    }
-                } else {
-                    return true;
-                }
-            }
-            false
+            ) && table.may_contain_key(key)
         };
 
         for table in snapshot.l0_sstables.iter() {
             let table = snapshot.sstables[table].clone();
             if keep_table(key, &table) {
-                l0_iters.push(Box::new(SsTableIterator::create_and_seek_to_key(
-                    table,
-                    KeySlice::from_slice(key, key::TS_RANGE_BEGIN),
-                )?));
+                l0_iters.push(Box::new(
+                    SsTableIterator::create_and_seek_to_key_with_options(
+                        table,
+                        KeySlice::from_slice(key, key::TS_RANGE_BEGIN),
+                        *options,
+                    )?,
+                ));
             }
         }
         let l0_iter = MergeIterator::create(l0_iters);
@@ -548,57 +2130,243 @@ impl LsmStorageInner {
                     level_ssts.push(table);
                 }
             }
-            let level_iter = SstConcatIterator::create_and_seek_to_key(
+            let level_iter = SstConcatIterator::create_and_seek_to_key_with_options(
                 level_ssts,
                 KeySlice::from_slice(key, key::TS_RANGE_BEGIN),
+                *options,
             )?;
             level_iters.push(Box::new(level_iter));
         }
 
-        let iter = LsmIterator::new(
+        LsmIterator::new(
             TwoMergeIterator::create(
                 TwoMergeIterator::create(memtable_iter, l0_iter)?,
                 MergeIterator::create(level_iters),
             )?,
             Bound::Unbounded,
             read_ts,
-        )?;
-
-        if iter.is_valid() && iter.key() == key && !iter.value().is_empty() {
-            return Ok(Some(Bytes::copy_from_slice(iter.value())));
-        }
-        Ok(None)
+        )
     }
 
-    pub fn write_batch_inner<T: AsRef<[u8]>>(&self, batch: &[WriteBatchRecord<T>]) -> Result<u64> {
-        let _lck = self.mvcc().write_lock.lock();
-        let ts = self.mvcc().latest_commit_ts() + 1;
-        let mut batch_datas: Vec<(key::Key<&[u8]>, &[u8])> = vec![];
-        let size;
+    /// Reject empty keys and values, and records that exceed the configured key and value size limits, before
+    /// anything is written.
+    fn validate_batch<T: AsRef<[u8]>>(&self, batch: &[WriteBatchRecord<T>]) -> Result<()> {
         for record in batch {
-            match record {
-                WriteBatchRecord::Del(key) => {
-                    let key = key.as_ref();
-                    assert!(!key.is_empty(), "key cannot be empty");
-                    batch_datas.push((KeySlice::from_slice(key, ts), b""));
+            let (key, value) = match record {
+                WriteBatchRecord::Del(key) => (key.as_ref(), None),
+                WriteBatchRecord::Put(key, value) => (key.as_ref(), Some(value.as_ref())),
+            };
+            if key.is_empty() {
+                bail!(Error::InvalidArgument("key cannot be empty".to_string()));
+            }
+            if value.is_some_and(|value| value.is_empty()) {
+                bail!(Error::InvalidArgument("value cannot be empty".to_string()));
+            }
+            if key.len() > self.options().max_key_size {
+                return Err(Error::KeyTooLarge {
+                    size: key.len(),
+                    limit: self.options().max_key_size,
                 }
-                WriteBatchRecord::Put(key, value) => {
-                    let key = key.as_ref();
-                    let value = value.as_ref();
-                    assert!(!key.is_empty(), "key cannot be empty");
-                    assert!(!value.is_empty(), "value cannot be empty");
-                    batch_datas.push((KeySlice::from_slice(key, ts), value));
+                .into());
+            }
+            if let Some(value) = value.filter(|value| value.len() > self.options().max_value_size) {
+                return Err(Error::ValueTooLarge {
+                    size: value.len(),
+                    limit: self.options().max_value_size,
                 }
+                .into());
             }
         }
+        Ok(())
+    }
+
+    /// A builder for the SSTs written by the engine, checking the key order if configured.
+    pub(crate) fn new_sst_builder(&self, origin: SstOrigin) -> SsTableBuilder {
+        let mut builder = SsTableBuilder::new(self.options().block_size);
+        builder.set_origin(origin);
+        builder.set_io_retry(self.io_retry.clone());
+        builder.set_file_system(self.options().file_system.clone());
+        builder.set_block_restart_interval(self.options().block_restart_interval);
+        builder.set_value_checksums(self.options().value_checksums);
+        if let Some(rng) = &self.seeded_rng {
+            builder.set_unique_id_bits(rng.lock().r#gen());
+        }
+        builder.set_block_filters(self.options().block_filters);
+        if let Some(extractor) = &self.options().prefix_extractor {
+            builder.set_prefix_extractor(extractor.clone());
+        }
+        if let Some(schema) = self.options().value_schema {
+            builder.set_value_schema(schema);
+        }
+        if let Some(threshold) = self.options().value_compression_threshold {
+            builder.set_value_compression_threshold(threshold);
+        }
+        for factory in self.properties_collectors.lock().iter() {
+            builder.add_properties_collector(factory.create());
+        }
+        if self.options().check_sst_key_order {
+            builder.set_check_key_order(true);
+        }
+        // Flushed and ingested SSTs go to L0, compactions set the bits of their output level.
+        if let SstOrigin::Flush | SstOrigin::Ingest = origin
+            && let Some(bits) = self.options().bloom_bits_per_key_of_level(0)
         {
-            let guard = self.state.read();
-            guard.memtable.put_batch(&batch_datas)?;
-            size = guard.memtable.approximate_size();
+            builder.set_bloom_bits_per_key(bits);
         }
-        self.try_freeze(size)?;
+        builder
+    }
+
+    /// Apply `delta` to the current options and swap them in along with a compaction controller built from them, so
+    /// that the flush, compaction and scrub threads pick them up at their next check.
+    pub fn set_options(&self, delta: &OptionsDelta) -> Result<()> {
+        // serializes concurrent changes, which would otherwise drop each other's updates
+        let _state_lock = self.state_lock.lock();
+        let mut options = self.options().as_ref().clone();
+        if let Some(limit) = delta.num_memtable_limit {
+            options.num_memtable_limit = limit;
+        }
+        if let Some(trigger) = delta.level0_file_num_compaction_trigger {
+            options.level0_file_num_compaction_trigger = Some(trigger);
+            options
+                .compaction_options
+                .set_level0_file_num_compaction_trigger(trigger);
+        }
+        if let Some(ttl) = delta.periodic_compaction_ttl {
+            options.periodic_compaction_ttl = ttl;
+        }
+        if let Some(ratio) = delta.tombstone_compaction_ratio {
+            options.tombstone_compaction_ratio = ratio;
+        }
+        if let Some(ratio) = delta.delete_compaction_ratio {
+            options.delete_compaction_ratio = ratio;
+        }
+        if let Some(rate) = delta.scrub_bytes_per_sec {
+            if rate.is_some() && self.options.scrub_bytes_per_sec.is_none() {
+                bail!(Error::InvalidArgument(
+                    "scrubbing must be enabled when opening the storage".to_string()
+                ));
+            }
+            options.scrub_bytes_per_sec = rate;
+        }
+        options.validate()?;
+        self.compaction_controller
+            .store(Arc::new(CompactionController::new(
+                &options.compaction_options,
+            )));
+        self.live_options.store(Arc::new(options));
+        Ok(())
+    }
+
+    pub(crate) fn check_writable(&self) -> Result<()> {
+        if self.options().read_only {
+            bail!(Error::ReadOnly);
+        }
+        self.check_background_error()
+    }
 
-        self.mvcc().update_commit_ts(ts);
+    pub fn write_batch_inner<T: AsRef<[u8]>>(
+        &self,
+        batch: &[WriteBatchRecord<T>],
+        options: &WriteOptions,
+    ) -> Result<u64> {
+        self.write_batch_with_owner(batch, options, None, None, None)
+    }
+
+    /// Write a batch at the commit timestamp `ts` rather than one from the timestamp provider, for a replication layer
+    /// replaying the log of a leader at its timestamps. The timestamps must increase from one batch to the next.
+    /// It bypasses transactions, so a serializable storage does not check the batch for conflicts.
+    pub(crate) fn write_batch_with_ts<T: AsRef<[u8]>>(
+        &self,
+        batch: &[WriteBatchRecord<T>],
+        ts: u64,
+    ) -> Result<()> {
+        self.write_batch_with_owner(batch, &WriteOptions::default(), None, None, Some(ts))?;
+        Ok(())
+    }
+
+    /// Write a batch like `write_batch_inner`, on behalf of the prepared transaction `owner` and the transaction
+    /// `txn_id`, whose locked keys it may write, at the commit timestamp `commit_ts` if given.
+    pub(crate) fn write_batch_with_owner<T: AsRef<[u8]>>(
+        &self,
+        batch: &[WriteBatchRecord<T>],
+        options: &WriteOptions,
+        owner: Option<&str>,
+        txn_id: Option<u64>,
+        commit_ts: Option<u64>,
+    ) -> Result<u64> {
+        self.check_writable()?;
+        self.validate_batch(batch)?;
+        for record in batch {
+            let (WriteBatchRecord::Put(key, _) | WriteBatchRecord::Del(key)) = record;
+            self.hot_keys.record(key.as_ref(), Access::Write);
+        }
+        // The batch is logged under the write lock, so that the WAL holds the batches in commit order, but applied to
+        // the memtable and synced outside of it, so that the next batch is logged meanwhile. The commit is published
+        // after both, once all earlier ones are.
+        // The records the write callbacks add to the batch
+        let extra;
+        let (ts, published, guard, batch_datas) = {
+            let _lck = self.mvcc().write_lock.lock();
+            self.mvcc().intent_locks.lock().check(
+                batch.iter().map(|record| {
+                    let (WriteBatchRecord::Put(key, _) | WriteBatchRecord::Del(key)) = record;
+                    key.as_ref()
+                }),
+                owner,
+            )?;
+            self.mvcc().lock_manager.check(
+                batch.iter().map(|record| {
+                    let (WriteBatchRecord::Put(key, _) | WriteBatchRecord::Del(key)) = record;
+                    key.as_ref()
+                }),
+                txn_id,
+            )?;
+            let charges = self.quotas.check(batch)?;
+            let ts = match commit_ts {
+                Some(ts) => self.mvcc().allocate_commit_ts_at(ts)?,
+                None => self.mvcc().allocate_commit_ts()?,
+            };
+            // Published even if the callbacks below panic, so that the writes after this one do not wait for it forever
+            let published = self.mvcc().publish_on_drop(ts);
+            extra = self.notify_write_callbacks(ts, batch);
+            self.validate_batch(&extra)?;
+            let records = batch.iter().map(|record| match record {
+                WriteBatchRecord::Put(key, value) => (key.as_ref(), value.as_ref()),
+                WriteBatchRecord::Del(key) => (key.as_ref(), &b""[..]),
+            });
+            let extra_records = extra.iter().map(|record| match record {
+                WriteBatchRecord::Put(key, value) => (&key[..], &value[..]),
+                WriteBatchRecord::Del(key) => (&key[..], &b""[..]),
+            });
+            let batch_datas = records
+                .chain(extra_records)
+                .map(|(key, value)| (KeySlice::from_slice(key, ts), value))
+                .collect::<Vec<_>>();
+            // keep the memtable from being frozen until the batch is applied to it
+            let guard = self.state.read();
+            if !options.disable_wal
+                && let Err(e) = guard.memtable.append_wal(&batch_datas)
+            {
+                drop(guard);
+                drop(published);
+                return Err(e);
+            }
+            self.quotas.charge(charges);
+            self.tracer.record_writes(ts, batch);
+            self.tracer.record_writes(ts, &extra);
+            (ts, published, guard, batch_datas)
+        };
+        guard.memtable.insert_batch(&batch_datas);
+        let synced = if options.sync && !options.disable_wal {
+            guard.memtable.sync_wal()
+        } else {
+            Ok(())
+        };
+        let size = guard.memtable.approximate_size();
+        drop(guard);
+        drop(published);
+        synced?;
+        self.try_freeze(size)?;
         Ok(ts)
     }
 
@@ -606,10 +2374,20 @@ impl LsmStorageInner {
         self: &Arc<Self>,
         batch: &[WriteBatchRecord<T>],
     ) -> Result<()> {
-        if !self.options.serializable {
-            self.write_batch_inner(batch)?;
+        self.write_batch_with_options(batch, &WriteOptions::default())
+    }
+
+    pub fn write_batch_with_options<T: AsRef<[u8]>>(
+        self: &Arc<Self>,
+        batch: &[WriteBatchRecord<T>],
+        options: &WriteOptions,
+    ) -> Result<()> {
+        if !self.options().serializable {
+            self.write_batch_inner(batch, options)?;
         } else {
-            let txn = self.mvcc().new_txn(self.clone(), self.options.serializable);
+            let txn = self
+                .mvcc()
+                .new_txn(self.clone(), self.options().serializable);
             for record in batch {
                 match record {
                     WriteBatchRecord::Del(key) => {
@@ -620,41 +2398,82 @@ impl LsmStorageInner {
                     }
                 }
             }
-            txn.commit()?;
+            txn.commit_with_options(options)?;
         }
         Ok(())
     }
 
     /// Put a key-value pair into the storage by writing into the current memtable.
     pub fn put(self: &Arc<Self>, key: &[u8], value: &[u8]) -> Result<()> {
-        if !self.options.serializable {
-            self.write_batch_inner(&[WriteBatchRecord::Put(key, value)])?;
-        } else {
-            let txn = self.mvcc().new_txn(self.clone(), self.options.serializable);
-            txn.put(key, value);
-            txn.commit()?;
-        }
-        Ok(())
+        self.write_batch(&[WriteBatchRecord::Put(key, value)])
     }
 
     /// Remove a key from the storage by writing an empty value.
     pub fn delete(self: &Arc<Self>, key: &[u8]) -> Result<()> {
-        if !self.options.serializable {
-            self.write_batch_inner(&[WriteBatchRecord::Del(key)])?;
-        } else {
-            let txn = self.mvcc().new_txn(self.clone(), self.options.serializable);
-            txn.delete(key);
-            txn.commit()?;
+        self.write_batch(&[WriteBatchRecord::Del(key)])
+    }
+
+    /// Read, add to and write back a counter while holding the latch of the key.
+    pub fn increment(self: &Arc<Self>, key: &[u8], delta: i64) -> Result<i64> {
+        let latch = farmhash::hash32(key) as usize % NUM_INCREMENT_LATCHES;
+        let _latch = self.increment_latches[latch].lock();
+        let current = match self.get(key)? {
+            Some(value) => i64::from_be_bytes(value[..].try_into().map_err(|_| {
+                Error::InvalidArgument(format!(
+                    "value of {:?} is not an 8-byte counter",
+                    Bytes::copy_from_slice(key)
+                ))
+            })?),
+            None => 0,
+        };
+        let Some(new) = current.checked_add(delta) else {
+            bail!(Error::InvalidArgument(format!(
+                "incrementing {current} by {delta} overflows"
+            )));
+        };
+        self.put(key, &new.to_be_bytes())?;
+        Ok(new)
+    }
+
+    /// Write `new` to a key if its value is `expected`, in a serializable transaction so that the commit fails if the
+    /// key is written after it is read.
+    pub fn compare_and_swap(
+        self: &Arc<Self>,
+        key: &[u8],
+        expected: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool> {
+        self.check_writable()?;
+        let record = match new {
+            Some(value) => WriteBatchRecord::Put(key, value),
+            None => WriteBatchRecord::Del(key),
+        };
+        // the transaction writes an empty value as a delete, so check the write before it
+        self.validate_batch(std::slice::from_ref(&record))?;
+        loop {
+            let txn = self.mvcc().new_txn(self.clone(), true);
+            if txn.get(key)?.as_deref() != expected {
+                return Ok(false);
+            }
+            match record {
+                WriteBatchRecord::Put(key, value) => txn.put(key, value),
+                WriteBatchRecord::Del(key) => txn.delete(key),
+            }
+            match txn.commit() {
+                Ok(()) => return Ok(true),
+                // the key was written meanwhile, check it again
+                Err(Error::Busy(_)) => continue,
+                Err(e) => return Err(e.into()),
+            }
         }
-        Ok(())
     }
 
     fn try_freeze(&self, estimated_size: usize) -> Result<()> {
-        if estimated_size >= self.options.target_sst_size {
+        if estimated_size >= self.options().target_sst_size {
             let state_lock = self.state_lock.lock();
             let guard = self.state.read();
             // the memtable could have already been frozen, check again to ensure we really need to freeze
-            if guard.memtable.approximate_size() >= self.options.target_sst_size {
+            if guard.memtable.approximate_size() >= self.options().target_sst_size {
                 drop(guard);
                 self.force_freeze_memtable(&state_lock)?;
             }
@@ -670,6 +2489,33 @@ impl LsmStorageInner {
         Self::path_of_sst_static(&self.path, id)
     }
 
+    /// Delete the file of an SST that is no longer part of the state and notify the event listeners.
+    pub(crate) fn delete_sst(&self, id: usize) -> Result<()> {
+        self.options()
+            .file_system
+            .remove_file(&self.path_of_sst(id))?;
+        self.notify_event_listeners(|listener| listener.on_table_deleted(id));
+        Ok(())
+    }
+
+    fn create_memtable_with_wal(
+        path: &Path,
+        id: usize,
+        options: &LsmStorageOptions,
+        wal_pool: &WalPool,
+        io_retry: &Arc<IoRetry>,
+    ) -> Result<MemTable> {
+        let wal_path = Self::path_of_wal_static(path, id);
+        let mut memtable = match options.wal_segment_size {
+            Some(segment_size) => {
+                MemTable::create_with_preallocated_wal(id, wal_path, segment_size, wal_pool)?
+            }
+            None => MemTable::create_with_wal_in(id, &options.file_system, wal_path)?,
+        };
+        memtable.set_io_retry(io_retry.clone());
+        Ok(memtable)
+    }
+
     pub(crate) fn path_of_wal_static(path: impl AsRef<Path>, id: usize) -> PathBuf {
         path.as_ref().join(format!("{:05}.wal", id))
     }
@@ -678,8 +2524,12 @@ impl LsmStorageInner {
         Self::path_of_wal_static(&self.path, id)
     }
 
+    pub(crate) fn path_of_manifest(&self) -> PathBuf {
+        self.path.join("MANIFEST")
+    }
+
     pub(super) fn sync_dir(&self) -> Result<()> {
-        File::open(&self.path)?.sync_all()?;
+        platform::sync_dir(&self.path)?;
         Ok(())
     }
 
@@ -701,21 +2551,31 @@ impl LsmStorageInner {
 
     /// Force freeze the current memtable to an immutable memtable
     pub fn force_freeze_memtable(&self, state_lock_observer: &MutexGuard<'_, ()>) -> Result<()> {
+        self.check_writable()?;
         let memtable_id = self.next_sst_id();
-        let memtable = if self.options.enable_wal {
-            Arc::new(MemTable::create_with_wal(
+        let memtable = if self.options().enable_wal {
+            Arc::new(Self::create_memtable_with_wal(
+                &self.path,
                 memtable_id,
-                self.path_of_wal(memtable_id),
+                &self.options(),
+                &self.wal_pool,
+                &self.io_retry,
             )?)
         } else {
             Arc::new(MemTable::create(memtable_id))
         };
 
         self.freeze_memtable_with_memtable(memtable)?;
+        self.update_write_stall(state_lock_observer);
 
-        self.manifest().add_record(
+        // The frozen memtable holds no commit ts above the latest one handed out, which is recorded before it can be
+        // flushed and compacted away.
+        self.manifest().add_records(
             state_lock_observer,
-            ManifestRecord::NewMemtable(memtable_id),
+            &[
+                ManifestRecord::CommitTs(self.mvcc().last_allocated_ts()),
+                ManifestRecord::NewMemtable(memtable_id),
+            ],
         )?;
         self.sync_dir()?;
 
@@ -724,6 +2584,7 @@ impl LsmStorageInner {
 
     /// Force flush the earliest-created immutable memtable to disk
     pub fn force_flush_next_imm_memtable(&self) -> Result<()> {
+        self.check_writable()?;
         let state_lock = self.state_lock.lock();
 
         let flush_memtable;
@@ -737,50 +2598,224 @@ impl LsmStorageInner {
                 .clone();
         }
 
-        let mut builder = SsTableBuilder::new(self.options.block_size);
-        flush_memtable.flush(&mut builder)?;
-        let sst_id = flush_memtable.id();
-        let sst = Arc::new(builder.build(
-            sst_id,
-            Some(self.block_cache.clone()),
-            self.path_of_sst(sst_id),
-        )?);
+        self.notify_event_listeners(|listener| listener.on_flush_begin(&[flush_memtable.id()]));
+        let ssts = self.build_flush_ssts(&flush_memtable)?;
+        self.install_flushed_ssts(&state_lock, vec![ssts])
+    }
+
+    /// Force flush up to `flush_threads` of the earliest-created immutable memtables to disk in parallel, each to its
+    /// own SSTs, and install them together. Returns the number of memtables flushed.
+    pub fn force_flush_imm_memtables(&self) -> Result<usize> {
+        self.check_writable()?;
+        let state_lock = self.state_lock.lock();
+
+        let flush_memtables = {
+            let guard = self.state.read();
+            guard
+                .imm_memtables
+                .iter()
+                .rev()
+                .take(self.options().flush_threads)
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+
+        let memtable_ids = flush_memtables
+            .iter()
+            .map(|memtable| memtable.id())
+            .collect::<Vec<_>>();
+        self.notify_event_listeners(|listener| listener.on_flush_begin(&memtable_ids));
+        let mut ssts = vec![None; flush_memtables.len()];
+        open_in_parallel(
+            &flush_memtables,
+            self.options().flush_threads,
+            |memtable| self.build_flush_ssts(memtable),
+            |idx, memtable_ssts| {
+                ssts[idx] = Some(memtable_ssts);
+                Ok(())
+            },
+        )?;
+        self.install_flushed_ssts(&state_lock, ssts.into_iter().map(Option::unwrap).collect())?;
+        Ok(flush_memtables.len())
+    }
+
+    /// Write `memtable` to an L0 SST with the same id, or to several in key order if it has outgrown the SST size, the
+    /// first of which has the id of the memtable.
+    fn build_flush_ssts(&self, memtable: &MemTable) -> Result<Vec<Arc<SsTable>>> {
+        let builders = memtable.flush_split(self.options().target_sst_size, || {
+            self.new_sst_builder(SstOrigin::Flush)
+        });
+        let mut ssts = Vec::with_capacity(builders.len());
+        for builder in builders {
+            let sst_id = if ssts.is_empty() {
+                memtable.id()
+            } else {
+                self.next_sst_id()
+            };
+            ssts.push(Arc::new(builder.build(
+                sst_id,
+                Some(self.block_cache.clone()),
+                self.path_of_sst(sst_id),
+            )?));
+        }
+        Ok(ssts)
+    }
+
+    /// Replace the earliest-created immutable memtables with `ssts`, the SSTs each was flushed to in the same order,
+    /// and record the flush. The manifest record and the new state cover all the SSTs at once, so that neither readers
+    /// nor a recovery ever see the data of a memtable in neither place.
+    fn install_flushed_ssts(
+        &self,
+        state_lock: &MutexGuard<'_, ()>,
+        ssts: Vec<Vec<Arc<SsTable>>>,
+    ) -> Result<()> {
+        let sst_ids = ssts
+            .iter()
+            .map(|ssts| ssts.iter().map(|sst| sst.sst_id()).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let memtable_ids = sst_ids.iter().map(|ids| ids[0]).collect::<Vec<_>>();
+
+        // The SSTs must be durable before the manifest refers to them
+        self.sync_dir()?;
+        let record = if sst_ids.iter().any(|ids| ids.len() > 1) {
+            ManifestRecord::FlushSplit(sst_ids.clone())
+        } else if let [memtable_id] = memtable_ids[..] {
+            ManifestRecord::Flush(memtable_id)
+        } else {
+            ManifestRecord::FlushBatch(memtable_ids.clone())
+        };
+        self.manifest().add_record(state_lock, record)?;
 
-        // Add the flushed L0 table to the list.
+        // Add the flushed L0 tables to the list.
         {
             let mut guard = self.state.write();
             let mut snapshot = guard.as_ref().clone();
-            // Remove the memtable from the immutable memtables.
-            let mem = snapshot.imm_memtables.pop().unwrap();
-            assert_eq!(mem.id(), sst_id);
-            // Add L0 table
-            if self.compaction_controller.flush_to_l0() {
-                // In leveled compaction or no compaction, simply flush to L0
-                snapshot.l0_sstables.insert(0, sst_id);
-            } else {
-                // In tiered compaction, create a new tier
-                snapshot.levels.insert(0, (sst_id, vec![sst_id]));
+            for memtable_ssts in ssts {
+                let memtable_id = memtable_ssts[0].sst_id();
+                // Remove the memtable from the immutable memtables.
+                let mem = snapshot.imm_memtables.pop().unwrap();
+                assert_eq!(mem.id(), memtable_id);
+                // Add L0 tables
+                if self.compaction_controller().flush_to_l0() {
+                    // In leveled compaction or no compaction, simply flush to L0
+                    for sst in memtable_ssts.iter().rev() {
+                        snapshot.l0_sstables.insert(0, sst.sst_id());
+                    }
+                } else {
+                    // In tiered compaction, create a new tier
+                    snapshot.levels.insert(
+                        0,
+                        (
+                            memtable_id,
+                            memtable_ssts.iter().map(|sst| sst.sst_id()).collect(),
+                        ),
+                    );
+                }
+                for sst in memtable_ssts {
+                    println!(
+                        "flushed {}.sst with size={}",
+                        sst.sst_id(),
+                        sst.table_size()
+                    );
+                    snapshot.sstables.insert(sst.sst_id(), sst);
+                }
             }
-            println!("flushed {}.sst with size={}", sst_id, sst.table_size());
-            snapshot.sstables.insert(sst_id, sst);
             // Update the snapshot.
             *guard = Arc::new(snapshot);
         }
-
-        if self.options.enable_wal {
-            std::fs::remove_file(self.path_of_wal(sst_id))?;
+        self.update_write_stall(state_lock);
+
+        // The WALs are only dropped once the flush is recorded, as a recovery replays them until then
+        if self.options().enable_wal {
+            for &sst_id in &memtable_ids {
+                match self.options().wal_segment_size {
+                    None => self
+                        .options()
+                        .file_system
+                        .remove_file(&self.path_of_wal(sst_id))?,
+                    Some(segment_size) => self.wal_pool.recycle(
+                        &self.path_of_wal(sst_id),
+                        segment_size,
+                        self.options().num_memtable_limit,
+                    )?,
+                }
+            }
         }
 
-        self.manifest()
-            .add_record(&state_lock, ManifestRecord::Flush(sst_id))?;
-
         self.sync_dir()?;
+        self.notify_event_listeners(|listener| {
+            listener.on_flush_completed(&memtable_ids, &sst_ids)
+        });
 
         Ok(())
     }
 
+    /// Replace the manifest with a snapshot of the current state once it grows beyond
+    /// `LsmStorageOptions::manifest_rotation_size`.
+    pub(crate) fn maybe_rotate_manifest(&self) -> Result<()> {
+        let (Some(rotation_size), Some(manifest)) =
+            (self.options().manifest_rotation_size, &self.manifest)
+        else {
+            return Ok(());
+        };
+        if self.options().read_only || manifest.size()? <= rotation_size {
+            return Ok(());
+        }
+        let state_lock = self.state_lock.lock();
+        self.rotate_manifest(&state_lock)
+    }
+
+    /// Replace the manifest with the options and a snapshot of the LSM structure, which is consistent with the
+    /// records written so far while the state lock is held.
+    pub(crate) fn rotate_manifest(&self, state_lock_observer: &MutexGuard<'_, ()>) -> Result<()> {
+        let snapshot = self.snapshot();
+        let manifest = self.manifest();
+        let memtables = snapshot
+            .imm_memtables
+            .iter()
+            .rev()
+            .chain(std::iter::once(&snapshot.memtable))
+            .map(|memtable| memtable.id())
+            .collect();
+        manifest.rotate(
+            state_lock_observer,
+            &[
+                ManifestRecord::Options(self.options().format_options()),
+                ManifestRecord::Snapshot {
+                    l0_sstables: snapshot.l0_sstables.clone(),
+                    levels: snapshot.levels.clone(),
+                    memtables,
+                    // Ids may be taken by compactions in progress, which must not be handed out again
+                    max_sst_id: self
+                        .next_sst_id
+                        .load(std::sync::atomic::Ordering::SeqCst)
+                        .saturating_sub(1),
+                    applied_index: manifest.applied_index(),
+                    commit_ts: self.mvcc().last_allocated_ts(),
+                    gc_watermark: Some(self.mvcc().gc_watermark()),
+                },
+            ],
+        )
+    }
+
     pub fn new_txn(self: &Arc<Self>) -> Result<Arc<Transaction>> {
-        Ok(self.mvcc().new_txn(self.clone(), self.options.serializable))
+        Ok(self
+            .mvcc()
+            .new_txn(self.clone(), self.options().serializable))
+    }
+
+    /// A transaction reading the snapshot requested by `options`, or the latest one.
+    pub(crate) fn txn_for_read(
+        self: &Arc<Self>,
+        options: &ReadOptions,
+    ) -> Result<Arc<Transaction>> {
+        match options.snapshot {
+            Some(read_ts) => {
+                self.mvcc()
+                    .new_txn_at(self.clone(), read_ts, self.options().serializable)
+            }
+            None => self.new_txn(),
+        }
     }
 
     /// Create an iterator over a range of keys.
@@ -789,8 +2824,10 @@ impl LsmStorageInner {
         lower: Bound<&[u8]>,
         upper: Bound<&[u8]>,
     ) -> Result<TxnIterator> {
-        let txn = self.mvcc().new_txn(self.clone(), self.options.serializable);
-        txn.scan(lower, upper)
+        let txn = self
+            .mvcc()
+            .new_txn(self.clone(), self.options().serializable);
+        Ok(txn.scan(lower, upper)?)
     }
 
     pub(crate) fn scan_with_ts(
@@ -799,37 +2836,197 @@ impl LsmStorageInner {
         upper: Bound<&[u8]>,
         read_ts: u64,
     ) -> Result<FusedIterator<LsmIterator>> {
-        let snapshot = {
-            let guard = self.state.read();
-            Arc::clone(&guard)
-        }; // drop global lock here
+        self.tracer.record_scan(read_ts, lower, upper);
+        let iter = self.create_scan_iterator(
+            &self.snapshot(),
+            lower,
+            upper,
+            read_ts,
+            &ReadOptions::default(),
+            None,
+        )?;
+        Ok(FusedIterator::new(LsmIterator::new(
+            iter,
+            map_bound(upper),
+            read_ts,
+        )?))
+    }
+
+    /// Scan the range at `read_ts`, reading SST blocks according to `options`. `options.snapshot` is ignored.
+    pub(crate) fn scan_with_options(
+        self: &Arc<Self>,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        read_ts: u64,
+        options: &ReadOptions,
+    ) -> Result<FusedIterator<LsmIterator>> {
+        self.tracer.record_scan(read_ts, lower, upper);
+        let report = options
+            .skip_corrupted_blocks
+            .then(CorruptionReport::default);
+        let iter = self.create_scan_iterator(
+            &self.snapshot(),
+            lower,
+            upper,
+            read_ts,
+            options,
+            report.as_ref(),
+        )?;
+        let mut iter = LsmIterator::with_report(iter, map_bound(upper), read_ts, report.clone())?;
+        if let Some(max_keys) = options.max_keys_per_poll {
+            let inner = self.clone();
+            let upper = map_bound(upper);
+            let options = *options;
+            iter.set_reopen(
+                max_keys,
+                Box::new(move |key| {
+                    inner.create_scan_iterator(
+                        &inner.snapshot(),
+                        Bound::Included(key),
+                        upper.as_ref().map(|upper| upper.as_ref()),
+                        read_ts,
+                        &options,
+                        report.as_ref(),
+                    )
+                }),
+            );
+        }
+        Ok(FusedIterator::new(iter))
+    }
+
+    /// Scan the range at the latest commit timestamp like `get_read_committed`, returning the timestamp with the
+    /// iterator.
+    pub(crate) fn scan_read_committed(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        options: &ReadOptions,
+    ) -> Result<(u64, FusedIterator<LsmIterator>)> {
+        if options.max_keys_per_poll.is_some() {
+            bail!(Error::InvalidArgument(
+                "read_committed cannot be combined with max_keys_per_poll".to_string()
+            ));
+        }
+        let snapshot = self.snapshot();
+        let read_ts = self.mvcc().latest_commit_ts();
+        self.tracer.record_scan(read_ts, lower, upper);
+        let report = options
+            .skip_corrupted_blocks
+            .then(CorruptionReport::default);
+        let iter =
+            self.create_scan_iterator(&snapshot, lower, upper, read_ts, options, report.as_ref())?;
+        Ok((
+            read_ts,
+            FusedIterator::new(LsmIterator::with_report(
+                iter,
+                map_bound(upper),
+                read_ts,
+                report,
+            )?),
+        ))
+    }
+
+    fn create_scan_iterator(
+        &self,
+        snapshot: &LsmStorageState,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        read_ts: u64,
+        options: &ReadOptions,
+        report: Option<&CorruptionReport>,
+    ) -> Result<LsmIteratorInner> {
+        let extractor = self.options().prefix_extractor.clone();
+        let prefix = extractor
+            .as_ref()
+            .and_then(|extractor| Some((extractor, range_prefix(extractor, lower, upper)?)));
+        // SSTs with all entries committed after `read_ts` have nothing visible to the scan, and SSTs without the
+        // prefix of a scan within a prefix have no key in its range.
+        self.create_merge_iterator_in(
+            snapshot,
+            lower,
+            upper,
+            read_ts,
+            options,
+            report,
+            |_| true,
+            |table| {
+                table.time_range().min_ts <= read_ts
+                    && prefix.is_none_or(|(extractor, prefix)| {
+                        table.may_contain_prefix(extractor, prefix)
+                    })
+            },
+        )
+    }
+
+    /// Merge all versions of the keys in the range from the memtables and SSTs that pass the filters.
+    pub(crate) fn create_merge_iterator(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        read_ts: u64,
+        options: &ReadOptions,
+        memtable_filter: impl Fn(&MemTable) -> bool,
+        table_filter: impl Fn(&SsTable) -> bool,
+    ) -> Result<LsmIteratorInner> {
+        self.create_merge_iterator_in(
+            &self.snapshot(),
+            lower,
+            upper,
+            read_ts,
+            options,
+            None,
+            memtable_filter,
+            table_filter,
+        )
+    }
 
+    #[allow(clippy::too_many_arguments)]
+    fn create_merge_iterator_in(
+        &self,
+        snapshot: &LsmStorageState,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        read_ts: u64,
+        options: &ReadOptions,
+        report: Option<&CorruptionReport>,
+        memtable_filter: impl Fn(&MemTable) -> bool,
+        table_filter: impl Fn(&SsTable) -> bool,
+    ) -> Result<LsmIteratorInner> {
         let mut memtable_iters = Vec::with_capacity(snapshot.imm_memtables.len() + 1);
         let (begin, end) = map_key_bound_plus_ts(lower, upper, read_ts);
-        memtable_iters.push(Box::new(snapshot.memtable.scan(begin, end)));
-        for memtable in snapshot.imm_memtables.iter() {
-            memtable_iters.push(Box::new(memtable.scan(begin, end)));
+        for memtable in std::iter::once(&snapshot.memtable).chain(snapshot.imm_memtables.iter()) {
+            if memtable_filter(memtable) {
+                memtable_iters.push(Box::new(memtable.scan(begin, end)));
+            }
         }
         let memtable_iter = MergeIterator::create(memtable_iters);
 
+        // The SST iterators stop at the upper bound on their own, so that they do not read blocks past it.
+        let upper_bound = map_bound(upper);
         let mut table_iters = Vec::with_capacity(snapshot.l0_sstables.len());
         for table_id in snapshot.l0_sstables.iter() {
             let table = snapshot.sstables[table_id].clone();
-            if range_overlap(
-                lower,
-                upper,
-                table.first_key().as_key_slice(),
-                table.last_key().as_key_slice(),
-            ) {
-                let iter = match lower {
-                    Bound::Included(key) => SsTableIterator::create_and_seek_to_key(
+            if table_filter(&table)
+                && range_overlap(
+                    lower,
+                    upper,
+                    table.first_key().as_key_slice(),
+                    table.last_key().as_key_slice(),
+                )
+            {
+                let mut iter = match lower {
+                    Bound::Included(key) => SsTableIterator::create_and_seek_to_key_with_report(
                         table,
                         KeySlice::from_slice(key, key::TS_RANGE_BEGIN),
+                        *options,
+                        report.cloned(),
                     )?,
                     Bound::Excluded(key) => {
-                        let mut iter = SsTableIterator::create_and_seek_to_key(
+                        let mut iter = SsTableIterator::create_and_seek_to_key_with_report(
                             table,
                             KeySlice::from_slice(key, key::TS_RANGE_BEGIN),
+                            *options,
+                            report.cloned(),
                         )?;
                         // TODO: we can implement `key.next()` so that we can directly seek to the
                         // right place in the previous line.
@@ -838,9 +3035,13 @@ impl LsmStorageInner {
                         }
                         iter
                     }
-                    Bound::Unbounded => SsTableIterator::create_and_seek_to_first(table)?,
+                    Bound::Unbounded => SsTableIterator::create_and_seek_to_first_with_report(
+                        table,
+                        *options,
+                        report.cloned(),
+                    )?,
                 };
-
+                iter.set_upper_bound(upper_bound.clone())?;
                 table_iters.push(Box::new(iter));
             }
         }
@@ -851,43 +3052,48 @@ impl LsmStorageInner {
             let mut level_ssts = Vec::with_capacity(level_sst_ids.len());
             for table in level_sst_ids {
                 let table = snapshot.sstables[table].clone();
-                if range_overlap(
-                    lower,
-                    upper,
-                    table.first_key().as_key_slice(),
-                    table.last_key().as_key_slice(),
-                ) {
+                if table_filter(&table)
+                    && range_overlap(
+                        lower,
+                        upper,
+                        table.first_key().as_key_slice(),
+                        table.last_key().as_key_slice(),
+                    )
+                {
                     level_ssts.push(table);
                 }
             }
 
-            let level_iter = match lower {
-                Bound::Included(key) => SstConcatIterator::create_and_seek_to_key(
+            let mut level_iter = match lower {
+                Bound::Included(key) => SstConcatIterator::create_and_seek_to_key_with_report(
                     level_ssts,
                     KeySlice::from_slice(key, key::TS_RANGE_BEGIN),
+                    *options,
+                    report.cloned(),
                 )?,
                 Bound::Excluded(key) => {
-                    let mut iter = SstConcatIterator::create_and_seek_to_key(
+                    let mut iter = SstConcatIterator::create_and_seek_to_key_with_report(
                         level_ssts,
                         KeySlice::from_slice(key, key::TS_RANGE_BEGIN),
+                        *options,
+                        report.cloned(),
                     )?;
                     while iter.is_valid() && iter.key().key_ref() == key {
                         iter.next()?;
                     }
                     iter
                 }
-                Bound::Unbounded => SstConcatIterator::create_and_seek_to_first(level_ssts)?,
+                Bound::Unbounded => SstConcatIterator::create_and_seek_to_first_with_report(
+                    level_ssts,
+                    *options,
+                    report.cloned(),
+                )?,
             };
+            level_iter.set_upper_bound(upper_bound.clone())?;
             level_iters.push(Box::new(level_iter));
         }
 
         let iter = TwoMergeIterator::create(memtable_iter, l0_iter)?;
-        let iter = TwoMergeIterator::create(iter, MergeIterator::create(level_iters))?;
-
-        Ok(FusedIterator::new(LsmIterator::new(
-            iter,
-            map_bound(upper),
-            read_ts,
-        )?))
+        TwoMergeIterator::create(iter, MergeIterator::create(level_iters))
     }
 }
@@ -12,44 +12,210 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::BTreeSet;
 use std::fs::{File, OpenOptions};
-use std::io::{Read, Write};
-use std::path::Path;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
 use anyhow::{Context, Result, bail};
 use bytes::{Buf, BufMut};
 use parking_lot::{Mutex, MutexGuard};
 use serde::{Deserialize, Serialize};
 
-use crate::compact::CompactionTask;
+use crate::compact::{CompactionController, CompactionTask};
+use crate::io_retry::{IoRetry, IoTarget};
+use crate::lsm_error::Error;
+use crate::lsm_storage::LsmStorageState;
+use crate::platform;
 
 pub struct Manifest {
+    path: PathBuf,
     file: Arc<Mutex<File>>,
+    /// Encoded records waiting to be written, and the sequence number of the last of them. Concurrent writers queue
+    /// their records here, and whoever gets the file next writes and syncs all of them at once.
+    pending: Mutex<(Vec<u8>, u64)>,
+    /// The sequence number of the last record synced to the file.
+    synced: AtomicU64,
+    /// Set after a failed write, as the records queued with it are lost.
+    failed: AtomicBool,
+    /// The number of times the manifest has been replaced by a snapshot.
+    rotations: AtomicU64,
+    /// The latest applied index recorded, to be carried over into a snapshot.
+    applied_index: AtomicU64,
+    io_retry: Arc<IoRetry>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub enum ManifestRecord {
     Flush(usize),
+    /// Memtables flushed together, oldest first.
+    FlushBatch(Vec<usize>),
+    /// Memtables flushed together, oldest first, some of which were split across several SSTs. Each is listed as the
+    /// SSTs it was flushed to in key order, the first of which has the id of the memtable.
+    FlushSplit(Vec<Vec<usize>>),
     NewMemtable(usize),
     Compaction(CompactionTask, Vec<usize>),
+    /// SSTs added by bulk ingestion, in key order.
+    Ingest(Vec<usize>),
+    /// The index of the last log entry applied through `LsmStateMachine`.
+    AppliedIndex(u64),
+    /// No commit timestamp above this was handed out before the record, so that a reopened storage hands out greater
+    /// ones even if the versions carrying the latest timestamps were compacted away.
+    CommitTs(u64),
+    /// Compactions may have dropped the versions below this timestamp, so that a reopened storage refuses reads below
+    /// it.
+    GcWatermark(u64),
+    /// The format-affecting options the engine was created with, recorded once at creation.
+    Options(FormatOptions),
+    /// The whole LSM structure rebuilt by `MiniLsm::repair`, replacing the state replayed so far.
+    Repair {
+        l0_sstables: Vec<usize>,
+        levels: Vec<(usize, Vec<usize>)>,
+    },
+    /// The whole state written when the manifest is rotated, replacing the state replayed so far.
+    Snapshot {
+        l0_sstables: Vec<usize>,
+        levels: Vec<(usize, Vec<usize>)>,
+        /// The memtables that have not been flushed.
+        memtables: Vec<usize>,
+        /// The largest SST or memtable id allocated so far.
+        max_sst_id: usize,
+        applied_index: u64,
+        /// The latest commit timestamp handed out so far.
+        #[serde(default)]
+        commit_ts: u64,
+        /// The GC watermark, missing from the snapshots written before it was recorded.
+        #[serde(default)]
+        gc_watermark: Option<u64>,
+    },
+}
+
+/// The options that determine how the files of the engine are interpreted. Reopening an engine with different ones
+/// would misread its SSTs or its manifest, so it is refused.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FormatOptions {
+    pub format_version: u32,
+    /// The name of the key order.
+    pub comparator: String,
+    /// Whether keys carry timestamps.
+    pub mvcc: bool,
+    /// The compression applied to blocks.
+    pub compression: String,
+    /// The checksum algorithm of blocks.
+    #[serde(default = "default_block_checksum")]
+    pub checksum: String,
+    /// The compression applied to each value on its own.
+    #[serde(default = "default_value_compression")]
+    pub value_compression: String,
+    /// The compaction strategy, which determines how manifest records are replayed.
+    pub compaction: String,
+}
+
+fn default_block_checksum() -> String {
+    "crc32".to_string()
+}
+
+fn default_value_compression() -> String {
+    "none".to_string()
+}
+
+impl FormatOptions {
+    /// Version 1 is the format from before the options were recorded. Version 2 moved the value length of block
+    /// entries next to the key length and added restart points to blocks; added to the SST meta the prefix extractor of
+    /// the bloom filter, the entry counts, the unique ID, creation time, origin and engine version, the user-collected
+    /// properties, the filter and value range of each block, and the value flags; and added the manifest records of
+    /// memtables flushed together or split across several SSTs, of the latest commit timestamp and of the GC
+    /// watermark.
+    pub const FORMAT_VERSION: u32 = 2;
+
+    /// The oldest format version this build reads. The blocks of version 1 cannot be read anymore.
+    pub const MIN_READABLE_FORMAT_VERSION: u32 = 2;
+
+    /// Describe every option of an engine created with `self` that differs from `other`, the options it is opened
+    /// with, or return `None` if they are compatible. An older format version is compatible as long as it can still
+    /// be read, while a newer one is not.
+    pub fn mismatch(&self, other: &FormatOptions) -> Option<String> {
+        let mut diffs = Vec::new();
+        if self.format_version > other.format_version {
+            diffs.push(format!(
+                "format_version is `{}` but this build reads up to `{}`",
+                self.format_version, other.format_version
+            ));
+        } else if self.format_version < Self::MIN_READABLE_FORMAT_VERSION {
+            diffs.push(format!(
+                "format_version is `{}` but this build reads from `{}`",
+                self.format_version,
+                Self::MIN_READABLE_FORMAT_VERSION
+            ));
+        }
+        let mut check = |name: &str, created: String, opened: String| {
+            if created != opened {
+                diffs.push(format!("{name} is `{created}` but opened with `{opened}`"));
+            }
+        };
+        check(
+            "comparator",
+            self.comparator.clone(),
+            other.comparator.clone(),
+        );
+        check("mvcc", self.mvcc.to_string(), other.mvcc.to_string());
+        check(
+            "compression",
+            self.compression.clone(),
+            other.compression.clone(),
+        );
+        check("checksum", self.checksum.clone(), other.checksum.clone());
+        check(
+            "value_compression",
+            self.value_compression.clone(),
+            other.value_compression.clone(),
+        );
+        check(
+            "compaction",
+            self.compaction.clone(),
+            other.compaction.clone(),
+        );
+        if diffs.is_empty() {
+            None
+        } else {
+            Some(diffs.join(", "))
+        }
+    }
 }
 
 impl Manifest {
+    fn new(path: &Path, file: File, applied_index: u64) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            file: Arc::new(Mutex::new(file)),
+            pending: Mutex::new((Vec::new(), 0)),
+            synced: AtomicU64::new(0),
+            failed: AtomicBool::new(false),
+            rotations: AtomicU64::new(0),
+            applied_index: AtomicU64::new(applied_index),
+            io_retry: Arc::default(),
+        }
+    }
+
+    pub(crate) fn set_io_retry(&mut self, io_retry: Arc<IoRetry>) {
+        self.io_retry = io_retry;
+    }
+
     pub fn create(path: impl AsRef<Path>) -> Result<Self> {
-        Ok(Self {
-            file: Arc::new(Mutex::new(
-                OpenOptions::new()
-                    .read(true)
-                    .create_new(true)
-                    .write(true)
-                    .open(path)
-                    .context("failed to create manifest")?,
-            )),
-        })
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .read(true)
+            .create_new(true)
+            .write(true)
+            .open(path)
+            .context("failed to create manifest")?;
+        Ok(Self::new(path, file, 0))
     }
 
     pub fn recover(path: impl AsRef<Path>) -> Result<(Self, Vec<ManifestRecord>)> {
+        let path = path.as_ref();
         let mut file = OpenOptions::new()
             .read(true)
             .append(true)
@@ -59,23 +225,46 @@ impl Manifest {
         file.read_to_end(&mut buf)?;
         let mut buf_ptr = buf.as_slice();
         let mut records = Vec::new();
+        let mut applied_index = 0;
         while buf_ptr.has_remaining() {
-            let len = buf_ptr.get_u64();
-            let slice = &buf_ptr[..len as usize];
-            let json = serde_json::from_slice::<ManifestRecord>(slice)?;
-            buf_ptr.advance(len as usize);
-            let checksum = buf_ptr.get_u32();
-            if checksum != crc32fast::hash(slice) {
-                bail!("checksum mismatched!");
+            let Some(record) = Self::decode_record(&mut buf_ptr)? else {
+                bail!(Error::Corruption("incomplete manifest".to_string()));
+            };
+            if let ManifestRecord::AppliedIndex(index)
+            | ManifestRecord::Snapshot {
+                applied_index: index,
+                ..
+            } = record
+            {
+                applied_index = index;
             }
-            records.push(json);
+            records.push(record);
         }
-        Ok((
-            Self {
-                file: Arc::new(Mutex::new(file)),
-            },
-            records,
-        ))
+        Ok((Self::new(path, file, applied_index), records))
+    }
+
+    /// Decode the record at the beginning of `buf` and advance past it, or return `None` without advancing if `buf`
+    /// does not hold a complete record.
+    pub(crate) fn decode_record(buf: &mut &[u8]) -> Result<Option<ManifestRecord>> {
+        let mut buf_ptr = *buf;
+        if buf_ptr.remaining() < 8 {
+            return Ok(None);
+        }
+        let len = buf_ptr.get_u64() as usize;
+        if buf_ptr.remaining() < len.saturating_add(4) {
+            return Ok(None);
+        }
+        let slice = &buf_ptr[..len];
+        buf_ptr.advance(len);
+        let checksum = buf_ptr.get_u32();
+        if checksum != crc32fast::hash(slice) {
+            bail!(Error::Corruption(
+                "manifest checksum mismatched".to_string()
+            ));
+        }
+        let record = serde_json::from_slice::<ManifestRecord>(slice)?;
+        *buf = buf_ptr;
+        Ok(Some(record))
     }
 
     pub fn add_record(
@@ -83,17 +272,263 @@ impl Manifest {
         _state_lock_observer: &MutexGuard<()>,
         record: ManifestRecord,
     ) -> Result<()> {
-        self.add_record_when_init(record)
+        self.add_records_when_init(&[record])
+    }
+
+    /// Append the records with a single write and sync.
+    pub fn add_records(
+        &self,
+        _state_lock_observer: &MutexGuard<()>,
+        records: &[ManifestRecord],
+    ) -> Result<()> {
+        self.add_records_when_init(records)
     }
 
     pub fn add_record_when_init(&self, record: ManifestRecord) -> Result<()> {
+        self.add_records_when_init(&[record])
+    }
+
+    /// Append the records with a single write and sync.
+    pub fn add_records_when_init(&self, records: &[ManifestRecord]) -> Result<()> {
+        let seq = {
+            let mut pending = self.pending.lock();
+            for record in records {
+                Self::encode_record(record, &mut pending.0)?;
+                if let ManifestRecord::AppliedIndex(index) = record {
+                    self.applied_index.store(*index, Ordering::SeqCst);
+                }
+            }
+            pending.1 += 1;
+            pending.1
+        };
+        let mut file = self.file.lock();
+        if self.failed.load(Ordering::SeqCst) {
+            bail!(Error::Poisoned("a manifest write has failed".to_string()));
+        }
+        if self.synced.load(Ordering::SeqCst) >= seq {
+            // Written along with the records of another writer
+            return Ok(());
+        }
+        let (buf, last_seq) = {
+            let mut pending = self.pending.lock();
+            (std::mem::take(&mut pending.0), pending.1)
+        };
+        let result = self
+            .io_retry
+            .write_all(IoTarget::ManifestWrite, &mut *file, &buf)
+            .and_then(|_| {
+                self.io_retry
+                    .run(IoTarget::ManifestWrite, || file.sync_all())
+            })
+            .context("failed to write manifest");
+        if result.is_err() {
+            self.failed.store(true, Ordering::SeqCst);
+        }
+        result?;
+        self.synced.store(last_seq, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn encode_record(record: &ManifestRecord, buf: &mut Vec<u8>) -> Result<()> {
+        let data = serde_json::to_vec(record)?;
+        buf.put_u64(data.len() as u64);
+        buf.put_slice(&data);
+        buf.put_u32(crc32fast::hash(&data));
+        Ok(())
+    }
+
+    /// The latest applied index written to the manifest.
+    pub fn applied_index(&self) -> u64 {
+        self.applied_index.load(Ordering::SeqCst)
+    }
+
+    /// The size of the manifest file in bytes.
+    pub fn size(&self) -> Result<u64> {
+        Ok(self.file.lock().metadata()?.len())
+    }
+
+    /// The number of times the manifest has been replaced by [`Manifest::rotate`], which readers tailing the file use
+    /// to notice that it has been replaced.
+    pub fn rotations(&self) -> u64 {
+        self.rotations.load(Ordering::SeqCst)
+    }
+
+    /// Replace the manifest with a new one holding only `records`, which must describe the whole state, e.g., the
+    /// options and a [`ManifestRecord::Snapshot`]. The new manifest is written aside and renamed over the old one, so
+    /// a crash leaves either of them in place.
+    pub fn rotate(
+        &self,
+        _state_lock_observer: &MutexGuard<()>,
+        records: &[ManifestRecord],
+    ) -> Result<()> {
         let mut file = self.file.lock();
-        let mut buf = serde_json::to_vec(&record)?;
-        let hash = crc32fast::hash(&buf);
-        file.write_all(&(buf.len() as u64).to_be_bytes())?;
-        buf.put_u32(hash);
-        file.write_all(&buf)?;
-        file.sync_all()?;
+        if self.failed.load(Ordering::SeqCst) {
+            bail!(Error::Poisoned("a manifest write has failed".to_string()));
+        }
+        let mut buf = Vec::new();
+        for record in records {
+            Self::encode_record(record, &mut buf)?;
+        }
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let mut new_file = OpenOptions::new()
+            .read(true)
+            .create(true)
+            .truncate(true)
+            .write(true)
+            .open(&tmp_path)
+            .context("failed to create manifest")?;
+        self.io_retry
+            .write_all(IoTarget::ManifestWrite, &mut new_file, &buf)?;
+        self.io_retry
+            .run(IoTarget::ManifestWrite, || new_file.sync_all())?;
+        std::fs::rename(&tmp_path, &self.path)?;
+        if let Some(dir) = self.path.parent() {
+            platform::sync_dir(dir)?;
+        }
+        *file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(&self.path)?;
+        self.rotations.fetch_add(1, Ordering::SeqCst);
         Ok(())
     }
 }
+
+/// The structure of the engine rebuilt by replaying manifest records, before any SST or WAL is opened.
+#[derive(Clone)]
+pub(crate) struct ManifestReplay {
+    /// The SST ids on each level. `sstables` is left untouched.
+    pub(crate) state: LsmStorageState,
+    /// The memtables that have not been flushed.
+    pub(crate) memtables: BTreeSet<usize>,
+    /// The largest SST or memtable id seen so far.
+    pub(crate) next_sst_id: usize,
+    pub(crate) applied_index: u64,
+    /// The latest commit timestamp recorded.
+    pub(crate) commit_ts: u64,
+    /// The latest GC watermark recorded, if any.
+    pub(crate) gc_watermark: Option<u64>,
+}
+
+impl ManifestReplay {
+    pub(crate) fn new(state: LsmStorageState, next_sst_id: usize) -> Self {
+        Self {
+            state,
+            memtables: BTreeSet::new(),
+            next_sst_id,
+            applied_index: 0,
+            commit_ts: 0,
+            gc_watermark: None,
+        }
+    }
+
+    pub(crate) fn apply(
+        &mut self,
+        compaction_controller: &CompactionController,
+        record: ManifestRecord,
+    ) {
+        let state = &mut self.state;
+        match record {
+            ManifestRecord::Flush(sst_id) => self.apply_flush(compaction_controller, &[sst_id]),
+            ManifestRecord::FlushBatch(sst_ids) => {
+                for sst_id in sst_ids {
+                    self.apply_flush(compaction_controller, &[sst_id]);
+                }
+            }
+            ManifestRecord::FlushSplit(sst_ids) => {
+                for sst_ids in sst_ids {
+                    self.apply_flush(compaction_controller, &sst_ids);
+                }
+            }
+            ManifestRecord::NewMemtable(x) => {
+                self.next_sst_id = self.next_sst_id.max(x);
+                self.memtables.insert(x);
+            }
+            ManifestRecord::Ingest(sst_ids) => {
+                if compaction_controller.flush_to_l0() {
+                    for sst_id in sst_ids.iter().rev() {
+                        state.l0_sstables.insert(0, *sst_id);
+                    }
+                } else {
+                    state.levels.insert(0, (sst_ids[0], sst_ids.clone()));
+                }
+                self.next_sst_id = self
+                    .next_sst_id
+                    .max(sst_ids.iter().max().copied().unwrap_or_default());
+            }
+            ManifestRecord::AppliedIndex(index) => {
+                self.applied_index = index;
+            }
+            ManifestRecord::CommitTs(ts) => {
+                self.commit_ts = self.commit_ts.max(ts);
+            }
+            ManifestRecord::GcWatermark(ts) => {
+                self.gc_watermark = self.gc_watermark.max(Some(ts));
+            }
+            ManifestRecord::Options(_) => {}
+            ManifestRecord::Repair {
+                l0_sstables,
+                levels,
+            } => {
+                state.l0_sstables = l0_sstables;
+                state.levels = levels;
+                self.next_sst_id = self
+                    .next_sst_id
+                    .max(self.live_ssts().last().copied().unwrap_or_default());
+            }
+            ManifestRecord::Snapshot {
+                l0_sstables,
+                levels,
+                memtables,
+                max_sst_id,
+                applied_index,
+                commit_ts,
+                gc_watermark,
+            } => {
+                state.l0_sstables = l0_sstables;
+                state.levels = levels;
+                self.memtables = memtables.into_iter().collect();
+                self.next_sst_id = self.next_sst_id.max(max_sst_id);
+                self.applied_index = applied_index;
+                self.commit_ts = self.commit_ts.max(commit_ts);
+                self.gc_watermark = self.gc_watermark.max(gc_watermark);
+            }
+            ManifestRecord::Compaction(task, output) => {
+                let (new_state, _) =
+                    compaction_controller.apply_compaction_result(state, &task, &output, true);
+                // TODO: apply remove again
+                *state = new_state;
+                self.next_sst_id = self
+                    .next_sst_id
+                    .max(output.iter().max().copied().unwrap_or_default());
+            }
+        }
+    }
+
+    /// Replace a memtable with the SSTs it was flushed to, the first of which has its id.
+    fn apply_flush(&mut self, compaction_controller: &CompactionController, sst_ids: &[usize]) {
+        let res = self.memtables.remove(&sst_ids[0]);
+        assert!(res, "memtable not exist?");
+        if compaction_controller.flush_to_l0() {
+            for sst_id in sst_ids.iter().rev() {
+                self.state.l0_sstables.insert(0, *sst_id);
+            }
+        } else {
+            self.state.levels.insert(0, (sst_ids[0], sst_ids.to_vec()));
+        }
+        self.next_sst_id = self
+            .next_sst_id
+            .max(sst_ids.iter().max().copied().unwrap_or_default());
+    }
+
+    /// The ids of all SSTs in the LSM tree.
+    pub(crate) fn live_ssts(&self) -> BTreeSet<usize> {
+        self.state
+            .l0_sstables
+            .iter()
+            .chain(self.state.levels.iter().flat_map(|(_, files)| files))
+            .copied()
+            .collect()
+    }
+}
@@ -15,18 +15,65 @@
 use std::ops::Bound;
 use std::path::Path;
 use std::sync::Arc;
-use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::{AtomicU64, AtomicUsize};
+use std::time::SystemTime;
 
 use anyhow::Result;
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use crossbeam_skiplist::SkipMap;
 use crossbeam_skiplist::map::Entry;
 use ouroboros::self_referencing;
+use parking_lot::Mutex;
 
-use crate::iterators::StorageIterator;
+use crate::file_system::{FileSystem, FileSystemOptions};
+use crate::io_retry::IoRetry;
+use crate::iterators::{SeekableIterator, StorageIterator};
 use crate::key::{KeyBytes, KeySlice, TS_DEFAULT, TS_RANGE_BEGIN, TS_RANGE_END};
-use crate::table::SsTableBuilder;
-use crate::wal::Wal;
+use crate::table::{SsTableBuilder, unix_millis};
+use crate::wal::{Wal, WalPool};
+
+/// The size of the chunks the arena carves entries out of.
+const ARENA_CHUNK_SIZE: usize = 1 << 20;
+
+/// A bump allocator for the keys and values of a memtable. Entries are copied back to back into large chunks and
+/// handed out as `Bytes` sharing the chunk, so that a chunk is freed at once when the memtable and every iterator
+/// over it are dropped, instead of one small allocation per key and value.
+struct Arena {
+    chunk: Mutex<BytesMut>,
+}
+
+impl Arena {
+    fn new() -> Self {
+        Self {
+            chunk: Mutex::new(BytesMut::new()),
+        }
+    }
+
+    fn alloc(&self, data: &[u8]) -> Bytes {
+        // Large entries would waste most of a chunk
+        if data.len() > ARENA_CHUNK_SIZE / 4 {
+            return Bytes::copy_from_slice(data);
+        }
+        let mut chunk = self.chunk.lock();
+        if chunk.capacity() < data.len() {
+            *chunk = BytesMut::with_capacity(ARENA_CHUNK_SIZE);
+        }
+        chunk.extend_from_slice(data);
+        // The rest of the chunk stays in `chunk` for the next entries
+        chunk.split().freeze()
+    }
+}
+
+/// The size of a memtable, as reported by `MiniLsm::memtable_stats`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemTableStats {
+    /// The id of the memtable, which is also the id of the SST it is flushed to.
+    pub id: usize,
+    pub approximate_size: usize,
+    pub is_empty: bool,
+    /// Whether the memtable is frozen and waiting to be flushed.
+    pub immutable: bool,
+}
 
 /// A basic mem-table based on crossbeam-skiplist.
 ///
@@ -34,9 +81,14 @@ use crate::wal::Wal;
 /// chapters of week 1 and week 2.
 pub struct MemTable {
     pub(crate) map: Arc<SkipMap<KeyBytes, Bytes>>,
+    arena: Arena,
     wal: Option<Wal>,
     id: usize,
     approximate_size: Arc<AtomicUsize>,
+    // The earliest and latest write time (in milliseconds since the UNIX epoch) of the entries
+    min_write_time: AtomicU64,
+    max_write_time: AtomicU64,
+    max_ts: AtomicU64,
 }
 
 /// Create a bound of `Bytes` from a bound of `&[u8]`.
@@ -94,29 +146,92 @@ impl MemTable {
         Self {
             id,
             map: Arc::new(SkipMap::new()),
+            arena: Arena::new(),
             wal: None,
             approximate_size: Arc::new(AtomicUsize::new(0)),
+            min_write_time: AtomicU64::new(u64::MAX),
+            max_write_time: AtomicU64::new(0),
+            max_ts: AtomicU64::new(0),
         }
     }
 
     /// Create a new mem-table with WAL
     pub fn create_with_wal(id: usize, path: impl AsRef<Path>) -> Result<Self> {
+        Self::create_with_wal_in(id, &FileSystemOptions::Std, path)
+    }
+
+    /// Create a new mem-table with WAL in `file_system`.
+    pub fn create_with_wal_in(
+        id: usize,
+        file_system: &dyn FileSystem,
+        path: impl AsRef<Path>,
+    ) -> Result<Self> {
         Ok(Self {
             id,
             map: Arc::new(SkipMap::new()),
-            wal: Some(Wal::create(path.as_ref())?),
+            arena: Arena::new(),
+            wal: Some(Wal::create_in(file_system, path)?),
             approximate_size: Arc::new(AtomicUsize::new(0)),
+            min_write_time: AtomicU64::new(u64::MAX),
+            max_write_time: AtomicU64::new(0),
+            max_ts: AtomicU64::new(0),
         })
     }
 
+    /// Create a new mem-table with a WAL preallocated to `segment_size` bytes, reusing a WAL from `pool` if possible.
+    pub(crate) fn create_with_preallocated_wal(
+        id: usize,
+        path: impl AsRef<Path>,
+        segment_size: usize,
+        pool: &WalPool,
+    ) -> Result<Self> {
+        Ok(Self {
+            id,
+            map: Arc::new(SkipMap::new()),
+            arena: Arena::new(),
+            wal: Some(Wal::create_preallocated(path, segment_size, pool)?),
+            approximate_size: Arc::new(AtomicUsize::new(0)),
+            min_write_time: AtomicU64::new(u64::MAX),
+            max_write_time: AtomicU64::new(0),
+            max_ts: AtomicU64::new(0),
+        })
+    }
+
+    /// Retry the writes to the WAL, if any, under `io_retry`.
+    pub(crate) fn set_io_retry(&mut self, io_retry: Arc<IoRetry>) {
+        if let Some(wal) = &mut self.wal {
+            wal.set_io_retry(io_retry);
+        }
+    }
+
     /// Create a memtable from WAL
     pub fn recover_from_wal(id: usize, path: impl AsRef<Path>) -> Result<Self> {
+        Self::recover_from_wal_in(id, &FileSystemOptions::Std, path)
+    }
+
+    /// Create a memtable from WAL in `file_system`.
+    pub fn recover_from_wal_in(
+        id: usize,
+        file_system: &dyn FileSystem,
+        path: impl AsRef<Path>,
+    ) -> Result<Self> {
         let map = Arc::new(SkipMap::new());
+        let wal = Wal::recover_in(file_system, path, &map)?;
+        let max_ts = map.iter().map(|entry| entry.key().ts()).max().unwrap_or(0);
+        let approximate_size = map
+            .iter()
+            .map(|entry| entry.key().raw_len() + entry.value().len())
+            .sum();
         Ok(Self {
             id,
-            wal: Some(Wal::recover(path.as_ref(), &map)?),
+            wal: Some(wal),
             map,
-            approximate_size: Arc::new(AtomicUsize::new(0)),
+            arena: Arena::new(),
+            approximate_size: Arc::new(AtomicUsize::new(approximate_size)),
+            // The WAL does not record when the entries were written
+            min_write_time: AtomicU64::new(0),
+            max_write_time: AtomicU64::new(unix_millis(SystemTime::now())),
+            max_ts: AtomicU64::new(max_ts),
         })
     }
 
@@ -159,16 +274,35 @@ impl MemTable {
 
     /// Implement this in week 3, day 5.
     pub fn put_batch(&self, data: &[(KeySlice, &[u8])]) -> Result<()> {
+        self.insert_batch(data);
+        self.append_wal(data)
+    }
+
+    /// Insert a batch into the skiplist without writing it to the WAL, e.g., after `append_wal` logged it.
+    pub(crate) fn insert_batch(&self, data: &[(KeySlice, &[u8])]) {
         let mut estimated_size = 0;
+        let mut max_ts = 0;
         for (key, value) in data {
             estimated_size += key.raw_len() + value.len();
+            max_ts = max_ts.max(key.ts());
             self.map.insert(
-                key.to_key_vec().into_key_bytes(),
-                Bytes::copy_from_slice(value),
+                KeyBytes::from_bytes_with_ts(self.arena.alloc(key.key_ref()), key.ts()),
+                self.arena.alloc(value),
             );
         }
         self.approximate_size
             .fetch_add(estimated_size, std::sync::atomic::Ordering::Relaxed);
+        let now = unix_millis(SystemTime::now());
+        self.min_write_time
+            .fetch_min(now, std::sync::atomic::Ordering::Relaxed);
+        self.max_write_time
+            .fetch_max(now, std::sync::atomic::Ordering::Relaxed);
+        self.max_ts
+            .fetch_max(max_ts, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// Append a batch to the WAL without inserting it into the skiplist.
+    pub(crate) fn append_wal(&self, data: &[(KeySlice, &[u8])]) -> Result<()> {
         if let Some(ref wal) = self.wal {
             wal.put_batch(data)?;
         }
@@ -187,6 +321,7 @@ impl MemTable {
         let (lower, upper) = (map_key_bound(lower), map_key_bound(upper));
         let mut iter = MemTableIteratorBuilder {
             map: self.map.clone(),
+            upper: upper.clone(),
             iter_builder: |map| map.range((lower, upper)),
             item: (KeyBytes::new(), Bytes::new()),
         }
@@ -200,19 +335,78 @@ impl MemTable {
         for entry in self.map.iter() {
             builder.add(entry.key().as_key_slice(), &entry.value()[..]);
         }
+        if let Some((min, max)) = self.write_time_range() {
+            builder.add_write_time_range(min, max);
+        }
         Ok(())
     }
 
+    /// Flush the memtable to builders from `new_builder`, splitting it between keys into parts of about `target_size`
+    /// bytes of keys and values if it has outgrown twice that, e.g., when replayed from a large WAL, so that it does
+    /// not make one oversized SST. The parts are in key order.
+    pub fn flush_split(
+        &self,
+        target_size: usize,
+        mut new_builder: impl FnMut() -> SsTableBuilder,
+    ) -> Vec<SsTableBuilder> {
+        let num_parts = (self.approximate_size() / target_size.max(1)).max(1);
+        let part_size = self.approximate_size().div_ceil(num_parts);
+        let mut builders = vec![new_builder()];
+        let mut size = 0;
+        let mut last_key: Option<KeyBytes> = None;
+        for entry in self.map.iter() {
+            let key = entry.key();
+            let same_as_last_key = last_key
+                .as_ref()
+                .is_some_and(|last_key| last_key.key_ref() == key.key_ref());
+            if size >= part_size && builders.len() < num_parts && !same_as_last_key {
+                builders.push(new_builder());
+                size = 0;
+            }
+            size += key.raw_len() + entry.value().len();
+            builders
+                .last_mut()
+                .unwrap()
+                .add(key.as_key_slice(), &entry.value()[..]);
+            last_key = Some(key.clone());
+        }
+        if let Some((min, max)) = self.write_time_range() {
+            for builder in &mut builders {
+                builder.add_write_time_range(min, max);
+            }
+        }
+        builders
+    }
+
+    /// The largest commit timestamp of the entries.
+    pub fn max_ts(&self) -> u64 {
+        self.max_ts.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// The earliest and latest write time of the entries in milliseconds since the UNIX epoch, or `None` if nothing
+    /// has been written.
+    pub fn write_time_range(&self) -> Option<(u64, u64)> {
+        let min = self
+            .min_write_time
+            .load(std::sync::atomic::Ordering::Relaxed);
+        let max = self
+            .max_write_time
+            .load(std::sync::atomic::Ordering::Relaxed);
+        (min <= max).then_some((min, max))
+    }
+
+    /// The id of the memtable, which is also the id of its WAL and of the (first) SST it is flushed to.
     pub fn id(&self) -> usize {
         self.id
     }
 
+    /// The total size of the keys and values written, which decides when the memtable is frozen.
     pub fn approximate_size(&self) -> usize {
         self.approximate_size
             .load(std::sync::atomic::Ordering::Relaxed)
     }
 
-    /// Only use this function when closing the database
+    /// Whether nothing has been written to the memtable.
     pub fn is_empty(&self) -> bool {
         self.map.is_empty()
     }
@@ -234,6 +428,8 @@ type SkipMapRangeIter<'a> = crossbeam_skiplist::map::Range<
 pub struct MemTableIterator {
     /// Stores a reference to the skipmap.
     map: Arc<SkipMap<KeyBytes, Bytes>>,
+    /// The upper bound of the range, kept for seeking.
+    upper: Bound<KeyBytes>,
     /// Stores a skipmap iterator that refers to the lifetime of `MemTableIterator` itself.
     #[borrows(map)]
     #[not_covariant]
@@ -271,3 +467,17 @@ impl StorageIterator for MemTableIterator {
         Ok(())
     }
 }
+
+impl SeekableIterator for MemTableIterator {
+    fn seek(&mut self, key: KeySlice) -> Result<()> {
+        if !self.is_valid() || self.key() >= key {
+            return Ok(());
+        }
+        let lower = map_key_bound(Bound::Included(key));
+        self.with_mut(|x| {
+            *x.iter = x.map.range((lower, x.upper.clone()));
+            *x.item = MemTableIterator::entry_to_item(x.iter.next());
+        });
+        Ok(())
+    }
+}
@@ -15,20 +15,50 @@
 #![allow(unused_variables)] // TODO(you): remove this lint after implementing this mod
 #![allow(dead_code)] // TODO(you): remove this lint after implementing this mod
 
+pub(crate) mod lock_manager;
+pub(crate) mod prepared;
+pub mod snapshot;
+pub mod ts_provider;
 pub mod txn;
 pub mod watermark;
 
 use std::{
     collections::{BTreeMap, HashSet},
-    sync::{Arc, atomic::AtomicBool},
+    fmt,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
 };
 
+use anyhow::{Result, bail};
+use bytes::Bytes;
 use crossbeam_skiplist::SkipMap;
-use parking_lot::Mutex;
+use parking_lot::{Condvar, Mutex};
 
+use crate::lsm_error::Error;
 use crate::lsm_storage::LsmStorageInner;
+use crate::stats::TxnStats;
 
-use self::{txn::Transaction, watermark::Watermark};
+use self::{
+    lock_manager::LockManager,
+    prepared::{IntentLocks, Intents},
+    snapshot::Snapshot,
+    ts_provider::{TsProvider, TsProviderOptions},
+    txn::Transaction,
+    watermark::Watermark,
+};
+
+/// Attached as context to the `Error::Busy` of a write to a key locked by another transaction, telling which key it
+/// is. The conversion to `lsm_error::Error` drops it.
+#[derive(Debug)]
+pub(crate) struct WriteConflict(pub(crate) Bytes);
+
+impl fmt::Display for WriteConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "write conflict on key {:?}", self.0)
+    }
+}
 
 pub(crate) struct CommittedTxnData {
     pub(crate) key_hashes: HashSet<u32>,
@@ -38,20 +68,62 @@ pub(crate) struct CommittedTxnData {
     pub(crate) commit_ts: u64,
 }
 
+/// Publishes a commit timestamp handed out by `LsmMvccInner` when dropped, so that a write failing or panicking before
+/// it publishes does not block the writes after it forever.
+pub(crate) struct PublishOnDrop<'a> {
+    mvcc: &'a LsmMvccInner,
+    ts: u64,
+}
+
+impl Drop for PublishOnDrop<'_> {
+    fn drop(&mut self) {
+        self.mvcc.publish_commit_ts(self.ts);
+    }
+}
+
 pub(crate) struct LsmMvccInner {
     pub(crate) write_lock: Mutex<()>,
     pub(crate) commit_lock: Mutex<()>,
+    /// The latest published commit timestamp, which all reads start from, and the read timestamps in use.
     pub(crate) ts: Arc<Mutex<(u64, Watermark)>>,
+    /// Signalled whenever the published commit timestamp advances.
+    ts_published: Condvar,
+    /// The latest commit timestamp handed out to a write, which may not be published yet.
+    next_commit_ts: AtomicU64,
+    /// The highest watermark compactions may have dropped versions below, under which no read can start.
+    gc_watermark: AtomicU64,
+    /// The commit timestamps handed out but not yet published, which are published in order.
+    /// The number of unpublished writes at each handed out timestamp.
+    unpublished: Mutex<BTreeMap<u64, usize>>,
+    ts_provider: TsProviderOptions,
     pub(crate) committed_txns: Arc<Mutex<BTreeMap<u64, CommittedTxnData>>>,
+    pub(crate) intent_locks: Mutex<IntentLocks>,
+    /// The keys locked by `Transaction::get_for_update`.
+    pub(crate) lock_manager: LockManager,
+    next_txn_id: AtomicU64,
+    /// Transactions found prepared when opening or dropped undecided, until taken by `take_recovered_prepared`.
+    pub(crate) recovered_prepared: Mutex<Vec<(String, Intents)>>,
+    /// The commits and aborts of transactions since opening.
+    pub(crate) txn_stats: Mutex<TxnStats>,
 }
 
 impl LsmMvccInner {
-    pub fn new(initial_ts: u64) -> Self {
+    pub fn new(initial_ts: u64, gc_watermark: u64, ts_provider: TsProviderOptions) -> Self {
         Self {
             write_lock: Mutex::new(()),
             commit_lock: Mutex::new(()),
             ts: Arc::new(Mutex::new((initial_ts, Watermark::new()))),
+            ts_published: Condvar::new(),
+            next_commit_ts: AtomicU64::new(initial_ts),
+            gc_watermark: AtomicU64::new(gc_watermark),
+            unpublished: Mutex::new(BTreeMap::new()),
+            ts_provider,
             committed_txns: Arc::new(Mutex::new(BTreeMap::new())),
+            intent_locks: Mutex::new(IntentLocks::default()),
+            lock_manager: LockManager::default(),
+            next_txn_id: AtomicU64::new(1),
+            recovered_prepared: Mutex::new(Vec::new()),
+            txn_stats: Mutex::new(TxnStats::default()),
         }
     }
 
@@ -59,8 +131,90 @@ impl LsmMvccInner {
         self.ts.lock().0
     }
 
-    pub fn update_commit_ts(&self, ts: u64) {
-        self.ts.lock().0 = ts;
+    /// Hand out the commit timestamp of a write from the timestamp provider, which must be passed to
+    /// `publish_commit_ts` even if the write fails. The caller holds `write_lock`, so that timestamps are handed out in
+    /// the order the writes are logged.
+    pub(crate) fn allocate_commit_ts(&self) -> Result<u64> {
+        let last = self.next_commit_ts.load(Ordering::SeqCst);
+        let ts = self.ts_provider.next_ts(last)?;
+        if ts <= last {
+            bail!(Error::InvalidArgument(format!(
+                "timestamp provider returned ts={ts}, which is not above the latest commit ts={last}"
+            )));
+        }
+        *self.unpublished.lock().entry(ts).or_default() += 1;
+        self.next_commit_ts.store(ts, Ordering::SeqCst);
+        Ok(ts)
+    }
+
+    /// Hand out `ts` as the commit timestamp of a write, e.g., one replayed from the log of a leader, like
+    /// `allocate_commit_ts`. It cannot go below the latest one handed out, and several writes may only share it while
+    /// it is unpublished, as reads may have started at it since.
+    pub(crate) fn allocate_commit_ts_at(&self, ts: u64) -> Result<u64> {
+        let last = self.next_commit_ts.load(Ordering::SeqCst);
+        let mut unpublished = self.unpublished.lock();
+        if ts < last || (ts == last && !unpublished.contains_key(&ts)) {
+            bail!(Error::InvalidArgument(format!(
+                "commit ts={ts} is not above the latest commit ts={last}"
+            )));
+        }
+        *unpublished.entry(ts).or_default() += 1;
+        drop(unpublished);
+        self.next_commit_ts.store(ts, Ordering::SeqCst);
+        Ok(ts)
+    }
+
+    /// The latest commit timestamp handed out, which may not be published yet.
+    pub(crate) fn last_allocated_ts(&self) -> u64 {
+        self.next_commit_ts.load(Ordering::SeqCst)
+    }
+
+    /// Make later commits take timestamps above `ts`. The caller holds `write_lock`.
+    pub(crate) fn observe_ts(&self, ts: u64) {
+        self.next_commit_ts.fetch_max(ts, Ordering::SeqCst);
+    }
+
+    /// Publish `ts`, handed out by `allocate_commit_ts` or `allocate_commit_ts_at`, once the returned guard is dropped.
+    pub(crate) fn publish_on_drop(&self, ts: u64) -> PublishOnDrop<'_> {
+        PublishOnDrop { mvcc: self, ts }
+    }
+
+    /// Make the write at `ts` visible to new reads once all writes before it are, so that no read sees a write without
+    /// the earlier ones.
+    pub(crate) fn publish_commit_ts(&self, ts: u64) {
+        let mut guard = self.ts.lock();
+        while self
+            .unpublished
+            .lock()
+            .first_key_value()
+            .map(|(first, _)| *first)
+            != Some(ts)
+        {
+            self.ts_published.wait(&mut guard);
+        }
+        let mut unpublished = self.unpublished.lock();
+        let count = unpublished.get_mut(&ts).unwrap();
+        *count -= 1;
+        // Other writes sharing `ts` are still in flight, so reads cannot start at it yet
+        if *count == 0 {
+            unpublished.remove(&ts);
+            drop(unpublished);
+            guard.0 = ts;
+            self.ts_published.notify_all();
+        }
+    }
+
+    /// Wait until the writes at `ts` and all before it are published.
+    pub(crate) fn wait_published(&self, ts: u64) {
+        let mut guard = self.ts.lock();
+        while self
+            .unpublished
+            .lock()
+            .first_key_value()
+            .is_some_and(|(first, _)| *first <= ts)
+        {
+            self.ts_published.wait(&mut guard);
+        }
     }
 
     /// All ts (strictly) below this ts can be garbage collected.
@@ -69,20 +223,132 @@ impl LsmMvccInner {
         ts.1.watermark().unwrap_or(ts.0)
     }
 
+    /// The lowest timestamp a read can start at, as compactions may have dropped the versions visible below it.
+    pub fn gc_watermark(&self) -> u64 {
+        self.gc_watermark.load(Ordering::SeqCst)
+    }
+
+    /// The watermark for a compaction to drop versions below, which keeps reads from starting below it from now on.
+    pub(crate) fn watermark_for_gc(&self) -> u64 {
+        let ts = self.ts.lock();
+        let watermark = ts.1.watermark().unwrap_or(ts.0);
+        self.gc_watermark.fetch_max(watermark, Ordering::SeqCst);
+        watermark
+    }
+
+    /// Keep reads from starting below `ts` from now on, so that compactions drop the versions below it. Fails if a read
+    /// in progress is below it.
+    pub(crate) fn raise_gc_watermark(&self, ts: u64) -> Result<()> {
+        let guard = self.ts.lock();
+        let watermark = guard.1.watermark().unwrap_or(guard.0);
+        if ts > watermark {
+            bail!(Error::InvalidArgument(format!(
+                "cannot gc below ts={ts}, above the watermark ts={watermark}"
+            )));
+        }
+        self.gc_watermark.fetch_max(ts, Ordering::SeqCst);
+        Ok(())
+    }
+
     pub fn new_txn(&self, inner: Arc<LsmStorageInner>, serializable: bool) -> Arc<Transaction> {
         let mut ts = self.ts.lock();
         let read_ts = ts.0;
         ts.1.add_reader(read_ts);
+        self.txn_at(inner, read_ts, serializable, true)
+    }
+
+    /// Pin the latest commit timestamp in the watermark until the returned snapshot is dropped.
+    pub(crate) fn new_snapshot(&self, inner: Arc<LsmStorageInner>) -> Snapshot {
+        let mut ts = self.ts.lock();
+        let read_ts = ts.0;
+        ts.1.add_reader(read_ts);
+        Snapshot { inner, read_ts }
+    }
+
+    /// Create a transaction that reads the snapshot at `read_ts`, which must not be below the GC watermark, as older
+    /// versions may already be garbage collected.
+    pub(crate) fn new_txn_at(
+        &self,
+        inner: Arc<LsmStorageInner>,
+        read_ts: u64,
+        serializable: bool,
+    ) -> Result<Arc<Transaction>> {
+        let mut ts = self.ts.lock();
+        let (latest_commit_ts, watermark) = &mut *ts;
+        let lowest_retained_ts = self.gc_watermark();
+        if read_ts > *latest_commit_ts || read_ts < lowest_retained_ts {
+            bail!(Error::InvalidArgument(format!(
+                "cannot read at ts={}, only [{}, {}] is retained",
+                read_ts, lowest_retained_ts, latest_commit_ts
+            )));
+        }
+        watermark.add_reader(read_ts);
+        Ok(self.txn_at(inner, read_ts, serializable, true))
+    }
+
+    /// Create a transaction for a read-committed scan at `read_ts`, which is not pinned in the watermark, as the scan
+    /// holds the state it reads.
+    pub(crate) fn read_committed_txn(
+        &self,
+        inner: Arc<LsmStorageInner>,
+        read_ts: u64,
+    ) -> Arc<Transaction> {
+        self.txn_at(inner, read_ts, false, false)
+    }
+
+    /// Lock the keys of the transactions found prepared when opening, and keep them until `take_recovered_prepared`.
+    pub(crate) fn set_recovered_prepared(&self, prepared: Vec<(String, Intents)>) -> Result<()> {
+        let mut intent_locks = self.intent_locks.lock();
+        for (name, intents) in &prepared {
+            let keys = intents
+                .iter()
+                .map(|(key, _)| key.clone())
+                .collect::<Vec<_>>();
+            intent_locks.acquire(name, &keys, HashSet::new())?;
+        }
+        *self.recovered_prepared.lock() = prepared;
+        Ok(())
+    }
+
+    /// Take the transactions found prepared when opening, as prepared transactions reading the latest commit.
+    pub(crate) fn take_recovered_prepared(
+        &self,
+        inner: &Arc<LsmStorageInner>,
+    ) -> Vec<Arc<Transaction>> {
+        std::mem::take(&mut *self.recovered_prepared.lock())
+            .into_iter()
+            .map(|(name, intents)| {
+                let txn = self.new_txn(inner.clone(), false);
+                for (key, value) in intents {
+                    txn.local_storage.insert(key, value);
+                }
+                *txn.prepared.lock() = Some(name);
+                txn
+            })
+            .collect()
+    }
+
+    fn txn_at(
+        &self,
+        inner: Arc<LsmStorageInner>,
+        read_ts: u64,
+        serializable: bool,
+        pinned: bool,
+    ) -> Arc<Transaction> {
         Arc::new(Transaction {
             inner,
+            id: self.next_txn_id.fetch_add(1, Ordering::Relaxed),
             read_ts,
             local_storage: Arc::new(SkipMap::new()),
             committed: Arc::new(AtomicBool::new(false)),
+            prepared: Mutex::new(None),
+            locked_keys: Mutex::new(Vec::new()),
             key_hashes: if serializable {
                 Some(Mutex::new((HashSet::new(), HashSet::new())))
             } else {
                 None
             },
+            pinned,
         })
     }
 }
@@ -0,0 +1,128 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Per-key locks for pessimistic transactions, taken by `Transaction::get_for_update`, so that write-write conflicts
+//! block up front instead of aborting at commit. A lock is held by one transaction until it commits, rolls back or is
+//! dropped. Waits that would close a cycle in the wait-for graph fail immediately, and all waits are bounded by a
+//! timeout.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, bail};
+use bytes::Bytes;
+use parking_lot::{Condvar, Mutex};
+
+use crate::lsm_error::Error;
+use crate::mvcc::WriteConflict;
+
+#[derive(Default)]
+struct LockTable {
+    /// The transaction holding the lock of each key.
+    owners: HashMap<Bytes, u64>,
+    /// The transaction each waiting transaction waits for.
+    waits_for: HashMap<u64, u64>,
+}
+
+impl LockTable {
+    /// Whether `txn_id` waiting for `owner` would wait for itself.
+    fn would_deadlock(&self, txn_id: u64, mut owner: u64) -> bool {
+        // Every transaction waits for at most one other, so the chain either ends or reaches a cycle.
+        for _ in 0..=self.waits_for.len() {
+            if owner == txn_id {
+                return true;
+            }
+            match self.waits_for.get(&owner) {
+                Some(next) => owner = *next,
+                None => return false,
+            }
+        }
+        false
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct LockManager {
+    table: Mutex<LockTable>,
+    released: Condvar,
+}
+
+impl LockManager {
+    /// Lock `key` for `txn_id`, waiting at most `timeout` for its holder to release it, and return whether it was newly
+    /// locked. Fails with `Error::Busy` on a timeout or if waiting would deadlock.
+    pub(crate) fn lock(&self, txn_id: u64, key: &[u8], timeout: Duration) -> Result<bool> {
+        let deadline = Instant::now() + timeout;
+        let mut table = self.table.lock();
+        loop {
+            let owner = match table.owners.get(key) {
+                None => {
+                    table.owners.insert(Bytes::copy_from_slice(key), txn_id);
+                    return Ok(true);
+                }
+                Some(&owner) if owner == txn_id => return Ok(false),
+                Some(&owner) => owner,
+            };
+            if table.would_deadlock(txn_id, owner) {
+                bail!(Error::Busy(format!(
+                    "deadlock detected waiting for the lock held by transaction {owner}"
+                )));
+            }
+            table.waits_for.insert(txn_id, owner);
+            let timed_out = self.released.wait_until(&mut table, deadline).timed_out();
+            table.waits_for.remove(&txn_id);
+            if timed_out && table.owners.contains_key(key) {
+                bail!(Error::Busy(format!(
+                    "timed out waiting for the lock held by transaction {owner}"
+                )));
+            }
+        }
+    }
+
+    /// Fail with `Error::Busy` if any of `keys` is locked by a transaction other than `txn_id`.
+    pub(crate) fn check<'a>(
+        &self,
+        keys: impl IntoIterator<Item = &'a [u8]>,
+        txn_id: Option<u64>,
+    ) -> Result<()> {
+        let table = self.table.lock();
+        if table.owners.is_empty() {
+            return Ok(());
+        }
+        for key in keys {
+            if let Some(&owner) = table.owners.get(key)
+                && Some(owner) != txn_id
+            {
+                return Err(anyhow::Error::from(Error::Busy(format!(
+                    "key is locked by transaction {owner}"
+                )))
+                .context(WriteConflict(Bytes::copy_from_slice(key))));
+            }
+        }
+        Ok(())
+    }
+
+    /// Release the locks of `keys` held by `txn_id` and wake up the waiters.
+    pub(crate) fn unlock(&self, txn_id: u64, keys: &[Bytes]) {
+        if keys.is_empty() {
+            return;
+        }
+        let mut table = self.table.lock();
+        for key in keys {
+            if table.owners.get(key) == Some(&txn_id) {
+                table.owners.remove(key);
+            }
+        }
+        self.released.notify_all();
+    }
+}
@@ -0,0 +1,176 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The durable state of prepared transactions, for taking part in two-phase commits coordinated outside the engine.
+//! Each prepared transaction logs its writes to its own `<name>.prepared` file in the WAL format, which outlives the
+//! memtable WALs, and holds a lock on every key it writes until it is committed or rolled back.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, bail};
+use bytes::Bytes;
+use crossbeam_skiplist::SkipMap;
+
+use crate::key::{KeyBytes, KeySlice};
+use crate::lsm_error::Error;
+use crate::mvcc::WriteConflict;
+use crate::platform;
+use crate::wal::Wal;
+
+pub(crate) const PREPARED_SUFFIX: &str = ".prepared";
+
+/// The writes of a prepared transaction, where an empty value deletes the key.
+pub(crate) type Intents = Vec<(Bytes, Bytes)>;
+
+pub(crate) fn path_of_prepared(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{name}{PREPARED_SUFFIX}"))
+}
+
+/// Names become file names, so only short names of ASCII letters, digits, `-` and `_` are accepted.
+pub(crate) fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || name.len() > 128
+        || !name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+    {
+        bail!(Error::InvalidArgument(format!(
+            "invalid prepared transaction name {name:?}"
+        )));
+    }
+    Ok(())
+}
+
+/// Durably log the intents of the prepared transaction `name`. Fails if a transaction of that name is prepared.
+pub(crate) fn write_intents(dir: &Path, name: &str, read_ts: u64, intents: &Intents) -> Result<()> {
+    let path = path_of_prepared(dir, name);
+    if path.exists() {
+        bail!(Error::InvalidArgument(format!(
+            "transaction {name} is already prepared"
+        )));
+    }
+    let wal = Wal::create(&path)?;
+    let data = intents
+        .iter()
+        .map(|(key, value)| (KeySlice::from_slice(key, read_ts), value.as_ref()))
+        .collect::<Vec<_>>();
+    let written = wal
+        .put_batch(&data)
+        .and_then(|()| wal.sync())
+        .and_then(|()| Ok(platform::sync_dir(dir)?));
+    if written.is_err() {
+        std::fs::remove_file(&path).ok();
+    }
+    written
+}
+
+pub(crate) fn remove_intents(dir: &Path, name: &str) -> Result<()> {
+    std::fs::remove_file(path_of_prepared(dir, name))?;
+    platform::sync_dir(dir)?;
+    Ok(())
+}
+
+/// The prepared transactions left in `dir`, by name.
+pub(crate) fn recover(dir: &Path) -> Result<Vec<(String, Intents)>> {
+    let mut prepared = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(name) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(PREPARED_SUFFIX))
+        else {
+            continue;
+        };
+        let map = SkipMap::<KeyBytes, Bytes>::new();
+        Wal::recover(&path, &map)?;
+        let intents = map
+            .iter()
+            .map(|entry| {
+                (
+                    Bytes::copy_from_slice(entry.key().key_ref()),
+                    entry.value().clone(),
+                )
+            })
+            .collect();
+        prepared.push((name.to_string(), intents));
+    }
+    prepared.sort_by(|(x, _), (y, _)| x.cmp(y));
+    Ok(prepared)
+}
+
+/// The keys written by prepared transactions, which no other write may touch until they are decided.
+#[derive(Default)]
+pub(crate) struct IntentLocks {
+    owners: HashMap<Bytes, String>,
+    /// The hashes of the keys read by each serializable prepared transaction, which no other write may touch either,
+    /// as the transaction is no longer validated when committed.
+    read_sets: HashMap<String, HashSet<u32>>,
+}
+
+impl IntentLocks {
+    /// Fail with `Error::Busy` if any of `keys` is locked by a prepared transaction other than `owner`.
+    pub(crate) fn check<'a>(
+        &self,
+        keys: impl IntoIterator<Item = &'a [u8]>,
+        owner: Option<&str>,
+    ) -> Result<()> {
+        if self.owners.is_empty() && self.read_sets.is_empty() {
+            return Ok(());
+        }
+        for key in keys {
+            if let Some(name) = self.owners.get(key)
+                && Some(name.as_str()) != owner
+            {
+                return Err(anyhow::Error::from(Error::Busy(format!(
+                    "key is locked by prepared transaction {name}"
+                )))
+                .context(WriteConflict(Bytes::copy_from_slice(key))));
+            }
+            let key_hash = farmhash::hash32(key);
+            if let Some((name, _)) = self.read_sets.iter().find(|(name, read_set)| {
+                Some(name.as_str()) != owner && read_set.contains(&key_hash)
+            }) {
+                return Err(anyhow::Error::from(Error::Busy(format!(
+                    "key was read by prepared transaction {name}"
+                )))
+                .context(WriteConflict(Bytes::copy_from_slice(key))));
+            }
+        }
+        Ok(())
+    }
+
+    /// Lock all of `keys` and the keys hashed in `read_set` for `owner`, or none of them if any of `keys` is locked.
+    pub(crate) fn acquire(
+        &mut self,
+        owner: &str,
+        keys: &[Bytes],
+        read_set: HashSet<u32>,
+    ) -> Result<()> {
+        self.check(keys.iter().map(|key| key.as_ref()), None)?;
+        for key in keys {
+            self.owners.insert(key.clone(), owner.to_string());
+        }
+        if !read_set.is_empty() {
+            self.read_sets.insert(owner.to_string(), read_set);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn release(&mut self, owner: &str) {
+        self.owners.retain(|_, name| name != owner);
+        self.read_sets.remove(owner);
+    }
+}
@@ -0,0 +1,83 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+use std::sync::Arc;
+
+use bytes::Bytes;
+
+use crate::{
+    lsm_error,
+    lsm_storage::{LsmStorageInner, ReadOptions},
+    mvcc::txn::TxnIterator,
+};
+
+/// A consistent view of the storage at a read timestamp, which stays readable until the handle is dropped, as it holds
+/// back the watermark and thus the garbage collection of the versions it sees. Unlike a transaction, it cannot write
+/// and keeps no read set, so it can serve any number of reads at no extra cost.
+pub struct Snapshot {
+    pub(crate) inner: Arc<LsmStorageInner>,
+    pub(crate) read_ts: u64,
+}
+
+impl Snapshot {
+    /// The timestamp this snapshot reads at.
+    pub fn read_ts(&self) -> u64 {
+        self.read_ts
+    }
+
+    pub fn get(&self, key: &[u8]) -> lsm_error::Result<Option<Bytes>> {
+        self.get_with_options(key, &ReadOptions::default())
+    }
+
+    /// Get a key, reading SST blocks according to `options`. `options.snapshot` is ignored.
+    pub fn get_with_options(
+        &self,
+        key: &[u8],
+        options: &ReadOptions,
+    ) -> lsm_error::Result<Option<Bytes>> {
+        Ok(self.inner.get_with_options(key, self.read_ts, options)?)
+    }
+
+    /// Check whether a key exists like `get`, without copying its value.
+    pub fn contains(&self, key: &[u8]) -> lsm_error::Result<bool> {
+        Ok(self
+            .inner
+            .contains_with_options(key, self.read_ts, &ReadOptions::default())?)
+    }
+
+    pub fn scan(&self, lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> lsm_error::Result<TxnIterator> {
+        self.scan_with_options(lower, upper, &ReadOptions::default())
+    }
+
+    /// Scan a range, reading SST blocks according to `options`. `options.snapshot` is ignored. The iterator may outlive
+    /// the snapshot, as it pins the read timestamp on its own.
+    pub fn scan_with_options(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        options: &ReadOptions,
+    ) -> lsm_error::Result<TxnIterator> {
+        self.inner
+            .mvcc()
+            .new_txn_at(self.inner.clone(), self.read_ts, false)?
+            .scan_with_options(lower, upper, options)
+    }
+}
+
+impl Drop for Snapshot {
+    fn drop(&mut self) {
+        self.inner.mvcc().ts.lock().1.remove_reader(self.read_ts)
+    }
+}
@@ -0,0 +1,69 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Sources of commit timestamps. Each commit asks the provider of the storage for a timestamp above the latest one
+//! handed out, which is recovered from the manifest and the data when reopening, so that timestamps keep increasing
+//! across restarts whichever provider is used.
+
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::table::unix_millis;
+
+/// The number of low bits of a hybrid logical timestamp holding its logical counter.
+pub const HLC_LOGICAL_BITS: u32 = 16;
+
+pub trait TsProvider: Send + Sync + Debug {
+    /// A commit timestamp greater than `last`, the latest one handed out. Commits fail if it is not greater.
+    fn next_ts(&self, last: u64) -> Result<u64>;
+}
+
+/// The commit timestamp provider of the storage. The built-in providers can be read from an options file, while an
+/// external one cannot be serialized.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub enum TsProviderOptions {
+    /// A counter incremented by each commit.
+    #[default]
+    Logical,
+    /// A hybrid logical clock, holding the milliseconds since the Unix epoch above `HLC_LOGICAL_BITS` bits of a
+    /// counter, which orders the commits within a millisecond and keeps the timestamps increasing when the clock goes
+    /// back. Timestamps can be compared with those of other nodes, whose clocks are roughly in sync.
+    HybridLogical,
+    /// Another source, e.g., a timestamp oracle shared by several nodes.
+    #[serde(skip)]
+    External(Arc<dyn TsProvider>),
+}
+
+impl TsProvider for TsProviderOptions {
+    fn next_ts(&self, last: u64) -> Result<u64> {
+        match self {
+            TsProviderOptions::Logical => Ok(last + 1),
+            TsProviderOptions::HybridLogical => {
+                let physical = unix_millis(SystemTime::now()) << HLC_LOGICAL_BITS;
+                Ok(physical.max(last + 1))
+            }
+            TsProviderOptions::External(provider) => provider.next_ts(last),
+        }
+    }
+}
+
+/// The milliseconds since the Unix epoch at which a hybrid logical timestamp was handed out, or after which if the
+/// clock went back.
+pub fn hlc_physical_millis(ts: u64) -> u64 {
+    ts >> HLC_LOGICAL_BITS
+}
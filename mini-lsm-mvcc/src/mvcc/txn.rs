@@ -21,31 +21,75 @@ use std::{
     },
 };
 
-use anyhow::{Result, bail};
+use anyhow::Result;
 use bytes::Bytes;
 use crossbeam_skiplist::{SkipMap, map::Entry};
 use ouroboros::self_referencing;
 use parking_lot::Mutex;
 
 use crate::{
-    iterators::{StorageIterator, two_merge_iterator::TwoMergeIterator},
-    lsm_iterator::{FusedIterator, LsmIterator},
-    lsm_storage::{LsmStorageInner, WriteBatchRecord},
+    iterators::{SeekableIterator, StorageIterator, two_merge_iterator::TwoMergeIterator},
+    lsm_error::{self, Error},
+    lsm_iterator::{CancelHandle, FusedIterator, LsmIterator},
+    lsm_storage::{LsmStorageInner, ReadOptions, WriteBatchRecord, WriteOptions},
     mem_table::map_bound,
-    mvcc::CommittedTxnData,
+    mvcc::{CommittedTxnData, WriteConflict, prepared},
+    stats::AbortReason,
+    table::{CorruptedBlock, CorruptionReport},
 };
 
 pub struct Transaction {
+    /// Identifies the transaction as the holder of key locks.
+    pub(crate) id: u64,
     pub(crate) read_ts: u64,
     pub(crate) inner: Arc<LsmStorageInner>,
     pub(crate) local_storage: Arc<SkipMap<Bytes, Bytes>>,
     pub(crate) committed: Arc<AtomicBool>,
+    /// The name of the transaction once prepared for a two-phase commit, until it is decided.
+    pub(crate) prepared: Mutex<Option<String>>,
+    /// The keys locked by `get_for_update`, until the transaction is committed, rolled back or dropped.
+    pub(crate) locked_keys: Mutex<Vec<Bytes>>,
     /// Write set and read set
     pub(crate) key_hashes: Option<Mutex<(HashSet<u32>, HashSet<u32>)>>,
+    /// Whether `read_ts` is pinned in the watermark until the transaction is dropped, which read-committed reads skip.
+    pub(crate) pinned: bool,
 }
 
 impl Transaction {
-    pub fn get(&self, key: &[u8]) -> Result<Option<Bytes>> {
+    /// The timestamp of the snapshot this transaction reads from.
+    pub fn read_ts(&self) -> u64 {
+        self.read_ts
+    }
+
+    pub fn get(&self, key: &[u8]) -> lsm_error::Result<Option<Bytes>> {
+        self.get_with_options(key, &ReadOptions::default())
+    }
+
+    /// Check whether a key exists like `get`, without copying its value.
+    pub fn contains(&self, key: &[u8]) -> lsm_error::Result<bool> {
+        if self.committed.load(Ordering::SeqCst) {
+            panic!("cannot operate on committed txn!");
+        }
+        if let Some(guard) = &self.key_hashes {
+            let mut guard = guard.lock();
+            let (_, read_set) = &mut *guard;
+            read_set.insert(farmhash::hash32(key));
+        }
+        if let Some(entry) = self.local_storage.get(key) {
+            return Ok(!entry.value().is_empty());
+        }
+        Ok(self
+            .inner
+            .contains_with_options(key, self.read_ts, &ReadOptions::default())?)
+    }
+
+    /// Get a key, reading SST blocks according to `options`. `options.snapshot` is ignored, the transaction always
+    /// reads at `read_ts`.
+    pub fn get_with_options(
+        &self,
+        key: &[u8],
+        options: &ReadOptions,
+    ) -> lsm_error::Result<Option<Bytes>> {
         if self.committed.load(Ordering::SeqCst) {
             panic!("cannot operate on committed txn!");
         }
@@ -61,15 +105,123 @@ impl Transaction {
                 return Ok(Some(entry.value().clone()));
             }
         }
-        self.inner.get_with_ts(key, self.read_ts)
+        Ok(self.inner.get_with_options(key, self.read_ts, options)?)
+    }
+
+    /// Get several keys like `get`, returning the values in the order of `keys`. The keys not written by the
+    /// transaction are looked up in the same state of the storage.
+    pub fn get_multi<T: AsRef<[u8]>>(&self, keys: &[T]) -> lsm_error::Result<Vec<Option<Bytes>>> {
+        if self.committed.load(Ordering::SeqCst) {
+            panic!("cannot operate on committed txn!");
+        }
+        if let Some(guard) = &self.key_hashes {
+            let mut guard = guard.lock();
+            let (_, read_set) = &mut *guard;
+            for key in keys {
+                read_set.insert(farmhash::hash32(key.as_ref()));
+            }
+        }
+        let mut values = Vec::with_capacity(keys.len());
+        let mut missing = Vec::new();
+        for (idx, key) in keys.iter().enumerate() {
+            match self.local_storage.get(key.as_ref()) {
+                Some(entry) if entry.value().is_empty() => values.push(None),
+                Some(entry) => values.push(Some(entry.value().clone())),
+                None => {
+                    values.push(None);
+                    missing.push(idx);
+                }
+            }
+        }
+        let missing_keys = missing
+            .iter()
+            .map(|idx| keys[*idx].as_ref())
+            .collect::<Vec<_>>();
+        let found = self.inner.get_multi_with_options(
+            &missing_keys,
+            self.read_ts,
+            &ReadOptions::default(),
+        )?;
+        for (idx, value) in missing.into_iter().zip(found) {
+            values[idx] = value;
+        }
+        Ok(values)
+    }
+
+    /// Lock a key for this transaction and get it, so that no other write touches the key until the transaction is
+    /// committed, rolled back or dropped. This avoids aborting on commit under high contention. If another transaction
+    /// holds the lock, waits up to `LsmStorageOptions::lock_timeout` for it, and fails with `Error::Busy` on a timeout,
+    /// if waiting would deadlock, or if the key was changed after `read_ts`.
+    pub fn get_for_update(&self, key: &[u8]) -> lsm_error::Result<Option<Bytes>> {
+        if self.committed.load(Ordering::SeqCst) {
+            panic!("cannot operate on committed txn!");
+        }
+        if self.prepared.lock().is_some() {
+            panic!("cannot write to prepared txn!");
+        }
+        let mvcc = self.inner.mvcc();
+        let lock_manager = &mvcc.lock_manager;
+        if lock_manager.lock(self.id, key, self.inner.options().lock_timeout)? {
+            let key = Bytes::copy_from_slice(key);
+            // A write that passed the lock check before the lock was taken has its timestamp handed out once the write
+            // lock is free, but may still be on its way to the memtable, so wait for it before looking for it.
+            let last_ts = {
+                let _lck = mvcc.write_lock.lock();
+                mvcc.last_allocated_ts()
+            };
+            mvcc.wait_published(last_ts);
+            match self.inner.modified_after(&key, self.read_ts) {
+                Ok(false) => self.locked_keys.lock().push(key),
+                Ok(true) => {
+                    lock_manager.unlock(self.id, &[key]);
+                    return Err(Error::Busy(
+                        "key was changed after the transaction started".to_string(),
+                    ));
+                }
+                Err(e) => {
+                    lock_manager.unlock(self.id, &[key]);
+                    return Err(e.into());
+                }
+            }
+        }
+        self.get(key)
     }
 
-    pub fn scan(self: &Arc<Self>, lower: Bound<&[u8]>, upper: Bound<&[u8]>) -> Result<TxnIterator> {
+    pub fn scan(
+        self: &Arc<Self>,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+    ) -> lsm_error::Result<TxnIterator> {
+        self.scan_with_options(lower, upper, &ReadOptions::default())
+    }
+
+    /// Scan a range, reading SST blocks according to `options`. `options.snapshot` is ignored, the transaction always
+    /// reads at `read_ts`.
+    pub fn scan_with_options(
+        self: &Arc<Self>,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        options: &ReadOptions,
+    ) -> lsm_error::Result<TxnIterator> {
         if self.committed.load(Ordering::SeqCst) {
             panic!("cannot operate on committed txn!");
         }
+        let storage_iter = self
+            .inner
+            .scan_with_options(lower, upper, self.read_ts, options)?;
+        self.scan_storage(lower, upper, storage_iter)
+    }
+
+    /// Merge the writes of the transaction in the range into `storage_iter`, a scan of the range at `read_ts`.
+    pub(crate) fn scan_storage(
+        self: &Arc<Self>,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        storage_iter: FusedIterator<LsmIterator>,
+    ) -> lsm_error::Result<TxnIterator> {
         let mut local_iter = TxnLocalIteratorBuilder {
             map: self.local_storage.clone(),
+            upper: map_bound(upper),
             iter_builder: |map| map.range((map_bound(lower), map_bound(upper))),
             item: (Bytes::new(), Bytes::new()),
         }
@@ -77,19 +229,24 @@ impl Transaction {
         let entry = local_iter.with_iter_mut(|iter| TxnLocalIterator::entry_to_item(iter.next()));
         local_iter.with_mut(|x| *x.item = entry);
 
-        TxnIterator::create(
+        let cancel = storage_iter.cancel_handle();
+        let corrupted = storage_iter.corruption_report();
+        let mut iter = TxnIterator::create(
             self.clone(),
-            TwoMergeIterator::create(
-                local_iter,
-                self.inner.scan_with_ts(lower, upper, self.read_ts)?,
-            )?,
-        )
+            TwoMergeIterator::create(local_iter, storage_iter)?,
+            cancel,
+        )?;
+        iter.corrupted = corrupted;
+        Ok(iter)
     }
 
     pub fn put(&self, key: &[u8], value: &[u8]) {
         if self.committed.load(Ordering::SeqCst) {
             panic!("cannot operate on committed txn!");
         }
+        if self.prepared.lock().is_some() {
+            panic!("cannot write to prepared txn!");
+        }
         self.local_storage
             .insert(Bytes::copy_from_slice(key), Bytes::copy_from_slice(value));
         if let Some(key_hashes) = &self.key_hashes {
@@ -103,6 +260,9 @@ impl Transaction {
         if self.committed.load(Ordering::SeqCst) {
             panic!("cannot operate on committed txn!");
         }
+        if self.prepared.lock().is_some() {
+            panic!("cannot write to prepared txn!");
+        }
         self.local_storage
             .insert(Bytes::copy_from_slice(key), Bytes::new());
         if let Some(key_hashes) = &self.key_hashes {
@@ -112,32 +272,33 @@ impl Transaction {
         }
     }
 
-    pub fn commit(&self) -> Result<()> {
+    pub fn commit(&self) -> lsm_error::Result<()> {
+        self.commit_with_options(&WriteOptions::default())
+    }
+
+    /// Commit the transaction, writing its changes according to `options`. A prepared transaction is not validated
+    /// again, and its changes are always synced to the WAL before its log is removed.
+    pub fn commit_with_options(&self, options: &WriteOptions) -> lsm_error::Result<()> {
         self.committed
             .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
             .expect("cannot operate on committed txn!");
+        let result = self.write_changes(options);
+        if result.is_ok() {
+            self.inner.mvcc().txn_stats.lock().record_commit();
+        }
+        // a prepared transaction that failed to commit is still prepared, and keeps its locks until dropped
+        if self.prepared.lock().is_none() {
+            self.unlock_keys();
+        }
+        result
+    }
+
+    fn write_changes(&self, options: &WriteOptions) -> lsm_error::Result<()> {
         let _commit_lock = self.inner.mvcc().commit_lock.lock();
-        let serializability_check;
-        if let Some(guard) = &self.key_hashes {
-            let guard = guard.lock();
-            let (write_set, read_set) = &*guard;
-            println!(
-                "commit txn: write_set: {:?}, read_set: {:?}",
-                write_set, read_set
-            );
-            if !write_set.is_empty() {
-                let committed_txns = self.inner.mvcc().committed_txns.lock();
-                for (_, txn_data) in committed_txns.range((self.read_ts + 1)..) {
-                    for key_hash in read_set {
-                        if txn_data.key_hashes.contains(key_hash) {
-                            bail!("serializable check failed");
-                        }
-                    }
-                }
-            }
-            serializability_check = true;
-        } else {
-            serializability_check = false;
+        let mut prepared = self.prepared.lock();
+        let serializability_check = self.key_hashes.is_some();
+        if prepared.is_none() {
+            self.check_serializable()?;
         }
         let batch = self
             .local_storage
@@ -150,7 +311,26 @@ impl Transaction {
                 }
             })
             .collect::<Vec<_>>();
-        let ts = self.inner.write_batch_inner(&batch)?;
+        let ts = match prepared.as_deref() {
+            None => self
+                .inner
+                .write_batch_with_owner(&batch, options, None, Some(self.id), None)
+                .map_err(|e| self.record_write_failure(e))?,
+            Some(name) => {
+                let options = WriteOptions {
+                    sync: true,
+                    disable_wal: false,
+                };
+                let ts = self
+                    .inner
+                    .write_batch_with_owner(&batch, &options, Some(name), Some(self.id), None)
+                    .map_err(|e| self.record_write_failure(e))?;
+                prepared::remove_intents(&self.inner.path, name)?;
+                self.inner.mvcc().intent_locks.lock().release(name);
+                *prepared = None;
+                ts
+            }
+        };
         if serializability_check {
             let mut committed_txns = self.inner.mvcc().committed_txns.lock();
             let mut key_hashes = self.key_hashes.as_ref().unwrap().lock();
@@ -178,11 +358,145 @@ impl Transaction {
         }
         Ok(())
     }
+
+    /// Fail with `Error::Busy` if a transaction committed after `read_ts` wrote a key this transaction read, recording
+    /// the abort. Only serializable transactions that write are checked.
+    fn check_serializable(&self) -> lsm_error::Result<()> {
+        if let Some(guard) = &self.key_hashes {
+            let guard = guard.lock();
+            let (write_set, read_set) = &*guard;
+            println!(
+                "commit txn: write_set: {:?}, read_set: {:?}",
+                write_set, read_set
+            );
+            if !write_set.is_empty() {
+                let committed_txns = self.inner.mvcc().committed_txns.lock();
+                for (commit_ts, txn_data) in committed_txns.range((self.read_ts + 1)..) {
+                    if read_set
+                        .iter()
+                        .any(|key_hash| txn_data.key_hashes.contains(key_hash))
+                    {
+                        self.inner.mvcc().txn_stats.lock().record_abort(
+                            AbortReason::ReadValidation {
+                                read_ts: self.read_ts,
+                                commit_ts: *commit_ts,
+                            },
+                        );
+                        return Err(Error::Busy("serializable check failed".to_string()));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Record the abort of the transaction by `e`, a failure to write its changes, and convert it.
+    fn record_write_failure(&self, e: anyhow::Error) -> Error {
+        let reason = match e.downcast_ref::<WriteConflict>() {
+            Some(WriteConflict(key)) => AbortReason::WriteConflict { key: key.clone() },
+            None => AbortReason::WriteFailed(format!("{e:#}")),
+        };
+        self.inner.mvcc().txn_stats.lock().record_abort(reason);
+        e.into()
+    }
+
+    /// Prepare the transaction for a two-phase commit coordinated outside the engine, as `name`, which must not be in
+    /// use by another prepared transaction. The transaction is validated like on `commit`, the keys it writes are
+    /// locked against all other writes, as are the keys it read if it is serializable, and its writes are durably
+    /// logged. Afterwards it can no longer write, and is decided by `commit` or `rollback`, which cannot fail because
+    /// of conflicts. A prepared transaction that is dropped undecided keeps its locks, and is handed out again by
+    /// `MiniLsm::recovered_prepared_txns`, like after reopening, where the keys it read are no longer locked.
+    pub fn prepare(&self, name: &str) -> lsm_error::Result<()> {
+        if self.committed.load(Ordering::SeqCst) {
+            panic!("cannot operate on committed txn!");
+        }
+        let mut prepared = self.prepared.lock();
+        if prepared.is_some() {
+            return Err(Error::InvalidArgument(
+                "transaction is already prepared".to_string(),
+            ));
+        }
+        self.inner.check_writable()?;
+        prepared::validate_name(name)?;
+        let mvcc = self.inner.mvcc();
+        let _commit_lock = mvcc.commit_lock.lock();
+        if prepared::path_of_prepared(&self.inner.path, name).exists() {
+            return Err(Error::InvalidArgument(format!(
+                "transaction {name} is already prepared"
+            )));
+        }
+        self.check_serializable()?;
+        let intents = self
+            .local_storage
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect::<Vec<_>>();
+        let keys = intents
+            .iter()
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        let read_set = match &self.key_hashes {
+            Some(key_hashes) => key_hashes.lock().1.clone(),
+            None => HashSet::new(),
+        };
+        mvcc.intent_locks
+            .lock()
+            .acquire(name, &keys, read_set)
+            .map_err(|e| self.record_write_failure(e))?;
+        if let Err(e) = prepared::write_intents(&self.inner.path, name, self.read_ts, &intents) {
+            mvcc.intent_locks.lock().release(name);
+            return Err(self.record_write_failure(e));
+        }
+        *prepared = Some(name.to_string());
+        Ok(())
+    }
+
+    /// The name the transaction is prepared as, if it is prepared and not yet decided.
+    pub fn prepared_name(&self) -> Option<String> {
+        self.prepared.lock().clone()
+    }
+
+    /// Discard the changes of the transaction. A prepared transaction also releases its locks and removes its log.
+    pub fn rollback(&self) -> lsm_error::Result<()> {
+        self.committed
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .expect("cannot operate on committed txn!");
+        let mut prepared = self.prepared.lock();
+        if let Some(name) = prepared.as_deref() {
+            prepared::remove_intents(&self.inner.path, name)?;
+            self.inner.mvcc().intent_locks.lock().release(name);
+            *prepared = None;
+        }
+        drop(prepared);
+        self.unlock_keys();
+        Ok(())
+    }
+
+    fn unlock_keys(&self) {
+        let keys = std::mem::take(&mut *self.locked_keys.lock());
+        self.inner.mvcc().lock_manager.unlock(self.id, &keys);
+    }
 }
 
 impl Drop for Transaction {
     fn drop(&mut self) {
-        self.inner.mvcc().ts.lock().1.remove_reader(self.read_ts)
+        if let Some(name) = self.prepared.get_mut().take() {
+            // Undecided, so the locks are kept until the transaction is taken again and decided
+            let intents = self
+                .local_storage
+                .iter()
+                .map(|entry| (entry.key().clone(), entry.value().clone()))
+                .collect();
+            self.inner
+                .mvcc()
+                .recovered_prepared
+                .lock()
+                .push((name, intents));
+        }
+        self.unlock_keys();
+        if self.pinned {
+            self.inner.mvcc().ts.lock().1.remove_reader(self.read_ts)
+        }
     }
 }
 
@@ -193,6 +507,8 @@ type SkipMapRangeIter<'a> =
 pub struct TxnLocalIterator {
     /// Stores a reference to the skipmap.
     map: Arc<SkipMap<Bytes, Bytes>>,
+    /// The upper bound of the range, kept for seeking.
+    upper: Bound<Bytes>,
     /// Stores a skipmap iterator that refers to the lifetime of `TxnLocalIterator` itself.
     #[borrows(map)]
     #[not_covariant]
@@ -231,17 +547,40 @@ impl StorageIterator for TxnLocalIterator {
     }
 }
 
+impl SeekableIterator for TxnLocalIterator {
+    fn seek(&mut self, key: &[u8]) -> Result<()> {
+        if !self.is_valid() || self.key() >= key {
+            return Ok(());
+        }
+        let lower = Bound::Included(Bytes::copy_from_slice(key));
+        self.with_mut(|x| {
+            *x.iter = x.map.range((lower, x.upper.clone()));
+            *x.item = TxnLocalIterator::entry_to_item(x.iter.next());
+        });
+        Ok(())
+    }
+}
+
 pub struct TxnIterator {
     txn: Arc<Transaction>,
     iter: TwoMergeIterator<TxnLocalIterator, FusedIterator<LsmIterator>>,
+    cancel: CancelHandle,
+    corrupted: Option<CorruptionReport>,
 }
 
 impl TxnIterator {
+    /// Create an iterator over the merged local and storage iterators, where `cancel` aborts the storage iterator.
     pub fn create(
         txn: Arc<Transaction>,
         iter: TwoMergeIterator<TxnLocalIterator, FusedIterator<LsmIterator>>,
+        cancel: CancelHandle,
     ) -> Result<Self> {
-        let mut iter = Self { txn, iter };
+        let mut iter = Self {
+            txn,
+            iter,
+            cancel,
+            corrupted: None,
+        };
         iter.skip_deletes()?;
         if iter.is_valid() {
             iter.add_to_read_set(iter.key());
@@ -249,6 +588,19 @@ impl TxnIterator {
         Ok(iter)
     }
 
+    /// A handle to abort the scan from another thread, e.g., when a server gives up on an abandoned query.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    /// The SST blocks the scan skipped so far as corrupted, see `ReadOptions::skip_corrupted_blocks`.
+    pub fn corrupted_blocks(&self) -> Vec<CorruptedBlock> {
+        self.corrupted
+            .as_ref()
+            .map(CorruptionReport::blocks)
+            .unwrap_or_default()
+    }
+
     fn skip_deletes(&mut self) -> Result<()> {
         while self.iter.is_valid() && self.iter.value().is_empty() {
             self.iter.next()?;
@@ -296,3 +648,17 @@ impl StorageIterator for TxnIterator {
         self.iter.num_active_iterators()
     }
 }
+
+impl SeekableIterator for TxnIterator {
+    fn seek(&mut self, key: &[u8]) -> Result<()> {
+        if !self.is_valid() || self.key() >= key {
+            return Ok(());
+        }
+        self.iter.seek(key)?;
+        self.skip_deletes()?;
+        if self.is_valid() {
+            self.add_to_read_set(self.key());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Paginated scans for list APIs: each page carries a token that resumes the scan at the same snapshot.
+//!
+//! No reader holds the snapshot between pages, so each page leases it for `LsmStorageOptions::scan_page_ttl`, keeping
+//! compaction from garbage collecting the versions the next page reads.
+
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::time::Instant;
+
+use anyhow::{Result, bail};
+use bytes::{Buf, BufMut, Bytes};
+use parking_lot::Mutex;
+
+use crate::iterators::StorageIterator;
+use crate::lsm_error::{self, Error};
+use crate::lsm_storage::{LsmStorageInner, MiniLsm, ReadOptions};
+
+/// A page of a scan returned by `MiniLsm::scan_page`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScanPage {
+    pub entries: Vec<(Bytes, Bytes)>,
+    /// An opaque token to pass to `MiniLsm::scan_next_page` for the rest of the range, or `None` if this is the last
+    /// page.
+    pub continuation: Option<Bytes>,
+}
+
+/// The snapshots leased by paginated scans and when the leases expire. Each one is registered as a reader in the
+/// watermark until it expires.
+#[derive(Default)]
+pub(crate) struct PageLeases {
+    expires: Mutex<BTreeMap<u64, Instant>>,
+}
+
+impl LsmStorageInner {
+    /// Keep the snapshot at `read_ts` for another `scan_page_ttl`. The caller must hold a reader at `read_ts`.
+    fn renew_page_lease(&self, read_ts: u64) {
+        let mut ts = self.mvcc().ts.lock();
+        let expires = Instant::now() + self.options().scan_page_ttl;
+        self.page_leases
+            .expires
+            .lock()
+            .entry(read_ts)
+            .and_modify(|x| *x = expires)
+            .or_insert_with(|| {
+                ts.1.add_reader(read_ts);
+                expires
+            });
+    }
+
+    /// Release the snapshots whose leases expired.
+    pub(crate) fn expire_page_leases(&self) {
+        let mut ts = self.mvcc().ts.lock();
+        let now = Instant::now();
+        self.page_leases.expires.lock().retain(|read_ts, expires| {
+            if *expires <= now {
+                ts.1.remove_reader(*read_ts);
+            }
+            *expires > now
+        });
+    }
+}
+
+/// Where a scan stopped: the last key returned and the snapshot the scan reads.
+struct Continuation {
+    read_ts: u64,
+    last_key: Bytes,
+}
+
+impl Continuation {
+    fn encode(&self) -> Bytes {
+        let mut buf = Vec::with_capacity(std::mem::size_of::<u64>() + self.last_key.len());
+        buf.put_u64(self.read_ts);
+        buf.put_slice(&self.last_key);
+        buf.into()
+    }
+
+    fn decode(mut token: &[u8]) -> Result<Self> {
+        if token.len() <= std::mem::size_of::<u64>() {
+            bail!(Error::InvalidArgument(
+                "invalid continuation token".to_string()
+            ));
+        }
+        Ok(Self {
+            read_ts: token.get_u64(),
+            last_key: Bytes::copy_from_slice(token),
+        })
+    }
+}
+
+impl MiniLsm {
+    /// Scan up to `limit` entries of the range from the latest snapshot. If more entries remain, the page holds a
+    /// continuation token to fetch them with `scan_next_page`.
+    pub fn scan_page(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        limit: usize,
+    ) -> lsm_error::Result<ScanPage> {
+        Ok(self.scan_page_inner(lower, upper, None, limit)?)
+    }
+
+    /// Scan up to `limit` entries after the previous page, at the snapshot of the first page, so that the pages are
+    /// consistent with each other. `upper` should be the same as for the first page. Fails with
+    /// `Error::InvalidArgument` if the snapshot has been garbage collected, which may happen once the previous page is
+    /// `LsmStorageOptions::scan_page_ttl` old.
+    pub fn scan_next_page(
+        &self,
+        continuation: &[u8],
+        upper: Bound<&[u8]>,
+        limit: usize,
+    ) -> lsm_error::Result<ScanPage> {
+        let continuation = Continuation::decode(continuation)?;
+        Ok(self.scan_page_inner(
+            Bound::Excluded(&continuation.last_key),
+            upper,
+            Some(continuation.read_ts),
+            limit,
+        )?)
+    }
+
+    fn scan_page_inner(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        snapshot: Option<u64>,
+        limit: usize,
+    ) -> Result<ScanPage> {
+        if limit == 0 {
+            bail!(Error::InvalidArgument(
+                "page limit must be positive".to_string()
+            ));
+        }
+        let txn = self.inner.txn_for_read(&ReadOptions {
+            snapshot,
+            ..Default::default()
+        })?;
+        self.inner.expire_page_leases();
+        let mut iter = txn.scan(lower, upper)?;
+        let mut entries = Vec::new();
+        while entries.len() < limit && iter.is_valid() {
+            entries.push((
+                Bytes::copy_from_slice(iter.key()),
+                Bytes::copy_from_slice(iter.value()),
+            ));
+            iter.next()?;
+        }
+        let continuation = iter.is_valid().then(|| {
+            self.inner.renew_page_lease(txn.read_ts());
+            Continuation {
+                read_ts: txn.read_ts(),
+                last_key: entries.last().unwrap().0.clone(),
+            }
+            .encode()
+        });
+        Ok(ScanPage {
+            entries,
+            continuation,
+        })
+    }
+}
@@ -0,0 +1,237 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A second tier of the block cache in files on a local disk, consulted when a block misses the in-memory cache before
+//! reading it from its SST. Blocks are keyed by the unique id of their SST rather than its id, so that the cache
+//! survives restarts, and each one is stored in its own file with a checksum. Each store keeps its blocks in a
+//! directory of its own named after the store and the format version, so that stores sharing the cache path never read
+//! each other's blocks or blocks of another format. When the cache is over its capacity, the least recently used
+//! blocks are removed.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use bytes::{Buf, BufMut};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::block::Block;
+use crate::checksum::block_checksum;
+use crate::manifest::FormatOptions;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PersistentCacheOptions {
+    /// The directory holding the cached blocks, ideally on a local SSD.
+    pub path: PathBuf,
+    /// The total size of the cached blocks of each store in bytes.
+    pub capacity: u64,
+}
+
+/// The unique id of an SST and the index of a block within it.
+type BlockKey = (u128, usize);
+
+#[derive(Default)]
+struct Index {
+    /// The size and the last use of each cached block.
+    blocks: HashMap<BlockKey, (u64, u64)>,
+    /// The cached blocks by their last use.
+    lru: BTreeMap<u64, BlockKey>,
+    /// The total size of the cached blocks.
+    size: u64,
+    /// Increases with each use of a block.
+    clock: u64,
+}
+
+impl Index {
+    fn touch(&mut self, key: BlockKey) -> bool {
+        let Some((_, used)) = self.blocks.get_mut(&key) else {
+            return false;
+        };
+        self.lru.remove(used);
+        self.clock += 1;
+        *used = self.clock;
+        self.lru.insert(self.clock, key);
+        true
+    }
+
+    fn insert(&mut self, key: BlockKey, size: u64) {
+        self.remove(key);
+        self.clock += 1;
+        self.blocks.insert(key, (size, self.clock));
+        self.lru.insert(self.clock, key);
+        self.size += size;
+    }
+
+    fn remove(&mut self, key: BlockKey) -> bool {
+        let Some((size, used)) = self.blocks.remove(&key) else {
+            return false;
+        };
+        self.lru.remove(&used);
+        self.size -= size;
+        true
+    }
+
+    /// Remove the least recently used blocks until the cache fits in `capacity`, returning them.
+    fn evict(&mut self, capacity: u64) -> Vec<BlockKey> {
+        let mut evicted = Vec::new();
+        while self.size > capacity {
+            let (_, key) = self.lru.pop_first().unwrap();
+            let (size, _) = self.blocks.remove(&key).unwrap();
+            self.size -= size;
+            evicted.push(key);
+        }
+        evicted
+    }
+}
+
+pub struct PersistentBlockCache {
+    options: PersistentCacheOptions,
+    /// The directory of the store under `options.path`.
+    dir: PathBuf,
+    index: Mutex<Index>,
+}
+
+impl PersistentBlockCache {
+    /// Open the cache of the store at `store_path` in `options.path`, creating its directory if needed and keeping the
+    /// blocks cached by a previous run, the most recently written last.
+    pub fn open(options: &PersistentCacheOptions, store_path: &Path) -> Result<Self> {
+        let store_path = store_path
+            .canonicalize()
+            .with_context(|| format!("failed to resolve store dir {store_path:?}"))?;
+        let dir = options.path.join(format!(
+            "v{}-{:016x}",
+            FormatOptions::FORMAT_VERSION,
+            farmhash::fingerprint64(store_path.as_os_str().as_encoded_bytes())
+        ));
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create persistent cache dir {dir:?}"))?;
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.ends_with(".tmp") {
+                // Left by a crash while inserting
+                std::fs::remove_file(entry.path())?;
+                continue;
+            }
+            let Some(key) = parse_file_name(&name) else {
+                continue;
+            };
+            let metadata = entry.metadata()?;
+            files.push((metadata.modified()?, key, metadata.len()));
+        }
+        files.sort();
+        let mut index = Index::default();
+        for (_, key, size) in files {
+            index.insert(key, size);
+        }
+        // The capacity may have been lowered since
+        let evicted = index.evict(options.capacity);
+        let cache = Self {
+            options: options.clone(),
+            dir,
+            index: Mutex::new(index),
+        };
+        for key in evicted {
+            cache.remove_file(key);
+        }
+        Ok(cache)
+    }
+
+    /// The directory holding the cached blocks of the store.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path_of(&self, (unique_id, block_idx): BlockKey) -> PathBuf {
+        self.dir.join(format!("{unique_id:032x}-{block_idx}.block"))
+    }
+
+    fn remove_file(&self, key: BlockKey) {
+        std::fs::remove_file(self.path_of(key)).ok();
+    }
+
+    /// Get block `block_idx` of the SST with `unique_id`. A block that cannot be read or fails its checksum is
+    /// removed from the cache and reported as missing.
+    pub fn get(&self, unique_id: u128, block_idx: usize) -> Option<Arc<Block>> {
+        let key = (unique_id, block_idx);
+        if !self.index.lock().touch(key) {
+            return None;
+        }
+        match read_block_file(&self.path_of(key)) {
+            Some(block) => Some(Arc::new(block)),
+            None => {
+                if self.index.lock().remove(key) {
+                    self.remove_file(key);
+                }
+                None
+            }
+        }
+    }
+
+    /// Add block `block_idx` of the SST with `unique_id`, evicting the least recently used blocks if the cache is
+    /// full.
+    pub fn insert(&self, unique_id: u128, block_idx: usize, block: &Block) -> Result<()> {
+        let key = (unique_id, block_idx);
+        let mut data = block.encode().to_vec();
+        data.put_u32(block_checksum(&data));
+        let path = self.path_of(key);
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, &data)?;
+        std::fs::rename(&tmp_path, &path)?;
+        let evicted = {
+            let mut index = self.index.lock();
+            index.insert(key, data.len() as u64);
+            index.evict(self.options.capacity)
+        };
+        for key in evicted {
+            self.remove_file(key);
+        }
+        Ok(())
+    }
+
+    /// The number of cached blocks.
+    pub fn len(&self) -> usize {
+        self.index.lock().blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The total size of the cached blocks in bytes.
+    pub fn size(&self) -> u64 {
+        self.index.lock().size
+    }
+}
+
+fn parse_file_name(name: &str) -> Option<BlockKey> {
+    let (unique_id, block_idx) = name.strip_suffix(".block")?.split_once('-')?;
+    Some((
+        u128::from_str_radix(unique_id, 16).ok()?,
+        block_idx.parse().ok()?,
+    ))
+}
+
+fn read_block_file(path: &Path) -> Option<Block> {
+    let data = std::fs::read(path).ok()?;
+    let len = data.len().checked_sub(4)?;
+    if (&data[len..]).get_u32() != block_checksum(&data[..len]) {
+        return None;
+    }
+    Some(Block::decode(&data[..len]))
+}
@@ -0,0 +1,100 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! File operations whose semantics differ between platforms.
+//!
+//! - Reads of SSTs are positional, so that concurrent readers can share a handle.
+//! - A file can only be synced through a handle opened for writing on Windows, where syncing is `FlushFileBuffers`.
+//! - Directories can only be opened with `FILE_FLAG_BACKUP_SEMANTICS` on Windows, and are synced through a handle
+//!   opened for writing like files.
+//! - Renames replace the target atomically on every platform: `std::fs::rename` is `MoveFileExW` with
+//!   `MOVEFILE_REPLACE_EXISTING` on Windows.
+//! - SSTs are opened with `FILE_SHARE_DELETE` on Windows, so that compaction can delete an SST that an iterator is
+//!   still reading. The file goes away once the last handle is closed, as with unlinking on unix.
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+#[cfg(unix)]
+pub(crate) fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    use std::os::unix::fs::FileExt;
+    file.read_exact_at(buf, offset)
+}
+
+/// `seek_read` may return fewer bytes than asked for, and moves the cursor of the handle, which is never used otherwise.
+#[cfg(windows)]
+pub(crate) fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Targets without stable positional reads, e.g., wasm32-wasi, seek the shared handle instead, one read at a time.
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn read_exact_at(mut file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    use std::io::{Read, Seek, SeekFrom};
+    static SEEK_LOCK: parking_lot::Mutex<()> = parking_lot::Mutex::new(());
+    let _guard = SEEK_LOCK.lock();
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(buf)
+}
+
+/// Open a file for reading that can still be deleted or renamed while open.
+pub(crate) fn open_read_only(path: &Path) -> io::Result<File> {
+    let mut options = File::options();
+    options.read(true);
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        // FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE
+        options.share_mode(0x1 | 0x2 | 0x4);
+    }
+    options.open(path)
+}
+
+/// Write `data` to a new file at `path`, replacing any existing one, and sync it through the same handle.
+pub(crate) fn write_synced(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(data)?;
+    file.sync_all()
+}
+
+/// Make the creation, deletion and renaming of the files in `dir` durable.
+pub(crate) fn sync_dir(dir: &Path) -> io::Result<()> {
+    #[cfg(not(windows))]
+    File::open(dir)?.sync_all()?;
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        // A directory can only be opened with FILE_FLAG_BACKUP_SEMANTICS, and flushed with write access.
+        File::options()
+            .write(true)
+            .share_mode(0x1 | 0x2 | 0x4)
+            .custom_flags(0x0200_0000)
+            .open(dir)?
+            .sync_all()?;
+    }
+    Ok(())
+}
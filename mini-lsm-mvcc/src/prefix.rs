@@ -0,0 +1,73 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Prefix extractors, which map a key to the prefix that groups it with others, e.g., a tenant or a table. The one in
+//! `LsmStorageOptions::prefix_extractor` adds the prefixes of the keys to the bloom filters of the SSTs, so that scans
+//! within a prefix skip the SSTs without it, and groups the hot keys into hot prefixes.
+
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+pub trait PrefixExtractor: Send + Sync + Debug {
+    /// Identifies the extractor and its parameters. It is recorded in the SSTs whose filters hold the prefixes, so
+    /// that the filters are not trusted by another extractor.
+    fn name(&self) -> String;
+
+    /// The prefix of `key`, or `None` if it has none, e.g., because it is too short. Every key starting with the prefix
+    /// of a key must have the same prefix, which lets a scan within a prefix rely on the filters.
+    fn prefix<'a>(&self, key: &'a [u8]) -> Option<&'a [u8]>;
+}
+
+/// The prefix extractor of the storage. The built-in extractors can be read from an options file, while a custom one
+/// cannot be serialized.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum PrefixExtractorOptions {
+    /// The first `n` bytes. Shorter keys have no prefix.
+    Fixed(usize),
+    /// The bytes up to and including the first occurrence of the delimiter. Keys without it have no prefix.
+    Delimited(u8),
+    #[serde(skip)]
+    Custom(Arc<dyn PrefixExtractor>),
+}
+
+impl PrefixExtractor for PrefixExtractorOptions {
+    fn name(&self) -> String {
+        match self {
+            PrefixExtractorOptions::Fixed(n) => format!("fixed:{n}"),
+            PrefixExtractorOptions::Delimited(delimiter) => format!("delimited:{delimiter}"),
+            PrefixExtractorOptions::Custom(extractor) => extractor.name(),
+        }
+    }
+
+    fn prefix<'a>(&self, key: &'a [u8]) -> Option<&'a [u8]> {
+        match self {
+            PrefixExtractorOptions::Fixed(n) => key.get(..*n),
+            PrefixExtractorOptions::Delimited(delimiter) => {
+                let end = key.iter().position(|byte| byte == delimiter)?;
+                Some(&key[..=end])
+            }
+            PrefixExtractorOptions::Custom(extractor) => extractor.prefix(key),
+        }
+    }
+}
+
+/// The smallest key greater than all keys starting with `prefix`, or `None` if there is none.
+pub fn prefix_successor(prefix: &[u8]) -> Option<Vec<u8>> {
+    let end = prefix.iter().rposition(|byte| *byte != u8::MAX)?;
+    let mut successor = prefix[..=end].to_vec();
+    successor[end] += 1;
+    Some(successor)
+}
@@ -0,0 +1,133 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Per-prefix (tenant) write quotas.
+//!
+//! Each quota is identified by a key prefix. Every write is charged to the quota with the longest prefix matching
+//! the key, using the same accounting as the memtable's approximate size (key with timestamp plus value). A quota
+//! with a limit rejects batches that would push its usage over the limit. Usage is kept in memory and starts from
+//! zero when the engine is opened.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use bytes::Bytes;
+use parking_lot::Mutex;
+
+use crate::lsm_error::Error;
+use crate::lsm_storage::WriteBatchRecord;
+
+/// The usage of a single prefix quota.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QuotaUsage {
+    pub prefix: Bytes,
+    /// Approximate number of bytes written to keys under this prefix since the engine was opened.
+    pub bytes_written: u64,
+    /// The maximum number of bytes that may be written, or `None` if the prefix is only tracked.
+    pub limit: Option<u64>,
+}
+
+/// The bytes a batch adds to each quota, charged once the batch is written.
+#[derive(Default)]
+pub(crate) struct QuotaCharges(BTreeMap<Bytes, u64>);
+
+#[derive(Default)]
+pub(crate) struct PrefixQuotas {
+    quotas: Mutex<BTreeMap<Bytes, QuotaUsage>>,
+}
+
+/// Find the quota with the longest prefix matching the key.
+fn quota_of<'a>(quotas: &'a BTreeMap<Bytes, QuotaUsage>, key: &[u8]) -> Option<&'a Bytes> {
+    quotas
+        .keys()
+        .filter(|prefix| key.starts_with(prefix))
+        .max_by_key(|prefix| prefix.len())
+}
+
+impl PrefixQuotas {
+    /// Track writes under `prefix`, optionally limiting them to `limit` bytes. Updating an existing quota keeps its
+    /// usage.
+    pub fn set_quota(&self, prefix: &[u8], limit: Option<u64>) {
+        let mut quotas = self.quotas.lock();
+        quotas
+            .entry(Bytes::copy_from_slice(prefix))
+            .and_modify(|usage| usage.limit = limit)
+            .or_insert_with(|| QuotaUsage {
+                prefix: Bytes::copy_from_slice(prefix),
+                bytes_written: 0,
+                limit,
+            });
+    }
+
+    pub fn remove_quota(&self, prefix: &[u8]) -> Option<QuotaUsage> {
+        self.quotas.lock().remove(prefix)
+    }
+
+    pub fn usage(&self, prefix: &[u8]) -> Option<QuotaUsage> {
+        self.quotas.lock().get(prefix).cloned()
+    }
+
+    pub fn all_usage(&self) -> Vec<QuotaUsage> {
+        self.quotas.lock().values().cloned().collect()
+    }
+
+    /// The charges of a batch to the matching quotas, or `Error::QuotaExceeded` if the batch does not fit in one of
+    /// them. Nothing is charged until the batch is written and `charge` is called.
+    pub(crate) fn check<T: AsRef<[u8]>>(
+        &self,
+        batch: &[WriteBatchRecord<T>],
+    ) -> Result<QuotaCharges> {
+        let quotas = self.quotas.lock();
+        if quotas.is_empty() {
+            return Ok(QuotaCharges::default());
+        }
+        let mut charges: BTreeMap<Bytes, u64> = BTreeMap::new();
+        for record in batch {
+            let (key, value_len) = match record {
+                WriteBatchRecord::Put(key, value) => (key.as_ref(), value.as_ref().len()),
+                WriteBatchRecord::Del(key) => (key.as_ref(), 0),
+            };
+            if let Some(prefix) = quota_of(&quotas, key) {
+                *charges.entry(prefix.clone()).or_default() +=
+                    (key.len() + std::mem::size_of::<u64>() + value_len) as u64;
+            }
+        }
+        for (prefix, charge) in &charges {
+            let usage = &quotas[prefix];
+            if let Some(limit) = usage
+                .limit
+                .filter(|limit| usage.bytes_written + charge > *limit)
+            {
+                return Err(Error::QuotaExceeded {
+                    prefix: prefix.clone(),
+                    usage: usage.bytes_written,
+                    limit,
+                }
+                .into());
+            }
+        }
+        Ok(QuotaCharges(charges))
+    }
+
+    /// Charge a written batch with the charges returned by `check`.
+    pub(crate) fn charge(&self, charges: QuotaCharges) {
+        let mut quotas = self.quotas.lock();
+        for (prefix, charge) in charges.0 {
+            // The quota may have been removed since
+            if let Some(usage) = quotas.get_mut(&prefix) {
+                usage.bytes_written += charge;
+            }
+        }
+    }
+}
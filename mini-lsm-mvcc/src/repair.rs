@@ -0,0 +1,235 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Utilities to remove a storage, and to rebuild its manifest from the SSTs left in the directory.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+
+use crate::compact::CompactionOptions;
+use crate::iterators::StorageIterator;
+use crate::key::KeyBytes;
+use crate::lsm_error;
+use crate::lsm_storage::{LsmStorageInner, LsmStorageOptions, LsmStorageState, MiniLsm};
+use crate::manifest::{Manifest, ManifestRecord};
+use crate::mvcc::prepared::PREPARED_SUFFIX;
+use crate::platform;
+use crate::table::{FileObject, SsTable, SsTableIterator};
+use crate::wal::RECYCLED_WAL_SUFFIX;
+
+const MANIFEST_NAME: &str = "MANIFEST";
+/// The name the manifest is moved to when it is replaced by repair.
+const OLD_MANIFEST_NAME: &str = "MANIFEST.old";
+/// The suffix appended to SSTs that repair could not read.
+const CORRUPT_SUFFIX: &str = ".corrupt";
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RepairSummary {
+    /// The SSTs in the rebuilt manifest.
+    pub sst_ids: Vec<usize>,
+    /// The WALs whose memtables are replayed on the next open.
+    pub wal_ids: Vec<usize>,
+    /// The SSTs that failed validation, renamed with a `.corrupt` suffix.
+    pub corrupt_files: Vec<PathBuf>,
+}
+
+/// The id of an engine file named `<id>.<extension>`.
+fn file_id(path: &Path, extension: &str) -> Option<usize> {
+    if path.extension()? != extension {
+        return None;
+    }
+    path.file_stem()?.to_str()?.parse().ok()
+}
+
+fn is_engine_file(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    name == MANIFEST_NAME
+        || name == OLD_MANIFEST_NAME
+        || file_id(path, "sst").is_some()
+        || file_id(path, "wal").is_some()
+        || name
+            .strip_suffix(CORRUPT_SUFFIX)
+            .is_some_and(|name| file_id(Path::new(name), "sst").is_some())
+        || name
+            .strip_suffix(RECYCLED_WAL_SUFFIX)
+            .is_some_and(|name| file_id(Path::new(name), "wal").is_some())
+        || name.ends_with(PREPARED_SUFFIX)
+}
+
+/// Open the SST and read every block, so that a damaged SST is rejected before it is referenced by the manifest.
+fn validate_sst(id: usize, path: &Path) -> Result<Arc<SsTable>> {
+    let table = Arc::new(SsTable::open(id, None, FileObject::open(path)?)?);
+    let mut iter = SsTableIterator::create_and_seek_to_first(table.clone())?;
+    while iter.is_valid() {
+        iter.next()?;
+    }
+    Ok(table)
+}
+
+/// Split the SSTs into sorted runs of non-overlapping SSTs. Each SST goes into the first run it does not overlap,
+/// visiting SSTs from the oldest to the newest, so that the first runs hold the older data of lower levels.
+fn split_into_runs(tables: &[Arc<SsTable>]) -> Vec<Vec<usize>> {
+    let mut runs: Vec<Vec<(KeyBytes, KeyBytes, usize)>> = Vec::new();
+    for table in tables {
+        let range = (
+            table.first_key().clone(),
+            table.last_key().clone(),
+            table.sst_id(),
+        );
+        let overlaps = |run: &Vec<(KeyBytes, KeyBytes, usize)>| {
+            run.iter().any(|(first, last, _)| {
+                range.0.key_ref() <= last.key_ref() && first.key_ref() <= range.1.key_ref()
+            })
+        };
+        match runs.iter_mut().find(|run| !overlaps(run)) {
+            Some(run) => run.push(range),
+            None => runs.push(vec![range]),
+        }
+    }
+    runs.into_iter()
+        .map(|mut run| {
+            run.sort_by(|x, y| x.0.cmp(&y.0));
+            run.into_iter().map(|(_, _, id)| id).collect()
+        })
+        .collect()
+}
+
+/// Place the runs, from the oldest to the newest, into the levels of the compaction strategy. Runs that do not fit
+/// into a level go to L0.
+fn layout_runs(
+    options: &LsmStorageOptions,
+    runs: Vec<Vec<usize>>,
+) -> (Vec<usize>, Vec<(usize, Vec<usize>)>) {
+    let mut levels = LsmStorageState::create(options).levels;
+    let mut runs = runs.into_iter();
+    match &options.compaction_options {
+        CompactionOptions::Leveled(_)
+        | CompactionOptions::Simple(_)
+        | CompactionOptions::NoCompaction => {
+            for (_, ssts) in levels.iter_mut().rev() {
+                match runs.next() {
+                    Some(run) => *ssts = run,
+                    None => break,
+                }
+            }
+        }
+        CompactionOptions::LazyLeveled(options) => {
+            if let Some(run) = runs.next() {
+                levels.push((options.max_levels, run));
+            }
+        }
+        CompactionOptions::Tiered(_) => {
+            levels = runs.by_ref().map(|run| (run[0], run)).collect();
+            levels.reverse();
+        }
+    }
+    let mut l0_sstables = runs.flatten().collect::<Vec<_>>();
+    l0_sstables.sort_by(|x, y| y.cmp(x));
+    (l0_sstables, levels)
+}
+
+impl MiniLsm {
+    /// Remove all files owned by the storage at `path`, and the directory itself if nothing else is left in it. The
+    /// storage must not be open.
+    pub fn destroy(path: impl AsRef<Path>) -> lsm_error::Result<()> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(());
+        }
+        for entry in std::fs::read_dir(path)? {
+            let entry_path = entry?.path();
+            if entry_path.is_file() && is_engine_file(&entry_path) {
+                std::fs::remove_file(&entry_path)?;
+            }
+        }
+        if std::fs::read_dir(path)?.next().is_none() {
+            std::fs::remove_dir(path)?;
+        }
+        Ok(())
+    }
+
+    /// Rebuild the manifest of the storage at `path` from the SSTs and WALs in the directory, e.g., after the
+    /// manifest is corrupted. SSTs are placed into the levels of the compaction strategy in `options` by their key
+    /// ranges; since every key carries its commit timestamp, the placement does not change what reads return. The
+    /// old manifest is kept as `MANIFEST.old`, and the storage must not be open.
+    pub fn repair(
+        path: impl AsRef<Path>,
+        options: &LsmStorageOptions,
+    ) -> lsm_error::Result<RepairSummary> {
+        Ok(repair(path.as_ref(), options)?)
+    }
+}
+
+fn repair(path: &Path, options: &LsmStorageOptions) -> Result<RepairSummary> {
+    options.validate()?;
+    let mut summary = RepairSummary::default();
+    let mut sst_ids = BTreeSet::new();
+    let mut wal_ids = BTreeSet::new();
+    for entry in std::fs::read_dir(path).context("failed to read DB dir")? {
+        let entry_path = entry?.path();
+        if let Some(id) = file_id(&entry_path, "sst") {
+            sst_ids.insert(id);
+        } else if let Some(id) = file_id(&entry_path, "wal") {
+            wal_ids.insert(id);
+        }
+    }
+
+    let mut tables = Vec::new();
+    for id in sst_ids {
+        let sst_path = LsmStorageInner::path_of_sst_static(path, id);
+        match validate_sst(id, &sst_path) {
+            Ok(table) => tables.push(table),
+            Err(_) => {
+                let mut corrupt_path = sst_path.clone().into_os_string();
+                corrupt_path.push(CORRUPT_SUFFIX);
+                std::fs::rename(&sst_path, &corrupt_path)?;
+                summary.corrupt_files.push(corrupt_path.into());
+            }
+        }
+    }
+    summary.sst_ids = tables.iter().map(|table| table.sst_id()).collect();
+    // A WAL whose memtable has been flushed shares its id with the SST
+    summary.wal_ids = wal_ids
+        .into_iter()
+        .filter(|id| !summary.sst_ids.contains(id))
+        .collect();
+
+    let (l0_sstables, levels) = layout_runs(options, split_into_runs(&tables));
+    let manifest_path = path.join(MANIFEST_NAME);
+    if manifest_path.exists() {
+        std::fs::rename(&manifest_path, path.join(OLD_MANIFEST_NAME))?;
+    }
+    let manifest = Manifest::create(&manifest_path)?;
+    let mut records = vec![
+        ManifestRecord::Options(options.format_options()),
+        ManifestRecord::Repair {
+            l0_sstables,
+            levels,
+        },
+    ];
+    records.extend(
+        summary
+            .wal_ids
+            .iter()
+            .map(|id| ManifestRecord::NewMemtable(*id)),
+    );
+    manifest.add_records_when_init(&records)?;
+    platform::sync_dir(path)?;
+    Ok(summary)
+}
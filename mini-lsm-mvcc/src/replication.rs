@@ -0,0 +1,433 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Physical leader/follower replication.
+//!
+//! A [`ReplicationSession`] on the leader tails its manifest and WAL files, and produces [`ReplicationBatch`]es holding
+//! new manifest records, the SSTs they add, and the WAL batches written since the last poll. A [`Replica`] applies
+//! the batches to its own directory, which ends up as a copy of the leader's files, and serves reads from them. The
+//! batches are serializable, so the session and the replica can live on different machines, connected by any
+//! [`ReplicationSource`].
+//!
+//! The replica only sees what the leader has written to its files: WAL batches are buffered by the leader until
+//! `sync` or a memtable freeze. A replica always starts from an empty directory, and the directory can be opened with
+//! `MiniLsm::open` to promote it once the leader is gone.
+
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{Context, Result, bail};
+use bytes::Bytes;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::compact::CompactionController;
+use crate::key::KeySlice;
+use crate::lsm_error::{self, Error};
+use crate::lsm_iterator::{FusedIterator, LsmIterator};
+use crate::lsm_storage::{LsmStorageInner, LsmStorageOptions, LsmStorageState, MiniLsm};
+use crate::manifest::{Manifest, ManifestReplay};
+use crate::mem_table::MemTable;
+use crate::table::{FileObject, SsTable};
+use crate::wal::Wal;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReplicationEvent {
+    /// The content of a new SST, sent before the manifest records referencing it.
+    Sst { id: usize, data: Vec<u8> },
+    /// Records appended to the manifest, in their on-disk encoding.
+    Manifest { data: Vec<u8> },
+    /// Complete batches appended to the WAL of memtable `id` at `offset`.
+    Wal {
+        id: usize,
+        offset: u64,
+        data: Vec<u8>,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplicationBatch {
+    pub events: Vec<ReplicationEvent>,
+    /// The latest commit timestamp of the leader when the batch was produced.
+    pub leader_commit_ts: u64,
+}
+
+/// A stream of replication batches, e.g., a network connection to a leader running a `ReplicationSession`.
+pub trait ReplicationSource {
+    /// Return everything written to the leader since the last call.
+    fn poll(&mut self) -> Result<ReplicationBatch>;
+}
+
+/// Tails the files of a leader for a single replica.
+pub struct ReplicationSession {
+    inner: Arc<LsmStorageInner>,
+    manifest_offset: u64,
+    /// The number of manifest rotations as of the last poll.
+    manifest_rotations: u64,
+    replay: ManifestReplay,
+    /// The SSTs that have been sent and are still in the LSM tree.
+    sent_ssts: BTreeSet<usize>,
+    /// The WAL offsets that have been sent, for each memtable that has not been flushed.
+    wal_offsets: BTreeMap<usize, u64>,
+}
+
+impl MiniLsm {
+    /// Start a replication session, whose first poll returns everything needed to build a replica from scratch.
+    pub fn replication_session(&self) -> ReplicationSession {
+        ReplicationSession {
+            inner: self.inner.clone(),
+            manifest_offset: 0,
+            manifest_rotations: self.inner.manifest().rotations(),
+            replay: ManifestReplay::new(LsmStorageState::create(&self.inner.options()), 1),
+            sent_ssts: BTreeSet::new(),
+            wal_offsets: BTreeMap::new(),
+        }
+    }
+}
+
+/// Read `path` from `offset` to the end, or return `None` if the file does not exist.
+fn read_from(path: &Path, offset: u64) -> Result<Option<Vec<u8>>> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    file.seek(SeekFrom::Start(offset))?;
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)?;
+    Ok(Some(data))
+}
+
+impl ReplicationSession {
+    /// Read the manifest records that have not been sent, along with the SSTs in the LSM tree after applying them.
+    /// Returns `None` if an SST has been removed in the meantime, in which case the manifest has a newer record
+    /// removing it.
+    fn read_manifest(&self) -> Result<Option<(ManifestReplay, Vec<ReplicationEvent>)>> {
+        let mut data = read_from(&self.inner.path_of_manifest(), self.manifest_offset)?
+            .context("manifest does not exist")?;
+        let mut buf = data.as_slice();
+        let mut replay = self.replay.clone();
+        while let Some(record) = Manifest::decode_record(&mut buf)? {
+            replay.apply(&self.inner.compaction_controller(), record);
+        }
+        // The leader may be in the middle of appending a record.
+        data.truncate(data.len() - buf.len());
+        let mut events = Vec::new();
+        for id in replay.live_ssts().difference(&self.sent_ssts) {
+            let Some(data) = read_from(&self.inner.path_of_sst(*id), 0)? else {
+                return Ok(None);
+            };
+            events.push(ReplicationEvent::Sst { id: *id, data });
+        }
+        if !data.is_empty() {
+            events.push(ReplicationEvent::Manifest { data });
+        }
+        Ok(Some((replay, events)))
+    }
+}
+
+impl ReplicationSource for ReplicationSession {
+    fn poll(&mut self) -> Result<ReplicationBatch> {
+        let leader_commit_ts = self.inner.mvcc().latest_commit_ts();
+        let mut last_manifest_size = None;
+        let (replay, mut events) = loop {
+            let rotations = self.inner.manifest().rotations();
+            if rotations != self.manifest_rotations {
+                // The manifest has been replaced by a snapshot, which is sent from the start
+                self.manifest_offset = 0;
+                self.replay =
+                    ManifestReplay::new(LsmStorageState::create(&self.inner.options()), 1);
+                self.manifest_rotations = rotations;
+            }
+            let manifest_size = self.inner.path_of_manifest().metadata()?.len();
+            let manifest = self.read_manifest()?;
+            if self.inner.manifest().rotations() != rotations {
+                continue;
+            }
+            if let Some((replay, events)) = manifest {
+                break (replay, events);
+            }
+            if last_manifest_size == Some(manifest_size) {
+                bail!(Error::Corruption(
+                    "an SST in the LSM tree does not exist".to_string()
+                ));
+            }
+            last_manifest_size = Some(manifest_size);
+        };
+        for event in &events {
+            match event {
+                ReplicationEvent::Sst { id, .. } => {
+                    self.sent_ssts.insert(*id);
+                }
+                ReplicationEvent::Manifest { data } => self.manifest_offset += data.len() as u64,
+                ReplicationEvent::Wal { .. } => unreachable!(),
+            }
+        }
+        let live_ssts = replay.live_ssts();
+        self.sent_ssts.retain(|id| live_ssts.contains(id));
+        self.wal_offsets
+            .retain(|id, _| replay.memtables.contains(id));
+        self.replay = replay;
+
+        for id in &self.replay.memtables {
+            let offset = self.wal_offsets.entry(*id).or_default();
+            // The WAL is removed right before the memtable is recorded as flushed.
+            let Some(mut data) = read_from(&self.inner.path_of_wal(*id), *offset)? else {
+                continue;
+            };
+            let mut buf = data.as_slice();
+            while Wal::decode_batch(&mut buf)?.is_some() {}
+            data.truncate(data.len() - buf.len());
+            if data.is_empty() {
+                continue;
+            }
+            events.push(ReplicationEvent::Wal {
+                id: *id,
+                offset: *offset,
+                data: data.clone(),
+            });
+            *offset += data.len() as u64;
+        }
+        Ok(ReplicationBatch {
+            events,
+            leader_commit_ts,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplicationStats {
+    /// The latest commit timestamp of the leader as of the last applied batch.
+    pub leader_commit_ts: u64,
+    /// The latest commit timestamp visible on the replica.
+    pub replica_commit_ts: u64,
+    /// The number of bytes applied from all batches.
+    pub bytes_received: u64,
+}
+
+impl ReplicationStats {
+    /// The number of commits the replica is behind the leader.
+    pub fn lag(&self) -> u64 {
+        self.leader_commit_ts.saturating_sub(self.replica_commit_ts)
+    }
+}
+
+struct ReplicaState {
+    replay: ManifestReplay,
+    manifest: File,
+    sstables: HashMap<usize, Arc<SsTable>>,
+    memtables: BTreeMap<usize, Arc<MemTable>>,
+    stats: ReplicationStats,
+}
+
+/// A read-only copy of a leader, kept up to date by applying replication batches.
+pub struct Replica {
+    inner: Arc<LsmStorageInner>,
+    state: Mutex<ReplicaState>,
+    read_ts: AtomicU64,
+}
+
+impl Replica {
+    /// Create a replica in `path`, which must not exist or be empty. `options` must use the same compaction options
+    /// as the leader.
+    pub fn create(path: impl AsRef<Path>, options: LsmStorageOptions) -> lsm_error::Result<Self> {
+        let path = path.as_ref();
+        if path.exists() && path.read_dir()?.next().is_some() {
+            return Err(Error::InvalidArgument(format!(
+                "replica directory {} is not empty",
+                path.display()
+            )));
+        }
+        let inner = Arc::new(LsmStorageInner::new_replica(path, options)?);
+        let manifest = OpenOptions::new()
+            .create_new(true)
+            .append(true)
+            .open(inner.path_of_manifest())?;
+        let replay = ManifestReplay::new(LsmStorageState::create(&inner.options()), 1);
+        Ok(Self {
+            inner,
+            state: Mutex::new(ReplicaState {
+                replay,
+                manifest,
+                sstables: HashMap::new(),
+                memtables: BTreeMap::new(),
+                stats: ReplicationStats::default(),
+            }),
+            read_ts: AtomicU64::new(0),
+        })
+    }
+
+    /// Apply a batch produced by the leader's session. Batches must be applied in order, and all of them must come
+    /// from the same session.
+    pub fn apply(&self, batch: ReplicationBatch) -> lsm_error::Result<()> {
+        Ok(self.apply_inner(batch)?)
+    }
+
+    /// Poll `source` once and apply the batch.
+    pub fn catch_up(&self, source: &mut impl ReplicationSource) -> lsm_error::Result<()> {
+        self.apply(source.poll()?)
+    }
+
+    fn apply_inner(&self, batch: ReplicationBatch) -> Result<()> {
+        let inner = &self.inner;
+        let mut state = self.state.lock();
+        let mut read_ts = self.read_ts.load(Ordering::SeqCst);
+        let mut wal_events = Vec::new();
+        for event in batch.events {
+            match event {
+                ReplicationEvent::Sst { id, data } => {
+                    state.stats.bytes_received += data.len() as u64;
+                    let mut file = FileObject::create_in(
+                        &inner.options().file_system,
+                        &inner.path_of_sst(id),
+                        data,
+                    )?;
+                    file.set_io_retry(inner.io_retry.clone());
+                    let sst = SsTable::open(id, Some(inner.block_cache.clone()), file)?;
+                    read_ts = read_ts.max(sst.max_ts());
+                    state.sstables.insert(id, Arc::new(sst));
+                }
+                ReplicationEvent::Manifest { data } => {
+                    state.stats.bytes_received += data.len() as u64;
+                    state.manifest.write_all(&data)?;
+                    state.manifest.sync_all()?;
+                    let mut buf = data.as_slice();
+                    while let Some(record) = Manifest::decode_record(&mut buf)? {
+                        state.replay.apply(&inner.compaction_controller(), record);
+                    }
+                    if !buf.is_empty() {
+                        bail!(Error::Corruption(
+                            "incomplete manifest record in replication batch".to_string()
+                        ));
+                    }
+                }
+                ReplicationEvent::Wal { id, offset, data } => wal_events.push((id, offset, data)),
+            }
+        }
+
+        // Drop the SSTs and memtables that have been compacted or flushed on the leader.
+        let live_ssts = state.replay.live_ssts();
+        let removed_ssts = state
+            .sstables
+            .keys()
+            .filter(|id| !live_ssts.contains(id))
+            .copied()
+            .collect::<Vec<_>>();
+        for id in &removed_ssts {
+            state.sstables.remove(id);
+        }
+        let flushed_memtables = state
+            .memtables
+            .keys()
+            .filter(|id| !state.replay.memtables.contains(id))
+            .copied()
+            .collect::<Vec<_>>();
+        for id in &flushed_memtables {
+            state.memtables.remove(id);
+        }
+        for id in state.replay.memtables.clone() {
+            if let Entry::Vacant(entry) = state.memtables.entry(id) {
+                // Create the WAL even if nothing is replicated into it, so that the replica can be opened as a
+                // database.
+                File::create(inner.path_of_wal(id))?.sync_all()?;
+                entry.insert(Arc::new(MemTable::create(id)));
+            }
+        }
+        for (id, offset, data) in wal_events {
+            state.stats.bytes_received += data.len() as u64;
+            let Some(memtable) = state.memtables.get(&id) else {
+                continue;
+            };
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(inner.path_of_wal(id))?;
+            if file.metadata()?.len() != offset {
+                bail!(Error::Corruption(format!(
+                    "WAL of memtable {} is not replicated in order",
+                    id
+                )));
+            }
+            file.write_all(&data)?;
+            file.sync_all()?;
+            let mut buf = data.as_slice();
+            while let Some(kv_pairs) = Wal::decode_batch(&mut buf)? {
+                let batch = kv_pairs
+                    .iter()
+                    .map(|(key, ts, value)| (KeySlice::from_slice(key, *ts), value.as_ref()))
+                    .collect::<Vec<_>>();
+                memtable.put_batch(&batch)?;
+                read_ts = kv_pairs
+                    .iter()
+                    .fold(read_ts, |read_ts, (_, ts, _)| read_ts.max(*ts));
+            }
+        }
+
+        let mut snapshot = state.replay.state.clone();
+        for id in &live_ssts {
+            let sst = state
+                .sstables
+                .get(id)
+                .with_context(|| format!("SST {} was not replicated", id))?;
+            snapshot.sstables.insert(*id, sst.clone());
+        }
+        if let CompactionController::Leveled(_) = inner.compaction_controller().as_ref() {
+            snapshot.sort_levels_by_first_key();
+        }
+        let mut memtables = state.memtables.values().rev().cloned();
+        if let Some(memtable) = memtables.next() {
+            snapshot.memtable = memtable;
+        }
+        snapshot.imm_memtables = memtables.collect();
+        *inner.state.write() = Arc::new(snapshot);
+        self.read_ts.store(read_ts, Ordering::SeqCst);
+
+        for id in removed_ssts {
+            std::fs::remove_file(inner.path_of_sst(id))?;
+        }
+        for id in flushed_memtables {
+            std::fs::remove_file(inner.path_of_wal(id))?;
+        }
+        inner.sync_dir()?;
+        state.stats.leader_commit_ts = batch.leader_commit_ts;
+        state.stats.replica_commit_ts = read_ts;
+        Ok(())
+    }
+
+    pub fn get(&self, key: &[u8]) -> lsm_error::Result<Option<Bytes>> {
+        Ok(self
+            .inner
+            .get_with_ts(key, self.read_ts.load(Ordering::SeqCst))?)
+    }
+
+    pub fn scan(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+    ) -> lsm_error::Result<FusedIterator<LsmIterator>> {
+        Ok(self
+            .inner
+            .scan_with_ts(lower, upper, self.read_ts.load(Ordering::SeqCst))?)
+    }
+
+    pub fn stats(&self) -> ReplicationStats {
+        self.state.lock().stats
+    }
+}
@@ -0,0 +1,360 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A listener speaking the Redis serialization protocol (RESP2), so that existing Redis clients and benchmarking tools
+//! such as `redis-benchmark` and `memtier_benchmark` can drive the storage engine.
+//!
+//! Supported commands are `GET`, `SET key value`, `DEL key [key ...]`, `EXISTS key [key ...]`, `MGET key [key ...]`,
+//! `SCAN cursor [MATCH pattern] [COUNT count]`, `PING`, `ECHO`, `QUIT` and `COMMAND` (which returns an empty reply so
+//! that clients probing for command docs keep working). The `SCAN` cursor is the number of keys already visited, so
+//! each call skips over them again; keys written between calls may shift the window.
+
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::net::{TcpListener, TcpStream};
+use std::ops::Bound;
+use std::sync::Arc;
+
+use anyhow::{Result, bail};
+
+use crate::iterators::StorageIterator;
+use crate::lsm_error::Error;
+use crate::lsm_storage::MiniLsm;
+
+/// The largest bulk string accepted from a client, the same as the Redis default `proto-max-bulk-len`.
+const MAX_BULK_LEN: usize = 512 << 20;
+
+/// The largest number of arguments accepted in a single command.
+const MAX_ARGS: usize = 1 << 20;
+
+/// The number of keys returned by `SCAN` without a `COUNT`, the same as Redis.
+const DEFAULT_SCAN_COUNT: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RespValue {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Vec<RespValue>),
+}
+
+impl RespValue {
+    fn ok() -> Self {
+        RespValue::Simple("OK".to_string())
+    }
+
+    fn error(msg: impl AsRef<str>) -> Self {
+        RespValue::Error(format!("ERR {}", msg.as_ref()))
+    }
+
+    pub fn write_to(&self, w: &mut impl Write) -> std::io::Result<()> {
+        match self {
+            RespValue::Simple(s) => write!(w, "+{}\r\n", s),
+            // Error messages must not contain a line break.
+            RespValue::Error(s) => write!(w, "-{}\r\n", s.replace(['\r', '\n'], " ")),
+            RespValue::Integer(x) => write!(w, ":{}\r\n", x),
+            RespValue::Bulk(None) => w.write_all(b"$-1\r\n"),
+            RespValue::Bulk(Some(data)) => {
+                write!(w, "${}\r\n", data.len())?;
+                w.write_all(data)?;
+                w.write_all(b"\r\n")
+            }
+            RespValue::Array(items) => {
+                write!(w, "*{}\r\n", items.len())?;
+                for item in items {
+                    item.write_to(w)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Read a line terminated by `\r\n` (or `\n` for inline commands), without the terminator. Returns `None` on EOF.
+fn read_line(reader: &mut impl BufRead) -> Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    if reader.read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+    if line.pop() != Some(b'\n') {
+        bail!("unexpected end of stream");
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    Ok(Some(line))
+}
+
+fn parse_len(data: &[u8], max: usize) -> Result<usize> {
+    let len = std::str::from_utf8(data)
+        .ok()
+        .and_then(|x| x.parse::<usize>().ok())
+        .ok_or_else(|| anyhow::anyhow!("invalid length"))?;
+    if len > max {
+        bail!("length {} exceeds the limit of {}", len, max);
+    }
+    Ok(len)
+}
+
+/// Read a command from the client, either as an array of bulk strings or as an inline command separated by spaces.
+/// Returns `None` on EOF.
+pub fn read_command(reader: &mut impl BufRead) -> Result<Option<Vec<Vec<u8>>>> {
+    let Some(line) = read_line(reader)? else {
+        return Ok(None);
+    };
+    let Some(num_args) = line.strip_prefix(b"*") else {
+        return Ok(Some(
+            line.split(|c| c.is_ascii_whitespace())
+                .filter(|arg| !arg.is_empty())
+                .map(|arg| arg.to_vec())
+                .collect(),
+        ));
+    };
+    let num_args = parse_len(num_args, MAX_ARGS)?;
+    let mut args = Vec::with_capacity(num_args.min(1024));
+    for _ in 0..num_args {
+        let Some(line) = read_line(reader)? else {
+            bail!("unexpected end of stream");
+        };
+        let Some(len) = line.strip_prefix(b"$") else {
+            bail!("expected a bulk string");
+        };
+        let len = parse_len(len, MAX_BULK_LEN)?;
+        let mut arg = vec![0; len + 2];
+        reader.read_exact(&mut arg)?;
+        if !arg.ends_with(b"\r\n") {
+            bail!("bulk string is not terminated by CRLF");
+        }
+        arg.truncate(len);
+        args.push(arg);
+    }
+    Ok(Some(args))
+}
+
+/// Match `key` against a glob `pattern` supporting `*`, `?` and `\` escapes.
+fn glob_match(pattern: &[u8], key: &[u8]) -> bool {
+    let (mut p, mut k) = (0, 0);
+    // The position after the last `*` in the pattern, and the key position it is currently matched up to.
+    let mut backtrack = None;
+    while k < key.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                p += 1;
+                backtrack = Some((p, k));
+                continue;
+            }
+            Some(b'?') => {
+                p += 1;
+                k += 1;
+                continue;
+            }
+            Some(b'\\') if p + 1 < pattern.len() && pattern[p + 1] == key[k] => {
+                p += 2;
+                k += 1;
+                continue;
+            }
+            Some(&c) if c != b'\\' && c == key[k] => {
+                p += 1;
+                k += 1;
+                continue;
+            }
+            _ => {}
+        }
+        let Some((bp, bk)) = backtrack else {
+            return false;
+        };
+        p = bp;
+        k = bk + 1;
+        backtrack = Some((bp, bk + 1));
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+fn scan(lsm: &MiniLsm, args: &[Vec<u8>]) -> Result<RespValue, Error> {
+    let parse_number = |arg: &[u8]| {
+        std::str::from_utf8(arg)
+            .ok()
+            .and_then(|x| x.parse::<usize>().ok())
+    };
+    let Some(cursor) = parse_number(&args[0]) else {
+        return Ok(RespValue::error("invalid cursor"));
+    };
+    let mut pattern = None;
+    let mut count = DEFAULT_SCAN_COUNT;
+    for option in args[1..].chunks(2) {
+        let [name, value] = option else {
+            return Ok(RespValue::error("syntax error"));
+        };
+        if name.eq_ignore_ascii_case(b"MATCH") {
+            pattern = Some(value.as_slice());
+        } else if name.eq_ignore_ascii_case(b"COUNT") {
+            match parse_number(value) {
+                Some(n) if n > 0 => count = n,
+                _ => return Ok(RespValue::error("value is out of range")),
+            }
+        } else {
+            return Ok(RespValue::error("syntax error"));
+        }
+    }
+    let mut iter = lsm.scan(Bound::Unbounded, Bound::Unbounded)?;
+    let mut skipped = 0;
+    while iter.is_valid() && skipped < cursor {
+        skipped += 1;
+        iter.next()?;
+    }
+    // Like Redis, `COUNT` bounds the keys visited rather than the keys returned when there is a `MATCH`.
+    let mut keys = Vec::new();
+    let mut visited = 0;
+    while iter.is_valid() && visited < count {
+        if pattern.is_none_or(|pattern| glob_match(pattern, iter.key())) {
+            keys.push(RespValue::Bulk(Some(iter.key().to_vec())));
+        }
+        visited += 1;
+        iter.next()?;
+    }
+    let next_cursor = if iter.is_valid() { cursor + visited } else { 0 };
+    Ok(RespValue::Array(vec![
+        RespValue::Bulk(Some(next_cursor.to_string().into_bytes())),
+        RespValue::Array(keys),
+    ]))
+}
+
+/// Count the keys in `keys` that exist, optionally deleting them in the same transaction.
+fn count_existing(lsm: &MiniLsm, keys: &[Vec<u8>], delete: bool) -> Result<RespValue, Error> {
+    let txn = lsm.new_txn()?;
+    let mut cnt = 0;
+    for key in keys {
+        if txn.get(key)?.is_some() {
+            cnt += 1;
+            if delete {
+                txn.delete(key);
+            }
+        }
+    }
+    if delete && cnt > 0 {
+        txn.commit()?;
+    }
+    Ok(RespValue::Integer(cnt))
+}
+
+fn check_arity(args: &[Vec<u8>], min: usize, max: Option<usize>) -> Option<RespValue> {
+    if args.len() < min || max.is_some_and(|max| args.len() > max) {
+        Some(RespValue::error(format!(
+            "wrong number of arguments for '{}' command",
+            String::from_utf8_lossy(&args[0]).to_lowercase()
+        )))
+    } else {
+        None
+    }
+}
+
+/// Execute a single command. `args` includes the command name.
+pub fn handle_command(lsm: &MiniLsm, args: &[Vec<u8>]) -> RespValue {
+    let Some(name) = args.first() else {
+        return RespValue::error("empty command");
+    };
+    let name = String::from_utf8_lossy(name).to_ascii_uppercase();
+    let arity = match name.as_str() {
+        "PING" => (1, Some(2)),
+        "ECHO" | "GET" => (2, Some(2)),
+        "SET" => (3, Some(3)),
+        "DEL" | "EXISTS" | "MGET" | "SCAN" => (2, None),
+        "QUIT" | "COMMAND" => (1, None),
+        _ => {
+            return RespValue::error(format!(
+                "unknown command '{}'",
+                String::from_utf8_lossy(&args[0])
+            ));
+        }
+    };
+    if let Some(error) = check_arity(args, arity.0, arity.1) {
+        return error;
+    }
+    let result = match name.as_str() {
+        "PING" => Ok(match args.get(1) {
+            Some(msg) => RespValue::Bulk(Some(msg.clone())),
+            None => RespValue::Simple("PONG".to_string()),
+        }),
+        "ECHO" => Ok(RespValue::Bulk(Some(args[1].clone()))),
+        "QUIT" => Ok(RespValue::ok()),
+        "COMMAND" => Ok(RespValue::Array(Vec::new())),
+        "GET" => lsm
+            .get(&args[1])
+            .map(|value| RespValue::Bulk(value.map(|value| value.to_vec()))),
+        "SET" => lsm.put(&args[1], &args[2]).map(|_| RespValue::ok()),
+        "DEL" => count_existing(lsm, &args[1..], true),
+        "EXISTS" => count_existing(lsm, &args[1..], false),
+        "MGET" => args[1..]
+            .iter()
+            .map(|key| {
+                lsm.get(key)
+                    .map(|value| RespValue::Bulk(value.map(|value| value.to_vec())))
+            })
+            .collect::<Result<_, _>>()
+            .map(RespValue::Array),
+        "SCAN" => scan(lsm, &args[1..]),
+        _ => unreachable!(),
+    };
+    result.unwrap_or_else(|e| RespValue::error(e.to_string()))
+}
+
+fn handle_connection(lsm: &MiniLsm, stream: TcpStream) -> Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    loop {
+        let args = match read_command(&mut reader) {
+            Ok(Some(args)) => args,
+            Ok(None) => break,
+            Err(e) => {
+                RespValue::Error(format!("ERR Protocol error: {}", e)).write_to(&mut writer)?;
+                break;
+            }
+        };
+        if args.is_empty() {
+            continue;
+        }
+        handle_command(lsm, &args).write_to(&mut writer)?;
+        if args[0].eq_ignore_ascii_case(b"QUIT") {
+            break;
+        }
+        // Reply to pipelined commands in one go.
+        if reader.buffer().is_empty() {
+            writer.flush()?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Serve clients on `addr`, with a thread per connection, until the process exits.
+pub fn serve(lsm: Arc<MiniLsm>, addr: &str) -> Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    println!("RESP listening on {}", listener.local_addr()?);
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                println!("failed to accept a connection: {}", e);
+                continue;
+            }
+        };
+        let lsm = lsm.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = handle_connection(&lsm, stream) {
+                println!("connection closed: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,164 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A background scrubber that reads every block of every SST at a limited rate, so that silent corruption is found
+//! and the damaged SSTs are quarantined before a read runs into them. Compactions that would rewrite a quarantined SST
+//! fail as a background error until it is repaired.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use anyhow::Result;
+use parking_lot::Mutex;
+
+use crate::lsm_storage::{LsmStorageInner, MiniLsm};
+
+/// How long the scrubber waits before looking again when there is no SST to scrub.
+const IDLE_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ScrubReport {
+    /// The number of completed passes over all SSTs.
+    pub passes: u64,
+    /// The number of bytes read and validated.
+    pub bytes_scrubbed: u64,
+    /// The SSTs that failed validation, with the error. Quarantined SSTs are no longer scrubbed nor picked by
+    /// compaction, so that the damage is not rewritten into new SSTs; `MiniLsm::repair` removes them from the LSM
+    /// structure.
+    pub quarantined: BTreeMap<usize, String>,
+}
+
+#[derive(Default)]
+pub(crate) struct Scrubber {
+    /// The SST and the block within it to scrub next.
+    cursor: Mutex<(usize, usize)>,
+    report: Mutex<ScrubReport>,
+}
+
+impl LsmStorageInner {
+    pub(crate) fn is_quarantined(&self, sst_id: usize) -> bool {
+        self.scrubber
+            .report
+            .lock()
+            .quarantined
+            .contains_key(&sst_id)
+    }
+
+    /// Validate the block under the cursor and move the cursor to the next block, going over the SSTs in the order
+    /// of their ids. Returns the number of bytes read, or 0 if there is nothing to scrub.
+    pub(crate) fn scrub_next_block(&self) -> Result<u64> {
+        let snapshot = self.snapshot();
+        let mut cursor = self.scrubber.cursor.lock();
+        let (mut sst_id, mut block_idx) = *cursor;
+        let in_range = |sst_id: usize, block_idx: usize| {
+            snapshot
+                .sstables
+                .get(&sst_id)
+                .is_some_and(|table| block_idx < table.num_of_blocks())
+                && !self.is_quarantined(sst_id)
+        };
+        if !in_range(sst_id, block_idx) {
+            // The SST under the cursor is done, or has been compacted away meanwhile
+            let mut ids = snapshot
+                .sstables
+                .keys()
+                .copied()
+                .filter(|id| !self.is_quarantined(*id))
+                .collect::<Vec<_>>();
+            ids.sort();
+            let Some(first_id) = ids.first().copied() else {
+                return Ok(0);
+            };
+            sst_id = match ids.iter().find(|id| **id > sst_id) {
+                Some(id) => *id,
+                None => {
+                    self.scrubber.report.lock().passes += 1;
+                    first_id
+                }
+            };
+            block_idx = 0;
+        }
+
+        let table = &snapshot.sstables[&sst_id];
+        let offset = table.block_meta[block_idx].offset;
+        let offset_end = table
+            .block_meta
+            .get(block_idx + 1)
+            .map_or(table.block_meta_offset, |meta| meta.offset);
+        // Bypass the block cache, which may hold a copy read before the file was damaged
+        match table.read_block(block_idx) {
+            Ok(_) => *cursor = (sst_id, block_idx + 1),
+            Err(e) => {
+                eprintln!("scrub: quarantining SST {sst_id}: {e:#}");
+                self.scrubber
+                    .report
+                    .lock()
+                    .quarantined
+                    .insert(sst_id, format!("block {block_idx}: {e:#}"));
+                *cursor = (sst_id, table.num_of_blocks());
+            }
+        }
+        let bytes = (offset_end - offset) as u64;
+        self.scrubber.report.lock().bytes_scrubbed += bytes;
+        Ok(bytes)
+    }
+
+    pub(crate) fn spawn_scrub_thread(
+        self: &Arc<Self>,
+        rx: crossbeam_channel::Receiver<()>,
+    ) -> Result<Option<std::thread::JoinHandle<()>>> {
+        if self.options.scrub_bytes_per_sec.is_none() || !self.spawns_background_threads() {
+            return Ok(None);
+        }
+        let this = self.clone();
+        let handle = std::thread::spawn(move || {
+            loop {
+                // The rate may be changed or unset with `set_options` meanwhile
+                let rate = this.options().scrub_bytes_per_sec;
+                let bytes = if this.background_paused.load(Ordering::SeqCst) || rate.is_none() {
+                    0
+                } else {
+                    this.scrub_next_block().unwrap_or_else(|e| {
+                        eprintln!("scrub failed: {}", e);
+                        0
+                    })
+                };
+                // Sleep long enough after each block to stay within the rate
+                let wait = match rate {
+                    Some(bytes_per_sec) if bytes > 0 => {
+                        Duration::from_secs_f64(bytes as f64 / bytes_per_sec as f64)
+                    }
+                    _ => IDLE_INTERVAL,
+                };
+                if !matches!(
+                    rx.recv_timeout(wait),
+                    Err(crossbeam_channel::RecvTimeoutError::Timeout)
+                ) {
+                    return;
+                }
+            }
+        });
+        Ok(Some(handle))
+    }
+}
+
+impl MiniLsm {
+    /// The progress of the background scrubber enabled by `LsmStorageOptions::scrub_bytes_per_sec`, and the SSTs it
+    /// has quarantined.
+    pub fn scrub_report(&self) -> ScrubReport {
+        self.inner.scrubber.report.lock().clone()
+    }
+}
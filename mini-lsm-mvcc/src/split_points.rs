@@ -0,0 +1,81 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Split points dividing the key space into ranges holding roughly the same amount of data, for users sharding on top
+//! of the engine.
+//!
+//! The data is weighed without reading it: each SST block counts with its size on disk at its first key, and each
+//! memtable entry with the size of its key and value.
+
+use anyhow::{Result, bail};
+use bytes::Bytes;
+
+use crate::lsm_error::{self, Error};
+use crate::lsm_storage::{LsmStorageInner, MiniLsm};
+
+impl LsmStorageInner {
+    /// Up to `n - 1` increasing keys dividing the data into `n` ranges of roughly equal size, each split point being
+    /// the first key of its range. Fewer keys are returned when there are too few blocks to tell the ranges apart.
+    pub fn suggest_split_points(&self, n: usize) -> Result<Vec<Bytes>> {
+        if n == 0 {
+            bail!(Error::InvalidArgument(
+                "the number of ranges must be at least 1".to_string()
+            ));
+        }
+        let snapshot = self.snapshot();
+        let mut weights = Vec::new();
+        for memtable in std::iter::once(&snapshot.memtable).chain(&snapshot.imm_memtables) {
+            for entry in memtable.map.iter() {
+                let key = entry.key().key_ref();
+                weights.push((
+                    Bytes::copy_from_slice(key),
+                    (key.len() + entry.value().len()) as u64,
+                ));
+            }
+        }
+        for table in snapshot.sstables.values() {
+            for (idx, meta) in table.block_meta().iter().enumerate() {
+                weights.push((
+                    Bytes::copy_from_slice(meta.first_key.key_ref()),
+                    (table.block_end(idx) - meta.offset) as u64,
+                ));
+            }
+        }
+        weights.sort_unstable_by(|x, y| x.0.cmp(&y.0));
+
+        let total = weights.iter().map(|(_, size)| size).sum::<u64>();
+        let mut split_points: Vec<Bytes> = Vec::with_capacity((n - 1).min(weights.len()));
+        let mut seen = 0;
+        for (key, size) in weights {
+            // the next range starts at the first key past its share of the data
+            let next_split = total * (split_points.len() as u64 + 1) / n as u64;
+            if split_points.len() + 1 < n
+                && seen >= next_split
+                && seen > 0
+                && split_points.last().is_none_or(|last| *last < key)
+            {
+                split_points.push(key);
+            }
+            seen += size;
+        }
+        Ok(split_points)
+    }
+}
+
+impl MiniLsm {
+    /// Up to `n - 1` keys dividing the data into `n` ranges of roughly equal size, estimated from the SST block metas
+    /// and the memtables without scanning, e.g., to rebalance shards.
+    pub fn suggest_split_points(&self, n: usize) -> lsm_error::Result<Vec<Bytes>> {
+        Ok(self.inner.suggest_split_points(n)?)
+    }
+}
@@ -0,0 +1,275 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An adapter to use the engine as the replicated state machine of a raft implementation such as raft-rs or openraft.
+//!
+//! Committed log entries are applied with [`LsmStateMachine::apply`], and the index of the last applied entry is
+//! persisted in the manifest after the entry is durable in the WAL, so that a restarted node knows where to resume.
+//! A crash between the two makes the node apply the last entry again, which leaves puts and deletes unchanged.
+//!
+//! A snapshot is a stream of all key-value pairs at the applied index:
+//!
+//! ```text
+//! | applied index (u64) | key_len (u16) | key | value_len (u16) | value | ... | 0 (u16) | crc32 of all above (u32) |
+//! ```
+
+use std::io::{Read, Write};
+use std::iter::Peekable;
+use std::ops::Bound;
+use std::sync::Arc;
+
+use anyhow::{Result, bail};
+use bytes::BufMut;
+
+use crate::iterators::StorageIterator;
+use crate::lsm_error::{self, Error};
+use crate::lsm_storage::{MiniLsm, WriteBatchRecord};
+use crate::manifest::ManifestRecord;
+use crate::mvcc::txn::TxnIterator;
+
+pub struct LsmStateMachine {
+    lsm: Arc<MiniLsm>,
+}
+
+impl LsmStateMachine {
+    /// Wrap `lsm`, which must have the WAL enabled so that applied entries survive a restart.
+    pub fn new(lsm: Arc<MiniLsm>) -> lsm_error::Result<Self> {
+        if !lsm.inner.options().enable_wal {
+            return Err(Error::InvalidArgument(
+                "the state machine requires the WAL to be enabled".to_string(),
+            ));
+        }
+        Ok(Self { lsm })
+    }
+
+    pub fn lsm(&self) -> &Arc<MiniLsm> {
+        &self.lsm
+    }
+
+    /// The index of the last applied log entry, or 0 if nothing has been applied.
+    pub fn applied_index(&self) -> u64 {
+        *self.lsm.inner.applied_index.lock()
+    }
+
+    /// Apply the log entry at `index`. Indexes must increase but may have gaps, e.g., for entries that do not carry a
+    /// write. Returns `false` without applying anything if the entry has already been applied.
+    pub fn apply<T: AsRef<[u8]>>(
+        &self,
+        index: u64,
+        batch: &[WriteBatchRecord<T>],
+    ) -> lsm_error::Result<bool> {
+        self.apply_inner(index, batch, None)
+    }
+
+    /// Apply the log entry at `index` like `apply`, committing its batch at `ts`, e.g., the commit timestamp on the
+    /// leader, so that reads at a timestamp see the same data on all replicas. Timestamps must increase from one entry
+    /// to the next.
+    pub fn apply_with_ts<T: AsRef<[u8]>>(
+        &self,
+        index: u64,
+        batch: &[WriteBatchRecord<T>],
+        ts: u64,
+    ) -> lsm_error::Result<bool> {
+        self.apply_inner(index, batch, Some(ts))
+    }
+
+    fn apply_inner<T: AsRef<[u8]>>(
+        &self,
+        index: u64,
+        batch: &[WriteBatchRecord<T>],
+        ts: Option<u64>,
+    ) -> lsm_error::Result<bool> {
+        let inner = &self.lsm.inner;
+        inner.check_writable()?;
+        let mut applied_index = inner.applied_index.lock();
+        if index <= *applied_index {
+            return Ok(false);
+        }
+        if !batch.is_empty() {
+            match ts {
+                Some(ts) => inner.write_batch_with_ts(batch, ts)?,
+                None => self.lsm.write_batch(batch)?,
+            }
+            self.lsm.sync()?;
+        }
+        let state_lock = inner.state_lock.lock();
+        inner
+            .manifest()
+            .add_record(&state_lock, ManifestRecord::AppliedIndex(index))?;
+        *applied_index = index;
+        Ok(true)
+    }
+
+    /// Write a snapshot of the state at the current applied index to `writer`, and return the index.
+    pub fn snapshot(&self, mut writer: impl Write) -> lsm_error::Result<u64> {
+        let (applied_index, mut iter) = {
+            let applied_index = self.lsm.inner.applied_index.lock();
+            let txn = self.lsm.new_txn()?;
+            (
+                *applied_index,
+                txn.scan(Bound::Unbounded, Bound::Unbounded)?,
+            )
+        };
+        let mut hasher = crc32fast::Hasher::new();
+        let mut buf = Vec::new();
+        buf.put_u64(applied_index);
+        while iter.is_valid() {
+            buf.put_u16(iter.key().len() as u16);
+            buf.put_slice(iter.key());
+            buf.put_u16(iter.value().len() as u16);
+            buf.put_slice(iter.value());
+            if buf.len() >= 1 << 16 {
+                hasher.update(&buf);
+                writer.write_all(&buf)?;
+                buf.clear();
+            }
+            iter.next()?;
+        }
+        buf.put_u16(0);
+        hasher.update(&buf);
+        buf.put_u32(hasher.finalize());
+        writer.write_all(&buf)?;
+        writer.flush()?;
+        Ok(applied_index)
+    }
+
+    /// Replace the whole state with a snapshot produced by [`LsmStateMachine::snapshot`], and return its applied
+    /// index. The snapshot is installed atomically by ingesting it along with deletes of all keys not in it; nothing is
+    /// changed if the snapshot turns out to be corrupted.
+    pub fn restore(&self, reader: impl Read) -> lsm_error::Result<u64> {
+        let inner = &self.lsm.inner;
+        let mut applied_index = inner.applied_index.lock();
+        let mut snapshot = SnapshotReader {
+            reader,
+            hasher: crc32fast::Hasher::new(),
+            done: false,
+        };
+        let mut header = [0; 8];
+        snapshot.read_exact(&mut header)?;
+        let index = u64::from_be_bytes(header);
+        let txn = self.lsm.new_txn()?;
+        let entries = RestoreIterator {
+            current: txn.scan(Bound::Unbounded, Bound::Unbounded)?,
+            snapshot: snapshot.peekable(),
+        };
+        inner.ingest_sorted(entries)?;
+        let state_lock = inner.state_lock.lock();
+        inner
+            .manifest()
+            .add_record(&state_lock, ManifestRecord::AppliedIndex(index))?;
+        *applied_index = index;
+        Ok(index)
+    }
+}
+
+/// Yields the entries of a snapshot, verifying the checksum after the last one.
+struct SnapshotReader<R> {
+    reader: R,
+    hasher: crc32fast::Hasher,
+    done: bool,
+}
+
+impl<R: Read> SnapshotReader<R> {
+    fn read_unhashed(&mut self, buf: &mut [u8]) -> Result<()> {
+        if let Err(e) = self.reader.read_exact(buf) {
+            if e.kind() == std::io::ErrorKind::UnexpectedEof {
+                bail!(Error::Corruption("incomplete snapshot".to_string()));
+            }
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.read_unhashed(buf)?;
+        self.hasher.update(buf);
+        Ok(())
+    }
+
+    fn read_slice(&mut self) -> Result<Vec<u8>> {
+        let mut len = [0; 2];
+        self.read_exact(&mut len)?;
+        let mut data = vec![0; u16::from_be_bytes(len) as usize];
+        self.read_exact(&mut data)?;
+        Ok(data)
+    }
+
+    fn read_entry(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let key = self.read_slice()?;
+        if key.is_empty() {
+            let expected = self.hasher.clone().finalize();
+            let mut checksum = [0; 4];
+            self.read_unhashed(&mut checksum)?;
+            if u32::from_be_bytes(checksum) != expected {
+                bail!(Error::Corruption(
+                    "snapshot checksum mismatched".to_string()
+                ));
+            }
+            return Ok(None);
+        }
+        let value = self.read_slice()?;
+        Ok(Some((key, value)))
+    }
+}
+
+impl<R: Read> Iterator for SnapshotReader<R> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let entry = self.read_entry().transpose();
+        self.done = !matches!(entry, Some(Ok(_)));
+        entry
+    }
+}
+
+/// Merges the current keys with a snapshot, yielding the snapshot entries and a delete (an empty value) for every
+/// current key not in the snapshot.
+struct RestoreIterator<R: Read> {
+    current: TxnIterator,
+    snapshot: Peekable<SnapshotReader<R>>,
+}
+
+impl<R: Read> RestoreIterator<R> {
+    fn next_inner(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let snapshot_key = match self.snapshot.peek() {
+            Some(Ok((key, _))) => Some(key.as_slice()),
+            Some(Err(_)) => return self.snapshot.next().transpose(),
+            None => None,
+        };
+        if !self.current.is_valid() {
+            return self.snapshot.next().transpose();
+        }
+        let current_key = self.current.key();
+        if snapshot_key.is_none_or(|key| current_key < key) {
+            let key = current_key.to_vec();
+            self.current.next()?;
+            return Ok(Some((key, Vec::new())));
+        }
+        if snapshot_key == Some(current_key) {
+            self.current.next()?;
+        }
+        self.snapshot.next().transpose()
+    }
+}
+
+impl<R: Read> Iterator for RestoreIterator<R> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_inner().transpose()
+    }
+}
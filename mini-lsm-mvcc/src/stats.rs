@@ -0,0 +1,473 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Statistics of the entries written by compactions, to tell what drives space usage, and the metadata of the live
+//! SSTs.
+
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use bytes::Bytes;
+use parking_lot::Mutex;
+
+use crate::key::KeySlice;
+use crate::lsm_error;
+use crate::lsm_storage::{LsmStorageInner, MiniLsm};
+
+/// A histogram with power-of-two buckets: bucket 0 counts zeros and bucket `i` counts values in `[2^(i-1), 2^i)`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Histogram {
+    buckets: Vec<u64>,
+    count: u64,
+    sum: u64,
+    max: u64,
+}
+
+impl Histogram {
+    pub fn record(&mut self, value: u64) {
+        let bucket = (u64::BITS - value.leading_zeros()) as usize;
+        if self.buckets.len() <= bucket {
+            self.buckets.resize(bucket + 1, 0);
+        }
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.sum = self.sum.saturating_add(value);
+        self.max = self.max.max(value);
+    }
+
+    fn bucket_upper_bound(bucket: usize) -> u64 {
+        if bucket == 0 {
+            0
+        } else {
+            u64::MAX >> (u64::BITS as usize - bucket)
+        }
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        if self.buckets.len() < other.buckets.len() {
+            self.buckets.resize(other.buckets.len(), 0);
+        }
+        for (bucket, count) in self.buckets.iter_mut().zip(&other.buckets) {
+            *bucket += count;
+        }
+        self.count += other.count;
+        self.sum = self.sum.saturating_add(other.sum);
+        self.max = self.max.max(other.max);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn sum(&self) -> u64 {
+        self.sum
+    }
+
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.sum as f64 / self.count as f64
+        }
+    }
+
+    /// An upper bound of the `p`-th percentile (`0.0..=1.0`), i.e., the exclusive end of the bucket it falls in,
+    /// capped at the maximum.
+    pub fn percentile(&self, p: f64) -> u64 {
+        let rank = ((self.count as f64 * p).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Self::bucket_upper_bound(bucket).min(self.max);
+            }
+        }
+        self.max
+    }
+
+    /// The non-empty buckets as `(inclusive upper bound, count)`, in increasing order.
+    pub fn buckets(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(bucket, count)| (Self::bucket_upper_bound(bucket), *count))
+    }
+}
+
+/// Histograms of the entries of some SSTs. Each version of a key is an entry, with an empty value for a delete.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EntryStats {
+    /// The number of SSTs covered. SSTs flushed from memtables or written before the storage was opened are not.
+    pub num_sstables: usize,
+    pub key_size: Histogram,
+    pub value_size: Histogram,
+    /// The number of versions of each key, which grows with the MVCC garbage retained for old snapshots.
+    pub versions_per_key: Histogram,
+}
+
+impl EntryStats {
+    /// The number of deletes, i.e., entries with an empty value.
+    pub fn tombstones(&self) -> u64 {
+        self.value_size
+            .buckets()
+            .next()
+            .filter(|(upper_bound, _)| *upper_bound == 0)
+            .map_or(0, |(_, count)| count)
+    }
+
+    /// The fraction of the entries that are not the newest version of their key.
+    pub fn obsolete_ratio(&self) -> f64 {
+        let entries = self.key_size.count();
+        if entries == 0 {
+            0.0
+        } else {
+            (entries - self.versions_per_key.count()) as f64 / entries as f64
+        }
+    }
+
+    pub fn merge(&mut self, other: &EntryStats) {
+        self.num_sstables += other.num_sstables;
+        self.key_size.merge(&other.key_size);
+        self.value_size.merge(&other.value_size);
+        self.versions_per_key.merge(&other.versions_per_key);
+    }
+}
+
+/// Collects the `EntryStats` of an SST while compaction builds it.
+pub(crate) struct EntryStatsCollector {
+    stats: EntryStats,
+    last_key: Vec<u8>,
+    versions: u64,
+}
+
+impl EntryStatsCollector {
+    pub(crate) fn new() -> Self {
+        Self {
+            stats: EntryStats {
+                num_sstables: 1,
+                ..Default::default()
+            },
+            last_key: Vec::new(),
+            versions: 0,
+        }
+    }
+
+    pub(crate) fn add(&mut self, key: KeySlice, value: &[u8]) {
+        if key.key_ref() != self.last_key {
+            self.finish_key();
+            self.last_key.clear();
+            self.last_key.extend(key.key_ref());
+        }
+        self.versions += 1;
+        self.stats.key_size.record(key.key_len() as u64);
+        self.stats.value_size.record(value.len() as u64);
+    }
+
+    fn finish_key(&mut self) {
+        if self.versions > 0 {
+            self.stats.versions_per_key.record(self.versions);
+        }
+        self.versions = 0;
+    }
+
+    pub(crate) fn finish(mut self) -> EntryStats {
+        self.finish_key();
+        self.stats
+    }
+}
+
+/// The entry stats of the SSTs written by compactions since the storage was opened.
+#[derive(Default)]
+pub(crate) struct SstEntryStats {
+    stats: Mutex<HashMap<usize, EntryStats>>,
+}
+
+impl SstEntryStats {
+    pub(crate) fn insert(&self, sst_id: usize, stats: EntryStats) {
+        self.stats.lock().insert(sst_id, stats);
+    }
+}
+
+impl LsmStorageInner {
+    pub(crate) fn level_entry_stats(&self) -> Vec<(usize, EntryStats)> {
+        let snapshot = self.snapshot();
+        let mut stats = self.sst_entry_stats.stats.lock();
+        // Forget the SSTs compacted away since
+        stats.retain(|id, _| snapshot.sstables.contains_key(id));
+        std::iter::once((0, &snapshot.l0_sstables))
+            .chain(snapshot.levels.iter().map(|(level, ssts)| (*level, ssts)))
+            .map(|(level, ssts)| {
+                let mut level_stats = EntryStats::default();
+                for sst_stats in ssts.iter().filter_map(|id| stats.get(id)) {
+                    level_stats.merge(sst_stats);
+                }
+                (level, level_stats)
+            })
+            .collect()
+    }
+}
+
+/// The space usage of a level.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LevelSpace {
+    pub level: usize,
+    pub num_sstables: usize,
+    pub size: u64,
+    /// The estimated bytes of versions shadowed by newer versions of the same key within the level.
+    pub obsolete_size: u64,
+    /// The deletes in the SSTs covered by entry stats.
+    pub tombstones: u64,
+}
+
+/// Where the disk space goes, to tell when a full compaction would pay off.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SpaceReport {
+    /// The size of all files in the storage directory, including WALs, the manifest and leftover files.
+    pub total_disk_size: u64,
+    pub sst_size: u64,
+    /// The estimated size of the live data, i.e., the newest versions in the bottommost non-empty level.
+    pub live_data_size: u64,
+    /// The estimated bytes a full compaction would reclaim: upper levels overwriting the bottom level and old versions.
+    pub garbage_size: u64,
+    pub tombstones: u64,
+    /// L0 first.
+    pub levels: Vec<LevelSpace>,
+}
+
+impl SpaceReport {
+    /// The total disk size over the live data size.
+    pub fn space_amplification(&self) -> f64 {
+        if self.live_data_size == 0 {
+            0.0
+        } else {
+            self.total_disk_size as f64 / self.live_data_size as f64
+        }
+    }
+}
+
+impl LsmStorageInner {
+    pub(crate) fn space_report(&self) -> Result<SpaceReport> {
+        let snapshot = self.snapshot();
+        let stats = self.sst_entry_stats.stats.lock().clone();
+        let mut report = SpaceReport::default();
+        for (level, ssts) in std::iter::once((0, &snapshot.l0_sstables))
+            .chain(snapshot.levels.iter().map(|(level, ssts)| (*level, ssts)))
+        {
+            let mut level_space = LevelSpace {
+                level,
+                num_sstables: ssts.len(),
+                ..Default::default()
+            };
+            for id in ssts {
+                let size = snapshot.sstables[id].table_size();
+                level_space.size += size;
+                if let Some(sst_stats) = stats.get(id) {
+                    level_space.obsolete_size += (size as f64 * sst_stats.obsolete_ratio()) as u64;
+                    level_space.tombstones += sst_stats.tombstones();
+                }
+            }
+            report.sst_size += level_space.size;
+            report.tombstones += level_space.tombstones;
+            if level_space.num_sstables > 0 {
+                // Upper levels are assumed to overwrite keys of the lower ones
+                report.live_data_size = level_space.size - level_space.obsolete_size;
+            }
+            report.levels.push(level_space);
+        }
+        report.garbage_size = report.sst_size - report.live_data_size;
+        for entry in std::fs::read_dir(&self.path)? {
+            let metadata = entry?.metadata()?;
+            if metadata.is_file() {
+                report.total_disk_size += metadata.len();
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// The approximate memory used by the storage in bytes, e.g., to fit it in the memory budget of the process.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// The keys and values in the active memtable.
+    pub active_memtable: usize,
+    /// The keys and values in the memtables waiting to be flushed.
+    pub imm_memtables: usize,
+    /// The blocks in the in-memory block cache.
+    pub block_cache: usize,
+    /// The block indexes, bloom filters and block filters of the SSTs, which stay in memory while they are open.
+    pub pinned_index_and_filters: usize,
+    /// The blocks held by open iterators, most of which are also in the block cache.
+    pub iterator_pinned_blocks: usize,
+}
+
+impl MemoryUsage {
+    /// The total of all parts, an upper bound as the blocks held by iterators are mostly counted twice.
+    pub fn total(&self) -> usize {
+        self.active_memtable
+            + self.imm_memtables
+            + self.block_cache
+            + self.pinned_index_and_filters
+            + self.iterator_pinned_blocks
+    }
+}
+
+impl LsmStorageInner {
+    pub(crate) fn memory_usage(&self) -> MemoryUsage {
+        let snapshot = self.snapshot();
+        MemoryUsage {
+            active_memtable: snapshot.memtable.approximate_size(),
+            imm_memtables: snapshot
+                .imm_memtables
+                .iter()
+                .map(|memtable| memtable.approximate_size())
+                .sum(),
+            block_cache: self.block_cache.size(),
+            pinned_index_and_filters: snapshot
+                .sstables
+                .values()
+                .map(|table| table.metadata_memory())
+                .sum(),
+            iterator_pinned_blocks: self.block_cache.pinned_size(),
+        }
+    }
+}
+
+/// The metadata of an SST in the LSM structure, read from its meta section without reading the data blocks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LiveFile {
+    pub id: usize,
+    /// 0 for L0, otherwise the level, or the tier id with tiered compaction.
+    pub level: usize,
+    pub path: PathBuf,
+    pub size: u64,
+    pub first_key: Bytes,
+    pub last_key: Bytes,
+    pub min_ts: u64,
+    pub max_ts: u64,
+    pub num_entries: u64,
+    pub num_deletes: u64,
+    /// When the SST was built, as recorded in its properties.
+    pub creation_time: SystemTime,
+}
+
+impl LsmStorageInner {
+    pub(crate) fn live_files(&self) -> Vec<LiveFile> {
+        let snapshot = self.snapshot();
+        std::iter::once((0, &snapshot.l0_sstables))
+            .chain(snapshot.levels.iter().map(|(level, ssts)| (*level, ssts)))
+            .flat_map(|(level, ssts)| ssts.iter().map(move |id| (level, *id)))
+            .map(|(level, id)| {
+                let table = &snapshot.sstables[&id];
+                let (time_range, entry_counts) = (table.time_range(), table.entry_counts());
+                LiveFile {
+                    id,
+                    level,
+                    path: self.path_of_sst(id),
+                    size: table.table_size(),
+                    first_key: table.first_key().key_ref().to_vec().into(),
+                    last_key: table.last_key().key_ref().to_vec().into(),
+                    min_ts: time_range.min_ts,
+                    max_ts: time_range.max_ts,
+                    num_entries: entry_counts.num_entries,
+                    num_deletes: entry_counts.num_deletes,
+                    creation_time: UNIX_EPOCH
+                        + Duration::from_millis(table.properties().creation_time),
+                }
+            })
+            .collect()
+    }
+}
+
+/// The number of aborts whose reasons `TxnStats` keeps.
+const MAX_RECENT_ABORTS: usize = 64;
+
+/// Why a transaction failed to commit or prepare.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AbortReason {
+    /// The transaction writes `key`, which another transaction locked with `get_for_update` or by preparing.
+    WriteConflict { key: Bytes },
+    /// A transaction committed at `commit_ts` wrote a key this serializable transaction read at `read_ts`. Other
+    /// commits in between may have conflicted too.
+    ReadValidation { read_ts: u64, commit_ts: u64 },
+    /// The changes could not be written, e.g., because of an I/O error or the storage rejecting writes.
+    WriteFailed(String),
+}
+
+/// The commits and aborts of transactions since the storage was opened.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TxnStats {
+    pub commits: u64,
+    pub write_conflicts: u64,
+    pub read_validation_failures: u64,
+    pub write_failures: u64,
+    /// The reasons of the latest aborts, oldest first.
+    pub recent_aborts: VecDeque<AbortReason>,
+}
+
+impl TxnStats {
+    pub fn aborts(&self) -> u64 {
+        self.write_conflicts + self.read_validation_failures + self.write_failures
+    }
+
+    pub(crate) fn record_commit(&mut self) {
+        self.commits += 1;
+    }
+
+    pub(crate) fn record_abort(&mut self, reason: AbortReason) {
+        match reason {
+            AbortReason::WriteConflict { .. } => self.write_conflicts += 1,
+            AbortReason::ReadValidation { .. } => self.read_validation_failures += 1,
+            AbortReason::WriteFailed(_) => self.write_failures += 1,
+        }
+        if self.recent_aborts.len() == MAX_RECENT_ABORTS {
+            self.recent_aborts.pop_front();
+        }
+        self.recent_aborts.push_back(reason);
+    }
+}
+
+impl MiniLsm {
+    /// Estimates the space amplification of the storage. The old versions are only known for the SSTs written by
+    /// compactions since the storage was opened.
+    pub fn space_report(&self) -> lsm_error::Result<SpaceReport> {
+        Ok(self.inner.space_report()?)
+    }
+
+    /// The metadata of every SST in the LSM structure, L0 first from the newest, then each level in key order.
+    pub fn live_files(&self) -> Vec<LiveFile> {
+        self.inner.live_files()
+    }
+
+    /// The entry stats of each level, L0 first, over the SSTs written by compactions since the storage was opened.
+    pub fn level_entry_stats(&self) -> Vec<(usize, EntryStats)> {
+        self.inner.level_entry_stats()
+    }
+
+    /// The approximate memory used by the memtables, the block cache, the SST metadata and the blocks held by
+    /// iterators.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        self.inner.memory_usage()
+    }
+
+    /// The commits and aborts of transactions since the storage was opened, with the reasons of the latest aborts.
+    pub fn txn_stats(&self) -> TxnStats {
+        self.inner.mvcc().txn_stats.lock().clone()
+    }
+}
@@ -15,22 +15,429 @@
 pub(crate) mod bloom;
 mod builder;
 mod iterator;
+mod properties;
 
-use std::fs::File;
+use std::collections::BTreeMap;
 use std::path::Path;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use anyhow::{Result, anyhow, bail};
 pub use builder::SsTableBuilder;
-use bytes::{Buf, BufMut};
-pub use iterator::SsTableIterator;
+use bytes::{Buf, BufMut, Bytes};
+pub(crate) use iterator::CorruptionReport;
+pub(crate) use iterator::is_past_upper;
+pub use iterator::{CorruptedBlock, SsTableIterator};
+pub use properties::{TablePropertiesCollector, TablePropertiesCollectorFactory};
+use serde::{Deserialize, Serialize};
 
 use crate::block::Block;
+use crate::checksum::{block_checksum, xxhash32};
+use crate::file_system::{FileSystem, FileSystemOptions, ReadFile};
+use crate::io_retry::{IoRetry, IoTarget};
 use crate::key::{KeyBytes, KeySlice};
-use crate::lsm_storage::BlockCache;
+use crate::lsm_error::Error;
+use crate::lsm_storage::{BlockCache, ReadOptions};
+use crate::prefix::PrefixExtractor;
 
 use self::bloom::Bloom;
 
+/// The range of commit timestamps and wall-clock write times of the entries in an SST, recorded in its meta section
+/// so that reads with a timestamp predicate can skip the whole file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SstTimeRange {
+    pub min_ts: u64,
+    pub max_ts: u64,
+    /// The earliest write time of the entries, in milliseconds since the UNIX epoch.
+    pub min_write_time: u64,
+    /// The latest write time of the entries, in milliseconds since the UNIX epoch.
+    pub max_write_time: u64,
+}
+
+impl SstTimeRange {
+    pub fn write_time_range(&self) -> (SystemTime, SystemTime) {
+        (
+            UNIX_EPOCH + Duration::from_millis(self.min_write_time),
+            UNIX_EPOCH + Duration::from_millis(self.max_write_time),
+        )
+    }
+}
+
+/// The number of entries and deletes in an SST, recorded in its meta section so that compaction can find the SSTs
+/// dominated by tombstones without reading them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SstEntryCounts {
+    pub num_entries: u64,
+    /// The entries with an empty value.
+    pub num_deletes: u64,
+}
+
+impl SstEntryCounts {
+    /// The fraction of the entries that are deletes.
+    pub fn tombstone_ratio(&self) -> f64 {
+        if self.num_entries == 0 {
+            0.0
+        } else {
+            self.num_deletes as f64 / self.num_entries as f64
+        }
+    }
+}
+
+/// How to interpret the values when recording their range in each block. Values are 8-byte big-endian numbers, e.g.,
+/// from `u64::to_be_bytes`; other values are not numbers and keep the blocks holding them from being pruned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ValueSchema {
+    U64,
+    I64,
+}
+
+impl ValueSchema {
+    /// Map a value to a `u64` in the same order as the numbers, or `None` if it is not a number.
+    pub fn order_key(&self, value: &[u8]) -> Option<u64> {
+        let value = u64::from_be_bytes(value.try_into().ok()?);
+        Some(match self {
+            ValueSchema::U64 => value,
+            ValueSchema::I64 => value ^ (1 << 63),
+        })
+    }
+
+    fn tag(&self) -> u8 {
+        match self {
+            ValueSchema::U64 => 1,
+            ValueSchema::I64 => 2,
+        }
+    }
+}
+
+/// The range of the numeric values in each block of an SST, as mapped by `ValueSchema::order_key`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockValueRanges {
+    pub schema: ValueSchema,
+    /// The inclusive range of each block, or `None` if the block holds a value that is not a number. Deletes are
+    /// skipped, so a block of deletes only has an empty range, with the minimum above the maximum.
+    pub ranges: Vec<Option<(u64, u64)>>,
+}
+
+impl BlockValueRanges {
+    /// Whether block `block_idx` may hold a value within `min..=max`, both mapped by `ValueSchema::order_key`.
+    pub fn may_overlap(&self, block_idx: usize, min: u64, max: u64) -> bool {
+        self.ranges[block_idx]
+            .is_none_or(|(block_min, block_max)| block_min <= max && min <= block_max)
+    }
+
+    fn encoded_size(value_ranges: Option<&Self>) -> usize {
+        std::mem::size_of::<u8>()
+            + value_ranges.map_or(0, |x| {
+                std::mem::size_of::<u32>()
+                    + x.ranges.len() * (std::mem::size_of::<u8>() + std::mem::size_of::<u64>() * 2)
+            })
+    }
+
+    fn encode(value_ranges: Option<&Self>, buf: &mut Vec<u8>) {
+        let Some(value_ranges) = value_ranges else {
+            buf.put_u8(0);
+            return;
+        };
+        buf.put_u8(value_ranges.schema.tag());
+        buf.put_u32(value_ranges.ranges.len() as u32);
+        for range in &value_ranges.ranges {
+            let (min, max) = range.unwrap_or_default();
+            buf.put_u8(range.is_some() as u8);
+            buf.put_u64(min);
+            buf.put_u64(max);
+        }
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Option<Self>> {
+        ensure_remaining(buf, 1)?;
+        let schema = match buf.get_u8() {
+            0 => return Ok(None),
+            1 => ValueSchema::U64,
+            2 => ValueSchema::I64,
+            tag => bail!(Error::Corruption(format!(
+                "unknown value schema {tag} in SST meta"
+            ))),
+        };
+        ensure_remaining(buf, 4)?;
+        let num = buf.get_u32() as usize;
+        ensure_remaining(buf, num.saturating_mul(17))?;
+        let mut ranges = Vec::with_capacity(num);
+        for _ in 0..num {
+            let known = buf.get_u8() != 0;
+            let range = (buf.get_u64(), buf.get_u64());
+            ranges.push(known.then_some(range));
+        }
+        Ok(Some(Self { schema, ranges }))
+    }
+}
+
+/// What wrote an SST.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SstOrigin {
+    /// Built outside of the engine, e.g., by a test or a tool.
+    #[default]
+    Unknown,
+    Flush,
+    /// A compaction job. Job IDs are drawn from the SST IDs, so they are unique within the storage.
+    Compaction {
+        job_id: u64,
+    },
+    Ingest,
+    Export,
+}
+
+impl SstOrigin {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        let (tag, job_id) = match self {
+            SstOrigin::Unknown => (0, 0),
+            SstOrigin::Flush => (1, 0),
+            SstOrigin::Compaction { job_id } => (2, *job_id),
+            SstOrigin::Ingest => (3, 0),
+            SstOrigin::Export => (4, 0),
+        };
+        buf.put_u8(tag);
+        buf.put_u64(job_id);
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        ensure_remaining(buf, 9)?;
+        let tag = buf.get_u8();
+        let job_id = buf.get_u64();
+        Ok(match tag {
+            1 => SstOrigin::Flush,
+            2 => SstOrigin::Compaction { job_id },
+            3 => SstOrigin::Ingest,
+            4 => SstOrigin::Export,
+            _ => SstOrigin::Unknown,
+        })
+    }
+}
+
+impl std::fmt::Display for SstOrigin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SstOrigin::Unknown => write!(f, "unknown"),
+            SstOrigin::Flush => write!(f, "flush"),
+            SstOrigin::Compaction { job_id } => write!(f, "compaction job {job_id}"),
+            SstOrigin::Ingest => write!(f, "ingest"),
+            SstOrigin::Export => write!(f, "export"),
+        }
+    }
+}
+
+/// The identity and provenance of an SST, recorded in its meta section so that the file can still be told apart after
+/// being moved between directories or backups.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SstProperties {
+    /// A random (version 4) UUID, drawn from `LsmStorageOptions::deterministic_seed` if set.
+    pub unique_id: u128,
+    /// When the SST was built, in milliseconds since the UNIX epoch.
+    pub creation_time: u64,
+    pub origin: SstOrigin,
+    /// The version of the engine that built the SST.
+    pub engine_version: String,
+    /// The properties emitted by the `TablePropertiesCollector`s of the SST.
+    pub user_collected: BTreeMap<String, Bytes>,
+}
+
+impl SstProperties {
+    /// Create the properties of a new SST, with a unique id made of the random `bits`.
+    pub(crate) fn new(origin: SstOrigin, bits: u128) -> Self {
+        // Set the version and variant bits of a random UUID
+        let unique_id = bits & !(0xf << 76 | 0x3 << 62) | (0x4 << 76 | 0x2 << 62);
+        Self {
+            unique_id,
+            creation_time: unix_millis(SystemTime::now()),
+            origin,
+            engine_version: env!("CARGO_PKG_VERSION").to_string(),
+            user_collected: BTreeMap::new(),
+        }
+    }
+
+    /// The unique ID in the hyphenated UUID format.
+    pub fn uuid(&self) -> String {
+        let hex = format!("{:032x}", self.unique_id);
+        format!(
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        )
+    }
+
+    fn encoded_size(&self) -> usize {
+        std::mem::size_of::<u128>()
+            + std::mem::size_of::<u64>()
+            + std::mem::size_of::<u8>()
+            + std::mem::size_of::<u64>()
+            + std::mem::size_of::<u16>()
+            + self.engine_version.len()
+            + std::mem::size_of::<u32>()
+            + self
+                .user_collected
+                .iter()
+                .map(|(name, value)| {
+                    std::mem::size_of::<u16>()
+                        + name.len()
+                        + std::mem::size_of::<u32>()
+                        + value.len()
+                })
+                .sum::<usize>()
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.put_u128(self.unique_id);
+        buf.put_u64(self.creation_time);
+        self.origin.encode(buf);
+        buf.put_u16(self.engine_version.len() as u16);
+        buf.put_slice(self.engine_version.as_bytes());
+        buf.put_u32(self.user_collected.len() as u32);
+        for (name, value) in &self.user_collected {
+            buf.put_u16(name.len() as u16);
+            buf.put_slice(name.as_bytes());
+            buf.put_u32(value.len() as u32);
+            buf.put_slice(value);
+        }
+    }
+
+    fn decode(buf: &mut &[u8]) -> Result<Self> {
+        ensure_remaining(buf, 24)?;
+        let unique_id = buf.get_u128();
+        let creation_time = buf.get_u64();
+        let origin = SstOrigin::decode(buf)?;
+        ensure_remaining(buf, 2)?;
+        let version_len = buf.get_u16() as usize;
+        ensure_remaining(buf, version_len)?;
+        let engine_version = String::from_utf8(buf.copy_to_bytes(version_len).to_vec())
+            .map_err(|_| Error::Corruption("invalid engine version in SST meta".to_string()))?;
+        let mut user_collected = BTreeMap::new();
+        ensure_remaining(buf, 4)?;
+        for _ in 0..buf.get_u32() {
+            ensure_remaining(buf, 2)?;
+            let name_len = buf.get_u16() as usize;
+            ensure_remaining(buf, name_len)?;
+            let name = String::from_utf8(buf.copy_to_bytes(name_len).to_vec())
+                .map_err(|_| Error::Corruption("invalid property name in SST meta".to_string()))?;
+            ensure_remaining(buf, 4)?;
+            let value_len = buf.get_u32() as usize;
+            ensure_remaining(buf, value_len)?;
+            user_collected.insert(name, buf.copy_to_bytes(value_len));
+        }
+        Ok(Self {
+            unique_id,
+            creation_time,
+            origin,
+            engine_version,
+            user_collected,
+        })
+    }
+}
+
+/// Fail with a corruption error unless `len` more bytes of the SST meta are left in `buf`, so that a meta block of
+/// another layout is reported instead of read out of bounds.
+fn ensure_remaining(buf: &[u8], len: usize) -> Result<()> {
+    if buf.remaining() < len {
+        bail!(Error::Corruption("SST meta is truncated".to_string()));
+    }
+    Ok(())
+}
+
+/// Milliseconds since the UNIX epoch, the unit of write times in the SST meta.
+pub(crate) fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// The flag of a value stored as is in an SST with compressed values.
+const RAW_VALUE: u8 = 0;
+/// The flag of a value compressed with Snappy in an SST with compressed values.
+const SNAPPY_VALUE: u8 = 1;
+
+/// Encode a non-empty value for an SST with compressed values into `buf`, compressing it if it is longer than
+/// `threshold` and compression makes it smaller.
+pub(crate) fn encode_value(value: &[u8], threshold: usize, buf: &mut Vec<u8>) {
+    buf.clear();
+    if value.len() > threshold {
+        buf.resize(1 + snap::raw::max_compress_len(value.len()), 0);
+        let len = snap::raw::Encoder::new()
+            .compress(value, &mut buf[1..])
+            .expect("the buffer fits the compressed value");
+        if len < value.len() {
+            buf[0] = SNAPPY_VALUE;
+            buf.truncate(1 + len);
+            return;
+        }
+        buf.clear();
+    }
+    buf.put_u8(RAW_VALUE);
+    buf.put_slice(value);
+}
+
+/// Decode a value of an SST with compressed values, which `decompress_value` has checked and decompressed into `buf`
+/// if needed.
+pub(crate) fn decode_value<'a>(value: &'a [u8], buf: &'a [u8]) -> &'a [u8] {
+    match value.split_first() {
+        Some((&RAW_VALUE, value)) => value,
+        Some(_) => buf,
+        // deletes are stored as is
+        None => value,
+    }
+}
+
+/// Decompress a value of an SST with compressed values into `buf` if it is compressed, for `decode_value`. Fails with a
+/// corruption error if the flag of the value is unknown.
+pub(crate) fn decompress_value(value: &[u8], buf: &mut Vec<u8>) -> Result<()> {
+    let compressed = match value.split_first() {
+        Some((&SNAPPY_VALUE, compressed)) => compressed,
+        Some((&RAW_VALUE, _)) | None => return Ok(()),
+        Some((flag, _)) => bail!(Error::Corruption(format!("unknown value flag {flag}"))),
+    };
+    buf.clear();
+    let len = snap::raw::decompress_len(compressed)
+        .map_err(|e| Error::Corruption(format!("invalid compressed value: {e}")))?;
+    buf.resize(len, 0);
+    snap::raw::Decoder::new()
+        .decompress(compressed, buf)
+        .map_err(|e| Error::Corruption(format!("invalid compressed value: {e}")))?;
+    Ok(())
+}
+
+/// The size of the checksum following each value in an SST with value checksums.
+pub(crate) const VALUE_CHECKSUM_SIZE: usize = 4;
+
+/// The checksum stored after the value of an entry in an SST with value checksums, covering the key, its timestamp
+/// and the value before compression.
+pub(crate) fn entry_checksum(key: KeySlice, value: &[u8]) -> u32 {
+    let seed = xxhash32(&key.ts().to_be_bytes(), xxhash32(key.key_ref(), 0));
+    xxhash32(value, seed)
+}
+
+/// Set in the value flags of the SST meta if the values are stored with `encode_value`.
+const COMPRESSED_VALUES_FLAG: u8 = 1;
+/// Set in the value flags of the SST meta if each value is followed by the checksum of its entry.
+const VALUE_CHECKSUMS_FLAG: u8 = 2;
+
+/// The contents of the meta section of an SST.
+pub struct SstMeta {
+    pub block_meta: Vec<BlockMeta>,
+    pub time_range: SstTimeRange,
+    pub entry_counts: SstEntryCounts,
+    pub properties: SstProperties,
+    /// The filter of each block, empty if the SST is built without block filters.
+    pub block_filters: Vec<Bloom>,
+    /// The range of the values in each block, if the SST is built with a value schema.
+    pub value_ranges: Option<BlockValueRanges>,
+    /// Whether each non-empty value starts with a flag telling whether the rest is compressed.
+    pub compressed_values: bool,
+    /// Whether each value, including deletes, is followed by the checksum of its entry.
+    pub value_checksums: bool,
+    /// The name of the prefix extractor whose prefixes are added to the bloom filter, if any.
+    pub prefix_extractor: Option<String>,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct BlockMeta {
     /// Offset of this data block.
@@ -43,7 +450,19 @@ pub struct BlockMeta {
 
 impl BlockMeta {
     /// Encode block meta to a buffer.
-    pub fn encode_block_meta(block_meta: &[BlockMeta], max_ts: u64, buf: &mut Vec<u8>) {
+    pub fn encode_block_meta(meta: &SstMeta, buf: &mut Vec<u8>) {
+        let SstMeta {
+            block_meta,
+            time_range,
+            entry_counts,
+            properties,
+            block_filters,
+            value_ranges,
+            compressed_values,
+            value_checksums,
+            prefix_extractor,
+        } = meta;
+        let value_ranges = value_ranges.as_ref();
         let mut estimated_size = std::mem::size_of::<u32>(); // number of blocks
         for meta in block_meta {
             // The size of offset
@@ -57,7 +476,17 @@ impl BlockMeta {
             // The size of actual key
             estimated_size += meta.last_key.raw_len();
         }
-        estimated_size += std::mem::size_of::<u64>(); // max timestamp
+        estimated_size += std::mem::size_of::<u64>() * 4; // timestamp and write time range
+        estimated_size += std::mem::size_of::<u64>() * 2; // entry counts
+        estimated_size += properties.encoded_size();
+        estimated_size += std::mem::size_of::<u32>(); // number of block filters
+        for filter in block_filters {
+            estimated_size += std::mem::size_of::<u32>() + filter.encoded_size();
+        }
+        estimated_size += BlockValueRanges::encoded_size(value_ranges);
+        estimated_size += std::mem::size_of::<u8>(); // value flags
+        estimated_size += std::mem::size_of::<u16>(); // prefix extractor name length
+        estimated_size += prefix_extractor.as_ref().map_or(0, String::len);
         estimated_size += std::mem::size_of::<u32>(); // checksum
 
         // Reserve the space to improve performance, especially when the size of incoming data is
@@ -74,22 +503,57 @@ impl BlockMeta {
             buf.put_slice(meta.last_key.key_ref());
             buf.put_u64(meta.last_key.ts());
         }
-        buf.put_u64(max_ts);
+        buf.put_u64(time_range.min_ts);
+        buf.put_u64(time_range.max_ts);
+        buf.put_u64(time_range.min_write_time);
+        buf.put_u64(time_range.max_write_time);
+        buf.put_u64(entry_counts.num_entries);
+        buf.put_u64(entry_counts.num_deletes);
+        properties.encode(buf);
+        buf.put_u32(block_filters.len() as u32);
+        for filter in block_filters {
+            buf.put_u32(filter.encoded_size() as u32);
+            filter.encode(buf);
+        }
+        BlockValueRanges::encode(value_ranges, buf);
+        let mut value_flags = 0;
+        if *compressed_values {
+            value_flags |= COMPRESSED_VALUES_FLAG;
+        }
+        if *value_checksums {
+            value_flags |= VALUE_CHECKSUMS_FLAG;
+        }
+        buf.put_u8(value_flags);
+        // An empty name means no prefixes are in the bloom filter
+        let prefix_extractor = prefix_extractor.as_deref().unwrap_or_default();
+        buf.put_u16(prefix_extractor.len() as u16);
+        buf.put_slice(prefix_extractor.as_bytes());
         buf.put_u32(crc32fast::hash(&buf[original_len + 4..]));
         assert_eq!(estimated_size, buf.len() - original_len);
     }
 
     /// Decode block meta from a buffer.
-    pub fn decode_block_meta(mut buf: &[u8]) -> Result<(Vec<BlockMeta>, u64)> {
+    pub fn decode_block_meta(mut buf: &[u8]) -> Result<SstMeta> {
         let mut block_meta = Vec::new();
+        if buf.remaining() < 8 {
+            bail!(Error::Corruption("meta block too short".to_string()));
+        }
         let num = buf.get_u32() as usize;
         let checksum = crc32fast::hash(&buf[..buf.remaining() - 4]);
+        // Verify before parsing, so that a damaged meta block is reported instead of read out of bounds
+        if (&buf[buf.remaining() - 4..]).get_u32() != checksum {
+            bail!(Error::Corruption("meta checksum mismatched".to_string()));
+        }
+        buf = &buf[..buf.remaining() - 4];
         for _ in 0..num {
+            ensure_remaining(buf, 6)?;
             let offset = buf.get_u32() as usize;
             let first_key_len = buf.get_u16() as usize;
+            ensure_remaining(buf, first_key_len + 10)?;
             let first_key =
                 KeyBytes::from_bytes_with_ts(buf.copy_to_bytes(first_key_len), buf.get_u64());
             let last_key_len: usize = buf.get_u16() as usize;
+            ensure_remaining(buf, last_key_len + 8)?;
             let last_key =
                 KeyBytes::from_bytes_with_ts(buf.copy_to_bytes(last_key_len), buf.get_u64());
             block_meta.push(BlockMeta {
@@ -98,47 +562,96 @@ impl BlockMeta {
                 last_key,
             });
         }
-        let max_ts = buf.get_u64();
-        if buf.get_u32() != checksum {
-            bail!("meta checksum mismatched");
+        ensure_remaining(buf, 48)?;
+        let time_range = SstTimeRange {
+            min_ts: buf.get_u64(),
+            max_ts: buf.get_u64(),
+            min_write_time: buf.get_u64(),
+            max_write_time: buf.get_u64(),
+        };
+        let entry_counts = SstEntryCounts {
+            num_entries: buf.get_u64(),
+            num_deletes: buf.get_u64(),
+        };
+        let properties = SstProperties::decode(&mut buf)?;
+        ensure_remaining(buf, 4)?;
+        let num_filters = buf.get_u32() as usize;
+        let mut block_filters = Vec::with_capacity(num_filters.min(block_meta.len()));
+        for _ in 0..num_filters {
+            ensure_remaining(buf, 4)?;
+            let len = buf.get_u32() as usize;
+            ensure_remaining(buf, len)?;
+            block_filters.push(Bloom::decode(&buf[..len])?);
+            buf.advance(len);
         }
+        let value_ranges = BlockValueRanges::decode(&mut buf)?;
+        ensure_remaining(buf, 3)?;
+        let value_flags = buf.get_u8();
+        let prefix_extractor_len = buf.get_u16() as usize;
+        ensure_remaining(buf, prefix_extractor_len)?;
+        let prefix_extractor = String::from_utf8(buf[..prefix_extractor_len].to_vec())
+            .map_err(|_| Error::Corruption("invalid prefix extractor name".to_string()))?;
+        buf.advance(prefix_extractor_len);
 
-        Ok((block_meta, max_ts))
+        Ok(SstMeta {
+            block_meta,
+            time_range,
+            entry_counts,
+            properties,
+            block_filters,
+            value_ranges,
+            compressed_values: value_flags & COMPRESSED_VALUES_FLAG != 0,
+            value_checksums: value_flags & VALUE_CHECKSUMS_FLAG != 0,
+            prefix_extractor: (!prefix_extractor.is_empty()).then_some(prefix_extractor),
+        })
     }
 }
 
 /// A file object.
-pub struct FileObject(Option<File>, u64);
+pub struct FileObject(Option<Box<dyn ReadFile>>, u64, Arc<IoRetry>);
 
 impl FileObject {
     pub fn read(&self, offset: u64, len: u64) -> Result<Vec<u8>> {
-        use std::os::unix::fs::FileExt;
         let mut data = vec![0; len as usize];
-        self.0
-            .as_ref()
-            .unwrap()
-            .read_exact_at(&mut data[..], offset)?;
+        let file = self.0.as_ref().unwrap();
+        self.2.run(IoTarget::SstRead, || {
+            file.read_exact_at(&mut data[..], offset)
+        })?;
         Ok(data)
     }
 
+    pub(crate) fn set_io_retry(&mut self, io_retry: Arc<IoRetry>) {
+        self.2 = io_retry;
+    }
+
     pub fn size(&self) -> u64 {
         self.1
     }
 
     /// Create a new file object (day 2) and write the file to the disk (day 4).
     pub fn create(path: &Path, data: Vec<u8>) -> Result<Self> {
-        std::fs::write(path, &data)?;
-        File::open(path)?.sync_all()?;
+        Self::create_in(&FileSystemOptions::Std, path, data)
+    }
+
+    /// Create a file object like `create`, in `file_system`.
+    pub fn create_in(file_system: &dyn FileSystem, path: &Path, data: Vec<u8>) -> Result<Self> {
+        file_system.write_synced(path, &data)?;
         Ok(FileObject(
-            Some(File::options().read(true).write(false).open(path)?),
+            Some(file_system.open_read(path)?),
             data.len() as u64,
+            Arc::default(),
         ))
     }
 
     pub fn open(path: &Path) -> Result<Self> {
-        let file = File::options().read(true).write(false).open(path)?;
-        let size = file.metadata()?.len();
-        Ok(FileObject(Some(file), size))
+        Self::open_in(&FileSystemOptions::Std, path)
+    }
+
+    /// Open a file object like `open`, from `file_system`.
+    pub fn open_in(file_system: &dyn FileSystem, path: &Path) -> Result<Self> {
+        let file = file_system.open_read(path)?;
+        let size = file.size()?;
+        Ok(FileObject(Some(file), size, Arc::default()))
     }
 }
 
@@ -155,7 +668,20 @@ pub struct SsTable {
     first_key: KeyBytes,
     last_key: KeyBytes,
     pub(crate) bloom: Option<Bloom>,
-    max_ts: u64,
+    /// The bloom filter loaded on first use, if the SST is opened without loading it.
+    lazy_bloom: Option<OnceLock<Option<Bloom>>>,
+    /// The filter of each block, empty if not built with block filters.
+    block_filters: Vec<Bloom>,
+    value_ranges: Option<BlockValueRanges>,
+    /// Whether the values are stored with `encode_value`.
+    pub(crate) compressed_values: bool,
+    /// Whether each value is followed by the checksum of its entry.
+    pub(crate) value_checksums: bool,
+    /// The name of the prefix extractor whose prefixes are in the bloom filter, if any.
+    prefix_extractor: Option<String>,
+    time_range: SstTimeRange,
+    entry_counts: SstEntryCounts,
+    properties: SstProperties,
 }
 impl SsTable {
     #[cfg(test)]
@@ -165,15 +691,58 @@ impl SsTable {
 
     /// Open SSTable from a file.
     pub fn open(id: usize, block_cache: Option<Arc<BlockCache>>, file: FileObject) -> Result<Self> {
+        Self::open_inner(id, block_cache, file, true)
+    }
+
+    /// Open SSTable from a file without reading its bloom filter until a lookup needs it, making the open cheaper.
+    pub fn open_with_lazy_bloom(
+        id: usize,
+        block_cache: Option<Arc<BlockCache>>,
+        file: FileObject,
+    ) -> Result<Self> {
+        Self::open_inner(id, block_cache, file, false)
+    }
+
+    fn open_inner(
+        id: usize,
+        block_cache: Option<Arc<BlockCache>>,
+        file: FileObject,
+        preload_bloom: bool,
+    ) -> Result<Self> {
         let len = file.size();
+        if len < 8 {
+            bail!(Error::Corruption(format!("SST {id} is truncated")));
+        }
         let raw_bloom_offset = file.read(len - 4, 4)?;
         let bloom_offset = (&raw_bloom_offset[..]).get_u32() as u64;
-        let raw_bloom = file.read(bloom_offset, len - 4 - bloom_offset)?;
-        let bloom_filter = Bloom::decode(&raw_bloom)?;
+        if bloom_offset < 4 || bloom_offset + 5 > len - 4 {
+            bail!(Error::Corruption(format!("SST {id} has an invalid footer")));
+        }
+        let bloom_filter = if preload_bloom {
+            Some(Self::read_bloom(&file, bloom_offset)?)
+        } else {
+            None
+        };
         let raw_meta_offset = file.read(bloom_offset - 4, 4)?;
         let block_meta_offset = (&raw_meta_offset[..]).get_u32() as u64;
+        if block_meta_offset > bloom_offset - 4 {
+            bail!(Error::Corruption(format!("SST {id} has an invalid footer")));
+        }
         let raw_meta = file.read(block_meta_offset, bloom_offset - 4 - block_meta_offset)?;
-        let (block_meta, max_ts) = BlockMeta::decode_block_meta(&raw_meta[..])?;
+        let SstMeta {
+            block_meta,
+            time_range,
+            entry_counts,
+            properties,
+            block_filters,
+            value_ranges,
+            compressed_values,
+            value_checksums,
+            prefix_extractor,
+        } = BlockMeta::decode_block_meta(&raw_meta[..])?;
+        if block_meta.is_empty() {
+            bail!(Error::Corruption(format!("SST {id} has no blocks")));
+        }
         Ok(Self {
             file,
             first_key: block_meta.first().unwrap().first_key.clone(),
@@ -182,8 +751,16 @@ impl SsTable {
             block_meta_offset: block_meta_offset as usize,
             id,
             block_cache,
-            bloom: Some(bloom_filter),
-            max_ts,
+            lazy_bloom: bloom_filter.is_none().then(OnceLock::new),
+            bloom: bloom_filter,
+            block_filters,
+            value_ranges,
+            compressed_values,
+            value_checksums,
+            prefix_extractor,
+            time_range,
+            entry_counts,
+            properties,
         })
     }
 
@@ -195,7 +772,7 @@ impl SsTable {
         last_key: KeyBytes,
     ) -> Self {
         Self {
-            file: FileObject(None, file_size),
+            file: FileObject(None, file_size, Arc::default()),
             block_meta: vec![],
             block_meta_offset: 0,
             id,
@@ -203,46 +780,266 @@ impl SsTable {
             first_key,
             last_key,
             bloom: None,
-            max_ts: 0,
+            lazy_bloom: None,
+            block_filters: Vec::new(),
+            value_ranges: None,
+            compressed_values: false,
+            value_checksums: false,
+            prefix_extractor: None,
+            time_range: SstTimeRange {
+                min_ts: 0,
+                max_ts: 0,
+                min_write_time: 0,
+                max_write_time: 0,
+            },
+            entry_counts: SstEntryCounts::default(),
+            properties: SstProperties::default(),
         }
     }
 
-    /// Read a block from the disk.
-    pub fn read_block(&self, block_idx: usize) -> Result<Arc<Block>> {
-        let offset = self.block_meta[block_idx].offset;
-        let offset_end = self
-            .block_meta
+    pub(crate) fn block_end(&self, block_idx: usize) -> usize {
+        self.block_meta
             .get(block_idx + 1)
-            .map_or(self.block_meta_offset, |x| x.offset);
-        let block_len = offset_end - offset - 4;
-        let block_data_with_chksum: Vec<u8> = self
-            .file
-            .read(offset as u64, (offset_end - offset) as u64)?;
+            .map_or(self.block_meta_offset, |x| x.offset)
+    }
+
+    fn decode_block(block_data_with_chksum: &[u8], verify_checksum: bool) -> Result<Arc<Block>> {
+        let block_len = block_data_with_chksum.len() - 4;
         let block_data = &block_data_with_chksum[..block_len];
         let checksum = (&block_data_with_chksum[block_len..]).get_u32();
-        if checksum != crc32fast::hash(block_data) {
-            bail!("block checksum mismatched");
+        if verify_checksum && checksum != block_checksum(block_data) {
+            bail!(Error::Corruption("block checksum mismatched".to_string()));
         }
         Ok(Arc::new(Block::decode(block_data)))
     }
 
+    /// Read a block from the disk.
+    pub fn read_block(&self, block_idx: usize) -> Result<Arc<Block>> {
+        self.read_blocks(block_idx, block_idx + 1, true)
+            .map(|mut blocks| blocks.pop().unwrap())
+    }
+
+    /// Read the blocks in `start..end` from the disk with a single I/O, bypassing the block cache.
+    pub fn read_blocks(
+        &self,
+        start: usize,
+        end: usize,
+        verify_checksums: bool,
+    ) -> Result<Vec<Arc<Block>>> {
+        let offset = self.block_meta[start].offset;
+        let data = self
+            .file
+            .read(offset as u64, (self.block_end(end - 1) - offset) as u64)?;
+        (start..end)
+            .map(|idx| {
+                let begin = self.block_meta[idx].offset - offset;
+                let end = self.block_end(idx) - offset;
+                Self::decode_block(&data[begin..end], verify_checksums)
+            })
+            .collect()
+    }
+
     /// Read a block from disk, with block cache.
     pub fn read_block_cached(&self, block_idx: usize) -> Result<Arc<Block>> {
         if let Some(ref block_cache) = self.block_cache {
             let blk = block_cache
-                .try_get_with((self.id, block_idx), || self.read_block(block_idx))
-                .map_err(|e| anyhow!("{}", e))?;
+                .try_get_with((self.id, block_idx), || {
+                    self.read_block_through_tier(block_cache, block_idx)
+                })
+                .map_err(|e| match e.downcast_ref::<Error>() {
+                    Some(Error::Corruption(msg)) => Error::Corruption(msg.clone()).into(),
+                    _ => anyhow!("{}", e),
+                })?;
             Ok(blk)
         } else {
             self.read_block(block_idx)
         }
     }
 
-    /// Find the block that may contain `key`.
+    /// Read a block from the persistent tier of `block_cache`, or from the disk on a miss, adding it to the tier.
+    fn read_block_through_tier(
+        &self,
+        block_cache: &BlockCache,
+        block_idx: usize,
+    ) -> Result<Arc<Block>> {
+        let Some(tier) = block_cache.persistent_tier() else {
+            return self.read_block(block_idx);
+        };
+        let unique_id = self.properties.unique_id;
+        if let Some(blk) = tier.get(unique_id, block_idx) {
+            return Ok(blk);
+        }
+        let blk = self.read_block(block_idx)?;
+        // The tier only saves the next read from the SST, so a failure to write it is not the reader's problem
+        tier.insert(unique_id, block_idx, &blk).ok();
+        Ok(blk)
+    }
+
+    /// Read a block from the block cache, or from the disk according to `options` on a miss.
+    pub fn read_block_with_options(
+        &self,
+        block_idx: usize,
+        options: &ReadOptions,
+    ) -> Result<Arc<Block>> {
+        // Only verified blocks may enter the cache, as other readers trust cached blocks.
+        if options.fill_cache && options.verify_checksums {
+            return self.read_block_cached(block_idx);
+        }
+        if let Some(blk) = self.cached_block(block_idx) {
+            return Ok(blk);
+        }
+        Ok(self
+            .read_blocks(block_idx, block_idx + 1, options.verify_checksums)?
+            .pop()
+            .unwrap())
+    }
+
+    /// The blocks after `block_idx` that a readahead of `readahead_size` bytes covers, including `block_idx`.
+    pub(crate) fn readahead_end(&self, block_idx: usize, readahead_size: usize) -> usize {
+        let offset = self.block_meta[block_idx].offset;
+        let mut end = block_idx + 1;
+        while end < self.block_meta.len() && self.block_end(end - 1) - offset < readahead_size {
+            end += 1;
+        }
+        end
+    }
+
+    /// The block in the block cache, if any.
+    pub(crate) fn cached_block(&self, block_idx: usize) -> Option<Arc<Block>> {
+        self.block_cache
+            .as_ref()
+            .and_then(|cache| cache.get(&(self.id, block_idx)))
+    }
+
+    /// Insert a block read by `read_blocks` into the block cache and its persistent tier.
+    pub(crate) fn insert_cached_block(&self, block_idx: usize, block: Arc<Block>) {
+        if let Some(cache) = &self.block_cache {
+            if let Some(tier) = cache.persistent_tier() {
+                tier.insert(self.properties.unique_id, block_idx, &block)
+                    .ok();
+            }
+            cache.insert((self.id, block_idx), block);
+        }
+    }
+
+    /// Find the first block whose last key is not less than `key`, i.e., the block holding the first entry at or
+    /// after `key`, or `num_of_blocks()` if `key` is after the whole SST. Keys compare by timestamp in reverse, so a
+    /// key with a timestamp lands on its newest version visible at that timestamp.
+    fn read_bloom(file: &FileObject, bloom_offset: u64) -> Result<Bloom> {
+        Bloom::decode(&file.read(bloom_offset, file.size() - 4 - bloom_offset)?)
+    }
+
+    /// The bloom filter of the SST, loading it if the SST is opened without it. A filter that fails to load is treated
+    /// as missing, so that lookups read the blocks instead.
+    fn bloom(&self) -> Option<&Bloom> {
+        self.bloom.as_ref().or_else(|| {
+            self.lazy_bloom
+                .as_ref()?
+                .get_or_init(|| {
+                    let raw_bloom_offset = self.file.read(self.file.size() - 4, 4).ok()?;
+                    let bloom_offset = (&raw_bloom_offset[..]).get_u32() as u64;
+                    Self::read_bloom(&self.file, bloom_offset).ok()
+                })
+                .as_ref()
+        })
+    }
+
     pub fn find_block_idx(&self, key: KeySlice) -> usize {
         self.block_meta
-            .partition_point(|meta| meta.first_key.as_key_slice() <= key)
-            .saturating_sub(1)
+            .partition_point(|meta| meta.last_key.as_key_slice() < key)
+    }
+
+    /// Whether the SST may hold a version of the user key `key` according to its bloom filter, and its block filters
+    /// if built with them. With block filters, the SST is ruled out when the filters of all blocks whose key range
+    /// covers `key` miss, without reading any of those blocks.
+    pub fn may_contain_key(&self, key: &[u8]) -> bool {
+        let key_hash = farmhash::fingerprint32(key);
+        if self
+            .bloom()
+            .is_some_and(|bloom| !bloom.may_contain(key_hash))
+        {
+            return false;
+        }
+        if self.block_filters.is_empty() {
+            return true;
+        }
+        let start = self
+            .block_meta
+            .partition_point(|meta| meta.last_key.key_ref() < key);
+        self.block_meta[start..]
+            .iter()
+            .zip(&self.block_filters[start..])
+            .take_while(|(meta, _)| meta.first_key.key_ref() <= key)
+            .any(|(_, filter)| filter.may_contain(key_hash))
+    }
+
+    /// Whether the SST may hold a key starting with `prefix`, a prefix under `extractor`, according to its bloom
+    /// filter. Only an SST built with the same extractor holds the prefixes in its filter, others may hold any.
+    pub fn may_contain_prefix(&self, extractor: &dyn PrefixExtractor, prefix: &[u8]) -> bool {
+        if self.prefix_extractor.as_deref() != Some(&extractor.name()) {
+            return true;
+        }
+        let prefix_hash = farmhash::fingerprint32(prefix);
+        self.bloom()
+            .is_none_or(|bloom| bloom.may_contain(prefix_hash))
+    }
+
+    /// The name of the prefix extractor whose prefixes are in the bloom filter, if any.
+    pub fn prefix_extractor(&self) -> Option<&str> {
+        self.prefix_extractor.as_deref()
+    }
+
+    /// The range of the values in each block, if built with a value schema.
+    pub fn value_ranges(&self) -> Option<&BlockValueRanges> {
+        self.value_ranges.as_ref()
+    }
+
+    /// The meta of the data blocks.
+    pub fn block_meta(&self) -> &[BlockMeta] {
+        &self.block_meta
+    }
+
+    /// Whether the values are stored with a flag telling whether each one is compressed.
+    pub fn has_compressed_values(&self) -> bool {
+        self.compressed_values
+    }
+
+    /// Whether each value is stored with the checksum of its entry.
+    pub fn has_value_checksums(&self) -> bool {
+        self.value_checksums
+    }
+
+    pub fn has_block_filters(&self) -> bool {
+        !self.block_filters.is_empty()
+    }
+
+    /// The memory held by the parts of the SST kept in memory while it is open: the block index, the filters loaded so
+    /// far and the value ranges of the blocks.
+    pub fn metadata_memory(&self) -> usize {
+        let index = self
+            .block_meta
+            .iter()
+            .map(|meta| {
+                std::mem::size_of::<BlockMeta>()
+                    + meta.first_key.raw_len()
+                    + meta.last_key.raw_len()
+            })
+            .sum::<usize>();
+        // A lazily loaded filter only counts once loaded
+        let bloom = self
+            .bloom
+            .as_ref()
+            .or_else(|| self.lazy_bloom.as_ref()?.get()?.as_ref())
+            .map_or(0, Bloom::encoded_size);
+        let block_filters = self
+            .block_filters
+            .iter()
+            .map(Bloom::encoded_size)
+            .sum::<usize>();
+        let value_ranges = self.value_ranges.as_ref().map_or(0, |value_ranges| {
+            value_ranges.ranges.len() * std::mem::size_of::<Option<(u64, u64)>>()
+        });
+        index + bloom + block_filters + value_ranges
     }
 
     /// Get number of data blocks.
@@ -267,6 +1064,25 @@ impl SsTable {
     }
 
     pub fn max_ts(&self) -> u64 {
-        self.max_ts
+        self.time_range.max_ts
+    }
+
+    pub fn time_range(&self) -> &SstTimeRange {
+        &self.time_range
+    }
+
+    pub fn entry_counts(&self) -> &SstEntryCounts {
+        &self.entry_counts
+    }
+
+    pub fn properties(&self) -> &SstProperties {
+        &self.properties
+    }
+
+    /// The time the SST was built, as recorded in its properties, which survives copying the file. Returns `None` for
+    /// in-memory SSTs.
+    pub fn creation_time(&self) -> Option<SystemTime> {
+        self.file.0.as_ref()?;
+        Some(UNIX_EPOCH + Duration::from_millis(self.properties.creation_time))
     }
 }
@@ -17,6 +17,8 @@
 use anyhow::{Result, bail};
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 
+use crate::lsm_error::Error;
+
 /// Implements a bloom filter
 pub struct Bloom {
     /// data of filter in bits
@@ -61,9 +63,14 @@ impl<T: AsMut<[u8]>> BitSliceMut for T {
 impl Bloom {
     /// Decode a bloom filter
     pub fn decode(buf: &[u8]) -> Result<Self> {
+        if buf.len() < 5 {
+            bail!(Error::Corruption("bloom filter too short".to_string()));
+        }
         let checksum = (&buf[buf.len() - 4..buf.len()]).get_u32();
         if checksum != crc32fast::hash(&buf[..buf.len() - 4]) {
-            bail!("checksum mismatched for bloom filters");
+            bail!(Error::Corruption(
+                "checksum mismatched for bloom filters".to_string()
+            ));
         }
         let filter = &buf[..buf.len() - 5];
         let k = buf[buf.len() - 5];
@@ -73,6 +80,11 @@ impl Bloom {
         })
     }
 
+    /// The size of the encoded filter, including the checksum.
+    pub fn encoded_size(&self) -> usize {
+        self.filter.len() + std::mem::size_of::<u8>() + std::mem::size_of::<u32>()
+    }
+
     /// Encode a bloom filter
     pub fn encode(&self, buf: &mut Vec<u8>) {
         let offset = buf.len();
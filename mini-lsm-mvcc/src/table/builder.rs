@@ -14,15 +14,25 @@
 
 use std::path::Path;
 use std::sync::Arc;
+use std::time::SystemTime;
 
-use anyhow::Result;
+use anyhow::{Result, bail};
 use bytes::BufMut;
 
 use super::bloom::Bloom;
-use super::{BlockMeta, FileObject, SsTable};
-use crate::block::BlockBuilder;
+use super::{
+    BlockMeta, BlockValueRanges, FileObject, SsTable, SstEntryCounts, SstMeta, SstOrigin,
+    SstProperties, SstTimeRange, TablePropertiesCollector, ValueSchema, encode_value,
+    entry_checksum, unix_millis,
+};
+use crate::block::{BlockBuilder, DEFAULT_RESTART_INTERVAL};
+use crate::checksum::block_checksum;
+use crate::file_system::FileSystemOptions;
+use crate::io_retry::IoRetry;
 use crate::key::{KeySlice, KeyVec};
+use crate::lsm_error::Error;
 use crate::lsm_storage::BlockCache;
+use crate::prefix::{PrefixExtractor, PrefixExtractorOptions};
 
 /// Builds an SSTable from key-value pairs.
 pub struct SsTableBuilder {
@@ -32,8 +42,43 @@ pub struct SsTableBuilder {
     data: Vec<u8>,
     pub(crate) meta: Vec<BlockMeta>,
     block_size: usize,
+    block_restart_interval: usize,
     key_hashes: Vec<u32>,
+    /// The filters of the finished blocks, if enabled.
+    block_filters: Option<Vec<Bloom>>,
+    /// The bits per key of the bloom filters, sized for a 1% false positive rate if not set.
+    bloom_bits_per_key: Option<usize>,
+    /// The index of the first key hash of the current block in `key_hashes`.
+    block_first_hash: usize,
+    /// The value ranges of the finished blocks, if built with a value schema.
+    value_ranges: Option<BlockValueRanges>,
+    /// The value range of the current block, `None` once it holds a value that is not a number.
+    block_value_range: Option<(u64, u64)>,
+    /// Compress the values longer than this, if set.
+    value_compression_threshold: Option<usize>,
+    /// Store the checksum of each entry after its value.
+    value_checksums: bool,
+    /// The encoding of the value being added, if values are compressed or checksummed.
+    value_buf: Vec<u8>,
+    /// Add the prefixes of the keys to the bloom filter, if set.
+    prefix_extractor: Option<PrefixExtractorOptions>,
+    /// The hashes of the distinct prefixes added, kept out of the block filters.
+    prefix_hashes: Vec<u32>,
+    /// The prefix of the last key added, if it has one.
+    last_prefix: Option<Vec<u8>>,
+    min_ts: u64,
     max_ts: u64,
+    write_time: Option<(u64, u64)>,
+    entry_counts: SstEntryCounts,
+    origin: SstOrigin,
+    /// The random bits of the unique id, drawn when building if not set.
+    unique_id_bits: Option<u128>,
+    io_retry: Option<Arc<IoRetry>>,
+    file_system: FileSystemOptions,
+    collectors: Vec<Box<dyn TablePropertiesCollector>>,
+    check_key_order: bool,
+    /// The first key added out of order, reported by `build`.
+    key_order_error: Option<String>,
 }
 
 impl SsTableBuilder {
@@ -45,35 +90,239 @@ impl SsTableBuilder {
             first_key: KeyVec::new(),
             last_key: KeyVec::new(),
             block_size,
+            block_restart_interval: DEFAULT_RESTART_INTERVAL,
             builder: BlockBuilder::new(block_size),
             key_hashes: Vec::new(),
+            block_filters: None,
+            block_first_hash: 0,
+            value_ranges: None,
+            block_value_range: Some((u64::MAX, 0)),
+            value_compression_threshold: None,
+            value_checksums: false,
+            value_buf: Vec::new(),
+            prefix_extractor: None,
+            prefix_hashes: Vec::new(),
+            last_prefix: None,
+            min_ts: u64::MAX,
             max_ts: 0,
+            write_time: None,
+            entry_counts: SstEntryCounts::default(),
+            origin: SstOrigin::default(),
+            bloom_bits_per_key: None,
+            unique_id_bits: None,
+            io_retry: None,
+            file_system: FileSystemOptions::Std,
+            collectors: Vec::new(),
+            check_key_order: cfg!(debug_assertions),
+            key_order_error: None,
         }
     }
 
+    /// Check that the keys are added in strictly increasing order, i.e., by increasing key and decreasing timestamp,
+    /// and fail `build` otherwise. Enabled by default in debug builds.
+    pub fn set_check_key_order(&mut self, check_key_order: bool) {
+        self.check_key_order = check_key_order;
+    }
+
+    fn key_order_violation(&self, key: KeySlice) -> Option<String> {
+        // `last_key` is the previously added key, or empty before the first one
+        if !self.last_key.is_empty() && key <= self.last_key.as_key_slice() {
+            Some(format!(
+                "key {:?} added to SST after {:?}",
+                key,
+                self.last_key.as_key_slice()
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// Adds a key-value pair to SSTable, failing if it is not ordered after the previous one.
+    pub fn try_add(&mut self, key: KeySlice, value: &[u8]) -> Result<()> {
+        if let Some(msg) = self.key_order_violation(key) {
+            bail!(Error::InvalidArgument(msg));
+        }
+        self.add_unchecked(key, value);
+        Ok(())
+    }
+
     /// Adds a key-value pair to SSTable
     pub fn add(&mut self, key: KeySlice, value: &[u8]) {
+        if self.check_key_order && self.key_order_error.is_none() {
+            self.key_order_error = self.key_order_violation(key);
+        }
+        self.add_unchecked(key, value);
+    }
+
+    fn add_unchecked(&mut self, key: KeySlice, value: &[u8]) {
         if self.first_key.is_empty() {
             self.first_key.set_from_slice(key);
         }
 
-        if key.ts() > self.max_ts {
-            self.max_ts = key.ts();
+        self.min_ts = self.min_ts.min(key.ts());
+        self.max_ts = self.max_ts.max(key.ts());
+        self.entry_counts.num_entries += 1;
+        if value.is_empty() {
+            self.entry_counts.num_deletes += 1;
+        }
+        for collector in &mut self.collectors {
+            collector.add(key, value);
+        }
+        let key_hash = farmhash::fingerprint32(key.key_ref());
+        self.record_prefix(key.key_ref());
+
+        let mut value_buf = std::mem::take(&mut self.value_buf);
+        let encoded = match self.value_compression_threshold {
+            // deletes are stored as is
+            Some(threshold) if !value.is_empty() => {
+                encode_value(value, threshold, &mut value_buf);
+                true
+            }
+            _ => false,
+        };
+        if self.value_checksums {
+            if !encoded {
+                value_buf.clear();
+                value_buf.put_slice(value);
+            }
+            value_buf.put_u32(entry_checksum(key, value));
         }
-        self.key_hashes.push(farmhash::fingerprint32(key.key_ref()));
+        let stored_value = if encoded || self.value_checksums {
+            &value_buf[..]
+        } else {
+            value
+        };
+        if !self.builder.add(key, stored_value) {
+            // create a new block builder and append block data
+            self.finish_block();
 
-        if self.builder.add(key, value) {
-            self.last_key.set_from_slice(key);
+            // add the key-value pair to the next block
+            assert!(self.builder.add(key, stored_value));
+            self.first_key.set_from_slice(key);
+        }
+        self.value_buf = value_buf;
+        self.key_hashes.push(key_hash);
+        self.last_key.set_from_slice(key);
+        self.record_value(value);
+    }
+
+    fn record_prefix(&mut self, key: &[u8]) {
+        let Some(extractor) = &self.prefix_extractor else {
+            return;
+        };
+        let Some(prefix) = extractor.prefix(key) else {
             return;
+        };
+        // the keys sharing a prefix are added one after another
+        if self.last_prefix.as_deref() != Some(prefix) {
+            self.prefix_hashes.push(farmhash::fingerprint32(prefix));
+            self.last_prefix = Some(prefix.to_vec());
         }
+    }
 
-        // create a new block builder and append block data
-        self.finish_block();
+    fn record_value(&mut self, value: &[u8]) {
+        let Some(value_ranges) = &self.value_ranges else {
+            return;
+        };
+        // deletes do not hold a value
+        if value.is_empty() {
+            return;
+        }
+        self.block_value_range =
+            match (self.block_value_range, value_ranges.schema.order_key(value)) {
+                (Some((min, max)), Some(value)) => Some((min.min(value), max.max(value))),
+                _ => None,
+            };
+    }
 
-        // add the key-value pair to the next block
-        assert!(self.builder.add(key, value));
-        self.first_key.set_from_slice(key);
-        self.last_key.set_from_slice(key);
+    /// Record that the entries added were written between `min` and `max` (in milliseconds since the UNIX epoch). If
+    /// this is never called, the entries are considered to be written when the SST is built.
+    pub fn add_write_time_range(&mut self, min: u64, max: u64) {
+        self.write_time = Some(match self.write_time {
+            Some((cur_min, cur_max)) => (cur_min.min(min), cur_max.max(max)),
+            None => (min, max),
+        });
+    }
+
+    /// Store a bloom filter for each data block besides the one for the whole SST, so that a point lookup can skip the
+    /// blocks covering its key range that do not hold the key. Must be set before adding any key.
+    pub fn set_block_filters(&mut self, block_filters: bool) {
+        self.block_filters = block_filters.then(Vec::new);
+    }
+
+    /// Size the bloom filters of the SST and its blocks with `bits_per_key` bits per key instead of for a 1% false
+    /// positive rate, e.g., fewer bits for the bottom level, which holds most keys but serves few lookups.
+    pub fn set_bloom_bits_per_key(&mut self, bits_per_key: usize) {
+        self.bloom_bits_per_key = Some(bits_per_key);
+    }
+
+    fn bloom_bits_for(&self, entries: usize) -> usize {
+        self.bloom_bits_per_key
+            .unwrap_or_else(|| Bloom::bloom_bits_per_key(entries, 0.01))
+    }
+
+    /// Store a full key every `interval` entries of each data block instead of every 16. Must be set before adding any
+    /// key.
+    pub fn set_block_restart_interval(&mut self, interval: usize) {
+        self.block_restart_interval = interval;
+        self.builder = BlockBuilder::with_restart_interval(self.block_size, interval);
+    }
+
+    /// Record the range of the values in each data block, interpreting them as `schema`, so that a scan filtering on
+    /// the values can skip the blocks out of its range. Must be set before adding any key.
+    pub fn set_value_schema(&mut self, schema: ValueSchema) {
+        self.value_ranges = Some(BlockValueRanges {
+            schema,
+            ranges: Vec::new(),
+        });
+    }
+
+    /// Compress each value longer than `threshold` bytes on its own, storing every value with a flag telling whether it
+    /// is compressed. Must be set before adding any key.
+    pub fn set_value_compression_threshold(&mut self, threshold: usize) {
+        self.value_compression_threshold = Some(threshold);
+    }
+
+    /// Store the checksum of each entry after its value, so that reads verifying checksums can tell which entry of a
+    /// block is damaged. Must be set before adding any key.
+    pub fn set_value_checksums(&mut self, value_checksums: bool) {
+        self.value_checksums = value_checksums;
+    }
+
+    /// Add the prefix of each key under `extractor` to the bloom filter of the SST, so that scans within a prefix can
+    /// skip it. Must be set before adding any key.
+    pub fn set_prefix_extractor(&mut self, extractor: PrefixExtractorOptions) {
+        self.prefix_extractor = Some(extractor);
+    }
+
+    /// Record what is writing the SST in its properties.
+    pub fn set_origin(&mut self, origin: SstOrigin) {
+        self.origin = origin;
+    }
+
+    /// Derive the unique id of the SST from `bits` instead of random ones, e.g., to build the same SSTs in every run.
+    pub fn set_unique_id_bits(&mut self, bits: u128) {
+        self.unique_id_bits = Some(bits);
+    }
+
+    /// Retry the reads of the built SST under `io_retry`.
+    pub(crate) fn set_io_retry(&mut self, io_retry: Arc<IoRetry>) {
+        self.io_retry = Some(io_retry);
+    }
+
+    /// Write the built SST to `file_system`.
+    pub(crate) fn set_file_system(&mut self, file_system: FileSystemOptions) {
+        self.file_system = file_system;
+    }
+
+    /// Let `collector` observe the entries added from now on and add its properties to the SST.
+    pub fn add_properties_collector(&mut self, collector: Box<dyn TablePropertiesCollector>) {
+        self.collectors.push(collector);
+    }
+
+    /// Whether no entry has been added.
+    pub fn is_empty(&self) -> bool {
+        self.meta.is_empty() && self.builder.is_empty()
     }
 
     /// Get the estimated size of the SSTable.
@@ -82,14 +331,27 @@ impl SsTableBuilder {
     }
 
     fn finish_block(&mut self) {
-        let builder = std::mem::replace(&mut self.builder, BlockBuilder::new(self.block_size));
+        let builder = std::mem::replace(
+            &mut self.builder,
+            BlockBuilder::with_restart_interval(self.block_size, self.block_restart_interval),
+        );
         let encoded_block = builder.build().encode();
         self.meta.push(BlockMeta {
             offset: self.data.len(),
             first_key: std::mem::take(&mut self.first_key).into_key_bytes(),
             last_key: std::mem::take(&mut self.last_key).into_key_bytes(),
         });
-        let checksum = crc32fast::hash(&encoded_block);
+        let bits_per_key = self.bloom_bits_for(self.key_hashes.len() - self.block_first_hash);
+        if let Some(block_filters) = &mut self.block_filters {
+            let key_hashes = &self.key_hashes[self.block_first_hash..];
+            block_filters.push(Bloom::build_from_key_hashes(key_hashes, bits_per_key));
+            self.block_first_hash = self.key_hashes.len();
+        }
+        let block_value_range = self.block_value_range.replace((u64::MAX, 0));
+        if let Some(value_ranges) = &mut self.value_ranges {
+            value_ranges.ranges.push(block_value_range);
+        }
+        let checksum = block_checksum(&encoded_block);
         self.data.extend(encoded_block);
         self.data.put_u32(checksum);
     }
@@ -101,29 +363,73 @@ impl SsTableBuilder {
         block_cache: Option<Arc<BlockCache>>,
         path: impl AsRef<Path>,
     ) -> Result<SsTable> {
+        if let Some(msg) = self.key_order_error.take() {
+            bail!(Error::InvalidArgument(msg));
+        }
         self.finish_block();
+        self.key_hashes.append(&mut self.prefix_hashes);
+        let bits_per_key = self.bloom_bits_for(self.key_hashes.len());
         let mut buf = self.data;
         let meta_offset = buf.len();
-        BlockMeta::encode_block_meta(&self.meta, self.max_ts, &mut buf);
-        buf.put_u32(meta_offset as u32);
-        let bloom = Bloom::build_from_key_hashes(
-            &self.key_hashes,
-            Bloom::bloom_bits_per_key(self.key_hashes.len(), 0.01),
+        let (min_write_time, max_write_time) = self.write_time.unwrap_or_else(|| {
+            let now = unix_millis(SystemTime::now());
+            (now, now)
+        });
+        let time_range = SstTimeRange {
+            min_ts: self.min_ts.min(self.max_ts),
+            max_ts: self.max_ts,
+            min_write_time,
+            max_write_time,
+        };
+        let mut properties = SstProperties::new(
+            self.origin,
+            self.unique_id_bits.unwrap_or_else(rand::random),
         );
+        for collector in &mut self.collectors {
+            properties.user_collected.extend(collector.finish());
+        }
+        let meta = SstMeta {
+            block_meta: self.meta,
+            time_range,
+            entry_counts: self.entry_counts,
+            properties,
+            block_filters: self.block_filters.unwrap_or_default(),
+            value_ranges: self.value_ranges,
+            compressed_values: self.value_compression_threshold.is_some(),
+            value_checksums: self.value_checksums,
+            prefix_extractor: self
+                .prefix_extractor
+                .as_ref()
+                .map(|extractor| extractor.name()),
+        };
+        BlockMeta::encode_block_meta(&meta, &mut buf);
+        buf.put_u32(meta_offset as u32);
+        let bloom = Bloom::build_from_key_hashes(&self.key_hashes, bits_per_key);
         let bloom_offset = buf.len();
         bloom.encode(&mut buf);
         buf.put_u32(bloom_offset as u32);
-        let file = FileObject::create(path.as_ref(), buf)?;
+        let mut file = FileObject::create_in(&self.file_system, path.as_ref(), buf)?;
+        if let Some(io_retry) = self.io_retry {
+            file.set_io_retry(io_retry);
+        }
         Ok(SsTable {
             id,
             file,
-            first_key: self.meta.first().unwrap().first_key.clone(),
-            last_key: self.meta.last().unwrap().last_key.clone(),
-            block_meta: self.meta,
+            first_key: meta.block_meta.first().unwrap().first_key.clone(),
+            last_key: meta.block_meta.last().unwrap().last_key.clone(),
+            block_meta: meta.block_meta,
             block_meta_offset: meta_offset,
             block_cache,
             bloom: Some(bloom),
-            max_ts: self.max_ts,
+            lazy_bloom: None,
+            block_filters: meta.block_filters,
+            value_ranges: meta.value_ranges,
+            compressed_values: meta.compressed_values,
+            value_checksums: meta.value_checksums,
+            prefix_extractor: meta.prefix_extractor,
+            time_range: meta.time_range,
+            entry_counts: meta.entry_counts,
+            properties: meta.properties,
         })
     }
 
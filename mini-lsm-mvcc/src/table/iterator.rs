@@ -12,79 +12,372 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::ops::Bound;
 use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{Result, bail};
+use bytes::{Buf, Bytes};
+use parking_lot::Mutex;
 
-use super::SsTable;
-use crate::block::BlockIterator;
-use crate::iterators::StorageIterator;
+use super::{SsTable, VALUE_CHECKSUM_SIZE, decode_value, decompress_value, entry_checksum};
+use crate::block::{Block, BlockIterator};
+use crate::block_cache::BlockCache;
+use crate::iterators::{SeekableIterator, StorageIterator};
 use crate::key::KeySlice;
+use crate::lsm_error::Error;
+use crate::lsm_storage::ReadOptions;
+
+/// A block that a scan skipped because it failed checksum verification, see `ReadOptions::skip_corrupted_blocks`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CorruptedBlock {
+    pub sst_id: usize,
+    pub block_idx: usize,
+    pub error: String,
+    /// The first and last keys of the block, whose versions up to the latest commit timestamp of the SST are hidden
+    /// from the scan, as the block may have held newer ones.
+    pub first_key: Bytes,
+    pub last_key: Bytes,
+    pub max_ts: u64,
+}
+
+/// The blocks skipped by a scan, shared between the scan and its SST iterators, including the ones recreated by
+/// polling.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct CorruptionReport(Arc<Mutex<Vec<CorruptedBlock>>>);
+
+impl CorruptionReport {
+    /// The skipped blocks, in the order they were found.
+    pub(crate) fn blocks(&self) -> Vec<CorruptedBlock> {
+        self.0.lock().clone()
+    }
+
+    /// Whether the version of `key` at `ts` may be older than one in a skipped block.
+    pub(crate) fn masks(&self, key: &[u8], ts: u64) -> bool {
+        self.0.lock().iter().any(|block| {
+            ts <= block.max_ts && key >= block.first_key.as_ref() && key <= block.last_key.as_ref()
+        })
+    }
+
+    /// Add a skipped block unless it is already reported, e.g., by the iterator a polling scan recreated.
+    fn add(&self, block: CorruptedBlock) {
+        let mut blocks = self.0.lock();
+        if !blocks
+            .iter()
+            .any(|b| b.sst_id == block.sst_id && b.block_idx == block.block_idx)
+        {
+            blocks.push(block);
+        }
+    }
+}
+
+/// Loads the blocks of an SST according to the read options, keeping the blocks of the last readahead.
+struct BlockLoader {
+    options: ReadOptions,
+    /// Where the skipped blocks are added if `options.skip_corrupted_blocks` is set. Blocks are only skipped with one.
+    report: Option<CorruptionReport>,
+    readahead: Vec<Arc<Block>>,
+    readahead_start: usize,
+    /// The block cache the last loaded block is counted as pinned in, and its size.
+    pinned: Option<(Arc<BlockCache>, usize)>,
+}
+
+impl BlockLoader {
+    fn new(options: ReadOptions, report: Option<CorruptionReport>) -> Self {
+        Self {
+            options,
+            report: report.filter(|_| options.skip_corrupted_blocks),
+            readahead: Vec::new(),
+            readahead_start: 0,
+            pinned: None,
+        }
+    }
+
+    /// Load the block at `blk_idx` for the iterator to hold until the next load, counting it as pinned in the block
+    /// cache of `table`.
+    fn load(&mut self, table: &SsTable, blk_idx: usize) -> Result<Arc<Block>> {
+        let blk = self.load_or_skip(table, blk_idx)?;
+        self.unpin();
+        if let Some(block_cache) = &table.block_cache {
+            block_cache.pin(blk.size());
+            self.pinned = Some((block_cache.clone(), blk.size()));
+        }
+        Ok(blk)
+    }
+
+    fn unpin(&mut self) {
+        if let Some((block_cache, size)) = self.pinned.take() {
+            block_cache.unpin(size);
+        }
+    }
+
+    /// Load the block at `blk_idx`, or an empty one in its place if it is corrupted and the options skip such blocks.
+    fn load_or_skip(&mut self, table: &SsTable, blk_idx: usize) -> Result<Arc<Block>> {
+        let Some(report) = &self.report else {
+            return self.load_block(table, blk_idx);
+        };
+        let report = report.clone();
+        let err = match self.load_block(table, blk_idx) {
+            Ok(blk) => return Ok(blk),
+            Err(err) if matches!(err.downcast_ref(), Some(Error::Corruption(_))) => err,
+            Err(err) => return Err(err),
+        };
+        // A readahead fails as a whole, so read the block alone in case another block is the corrupted one.
+        let err = if self.options.readahead_size > 0 {
+            match table.read_block_with_options(blk_idx, &self.options) {
+                Ok(blk) => return Ok(blk),
+                Err(err) => err,
+            }
+        } else {
+            err
+        };
+        eprintln!(
+            "skipping corrupted block {blk_idx} of SST {}: {err:#}",
+            table.sst_id()
+        );
+        report.add(CorruptedBlock {
+            sst_id: table.sst_id(),
+            block_idx: blk_idx,
+            error: format!("{err:#}"),
+            first_key: Bytes::copy_from_slice(table.block_meta[blk_idx].first_key.key_ref()),
+            last_key: Bytes::copy_from_slice(table.block_meta[blk_idx].last_key.key_ref()),
+            max_ts: table.time_range().max_ts,
+        });
+        Ok(Arc::new(Block::empty()))
+    }
+
+    fn load_block(&mut self, table: &SsTable, blk_idx: usize) -> Result<Arc<Block>> {
+        if self.options.readahead_size == 0 {
+            return table.read_block_with_options(blk_idx, &self.options);
+        }
+        if let Some(blk) = blk_idx
+            .checked_sub(self.readahead_start)
+            .and_then(|idx| self.readahead.get(idx))
+        {
+            return Ok(blk.clone());
+        }
+        if let Some(blk) = table.cached_block(blk_idx) {
+            return Ok(blk);
+        }
+        let end = table.readahead_end(blk_idx, self.options.readahead_size);
+        self.readahead = table.read_blocks(blk_idx, end, self.options.verify_checksums)?;
+        self.readahead_start = blk_idx;
+        if self.options.fill_cache && self.options.verify_checksums {
+            for (idx, blk) in self.readahead.iter().enumerate() {
+                table.insert_cached_block(blk_idx + idx, blk.clone());
+            }
+        }
+        Ok(self.readahead[0].clone())
+    }
+
+    /// Drop the blocks read ahead from the previous SST.
+    fn reset(&mut self) {
+        self.readahead.clear();
+    }
+}
+
+impl Drop for BlockLoader {
+    fn drop(&mut self) {
+        self.unpin();
+    }
+}
 
 /// An iterator over the contents of an SSTable.
 pub struct SsTableIterator {
     table: Arc<SsTable>,
     blk_iter: BlockIterator,
     blk_idx: usize,
+    loader: BlockLoader,
+    /// The current value if it is decompressed.
+    value_buf: Vec<u8>,
+    /// The iterator ends before the first key past this bound, without loading the blocks after it.
+    upper: Bound<Bytes>,
+    /// Whether the current key is past `upper`.
+    past_upper: bool,
 }
 
-impl SsTableIterator {
-    fn seek_to_first_inner(table: &Arc<SsTable>) -> Result<(usize, BlockIterator)> {
-        Ok((
-            0,
-            BlockIterator::create_and_seek_to_first(table.read_block_cached(0)?),
-        ))
+/// A value of `table` as stored in its block, without the checksum following it.
+fn stored_value<'a>(table: &SsTable, value: &'a [u8]) -> &'a [u8] {
+    if table.value_checksums {
+        &value[..value.len() - VALUE_CHECKSUM_SIZE]
+    } else {
+        value
+    }
+}
+
+/// Whether the user key `key` is past the upper bound `upper`.
+pub(crate) fn is_past_upper(key: &[u8], upper: &Bound<Bytes>) -> bool {
+    match upper {
+        Bound::Included(upper) => key > upper.as_ref(),
+        Bound::Excluded(upper) => key >= upper.as_ref(),
+        Bound::Unbounded => false,
     }
+}
 
+impl SsTableIterator {
     /// Create a new iterator and seek to the first key-value pair.
     pub fn create_and_seek_to_first(table: Arc<SsTable>) -> Result<Self> {
-        let (blk_idx, blk_iter) = Self::seek_to_first_inner(&table)?;
-        let iter = Self {
+        Self::create_and_seek_to_first_with_options(table, ReadOptions::default())
+    }
+
+    /// Create a new iterator that reads blocks according to `options` and seek to the first key-value pair.
+    pub fn create_and_seek_to_first_with_options(
+        table: Arc<SsTable>,
+        options: ReadOptions,
+    ) -> Result<Self> {
+        Self::create_and_seek_to_first_with_report(table, options, None)
+    }
+
+    /// Like `create_and_seek_to_first_with_options`, adding the blocks skipped as corrupted to `report`.
+    pub(crate) fn create_and_seek_to_first_with_report(
+        table: Arc<SsTable>,
+        options: ReadOptions,
+        report: Option<CorruptionReport>,
+    ) -> Result<Self> {
+        let mut loader = BlockLoader::new(options, report);
+        let blk_iter = BlockIterator::create_and_seek_to_first(loader.load(&table, 0)?);
+        let mut iter = Self {
             blk_iter,
             table,
-            blk_idx,
+            blk_idx: 0,
+            loader,
+            value_buf: Vec::new(),
+            upper: Bound::Unbounded,
+            past_upper: false,
         };
+        iter.move_to_next_block_if_exhausted()?;
         Ok(iter)
     }
 
     /// Seek to the first key-value pair.
     pub fn seek_to_first(&mut self) -> Result<()> {
-        let (blk_idx, blk_iter) = Self::seek_to_first_inner(&self.table)?;
-        self.blk_idx = blk_idx;
-        self.blk_iter = blk_iter;
-        Ok(())
+        self.blk_idx = 0;
+        self.blk_iter.reset(self.loader.load(&self.table, 0)?);
+        self.blk_iter.seek_to_first();
+        self.move_to_next_block_if_exhausted()
     }
 
-    fn seek_to_key_inner(table: &Arc<SsTable>, key: KeySlice) -> Result<(usize, BlockIterator)> {
-        let mut blk_idx = table.find_block_idx(key);
-        let mut blk_iter =
-            BlockIterator::create_and_seek_to_key(table.read_block_cached(blk_idx)?, key);
-        if !blk_iter.is_valid() {
-            blk_idx += 1;
-            if blk_idx < table.num_of_blocks() {
-                blk_iter =
-                    BlockIterator::create_and_seek_to_first(table.read_block_cached(blk_idx)?);
-            }
-        }
-        Ok((blk_idx, blk_iter))
+    /// Move the iterator to the first key-value pair of another SST, reusing its key buffer.
+    pub fn reset_and_seek_to_first(&mut self, table: Arc<SsTable>) -> Result<()> {
+        self.table = table;
+        self.loader.reset();
+        self.seek_to_first()
+    }
+
+    /// Move the iterator to the first key-value pair which >= `key` in another SST, reusing its key buffer.
+    pub fn reset_and_seek_to_key(&mut self, table: Arc<SsTable>, key: KeySlice) -> Result<()> {
+        self.table = table;
+        self.loader.reset();
+        self.seek_to_key(key)
     }
 
     /// Create a new iterator and seek to the first key-value pair which >= `key`.
     pub fn create_and_seek_to_key(table: Arc<SsTable>, key: KeySlice) -> Result<Self> {
-        let (blk_idx, blk_iter) = Self::seek_to_key_inner(&table, key)?;
-        let iter = Self {
+        Self::create_and_seek_to_key_with_options(table, key, ReadOptions::default())
+    }
+
+    /// Create a new iterator that reads blocks according to `options` and seek to the first key-value pair which >=
+    /// `key`.
+    pub fn create_and_seek_to_key_with_options(
+        table: Arc<SsTable>,
+        key: KeySlice,
+        options: ReadOptions,
+    ) -> Result<Self> {
+        Self::create_and_seek_to_key_with_report(table, key, options, None)
+    }
+
+    /// Like `create_and_seek_to_key_with_options`, adding the blocks skipped as corrupted to `report`.
+    pub(crate) fn create_and_seek_to_key_with_report(
+        table: Arc<SsTable>,
+        key: KeySlice,
+        options: ReadOptions,
+        report: Option<CorruptionReport>,
+    ) -> Result<Self> {
+        let mut loader = BlockLoader::new(options, report);
+        let blk_idx = Self::seek_block_idx(&table, key);
+        let blk_iter = BlockIterator::create_and_seek_to_key(loader.load(&table, blk_idx)?, key);
+        let mut iter = Self {
             blk_iter,
             table,
             blk_idx,
+            loader,
+            value_buf: Vec::new(),
+            upper: Bound::Unbounded,
+            past_upper: false,
         };
+        iter.move_to_next_block_if_exhausted()?;
         Ok(iter)
     }
 
     /// Seek to the first key-value pair which >= `key`.
     pub fn seek_to_key(&mut self, key: KeySlice) -> Result<()> {
-        let (blk_idx, blk_iter) = Self::seek_to_key_inner(&self.table, key)?;
-        self.blk_iter = blk_iter;
-        self.blk_idx = blk_idx;
+        self.blk_idx = Self::seek_block_idx(&self.table, key);
+        self.blk_iter
+            .reset(self.loader.load(&self.table, self.blk_idx)?);
+        self.blk_iter.seek_to_key(key);
+        self.move_to_next_block_if_exhausted()
+    }
+
+    /// End the iterator before the first key past `upper`, which applies to the current key and to all later seeks and
+    /// SSTs it is reset to.
+    pub fn set_upper_bound(&mut self, upper: Bound<Bytes>) -> Result<()> {
+        self.upper = upper;
+        self.settle()
+    }
+
+    /// Whether the iterator ends before the first key of the block at `blk_idx`.
+    fn block_past_upper(&self, blk_idx: usize) -> bool {
+        is_past_upper(
+            self.table.block_meta[blk_idx].first_key.key_ref(),
+            &self.upper,
+        )
+    }
+
+    /// The block to seek to `key` in. A key after the whole SST seeks in the last block, leaving the iterator invalid.
+    fn seek_block_idx(table: &SsTable, key: KeySlice) -> usize {
+        table.find_block_idx(key).min(table.num_of_blocks() - 1)
+    }
+
+    fn move_to_next_block_if_exhausted(&mut self) -> Result<()> {
+        // Only moves past more than one block if they are skipped as corrupted, which leaves them empty.
+        while !self.blk_iter.is_valid() {
+            self.blk_idx += 1;
+            if self.blk_idx >= self.table.num_of_blocks() || self.block_past_upper(self.blk_idx) {
+                break;
+            }
+            self.blk_iter
+                .reset(self.loader.load(&self.table, self.blk_idx)?);
+            self.blk_iter.seek_to_first();
+        }
+        self.settle()
+    }
+
+    /// Check the current key against the upper bound, decompress the current value if it is compressed, and verify the
+    /// checksum of the current entry if it has one and the read options ask for it.
+    fn settle(&mut self) -> Result<()> {
+        self.past_upper =
+            self.blk_iter.is_valid() && is_past_upper(self.blk_iter.key().key_ref(), &self.upper);
+        if self.table.compressed_values && self.is_valid() {
+            let stored = stored_value(&self.table, self.blk_iter.value());
+            decompress_value(stored, &mut self.value_buf)?;
+        }
+        if self.table.value_checksums && self.loader.options.verify_checksums && self.is_valid() {
+            self.verify_entry()?;
+        }
+        Ok(())
+    }
+
+    fn verify_entry(&self) -> Result<()> {
+        let stored = self.blk_iter.value();
+        let checksum = (&stored[stored.len() - VALUE_CHECKSUM_SIZE..]).get_u32();
+        if entry_checksum(self.key(), self.value()) != checksum {
+            bail!(Error::Corruption(format!(
+                "checksum mismatched for key {:?}@{} in block {} of SST {}",
+                Bytes::copy_from_slice(self.key().key_ref()),
+                self.key().ts(),
+                self.blk_idx,
+                self.table.sst_id()
+            )));
+        }
         Ok(())
     }
 }
@@ -93,7 +386,12 @@ impl StorageIterator for SsTableIterator {
     type KeyType<'a> = KeySlice<'a>;
 
     fn value(&self) -> &[u8] {
-        self.blk_iter.value()
+        let stored = stored_value(&self.table, self.blk_iter.value());
+        if self.table.compressed_values {
+            decode_value(stored, &self.value_buf)
+        } else {
+            stored
+        }
     }
 
     fn key(&self) -> KeySlice {
@@ -101,18 +399,19 @@ impl StorageIterator for SsTableIterator {
     }
 
     fn is_valid(&self) -> bool {
-        self.blk_iter.is_valid()
+        !self.past_upper && self.blk_iter.is_valid()
     }
 
     fn next(&mut self) -> Result<()> {
         self.blk_iter.next();
-        if !self.blk_iter.is_valid() {
-            self.blk_idx += 1;
-            if self.blk_idx < self.table.num_of_blocks() {
-                self.blk_iter = BlockIterator::create_and_seek_to_first(
-                    self.table.read_block_cached(self.blk_idx)?,
-                );
-            }
+        self.move_to_next_block_if_exhausted()
+    }
+}
+
+impl SeekableIterator for SsTableIterator {
+    fn seek(&mut self, key: KeySlice) -> Result<()> {
+        if self.is_valid() && self.key() < key {
+            self.seek_to_key(key)?;
         }
         Ok(())
     }
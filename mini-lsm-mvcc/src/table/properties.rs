@@ -0,0 +1,33 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use bytes::Bytes;
+
+use crate::key::KeySlice;
+
+/// Observes the entries added to an SST while it is built, and emits named properties stored in the SST meta, which
+/// can be read back through `SsTable::properties` without reading the data blocks.
+pub trait TablePropertiesCollector: Send {
+    /// Called for every entry in key order. A delete has an empty value.
+    fn add(&mut self, key: KeySlice, value: &[u8]);
+
+    /// The properties of the SST, called once all entries are added. Names must be unique across the collectors of an
+    /// SST, otherwise the last one wins.
+    fn finish(&mut self) -> Vec<(String, Bytes)>;
+}
+
+/// Creates a collector for each SST the engine writes, by flushes, compactions and ingestion alike.
+pub trait TablePropertiesCollectorFactory: Send + Sync {
+    /// A fresh collector for one SST, called before its first entry is added.
+    fn create(&self) -> Box<dyn TablePropertiesCollector>;
+}
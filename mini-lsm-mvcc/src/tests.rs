@@ -12,7 +12,99 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod atomic_install;
+mod background_error;
+mod block_filters;
+mod block_seek;
+mod bloom_bits_per_level;
+mod bulk_export;
+mod bulk_import;
+mod change_scan;
+mod checksum;
+mod commit_pipeline;
+mod compact_file;
+mod compact_offline;
+mod compaction_cache_warming;
+mod compaction_plan;
+mod compaction_verify;
+mod concurrent_flush;
+mod concurrent_reads;
+mod conditional_write;
+mod contains;
+mod corrupted_blocks;
+mod deterministic;
+mod entry_stats;
+mod error_kinds;
+mod event_listener;
+mod export_range_db;
+mod export_snapshot;
+#[cfg(feature = "rocksdb-import")]
+mod external_table;
+mod file_system;
+mod find_block_idx;
+mod flush_split;
+mod format_options;
+mod gc;
+mod get_multi;
 mod harness;
+mod hot_keys;
+#[cfg(feature = "server")]
+mod http_server;
+mod increment;
+mod ingest;
+mod integrity;
+mod io_retry;
+mod iterator_key_buffer;
+mod iterator_seek;
+mod key_history;
+mod key_value_limits;
+mod l0_trigger;
+mod lazy_leveled;
+mod live_files;
+mod manifest_rotation;
+mod memory_usage;
+mod memtable_arena;
+mod memtable_stats;
+mod next_batch;
+mod open_modes;
+mod options_builder;
+mod parallel_recovery;
+mod pause_background;
+mod periodic_compaction;
+mod persistent_cache;
+mod pessimistic_locking;
+mod prefix_extractor;
+mod prefix_quota;
+mod read_committed;
+mod read_options;
+mod recovery_progress;
+mod repair;
+mod replication;
+#[cfg(feature = "server")]
+mod resp_server;
+mod scan_page;
+mod scan_upper_bound;
+mod scan_yield;
+mod scrub;
+mod set_options;
+mod sharded_block_cache;
+mod snapshot_consistency;
+mod snapshot_handle;
+mod space_report;
+mod split_points;
+mod sst_key_order;
+mod sst_properties;
+mod state_machine;
+mod table_properties;
+mod tombstone_compaction;
+mod trace;
+mod ts_provider;
+mod two_phase_commit;
+mod txn_stats;
+mod value_checksums;
+mod value_compression;
+mod value_stats;
+mod wal_recycle;
 mod week1_day1;
 mod week1_day2;
 mod week1_day3;
@@ -33,3 +125,6 @@ mod week3_day4;
 mod week3_day5;
 mod week3_day6;
 mod week3_day7;
+mod write_batch_with_index;
+mod write_callback;
+mod write_options;
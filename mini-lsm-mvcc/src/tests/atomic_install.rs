@@ -0,0 +1,141 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::ops::Bound;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tempfile::tempdir;
+
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+use crate::manifest::{Manifest, ManifestRecord};
+
+const NUM_KEYS: usize = 400;
+
+fn key_of(i: usize) -> Vec<u8> {
+    format!("key{i:04}").into_bytes()
+}
+
+fn options() -> LsmStorageOptions {
+    LsmStorageOptions::builder()
+        .block_size(256)
+        .enable_wal(true)
+        .num_memtable_limit(100)
+        .flush_threads(4)
+        .build()
+        .unwrap()
+}
+
+/// Write every key again, spread over `num_memtables` frozen memtables.
+fn write_round(storage: &MiniLsm, round: usize, num_memtables: usize) {
+    for i in 0..NUM_KEYS {
+        storage
+            .put(&key_of(i), format!("value{round}").as_bytes())
+            .unwrap();
+        if (i + 1) % (NUM_KEYS / num_memtables) == 0 {
+            storage
+                .inner
+                .force_freeze_memtable(&storage.inner.state_lock.lock())
+                .unwrap();
+        }
+    }
+}
+
+#[test]
+fn test_readers_see_all_data_during_install() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    write_round(&storage, 0, 1);
+    let done = AtomicBool::new(false);
+    std::thread::scope(|s| {
+        for reader in 0..2 {
+            let (storage, done) = (&storage, &done);
+            s.spawn(move || {
+                while !done.load(Ordering::SeqCst) {
+                    if reader == 0 {
+                        for i in 0..NUM_KEYS {
+                            assert!(storage.get(&key_of(i)).unwrap().is_some(), "key {i}");
+                        }
+                    } else {
+                        let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+                        let mut count = 0;
+                        while iter.is_valid() {
+                            count += 1;
+                            iter.next().unwrap();
+                        }
+                        assert_eq!(count, NUM_KEYS);
+                    }
+                }
+            });
+        }
+        for round in 1..4 {
+            write_round(&storage, round, 8);
+            while !storage.inner.state.read().imm_memtables.is_empty() {
+                storage.inner.force_flush_imm_memtables().unwrap();
+            }
+            storage.force_full_compaction().unwrap();
+        }
+        done.store(true, Ordering::SeqCst);
+    });
+    for i in 0..NUM_KEYS {
+        assert_eq!(storage.get(&key_of(i)).unwrap().unwrap(), &b"value3"[..]);
+    }
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_batched_flush_is_one_manifest_record() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    write_round(&storage, 0, 4);
+    let memtable_ids = storage
+        .inner
+        .state
+        .read()
+        .imm_memtables
+        .iter()
+        .rev()
+        .map(|memtable| memtable.id())
+        .collect::<Vec<_>>();
+    assert_eq!(storage.inner.force_flush_imm_memtables().unwrap(), 4);
+
+    let (_, records) = Manifest::recover(storage.inner.path_of_manifest()).unwrap();
+    let flushes = records
+        .iter()
+        .filter(|record| {
+            matches!(
+                record,
+                ManifestRecord::Flush(_) | ManifestRecord::FlushBatch(_)
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(flushes.len(), 1);
+    assert!(matches!(flushes[0], ManifestRecord::FlushBatch(ids) if *ids == memtable_ids));
+    // The WALs of the flushed memtables are gone once the flush is recorded.
+    for id in &memtable_ids {
+        assert!(!storage.inner.path_of_wal(*id).exists());
+    }
+    storage.close().unwrap();
+
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    let l0_sstables = storage.inner.state.read().l0_sstables.clone();
+    assert_eq!(l0_sstables.len(), 4);
+    assert_eq!(
+        l0_sstables,
+        memtable_ids.iter().rev().copied().collect::<Vec<_>>()
+    );
+    for i in 0..NUM_KEYS {
+        assert_eq!(storage.get(&key_of(i)).unwrap().unwrap(), &b"value0"[..]);
+    }
+    storage.close().unwrap();
+}
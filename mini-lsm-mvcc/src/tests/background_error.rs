@@ -0,0 +1,182 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use tempfile::tempdir;
+
+use crate::{
+    background_error::{
+        BackgroundErrorAction, BackgroundErrorHandler, BackgroundErrorPolicy, BackgroundTask,
+    },
+    compact::CompactionOptions,
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+fn options(policy: BackgroundErrorPolicy) -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.num_memtable_limit = 1;
+    options.background_error_policy = policy;
+    options
+}
+
+fn wait_until(what: &str, mut done: impl FnMut() -> bool) {
+    let start = Instant::now();
+    while !done() {
+        assert!(start.elapsed() < Duration::from_secs(10), "{what}");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Freeze a memtable whose flush fails because the directory of the storage is gone.
+fn fail_next_flush(storage: &MiniLsm, dir: &std::path::Path) {
+    storage.put(b"key", b"value").unwrap();
+    storage.pause_background();
+    storage
+        .inner
+        .force_freeze_memtable(&storage.inner.state_lock.lock())
+        .unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+    storage.resume_background();
+}
+
+fn num_imm_memtables(storage: &MiniLsm) -> usize {
+    storage.inner.state.read().imm_memtables.len()
+}
+
+#[test]
+fn test_halt_writes_by_default() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options(BackgroundErrorPolicy::default())).unwrap();
+    fail_next_flush(&storage, dir.path());
+    wait_until("flush did not fail", || {
+        storage.background_error().is_some()
+    });
+
+    assert!(matches!(
+        storage.put(b"other", b"value"),
+        Err(Error::Poisoned(_))
+    ));
+    assert!(matches!(storage.delete(b"key"), Err(Error::Poisoned(_))));
+    // reads are still served
+    assert_eq!(storage.get(b"key").unwrap().as_deref(), Some(&b"value"[..]));
+    // the flush is not retried while halted
+    std::fs::create_dir_all(dir.path()).unwrap();
+    std::thread::sleep(Duration::from_millis(200));
+    assert_eq!(num_imm_memtables(&storage), 1);
+
+    assert!(storage.clear_background_error().is_some());
+    wait_until("memtable was not flushed", || {
+        num_imm_memtables(&storage) == 0
+    });
+    storage.put(b"other", b"value").unwrap();
+    assert_eq!(storage.get(b"key").unwrap().as_deref(), Some(&b"value"[..]));
+}
+
+#[test]
+fn test_retry_with_backoff() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(
+        &dir,
+        options(BackgroundErrorPolicy::Retry {
+            max_retries: 1000,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(20),
+        }),
+    )
+    .unwrap();
+    fail_next_flush(&storage, dir.path());
+    std::thread::sleep(Duration::from_millis(200));
+    assert_eq!(num_imm_memtables(&storage), 1);
+    assert!(storage.background_error().is_none());
+    storage.put(b"other", b"value").unwrap();
+
+    std::fs::create_dir_all(dir.path()).unwrap();
+    wait_until("memtable was not flushed", || {
+        num_imm_memtables(&storage) == 0
+    });
+    assert!(storage.background_error().is_none());
+}
+
+#[test]
+fn test_policy_backoff() {
+    let policy = BackgroundErrorPolicy::Retry {
+        max_retries: 5,
+        initial_backoff: Duration::from_millis(100),
+        max_backoff: Duration::from_millis(500),
+    };
+    let backoffs = (1..=6)
+        .map(|failures| policy.action(failures))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        backoffs,
+        [100, 200, 400, 500, 500]
+            .map(|ms| BackgroundErrorAction::RetryAfter(Duration::from_millis(ms)))
+            .into_iter()
+            .chain([BackgroundErrorAction::Halt])
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        BackgroundErrorPolicy::HaltWrites.action(1),
+        BackgroundErrorAction::Halt
+    );
+}
+
+#[derive(Default)]
+struct RecordingHandler {
+    calls: Mutex<Vec<(BackgroundTask, u32)>>,
+}
+
+impl BackgroundErrorHandler for RecordingHandler {
+    fn on_background_error(
+        &self,
+        task: BackgroundTask,
+        _error: &Error,
+        failures: u32,
+    ) -> BackgroundErrorAction {
+        self.calls.lock().push((task, failures));
+        if failures < 3 {
+            BackgroundErrorAction::RetryAfter(Duration::from_millis(1))
+        } else {
+            BackgroundErrorAction::Halt
+        }
+    }
+}
+
+#[test]
+fn test_error_handler() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options(BackgroundErrorPolicy::default())).unwrap();
+    let handler = Arc::new(RecordingHandler::default());
+    storage.set_background_error_handler(handler.clone());
+    fail_next_flush(&storage, dir.path());
+    wait_until("flush did not fail", || {
+        storage.background_error().is_some()
+    });
+    assert_eq!(
+        *handler.calls.lock(),
+        vec![
+            (BackgroundTask::Flush, 1),
+            (BackgroundTask::Flush, 2),
+            (BackgroundTask::Flush, 3)
+        ]
+    );
+    assert!(matches!(
+        storage.put(b"other", b"value"),
+        Err(Error::Poisoned(_))
+    ));
+    std::fs::create_dir_all(dir.path()).unwrap();
+}
@@ -0,0 +1,98 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use tempfile::tempdir;
+
+use crate::{
+    key::KeySlice,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    table::{FileObject, SsTable, SsTableBuilder},
+};
+
+fn build_sst(dir: &std::path::Path, name: &str, block_size: usize, block_filters: bool) -> SsTable {
+    let mut builder = SsTableBuilder::new(block_size);
+    builder.set_block_filters(block_filters);
+    for i in (0..200).step_by(2) {
+        for ts in [3, 2, 1] {
+            builder.add(
+                KeySlice::for_testing_from_slice_with_ts(format!("key{i:03}").as_bytes(), ts),
+                b"value",
+            );
+        }
+    }
+    let path = dir.join(name);
+    builder.build_for_test(&path).unwrap();
+    SsTable::open_for_test(FileObject::open(&path).unwrap()).unwrap()
+}
+
+#[test]
+fn test_block_filters() {
+    let dir = tempdir().unwrap();
+    let with_filters = build_sst(dir.path(), "1.sst", 64, true);
+    let without_filters = build_sst(dir.path(), "2.sst", 64, false);
+    assert!(with_filters.has_block_filters());
+    assert!(!without_filters.has_block_filters());
+    assert!(with_filters.num_of_blocks() > 10);
+    assert!(with_filters.table_size() > without_filters.table_size());
+
+    let mut with_hits = 0;
+    let mut without_hits = 0;
+    for i in 0..200 {
+        let key = format!("key{i:03}");
+        if i % 2 == 0 {
+            assert!(with_filters.may_contain_key(key.as_bytes()));
+            assert!(without_filters.may_contain_key(key.as_bytes()));
+        } else {
+            with_hits += with_filters.may_contain_key(key.as_bytes()) as usize;
+            without_hits += without_filters.may_contain_key(key.as_bytes()) as usize;
+        }
+    }
+    // The block filters only rule out more keys than the SST filter, which is the same for both SSTs
+    assert!(with_hits <= without_hits);
+
+    // With an entry per block, a missing key between two blocks has no block to be in
+    let one_entry_per_block = build_sst(dir.path(), "3.sst", 16, true);
+    assert_eq!(one_entry_per_block.num_of_blocks(), 300);
+    for i in (1..200).step_by(2) {
+        assert!(!one_entry_per_block.may_contain_key(format!("key{i:03}").as_bytes()));
+    }
+}
+
+#[test]
+fn test_block_filters_option() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week1_test();
+    options.block_size = 64;
+    options.block_filters = true;
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    for i in (0..200).step_by(2) {
+        storage
+            .put(format!("key{i:03}").as_bytes(), b"value")
+            .unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.close().unwrap();
+
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let snapshot = storage.inner.state.read().clone();
+    assert!(
+        snapshot
+            .sstables
+            .values()
+            .all(|sst| sst.has_block_filters())
+    );
+    for i in 0..200 {
+        let value = storage.get(format!("key{i:03}").as_bytes()).unwrap();
+        assert_eq!(value.is_some(), i % 2 == 0);
+    }
+}
@@ -0,0 +1,144 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::sync::Arc;
+
+use crate::{
+    block::{Block, BlockBuilder, BlockIterator},
+    key::{KeySlice, KeyVec},
+};
+
+#[test]
+fn test_seek_with_shared_prefix_and_versions() {
+    // Keys share prefixes of different lengths with the first key, and some have several versions.
+    let mut entries = Vec::new();
+    for key in ["app", "apple", "apply", "b", "ba", "banana"] {
+        for ts in [9, 5, 1] {
+            entries.push((
+                KeyVec::from_vec_with_ts(key.as_bytes().to_vec(), ts),
+                format!("{key}@{ts}"),
+            ));
+        }
+    }
+    let mut builder = BlockBuilder::new(4096);
+    for (key, value) in &entries {
+        assert!(builder.add(key.as_key_slice(), value.as_bytes()));
+    }
+    let block = Arc::new(builder.build());
+
+    for (idx, (key, value)) in entries.iter().enumerate() {
+        let iter = BlockIterator::create_and_seek_to_key(block.clone(), key.as_key_slice());
+        assert_eq!(iter.key(), key.as_key_slice());
+        assert_eq!(iter.value(), value.as_bytes());
+        // A version between two stored ones lands on the older one.
+        let between = KeySlice::from_slice(key.key_ref(), key.ts() + 1);
+        let iter = BlockIterator::create_and_seek_to_key(block.clone(), between);
+        assert_eq!(iter.key(), key.as_key_slice(), "{idx}");
+    }
+    for (target, expected) in [
+        ("a", Some("app")),
+        ("apples", Some("apply")),
+        ("az", Some("b")),
+        ("c", None),
+    ] {
+        let iter = BlockIterator::create_and_seek_to_key(
+            block.clone(),
+            KeySlice::from_slice(target.as_bytes(), u64::MAX),
+        );
+        assert_eq!(
+            iter.is_valid().then(|| iter.key().key_ref()),
+            expected.map(str::as_bytes)
+        );
+    }
+}
+
+#[test]
+fn test_seek_with_restart_intervals() {
+    let entries: Vec<_> = (0..100)
+        .map(|idx| {
+            (
+                KeyVec::from_vec_with_ts(format!("key_{:05}", idx * 2).into_bytes(), 1),
+                format!("value_{idx}"),
+            )
+        })
+        .collect();
+    let mut sizes = Vec::new();
+    for interval in [1, 2, 7, 16, 100, 1000] {
+        let mut builder = BlockBuilder::with_restart_interval(4096, interval);
+        for (key, value) in &entries {
+            assert!(builder.add(key.as_key_slice(), value.as_bytes()));
+        }
+        let encoded = builder.build().encode();
+        sizes.push(encoded.len());
+        let block = Arc::new(Block::decode(&encoded));
+        assert_eq!(block.restart_interval, interval);
+
+        let mut iter = BlockIterator::create_and_seek_to_first(block.clone());
+        for (key, value) in &entries {
+            assert_eq!(iter.key(), key.as_key_slice());
+            assert_eq!(iter.value(), value.as_bytes());
+            iter.next();
+        }
+        assert!(!iter.is_valid());
+
+        for (idx, (key, _)) in entries.iter().enumerate() {
+            let iter = BlockIterator::create_and_seek_to_key(block.clone(), key.as_key_slice());
+            assert_eq!(iter.key(), key.as_key_slice(), "interval {interval}");
+            // An absent key between two stored ones lands on the next one.
+            let absent = format!("key_{:05}", idx * 2 + 1).into_bytes();
+            let iter = BlockIterator::create_and_seek_to_key(
+                block.clone(),
+                KeySlice::from_slice(&absent, 1),
+            );
+            assert_eq!(
+                iter.is_valid().then(|| iter.key().key_ref()),
+                entries.get(idx + 1).map(|(key, _)| key.key_ref()),
+                "interval {interval}"
+            );
+        }
+    }
+    // Storing every key in full takes the most space.
+    assert!(sizes[1..].iter().all(|&size| size < sizes[0]), "{sizes:?}");
+}
+
+#[test]
+fn test_seek_across_restart_points() {
+    // The versions of a key span restart points for the small intervals.
+    let mut entries = Vec::new();
+    for key in ["a", "ab", "abc", "b", "bcd", "c"] {
+        for ts in [7, 4, 2] {
+            entries.push(KeyVec::from_vec_with_ts(key.as_bytes().to_vec(), ts));
+        }
+    }
+    for interval in 1..=entries.len() + 1 {
+        let mut builder = BlockBuilder::with_restart_interval(4096, interval);
+        for key in &entries {
+            assert!(builder.add(key.as_key_slice(), key.key_ref()));
+        }
+        let block = Arc::new(builder.build());
+        for ts in 0..=8 {
+            for target in [
+                "", "a", "aa", "ab", "abc", "abd", "b", "bc", "bcd", "c", "d",
+            ] {
+                let target = KeySlice::from_slice(target.as_bytes(), ts);
+                let expected = entries.iter().find(|key| key.as_key_slice() >= target);
+                let iter = BlockIterator::create_and_seek_to_key(block.clone(), target);
+                assert_eq!(
+                    iter.is_valid().then(|| iter.key()),
+                    expected.map(|key| key.as_key_slice()),
+                    "interval {interval}, target {target:?}"
+                );
+            }
+        }
+    }
+}
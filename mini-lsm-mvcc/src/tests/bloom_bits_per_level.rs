@@ -0,0 +1,79 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    table::SsTable,
+};
+
+fn bloom_bits(table: &SsTable) -> usize {
+    table.bloom.as_ref().unwrap().filter.len() * 8
+}
+
+#[test]
+fn test_bloom_bits_of_level() {
+    let options = LsmStorageOptions::builder()
+        .bloom_bits_per_key(vec![16, 10, 4])
+        .build()
+        .unwrap();
+    assert_eq!(options.bloom_bits_per_key_of_level(0), Some(16));
+    assert_eq!(options.bloom_bits_per_key_of_level(2), Some(4));
+    assert_eq!(options.bloom_bits_per_key_of_level(usize::MAX), Some(4));
+    let options = LsmStorageOptions::builder().build().unwrap();
+    assert_eq!(options.bloom_bits_per_key_of_level(0), None);
+    assert!(
+        LsmStorageOptions::builder()
+            .bloom_bits_per_key(vec![10, 0])
+            .build()
+            .is_err()
+    );
+}
+
+#[test]
+fn test_flush_and_compaction_use_level_bits() {
+    const NUM_KEYS: usize = 1000;
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.bloom_bits_per_key = vec![16, 2];
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for i in 0..NUM_KEYS {
+        storage
+            .put(format!("key{i:04}").as_bytes(), b"value")
+            .unwrap();
+    }
+    storage.force_flush().unwrap();
+    let snapshot = storage.inner.snapshot();
+    let l0 = &snapshot.sstables[&snapshot.l0_sstables[0]];
+    assert!(bloom_bits(l0) >= 16 * NUM_KEYS);
+
+    storage.force_full_compaction().unwrap();
+    let snapshot = storage.inner.snapshot();
+    assert!(snapshot.l0_sstables.is_empty());
+    let l1_bits = snapshot.levels[0]
+        .1
+        .iter()
+        .map(|id| bloom_bits(&snapshot.sstables[id]))
+        .sum::<usize>();
+    assert!(l1_bits < 4 * NUM_KEYS, "{l1_bits} bits for {NUM_KEYS} keys");
+    for i in 0..NUM_KEYS {
+        assert!(
+            storage
+                .get(format!("key{i:04}").as_bytes())
+                .unwrap()
+                .is_some()
+        );
+    }
+}
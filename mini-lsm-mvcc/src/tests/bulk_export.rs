@@ -0,0 +1,117 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    import::{DataFormat, ImportOptions},
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+#[test]
+fn test_export_csv() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.put(b"b", b"quoted \"value\", with comma").unwrap();
+    storage.force_flush().unwrap();
+    storage.put(b"c", b"3").unwrap();
+    storage.delete(b"a").unwrap();
+    storage.put(b"d", b"4").unwrap();
+
+    let mut output = Vec::new();
+    let cnt = storage
+        .export(
+            Bound::Included(b"a"),
+            Bound::Excluded(b"d"),
+            DataFormat::Csv,
+            false,
+            &mut output,
+        )
+        .unwrap();
+    assert_eq!(cnt, 2);
+    assert_eq!(
+        String::from_utf8(output.clone()).unwrap(),
+        "b,\"quoted \"\"value\"\", with comma\"\nc,3\n"
+    );
+
+    let mut with_ts = Vec::new();
+    storage
+        .export(
+            Bound::Unbounded,
+            Bound::Unbounded,
+            DataFormat::Csv,
+            true,
+            &mut with_ts,
+        )
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(with_ts).unwrap().lines().nth(1),
+        Some("c,3,3")
+    );
+
+    // The exported data can be imported again.
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let imported = MiniLsm::open(&dir, options).unwrap();
+    imported
+        .import(&output[..], &ImportOptions::new(DataFormat::Csv), |_| {})
+        .unwrap();
+    assert_eq!(
+        imported.get(b"b").unwrap(),
+        Some(Bytes::from("quoted \"value\", with comma"))
+    );
+    assert_eq!(imported.get(b"c").unwrap(), Some(Bytes::from("3")));
+}
+
+#[test]
+fn test_export_jsonl() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.put(b"b", b"\"2\"").unwrap();
+    let mut output = Vec::new();
+    storage
+        .export(
+            Bound::Unbounded,
+            Bound::Unbounded,
+            DataFormat::Jsonl,
+            true,
+            &mut output,
+        )
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "{\"key\":\"a\",\"value\":\"1\",\"ts\":1}\n{\"key\":\"b\",\"value\":\"\\\"2\\\"\",\"ts\":2}\n"
+    );
+
+    storage.put(b"c", &[0xff, 0xfe]).unwrap();
+    assert!(matches!(
+        storage.export(
+            Bound::Unbounded,
+            Bound::Unbounded,
+            DataFormat::Jsonl,
+            false,
+            Vec::new(),
+        ),
+        Err(Error::InvalidArgument(_))
+    ));
+}
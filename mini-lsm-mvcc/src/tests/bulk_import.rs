@@ -0,0 +1,115 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    import::{DataFormat, ImportOptions, ImportProgress},
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+#[test]
+fn test_import_csv_external_sort() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"key_0003", b"deleted").unwrap();
+
+    let mut input = String::new();
+    for i in (0..1000).rev() {
+        input += &format!("key_{:04},value_{}\n", i, i);
+    }
+    // Later records win, and an empty value deletes the key.
+    input += "key_0001,\"updated, \"\"quoted\"\"\"\r\n\nkey_0002,\nkey_0003,,123\n";
+
+    let mut import_options = ImportOptions::new(DataFormat::Csv);
+    import_options.sort_buffer_size = 4096;
+    import_options.tmp_dir = Some(dir.path().to_path_buf());
+    let mut events = Vec::new();
+    let summary = storage
+        .import(input.as_bytes(), &import_options, |progress| {
+            events.push(progress)
+        })
+        .unwrap();
+    assert_eq!(summary.num_entries, 1000);
+    assert!(matches!(events[0], ImportProgress::Sorted { runs } if runs > 1));
+    assert_eq!(events.last(), Some(&ImportProgress::Ingested(1000)));
+    // The run files are removed.
+    assert!(std::fs::read_dir(&dir).unwrap().all(|entry| {
+        entry
+            .unwrap()
+            .path()
+            .extension()
+            .is_none_or(|ext| ext != "run")
+    }));
+
+    assert_eq!(
+        storage.get(b"key_0000").unwrap(),
+        Some(Bytes::from("value_0"))
+    );
+    assert_eq!(
+        storage.get(b"key_0001").unwrap(),
+        Some(Bytes::from("updated, \"quoted\""))
+    );
+    assert_eq!(storage.get(b"key_0002").unwrap(), None);
+    assert_eq!(storage.get(b"key_0003").unwrap(), None);
+    assert_eq!(
+        storage.get(b"key_0999").unwrap(),
+        Some(Bytes::from("value_999"))
+    );
+}
+
+#[test]
+fn test_import_jsonl() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let input = r#"{"key": "b", "value": "2", "ts": 10}
+{"key": "a", "value": "1"}
+{"key": "c"}
+"#;
+    let summary = storage
+        .import(
+            input.as_bytes(),
+            &ImportOptions::new(DataFormat::Jsonl),
+            |_| {},
+        )
+        .unwrap();
+    assert_eq!(summary.num_entries, 3);
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from("1")));
+    assert_eq!(storage.get(b"b").unwrap(), Some(Bytes::from("2")));
+    assert_eq!(storage.get(b"c").unwrap(), None);
+}
+
+#[test]
+fn test_import_invalid_input() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+
+    let mut import_options = ImportOptions::new(DataFormat::Csv);
+    match storage.import(&b"a,1\nb\n"[..], &import_options, |_| {}) {
+        Err(Error::InvalidArgument(msg)) => assert!(msg.contains("line 2"), "{}", msg),
+        other => panic!("unexpected result {:?}", other.map(|_| ())),
+    }
+    import_options.sorted = true;
+    assert!(matches!(
+        storage.import(&b"b,1\na,2\n"[..], &import_options, |_| {}),
+        Err(Error::InvalidArgument(_))
+    ));
+    assert_eq!(storage.get(b"a").unwrap(), None);
+}
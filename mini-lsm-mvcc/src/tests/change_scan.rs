@@ -0,0 +1,171 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::ops::Bound;
+use std::time::{Duration, SystemTime};
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    cdc::{ChangeIterator, ChangesSince},
+    compact::CompactionOptions,
+    iterators::StorageIterator,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+fn collect_changes(mut iter: ChangeIterator) -> Vec<(Bytes, Bytes)> {
+    let mut changes = Vec::new();
+    while iter.is_valid() {
+        changes.push((
+            Bytes::copy_from_slice(iter.key()),
+            Bytes::copy_from_slice(iter.value()),
+        ));
+        iter.next().unwrap();
+    }
+    changes
+}
+
+fn changes(storage: &MiniLsm, since: ChangesSince) -> Vec<(Bytes, Bytes)> {
+    collect_changes(
+        storage
+            .scan_changes(Bound::Unbounded, Bound::Unbounded, since)
+            .unwrap(),
+    )
+}
+
+fn change(key: &'static str, value: &'static str) -> (Bytes, Bytes) {
+    (Bytes::from(key), Bytes::from(value))
+}
+
+#[test]
+fn test_sst_time_range() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let before = SystemTime::now() - Duration::from_millis(1);
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.put(b"b", b"1").unwrap();
+    storage.force_flush().unwrap();
+    storage.close().unwrap();
+    let after = SystemTime::now() + Duration::from_millis(1);
+
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let snapshot = storage.inner.state.read().clone();
+    let range = snapshot.sstables[&snapshot.l0_sstables[0]].time_range();
+    assert_eq!((range.min_ts, range.max_ts), (1, 2));
+    let (min_write_time, max_write_time) = range.write_time_range();
+    assert!(before <= min_write_time && min_write_time <= max_write_time);
+    assert!(max_write_time <= after);
+}
+
+#[test]
+fn test_scan_changes_since_ts() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.put(b"b", b"1").unwrap();
+    storage.force_flush().unwrap();
+    let ts = storage.inner.mvcc().latest_commit_ts();
+    storage.put(b"b", b"2").unwrap();
+    storage.put(b"c", b"2").unwrap();
+    storage.force_flush().unwrap();
+    storage.delete(b"a").unwrap();
+    storage.put(b"d", b"3").unwrap();
+
+    assert_eq!(
+        changes(&storage, ChangesSince::Ts(ts)),
+        vec![
+            change("a", ""),
+            change("b", "2"),
+            change("c", "2"),
+            change("d", "3"),
+        ]
+    );
+    assert_eq!(
+        changes(&storage, ChangesSince::Ts(0)),
+        vec![
+            change("a", ""),
+            change("b", "2"),
+            change("c", "2"),
+            change("d", "3"),
+        ]
+    );
+    let latest_ts = storage.inner.mvcc().latest_commit_ts();
+    assert!(changes(&storage, ChangesSince::Ts(latest_ts)).is_empty());
+
+    // The first SST is skipped as a whole.
+    let num_iters = |since| {
+        storage
+            .scan_changes(Bound::Unbounded, Bound::Unbounded, since)
+            .unwrap()
+            .num_active_iterators()
+    };
+    assert_eq!(
+        num_iters(ChangesSince::Ts(0)) - num_iters(ChangesSince::Ts(ts)),
+        1
+    );
+    let iter = storage
+        .scan_changes(
+            Bound::Included(b"b"),
+            Bound::Excluded(b"d"),
+            ChangesSince::Ts(ts),
+        )
+        .unwrap();
+    assert_eq!(
+        collect_changes(iter),
+        vec![change("b", "2"), change("c", "2")]
+    );
+}
+
+#[test]
+fn test_scan_changes_since_write_time() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.put(b"b", b"1").unwrap();
+    storage.force_flush().unwrap();
+    storage.put(b"c", b"1").unwrap();
+    storage.force_flush().unwrap();
+    std::thread::sleep(Duration::from_millis(10));
+    let since = SystemTime::now();
+    std::thread::sleep(Duration::from_millis(10));
+    storage.put(b"b", b"2").unwrap();
+    storage.force_flush().unwrap();
+    storage.delete(b"c").unwrap();
+
+    assert_eq!(
+        changes(&storage, ChangesSince::WriteTime(since)),
+        vec![change("b", "2"), change("c", "")]
+    );
+    // Both SSTs written before `since` are skipped as a whole.
+    let num_iters = |since| {
+        storage
+            .scan_changes(Bound::Unbounded, Bound::Unbounded, since)
+            .unwrap()
+            .num_active_iterators()
+    };
+    assert_eq!(
+        num_iters(ChangesSince::Ts(0)) - num_iters(ChangesSince::WriteTime(since)),
+        2
+    );
+    assert!(
+        changes(
+            &storage,
+            ChangesSince::WriteTime(SystemTime::now() + Duration::from_secs(1))
+        )
+        .is_empty()
+    );
+}
@@ -0,0 +1,61 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::checksum::{
+    BLOCK_CHECKSUM, block_checksum, common_prefix_len, crc32c, crc32c_software, xxhash32,
+};
+
+#[test]
+fn test_crc32c() {
+    assert_eq!(crc32c(b""), 0);
+    assert_eq!(crc32c(b"123456789"), 0xe3069283);
+    // Exercise both the 8-byte chunks and the remainder of the hardware path.
+    let data = (0..1000u32)
+        .map(|i| (i * 31 % 251) as u8)
+        .collect::<Vec<_>>();
+    for len in [1, 7, 8, 9, 63, 64, 65, 999, 1000] {
+        assert_eq!(crc32c(&data[..len]), crc32c_software(&data[..len]), "{len}");
+    }
+}
+
+#[test]
+fn test_block_checksum() {
+    // The same with and without the `simd` feature
+    assert_eq!(BLOCK_CHECKSUM, "crc32");
+    assert_eq!(block_checksum(b"123456789"), 0xcbf43926);
+}
+
+#[test]
+fn test_xxhash32() {
+    assert_eq!(xxhash32(b"", 0), 0x02cc5d05);
+    assert_eq!(xxhash32(b"abc", 0), 0x32d153ff);
+    // Longer than a stripe, with words and bytes left over.
+    assert_eq!(
+        xxhash32(b"Nobody inspects the spammish repetition", 0),
+        0xe2293b2f
+    );
+    assert_ne!(xxhash32(b"abc", 1), xxhash32(b"abc", 0));
+}
+
+#[test]
+fn test_common_prefix_len() {
+    let a = (0..100u8).collect::<Vec<_>>();
+    for i in 0..100 {
+        let mut b = a.clone();
+        b[i] ^= 1;
+        assert_eq!(common_prefix_len(&a, &b), i);
+        assert_eq!(common_prefix_len(&a[..i], &a), i);
+    }
+    assert_eq!(common_prefix_len(&a, &a), 100);
+    assert_eq!(common_prefix_len(b"", b"abc"), 0);
+}
@@ -0,0 +1,188 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    iterators::StorageIterator,
+    lsm_storage::{LsmStorageOptions, MiniLsm, WriteBatchRecord, WriteOptions},
+    wal::Wal,
+};
+
+const NUM_WRITERS: usize = 4;
+const NUM_WRITES: usize = 200;
+
+fn options() -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.enable_wal = true;
+    // freeze the memtable every few hundred writes, so that batches are logged across several WALs
+    options.target_sst_size = 4096;
+    options.num_memtable_limit = 1000;
+    options
+}
+
+fn key_of(writer: usize, idx: usize) -> Vec<u8> {
+    format!("key_{writer}_{idx:05}").into_bytes()
+}
+
+/// Write from several threads with synced WALs, returning the commit timestamp of each key.
+fn write_concurrently(storage: &MiniLsm) -> BTreeMap<Vec<u8>, u64> {
+    std::thread::scope(|s| {
+        let writers = (0..NUM_WRITERS)
+            .map(|writer| {
+                s.spawn(move || {
+                    (0..NUM_WRITES)
+                        .map(|idx| {
+                            let key = key_of(writer, idx);
+                            let ts = storage
+                                .inner
+                                .write_batch_inner(
+                                    &[WriteBatchRecord::Put(&key[..], b"value")],
+                                    &WriteOptions {
+                                        sync: idx % 2 == 0,
+                                        disable_wal: false,
+                                    },
+                                )
+                                .unwrap();
+                            (key, ts)
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        writers
+            .into_iter()
+            .flat_map(|writer| writer.join().unwrap())
+            .collect()
+    })
+}
+
+#[test]
+fn test_commits_are_published_in_order() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    let done = AtomicBool::new(false);
+    let (key_ts, observed) = std::thread::scope(|s| {
+        let reader = s.spawn(|| {
+            let mut observed = Vec::new();
+            while !done.load(Ordering::SeqCst) {
+                let txn = storage.new_txn().unwrap();
+                let mut iter = txn.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+                let mut keys = Vec::new();
+                while iter.is_valid() {
+                    keys.push(iter.key().to_vec());
+                    iter.next().unwrap();
+                }
+                observed.push((txn.read_ts(), keys));
+            }
+            observed
+        });
+        let key_ts = write_concurrently(&storage);
+        done.store(true, Ordering::SeqCst);
+        (key_ts, reader.join().unwrap())
+    });
+
+    // every write got its own timestamp, with none skipped
+    let mut all_ts = key_ts.values().copied().collect::<Vec<_>>();
+    all_ts.sort();
+    assert_eq!(
+        all_ts,
+        (1..=(NUM_WRITERS * NUM_WRITES) as u64).collect::<Vec<_>>()
+    );
+    // each read saw exactly the writes committed up to its timestamp, never a later one without an earlier one
+    for (read_ts, keys) in observed {
+        let expected = key_ts
+            .iter()
+            .filter(|(_, ts)| **ts <= read_ts)
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, expected, "read at ts={read_ts}");
+    }
+}
+
+#[test]
+fn test_wal_holds_batches_in_commit_order() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    let key_ts = write_concurrently(&storage);
+    assert!(!storage.inner.snapshot().imm_memtables.is_empty());
+    storage.sync().unwrap();
+
+    let mut wals = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "wal"))
+        .collect::<Vec<_>>();
+    wals.sort();
+    let mut logged_ts = Vec::new();
+    for wal in wals {
+        let data = std::fs::read(wal).unwrap();
+        let mut buf = &data[..];
+        while let Some(batch) = Wal::decode_batch(&mut buf).unwrap() {
+            logged_ts.extend(batch.into_iter().map(|(_, ts, _)| ts));
+        }
+    }
+    assert_eq!(
+        logged_ts,
+        (1..=(NUM_WRITERS * NUM_WRITES) as u64).collect::<Vec<_>>()
+    );
+
+    storage.close().unwrap();
+    drop(storage);
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    assert_eq!(
+        storage.inner.mvcc().latest_commit_ts(),
+        (NUM_WRITERS * NUM_WRITES) as u64
+    );
+    for key in key_ts.keys() {
+        assert!(storage.get(key).unwrap().is_some());
+    }
+}
+
+#[test]
+fn test_shared_ts_is_published_once_all_writes_are() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    // hold one more write at ts=10 open, so that the ones below cannot make it visible on their own
+    let ts = storage.inner.mvcc().allocate_commit_ts_at(10).unwrap();
+    std::thread::scope(|s| {
+        for writer in 0..NUM_WRITERS {
+            let storage = &storage;
+            s.spawn(move || {
+                for idx in 0..NUM_WRITES / 10 {
+                    let key = key_of(writer, idx);
+                    storage
+                        .inner
+                        .write_batch_with_ts(&[WriteBatchRecord::Put(&key[..], b"value")], ts)
+                        .unwrap();
+                    assert_eq!(storage.inner.mvcc().latest_commit_ts(), 0);
+                }
+            });
+        }
+    });
+    assert_eq!(storage.inner.mvcc().latest_commit_ts(), 0);
+    assert!(storage.get(&key_of(0, 0)).unwrap().is_none());
+
+    storage.inner.mvcc().publish_commit_ts(ts);
+    assert_eq!(storage.inner.mvcc().latest_commit_ts(), 10);
+    for writer in 0..NUM_WRITERS {
+        for idx in 0..NUM_WRITES / 10 {
+            assert!(storage.get(&key_of(writer, idx)).unwrap().is_some());
+        }
+    }
+}
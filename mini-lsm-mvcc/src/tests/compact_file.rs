@@ -0,0 +1,140 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::time::{Duration, Instant};
+
+use tempfile::tempdir;
+
+use crate::{
+    compact::{CompactionOptions, LeveledCompactionOptions, TieredCompactionOptions},
+    iterators::StorageIterator,
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    table::SsTableIterator,
+};
+
+fn num_entries_in_ssts(storage: &MiniLsm) -> usize {
+    let snapshot = storage.inner.state.read().clone();
+    let mut cnt = 0;
+    for sst in snapshot.sstables.values() {
+        let mut iter = SsTableIterator::create_and_seek_to_first(sst.clone()).unwrap();
+        while iter.is_valid() {
+            cnt += 1;
+            iter.next().unwrap();
+        }
+    }
+    cnt
+}
+
+#[test]
+fn test_compact_file() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(
+        &dir,
+        LsmStorageOptions::default_for_week2_test(CompactionOptions::Leveled(
+            LeveledCompactionOptions {
+                level0_file_num_compaction_trigger: 10,
+                max_levels: 3,
+                base_level_size_mb: 1,
+                level_size_multiplier: 2,
+            },
+        )),
+    )
+    .unwrap();
+    for i in 0..100 {
+        storage
+            .put(format!("key{:03}", i).as_bytes(), b"value")
+            .unwrap();
+    }
+    storage.force_flush().unwrap();
+    let sst_id = storage.inner.state.read().l0_sstables[0];
+    storage.compact_file(sst_id).unwrap();
+    let snapshot = storage.inner.state.read().clone();
+    assert!(snapshot.l0_sstables.is_empty());
+    assert_eq!(snapshot.levels[2].1.len(), 1);
+    let sst_id = snapshot.levels[2].1[0];
+    assert!(matches!(
+        storage.compact_file(sst_id + 100),
+        Err(Error::InvalidArgument(_))
+    ));
+
+    for i in 0..90 {
+        storage.delete(format!("key{:03}", i).as_bytes()).unwrap();
+    }
+    storage.force_flush().unwrap();
+    let l0_sst_id = storage.inner.state.read().l0_sstables[0];
+    storage.compact_file(l0_sst_id).unwrap();
+    assert_eq!(num_entries_in_ssts(&storage), 10);
+    // An SST in the bottom level is rewritten in place
+    let sst_id = storage.inner.state.read().levels[2].1[0];
+    storage.compact_file(sst_id).unwrap();
+    let snapshot = storage.inner.state.read().clone();
+    assert_eq!(snapshot.levels[2].1.len(), 1);
+    assert_ne!(snapshot.levels[2].1[0], sst_id);
+    for i in 0..100 {
+        let value = storage.get(format!("key{:03}", i).as_bytes()).unwrap();
+        assert_eq!(value.is_some(), i >= 90);
+    }
+    storage.close().unwrap();
+
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    storage.put(b"key", b"value").unwrap();
+    storage.force_flush().unwrap();
+    let sst_id = storage.inner.state.read().l0_sstables[0];
+    assert!(matches!(
+        storage.compact_file(sst_id),
+        Err(Error::InvalidArgument(_))
+    ));
+}
+
+#[test]
+fn test_delete_compaction() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::Tiered(
+        TieredCompactionOptions {
+            num_tiers: 10,
+            max_size_amplification_percent: 200,
+            size_ratio: 1,
+            min_merge_width: 2,
+            max_merge_width: None,
+        },
+    ));
+    options.delete_compaction_ratio = Some(0.5);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for i in 0..100 {
+        storage
+            .put(format!("key{:03}", i).as_bytes(), b"value")
+            .unwrap();
+    }
+    storage.force_flush().unwrap();
+    for i in 0..50 {
+        storage.delete(format!("key{:03}", i).as_bytes()).unwrap();
+    }
+    storage.force_flush().unwrap();
+
+    // The tiers never reach the compaction triggers, so the deletes are only dropped by the delete compaction
+    let start = Instant::now();
+    while num_entries_in_ssts(&storage) != 50 {
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "deletes are not reclaimed by delete compaction"
+        );
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    for i in 0..100 {
+        let value = storage.get(format!("key{:03}", i).as_bytes()).unwrap();
+        assert_eq!(value.is_some(), i >= 50);
+    }
+    storage.close().unwrap();
+}
@@ -0,0 +1,160 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::ops::Bound;
+
+use tempfile::tempdir;
+
+use crate::{
+    compact::{
+        CompactionOptions, LazyLeveledCompactionOptions, LeveledCompactionOptions,
+        TieredCompactionOptions,
+    },
+    iterators::StorageIterator,
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+fn contents(storage: &MiniLsm) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    let mut contents = Vec::new();
+    while iter.is_valid() {
+        contents.push((iter.key().to_vec(), iter.value().to_vec()));
+        iter.next().unwrap();
+    }
+    contents
+}
+
+fn run(compaction_options: CompactionOptions, enable_wal: bool) {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(compaction_options);
+    options.enable_wal = enable_wal;
+
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    for round in 0..8 {
+        for i in (round * 20)..(round * 20 + 100) {
+            storage
+                .put(
+                    format!("key{i:04}").as_bytes(),
+                    format!("v{round}").as_bytes(),
+                )
+                .unwrap();
+        }
+        for i in (round * 20)..(round * 20 + 10) {
+            storage.delete(format!("key{i:04}").as_bytes()).unwrap();
+        }
+        if round < 6 {
+            storage.force_flush().unwrap();
+        }
+    }
+    let expected = contents(&storage);
+    storage.close().unwrap();
+
+    let ids = MiniLsm::compact_offline(&dir, options.clone()).unwrap();
+    assert!(!ids.is_empty());
+
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(contents(&storage), expected);
+    {
+        let snapshot = storage.inner.state.read();
+        assert!(snapshot.l0_sstables.is_empty());
+        assert!(snapshot.imm_memtables.is_empty());
+        let runs = snapshot
+            .levels
+            .iter()
+            .filter(|(_, ssts)| !ssts.is_empty())
+            .collect::<Vec<_>>();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].1, ids);
+        assert_eq!(runs[0], snapshot.levels.last().unwrap());
+        // Deletes are dropped when compacting into the bottom level
+        assert!(
+            ids.iter()
+                .all(|id| snapshot.sstables[id].entry_counts().num_deletes == 0)
+        );
+    }
+    storage.close().unwrap();
+
+    // The inputs are gone from the directory
+    let num_ssts = std::fs::read_dir(&dir)
+        .unwrap()
+        .filter(|entry| {
+            entry
+                .as_ref()
+                .unwrap()
+                .path()
+                .extension()
+                .is_some_and(|ext| ext == "sst")
+        })
+        .count();
+    assert_eq!(num_ssts, ids.len());
+}
+
+#[test]
+fn test_compact_offline_no_compaction() {
+    run(CompactionOptions::NoCompaction, false);
+}
+
+#[test]
+fn test_compact_offline_leveled() {
+    run(
+        CompactionOptions::Leveled(LeveledCompactionOptions {
+            level0_file_num_compaction_trigger: 2,
+            max_levels: 3,
+            base_level_size_mb: 1,
+            level_size_multiplier: 2,
+        }),
+        true,
+    );
+}
+
+#[test]
+fn test_compact_offline_tiered() {
+    run(
+        CompactionOptions::Tiered(TieredCompactionOptions {
+            num_tiers: 3,
+            max_size_amplification_percent: 200,
+            size_ratio: 1,
+            min_merge_width: 2,
+            max_merge_width: None,
+        }),
+        true,
+    );
+}
+
+#[test]
+fn test_compact_offline_lazy_leveled() {
+    run(
+        CompactionOptions::LazyLeveled(LazyLeveledCompactionOptions {
+            size_ratio: 3,
+            level0_file_num_compaction_trigger: 2,
+            max_levels: 2,
+        }),
+        false,
+    );
+}
+
+#[test]
+fn test_compact_offline_read_only() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    storage.put(b"key", b"value").unwrap();
+    storage.close().unwrap();
+
+    options.read_only = true;
+    assert!(matches!(
+        MiniLsm::compact_offline(&dir, options),
+        Err(Error::ReadOnly)
+    ));
+}
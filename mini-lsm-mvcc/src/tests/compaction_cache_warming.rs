@@ -0,0 +1,83 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::sync::Arc;
+
+use tempfile::{TempDir, tempdir};
+
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+
+fn key_of(i: usize) -> Vec<u8> {
+    format!("key{i:04}").into_bytes()
+}
+
+/// Write two overlapping SSTs, read the keys in `hot` and compact them, returning the storage and whether each block
+/// of the output is cached.
+fn compact_after_reads(
+    warm: bool,
+    hot: std::ops::Range<usize>,
+) -> (TempDir, Arc<MiniLsm>, Vec<bool>) {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::builder()
+        .block_size(256)
+        .warm_cache_after_compaction(warm)
+        .build()
+        .unwrap();
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for round in 0..2 {
+        for i in 0..1000 {
+            storage
+                .put(&key_of(i), format!("value{round}").as_bytes())
+                .unwrap();
+        }
+        storage.force_flush().unwrap();
+    }
+    for i in hot {
+        assert_eq!(storage.get(&key_of(i)).unwrap().unwrap(), &b"value1"[..]);
+    }
+    storage.force_full_compaction().unwrap();
+
+    let snapshot = storage.inner.state.read().clone();
+    let cached = snapshot.levels[0]
+        .1
+        .iter()
+        .flat_map(|sst_id| {
+            let sst = &snapshot.sstables[sst_id];
+            (0..sst.num_of_blocks()).map(|idx| sst.cached_block(idx).is_some())
+        })
+        .collect();
+    (dir, storage, cached)
+}
+
+#[test]
+fn test_compaction_warms_hot_blocks() {
+    let (_dir, storage, cached) = compact_after_reads(true, 500..520);
+    let warmed = cached.iter().filter(|cached| **cached).count();
+    assert!(warmed > 0);
+    // Only the blocks around the hot keys are loaded, not the whole output.
+    assert!(warmed * 10 < cached.len(), "{warmed} of {}", cached.len());
+    assert!(!cached[0] && !cached[cached.len() - 1]);
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_compaction_without_warming() {
+    let (_dir, storage, cached) = compact_after_reads(false, 500..520);
+    assert!(!cached.iter().any(|cached| *cached));
+    storage.close().unwrap();
+
+    // Nothing read, nothing warmed.
+    let (_dir, storage, cached) = compact_after_reads(true, 0..0);
+    assert!(!cached.iter().any(|cached| *cached));
+    storage.close().unwrap();
+}
@@ -0,0 +1,82 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use tempfile::tempdir;
+
+use crate::{
+    compact::{
+        CompactionController, CompactionOptions, CompactionTask, LeveledCompactionOptions,
+        SimpleLeveledCompactionOptions,
+    },
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+fn simple(level0_file_num_compaction_trigger: usize) -> CompactionOptions {
+    CompactionOptions::Simple(SimpleLeveledCompactionOptions {
+        size_ratio_percent: 200,
+        level0_file_num_compaction_trigger,
+        max_levels: 2,
+    })
+}
+
+#[test]
+fn test_compaction_plan() {
+    let dir = tempdir().unwrap();
+    let storage =
+        MiniLsm::open(&dir, LsmStorageOptions::default_for_week2_test(simple(4))).unwrap();
+    for i in 0..3 {
+        storage.put(format!("key{i}").as_bytes(), b"value").unwrap();
+        storage.force_flush().unwrap();
+    }
+
+    let plan = storage.compaction_plan();
+    assert!(plan.task.is_none());
+    assert!(plan.reason.is_none());
+    assert_eq!(plan.scores[0].name, "L0");
+    assert_eq!(plan.scores[0].score, 0.75);
+    assert!(plan.to_string().starts_with("no compaction needed"));
+
+    // Plan with other options against the same state.
+    let snapshot = storage.inner.state.read().clone();
+    let l0_size = snapshot
+        .l0_sstables
+        .iter()
+        .map(|id| snapshot.sstables[id].table_size())
+        .sum::<u64>();
+    let plan = CompactionController::new(&simple(2)).plan(&snapshot);
+    assert!(matches!(
+        plan.task,
+        Some(CompactionTask::Simple(ref task)) if task.upper_level.is_none()
+    ));
+    assert_eq!(plan.input_sst_ids, snapshot.l0_sstables);
+    assert_eq!(plan.input_size, l0_size);
+    assert!(plan.reason.as_ref().unwrap().starts_with("L0"));
+    assert!(plan.to_string().starts_with("next compaction: L0"));
+
+    let plan = CompactionController::new(&CompactionOptions::Leveled(LeveledCompactionOptions {
+        level0_file_num_compaction_trigger: 2,
+        level_size_multiplier: 2,
+        base_level_size_mb: 1,
+        max_levels: 2,
+    }))
+    .plan(&snapshot);
+    assert!(matches!(
+        plan.task,
+        Some(CompactionTask::Leveled(ref task)) if task.lower_level == 2
+    ));
+    assert_eq!(plan.input_size, l0_size);
+
+    let plan = CompactionController::new(&CompactionOptions::NoCompaction).plan(&snapshot);
+    assert!(plan.task.is_none() && plan.scores.is_empty());
+    storage.close().unwrap();
+}
@@ -0,0 +1,93 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionTask,
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+fn options() -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week1_test();
+    options.verify_compaction = true;
+    options
+}
+
+fn key_of(i: usize) -> Vec<u8> {
+    format!("key_{:03}", i).into_bytes()
+}
+
+#[test]
+fn test_verify_full_compaction() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    for i in 0..100 {
+        storage.put(&key_of(i), b"v1").unwrap();
+    }
+    storage.force_flush().unwrap();
+    // Keeps the first versions visible, so compaction has to retain them
+    let txn = storage.new_txn().unwrap();
+    for i in 0..50 {
+        storage.put(&key_of(i), b"v2").unwrap();
+    }
+    for i in 50..60 {
+        storage.delete(&key_of(i)).unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    assert_eq!(txn.get(&key_of(0)).unwrap(), Some(Bytes::from("v1")));
+    assert_eq!(txn.get(&key_of(50)).unwrap(), Some(Bytes::from("v1")));
+    drop(txn);
+
+    // Old versions and tombstones are dropped once no reader needs them
+    storage.force_full_compaction().unwrap();
+    assert_eq!(storage.get(&key_of(0)).unwrap(), Some(Bytes::from("v2")));
+    assert_eq!(storage.get(&key_of(50)).unwrap(), None);
+    assert_eq!(storage.get(&key_of(60)).unwrap(), Some(Bytes::from("v1")));
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_verify_detects_lost_versions() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.force_flush().unwrap();
+    storage.put(b"b", b"2").unwrap();
+    storage.force_flush().unwrap();
+
+    let snapshot = storage.inner.snapshot();
+    let task = CompactionTask::ForceFullCompaction {
+        l0_sstables: snapshot.l0_sstables.clone(),
+        l1_sstables: Vec::new(),
+    };
+    let watermark = storage.inner.mvcc().watermark();
+    let all = snapshot
+        .l0_sstables
+        .iter()
+        .map(|id| snapshot.sstables[id].clone())
+        .collect::<Vec<_>>();
+    storage
+        .inner
+        .verify_compaction(&task, &snapshot, &all, watermark)
+        .unwrap();
+    let err = storage
+        .inner
+        .verify_compaction(&task, &snapshot, &all[..1], watermark)
+        .unwrap_err();
+    assert!(matches!(Error::from(err), Error::Corruption(_)));
+    storage.close().unwrap();
+}
@@ -0,0 +1,79 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use tempfile::tempdir;
+
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+
+fn key_of(i: usize) -> Vec<u8> {
+    format!("key{i:04}").into_bytes()
+}
+
+/// Key `i` was last written by round `i / 100`, or the last round for the keys past it.
+fn check_values(storage: &MiniLsm) {
+    for i in 0..700 {
+        let expected = format!("value{}", (i / 100).min(5));
+        assert_eq!(
+            storage.get(&key_of(i)).unwrap().unwrap(),
+            expected.as_bytes(),
+            "key {i}"
+        );
+    }
+}
+
+#[test]
+fn test_flush_imm_memtables_in_parallel() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::builder()
+        .num_memtable_limit(100)
+        .flush_threads(4)
+        .build()
+        .unwrap();
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    let mut memtable_ids = Vec::new();
+    for round in 0..6 {
+        for i in 0..200 {
+            // Each round overwrites half of the keys of the previous one.
+            storage
+                .put(&key_of(round * 100 + i), format!("value{round}").as_bytes())
+                .unwrap();
+        }
+        memtable_ids.push(storage.inner.state.read().memtable.id());
+        storage
+            .inner
+            .force_freeze_memtable(&storage.inner.state_lock.lock())
+            .unwrap();
+    }
+
+    // The four oldest memtables are flushed at once, and the newest SST comes first in L0.
+    assert_eq!(storage.inner.force_flush_imm_memtables().unwrap(), 4);
+    let state = storage.inner.state.read().clone();
+    assert_eq!(state.imm_memtables.len(), 2);
+    assert_eq!(
+        state.l0_sstables,
+        memtable_ids[..4].iter().rev().copied().collect::<Vec<_>>()
+    );
+    check_values(&storage);
+
+    assert_eq!(storage.inner.force_flush_imm_memtables().unwrap(), 2);
+    assert!(storage.inner.state.read().imm_memtables.is_empty());
+    storage.close().unwrap();
+
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(
+        storage.inner.state.read().l0_sstables,
+        memtable_ids.iter().rev().copied().collect::<Vec<_>>()
+    );
+    check_values(&storage);
+    storage.close().unwrap();
+}
@@ -0,0 +1,173 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::{CompactionOptions, SimpleLeveledCompactionOptions},
+    iterators::StorageIterator,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+use super::harness::check_lsm_iter_result_by_key;
+
+fn key_of(idx: usize) -> Vec<u8> {
+    format!("key_{:05}", idx).into_bytes()
+}
+
+fn value_of(idx: usize) -> Vec<u8> {
+    format!("value_{:05}", idx).into_bytes()
+}
+
+#[test]
+fn test_readers_do_not_hold_state_lock() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.force_flush().unwrap();
+    storage.put(b"b", b"2").unwrap();
+
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    // The iterator works on its own snapshot, so state swaps can proceed while it is alive.
+    assert!(storage.inner.state.try_write().is_some());
+    storage.put(b"c", b"3").unwrap();
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    check_lsm_iter_result_by_key(
+        &mut iter,
+        vec![
+            (Bytes::from("a"), Bytes::from("1")),
+            (Bytes::from("b"), Bytes::from("2")),
+        ],
+    );
+
+    let snapshot = storage.inner.snapshot();
+    assert!(storage.inner.state.try_write().is_some());
+    storage.put(b"d", b"4").unwrap();
+    storage.force_flush().unwrap();
+    assert!(
+        !std::sync::Arc::ptr_eq(&snapshot, &storage.inner.snapshot()),
+        "flush should install a new state"
+    );
+}
+
+#[test]
+fn test_get_does_not_wait_for_state_writer() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage
+        .inner
+        .force_freeze_memtable(&storage.inner.state_lock.lock())
+        .unwrap();
+    storage.put(b"b", b"2").unwrap();
+
+    let mut guard = storage.inner.state.write();
+    std::thread::scope(|s| {
+        // Probe both the active and the immutable memtable while a state change is in progress.
+        let reader = s.spawn(|| (storage.get(b"a").unwrap(), storage.get(b"b").unwrap()));
+        assert_eq!(
+            reader.join().unwrap(),
+            (Some(Bytes::from("1")), Some(Bytes::from("2")))
+        );
+    });
+    let mut state = guard.as_ref().clone();
+    state.imm_memtables.clear();
+    *guard = std::sync::Arc::new(state);
+    // The new state is only visible to readers once the writer is done.
+    assert_eq!(storage.inner.snapshot().imm_memtables.len(), 1);
+    drop(guard);
+    assert!(storage.inner.snapshot().imm_memtables.is_empty());
+    assert_eq!(storage.get(b"a").unwrap(), None);
+}
+
+#[test]
+fn test_concurrent_reads_during_flush_and_compaction() {
+    const NUM_KEYS: usize = 3000;
+    const NUM_READERS: usize = 4;
+
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::Simple(
+        SimpleLeveledCompactionOptions {
+            size_ratio_percent: 200,
+            level0_file_num_compaction_trigger: 2,
+            max_levels: 3,
+        },
+    ));
+    // Use small memtables so that the writer keeps freezing and the background threads keep flushing and compacting.
+    options.block_size = 256;
+    options.target_sst_size = 4096;
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    // Keys in `[0, written)` are guaranteed to be visible.
+    let written = AtomicUsize::new(0);
+    let done = AtomicBool::new(false);
+
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            for idx in 0..NUM_KEYS {
+                storage.put(&key_of(idx), &value_of(idx)).unwrap();
+                written.store(idx + 1, Ordering::SeqCst);
+            }
+            done.store(true, Ordering::SeqCst);
+        });
+        for reader in 0..NUM_READERS {
+            let storage = &storage;
+            let written = &written;
+            let done = &done;
+            s.spawn(move || {
+                let mut round = reader;
+                while !done.load(Ordering::SeqCst) {
+                    let upto = written.load(Ordering::SeqCst);
+                    if upto == 0 {
+                        continue;
+                    }
+                    let idx = (round * 7919) % upto;
+                    assert_eq!(
+                        storage.get(&key_of(idx)).unwrap(),
+                        Some(Bytes::from(value_of(idx))),
+                        "key {idx} is missing"
+                    );
+                    if round % 16 == 0 {
+                        let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+                        let mut cnt = 0;
+                        while iter.is_valid() {
+                            assert_eq!(iter.key(), key_of(cnt));
+                            assert_eq!(iter.value(), value_of(cnt));
+                            cnt += 1;
+                            iter.next().unwrap();
+                        }
+                        assert!(
+                            cnt >= upto,
+                            "scan returned {cnt} keys, expected at least {upto}"
+                        );
+                    }
+                    round += 1;
+                }
+            });
+        }
+    });
+
+    for idx in 0..NUM_KEYS {
+        assert_eq!(
+            storage.get(&key_of(idx)).unwrap(),
+            Some(Bytes::from(value_of(idx)))
+        );
+    }
+}
@@ -0,0 +1,106 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+fn open(dir: &tempfile::TempDir) -> std::sync::Arc<MiniLsm> {
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    MiniLsm::open(dir, options).unwrap()
+}
+
+#[test]
+fn test_put_if_absent() {
+    let dir = tempdir().unwrap();
+    let storage = open(&dir);
+    assert!(storage.put_if_absent(b"key", b"v1").unwrap());
+    assert!(!storage.put_if_absent(b"key", b"v2").unwrap());
+    assert_eq!(storage.get(b"key").unwrap(), Some(Bytes::from("v1")));
+    storage.delete(b"key").unwrap();
+    assert!(storage.put_if_absent(b"key", b"v3").unwrap());
+    assert_eq!(storage.get(b"key").unwrap(), Some(Bytes::from("v3")));
+    assert!(matches!(
+        storage.put_if_absent(b"other", b""),
+        Err(Error::InvalidArgument(_))
+    ));
+    assert_eq!(storage.get(b"other").unwrap(), None);
+}
+
+#[test]
+fn test_compare_and_swap() {
+    let dir = tempdir().unwrap();
+    let storage = open(&dir);
+    assert!(
+        !storage
+            .compare_and_swap(b"key", Some(b"v1"), Some(b"v2"))
+            .unwrap()
+    );
+    assert_eq!(storage.get(b"key").unwrap(), None);
+    storage.put(b"key", b"v1").unwrap();
+    storage.force_flush().unwrap();
+    assert!(
+        !storage
+            .compare_and_swap(b"key", Some(b"v0"), Some(b"v2"))
+            .unwrap()
+    );
+    assert!(
+        storage
+            .compare_and_swap(b"key", Some(b"v1"), Some(b"v2"))
+            .unwrap()
+    );
+    assert_eq!(storage.get(b"key").unwrap(), Some(Bytes::from("v2")));
+    assert!(storage.compare_and_swap(b"key", Some(b"v2"), None).unwrap());
+    assert_eq!(storage.get(b"key").unwrap(), None);
+    assert!(storage.compare_and_swap(b"key", None, Some(b"v3")).unwrap());
+    assert_eq!(storage.get(b"key").unwrap(), Some(Bytes::from("v3")));
+}
+
+#[test]
+fn test_concurrent_compare_and_swap() {
+    let dir = tempdir().unwrap();
+    let storage = open(&dir);
+    storage.put(b"counter", &0u64.to_be_bytes()).unwrap();
+    let handles = (0..4)
+        .map(|_| {
+            let storage = storage.clone();
+            std::thread::spawn(move || {
+                let mut increments = 0;
+                while increments < 100 {
+                    let current = storage.get(b"counter").unwrap().unwrap();
+                    let next =
+                        (u64::from_be_bytes(current[..].try_into().unwrap()) + 1).to_be_bytes();
+                    if storage
+                        .compare_and_swap(b"counter", Some(&current), Some(&next))
+                        .unwrap()
+                    {
+                        increments += 1;
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    // No increment is lost
+    assert_eq!(
+        storage.get(b"counter").unwrap(),
+        Some(Bytes::copy_from_slice(&400u64.to_be_bytes()))
+    );
+}
@@ -0,0 +1,60 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+#[test]
+fn test_contains() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for i in (0..100).step_by(2) {
+        storage
+            .put(format!("key{i:03}").as_bytes(), b"value")
+            .unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.delete(b"key010").unwrap();
+    storage.put(b"key011", b"value").unwrap();
+
+    for i in 0..100 {
+        let key = format!("key{i:03}");
+        let exists = (i % 2 == 0 && i != 10) || i == 11;
+        assert_eq!(storage.contains(key.as_bytes()).unwrap(), exists, "{key}");
+        assert_eq!(
+            storage.get(key.as_bytes()).unwrap().is_some(),
+            exists,
+            "{key}"
+        );
+        // `may_contain` never misses a key
+        if exists {
+            assert!(storage.may_contain(key.as_bytes()));
+        }
+    }
+    // The delete is in the memtable, so the key may still be there as far as the metadata tells
+    assert!(storage.may_contain(b"key010"));
+    assert!(!storage.may_contain(b"key100"));
+    assert!(!storage.may_contain(b"a"));
+
+    let txn = storage.new_txn().unwrap();
+    txn.put(b"key001", b"value");
+    txn.delete(b"key000");
+    assert!(txn.contains(b"key001").unwrap());
+    assert!(!txn.contains(b"key000").unwrap());
+    assert!(!storage.contains(b"key001").unwrap());
+}
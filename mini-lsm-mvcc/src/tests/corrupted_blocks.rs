@@ -0,0 +1,206 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::ops::Bound;
+use std::sync::Arc;
+
+use tempfile::tempdir;
+
+use crate::iterators::StorageIterator;
+use crate::lsm_error::Error;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm, ReadOptions};
+use crate::table::CorruptedBlock;
+
+type Entry = (Vec<u8>, Vec<u8>);
+
+fn key_of(i: usize) -> Vec<u8> {
+    format!("key{i:04}").into_bytes()
+}
+
+fn options() -> LsmStorageOptions {
+    LsmStorageOptions::builder()
+        .block_size(256)
+        .build()
+        .unwrap()
+}
+
+/// Close `storage` and damage the content of the block at `block_idx` of the SST `sst_id`, returning the keys of the
+/// block among `keys`.
+fn damage_block(
+    storage: Arc<MiniLsm>,
+    path: &std::path::Path,
+    sst_id: usize,
+    block_idx: usize,
+    keys: impl Iterator<Item = Vec<u8>>,
+) -> Vec<Vec<u8>> {
+    let sst = storage.inner.state.read().sstables[&sst_id].clone();
+    let meta = &sst.block_meta[block_idx];
+    let damaged = keys
+        .filter(|key| &key[..] >= meta.first_key.key_ref() && &key[..] <= meta.last_key.key_ref())
+        .collect();
+    let offset = meta.offset;
+    drop(sst);
+    storage.close().unwrap();
+
+    let sst_path = path.join(format!("{sst_id:05}.sst"));
+    let mut data = std::fs::read(&sst_path).unwrap();
+    data[offset + 10] ^= 0xff;
+    std::fs::write(&sst_path, data).unwrap();
+    damaged
+}
+
+/// Write 1000 keys to an SST and damage the content of its block at `block_idx`, returning the SST id and the keys
+/// of the damaged block.
+fn open_with_corrupted_block(path: &std::path::Path, block_idx: usize) -> (usize, Vec<Vec<u8>>) {
+    let storage = MiniLsm::open(path, options()).unwrap();
+    for i in 0..1000 {
+        storage.put(&key_of(i), b"value").unwrap();
+    }
+    storage.force_flush().unwrap();
+    let sst_id = storage.inner.state.read().l0_sstables[0];
+    let damaged = damage_block(storage, path, sst_id, block_idx, (0..1000).map(key_of));
+    (sst_id, damaged)
+}
+
+/// The entries of a full scan, and the blocks it skipped.
+fn scan_all(
+    storage: &MiniLsm,
+    options: &ReadOptions,
+) -> crate::lsm_error::Result<(Vec<Entry>, Vec<CorruptedBlock>)> {
+    let mut iter = storage.scan_with_options(Bound::Unbounded, Bound::Unbounded, options)?;
+    let mut entries = Vec::new();
+    while iter.is_valid() {
+        entries.push((iter.key().to_vec(), iter.value().to_vec()));
+        iter.next()?;
+    }
+    Ok((entries, iter.corrupted_blocks()))
+}
+
+fn keys_of(entries: &[Entry]) -> Vec<Vec<u8>> {
+    entries.iter().map(|(key, _)| key.clone()).collect()
+}
+
+#[test]
+fn test_scan_skips_corrupted_block() {
+    let dir = tempdir().unwrap();
+    let (sst_id, damaged) = open_with_corrupted_block(dir.path(), 3);
+    assert!(!damaged.is_empty());
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    assert!(matches!(
+        scan_all(&storage, &ReadOptions::default()),
+        Err(Error::Corruption(_))
+    ));
+
+    let expected = (0..1000)
+        .map(key_of)
+        .filter(|key| !damaged.contains(key))
+        .collect::<Vec<_>>();
+    for (readahead_size, max_keys_per_poll) in [(0, None), (4096, None), (0, Some(7))] {
+        let options = ReadOptions {
+            readahead_size,
+            max_keys_per_poll,
+            skip_corrupted_blocks: true,
+            ..Default::default()
+        };
+        let (entries, blocks) = scan_all(&storage, &options).unwrap();
+        assert_eq!(keys_of(&entries), expected);
+        assert_eq!(blocks.len(), 1, "{blocks:?}");
+        assert_eq!((blocks[0].sst_id, blocks[0].block_idx), (sst_id, 3));
+        assert!(blocks[0].error.contains("checksum"), "{}", blocks[0].error);
+        assert_eq!(
+            (blocks[0].first_key.as_ref(), blocks[0].last_key.as_ref()),
+            (&damaged[0][..], &damaged[damaged.len() - 1][..])
+        );
+
+        // Point lookups still fail rather than miss the key.
+        assert!(matches!(
+            storage.get_with_options(&damaged[0], &options),
+            Err(Error::Corruption(_))
+        ));
+    }
+
+    // A scan not reaching the damaged block reports nothing.
+    let options = ReadOptions {
+        skip_corrupted_blocks: true,
+        ..Default::default()
+    };
+    let mut iter = storage
+        .scan_with_options(
+            Bound::Included(&key_of(0)),
+            Bound::Excluded(&key_of(10)),
+            &options,
+        )
+        .unwrap();
+    while iter.is_valid() {
+        iter.next().unwrap();
+    }
+    assert!(iter.corrupted_blocks().is_empty());
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_scan_skips_corrupted_first_block() {
+    let dir = tempdir().unwrap();
+    let (_, damaged) = open_with_corrupted_block(dir.path(), 0);
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    let options = ReadOptions {
+        skip_corrupted_blocks: true,
+        ..Default::default()
+    };
+    let (entries, blocks) = scan_all(&storage, &options).unwrap();
+    assert_eq!(entries.len(), 1000 - damaged.len());
+    assert_eq!(entries[0].0, key_of(damaged.len()));
+    assert_eq!(blocks.len(), 1);
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_scan_hides_older_versions_of_corrupted_block() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    for i in 0..1000 {
+        storage.put(&key_of(i), b"old").unwrap();
+    }
+    storage.force_flush().unwrap();
+    for i in 0..1000 {
+        if i % 2 == 0 {
+            storage.delete(&key_of(i)).unwrap();
+        } else {
+            storage.put(&key_of(i), b"new").unwrap();
+        }
+    }
+    storage.force_flush().unwrap();
+    let sst_id = storage.inner.state.read().l0_sstables[0];
+    let damaged = damage_block(storage, dir.path(), sst_id, 3, (0..1000).map(key_of));
+    assert!(damaged.len() > 1);
+
+    // The overwritten and deleted versions in the older SST must not show through the skipped block, but a version
+    // written after the damaged SST does.
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    storage.put(&damaged[0], b"newer").unwrap();
+    let options = ReadOptions {
+        skip_corrupted_blocks: true,
+        ..Default::default()
+    };
+    let (entries, blocks) = scan_all(&storage, &options).unwrap();
+    assert_eq!(blocks.len(), 1);
+    let mut expected = (0..1000)
+        .filter(|i| i % 2 == 1)
+        .map(|i| (key_of(i), b"new".to_vec()))
+        .filter(|(key, _)| !damaged.contains(key))
+        .collect::<Vec<_>>();
+    expected.push((damaged[0].clone(), b"newer".to_vec()));
+    expected.sort();
+    assert_eq!(entries, expected);
+    storage.close().unwrap();
+}
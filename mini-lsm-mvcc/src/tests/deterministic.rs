@@ -0,0 +1,110 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::path::Path;
+
+use tempfile::tempdir;
+
+use crate::{
+    compact::{CompactionOptions, SimpleLeveledCompactionOptions},
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+fn deterministic_options(seed: u64) -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::Simple(
+        SimpleLeveledCompactionOptions {
+            size_ratio_percent: 200,
+            level0_file_num_compaction_trigger: 2,
+            max_levels: 3,
+        },
+    ));
+    options.deterministic_seed = Some(seed);
+    options
+}
+
+/// Write the same workload and return the levels and the unique ids of the SSTs in the resulting tree.
+fn run_workload(dir: &Path, seed: u64) -> (Vec<usize>, Vec<Vec<usize>>, Vec<u128>) {
+    let storage = MiniLsm::open(dir, deterministic_options(seed)).unwrap();
+    for round in 0..6 {
+        for i in 0..200 {
+            storage
+                .put(format!("key{:05}", i * 7 + round).as_bytes(), &[b'v'; 64])
+                .unwrap();
+        }
+        storage.force_flush().unwrap();
+        storage.run_pending_tasks().unwrap();
+    }
+    let snapshot = storage.inner.state.read().clone();
+    let levels = snapshot.levels.iter().map(|(_, ids)| ids.clone()).collect();
+    let unique_ids = storage
+        .live_files()
+        .into_iter()
+        .map(|file| snapshot.sstables[&file.id].properties().unique_id)
+        .collect();
+    storage.close().unwrap();
+    (snapshot.l0_sstables.clone(), levels, unique_ids)
+}
+
+#[test]
+fn test_same_seed_same_tree() {
+    let (dir1, dir2, dir3) = (tempdir().unwrap(), tempdir().unwrap(), tempdir().unwrap());
+    let first = run_workload(dir1.path(), 42);
+    let second = run_workload(dir2.path(), 42);
+    assert_eq!(first, second);
+    assert!(!first.2.is_empty());
+    let other = run_workload(dir3.path(), 43);
+    assert_eq!(first.0, other.0);
+    assert_eq!(first.1, other.1);
+    assert_ne!(first.2, other.2);
+}
+
+#[test]
+fn test_no_background_work_without_tick() {
+    let dir = tempdir().unwrap();
+    let mut options = deterministic_options(7);
+    options.num_memtable_limit = 2;
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for i in 0..4 {
+        storage.put(format!("key{i}").as_bytes(), b"value").unwrap();
+        storage.force_flush().unwrap();
+    }
+    for i in 0..3 {
+        storage.put(format!("key{i}").as_bytes(), b"value").unwrap();
+        storage
+            .inner
+            .force_freeze_memtable(&storage.inner.state_lock.lock())
+            .unwrap();
+    }
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    {
+        let snapshot = storage.inner.state.read();
+        assert_eq!(snapshot.l0_sstables.len(), 4);
+        assert_eq!(snapshot.imm_memtables.len(), 3);
+    }
+
+    // Flushing down to the limit, then compacting L0
+    assert!(storage.run_pending_tasks().unwrap() >= 2);
+    let snapshot = storage.inner.state.read().clone();
+    assert!(snapshot.imm_memtables.len() < 2);
+    assert!(snapshot.l0_sstables.len() < 2);
+    assert_eq!(storage.run_pending_tasks().unwrap(), 0);
+    for i in 0..4 {
+        assert_eq!(
+            storage
+                .get(format!("key{i}").as_bytes())
+                .unwrap()
+                .as_deref(),
+            Some(&b"value"[..])
+        );
+    }
+}
@@ -0,0 +1,92 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use tempfile::tempdir;
+
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+use crate::stats::Histogram;
+
+#[test]
+fn test_histogram() {
+    let mut histogram = Histogram::default();
+    assert_eq!(histogram.percentile(0.5), 0);
+    for value in [0, 1, 2, 3, 100, 1000, u64::MAX] {
+        histogram.record(value);
+    }
+    assert_eq!(histogram.count(), 7);
+    assert_eq!(histogram.max(), u64::MAX);
+    assert_eq!(
+        histogram.buckets().collect::<Vec<_>>(),
+        vec![(0, 1), (1, 1), (3, 2), (127, 1), (1023, 1), (u64::MAX, 1)]
+    );
+    assert_eq!(histogram.percentile(0.0), 0);
+    assert_eq!(histogram.percentile(0.5), 3);
+    assert_eq!(histogram.percentile(0.8), 1023);
+    assert_eq!(histogram.percentile(1.0), u64::MAX);
+
+    let mut merged = Histogram::default();
+    merged.record(5);
+    merged.merge(&histogram);
+    assert_eq!(merged.count(), 8);
+    assert_eq!(merged.percentile(0.5), 3);
+    assert_eq!(merged.percentile(0.75), 127);
+}
+
+#[test]
+fn test_level_entry_stats() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    for i in 0..100 {
+        storage
+            .put(format!("key{i:03}").as_bytes(), &[b'v'; 10])
+            .unwrap();
+    }
+    // Keep the old versions of the first keys alive across the compaction
+    let txn = storage.new_txn().unwrap();
+    for i in 0..10 {
+        storage
+            .put(format!("key{i:03}").as_bytes(), &[b'v'; 1000])
+            .unwrap();
+        storage.delete(format!("key{i:03}").as_bytes()).unwrap();
+    }
+    storage.force_flush().unwrap();
+    // Flushed SSTs are not covered
+    assert!(
+        storage
+            .level_entry_stats()
+            .iter()
+            .all(|(_, stats)| stats.num_sstables == 0)
+    );
+
+    storage.force_full_compaction().unwrap();
+    let stats = storage.level_entry_stats();
+    assert_eq!(stats[0].0, 0);
+    let (level, stats) = &stats[1];
+    assert_eq!(*level, 1);
+    assert_eq!(stats.num_sstables, 1);
+    assert_eq!(stats.key_size.count(), 120);
+    assert_eq!(stats.key_size.max(), 6);
+    assert_eq!(stats.value_size.max(), 1000);
+    assert_eq!(stats.value_size.percentile(0.5), 15);
+    assert_eq!(stats.versions_per_key.count(), 100);
+    assert_eq!(stats.versions_per_key.max(), 3);
+    assert_eq!(stats.versions_per_key.sum(), 120);
+
+    // Once the old versions are released, compaction drops them and the stats of the replaced SST
+    drop(txn);
+    storage.force_full_compaction().unwrap();
+    let stats = &storage.level_entry_stats()[1].1;
+    assert_eq!(stats.num_sstables, 1);
+    assert_eq!(stats.key_size.count(), 90);
+    assert_eq!(stats.versions_per_key.max(), 1);
+}
@@ -0,0 +1,95 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs::OpenOptions;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MiniLsm, WriteBatchRecord},
+};
+
+#[test]
+fn test_invalid_argument() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert!(matches!(
+        storage.put(b"", b"value"),
+        Err(Error::InvalidArgument(_))
+    ));
+    assert!(matches!(
+        storage.put(b"key", b""),
+        Err(Error::InvalidArgument(_))
+    ));
+    assert!(matches!(
+        storage.write_batch(&[
+            WriteBatchRecord::Put(&b"key"[..], &b"value"[..]),
+            WriteBatchRecord::Del(&b""[..]),
+        ]),
+        Err(Error::InvalidArgument(_))
+    ));
+    assert_eq!(storage.get(b"key").unwrap(), None);
+}
+
+#[test]
+fn test_serializable_conflict_is_busy() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.serializable = true;
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"key1", b"1").unwrap();
+    storage.put(b"key2", b"2").unwrap();
+    let txn1 = storage.new_txn().unwrap();
+    let txn2 = storage.new_txn().unwrap();
+    txn1.put(b"key1", &txn1.get(b"key2").unwrap().unwrap());
+    txn2.put(b"key2", &txn2.get(b"key1").unwrap().unwrap());
+    txn1.commit().unwrap();
+    assert!(matches!(txn2.commit(), Err(Error::Busy(_))));
+}
+
+#[test]
+fn test_block_corruption() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    storage.put(b"key", b"value").unwrap();
+    storage.force_flush().unwrap();
+    storage.close().unwrap();
+    drop(storage);
+
+    let sst = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.extension().is_some_and(|ext| ext == "sst"))
+        .expect("no SST was flushed");
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&sst)
+        .unwrap();
+    let mut byte = [0; 1];
+    file.seek(SeekFrom::Start(4)).unwrap();
+    file.read_exact(&mut byte).unwrap();
+    byte[0] ^= 0xff;
+    file.seek(SeekFrom::Start(4)).unwrap();
+    file.write_all(&byte).unwrap();
+    drop(file);
+
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert!(matches!(storage.get(b"key"), Err(Error::Corruption(_))));
+}
@@ -0,0 +1,150 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use tempfile::tempdir;
+
+use crate::{
+    compact::{CompactionOptions, CompactionTask},
+    event_listener::EventListener,
+    lsm_storage::{LsmStorageInner, LsmStorageOptions, MiniLsm},
+};
+
+#[derive(Default)]
+struct RecordingListener {
+    events: Mutex<Vec<String>>,
+}
+
+impl EventListener for RecordingListener {
+    fn on_flush_begin(&self, memtable_ids: &[usize]) {
+        self.events
+            .lock()
+            .push(format!("flush begin {memtable_ids:?}"));
+    }
+
+    fn on_flush_completed(&self, memtable_ids: &[usize], sst_ids: &[Vec<usize>]) {
+        self.events
+            .lock()
+            .push(format!("flush completed {memtable_ids:?} {sst_ids:?}"));
+    }
+
+    fn on_compaction_begin(&self, task: &CompactionTask) {
+        self.events
+            .lock()
+            .push(format!("compaction begin {task:?}"));
+    }
+
+    fn on_compaction_completed(&self, task: &CompactionTask, output: &[usize]) {
+        self.events
+            .lock()
+            .push(format!("compaction completed {task:?} {output:?}"));
+    }
+
+    fn on_table_deleted(&self, sst_id: usize) {
+        self.events.lock().push(format!("table deleted {sst_id}"));
+    }
+
+    fn on_write_stall(&self, stalled: bool, num_imm_memtables: usize) {
+        self.events
+            .lock()
+            .push(format!("write stall {stalled} {num_imm_memtables}"));
+    }
+}
+
+#[test]
+fn test_event_listener_flush_and_compaction() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(
+        &dir,
+        LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction),
+    )
+    .unwrap();
+    let listener = Arc::new(RecordingListener::default());
+    storage.add_event_listener(listener.clone());
+
+    storage.put(b"a", b"1").unwrap();
+    let first = storage.inner.state.read().memtable.id();
+    storage.force_flush().unwrap();
+    storage.put(b"b", b"2").unwrap();
+    let second = storage.inner.state.read().memtable.id();
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    let output = storage.inner.state.read().levels[0].1.clone();
+
+    let task = CompactionTask::ForceFullCompaction {
+        l0_sstables: vec![second, first],
+        l1_sstables: vec![],
+    };
+    assert_eq!(
+        *listener.events.lock(),
+        vec![
+            format!("flush begin [{first}]"),
+            format!("flush completed [{first}] [[{first}]]"),
+            format!("flush begin [{second}]"),
+            format!("flush completed [{second}] [[{second}]]"),
+            format!("compaction begin {task:?}"),
+            format!("compaction completed {task:?} {output:?}"),
+            format!("table deleted {second}"),
+            format!("table deleted {first}"),
+        ]
+    );
+    assert!(!storage.inner.path_of_sst(first).exists());
+    assert!(!storage.inner.path_of_sst(second).exists());
+    assert_eq!(storage.get(b"a").unwrap(), Some(b"1".as_slice().into()));
+}
+
+fn freeze(storage: &LsmStorageInner) {
+    storage
+        .force_freeze_memtable(&storage.state_lock.lock())
+        .unwrap();
+}
+
+#[test]
+fn test_event_listener_write_stall() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.num_memtable_limit = 2;
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let listener = Arc::new(RecordingListener::default());
+    storage.add_event_listener(listener.clone());
+    storage.pause_background();
+
+    let write_stalls = || {
+        listener
+            .events
+            .lock()
+            .iter()
+            .filter(|event| event.starts_with("write stall"))
+            .cloned()
+            .collect::<Vec<_>>()
+    };
+    for i in 0..3 {
+        storage.put(format!("key{i}").as_bytes(), b"v").unwrap();
+        freeze(&storage.inner);
+    }
+    assert_eq!(write_stalls(), vec!["write stall true 3"]);
+
+    // Still stalled, so not notified again
+    freeze(&storage.inner);
+    storage.inner.force_flush_next_imm_memtable().unwrap();
+    assert_eq!(write_stalls(), vec!["write stall true 3"]);
+    storage.inner.force_flush_next_imm_memtable().unwrap();
+    assert_eq!(
+        write_stalls(),
+        vec!["write stall true 3", "write stall false 2"]
+    );
+    storage.resume_background();
+}
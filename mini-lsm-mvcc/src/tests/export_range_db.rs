@@ -0,0 +1,132 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::ops::Bound;
+
+use tempfile::tempdir;
+
+use crate::{
+    compact::{CompactionOptions, LeveledCompactionOptions},
+    iterators::StorageIterator,
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+fn contents(
+    storage: &MiniLsm,
+    lower: Bound<&[u8]>,
+    upper: Bound<&[u8]>,
+) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut iter = storage.scan(lower, upper).unwrap();
+    let mut contents = Vec::new();
+    while iter.is_valid() {
+        contents.push((iter.key().to_vec(), iter.value().to_vec()));
+        iter.next().unwrap();
+    }
+    contents
+}
+
+fn run(options: LsmStorageOptions) {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(dir.path().join("source"), options.clone()).unwrap();
+    for round in 0..4 {
+        for i in 0..300 {
+            storage
+                .put(
+                    format!("key{i:03}").as_bytes(),
+                    format!("v{round}").as_bytes(),
+                )
+                .unwrap();
+        }
+        for i in (round..300).step_by(7) {
+            storage.delete(format!("key{i:03}").as_bytes()).unwrap();
+        }
+        if round < 3 {
+            storage.force_flush().unwrap();
+        }
+    }
+
+    let (lower, upper) = (
+        Bound::Included(&b"key100"[..]),
+        Bound::Excluded(&b"key200"[..]),
+    );
+    let expected = contents(&storage, lower, upper);
+    let target_dir = dir.path().join("target");
+    let cnt = storage
+        .export_range_as_db(lower, upper, &target_dir)
+        .unwrap();
+    assert_eq!(cnt, expected.len());
+    // later writes to the source are not copied
+    storage.put(b"key150", b"after").unwrap();
+
+    let target = MiniLsm::open(&target_dir, options).unwrap();
+    assert_eq!(
+        contents(&target, Bound::Unbounded, Bound::Unbounded),
+        expected
+    );
+    {
+        let snapshot = target.inner.state.read();
+        assert!(snapshot.l0_sstables.is_empty());
+        assert!(!snapshot.levels.last().unwrap().1.is_empty());
+    }
+    // the copy is a database of its own
+    target.put(b"key999", b"new").unwrap();
+    assert_eq!(storage.get(b"key999").unwrap(), None);
+    target.close().unwrap();
+
+    assert!(matches!(
+        storage.export_range_as_db(lower, upper, &target_dir),
+        Err(Error::InvalidArgument(_))
+    ));
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_export_range_as_db() {
+    run(LsmStorageOptions::default_for_week2_test(
+        CompactionOptions::NoCompaction,
+    ));
+}
+
+#[test]
+fn test_export_range_as_db_leveled_with_wal() {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::Leveled(
+        LeveledCompactionOptions {
+            level0_file_num_compaction_trigger: 2,
+            max_levels: 3,
+            base_level_size_mb: 1,
+            level_size_multiplier: 2,
+        },
+    ));
+    options.enable_wal = true;
+    run(options);
+}
+
+#[test]
+fn test_export_empty_range_as_db() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(dir.path().join("source"), options.clone()).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    let target_dir = dir.path().join("target");
+    assert_eq!(
+        storage
+            .export_range_as_db(Bound::Included(b"b"), Bound::Unbounded, &target_dir)
+            .unwrap(),
+        0
+    );
+    let target = MiniLsm::open(&target_dir, options).unwrap();
+    assert!(contents(&target, Bound::Unbounded, Bound::Unbounded).is_empty());
+    target.close().unwrap();
+    storage.close().unwrap();
+}
@@ -0,0 +1,123 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    export::ExportManifest,
+    iterators::{StorageIterator, concat_iterator::SstConcatIterator},
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    table::{FileObject, SsTable, SsTableIterator},
+};
+
+#[test]
+fn test_export_snapshot() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.block_size = 64;
+    options.target_sst_size = 256;
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for i in 0..100 {
+        storage
+            .put(format!("key_{:03}", i).as_bytes(), b"value1")
+            .unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.delete(b"key_000").unwrap();
+    let txn = storage.new_txn().unwrap();
+    for i in 0..100 {
+        storage
+            .put(format!("key_{:03}", i).as_bytes(), b"value2")
+            .unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+
+    let export_dir = tempdir().unwrap();
+    let manifest = storage
+        .export_snapshot(export_dir.path(), txn.read_ts())
+        .unwrap();
+    assert_eq!(manifest, ExportManifest::read(export_dir.path()).unwrap());
+    assert_eq!(manifest.ts, txn.read_ts());
+    assert!(manifest.sst_ids.len() > 1);
+
+    let ssts = manifest
+        .sst_ids
+        .iter()
+        .map(|id| {
+            let file =
+                FileObject::open(&ExportManifest::path_of_sst(export_dir.path(), *id)).unwrap();
+            Arc::new(SsTable::open(*id, None, file).unwrap())
+        })
+        .collect::<Vec<_>>();
+    for pair in ssts.windows(2) {
+        assert!(pair[0].last_key() < pair[1].first_key());
+    }
+    let mut iter = SstConcatIterator::create_and_seek_to_first(ssts).unwrap();
+    for i in 1..100 {
+        assert!(iter.is_valid());
+        assert_eq!(iter.key().key_ref(), format!("key_{:03}", i).as_bytes());
+        assert_eq!(iter.key().ts(), txn.read_ts());
+        assert_eq!(iter.value(), b"value1");
+        iter.next().unwrap();
+    }
+    assert!(!iter.is_valid());
+}
+
+#[test]
+fn test_export_snapshot_invalid() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.put(b"a", b"2").unwrap();
+    let latest_ts = storage.new_txn().unwrap().read_ts();
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+
+    // Versions older than the watermark of the compaction may have been garbage-collected.
+    let export_dir = tempdir().unwrap();
+    assert!(matches!(
+        storage.export_snapshot(export_dir.path(), latest_ts - 1),
+        Err(Error::InvalidArgument(_))
+    ));
+    assert!(matches!(
+        storage.export_snapshot(export_dir.path(), latest_ts + 1),
+        Err(Error::InvalidArgument(_))
+    ));
+    // The export directory must be empty.
+    assert!(matches!(
+        storage.export_snapshot(&dir, latest_ts),
+        Err(Error::InvalidArgument(_))
+    ));
+
+    let manifest = storage
+        .export_snapshot(export_dir.path().join("export"), latest_ts)
+        .unwrap();
+    assert_eq!(manifest.sst_ids, vec![0]);
+    let file = FileObject::open(&ExportManifest::path_of_sst(
+        export_dir.path().join("export"),
+        0,
+    ))
+    .unwrap();
+    let sst = SsTable::open(0, None, file).unwrap();
+    assert_eq!(sst.first_key().key_ref(), b"a");
+    assert_eq!(sst.last_key().key_ref(), b"a");
+    let iter = SsTableIterator::create_and_seek_to_first(Arc::new(sst)).unwrap();
+    assert_eq!(iter.value(), b"2");
+}
@@ -0,0 +1,280 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    checksum::crc32c,
+    compact::CompactionOptions,
+    external_table::{ExternalTable, mask_crc32c},
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+const TYPE_DELETION: u8 = 0;
+const TYPE_VALUE: u8 = 1;
+const TYPE_MERGE: u8 = 2;
+
+fn put_varint(buf: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        buf.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+fn put_handle(buf: &mut Vec<u8>, (offset, size): (u64, u64)) {
+    put_varint(buf, offset);
+    put_varint(buf, size);
+}
+
+fn internal_key(key: &[u8], seq: u64, value_type: u8) -> Vec<u8> {
+    let mut buf = key.to_vec();
+    buf.extend(((seq << 8) | value_type as u64).to_le_bytes());
+    buf
+}
+
+/// Encode a block with prefix-compressed keys. With `delta_handles`, values are `(offset, size)` block handles
+/// encoded the way RocksDB encodes index blocks with `format_version` >= 4.
+fn encode_block(
+    entries: &[(Vec<u8>, Vec<u8>)],
+    restart_interval: usize,
+    delta_handles: Option<&[(u64, u64)]>,
+) -> Vec<u8> {
+    let mut buf = Vec::new();
+    let mut restarts = Vec::new();
+    let mut last_key: &[u8] = &[];
+    for (idx, (key, value)) in entries.iter().enumerate() {
+        let shared = if idx % restart_interval == 0 {
+            restarts.push(buf.len() as u32);
+            0
+        } else {
+            key.iter().zip(last_key).take_while(|(a, b)| a == b).count()
+        };
+        put_varint(&mut buf, shared as u64);
+        put_varint(&mut buf, (key.len() - shared) as u64);
+        match delta_handles {
+            Some(handles) => {
+                buf.extend(&key[shared..]);
+                if shared > 0 {
+                    let delta = handles[idx].1 as i64 - handles[idx - 1].1 as i64;
+                    put_varint(&mut buf, ((delta << 1) ^ (delta >> 63)) as u64);
+                } else {
+                    put_handle(&mut buf, handles[idx]);
+                }
+            }
+            None => {
+                put_varint(&mut buf, value.len() as u64);
+                buf.extend(&key[shared..]);
+                buf.extend(value);
+            }
+        }
+        last_key = key;
+    }
+    for restart in &restarts {
+        buf.extend(restart.to_le_bytes());
+    }
+    buf.extend((restarts.len() as u32).to_le_bytes());
+    buf
+}
+
+struct TableWriter {
+    buf: Vec<u8>,
+    snappy: bool,
+}
+
+impl TableWriter {
+    fn add_block(&mut self, block: &[u8]) -> (u64, u64) {
+        let offset = self.buf.len() as u64;
+        let (compression_type, block) = if self.snappy {
+            (1, snap::raw::Encoder::new().compress_vec(block).unwrap())
+        } else {
+            (0, block.to_vec())
+        };
+        self.buf.extend(&block);
+        self.buf.push(compression_type);
+        let checksum = mask_crc32c(crc32c(&self.buf[offset as usize..]));
+        self.buf.extend(checksum.to_le_bytes());
+        (offset, block.len() as u64)
+    }
+}
+
+/// Write a table with one data block per element of `blocks`. Without `format_version`, a LevelDB table is written.
+fn write_table(
+    blocks: &[Vec<(Vec<u8>, Vec<u8>)>],
+    format_version: Option<u32>,
+    snappy: bool,
+) -> Vec<u8> {
+    let mut writer = TableWriter {
+        buf: Vec::new(),
+        snappy,
+    };
+    let mut handles = Vec::new();
+    let mut index_entries = Vec::new();
+    for block in blocks {
+        handles.push(writer.add_block(&encode_block(block, 4, None)));
+        index_entries.push((block.last().unwrap().0.clone(), Vec::new()));
+    }
+    let delta_handles = format_version.is_some_and(|version| version >= 4);
+    let mut metaindex = Vec::new();
+    if let Some(format_version) = format_version {
+        let properties = vec![
+            (
+                b"rocksdb.comparator".to_vec(),
+                b"leveldb.BytewiseComparator".to_vec(),
+            ),
+            (
+                b"rocksdb.index.value.is.delta.encoded".to_vec(),
+                vec![delta_handles as u8],
+            ),
+            (
+                b"rocksdb.format.version".to_vec(),
+                vec![format_version as u8],
+            ),
+        ];
+        let properties = writer.add_block(&encode_block(&properties, 1, None));
+        let mut value = Vec::new();
+        put_handle(&mut value, properties);
+        metaindex.push((b"rocksdb.properties".to_vec(), value));
+    }
+    let metaindex = writer.add_block(&encode_block(&metaindex, 1, None));
+    let index = if delta_handles {
+        writer.add_block(&encode_block(&index_entries, 2, Some(&handles)))
+    } else {
+        for (entry, handle) in index_entries.iter_mut().zip(&handles) {
+            put_handle(&mut entry.1, *handle);
+        }
+        writer.add_block(&encode_block(&index_entries, 1, None))
+    };
+
+    let mut footer = Vec::new();
+    if format_version.is_some() {
+        footer.push(1); // CRC32C
+    }
+    put_handle(&mut footer, metaindex);
+    put_handle(&mut footer, index);
+    match format_version {
+        Some(format_version) => {
+            footer.resize(41, 0);
+            footer.extend(format_version.to_le_bytes());
+            footer.extend(0x88e241b785f4cff7u64.to_le_bytes());
+        }
+        None => {
+            footer.resize(40, 0);
+            footer.extend(0xdb4775248b80fb57u64.to_le_bytes());
+        }
+    }
+    writer.buf.extend(footer);
+    writer.buf
+}
+
+fn test_data() -> Vec<Vec<(Vec<u8>, Vec<u8>)>> {
+    let mut entries = Vec::new();
+    for i in 0..100u64 {
+        let key = format!("key_{:03}", i).into_bytes();
+        if i % 10 == 0 {
+            entries.push((internal_key(&key, 1000 + i, TYPE_DELETION), Vec::new()));
+        }
+        entries.push((
+            internal_key(&key, 500 + i, TYPE_VALUE),
+            format!("value_{}", i).into_bytes(),
+        ));
+        if i % 7 == 0 {
+            entries.push((internal_key(&key, 100 + i, TYPE_VALUE), b"old".to_vec()));
+        }
+    }
+    entries.chunks(16).map(|chunk| chunk.to_vec()).collect()
+}
+
+fn check_imported(storage: &MiniLsm) {
+    for i in 0..100 {
+        let value = storage.get(format!("key_{:03}", i).as_bytes()).unwrap();
+        if i % 10 == 0 {
+            assert_eq!(value, None);
+        } else {
+            assert_eq!(value, Some(Bytes::from(format!("value_{}", i))));
+        }
+    }
+}
+
+#[test]
+fn test_import_leveldb_table() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    storage.put(b"key_000", b"overwritten").unwrap();
+    storage.put(b"other", b"kept").unwrap();
+    let table_path = dir.path().join("leveldb.ldb");
+    std::fs::write(&table_path, write_table(&test_data(), None, false)).unwrap();
+    let summary = storage.import_external_table(&table_path).unwrap();
+    assert_eq!(summary.num_entries, 100);
+    check_imported(&storage);
+    assert_eq!(storage.get(b"other").unwrap(), Some(Bytes::from("kept")));
+
+    storage.close().unwrap();
+    drop(storage);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    check_imported(&storage);
+    storage.put(b"key_001", b"new").unwrap();
+    assert_eq!(storage.get(b"key_001").unwrap(), Some(Bytes::from("new")));
+}
+
+#[test]
+fn test_import_rocksdb_table() {
+    for format_version in [2, 5] {
+        let dir = tempdir().unwrap();
+        let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+        let storage = MiniLsm::open(&dir, options).unwrap();
+        let table_path = dir.path().join("rocksdb.sst.external");
+        std::fs::write(
+            &table_path,
+            write_table(&test_data(), Some(format_version), true),
+        )
+        .unwrap();
+        storage.import_external_table(&table_path).unwrap();
+        check_imported(&storage);
+    }
+}
+
+#[test]
+fn test_import_unsupported_or_corrupted_table() {
+    let mut data = test_data();
+    data[0][0].0 = internal_key(b"key_000", 2000, TYPE_MERGE);
+    let table = ExternalTable::decode(write_table(&data, Some(5), false)).unwrap();
+    assert!(matches!(
+        table
+            .iter()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .map_err(Error::from),
+        Err(Error::InvalidArgument(_))
+    ));
+
+    let mut table = write_table(&test_data(), None, false);
+    table[10] ^= 0xff;
+    let table = ExternalTable::decode(table).unwrap();
+    assert!(matches!(
+        table
+            .iter()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .map_err(Error::from),
+        Err(Error::Corruption(_))
+    ));
+
+    let table = write_table(&test_data(), Some(6), false);
+    assert!(matches!(
+        ExternalTable::decode(table).map_err(Error::from),
+        Err(Error::InvalidArgument(_))
+    ));
+}
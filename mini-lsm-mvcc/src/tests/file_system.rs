@@ -0,0 +1,206 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use tempfile::tempdir;
+
+use crate::{
+    file_system::{FileSystem, FileSystemOptions, ReadFile, WriteFile},
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+/// Keeps the files in memory, as a host embedding the engine might.
+#[derive(Debug, Default)]
+struct MemFileSystem {
+    files: Mutex<BTreeMap<PathBuf, Arc<Mutex<Vec<u8>>>>>,
+}
+
+struct MemFile {
+    data: Arc<Mutex<Vec<u8>>>,
+    pos: Mutex<usize>,
+}
+
+impl MemFileSystem {
+    fn open(&self, path: &Path) -> io::Result<MemFile> {
+        let data = self
+            .files
+            .lock()
+            .get(path)
+            .cloned()
+            .ok_or(io::ErrorKind::NotFound)?;
+        Ok(MemFile {
+            data,
+            pos: Mutex::new(0),
+        })
+    }
+
+    fn names(&self) -> Vec<String> {
+        self.files
+            .lock()
+            .keys()
+            .map(|path| path.file_name().unwrap().to_str().unwrap().to_string())
+            .collect()
+    }
+}
+
+impl FileSystem for MemFileSystem {
+    fn open_read(&self, path: &Path) -> io::Result<Box<dyn ReadFile>> {
+        Ok(Box::new(self.open(path)?))
+    }
+
+    fn write_synced(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        self.files
+            .lock()
+            .insert(path.to_path_buf(), Arc::new(Mutex::new(data.to_vec())));
+        Ok(())
+    }
+
+    fn create_new(&self, path: &Path) -> io::Result<Box<dyn WriteFile>> {
+        if self.files.lock().contains_key(path) {
+            return Err(io::ErrorKind::AlreadyExists.into());
+        }
+        self.write_synced(path, &[])?;
+        self.open_write(path)
+    }
+
+    fn open_write(&self, path: &Path) -> io::Result<Box<dyn WriteFile>> {
+        Ok(Box::new(self.open(path)?))
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        Ok(self.open(path)?.data.lock().clone())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut files = self.files.lock();
+        let data = files.remove(from).ok_or(io::ErrorKind::NotFound)?;
+        files.insert(to.to_path_buf(), data);
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        self.files
+            .lock()
+            .remove(path)
+            .ok_or(io::ErrorKind::NotFound)?;
+        Ok(())
+    }
+
+    fn list_dir(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        Ok(self
+            .files
+            .lock()
+            .keys()
+            .filter(|path| path.parent() == Some(dir))
+            .cloned()
+            .collect())
+    }
+}
+
+impl ReadFile for MemFile {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        let data = self.data.lock();
+        let offset = offset as usize;
+        let src = data
+            .get(offset..offset + buf.len())
+            .ok_or(io::ErrorKind::UnexpectedEof)?;
+        buf.copy_from_slice(src);
+        Ok(())
+    }
+
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.data.lock().len() as u64)
+    }
+}
+
+impl WriteFile for MemFile {
+    fn write(&self, buf: &[u8]) -> io::Result<usize> {
+        let mut data = self.data.lock();
+        let mut pos = self.pos.lock();
+        let end = *pos + buf.len();
+        if data.len() < end {
+            data.resize(end, 0);
+        }
+        data[*pos..end].copy_from_slice(buf);
+        *pos = end;
+        Ok(buf.len())
+    }
+
+    fn seek(&self, offset: u64) -> io::Result<()> {
+        *self.pos.lock() = offset as usize;
+        Ok(())
+    }
+
+    fn set_len(&self, len: u64) -> io::Result<()> {
+        self.data.lock().resize(len as usize, 0);
+        Ok(())
+    }
+
+    fn sync(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn test_external_file_system(wal_segment_size: Option<usize>) {
+    let dir = tempdir().unwrap();
+    let file_system = Arc::new(MemFileSystem::default());
+    let mut options = LsmStorageOptions::default_for_week1_test();
+    options.enable_wal = true;
+    options.wal_segment_size = wal_segment_size;
+    options.file_system = FileSystemOptions::External(file_system.clone());
+
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.force_flush().unwrap();
+    storage.put(b"b", b"2").unwrap();
+    storage.force_flush().unwrap();
+    storage.put(b"a", b"3").unwrap();
+    storage.close().unwrap();
+    drop(storage);
+
+    // The SSTs and WALs are only in the external file system
+    let on_disk = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    assert!(
+        on_disk
+            .iter()
+            .all(|name| !name.contains(".sst") && !name.contains(".wal")),
+        "{on_disk:?}"
+    );
+    let names = file_system.names();
+    assert!(names.iter().any(|name| name.ends_with(".sst")), "{names:?}");
+    assert!(names.iter().any(|name| name.ends_with(".wal")), "{names:?}");
+
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(&storage.get(b"a").unwrap().unwrap()[..], b"3");
+    assert_eq!(&storage.get(b"b").unwrap().unwrap()[..], b"2");
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_external_file_system_with_wal() {
+    test_external_file_system(None);
+}
+
+#[test]
+fn test_external_file_system_with_recycled_wal() {
+    test_external_file_system(Some(64 << 10));
+}
@@ -0,0 +1,104 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::sync::Arc;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tempfile::tempdir;
+
+use crate::iterators::StorageIterator;
+use crate::key::{KeySlice, KeyVec};
+use crate::table::{SsTable, SsTableBuilder, SsTableIterator};
+
+fn random_key(rng: &mut StdRng) -> Vec<u8> {
+    // A small alphabet and short keys make shared prefixes and probes between stored keys likely
+    let len = rng.gen_range(1..6);
+    (0..len).map(|_| rng.gen_range(b'a'..b'e')).collect()
+}
+
+fn build_random_sst(rng: &mut StdRng, path: &std::path::Path) -> (SsTable, Vec<KeyVec>) {
+    let mut entries = Vec::new();
+    for _ in 0..rng.gen_range(1..300) {
+        let key = random_key(rng);
+        for _ in 0..rng.gen_range(1..4) {
+            entries.push(KeyVec::from_vec_with_ts(key.clone(), rng.gen_range(1..10)));
+        }
+    }
+    entries.sort();
+    entries.dedup();
+    let mut builder = SsTableBuilder::new(rng.gen_range(32..256));
+    for key in &entries {
+        builder.try_add(key.as_key_slice(), key.key_ref()).unwrap();
+    }
+    (builder.build_for_test(path).unwrap(), entries)
+}
+
+#[test]
+fn test_find_block_idx_against_linear_scan() {
+    let dir = tempdir().unwrap();
+    let mut rng = StdRng::seed_from_u64(410);
+    for round in 0..50 {
+        let (sst, entries) = build_random_sst(&mut rng, &dir.path().join(format!("{round}.sst")));
+        let sst = Arc::new(sst);
+        let mut probes = entries.clone();
+        for _ in 0..200 {
+            probes.push(KeyVec::from_vec_with_ts(
+                random_key(&mut rng),
+                rng.gen_range(0..11),
+            ));
+        }
+        for probe in &probes {
+            let probe = probe.as_key_slice();
+            let expected_block = sst
+                .block_meta
+                .iter()
+                .position(|meta| meta.last_key.as_key_slice() >= probe)
+                .unwrap_or(sst.num_of_blocks());
+            assert_eq!(sst.find_block_idx(probe), expected_block, "{probe:?}");
+
+            let expected_entry = entries.iter().find(|key| key.as_key_slice() >= probe);
+            let created = SsTableIterator::create_and_seek_to_key(sst.clone(), probe).unwrap();
+            let mut seeked = SsTableIterator::create_and_seek_to_first(sst.clone()).unwrap();
+            seeked.seek_to_key(probe).unwrap();
+            for iter in [created, seeked] {
+                assert_eq!(
+                    iter.is_valid().then(|| iter.key()),
+                    expected_entry.map(|key| key.as_key_slice()),
+                    "{probe:?}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_find_block_idx_lands_on_visible_version() {
+    let dir = tempdir().unwrap();
+    let mut builder = SsTableBuilder::new(32);
+    for ts in (1..=20).rev() {
+        builder.add(KeySlice::from_slice(b"key", ts), b"value");
+    }
+    let sst = builder.build_for_test(dir.path().join("1.sst")).unwrap();
+    assert!(sst.num_of_blocks() > 2);
+    for ts in 1..=20 {
+        let blk_idx = sst.find_block_idx(KeySlice::from_slice(b"key", ts));
+        let meta = &sst.block_meta[blk_idx];
+        assert!(meta.first_key.ts() >= ts && ts >= meta.last_key.ts());
+    }
+    assert_eq!(sst.find_block_idx(KeySlice::from_slice(b"key", 30)), 0);
+    assert_eq!(
+        sst.find_block_idx(KeySlice::from_slice(b"key", 0)),
+        sst.num_of_blocks()
+    );
+}
@@ -0,0 +1,125 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::ops::Bound;
+
+use tempfile::tempdir;
+
+use crate::compact::{CompactionOptions, TieredCompactionOptions};
+use crate::iterators::StorageIterator;
+use crate::key::KeySlice;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+use crate::mem_table::MemTable;
+use crate::table::SsTableBuilder;
+
+fn key_of(i: usize) -> Vec<u8> {
+    format!("key{i:04}").into_bytes()
+}
+
+fn options(compaction_options: CompactionOptions, target_sst_size: usize) -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(compaction_options);
+    options.enable_wal = true;
+    options.num_memtable_limit = 100;
+    options.target_sst_size = target_sst_size;
+    options
+}
+
+/// Leave a frozen memtable of about 80KB in a WAL, as a storage with a larger SST size would.
+fn prepare(dir: &std::path::Path, compaction_options: CompactionOptions) {
+    let storage = MiniLsm::open(dir, options(compaction_options, 1 << 20)).unwrap();
+    storage.pause_background();
+    for i in 0..1000 {
+        storage.put(&key_of(i), &[b'v'; 64]).unwrap();
+    }
+    storage
+        .inner
+        .force_freeze_memtable(&storage.inner.state_lock.lock())
+        .unwrap();
+    storage.close().unwrap();
+}
+
+fn check_contents(storage: &MiniLsm) {
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    let mut count = 0;
+    while iter.is_valid() {
+        assert_eq!(iter.key(), key_of(count));
+        count += 1;
+        iter.next().unwrap();
+    }
+    assert_eq!(count, 1000);
+}
+
+#[test]
+fn test_flush_splits_oversized_memtable() {
+    for compaction_options in [
+        CompactionOptions::NoCompaction,
+        CompactionOptions::Tiered(TieredCompactionOptions {
+            num_tiers: 10,
+            max_size_amplification_percent: 200,
+            size_ratio: 1,
+            min_merge_width: 2,
+            max_merge_width: None,
+        }),
+    ] {
+        let dir = tempdir().unwrap();
+        prepare(dir.path(), compaction_options.clone());
+        let options = options(compaction_options, 16 << 10);
+        let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+        storage.pause_background();
+        let memtable_id = storage.inner.state.read().imm_memtables[0].id();
+        assert_eq!(storage.inner.force_flush_imm_memtables().unwrap(), 1);
+
+        let state = storage.inner.state.read().clone();
+        let sst_ids = if state.l0_sstables.is_empty() {
+            assert_eq!(state.levels[0].0, memtable_id);
+            state.levels[0].1.clone()
+        } else {
+            state.l0_sstables.clone()
+        };
+        assert_eq!(sst_ids.len(), 4, "{sst_ids:?}");
+        assert_eq!(sst_ids[0], memtable_id);
+        // The SSTs are in key order and do not overlap.
+        for pair in sst_ids.windows(2) {
+            let (left, right) = (&state.sstables[&pair[0]], &state.sstables[&pair[1]]);
+            assert!(left.last_key() < right.first_key());
+        }
+        check_contents(&storage);
+        storage.close().unwrap();
+
+        let storage = MiniLsm::open(&dir, options).unwrap();
+        let state = storage.inner.state.read().clone();
+        assert_eq!(state.sstables.len(), 4);
+        assert!(state.imm_memtables.is_empty());
+        check_contents(&storage);
+        storage.close().unwrap();
+    }
+}
+
+#[test]
+fn test_flush_split_keeps_small_memtables_whole() {
+    let memtable = MemTable::create(0);
+    for i in 0..100 {
+        for ts in 1..4 {
+            memtable
+                .put(KeySlice::from_slice(&key_of(i), ts), b"value")
+                .unwrap();
+        }
+    }
+    let size = memtable.approximate_size();
+    let new_builder = || SsTableBuilder::new(4096);
+    assert_eq!(memtable.flush_split(size, new_builder).len(), 1);
+    assert_eq!(memtable.flush_split(size / 2 + 1, new_builder).len(), 1);
+    assert_eq!(memtable.flush_split(size / 2, new_builder).len(), 2);
+    // A key is never split between parts, even with a part per version.
+    assert_eq!(memtable.flush_split(1, new_builder).len(), 100);
+}
@@ -0,0 +1,141 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use tempfile::tempdir;
+
+use crate::{
+    compact::{CompactionOptions, SimpleLeveledCompactionOptions, TieredCompactionOptions},
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    manifest::{FormatOptions, Manifest, ManifestRecord},
+};
+
+fn simple() -> LsmStorageOptions {
+    LsmStorageOptions::default_for_week2_test(CompactionOptions::Simple(
+        SimpleLeveledCompactionOptions {
+            size_ratio_percent: 200,
+            level0_file_num_compaction_trigger: 2,
+            max_levels: 3,
+        },
+    ))
+}
+
+fn tiered() -> LsmStorageOptions {
+    LsmStorageOptions::default_for_week2_test(CompactionOptions::Tiered(TieredCompactionOptions {
+        num_tiers: 3,
+        max_size_amplification_percent: 200,
+        size_ratio: 1,
+        min_merge_width: 2,
+        max_merge_width: None,
+    }))
+}
+
+#[test]
+fn test_reopen_with_incompatible_options() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, simple()).unwrap();
+    storage.put(b"key", b"value").unwrap();
+    storage.force_flush().unwrap();
+    storage.close().unwrap();
+
+    let Err(Error::InvalidArgument(msg)) = MiniLsm::open(&dir, tiered()) else {
+        panic!("expected the reopen to be refused");
+    };
+    assert!(
+        msg.contains("compaction is `simple` but opened with `tiered`"),
+        "{msg}"
+    );
+
+    // Options that do not affect the format can change freely.
+    let mut options = simple();
+    options.block_size = 1024;
+    options.num_memtable_limit = 5;
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(&storage.get(b"key").unwrap().unwrap()[..], b"value");
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_reopen_legacy_manifest() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, simple()).unwrap();
+    storage.close().unwrap();
+
+    // Drop the options record, as in a manifest written before options were recorded.
+    let path = dir.path().join("MANIFEST");
+    let (_, records) = Manifest::recover(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let manifest = Manifest::create(&path).unwrap();
+    for record in records {
+        if !matches!(record, ManifestRecord::Options(_)) {
+            manifest.add_record_when_init(record).unwrap();
+        }
+    }
+    drop(manifest);
+
+    // Such a storage was written in the first format version, whose blocks cannot be read anymore.
+    let Err(Error::InvalidArgument(msg)) = MiniLsm::open(&dir, simple()) else {
+        panic!("expected the reopen to be refused");
+    };
+    assert!(msg.contains("format_version is `1`"), "{msg}");
+}
+
+#[test]
+fn test_reopen_newer_format_version() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, simple()).unwrap();
+    storage.put(b"key", b"value").unwrap();
+    storage.close().unwrap();
+
+    // Record a format version this build does not know, as a newer build would.
+    let path = dir.path().join("MANIFEST");
+    let (_, records) = Manifest::recover(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let manifest = Manifest::create(&path).unwrap();
+    for record in records {
+        let record = match record {
+            ManifestRecord::Options(options) => ManifestRecord::Options(FormatOptions {
+                format_version: FormatOptions::FORMAT_VERSION + 1,
+                ..options
+            }),
+            record => record,
+        };
+        manifest.add_record_when_init(record).unwrap();
+    }
+    drop(manifest);
+
+    let Err(Error::InvalidArgument(msg)) = MiniLsm::open(&dir, simple()) else {
+        panic!("expected the reopen to be refused");
+    };
+    let expected = format!(
+        "format_version is `{}` but this build reads up to `{}`",
+        FormatOptions::FORMAT_VERSION + 1,
+        FormatOptions::FORMAT_VERSION
+    );
+    assert!(msg.contains(&expected), "{msg}");
+}
+
+#[test]
+fn test_older_readable_format_version_is_compatible() {
+    let opened = simple().format_options();
+    let created = FormatOptions {
+        format_version: FormatOptions::MIN_READABLE_FORMAT_VERSION,
+        ..opened.clone()
+    };
+    assert_eq!(created.mismatch(&opened), None);
+    let created = FormatOptions {
+        format_version: FormatOptions::MIN_READABLE_FORMAT_VERSION - 1,
+        ..opened.clone()
+    };
+    assert!(created.mismatch(&opened).is_some());
+}
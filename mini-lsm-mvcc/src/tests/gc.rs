@@ -0,0 +1,122 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use tempfile::tempdir;
+
+use crate::{
+    compact::{CompactionOptions, LeveledCompactionOptions},
+    iterators::StorageIterator,
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    table::SsTableIterator,
+};
+
+fn num_entries_in_ssts(storage: &MiniLsm) -> usize {
+    let snapshot = storage.inner.state.read().clone();
+    let mut cnt = 0;
+    for sst in snapshot.sstables.values() {
+        let mut iter = SsTableIterator::create_and_seek_to_first(sst.clone()).unwrap();
+        while iter.is_valid() {
+            cnt += 1;
+            iter.next().unwrap();
+        }
+    }
+    cnt
+}
+
+#[test]
+fn test_gc() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(
+        &dir,
+        LsmStorageOptions::default_for_week2_test(CompactionOptions::Leveled(
+            LeveledCompactionOptions {
+                level0_file_num_compaction_trigger: 10,
+                max_levels: 3,
+                base_level_size_mb: 1,
+                level_size_multiplier: 2,
+            },
+        )),
+    )
+    .unwrap();
+    for i in 0..100 {
+        storage
+            .put(format!("key{:03}", i).as_bytes(), b"v1")
+            .unwrap();
+    }
+    storage.force_flush().unwrap();
+    let sst_id = storage.inner.state.read().l0_sstables[0];
+    storage.compact_file(sst_id).unwrap();
+    for i in 0..50 {
+        storage
+            .put(format!("key{:03}", i).as_bytes(), b"v2")
+            .unwrap();
+    }
+    storage.force_flush().unwrap();
+    for i in 50..60 {
+        storage.delete(format!("key{:03}", i).as_bytes()).unwrap();
+    }
+    assert_eq!(num_entries_in_ssts(&storage), 150);
+
+    // The versions below a snapshot cannot be purged while it is open
+    let latest_ts = storage.inner.mvcc().latest_commit_ts();
+    let txn = storage.new_txn().unwrap();
+    storage.put(b"key100", b"v1").unwrap();
+    assert!(matches!(
+        storage.gc(latest_ts + 1),
+        Err(Error::InvalidArgument(_))
+    ));
+    drop(txn);
+
+    // The overwritten versions and the deletes are purged from all levels and the memtables
+    storage.gc(latest_ts + 1).unwrap();
+    let snapshot = storage.inner.state.read().clone();
+    assert!(snapshot.memtable.is_empty() && snapshot.imm_memtables.is_empty());
+    assert!(snapshot.l0_sstables.is_empty());
+    assert!(snapshot.levels[..2].iter().all(|(_, ssts)| ssts.is_empty()));
+    assert_eq!(num_entries_in_ssts(&storage), 91);
+    for i in 0..101 {
+        let value = storage.get(format!("key{:03}", i).as_bytes()).unwrap();
+        match i {
+            0..50 => assert_eq!(value.as_deref(), Some(b"v2".as_slice())),
+            50..60 => assert_eq!(value, None),
+            _ => assert_eq!(value.as_deref(), Some(b"v1".as_slice())),
+        }
+    }
+    // Nothing is left to purge
+    storage.gc(latest_ts + 1).unwrap();
+    assert_eq!(num_entries_in_ssts(&storage), 91);
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_gc_without_compaction() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    for i in 0..100 {
+        storage
+            .put(format!("key{:03}", i).as_bytes(), b"value")
+            .unwrap();
+    }
+    storage.force_flush().unwrap();
+    for i in 0..100 {
+        storage.delete(format!("key{:03}", i).as_bytes()).unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.gc(storage.inner.mvcc().latest_commit_ts()).unwrap();
+    // All entries are dropped, leaving no SST
+    assert_eq!(num_entries_in_ssts(&storage), 0);
+    assert!(storage.inner.state.read().sstables.is_empty());
+    assert_eq!(storage.get(b"key000").unwrap(), None);
+}
@@ -0,0 +1,131 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+#[test]
+fn test_get_multi() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for i in 0..100 {
+        storage
+            .put(format!("key{i:03}").as_bytes(), b"sst")
+            .unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.put(b"key010", b"memtable").unwrap();
+    storage.delete(b"key020").unwrap();
+
+    let txn = storage.new_txn().unwrap();
+    txn.put(b"key030", b"local");
+    txn.delete(b"key040");
+    txn.put(b"key500", b"local");
+    // Not visible at the read ts of the transaction
+    storage.put(b"key050", b"later").unwrap();
+
+    let keys = [
+        "key050", "key500", "key040", "key030", "key020", "key010", "key000", "key999", "key010",
+    ];
+    let values = txn.get_multi(&keys).unwrap();
+    assert_eq!(values.len(), keys.len());
+    for (key, value) in keys.iter().zip(&values) {
+        assert_eq!(*value, txn.get(key.as_bytes()).unwrap(), "{key}");
+    }
+    assert_eq!(
+        values,
+        [
+            Some(Bytes::from("sst")),
+            Some(Bytes::from("local")),
+            None,
+            Some(Bytes::from("local")),
+            None,
+            Some(Bytes::from("memtable")),
+            Some(Bytes::from("sst")),
+            None,
+            Some(Bytes::from("memtable")),
+        ]
+    );
+    assert!(txn.get_multi::<&[u8]>(&[]).unwrap().is_empty());
+}
+
+#[test]
+fn test_get_multi_serializable() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.serializable = true;
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"key1", b"1").unwrap();
+    storage.put(b"key2", b"2").unwrap();
+    let txn1 = storage.new_txn().unwrap();
+    let txn2 = storage.new_txn().unwrap();
+    let values = txn1.get_multi(&[b"key2", b"key3"]).unwrap();
+    assert_eq!(values, [Some(Bytes::from("2")), None]);
+    txn1.put(b"key1", b"3");
+    txn2.put(b"key3", b"3");
+    txn2.commit().unwrap();
+    // txn1 read key3, which txn2 wrote since
+    assert!(txn1.commit().is_err());
+    assert_eq!(storage.get(b"key1").unwrap(), Some(Bytes::from("1")));
+}
+
+#[test]
+fn test_get_multi_across_ssts() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    // Every third key goes to the levels, every other one to L0 SSTs, and a few to the memtables
+    for i in (0..300).step_by(3) {
+        storage
+            .put(format!("key{i:03}").as_bytes(), b"level")
+            .unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    for i in (0..300).step_by(2) {
+        storage.put(format!("key{i:03}").as_bytes(), b"l0").unwrap();
+        if i % 50 == 0 {
+            storage.force_flush().unwrap();
+        }
+    }
+    storage.force_flush().unwrap();
+    storage.delete(b"key006").unwrap();
+    storage.put(b"key007", b"memtable").unwrap();
+
+    let keys = (0..320)
+        .rev()
+        .step_by(7)
+        .chain([6, 7, 9, 6])
+        .map(|i| format!("key{i:03}"))
+        .collect::<Vec<_>>();
+    let values = storage.new_txn().unwrap().get_multi(&keys).unwrap();
+    assert_eq!(values.len(), keys.len());
+    for (key, value) in keys.iter().zip(&values) {
+        assert_eq!(*value, storage.get(key.as_bytes()).unwrap(), "{key}");
+    }
+    assert_eq!(
+        values[values.len() - 4..],
+        [
+            None,
+            Some(Bytes::from("memtable")),
+            Some(Bytes::from("level")),
+            None
+        ]
+    );
+}
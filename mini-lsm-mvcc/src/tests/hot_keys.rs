@@ -0,0 +1,88 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::time::Duration;
+
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    prefix::PrefixExtractorOptions,
+};
+
+#[test]
+fn test_hot_keys() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.prefix_extractor = Some(PrefixExtractorOptions::Delimited(b':'));
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"untracked", b"1").unwrap();
+    assert!(storage.hot_keys(10).is_empty());
+
+    storage.enable_hot_key_tracking(1, Duration::from_secs(3600));
+    for i in 0..1000 {
+        storage
+            .put(format!("user:{:04}", i).as_bytes(), b"1")
+            .unwrap();
+    }
+    for _ in 0..100 {
+        storage.put(b"user:hot", b"1").unwrap();
+        storage.get(b"user:hot").unwrap();
+        storage.get(b"order:hot").unwrap();
+        storage.get(b"order:hot").unwrap();
+        storage.get(b"order:hot").unwrap();
+    }
+    let hot_keys = storage.hot_keys(2);
+    assert_eq!(hot_keys.len(), 2);
+    assert_eq!(hot_keys[0].key.as_ref(), b"order:hot");
+    assert!(hot_keys[0].reads >= 300 && hot_keys[0].writes < 50);
+    assert_eq!(hot_keys[1].key.as_ref(), b"user:hot");
+    assert!(hot_keys[1].reads >= 100 && hot_keys[1].writes >= 100);
+
+    let hot_prefixes = storage.hot_prefixes(5, 2);
+    assert_eq!(hot_prefixes[0].key.as_ref(), b"user:");
+    assert_eq!(hot_prefixes[1].key.as_ref(), b"order");
+
+    let hot_prefixes = storage.hot_extracted_prefixes(2);
+    assert_eq!(hot_prefixes[0].key.as_ref(), b"user:");
+    assert_eq!(hot_prefixes[1].key.as_ref(), b"order:");
+
+    storage.disable_hot_key_tracking();
+    assert!(storage.hot_keys(10).is_empty());
+}
+
+#[test]
+fn test_hot_keys_sliding_window() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.enable_hot_key_tracking(1, Duration::from_millis(100));
+    for _ in 0..100 {
+        storage.get(b"old").unwrap();
+    }
+    std::thread::sleep(Duration::from_millis(120));
+    for _ in 0..10 {
+        storage.get(b"new").unwrap();
+    }
+    // The previous window is only partially counted.
+    let hot_keys = storage.hot_keys(10);
+    assert_eq!(hot_keys.len(), 2);
+    assert!(
+        hot_keys
+            .iter()
+            .any(|hot_key| hot_key.key.as_ref() == b"old" && hot_key.reads < 100)
+    );
+    std::thread::sleep(Duration::from_millis(250));
+    assert!(storage.hot_keys(10).is_empty());
+}
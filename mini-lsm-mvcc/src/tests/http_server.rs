@@ -0,0 +1,118 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    http_server::{self, handle_request},
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+#[test]
+fn test_http_handler() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+
+    assert_eq!(
+        handle_request(&storage, "GET", "/kv/a%20b", b"").status,
+        404
+    );
+    assert_eq!(
+        handle_request(&storage, "PUT", "/kv/a%20b", b"1").status,
+        204
+    );
+    let response = handle_request(&storage, "GET", "/kv/a+b", b"");
+    assert_eq!((response.status, response.body), (200, b"1".to_vec()));
+    assert_eq!(
+        handle_request(&storage, "DELETE", "/kv/a%20b", b"").status,
+        204
+    );
+    assert_eq!(
+        handle_request(&storage, "GET", "/kv/a%20b", b"").status,
+        404
+    );
+    // An empty value is rejected by the engine.
+    assert_eq!(handle_request(&storage, "PUT", "/kv/a", b"").status, 400);
+    assert_eq!(handle_request(&storage, "PATCH", "/kv/a", b"").status, 405);
+    assert_eq!(handle_request(&storage, "GET", "/unknown", b"").status, 404);
+
+    let batch = br#"[{"op": "put", "key": "k1", "value": "v1"},
+        {"op": "put", "key": "k2", "value": "v2"},
+        {"op": "put", "key": "k3", "value": "v3"},
+        {"op": "delete", "key": "k2"}]"#;
+    assert_eq!(
+        handle_request(&storage, "POST", "/batch", batch).status,
+        204
+    );
+    assert_eq!(
+        handle_request(&storage, "POST", "/batch", b"[{\"op\": \"merge\"}]").status,
+        400
+    );
+    let response = handle_request(&storage, "GET", "/scan?begin=k1&end=k3", b"");
+    assert_eq!(response.status, 200);
+    assert_eq!(
+        String::from_utf8(response.body).unwrap(),
+        r#"[{"key":"k1","value":"v1"}]"#
+    );
+    let response = handle_request(&storage, "GET", "/scan?limit=1&begin=k2", b"");
+    assert_eq!(
+        String::from_utf8(response.body).unwrap(),
+        r#"[{"key":"k3","value":"v3"}]"#
+    );
+    assert_eq!(
+        handle_request(&storage, "GET", "/scan?limit=x", b"").status,
+        400
+    );
+}
+
+#[test]
+fn test_http_server() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    drop(listener);
+    {
+        let addr = addr.clone();
+        std::thread::spawn(move || http_server::serve(storage, &addr, 1));
+    }
+
+    let request = |raw: &str| {
+        let mut stream = (0..100)
+            .find_map(|_| {
+                TcpStream::connect(&addr)
+                    .inspect_err(|_| std::thread::sleep(std::time::Duration::from_millis(10)))
+                    .ok()
+            })
+            .unwrap();
+        stream.write_all(raw.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    };
+    let response = request(
+        "PUT /kv/hello HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: 5\r\n\r\nworld",
+    );
+    assert!(response.starts_with("HTTP/1.1 204"), "{}", response);
+    let response =
+        request("GET /kv/hello HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.ends_with("\r\n\r\nworld"), "{}", response);
+}
@@ -0,0 +1,78 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+#[test]
+fn test_increment() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(storage.increment(b"counter", 5).unwrap(), 5);
+    assert_eq!(storage.increment(b"counter", -8).unwrap(), -3);
+    storage.force_flush().unwrap();
+    assert_eq!(storage.increment(b"counter", 1).unwrap(), -2);
+    assert_eq!(
+        storage.get(b"counter").unwrap(),
+        Some(Bytes::copy_from_slice(&(-2i64).to_be_bytes()))
+    );
+
+    storage.put(b"text", b"hello").unwrap();
+    assert!(matches!(
+        storage.increment(b"text", 1),
+        Err(Error::InvalidArgument(_))
+    ));
+    storage.put(b"max", &i64::MAX.to_be_bytes()).unwrap();
+    assert!(matches!(
+        storage.increment(b"max", 1),
+        Err(Error::InvalidArgument(_))
+    ));
+    assert_eq!(storage.increment(b"max", -1).unwrap(), i64::MAX - 1);
+}
+
+#[test]
+fn test_concurrent_increment() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let handles = (0..8)
+        .map(|thread| {
+            let storage = storage.clone();
+            std::thread::spawn(move || {
+                for i in 0..200 {
+                    let key = format!("counter{}", (thread + i) % 3);
+                    storage.increment(key.as_bytes(), 1).unwrap();
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    // No increment is lost
+    let total: i64 = (0..3)
+        .map(|i| {
+            storage
+                .increment(format!("counter{i}").as_bytes(), 0)
+                .unwrap()
+        })
+        .sum();
+    assert_eq!(total, 8 * 200);
+}
@@ -0,0 +1,117 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::{CompactionOptions, TieredCompactionOptions},
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+fn entries(range: std::ops::Range<usize>, value: &str) -> Vec<anyhow::Result<(Vec<u8>, Vec<u8>)>> {
+    range
+        .map(|i| {
+            Ok((
+                format!("key_{:04}", i).into_bytes(),
+                value.as_bytes().to_vec(),
+            ))
+        })
+        .collect()
+}
+
+fn test_ingest_sorted(compaction_options: CompactionOptions) {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(compaction_options);
+    options.target_sst_size = 1024;
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    storage.put(b"key_0000", b"old").unwrap();
+    let txn = storage.new_txn().unwrap();
+    let summary = storage
+        .ingest_sorted(entries(0..500, "ingested").into_iter())
+        .unwrap();
+    assert_eq!(summary.num_entries, 500);
+    assert!(summary.sst_ids.len() > 1);
+    // Ingested entries are committed atomically.
+    assert_eq!(txn.get(b"key_0000").unwrap(), Some(Bytes::from("old")));
+    assert_eq!(txn.get(b"key_0001").unwrap(), None);
+    assert_eq!(
+        storage.get(b"key_0000").unwrap(),
+        Some(Bytes::from("ingested"))
+    );
+    storage
+        .ingest_sorted([Ok((b"key_0001", b""))].into_iter())
+        .unwrap();
+    assert_eq!(storage.get(b"key_0001").unwrap(), None);
+    storage.put(b"key_0002", b"new").unwrap();
+    drop(txn);
+    storage.close().unwrap();
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(
+        storage.get(b"key_0000").unwrap(),
+        Some(Bytes::from("ingested"))
+    );
+    assert_eq!(storage.get(b"key_0001").unwrap(), None);
+    assert_eq!(storage.get(b"key_0002").unwrap(), Some(Bytes::from("new")));
+    assert_eq!(
+        storage.get(b"key_0499").unwrap(),
+        Some(Bytes::from("ingested"))
+    );
+    storage.put(b"key_0003", b"newer").unwrap();
+    assert_eq!(
+        storage.get(b"key_0003").unwrap(),
+        Some(Bytes::from("newer"))
+    );
+}
+
+#[test]
+fn test_ingest_sorted_l0() {
+    test_ingest_sorted(CompactionOptions::NoCompaction);
+}
+
+#[test]
+fn test_ingest_sorted_tiered() {
+    test_ingest_sorted(CompactionOptions::Tiered(TieredCompactionOptions {
+        num_tiers: 3,
+        max_size_amplification_percent: 200,
+        size_ratio: 1,
+        min_merge_width: 2,
+        max_merge_width: None,
+    }));
+}
+
+#[test]
+fn test_ingest_unsorted() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let mut input = entries(0..10, "value");
+    input.swap(3, 4);
+    assert!(matches!(
+        storage.ingest_sorted(input.into_iter()),
+        Err(Error::InvalidArgument(_))
+    ));
+    assert_eq!(storage.get(b"key_0000").unwrap(), None);
+    // No SST is left behind.
+    assert!(std::fs::read_dir(&dir).unwrap().all(|entry| {
+        entry
+            .unwrap()
+            .path()
+            .extension()
+            .is_none_or(|ext| ext != "sst")
+    }));
+}
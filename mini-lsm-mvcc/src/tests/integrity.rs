@@ -0,0 +1,87 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::sync::Arc;
+
+use tempfile::tempdir;
+
+use crate::{
+    integrity::Violation,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+fn fill(storage: &MiniLsm) {
+    for round in 0..3 {
+        for i in 0..100 {
+            storage
+                .put(
+                    format!("key{i:03}").as_bytes(),
+                    format!("value{round}").as_bytes(),
+                )
+                .unwrap();
+        }
+        storage.force_flush().unwrap();
+    }
+}
+
+#[test]
+fn test_check_integrity_ok() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    fill(&storage);
+    storage.put(b"key000", b"unflushed").unwrap();
+    let report = storage.check_integrity().unwrap();
+    assert!(report.is_ok(), "{report}");
+    assert_eq!(report.num_sstables, 3);
+    assert_eq!(report.num_entries, 300);
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_check_integrity_violations() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    fill(&storage);
+    let l0_sstables = storage.inner.state.read().l0_sstables.clone();
+    storage.close().unwrap();
+    drop(storage);
+
+    let sst_path = dir.path().join(format!("{:05}.sst", l0_sstables[0]));
+    let mut data = std::fs::read(&sst_path).unwrap();
+    data[0] ^= 0xff;
+    std::fs::write(&sst_path, data).unwrap();
+
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    {
+        let mut state = storage.inner.state.write();
+        let mut snapshot = state.as_ref().clone();
+        snapshot.l0_sstables.swap(1, 2);
+        *state = Arc::new(snapshot);
+    }
+    std::fs::remove_file(dir.path().join(format!("{:05}.sst", l0_sstables[2]))).unwrap();
+
+    let report = storage.check_integrity().unwrap();
+    assert!(!report.is_ok());
+    let violations = &report.violations;
+    assert!(violations.contains(&Violation::MissingFile {
+        sst_id: l0_sstables[2]
+    }));
+    assert!(violations.contains(&Violation::L0OutOfOrder {
+        sst_id: l0_sstables[2],
+        next_sst_id: l0_sstables[1],
+    }));
+    assert!(violations.iter().any(|violation| matches!(
+        violation,
+        Violation::Unreadable { sst_id, .. } if *sst_id == l0_sstables[0]
+    )));
+}
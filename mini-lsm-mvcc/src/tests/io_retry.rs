@@ -0,0 +1,149 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::io::{self, ErrorKind, Write};
+use std::time::Duration;
+
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    io_retry::{IoRetry, IoRetryPolicy, IoRetryStats, IoTarget},
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+fn policy(max_retries: u32) -> IoRetryPolicy {
+    IoRetryPolicy {
+        max_retries,
+        initial_backoff: Duration::from_micros(10),
+        max_backoff: Duration::from_micros(100),
+    }
+}
+
+/// Fails with `kind` the first `failures` times.
+fn flaky(failures: u32, kind: ErrorKind) -> impl FnMut() -> io::Result<u32> {
+    let mut calls = 0;
+    move || {
+        calls += 1;
+        if calls <= failures {
+            Err(kind.into())
+        } else {
+            Ok(calls)
+        }
+    }
+}
+
+#[test]
+fn test_retry_transient_errors() {
+    let retry = IoRetry::new(Some(policy(5)));
+    assert_eq!(
+        retry
+            .run(IoTarget::SstRead, flaky(2, ErrorKind::WouldBlock))
+            .unwrap(),
+        3
+    );
+    assert_eq!(
+        retry
+            .run(IoTarget::WalWrite, flaky(1, ErrorKind::Interrupted))
+            .unwrap(),
+        2
+    );
+    // give up after the retries
+    let err = retry
+        .run(IoTarget::ManifestWrite, flaky(10, ErrorKind::TimedOut))
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    // other errors are returned right away
+    let err = retry
+        .run(IoTarget::SstRead, flaky(1, ErrorKind::NotFound))
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotFound);
+    assert_eq!(
+        retry.stats(),
+        IoRetryStats {
+            sst_read_retries: 2,
+            wal_write_retries: 1,
+            manifest_write_retries: 5,
+            exhausted: 1,
+        }
+    );
+}
+
+#[test]
+fn test_no_retry_without_policy() {
+    let retry = IoRetry::new(None);
+    let err = retry
+        .run(IoTarget::SstRead, flaky(1, ErrorKind::WouldBlock))
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+    assert_eq!(retry.stats(), IoRetryStats::default());
+}
+
+/// Accepts at most 3 bytes at a time, and fails every other write.
+#[derive(Default)]
+struct FlakyWriter {
+    data: Vec<u8>,
+    calls: usize,
+}
+
+impl Write for FlakyWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.calls += 1;
+        if self.calls % 2 == 1 {
+            return Err(ErrorKind::WouldBlock.into());
+        }
+        let n = buf.len().min(3);
+        self.data.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_write_all_does_not_duplicate() {
+    let retry = IoRetry::new(Some(policy(1)));
+    let mut writer = FlakyWriter::default();
+    retry
+        .write_all(IoTarget::WalWrite, &mut writer, b"hello, world")
+        .unwrap();
+    assert_eq!(writer.data, b"hello, world");
+    assert_eq!(retry.stats().wal_write_retries, 4);
+}
+
+#[test]
+fn test_engine_with_retry_policy() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.enable_wal = true;
+    options.io_retry_policy = Some(policy(3));
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    storage.put(b"key1", b"value1").unwrap();
+    storage.force_flush().unwrap();
+    storage.put(b"key2", b"value2").unwrap();
+    storage.sync().unwrap();
+    storage.close().unwrap();
+
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(
+        storage.get(b"key1").unwrap().as_deref(),
+        Some(&b"value1"[..])
+    );
+    assert_eq!(
+        storage.get(b"key2").unwrap().as_deref(),
+        Some(&b"value2"[..])
+    );
+    assert_eq!(storage.io_retry_stats(), IoRetryStats::default());
+}
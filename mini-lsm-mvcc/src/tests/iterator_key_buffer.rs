@@ -0,0 +1,75 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::sync::Arc;
+
+use tempfile::tempdir;
+
+use crate::{
+    iterators::{StorageIterator, concat_iterator::SstConcatIterator},
+    key::{KeySlice, TS_DEFAULT},
+    table::{SsTable, SsTableBuilder, SsTableIterator},
+};
+
+fn build_sst(dir: &std::path::Path, id: usize, range: std::ops::Range<usize>) -> Arc<SsTable> {
+    let mut builder = SsTableBuilder::new(128);
+    for i in range {
+        builder.add(
+            KeySlice::for_testing_from_slice_with_ts(format!("key_{i:05}").as_bytes(), TS_DEFAULT),
+            b"value",
+        );
+    }
+    Arc::new(
+        builder
+            .build(id, None, dir.join(format!("{id}.sst")))
+            .unwrap(),
+    )
+}
+
+#[test]
+fn test_key_buffer_reused_across_blocks_and_ssts() {
+    let dir = tempdir().unwrap();
+    let ssts = vec![
+        build_sst(dir.path(), 1, 0..100),
+        build_sst(dir.path(), 2, 100..200),
+    ];
+    assert!(ssts[0].num_of_blocks() > 1);
+
+    let mut iter = SsTableIterator::create_and_seek_to_first(ssts[0].clone()).unwrap();
+    let buffer = iter.key().key_ref().as_ptr();
+    let mut count = 0;
+    while iter.is_valid() {
+        assert_eq!(iter.key().key_ref().as_ptr(), buffer);
+        count += 1;
+        iter.next().unwrap();
+    }
+    assert_eq!(count, 100);
+    iter.seek_to_key(KeySlice::for_testing_from_slice_with_ts(
+        b"key_00050",
+        TS_DEFAULT,
+    ))
+    .unwrap();
+    assert_eq!(iter.key().key_ref(), b"key_00050");
+    assert_eq!(iter.key().key_ref().as_ptr(), buffer);
+
+    let mut iter = SstConcatIterator::create_and_seek_to_first(ssts).unwrap();
+    let buffer = iter.key().key_ref().as_ptr();
+    let mut count = 0;
+    while iter.is_valid() {
+        assert_eq!(iter.key().key_ref(), format!("key_{count:05}").as_bytes());
+        assert_eq!(iter.key().key_ref().as_ptr(), buffer);
+        count += 1;
+        iter.next().unwrap();
+    }
+    assert_eq!(count, 200);
+}
@@ -0,0 +1,127 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::ops::Bound;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tempfile::tempdir;
+
+use crate::iterators::{SeekableIterator, StorageIterator};
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+
+fn key_of(i: usize) -> Vec<u8> {
+    format!("key{i:04}").into_bytes()
+}
+
+fn collect(
+    iter: &mut impl for<'a> StorageIterator<KeyType<'a> = &'a [u8]>,
+    n: usize,
+) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut entries = Vec::new();
+    while entries.len() < n && iter.is_valid() {
+        entries.push((iter.key().to_vec(), iter.value().to_vec()));
+        iter.next().unwrap();
+    }
+    entries
+}
+
+/// Spread versions of the keys over a lower level, L0 SSTs, and the memtable.
+fn open_with_layers(path: &std::path::Path) -> std::sync::Arc<MiniLsm> {
+    let options = LsmStorageOptions::builder()
+        .block_size(128)
+        .build()
+        .unwrap();
+    let storage = MiniLsm::open(path, options).unwrap();
+    for i in (0..1000).step_by(2) {
+        storage.put(&key_of(i), b"level").unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    for i in (0..1000).step_by(3) {
+        storage.put(&key_of(i), b"l0").unwrap();
+    }
+    storage.force_flush().unwrap();
+    for i in (0..1000).step_by(7) {
+        storage.delete(&key_of(i)).unwrap();
+    }
+    for i in (0..1000).step_by(11) {
+        storage.put(&key_of(i), b"memtable").unwrap();
+    }
+    storage
+}
+
+#[test]
+fn test_seek_matches_new_scan() {
+    let dir = tempdir().unwrap();
+    let storage = open_with_layers(dir.path());
+    let upper = key_of(900);
+    let mut iter = storage
+        .scan(Bound::Included(&key_of(100)), Bound::Excluded(&upper))
+        .unwrap();
+    let mut rng = StdRng::seed_from_u64(411);
+    let mut target = 0;
+    while target < 1000 && iter.is_valid() {
+        let key = key_of(target);
+        // Seeking never moves backwards from the current entry
+        let lower = key.clone().max(iter.key().to_vec());
+        iter.seek(&key).unwrap();
+        let mut expected_iter = storage
+            .scan(Bound::Included(&lower), Bound::Excluded(&upper))
+            .unwrap();
+        let expected = collect(&mut expected_iter, 5);
+        // Seeking to a key the iterator already passed does not move it
+        let current = iter.is_valid().then(|| iter.key().to_vec());
+        if let Some(current) = current {
+            iter.seek(&key_of(0)).unwrap();
+            assert_eq!(iter.key(), current);
+        }
+        assert_eq!(collect(&mut iter, 5), expected, "seek to {target}");
+        target += rng.gen_range(1..40);
+    }
+    assert!(!iter.is_valid());
+
+    // A scan whose first entry is already past the upper bound is empty
+    let iter = storage
+        .scan(Bound::Included(&key_of(913)), Bound::Excluded(&upper))
+        .unwrap();
+    assert!(!iter.is_valid());
+}
+
+#[test]
+fn test_seek_txn_iterator_with_local_writes() {
+    let dir = tempdir().unwrap();
+    let storage = open_with_layers(dir.path());
+    let txn = storage.new_txn().unwrap();
+    for i in (0..1000).step_by(5) {
+        txn.put(&key_of(i), b"txn");
+    }
+    for i in (0..1000).step_by(13) {
+        txn.delete(&key_of(i));
+    }
+    let mut iter = txn.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    let mut rng = StdRng::seed_from_u64(411);
+    let mut target = 0;
+    while target < 1000 && iter.is_valid() {
+        let key = key_of(target);
+        let lower = key.clone().max(iter.key().to_vec());
+        iter.seek(&key).unwrap();
+        let mut expected_iter = txn.scan(Bound::Included(&lower), Bound::Unbounded).unwrap();
+        assert_eq!(
+            collect(&mut iter, 5),
+            collect(&mut expected_iter, 5),
+            "seek to {target}"
+        );
+        target += rng.gen_range(1..40);
+    }
+}
@@ -0,0 +1,96 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    history::{KeyVersion, VersionOp},
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+fn version(value: &'static str, ts: u64) -> KeyVersion {
+    KeyVersion {
+        value: value.into(),
+        ts,
+        op: if value.is_empty() {
+            VersionOp::Delete
+        } else {
+            VersionOp::Put
+        },
+    }
+}
+
+#[test]
+fn test_get_versions() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let latest_ts = || storage.inner.mvcc().latest_commit_ts();
+    storage.put(b"key", b"1").unwrap();
+    let ts1 = latest_ts();
+    storage.put(b"key1", b"other").unwrap();
+    // Keep the first version from being compacted away
+    let txn = storage.new_txn().unwrap();
+    storage.force_flush().unwrap();
+    storage.put(b"key", b"2").unwrap();
+    let ts2 = latest_ts();
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    storage.delete(b"key").unwrap();
+    let ts3 = latest_ts();
+    storage.force_flush().unwrap();
+    storage.put(b"key", b"3").unwrap();
+    let ts4 = latest_ts();
+
+    // The versions are gathered from the memtable, L0 and L1, and do not run into the next key
+    assert_eq!(
+        storage.get_versions(b"key", 10).unwrap(),
+        vec![
+            version("3", ts4),
+            version("", ts3),
+            version("2", ts2),
+            version("1", ts1),
+        ]
+    );
+    assert_eq!(
+        storage.get_versions(b"key", 2).unwrap(),
+        vec![version("3", ts4), version("", ts3)]
+    );
+    assert_eq!(
+        storage.get_versions(b"key1", 10).unwrap(),
+        vec![version("other", ts1 + 1)]
+    );
+    assert!(storage.get_versions(b"key0", 10).unwrap().is_empty());
+    assert!(storage.get_versions(b"key", 0).unwrap().is_empty());
+
+    // Only the versions committed at or before the read timestamp are visible
+    assert_eq!(
+        storage.inner.get_versions(b"key", ts3, 10).unwrap(),
+        vec![version("", ts3), version("2", ts2), version("1", ts1)]
+    );
+    assert_eq!(
+        storage.inner.get_versions(b"key", ts2 - 1, 10).unwrap(),
+        vec![version("1", ts1)]
+    );
+
+    // Without the snapshot, the versions below the watermark are compacted away
+    drop(txn);
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    assert_eq!(
+        storage.get_versions(b"key", 10).unwrap(),
+        vec![version("3", ts4)]
+    );
+}
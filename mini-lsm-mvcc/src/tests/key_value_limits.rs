@@ -0,0 +1,89 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MAX_KEY_VALUE_SIZE, MiniLsm, WriteBatchRecord},
+};
+
+#[test]
+fn test_key_value_size_limits() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.max_key_size = 8;
+    options.max_value_size = 16;
+    let storage = MiniLsm::open(&dir, options).unwrap();
+
+    storage.put(b"12345678", &[b'v'; 16]).unwrap();
+    let err = storage.put(b"123456789", b"v").unwrap_err();
+    assert!(matches!(err, Error::KeyTooLarge { size: 9, limit: 8 }));
+    let err = storage.delete(b"123456789").unwrap_err();
+    assert!(matches!(err, Error::KeyTooLarge { .. }));
+
+    // A rejected batch must not be partially applied.
+    let value = [b'v'; 17];
+    let err = storage
+        .write_batch(&[
+            WriteBatchRecord::Put(&b"a"[..], &b"1"[..]),
+            WriteBatchRecord::Put(&b"b"[..], &value[..]),
+        ])
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        Error::ValueTooLarge {
+            size: 17,
+            limit: 16
+        }
+    ));
+    assert_eq!(storage.get(b"a").unwrap(), None);
+    assert_eq!(
+        storage.get(b"12345678").unwrap(),
+        Some(Bytes::copy_from_slice(&[b'v'; 16]))
+    );
+}
+
+#[test]
+fn test_key_value_size_limits_at_format_limit() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let key = vec![b'k'; MAX_KEY_VALUE_SIZE];
+    let value = vec![b'v'; MAX_KEY_VALUE_SIZE];
+    storage.put(&key, &value).unwrap();
+    storage.put(b"small", b"value").unwrap();
+    storage.force_flush().unwrap();
+    assert_eq!(
+        storage.get(&key).unwrap(),
+        Some(Bytes::copy_from_slice(&value))
+    );
+    assert_eq!(storage.get(b"small").unwrap(), Some(Bytes::from("value")));
+    assert!(matches!(
+        storage
+            .put(&vec![b'k'; MAX_KEY_VALUE_SIZE + 1], b"v")
+            .unwrap_err(),
+        Error::KeyTooLarge { .. }
+    ));
+
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.max_value_size = MAX_KEY_VALUE_SIZE + 1;
+    assert!(matches!(
+        MiniLsm::open(&dir, options),
+        Err(Error::InvalidArgument(_))
+    ));
+}
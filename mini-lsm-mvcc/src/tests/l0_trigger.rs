@@ -0,0 +1,66 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use tempfile::tempdir;
+
+use crate::{
+    compact::{CompactionOptions, SimpleLeveledCompactionOptions},
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+fn simple_options() -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::Simple(
+        SimpleLeveledCompactionOptions {
+            size_ratio_percent: 200,
+            level0_file_num_compaction_trigger: 2,
+            max_levels: 2,
+        },
+    ));
+    options.num_memtable_limit = 1;
+    options
+}
+
+#[test]
+fn test_level0_trigger_overrides_compaction_options() {
+    let dir = tempdir().unwrap();
+    let mut options = simple_options();
+    options.level0_file_num_compaction_trigger = Some(4);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    match &storage.inner.options.compaction_options {
+        CompactionOptions::Simple(options) => {
+            assert_eq!(options.level0_file_num_compaction_trigger, 4)
+        }
+        _ => unreachable!(),
+    }
+
+    // Keep the background compaction from racing with the plan checks below.
+    storage.pause_background();
+    // Every flush lands in L0, but three SSTs are below the storage-level trigger.
+    for i in 0..3 {
+        storage.put(format!("key{i}").as_bytes(), b"value").unwrap();
+        storage.force_flush().unwrap();
+    }
+    assert!(storage.compaction_plan().task.is_none());
+    storage.put(b"key3", b"value").unwrap();
+    storage.force_flush().unwrap();
+    assert!(storage.compaction_plan().task.is_some());
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_level0_trigger_rejects_zero() {
+    let dir = tempdir().unwrap();
+    let mut options = simple_options();
+    options.level0_file_num_compaction_trigger = Some(0);
+    assert!(MiniLsm::open(&dir, options).is_err());
+}
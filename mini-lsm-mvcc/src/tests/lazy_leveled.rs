@@ -0,0 +1,64 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use tempfile::tempdir;
+
+use crate::{
+    compact::{CompactionOptions, LazyLeveledCompactionOptions},
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+use super::harness::compaction_bench;
+
+#[test]
+fn test_lazy_leveled_integration() {
+    let dir = tempdir().unwrap();
+    let compaction_options = LazyLeveledCompactionOptions {
+        size_ratio: 3,
+        level0_file_num_compaction_trigger: 2,
+        max_levels: 2,
+    };
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::LazyLeveled(
+        compaction_options.clone(),
+    ));
+    options.enable_wal = true;
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    compaction_bench(storage.clone());
+
+    let snapshot = storage.inner.state.read().clone();
+    assert!(snapshot.l0_sstables.len() < compaction_options.level0_file_num_compaction_trigger);
+    for level in 1..compaction_options.max_levels {
+        let num_runs = snapshot
+            .levels
+            .iter()
+            .filter(|(run_level, _)| *run_level == level)
+            .count();
+        assert!(num_runs < compaction_options.size_ratio);
+    }
+    // The runs are ordered from the top level to the last level, which has at most one run.
+    assert!(snapshot.levels.is_sorted_by_key(|(level, _)| *level));
+    assert!(
+        snapshot
+            .levels
+            .iter()
+            .filter(|(level, _)| *level == compaction_options.max_levels)
+            .count()
+            <= 1
+    );
+    assert!(snapshot.levels.iter().all(|(_, ssts)| !ssts.is_empty()));
+    storage.close().unwrap();
+
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(storage.inner.state.read().levels, snapshot.levels);
+    assert_eq!(storage.inner.state.read().l0_sstables, snapshot.l0_sstables);
+}
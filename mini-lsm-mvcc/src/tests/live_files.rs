@@ -0,0 +1,79 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::time::SystemTime;
+
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+#[test]
+fn test_live_files() {
+    let dir = tempdir().unwrap();
+    let start = SystemTime::now();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert!(storage.live_files().is_empty());
+
+    for round in 0..3 {
+        for i in 0..100 {
+            storage
+                .put(format!("key{:03}", i + round * 50).as_bytes(), b"value")
+                .unwrap();
+        }
+        storage
+            .delete(format!("key{:03}", round).as_bytes())
+            .unwrap();
+        storage.force_flush().unwrap();
+    }
+
+    let files = storage.live_files();
+    let l0_sstables = storage.inner.state.read().l0_sstables.clone();
+    assert_eq!(
+        files.iter().map(|file| file.id).collect::<Vec<_>>(),
+        l0_sstables
+    );
+    for (round, file) in files.iter().rev().enumerate() {
+        assert_eq!(file.level, 0);
+        assert_eq!(file.size, std::fs::metadata(&file.path).unwrap().len());
+        assert_eq!(file.num_entries, 101);
+        assert_eq!(file.num_deletes, 1);
+        assert!(file.min_ts <= file.max_ts);
+        // the delete of each round is below its puts
+        assert_eq!(file.first_key, format!("key{round:03}").as_bytes());
+        assert_eq!(
+            file.last_key,
+            format!("key{:03}", round * 50 + 99).as_bytes()
+        );
+        assert!(file.creation_time >= start - std::time::Duration::from_secs(1));
+        assert!(file.creation_time <= SystemTime::now());
+    }
+
+    storage.force_full_compaction().unwrap();
+    let files = storage.live_files();
+    assert!(!files.is_empty());
+    assert!(
+        files
+            .iter()
+            .all(|file| file.level == 1 && file.path.exists())
+    );
+    assert!(files.windows(2).all(|x| x[0].last_key < x[1].first_key));
+    assert_eq!(
+        files.iter().map(|file| file.num_entries).sum::<u64>(),
+        200 - 3
+    );
+    storage.close().unwrap();
+}
@@ -0,0 +1,137 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    manifest::{Manifest, ManifestRecord},
+    replication::{Replica, ReplicationSource},
+};
+
+fn options() -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.enable_wal = true;
+    options.manifest_rotation_size = Some(1);
+    options
+}
+
+fn key_of(i: usize) -> Vec<u8> {
+    format!("key_{:05}", i).into_bytes()
+}
+
+#[test]
+fn test_group_commit() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("MANIFEST");
+    let manifest = Arc::new(Manifest::create(&path).unwrap());
+    let handles = (0..8)
+        .map(|thread| {
+            let manifest = manifest.clone();
+            std::thread::spawn(move || {
+                for i in 0..50 {
+                    manifest
+                        .add_record_when_init(ManifestRecord::AppliedIndex(thread * 100 + i))
+                        .unwrap();
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    drop(manifest);
+    let (_, records) = Manifest::recover(&path).unwrap();
+    let mut indexes = records
+        .into_iter()
+        .map(|record| match record {
+            ManifestRecord::AppliedIndex(index) => index,
+            _ => unreachable!(),
+        })
+        .collect::<Vec<_>>();
+    indexes.sort();
+    let expected = (0..8)
+        .flat_map(|thread| (0..50).map(move |i| thread * 100 + i))
+        .collect::<Vec<_>>();
+    assert_eq!(indexes, expected);
+}
+
+#[test]
+fn test_manifest_rotation() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    storage.pause_background();
+    for round in 0..10 {
+        for i in 0..10 {
+            storage
+                .put(&key_of(i), format!("v{round}").as_bytes())
+                .unwrap();
+        }
+        storage.force_flush().unwrap();
+    }
+    storage.put(&key_of(0), b"unflushed").unwrap();
+    let l0_sstables = storage.inner.state.read().l0_sstables.clone();
+    let manifest = storage.inner.manifest();
+    let size = manifest.size().unwrap();
+    storage.inner.maybe_rotate_manifest().unwrap();
+    assert_eq!(manifest.rotations(), 1);
+    assert!(manifest.size().unwrap() < size);
+    let (_, records) = Manifest::recover(storage.inner.path_of_manifest()).unwrap();
+    assert_eq!(records.len(), 2);
+    assert!(matches!(records[0], ManifestRecord::Options(_)));
+    assert!(matches!(records[1], ManifestRecord::Snapshot { .. }));
+
+    storage.force_flush().unwrap();
+    storage.close().unwrap();
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    let state = storage.inner.state.read().clone();
+    assert_eq!(state.l0_sstables[1..], l0_sstables[..]);
+    assert!(state.memtable.id() > state.l0_sstables[0]);
+    assert_eq!(
+        storage.get(&key_of(0)).unwrap(),
+        Some(Bytes::from("unflushed"))
+    );
+    assert_eq!(storage.get(&key_of(1)).unwrap(), Some(Bytes::from("v9")));
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_replication_across_rotation() {
+    let leader_dir = tempdir().unwrap();
+    let leader = MiniLsm::open(&leader_dir, options()).unwrap();
+    leader.pause_background();
+    leader.put(&key_of(0), b"v1").unwrap();
+    leader.force_flush().unwrap();
+
+    let replica_dir = tempdir().unwrap();
+    let replica = Replica::create(&replica_dir, options()).unwrap();
+    let mut source = leader.replication_session();
+    replica.catch_up(&mut source).unwrap();
+    assert_eq!(replica.get(&key_of(0)).unwrap(), Some(Bytes::from("v1")));
+
+    leader.inner.maybe_rotate_manifest().unwrap();
+    leader.put(&key_of(1), b"v1").unwrap();
+    leader.force_flush().unwrap();
+    leader.put(&key_of(0), b"v2").unwrap();
+    leader.sync().unwrap();
+    replica.apply(source.poll().unwrap()).unwrap();
+    assert_eq!(replica.get(&key_of(0)).unwrap(), Some(Bytes::from("v2")));
+    assert_eq!(replica.get(&key_of(1)).unwrap(), Some(Bytes::from("v1")));
+    leader.close().unwrap();
+}
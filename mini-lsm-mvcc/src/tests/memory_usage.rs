@@ -0,0 +1,74 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::ops::Bound;
+
+use tempfile::tempdir;
+
+use crate::iterators::StorageIterator;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+
+#[test]
+fn test_memory_usage() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::builder()
+        .block_size(256)
+        .build()
+        .unwrap();
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(storage.memory_usage().total(), 0);
+    for i in 0..1000 {
+        storage
+            .put(format!("key{i:04}").as_bytes(), &[b'v'; 100])
+            .unwrap();
+    }
+    let usage = storage.memory_usage();
+    assert_eq!(
+        usage.active_memtable,
+        storage.memtable_stats()[0].approximate_size
+    );
+    assert!(usage.active_memtable >= 1000 * 107);
+    assert_eq!(usage.total(), usage.active_memtable);
+
+    storage
+        .inner
+        .force_freeze_memtable(&storage.inner.state_lock.lock())
+        .unwrap();
+    let frozen = storage.memory_usage();
+    assert_eq!(frozen.active_memtable, 0);
+    assert_eq!(frozen.imm_memtables, usage.active_memtable);
+
+    storage.force_flush().unwrap();
+    let flushed = storage.memory_usage();
+    assert_eq!(flushed.imm_memtables, 0);
+    assert_eq!(flushed.block_cache, 0);
+    let sst_id = storage.inner.state.read().l0_sstables[0];
+    let num_blocks = storage.inner.state.read().sstables[&sst_id].num_of_blocks();
+    // At least the first and last key of each block are kept in memory.
+    assert!(flushed.pinned_index_and_filters > num_blocks * 14);
+
+    // An open iterator holds its current block, which is also cached.
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    let reading = storage.memory_usage();
+    assert!(reading.iterator_pinned_blocks > 0);
+    assert!(reading.iterator_pinned_blocks <= 256 * 2);
+    assert_eq!(reading.block_cache, reading.iterator_pinned_blocks);
+    while iter.is_valid() {
+        iter.next().unwrap();
+    }
+    drop(iter);
+    let read = storage.memory_usage();
+    assert_eq!(read.iterator_pinned_blocks, 0);
+    assert!(read.block_cache >= 1000 * 100);
+    storage.close().unwrap();
+}
@@ -0,0 +1,74 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::{key::KeySlice, mem_table::MemTable};
+
+#[test]
+fn test_entries_share_arena_chunks() {
+    let memtable = MemTable::create(0);
+    memtable
+        .put(KeySlice::from_slice(b"key1", 1), b"value1")
+        .unwrap();
+    memtable
+        .put(KeySlice::from_slice(b"key2", 1), b"value2")
+        .unwrap();
+    let value1 = memtable.get(KeySlice::from_slice(b"key1", 1)).unwrap();
+    let value2 = memtable.get(KeySlice::from_slice(b"key2", 1)).unwrap();
+    // The second key and value are bump-allocated right after the first value.
+    assert_eq!(
+        value2.as_ptr() as usize,
+        value1.as_ptr() as usize + value1.len() + b"key2".len()
+    );
+
+    // Large values get their own allocation, and small ones keep filling the chunk.
+    let large = vec![b'x'; 1 << 19];
+    memtable
+        .put(KeySlice::from_slice(b"key3", 1), &large)
+        .unwrap();
+    memtable
+        .put(KeySlice::from_slice(b"key4", 1), b"value4")
+        .unwrap();
+    let value4 = memtable.get(KeySlice::from_slice(b"key4", 1)).unwrap();
+    assert_eq!(
+        value4.as_ptr() as usize,
+        value2.as_ptr() as usize + value2.len() + b"key3".len() + b"key4".len()
+    );
+    assert_eq!(
+        &memtable.get(KeySlice::from_slice(b"key3", 1)).unwrap()[..],
+        &large[..]
+    );
+}
+
+#[test]
+fn test_entries_span_arena_chunks() {
+    let memtable = MemTable::create(0);
+    let value = vec![b'v'; 1000];
+    for i in 0..5000 {
+        memtable
+            .put(
+                KeySlice::from_slice(format!("key{i:05}").as_bytes(), 1),
+                &value,
+            )
+            .unwrap();
+    }
+    for i in 0..5000 {
+        let got = memtable
+            .get(KeySlice::from_slice(format!("key{i:05}").as_bytes(), 1))
+            .unwrap();
+        assert_eq!(&got[..], &value[..]);
+    }
+    // Values outlive the memtable, keeping their chunk alive.
+    let got = memtable.get(KeySlice::from_slice(b"key00000", 1)).unwrap();
+    drop(memtable);
+    assert_eq!(&got[..], &value[..]);
+}
@@ -0,0 +1,57 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+#[test]
+fn test_memtable_stats() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.target_sst_size = 1024;
+    options.num_memtable_limit = 1000;
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.pause_background();
+    let stats = storage.memtable_stats();
+    assert_eq!(stats.len(), 1);
+    assert!(stats[0].is_empty && !stats[0].immutable);
+    assert_eq!(stats[0].approximate_size, 0);
+
+    for i in 0..100 {
+        storage
+            .put(format!("key{i:03}").as_bytes(), &[b'x'; 32])
+            .unwrap();
+    }
+    let stats = storage.memtable_stats();
+    // Each memtable is frozen once it reaches the target SST size
+    assert!(stats.len() > 2);
+    assert!(!stats[0].immutable);
+    for memtable in &stats[1..] {
+        assert!(memtable.immutable && !memtable.is_empty);
+        assert!(memtable.approximate_size >= 1024);
+    }
+    // Newer memtables have larger ids
+    assert!(stats.windows(2).all(|x| x[0].id > x[1].id));
+    storage.dump_structure();
+
+    let oldest = stats.last().unwrap().id;
+    storage.inner.force_flush_next_imm_memtable().unwrap();
+    let after_flush = storage.memtable_stats();
+    assert_eq!(after_flush.len(), stats.len() - 1);
+    assert!(after_flush.iter().all(|x| x.id != oldest));
+    assert_eq!(storage.inner.state.read().l0_sstables, vec![oldest]);
+}
@@ -0,0 +1,74 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::ops::Bound;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    iterators::StorageIterator,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+#[test]
+fn test_next_batch() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    for i in 0..10 {
+        storage
+            .put(
+                format!("key_{i:02}").as_bytes(),
+                format!("value_{i}").as_bytes(),
+            )
+            .unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.put(b"key_03", b"value_new").unwrap();
+    storage.delete(b"key_05").unwrap();
+    let read_ts = storage.inner.mvcc().latest_commit_ts();
+
+    let mut iter = storage
+        .inner
+        .scan_with_ts(Bound::Unbounded, Bound::Unbounded, read_ts)
+        .unwrap();
+    let mut batch = Vec::new();
+    assert_eq!(iter.next_batch(&mut batch, 4).unwrap(), 4);
+    assert_eq!(iter.next_batch(&mut batch, 4).unwrap(), 4);
+    assert_eq!(iter.next_batch(&mut batch, 4).unwrap(), 1);
+    assert!(!iter.is_valid());
+    assert_eq!(iter.next_batch(&mut batch, 4).unwrap(), 0);
+
+    assert!(batch.iter().all(|(key, _)| key.key_ref() != b"key_05"));
+    let (key, value) = &batch[3];
+    assert_eq!(key.key_ref(), b"key_03");
+    assert_eq!(key.ts(), read_ts - 1);
+    assert_eq!(value, &Bytes::from_static(b"value_new"));
+
+    let mut iter = storage
+        .inner
+        .scan_with_ts(Bound::Unbounded, Bound::Unbounded, read_ts)
+        .unwrap();
+    let mut expected = Vec::new();
+    while iter.is_valid() {
+        expected.push((iter.key().to_vec(), Bytes::copy_from_slice(iter.value())));
+        iter.next().unwrap();
+    }
+    assert_eq!(
+        batch
+            .iter()
+            .map(|(key, value)| (key.key_ref().to_vec(), value.clone()))
+            .collect::<Vec<_>>(),
+        expected
+    );
+}
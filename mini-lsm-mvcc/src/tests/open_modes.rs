@@ -0,0 +1,104 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::ops::Bound;
+
+use crate::iterators::StorageIterator;
+
+use tempfile::tempdir;
+
+use crate::{
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+fn options() -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week1_test();
+    options.enable_wal = true;
+    options
+}
+
+#[test]
+fn test_create_if_missing_and_error_if_exists() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("db");
+    let mut opts = options();
+    opts.create_if_missing = false;
+    assert!(matches!(
+        MiniLsm::open(&path, opts.clone()),
+        Err(Error::InvalidArgument(_))
+    ));
+    assert!(!path.exists());
+
+    let mut create = options();
+    create.error_if_exists = true;
+    let storage = MiniLsm::open(&path, create.clone()).unwrap();
+    storage.close().unwrap();
+    drop(storage);
+
+    assert!(matches!(
+        MiniLsm::open(&path, create),
+        Err(Error::InvalidArgument(_))
+    ));
+    let storage = MiniLsm::open(&path, opts).unwrap();
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_read_only() {
+    let dir = tempdir().unwrap();
+    let mut opts = options();
+    opts.read_only = true;
+    assert!(MiniLsm::open(&dir, opts.clone()).is_err());
+
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    storage.put(b"flushed", b"1").unwrap();
+    storage.force_flush().unwrap();
+    storage.put(b"in_wal", b"2").unwrap();
+    storage.close().unwrap();
+    drop(storage);
+
+    let files = |dir: &std::path::Path| {
+        let mut files = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                (entry.file_name(), entry.metadata().unwrap().len())
+            })
+            .collect::<Vec<_>>();
+        files.sort();
+        files
+    };
+    let before = files(dir.path());
+
+    let storage = MiniLsm::open(&dir, opts).unwrap();
+    assert_eq!(&storage.get(b"flushed").unwrap().unwrap()[..], b"1");
+    assert_eq!(&storage.get(b"in_wal").unwrap().unwrap()[..], b"2");
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    let mut count = 0;
+    while iter.is_valid() {
+        count += 1;
+        iter.next().unwrap();
+    }
+    assert_eq!(count, 2);
+    assert!(matches!(
+        storage.put(b"key", b"value"),
+        Err(Error::ReadOnly)
+    ));
+    assert!(matches!(storage.delete(b"flushed"), Err(Error::ReadOnly)));
+    assert!(matches!(storage.force_flush(), Err(Error::ReadOnly)));
+    storage.close().unwrap();
+    drop(storage);
+
+    assert_eq!(files(dir.path()), before);
+}
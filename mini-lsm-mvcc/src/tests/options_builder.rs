@@ -0,0 +1,126 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::time::Duration;
+
+use tempfile::tempdir;
+
+use crate::{
+    compact::{CompactionOptions, LeveledCompactionOptions, TieredCompactionOptions},
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+fn leveled() -> CompactionOptions {
+    CompactionOptions::Leveled(LeveledCompactionOptions {
+        level_size_multiplier: 2,
+        level0_file_num_compaction_trigger: 2,
+        max_levels: 4,
+        base_level_size_mb: 128,
+    })
+}
+
+#[test]
+fn test_builder() {
+    let options = LsmStorageOptions::builder()
+        .block_size(1024)
+        .target_sst_size(1 << 20)
+        .compaction_options(leveled())
+        .enable_wal(true)
+        .periodic_compaction_ttl(Duration::from_secs(3600))
+        .build()
+        .unwrap();
+    assert_eq!(options.block_size, 1024);
+    assert_eq!(options.target_sst_size, 1 << 20);
+    assert!(options.enable_wal);
+    assert_eq!(
+        options.periodic_compaction_ttl,
+        Some(Duration::from_secs(3600))
+    );
+    assert!(matches!(
+        options.compaction_options,
+        CompactionOptions::Leveled(_)
+    ));
+}
+
+#[test]
+fn test_builder_validation() {
+    let invalid = [
+        LsmStorageOptions::builder().block_size(0),
+        LsmStorageOptions::builder().block_size(1 << 16),
+        LsmStorageOptions::builder()
+            .block_size(4096)
+            .target_sst_size(1024),
+        LsmStorageOptions::builder().num_memtable_limit(0),
+        LsmStorageOptions::builder().level0_file_num_compaction_trigger(0),
+        LsmStorageOptions::builder().compaction_options(CompactionOptions::Tiered(
+            TieredCompactionOptions {
+                num_tiers: 3,
+                max_size_amplification_percent: 200,
+                size_ratio: 1,
+                min_merge_width: 4,
+                max_merge_width: Some(2),
+            },
+        )),
+        LsmStorageOptions::builder().compaction_options(CompactionOptions::Leveled(
+            LeveledCompactionOptions {
+                level_size_multiplier: 1,
+                level0_file_num_compaction_trigger: 2,
+                max_levels: 4,
+                base_level_size_mb: 128,
+            },
+        )),
+    ];
+    for builder in invalid {
+        assert!(
+            matches!(builder.clone().build(), Err(Error::InvalidArgument(_))),
+            "{builder:?}"
+        );
+    }
+
+    // Options assembled by hand are checked when the storage is opened.
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week1_test();
+    options.block_size = 0;
+    assert!(matches!(
+        MiniLsm::open(&dir, options),
+        Err(Error::InvalidArgument(_))
+    ));
+}
+
+#[test]
+fn test_options_serde() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::builder()
+        .compaction_options(leveled())
+        .level0_file_num_compaction_trigger(4)
+        .build()
+        .unwrap();
+    let path = dir.path().join("options.json");
+    std::fs::write(&path, serde_json::to_vec(&options).unwrap()).unwrap();
+    let loaded = LsmStorageOptions::load(&path).unwrap();
+    assert_eq!(format!("{loaded:?}"), format!("{options:?}"));
+
+    let mut invalid = serde_json::to_value(&options).unwrap();
+    invalid["num_memtable_limit"] = 0.into();
+    std::fs::write(&path, serde_json::to_vec(&invalid).unwrap()).unwrap();
+    assert!(matches!(
+        LsmStorageOptions::load(&path),
+        Err(Error::InvalidArgument(_))
+    ));
+    std::fs::write(&path, b"not json").unwrap();
+    assert!(matches!(
+        LsmStorageOptions::load(&path),
+        Err(Error::InvalidArgument(_))
+    ));
+}
@@ -0,0 +1,126 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::ops::Bound;
+
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    iterators::StorageIterator,
+    lsm_storage::{LsmStorageOptions, MiniLsm, RecoveryProgress},
+};
+
+fn options(recovery_threads: usize) -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.enable_wal = true;
+    options.recovery_threads = recovery_threads;
+    options
+}
+
+/// Write 20 SSTs and 5 WALs of unflushed memtables, each round overwriting part of the previous ones.
+fn prepare(dir: &std::path::Path) {
+    let storage = MiniLsm::open(dir, options(1)).unwrap();
+    storage.pause_background();
+    for round in 0..25 {
+        for i in round..round + 10 {
+            storage
+                .put(
+                    format!("key{i:03}").as_bytes(),
+                    format!("v{round}").as_bytes(),
+                )
+                .unwrap();
+        }
+        if round < 20 {
+            storage.force_flush().unwrap();
+        } else {
+            storage
+                .inner
+                .force_freeze_memtable(&storage.inner.state_lock.lock())
+                .unwrap();
+        }
+    }
+    storage.close().unwrap();
+}
+
+fn contents(storage: &MiniLsm) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    let mut contents = Vec::new();
+    while iter.is_valid() {
+        contents.push((iter.key().to_vec(), iter.value().to_vec()));
+        iter.next().unwrap();
+    }
+    contents
+}
+
+#[test]
+fn test_parallel_recovery() {
+    let dir = tempdir().unwrap();
+    prepare(dir.path());
+
+    let storage = MiniLsm::open(&dir, options(1)).unwrap();
+    let expected = contents(&storage);
+    storage.close().unwrap();
+    assert_eq!(expected.len(), 34);
+    assert_eq!(
+        expected.last().unwrap(),
+        &(b"key033".to_vec(), b"v24".to_vec())
+    );
+
+    for threads in [2, 8, 64] {
+        let mut steps = Vec::new();
+        let storage =
+            MiniLsm::open_with_progress(&dir, options(threads), |step| steps.push(step)).unwrap();
+        assert_eq!(contents(&storage), expected);
+        assert_eq!(storage.inner.state.read().imm_memtables.len(), 5);
+        storage.close().unwrap();
+
+        // Progress is reported from the opening thread, counting up however the work was split
+        let opened = steps
+            .iter()
+            .filter_map(|step| match step {
+                RecoveryProgress::SstsOpened { opened, total: 20 } => Some(*opened),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(opened, (1..=20).collect::<Vec<_>>());
+        let replayed = steps
+            .iter()
+            .filter_map(|step| match step {
+                RecoveryProgress::WalsReplayed { replayed, total } => Some((*replayed, *total)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        // Each reopen leaves the WAL of its empty memtable behind as well
+        assert!(replayed.len() >= 5);
+        assert!(
+            replayed
+                .iter()
+                .enumerate()
+                .all(|(idx, &(n, total))| n == idx + 1 && total == replayed.len())
+        );
+    }
+}
+
+#[test]
+fn test_parallel_recovery_missing_sst() {
+    let dir = tempdir().unwrap();
+    prepare(dir.path());
+
+    let storage = MiniLsm::open(&dir, options(1)).unwrap();
+    let sst_id = storage.inner.state.read().l0_sstables[7];
+    storage.close().unwrap();
+    std::fs::remove_file(storage.inner.path_of_sst(sst_id)).unwrap();
+
+    assert!(MiniLsm::open(&dir, options(8)).is_err());
+}
@@ -0,0 +1,73 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::time::{Duration, Instant};
+
+use tempfile::tempdir;
+
+use crate::{
+    compact::{CompactionOptions, SimpleLeveledCompactionOptions},
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+fn wait_until(mut cond: impl FnMut() -> bool) {
+    let start = Instant::now();
+    while !cond() {
+        assert!(start.elapsed() < Duration::from_secs(10), "timed out");
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn test_pause_resume_background() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::Simple(
+        SimpleLeveledCompactionOptions {
+            size_ratio_percent: 200,
+            level0_file_num_compaction_trigger: 2,
+            max_levels: 2,
+        },
+    ));
+    options.num_memtable_limit = 1;
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.pause_background();
+    assert!(storage.is_background_paused());
+
+    // Neither flushes nor compactions happen while paused.
+    for i in 0..3 {
+        storage.put(format!("key{i}").as_bytes(), b"value").unwrap();
+        storage.force_flush().unwrap();
+    }
+    storage.put(b"key3", b"value").unwrap();
+    storage
+        .inner
+        .force_freeze_memtable(&storage.inner.state_lock.lock())
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(200));
+    {
+        let snapshot = storage.inner.state.read();
+        assert_eq!(snapshot.l0_sstables.len(), 3);
+        assert_eq!(snapshot.imm_memtables.len(), 1);
+    }
+
+    storage.resume_background();
+    assert!(!storage.is_background_paused());
+    wait_until(|| {
+        let snapshot = storage.inner.state.read();
+        snapshot.imm_memtables.is_empty() && snapshot.l0_sstables.len() < 2
+    });
+    for i in 0..4 {
+        assert!(storage.get(format!("key{i}").as_bytes()).unwrap().is_some());
+    }
+    storage.close().unwrap();
+}
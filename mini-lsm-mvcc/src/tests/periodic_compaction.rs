@@ -0,0 +1,127 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::time::{Duration, Instant};
+
+use tempfile::tempdir;
+
+use crate::{
+    compact::{
+        CompactionOptions, LazyLeveledCompactionOptions, LeveledCompactionOptions,
+        SimpleLeveledCompactionOptions, TieredCompactionOptions,
+    },
+    iterators::StorageIterator,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    table::SsTableIterator,
+};
+
+fn sst_ids(storage: &MiniLsm) -> Vec<usize> {
+    let snapshot = storage.inner.state.read().clone();
+    snapshot
+        .l0_sstables
+        .iter()
+        .chain(snapshot.levels.iter().flat_map(|(_, ssts)| ssts))
+        .copied()
+        .collect()
+}
+
+fn num_entries_in_ssts(storage: &MiniLsm) -> usize {
+    let snapshot = storage.inner.state.read().clone();
+    let mut cnt = 0;
+    for sst_id in sst_ids(storage) {
+        let mut iter =
+            SsTableIterator::create_and_seek_to_first(snapshot.sstables[&sst_id].clone()).unwrap();
+        while iter.is_valid() {
+            cnt += 1;
+            iter.next().unwrap();
+        }
+    }
+    cnt
+}
+
+fn test_periodic_compaction(compaction_options: CompactionOptions) {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(compaction_options);
+    options.periodic_compaction_ttl = Some(Duration::from_millis(200));
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for i in 0..100 {
+        storage
+            .put(format!("key{:03}", i).as_bytes(), b"value")
+            .unwrap();
+    }
+    storage.force_flush().unwrap();
+    for i in 0..50 {
+        storage.delete(format!("key{:03}", i).as_bytes()).unwrap();
+    }
+    storage.force_flush().unwrap();
+    let old_ssts = sst_ids(&storage);
+    assert_eq!(old_ssts.len(), 2);
+    assert_eq!(num_entries_in_ssts(&storage), 150);
+
+    // The old SSTs never reach the compaction triggers, so they can only be rewritten because they expired.
+    let start = Instant::now();
+    while num_entries_in_ssts(&storage) != 50 {
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "tombstones are not reclaimed by periodic compaction"
+        );
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    assert!(sst_ids(&storage).iter().all(|id| !old_ssts.contains(id)));
+    for i in 0..100 {
+        let value = storage.get(format!("key{:03}", i).as_bytes()).unwrap();
+        assert_eq!(value.is_some(), i >= 50);
+    }
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_periodic_compaction_simple() {
+    test_periodic_compaction(CompactionOptions::Simple(SimpleLeveledCompactionOptions {
+        size_ratio_percent: 200,
+        level0_file_num_compaction_trigger: 10,
+        max_levels: 3,
+    }));
+}
+
+#[test]
+fn test_periodic_compaction_leveled() {
+    test_periodic_compaction(CompactionOptions::Leveled(LeveledCompactionOptions {
+        level0_file_num_compaction_trigger: 10,
+        max_levels: 3,
+        base_level_size_mb: 1,
+        level_size_multiplier: 2,
+    }));
+}
+
+#[test]
+fn test_periodic_compaction_tiered() {
+    test_periodic_compaction(CompactionOptions::Tiered(TieredCompactionOptions {
+        num_tiers: 10,
+        max_size_amplification_percent: 200,
+        size_ratio: 1,
+        min_merge_width: 2,
+        max_merge_width: None,
+    }));
+}
+
+#[test]
+fn test_periodic_compaction_lazy_leveled() {
+    test_periodic_compaction(CompactionOptions::LazyLeveled(
+        LazyLeveledCompactionOptions {
+            size_ratio: 10,
+            level0_file_num_compaction_trigger: 10,
+            max_levels: 3,
+        },
+    ));
+}
@@ -0,0 +1,151 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::ops::Bound;
+
+use tempfile::tempdir;
+
+use crate::block::BlockBuilder;
+use crate::iterators::StorageIterator;
+use crate::key::KeySlice;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+use crate::persistent_cache::{PersistentBlockCache, PersistentCacheOptions};
+
+fn key_of(i: usize) -> Vec<u8> {
+    format!("key{i:04}").into_bytes()
+}
+
+fn block_of(i: usize) -> crate::block::Block {
+    let mut builder = BlockBuilder::new(4096);
+    for j in 0..10 {
+        assert!(builder.add(
+            KeySlice::for_testing_from_slice_no_ts(&key_of(i * 10 + j)),
+            b"value"
+        ));
+    }
+    builder.build()
+}
+
+#[test]
+fn test_persistent_cache_evicts_and_recovers() {
+    let dir = tempdir().unwrap();
+    let block_size = block_of(0).encode().len() as u64 + 4;
+    let options = PersistentCacheOptions {
+        path: dir.path().join("cache"),
+        capacity: block_size * 3,
+    };
+    let cache = PersistentBlockCache::open(&options, dir.path()).unwrap();
+    assert!(cache.is_empty());
+    for i in 0..3 {
+        cache.insert(1, i, &block_of(i)).unwrap();
+    }
+    assert_eq!(cache.len(), 3);
+    assert_eq!(cache.size(), block_size * 3);
+
+    // Using block 0 makes block 1 the least recently used one.
+    let block = cache.get(1, 0).unwrap();
+    assert_eq!(block.encode(), block_of(0).encode());
+    cache.insert(2, 0, &block_of(3)).unwrap();
+    assert_eq!(cache.len(), 3);
+    assert!(cache.get(1, 1).is_none());
+    assert!(cache.get(1, 0).is_some());
+    assert!(cache.get(1, 2).is_some());
+    drop(cache);
+
+    // Another store sharing the cache path has blocks of its own.
+    let other_dir = tempdir().unwrap();
+    let other = PersistentBlockCache::open(&options, other_dir.path()).unwrap();
+    assert!(other.is_empty());
+    assert!(other.get(1, 0).is_none());
+    drop(other);
+
+    let cache = PersistentBlockCache::open(&options, dir.path()).unwrap();
+    assert_eq!(cache.len(), 3);
+    assert_eq!(cache.get(2, 0).unwrap().encode(), block_of(3).encode());
+    assert!(cache.get(1, 1).is_none());
+
+    // A damaged file is dropped from the cache.
+    let path = cache.dir().join(format!("{:032x}-{}.block", 1, 2));
+    let mut data = std::fs::read(&path).unwrap();
+    data[0] ^= 0xff;
+    std::fs::write(&path, data).unwrap();
+    assert!(cache.get(1, 2).is_none());
+    assert_eq!(cache.len(), 2);
+    assert!(!path.exists());
+    drop(cache);
+
+    // Lowering the capacity evicts blocks on open.
+    let cache = PersistentBlockCache::open(
+        &PersistentCacheOptions {
+            capacity: block_size,
+            ..options.clone()
+        },
+        dir.path(),
+    )
+    .unwrap();
+    assert_eq!(cache.len(), 1);
+    assert_eq!(std::fs::read_dir(cache.dir()).unwrap().count(), 1);
+}
+
+#[test]
+fn test_reads_served_from_persistent_cache() {
+    let dir = tempdir().unwrap();
+    let cache_dir = tempdir().unwrap();
+    let options = LsmStorageOptions::builder()
+        .block_size(256)
+        .persistent_cache(PersistentCacheOptions {
+            path: cache_dir.path().to_path_buf(),
+            capacity: 1 << 30,
+        })
+        .build()
+        .unwrap();
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    for i in 0..1000 {
+        storage.put(&key_of(i), b"value").unwrap();
+    }
+    storage.force_flush().unwrap();
+    let sst_id = storage.inner.state.read().l0_sstables[0];
+    let sst = storage.inner.state.read().sstables[&sst_id].clone();
+    let num_blocks = sst.num_of_blocks();
+    let offset = sst.block_meta[3].offset;
+    drop(sst);
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    while iter.is_valid() {
+        iter.next().unwrap();
+    }
+    drop(iter);
+    let tier = storage.inner.block_cache.persistent_tier().unwrap();
+    assert_eq!(tier.len(), num_blocks);
+    storage.close().unwrap();
+
+    // Damage a block of the SST, which the copy in the persistent cache hides after a restart.
+    let sst_path = dir.path().join(format!("{sst_id:05}.sst"));
+    let mut data = std::fs::read(&sst_path).unwrap();
+    data[offset + 10] ^= 0xff;
+    std::fs::write(&sst_path, data).unwrap();
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(
+        storage.inner.block_cache.persistent_tier().unwrap().len(),
+        num_blocks
+    );
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    let mut count = 0;
+    while iter.is_valid() {
+        assert_eq!(iter.key(), key_of(count));
+        count += 1;
+        iter.next().unwrap();
+    }
+    assert_eq!(count, 1000);
+    drop(iter);
+    storage.close().unwrap();
+}
@@ -0,0 +1,136 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::{
+    sync::{Arc, Barrier},
+    thread,
+    time::Duration,
+};
+
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+fn options(lock_timeout: Duration) -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.lock_timeout = lock_timeout;
+    options
+}
+
+#[test]
+fn test_get_for_update_blocks_other_writes() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options(Duration::from_millis(50))).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    let txn = storage.new_txn().unwrap();
+    assert_eq!(
+        txn.get_for_update(b"a").unwrap().as_deref(),
+        Some(&b"1"[..])
+    );
+    assert_eq!(
+        txn.get_for_update(b"a").unwrap().as_deref(),
+        Some(&b"1"[..])
+    );
+    assert_eq!(txn.get_for_update(b"b").unwrap(), None);
+
+    assert!(matches!(storage.put(b"a", b"2"), Err(Error::Busy(_))));
+    assert!(matches!(storage.delete(b"b"), Err(Error::Busy(_))));
+    let other = storage.new_txn().unwrap();
+    assert!(matches!(other.get_for_update(b"a"), Err(Error::Busy(_))));
+    other.put(b"b", b"2");
+    assert!(matches!(other.commit(), Err(Error::Busy(_))));
+
+    txn.put(b"a", b"3");
+    txn.commit().unwrap();
+    assert_eq!(storage.get(b"a").unwrap().as_deref(), Some(&b"3"[..]));
+    storage.put(b"b", b"4").unwrap();
+
+    let txn = storage.new_txn().unwrap();
+    txn.get_for_update(b"a").unwrap();
+    txn.rollback().unwrap();
+    storage.put(b"a", b"5").unwrap();
+    let txn = storage.new_txn().unwrap();
+    txn.get_for_update(b"a").unwrap();
+    drop(txn);
+    storage.put(b"a", b"6").unwrap();
+}
+
+#[test]
+fn test_get_for_update_waits_for_commit() {
+    let dir = tempdir().unwrap();
+    let storage = Arc::new(MiniLsm::open(&dir, options(Duration::from_secs(10))).unwrap());
+    let txn = storage.new_txn().unwrap();
+    txn.get_for_update(b"counter").unwrap();
+    let waiter = {
+        let storage = storage.clone();
+        thread::spawn(move || {
+            let txn = storage.new_txn().unwrap();
+            // the holder commits after this transaction started, so reading the key for update fails
+            let result = txn.get_for_update(b"counter");
+            assert!(matches!(result, Err(Error::Busy(_))));
+            let txn = storage.new_txn().unwrap();
+            txn.get_for_update(b"counter").unwrap()
+        })
+    };
+    thread::sleep(Duration::from_millis(100));
+    txn.put(b"counter", b"1");
+    txn.commit().unwrap();
+    assert_eq!(waiter.join().unwrap().as_deref(), Some(&b"1"[..]));
+}
+
+#[test]
+fn test_get_for_update_rejects_changes_after_read_ts() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options(Duration::from_millis(50))).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    let txn = storage.new_txn().unwrap();
+    storage.delete(b"a").unwrap();
+    storage.force_flush().unwrap();
+    assert!(matches!(txn.get_for_update(b"a"), Err(Error::Busy(_))));
+    let txn = storage.new_txn().unwrap();
+    assert_eq!(txn.get_for_update(b"a").unwrap(), None);
+}
+
+#[test]
+fn test_get_for_update_detects_deadlock() {
+    let dir = tempdir().unwrap();
+    let storage = Arc::new(MiniLsm::open(&dir, options(Duration::from_secs(10))).unwrap());
+    let barrier = Arc::new(Barrier::new(2));
+    let lock_both = |first: &'static [u8], second: &'static [u8]| {
+        let storage = storage.clone();
+        let barrier = barrier.clone();
+        thread::spawn(move || {
+            let txn = storage.new_txn().unwrap();
+            txn.get_for_update(first).unwrap();
+            barrier.wait();
+            // dropping the transaction releases its locks, letting the other one through
+            txn.get_for_update(second).map(|_| ())
+        })
+    };
+    let x = lock_both(b"a", b"b");
+    let y = lock_both(b"b", b"a");
+    let results = [x.join().unwrap(), y.join().unwrap()];
+    assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 1);
+    for result in results {
+        if let Err(e) = result {
+            assert!(
+                matches!(&e, Error::Busy(msg) if msg.contains("deadlock")),
+                "{e}"
+            );
+        }
+    }
+}
@@ -0,0 +1,166 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::sync::Arc;
+
+use tempfile::tempdir;
+
+use crate::iterators::StorageIterator;
+use crate::key::KeySlice;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+use crate::prefix::{PrefixExtractor, PrefixExtractorOptions, prefix_successor};
+use crate::table::{FileObject, SsTable, SsTableBuilder};
+
+/// The key up to the second byte, if it is a digit.
+#[derive(Debug)]
+struct DigitPrefix;
+
+impl PrefixExtractor for DigitPrefix {
+    fn name(&self) -> String {
+        "digit".to_string()
+    }
+
+    fn prefix<'a>(&self, key: &'a [u8]) -> Option<&'a [u8]> {
+        key.get(..2).filter(|prefix| prefix[1].is_ascii_digit())
+    }
+}
+
+#[test]
+fn test_prefix_extractors() {
+    let fixed = PrefixExtractorOptions::Fixed(3);
+    assert_eq!(fixed.prefix(b"abcd"), Some(&b"abc"[..]));
+    assert_eq!(fixed.prefix(b"abc"), Some(&b"abc"[..]));
+    assert_eq!(fixed.prefix(b"ab"), None);
+    let delimited = PrefixExtractorOptions::Delimited(b':');
+    assert_eq!(delimited.prefix(b"user:1:a"), Some(&b"user:"[..]));
+    assert_eq!(delimited.prefix(b"user"), None);
+    let custom = PrefixExtractorOptions::Custom(Arc::new(DigitPrefix));
+    assert_eq!(custom.prefix(b"a1b"), Some(&b"a1"[..]));
+    assert_eq!(custom.prefix(b"ab1"), None);
+    assert_eq!(fixed.name(), "fixed:3");
+    assert_ne!(
+        delimited.name(),
+        PrefixExtractorOptions::Delimited(b'/').name()
+    );
+    assert_eq!(custom.name(), "digit");
+
+    assert_eq!(prefix_successor(b"ab"), Some(b"ac".to_vec()));
+    assert_eq!(prefix_successor(b"a\xff\xff"), Some(b"b".to_vec()));
+    assert_eq!(prefix_successor(b"\xff"), None);
+    assert_eq!(prefix_successor(b""), None);
+}
+
+#[test]
+fn test_sst_prefix_filter() {
+    let dir = tempdir().unwrap();
+    let extractor = PrefixExtractorOptions::Delimited(b':');
+    let mut builder = SsTableBuilder::new(64);
+    builder.set_prefix_extractor(extractor.clone());
+    for prefix in ["a", "c"] {
+        for i in 0..50 {
+            let key = format!("{prefix}{i:02}:{i}");
+            builder.add(
+                KeySlice::for_testing_from_slice_with_ts(key.as_bytes(), 1),
+                b"value",
+            );
+        }
+    }
+    let path = dir.path().join("1.sst");
+    builder.build_for_test(&path).unwrap();
+    let table = SsTable::open_for_test(FileObject::open(&path).unwrap()).unwrap();
+    assert_eq!(table.prefix_extractor(), Some("delimited:58"));
+
+    let mut hits = 0;
+    for i in 0..50 {
+        assert!(table.may_contain_prefix(&extractor, format!("a{i:02}:").as_bytes()));
+        assert!(table.may_contain_prefix(&extractor, format!("c{i:02}:").as_bytes()));
+        hits += table.may_contain_prefix(&extractor, format!("b{i:02}:").as_bytes()) as usize;
+    }
+    assert!(hits < 5, "{hits} absent prefixes passed the filter");
+    // The filter holds no prefixes of another extractor
+    let other = PrefixExtractorOptions::Fixed(3);
+    assert!(table.may_contain_prefix(&other, b"b00"));
+}
+
+#[test]
+fn test_scan_prefix() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::builder()
+        .block_size(256)
+        .prefix_extractor(PrefixExtractorOptions::Delimited(b':'))
+        .build()
+        .unwrap();
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for tenant in ["a", "c"] {
+        for i in 0..100 {
+            storage
+                .put(format!("{tenant}:{i:03}").as_bytes(), b"sst")
+                .unwrap();
+        }
+    }
+    storage.force_flush().unwrap();
+    for i in 0..10 {
+        storage
+            .put(format!("b:{i:03}").as_bytes(), b"memtable")
+            .unwrap();
+    }
+    storage.put(b"c:000", b"updated").unwrap();
+
+    // The SST spans tenant b but does not hold it, so the scan reads no block
+    let mut iter = storage.scan_prefix(b"b:").unwrap();
+    let mut count = 0;
+    while iter.is_valid() {
+        assert!(iter.key().starts_with(b"b:"));
+        assert_eq!(iter.value(), b"memtable");
+        count += 1;
+        iter.next().unwrap();
+    }
+    assert_eq!(count, 10);
+    drop(iter);
+    assert_eq!(storage.memory_usage().block_cache, 0);
+
+    let mut iter = storage.scan_prefix(b"c:").unwrap();
+    assert_eq!(iter.key(), b"c:000");
+    assert_eq!(iter.value(), b"updated");
+    let mut count = 0;
+    while iter.is_valid() {
+        assert!(iter.key().starts_with(b"c:"));
+        count += 1;
+        iter.next().unwrap();
+    }
+    assert_eq!(count, 100);
+    drop(iter);
+    assert!(storage.memory_usage().block_cache > 0);
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_scan_prefix_without_extractor() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::builder()
+        .block_size(256)
+        .build()
+        .unwrap();
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for tenant in ["a", "c"] {
+        storage.put(format!("{tenant}:1").as_bytes(), b"1").unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.put(b"b:1", b"1").unwrap();
+    let iter = storage.scan_prefix(b"b:").unwrap();
+    assert_eq!(iter.key(), b"b:1");
+    drop(iter);
+    // Without the prefixes in the filter, the SST spanning the prefix is read
+    assert!(storage.memory_usage().block_cache > 0);
+    storage.close().unwrap();
+}
@@ -0,0 +1,124 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MiniLsm, WriteBatchRecord},
+    mvcc::ts_provider::{TsProvider, TsProviderOptions},
+};
+
+#[test]
+fn test_prefix_quota_usage() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.set_prefix_quota(b"t1/", None);
+    storage.set_prefix_quota(b"t1/hot/", None);
+    storage.put(b"t1/a", b"12345").unwrap();
+    storage.put(b"t1/hot/a", b"1").unwrap();
+    storage.delete(b"t1/b").unwrap();
+    storage.put(b"t2/a", b"1").unwrap();
+
+    // key + timestamp + value, charged to the longest matching prefix
+    assert_eq!(
+        storage.prefix_quota_usage(b"t1/").unwrap().bytes_written,
+        (4 + 8 + 5) + (4 + 8)
+    );
+    assert_eq!(
+        storage
+            .prefix_quota_usage(b"t1/hot/")
+            .unwrap()
+            .bytes_written,
+        8 + 8 + 1
+    );
+    assert_eq!(storage.prefix_quota_usage(b"t2/"), None);
+    assert_eq!(storage.all_prefix_quota_usage().len(), 2);
+    assert!(storage.remove_prefix_quota(b"t1/hot/").is_some());
+    storage.put(b"t1/hot/a", b"1").unwrap();
+    assert_eq!(
+        storage.prefix_quota_usage(b"t1/").unwrap().bytes_written,
+        (4 + 8 + 5) + (4 + 8) + (8 + 8 + 1)
+    );
+}
+
+#[test]
+fn test_prefix_quota_reject() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.set_prefix_quota(b"t1/", Some(40));
+    storage.put(b"t1/a", b"0123456789").unwrap();
+    // A batch is either charged as a whole or rejected as a whole.
+    let err = storage
+        .write_batch(&[
+            WriteBatchRecord::Put(&b"t2/a"[..], &b"1"[..]),
+            WriteBatchRecord::Put(&b"t1/b"[..], &b"0123456789"[..]),
+        ])
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        Error::QuotaExceeded {
+            usage: 22,
+            limit: 40,
+            ..
+        }
+    ));
+    assert_eq!(storage.get(b"t2/a").unwrap(), None);
+    assert_eq!(
+        storage.prefix_quota_usage(b"t1/").unwrap().bytes_written,
+        22
+    );
+    storage.put(b"t1/b", b"1").unwrap();
+    storage.put(b"t2/a", b"0123456789").unwrap();
+
+    // Raising the limit keeps the usage.
+    storage.set_prefix_quota(b"t1/", Some(100));
+    storage.put(b"t1/c", b"0123456789").unwrap();
+    assert_eq!(
+        storage.prefix_quota_usage(b"t1/").unwrap().bytes_written,
+        22 + 13 + 22
+    );
+    assert_eq!(
+        storage.get(b"t1/c").unwrap(),
+        Some(Bytes::from("0123456789"))
+    );
+}
+
+/// Hands out no timestamps, failing every commit.
+#[derive(Debug)]
+struct Unavailable;
+
+impl TsProvider for Unavailable {
+    fn next_ts(&self, _last: u64) -> anyhow::Result<u64> {
+        anyhow::bail!("timestamp oracle unavailable")
+    }
+}
+
+#[test]
+fn test_prefix_quota_failed_write() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.ts_provider = TsProviderOptions::External(Arc::new(Unavailable));
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.set_prefix_quota(b"t1/", Some(100));
+    assert!(storage.put(b"t1/a", b"12345").is_err());
+    // A batch that is not written is not charged
+    assert_eq!(storage.prefix_quota_usage(b"t1/").unwrap().bytes_written, 0);
+}
@@ -0,0 +1,121 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Bound;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    iterators::StorageIterator,
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MiniLsm, ReadOptions},
+    mvcc::txn::TxnIterator,
+};
+
+fn read_committed() -> ReadOptions {
+    ReadOptions {
+        read_committed: true,
+        ..Default::default()
+    }
+}
+
+fn collect(mut iter: TxnIterator) -> Vec<(Bytes, Bytes)> {
+    let mut entries = Vec::new();
+    while iter.is_valid() {
+        entries.push((
+            Bytes::copy_from_slice(iter.key()),
+            Bytes::copy_from_slice(iter.value()),
+        ));
+        iter.next().unwrap();
+    }
+    entries
+}
+
+#[test]
+fn test_read_committed_get() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    assert_eq!(
+        storage.get_with_options(b"a", &read_committed()).unwrap(),
+        Some(Bytes::from("1"))
+    );
+    storage.put(b"a", b"2").unwrap();
+    storage.delete(b"b").unwrap();
+    assert_eq!(
+        storage.get_with_options(b"a", &read_committed()).unwrap(),
+        Some(Bytes::from("2"))
+    );
+    assert_eq!(
+        storage.get_with_options(b"b", &read_committed()).unwrap(),
+        None
+    );
+    storage.force_flush().unwrap();
+    assert_eq!(
+        storage.get_with_options(b"a", &read_committed()).unwrap(),
+        Some(Bytes::from("2"))
+    );
+}
+
+#[test]
+fn test_read_committed_scan() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let expected = (0..100)
+        .map(|i| (Bytes::from(format!("key{i:03}")), Bytes::from("old")))
+        .collect::<Vec<_>>();
+    for (key, value) in &expected {
+        storage.put(key, value).unwrap();
+    }
+    storage.force_flush().unwrap();
+
+    let iter = storage
+        .scan_with_options(Bound::Unbounded, Bound::Unbounded, &read_committed())
+        .unwrap();
+    // The scan pins nothing in the watermark, so the old versions are compacted away
+    let latest_ts = storage.inner.mvcc().latest_commit_ts();
+    assert_eq!(storage.inner.mvcc().watermark(), latest_ts);
+    for (key, _) in &expected {
+        storage.put(key, b"new").unwrap();
+    }
+    storage.put(b"key100", b"new").unwrap();
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    assert_eq!(
+        storage.inner.mvcc().watermark(),
+        storage.inner.mvcc().latest_commit_ts()
+    );
+
+    // but the scan still reads the state it was created on
+    assert_eq!(collect(iter), expected);
+    let iter = storage
+        .scan_with_options(Bound::Unbounded, Bound::Unbounded, &read_committed())
+        .unwrap();
+    let entries = collect(iter);
+    assert_eq!(entries.len(), 101);
+    assert!(entries.iter().all(|(_, value)| value == "new"));
+
+    let options = ReadOptions {
+        max_keys_per_poll: Some(10),
+        ..read_committed()
+    };
+    assert!(matches!(
+        storage.scan_with_options(Bound::Unbounded, Bound::Unbounded, &options),
+        Err(Error::InvalidArgument(_))
+    ));
+}
@@ -0,0 +1,216 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::ops::Bound;
+
+use tempfile::tempdir;
+
+use crate::iterators::StorageIterator;
+use crate::lsm_error::Error;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm, ReadOptions};
+
+fn key_of(i: usize) -> Vec<u8> {
+    format!("key{i:04}").into_bytes()
+}
+
+fn open_with_sst(path: &std::path::Path) -> std::sync::Arc<MiniLsm> {
+    let options = LsmStorageOptions::builder()
+        .block_size(256)
+        .build()
+        .unwrap();
+    let storage = MiniLsm::open(path, options).unwrap();
+    for i in 0..1000 {
+        storage.put(&key_of(i), b"value").unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage
+}
+
+fn scan_all(storage: &MiniLsm, options: &ReadOptions) -> Vec<Vec<u8>> {
+    let mut iter = storage
+        .scan_with_options(Bound::Unbounded, Bound::Unbounded, options)
+        .unwrap();
+    let mut keys = Vec::new();
+    while iter.is_valid() {
+        keys.push(iter.key().to_vec());
+        iter.next().unwrap();
+    }
+    keys
+}
+
+#[test]
+fn test_read_options_fill_cache() {
+    let dir = tempdir().unwrap();
+    let storage = open_with_sst(dir.path());
+    let options = ReadOptions {
+        fill_cache: false,
+        ..Default::default()
+    };
+    assert_eq!(scan_all(&storage, &options).len(), 1000);
+    assert_eq!(
+        storage.get_with_options(&key_of(500), &options).unwrap(),
+        Some("value".into())
+    );
+    let cache = &storage.inner.block_cache;
+    assert_eq!(cache.iter().count(), 0);
+
+    assert_eq!(scan_all(&storage, &ReadOptions::default()).len(), 1000);
+    assert!(cache.iter().count() > 1);
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_read_options_readahead() {
+    let dir = tempdir().unwrap();
+    let storage = open_with_sst(dir.path());
+    let expected = scan_all(&storage, &ReadOptions::default());
+    for (readahead_size, fill_cache) in [(1, false), (1000, false), (1 << 20, true)] {
+        let options = ReadOptions {
+            readahead_size,
+            fill_cache,
+            ..Default::default()
+        };
+        assert_eq!(scan_all(&storage, &options), expected);
+        for i in [0, 1, 499, 999] {
+            assert_eq!(
+                storage.get_with_options(&key_of(i), &options).unwrap(),
+                Some("value".into())
+            );
+        }
+    }
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_read_options_skip_checksum_verification() {
+    let dir = tempdir().unwrap();
+    let storage = open_with_sst(dir.path());
+    let sst_id = storage.inner.state.read().l0_sstables[0];
+    let block_end = storage.inner.state.read().sstables[&sst_id].block_meta[1].offset;
+    storage.close().unwrap();
+
+    // Damage the checksum of the first block but not its content
+    let sst_path = dir.path().join(format!("{sst_id:05}.sst"));
+    let mut data = std::fs::read(&sst_path).unwrap();
+    data[block_end - 1] ^= 0xff;
+    std::fs::write(&sst_path, data).unwrap();
+
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    assert!(matches!(storage.get(&key_of(0)), Err(Error::Corruption(_))));
+    let options = ReadOptions {
+        verify_checksums: false,
+        ..Default::default()
+    };
+    assert_eq!(
+        storage.get_with_options(&key_of(0), &options).unwrap(),
+        Some("value".into())
+    );
+    assert_eq!(scan_all(&storage, &options).len(), 1000);
+    // The unverified block was not cached for other readers
+    assert!(matches!(storage.get(&key_of(0)), Err(Error::Corruption(_))));
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_read_options_snapshot() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.put(b"b", b"1").unwrap();
+    let txn = storage.new_txn().unwrap();
+    storage.put(b"a", b"2").unwrap();
+    storage.delete(b"b").unwrap();
+    storage.force_flush().unwrap();
+
+    let options = ReadOptions {
+        snapshot: Some(txn.read_ts()),
+        ..Default::default()
+    };
+    assert_eq!(
+        storage.get_with_options(b"a", &options).unwrap(),
+        Some("1".into())
+    );
+    assert_eq!(
+        scan_all(&storage, &options),
+        vec![b"a".to_vec(), b"b".to_vec()]
+    );
+    assert_eq!(storage.get(b"a").unwrap(), Some("2".into()));
+    assert_eq!(
+        scan_all(&storage, &ReadOptions::default()),
+        vec![b"a".to_vec()]
+    );
+
+    let future = ReadOptions {
+        snapshot: Some(txn.read_ts() + 100),
+        ..Default::default()
+    };
+    assert!(matches!(
+        storage.get_with_options(b"a", &future),
+        Err(Error::InvalidArgument(_))
+    ));
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_read_options_snapshot_below_gc_watermark() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    let ts1 = storage.inner.mvcc().latest_commit_ts();
+    storage.put(b"a", b"2").unwrap();
+    let ts2 = storage.inner.mvcc().latest_commit_ts();
+    storage.put(b"a", b"3").unwrap();
+    storage.force_flush().unwrap();
+    storage.close().unwrap();
+    drop(storage);
+
+    // Nothing was compacted, so the whole history can be read, even after reopening without any snapshot open
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    assert_eq!(storage.gc_watermark(), 0);
+    let at = |ts| ReadOptions {
+        snapshot: Some(ts),
+        ..Default::default()
+    };
+    assert_eq!(
+        storage.get_with_options(b"a", &at(ts1)).unwrap(),
+        Some("1".into())
+    );
+
+    // A compaction drops the versions below the watermark, keeping those visible to the open snapshot
+    let txn = storage
+        .inner
+        .mvcc()
+        .new_txn_at(storage.inner.clone(), ts2, false)
+        .unwrap();
+    storage.force_full_compaction().unwrap();
+    assert_eq!(storage.gc_watermark(), ts2);
+    assert_eq!(txn.get(b"a").unwrap(), Some("2".into()));
+    assert!(matches!(
+        storage.get_with_options(b"a", &at(ts1)),
+        Err(Error::InvalidArgument(_))
+    ));
+    drop(txn);
+    storage.close().unwrap();
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    assert_eq!(storage.gc_watermark(), ts2);
+    assert_eq!(
+        storage.get_with_options(b"a", &at(ts2)).unwrap(),
+        Some("2".into())
+    );
+    assert!(matches!(
+        storage.get_with_options(b"a", &at(ts1)),
+        Err(Error::InvalidArgument(_))
+    ));
+}
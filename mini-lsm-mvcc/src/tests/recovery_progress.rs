@@ -0,0 +1,142 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::time::Duration;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MiniLsm, RecoveryProgress},
+};
+
+fn options() -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.enable_wal = true;
+    options
+}
+
+/// Write three SSTs and two WALs of unflushed memtables.
+fn prepare(dir: &std::path::Path) {
+    let storage = MiniLsm::open(dir, options()).unwrap();
+    storage.pause_background();
+    for round in 0..5 {
+        for i in 0..10 {
+            storage
+                .put(format!("key{i}").as_bytes(), format!("v{round}").as_bytes())
+                .unwrap();
+        }
+        if round < 3 {
+            storage.force_flush().unwrap();
+        } else {
+            storage
+                .inner
+                .force_freeze_memtable(&storage.inner.state_lock.lock())
+                .unwrap();
+        }
+    }
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_recovery_progress() {
+    let dir = tempdir().unwrap();
+    prepare(dir.path());
+
+    let mut steps = Vec::new();
+    let storage = MiniLsm::open_with_progress(&dir, options(), |step| steps.push(step)).unwrap();
+    assert_eq!(storage.get(b"key0").unwrap(), Some(Bytes::from("v4")));
+    storage.close().unwrap();
+
+    let last_of = |matches: fn(&RecoveryProgress) -> bool| {
+        steps
+            .iter()
+            .rev()
+            .find(|step| matches(step))
+            .copied()
+            .unwrap()
+    };
+    let RecoveryProgress::ManifestRecordsApplied { applied, total } =
+        last_of(|step| matches!(step, RecoveryProgress::ManifestRecordsApplied { .. }))
+    else {
+        unreachable!()
+    };
+    assert!(applied == total && total > 0);
+    assert_eq!(
+        last_of(|step| matches!(step, RecoveryProgress::SstsOpened { .. })),
+        RecoveryProgress::SstsOpened {
+            opened: 3,
+            total: 3
+        }
+    );
+    let RecoveryProgress::WalsReplayed { replayed, total } =
+        last_of(|step| matches!(step, RecoveryProgress::WalsReplayed { .. }))
+    else {
+        unreachable!()
+    };
+    assert!(replayed == total && total >= 2);
+    // The manifest is replayed before the SSTs are opened, and the WALs are replayed last
+    let first_sst = steps
+        .iter()
+        .position(|step| matches!(step, RecoveryProgress::SstsOpened { .. }))
+        .unwrap();
+    assert!(
+        steps[..first_sst]
+            .iter()
+            .all(|step| matches!(step, RecoveryProgress::ManifestRecordsApplied { .. }))
+    );
+    assert!(matches!(
+        steps.last().unwrap(),
+        RecoveryProgress::WalsReplayed { .. }
+    ));
+}
+
+#[test]
+fn test_open_timeout() {
+    let dir = tempdir().unwrap();
+    prepare(dir.path());
+    let mut options = options();
+    options.open_timeout = Some(Duration::from_millis(10));
+    let result = MiniLsm::open_with_progress(&dir, options.clone(), |step| {
+        if matches!(step, RecoveryProgress::SstsOpened { opened: 1, .. }) {
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    });
+    assert!(matches!(result, Err(Error::Busy(_))));
+
+    // Nothing is lost by the aborted open
+    options.open_timeout = None;
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(storage.get(b"key9").unwrap(), Some(Bytes::from("v4")));
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_lazy_bloom_filters() {
+    let dir = tempdir().unwrap();
+    prepare(dir.path());
+    let mut options = options();
+    options.preload_bloom_filters = false;
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let snapshot = storage.inner.state.read().clone();
+    for sst in snapshot.sstables.values() {
+        assert!(sst.bloom.is_none());
+        assert!(sst.may_contain_key(b"key3"));
+        assert!(!sst.may_contain_key(b"missing"));
+    }
+    assert_eq!(storage.get(b"key3").unwrap(), Some(Bytes::from("v4")));
+    assert_eq!(storage.get(b"missing").unwrap(), None);
+    storage.close().unwrap();
+}
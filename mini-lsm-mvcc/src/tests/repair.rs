@@ -0,0 +1,158 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use tempfile::tempdir;
+
+use crate::{
+    compact::{CompactionOptions, SimpleLeveledCompactionOptions, TieredCompactionOptions},
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+fn simple() -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::Simple(
+        SimpleLeveledCompactionOptions {
+            size_ratio_percent: 200,
+            level0_file_num_compaction_trigger: 2,
+            max_levels: 3,
+        },
+    ));
+    options.enable_wal = true;
+    options
+}
+
+fn fill(storage: &MiniLsm) {
+    for round in 0..4 {
+        for i in 0..100 {
+            storage
+                .put(
+                    format!("key{i:03}").as_bytes(),
+                    format!("value{round}").as_bytes(),
+                )
+                .unwrap();
+        }
+        storage.force_flush().unwrap();
+    }
+    storage.delete(b"key000").unwrap();
+    storage.force_flush().unwrap();
+    // Left in the WAL
+    storage.put(b"key001", b"unflushed").unwrap();
+}
+
+fn check(storage: &MiniLsm) {
+    assert_eq!(storage.get(b"key000").unwrap(), None);
+    assert_eq!(&storage.get(b"key001").unwrap().unwrap()[..], b"unflushed");
+    for i in 2..100 {
+        assert_eq!(
+            &storage
+                .get(format!("key{i:03}").as_bytes())
+                .unwrap()
+                .unwrap()[..],
+            b"value3"
+        );
+    }
+}
+
+#[test]
+fn test_repair_corrupted_manifest() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, simple()).unwrap();
+    fill(&storage);
+    storage.close().unwrap();
+    drop(storage);
+
+    std::fs::write(dir.path().join("MANIFEST"), b"garbage").unwrap();
+    assert!(MiniLsm::open(&dir, simple()).is_err());
+
+    let summary = MiniLsm::repair(&dir, &simple()).unwrap();
+    assert!(!summary.sst_ids.is_empty());
+    assert!(summary.corrupt_files.is_empty());
+    assert!(dir.path().join("MANIFEST.old").exists());
+    let storage = MiniLsm::open(&dir, simple()).unwrap();
+    check(&storage);
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_repair_into_other_strategy() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, simple()).unwrap();
+    fill(&storage);
+    storage.close().unwrap();
+    drop(storage);
+
+    let mut tiered = LsmStorageOptions::default_for_week2_test(CompactionOptions::Tiered(
+        TieredCompactionOptions {
+            num_tiers: 3,
+            max_size_amplification_percent: 200,
+            size_ratio: 1,
+            min_merge_width: 2,
+            max_merge_width: None,
+        },
+    ));
+    tiered.enable_wal = true;
+    MiniLsm::repair(&dir, &tiered).unwrap();
+    let storage = MiniLsm::open(&dir, tiered).unwrap();
+    check(&storage);
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_repair_skips_corrupted_sst() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, simple()).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.force_flush().unwrap();
+    storage.put(b"b", b"2").unwrap();
+    storage.force_flush().unwrap();
+    let sst_id = storage.inner.state.read().l0_sstables[0];
+    storage.close().unwrap();
+    drop(storage);
+
+    let sst_path = dir.path().join(format!("{sst_id:05}.sst"));
+    let mut data = std::fs::read(&sst_path).unwrap();
+    data[0] ^= 0xff;
+    std::fs::write(&sst_path, data).unwrap();
+
+    let summary = MiniLsm::repair(&dir, &simple()).unwrap();
+    assert_eq!(summary.corrupt_files.len(), 1);
+    assert!(!sst_path.exists());
+    let storage = MiniLsm::open(&dir, simple()).unwrap();
+    assert_eq!(&storage.get(b"a").unwrap().unwrap()[..], b"1");
+    assert_eq!(storage.get(b"b").unwrap(), None);
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_destroy() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("db");
+    let storage = MiniLsm::open(&path, simple()).unwrap();
+    fill(&storage);
+    storage.close().unwrap();
+    drop(storage);
+    MiniLsm::destroy(&path).unwrap();
+    assert!(!path.exists());
+    MiniLsm::destroy(&path).unwrap();
+
+    // Files the engine does not own are kept.
+    let storage = MiniLsm::open(&path, simple()).unwrap();
+    storage.close().unwrap();
+    drop(storage);
+    std::fs::write(path.join("notes.txt"), b"keep").unwrap();
+    MiniLsm::destroy(&path).unwrap();
+    let names = std::fs::read_dir(&path)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["notes.txt"]);
+}
@@ -0,0 +1,133 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    iterators::StorageIterator,
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    replication::{Replica, ReplicationBatch, ReplicationSession, ReplicationSource},
+};
+
+/// Sends batches through their serialized form, as a network transport would.
+struct SerializingSource(ReplicationSession);
+
+impl ReplicationSource for SerializingSource {
+    fn poll(&mut self) -> anyhow::Result<ReplicationBatch> {
+        let data = serde_json::to_vec(&self.0.poll()?)?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+}
+
+fn options() -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.enable_wal = true;
+    options
+}
+
+fn key_of(i: usize) -> Vec<u8> {
+    format!("key_{:05}", i).into_bytes()
+}
+
+#[test]
+fn test_replication() {
+    let leader_dir = tempdir().unwrap();
+    let leader = MiniLsm::open(&leader_dir, options()).unwrap();
+    for i in 0..100 {
+        leader.put(&key_of(i), b"v1").unwrap();
+    }
+    leader.force_flush().unwrap();
+    for i in 0..50 {
+        leader.put(&key_of(i), b"v2").unwrap();
+    }
+    leader.delete(&key_of(99)).unwrap();
+    leader.sync().unwrap();
+
+    let replica_dir = tempdir().unwrap();
+    let replica = Replica::create(&replica_dir, options()).unwrap();
+    let mut source = SerializingSource(leader.replication_session());
+    replica.catch_up(&mut source).unwrap();
+    assert_eq!(replica.stats().lag(), 0);
+    assert!(replica.stats().bytes_received > 0);
+    assert_eq!(replica.get(&key_of(0)).unwrap(), Some(Bytes::from("v2")));
+    assert_eq!(replica.get(&key_of(98)).unwrap(), Some(Bytes::from("v1")));
+    assert_eq!(replica.get(&key_of(99)).unwrap(), None);
+
+    // Writes are not visible until the replica catches up.
+    for i in 0..1000 {
+        leader.put(&key_of(i), b"v3").unwrap();
+    }
+    leader.force_flush().unwrap();
+    leader.force_full_compaction().unwrap();
+    leader.put(&key_of(1000), b"v3").unwrap();
+    leader.sync().unwrap();
+    assert_eq!(replica.get(&key_of(0)).unwrap(), Some(Bytes::from("v2")));
+
+    replica.catch_up(&mut source).unwrap();
+    let stats = replica.stats();
+    assert_eq!(stats.lag(), 0, "{:?}", stats);
+    let mut iter = replica
+        .scan(std::ops::Bound::Unbounded, std::ops::Bound::Unbounded)
+        .unwrap();
+    for i in 0..=1000 {
+        assert!(iter.is_valid());
+        assert_eq!(iter.key(), key_of(i));
+        assert_eq!(iter.value(), b"v3");
+        iter.next().unwrap();
+    }
+    assert!(!iter.is_valid());
+    drop(iter);
+
+    // SSTs compacted away on the leader are removed from the replica.
+    let sst_files = |dir: &std::path::Path| {
+        let mut files = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .filter(|name| name.ends_with(".sst"))
+            .collect::<Vec<_>>();
+        files.sort();
+        files
+    };
+    assert_eq!(sst_files(leader_dir.path()), sst_files(replica_dir.path()));
+
+    // Nothing new is sent if the leader is idle.
+    let batch = source.poll().unwrap();
+    assert!(batch.events.is_empty(), "{:?}", batch.events);
+
+    // The replica can be promoted once the leader is gone.
+    leader.close().unwrap();
+    drop(source);
+    drop(replica);
+    let promoted = MiniLsm::open(&replica_dir, options()).unwrap();
+    assert_eq!(promoted.get(&key_of(0)).unwrap(), Some(Bytes::from("v3")));
+    assert_eq!(
+        promoted.get(&key_of(1000)).unwrap(),
+        Some(Bytes::from("v3"))
+    );
+    promoted.put(&key_of(0), b"v4").unwrap();
+    assert_eq!(promoted.get(&key_of(0)).unwrap(), Some(Bytes::from("v4")));
+}
+
+#[test]
+fn test_replica_requires_empty_dir() {
+    let dir = tempdir().unwrap();
+    std::fs::write(dir.path().join("MANIFEST"), b"").unwrap();
+    assert!(matches!(
+        Replica::create(&dir, options()),
+        Err(Error::InvalidArgument(_))
+    ));
+}
@@ -0,0 +1,194 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    resp_server::{self, RespValue, handle_command, read_command},
+};
+
+fn command(args: &[&str]) -> Vec<Vec<u8>> {
+    args.iter().map(|arg| arg.as_bytes().to_vec()).collect()
+}
+
+fn bulk(data: &str) -> RespValue {
+    RespValue::Bulk(Some(data.as_bytes().to_vec()))
+}
+
+#[test]
+fn test_resp_read_command() {
+    let mut input: &[u8] = b"*3\r\n$3\r\nSET\r\n$4\r\na\r\nb\r\n$0\r\n\r\nPING  hello\r\nGET k\n";
+    assert_eq!(
+        read_command(&mut input).unwrap(),
+        Some(vec![b"SET".to_vec(), b"a\r\nb".to_vec(), Vec::new()])
+    );
+    assert_eq!(
+        read_command(&mut input).unwrap(),
+        Some(command(&["PING", "hello"]))
+    );
+    assert_eq!(
+        read_command(&mut input).unwrap(),
+        Some(command(&["GET", "k"]))
+    );
+    assert_eq!(read_command(&mut input).unwrap(), None);
+
+    let mut input: &[u8] = b"*1\r\n$3\r\nGETX\r\n";
+    assert!(read_command(&mut input).is_err());
+    let mut input: &[u8] = b"*2\r\n$3\r\nGET\r\n";
+    assert!(read_command(&mut input).is_err());
+}
+
+#[test]
+fn test_resp_commands() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+
+    assert_eq!(
+        handle_command(&storage, &command(&["ping"])),
+        RespValue::Simple("PONG".to_string())
+    );
+    assert_eq!(
+        handle_command(&storage, &command(&["GET", "a"])),
+        RespValue::Bulk(None)
+    );
+    for (key, value) in [
+        ("a", "1"),
+        ("b", "2"),
+        ("c", "3"),
+        ("user:1", "x"),
+        ("user:2", "y"),
+    ] {
+        assert_eq!(
+            handle_command(&storage, &command(&["SET", key, value])),
+            RespValue::Simple("OK".to_string())
+        );
+    }
+    assert_eq!(handle_command(&storage, &command(&["GET", "a"])), bulk("1"));
+    assert_eq!(
+        handle_command(&storage, &command(&["MGET", "a", "z", "c"])),
+        RespValue::Array(vec![bulk("1"), RespValue::Bulk(None), bulk("3")])
+    );
+    assert_eq!(
+        handle_command(&storage, &command(&["EXISTS", "a", "b", "z"])),
+        RespValue::Integer(2)
+    );
+    assert_eq!(
+        handle_command(&storage, &command(&["DEL", "b", "z"])),
+        RespValue::Integer(1)
+    );
+    assert_eq!(
+        handle_command(&storage, &command(&["GET", "b"])),
+        RespValue::Bulk(None)
+    );
+
+    // Scan with a small page size until the cursor wraps around to 0.
+    let mut cursor = "0".to_string();
+    let mut keys = Vec::new();
+    loop {
+        let RespValue::Array(reply) =
+            handle_command(&storage, &command(&["SCAN", &cursor, "COUNT", "2"]))
+        else {
+            panic!("unexpected reply");
+        };
+        let [RespValue::Bulk(Some(next)), RespValue::Array(page)] = reply.as_slice() else {
+            panic!("unexpected reply {:?}", reply);
+        };
+        keys.extend(page.iter().cloned());
+        cursor = String::from_utf8(next.clone()).unwrap();
+        if cursor == "0" {
+            break;
+        }
+    }
+    assert_eq!(
+        keys,
+        vec![bulk("a"), bulk("c"), bulk("user:1"), bulk("user:2")]
+    );
+    assert_eq!(
+        handle_command(
+            &storage,
+            &command(&["SCAN", "0", "MATCH", "user:*", "COUNT", "100"])
+        ),
+        RespValue::Array(vec![
+            bulk("0"),
+            RespValue::Array(vec![bulk("user:1"), bulk("user:2")])
+        ])
+    );
+    assert_eq!(
+        handle_command(
+            &storage,
+            &command(&["SCAN", "0", "MATCH", "?", "COUNT", "100"])
+        ),
+        RespValue::Array(vec![
+            bulk("0"),
+            RespValue::Array(vec![bulk("a"), bulk("c")])
+        ])
+    );
+
+    for args in [
+        &["SET", "a"][..],
+        &["GET"],
+        &["SCAN", "x"],
+        &["SCAN", "0", "COUNT"],
+        &["SCAN", "0", "COUNT", "0"],
+        &["FLUSHALL"],
+        // An empty value is rejected by the engine.
+        &["SET", "a", ""],
+    ] {
+        assert!(
+            matches!(
+                handle_command(&storage, &command(args)),
+                RespValue::Error(_)
+            ),
+            "{:?}",
+            args
+        );
+    }
+}
+
+#[test]
+fn test_resp_server() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    drop(listener);
+    {
+        let addr = addr.clone();
+        std::thread::spawn(move || resp_server::serve(storage, &addr));
+    }
+    let mut stream = (0..100)
+        .find_map(|_| {
+            TcpStream::connect(&addr)
+                .inspect_err(|_| std::thread::sleep(std::time::Duration::from_millis(10)))
+                .ok()
+        })
+        .unwrap();
+
+    // Pipeline a few commands, and expect the replies in order.
+    stream
+        .write_all(b"*3\r\n$3\r\nSET\r\n$5\r\nhello\r\n$5\r\nworld\r\n*2\r\n$3\r\nGET\r\n$5\r\nhello\r\nGET missing\r\nQUIT\r\n")
+        .unwrap();
+    let lines = BufReader::new(stream)
+        .lines()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(lines, vec!["+OK", "$5", "world", "$-1", "+OK"]);
+}
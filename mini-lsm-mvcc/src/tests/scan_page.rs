@@ -0,0 +1,128 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::ops::Bound;
+use std::time::Duration;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::lsm_error::Error;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+
+fn key_of(i: usize) -> Bytes {
+    Bytes::from(format!("key{i:03}"))
+}
+
+#[test]
+fn test_scan_pages() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    for i in 0..25 {
+        storage.put(&key_of(i), b"1").unwrap();
+    }
+    let upper = Bound::Excluded(&b"key020"[..]);
+    let page = storage
+        .scan_page(Bound::Included(b"key003"), upper, 10)
+        .unwrap();
+    assert_eq!(page.entries.len(), 10);
+    assert_eq!(page.entries[0].0, key_of(3));
+
+    let mut entries = page.entries;
+    let mut continuation = page.continuation;
+    while let Some(token) = continuation {
+        let page = storage.scan_next_page(&token, upper, 10).unwrap();
+        entries.extend(page.entries);
+        continuation = page.continuation;
+    }
+    assert_eq!(
+        entries,
+        (3..20)
+            .map(|i| (key_of(i), Bytes::from("1")))
+            .collect::<Vec<_>>()
+    );
+
+    // A page that ends the range exactly has no continuation
+    let page = storage
+        .scan_page(Bound::Included(b"key015"), upper, 5)
+        .unwrap();
+    assert!(page.continuation.is_none());
+
+    assert!(matches!(
+        storage.scan_page(Bound::Unbounded, Bound::Unbounded, 0),
+        Err(Error::InvalidArgument(_))
+    ));
+    assert!(matches!(
+        storage.scan_next_page(b"bad", Bound::Unbounded, 10),
+        Err(Error::InvalidArgument(_))
+    ));
+}
+
+#[test]
+fn test_scan_pages_read_one_snapshot() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    for i in 0..20 {
+        storage.put(&key_of(i), b"1").unwrap();
+    }
+    let page = storage
+        .scan_page(Bound::Unbounded, Bound::Unbounded, 5)
+        .unwrap();
+
+    // Later writes, even once compacted, do not show up in the following pages
+    for i in 0..20 {
+        storage.put(&key_of(i), b"2").unwrap();
+    }
+    storage.delete(&key_of(10)).unwrap();
+    storage.put(&key_of(100), b"2").unwrap();
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+
+    let page = storage
+        .scan_next_page(&page.continuation.unwrap(), Bound::Unbounded, 100)
+        .unwrap();
+    assert_eq!(
+        page.entries,
+        (5..20)
+            .map(|i| (key_of(i), Bytes::from("1")))
+            .collect::<Vec<_>>()
+    );
+    assert!(page.continuation.is_none());
+}
+
+#[test]
+fn test_scan_page_lease_expires() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::builder()
+        .scan_page_ttl(Duration::ZERO)
+        .build()
+        .unwrap();
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for i in 0..20 {
+        storage.put(&key_of(i), b"1").unwrap();
+    }
+    let page = storage
+        .scan_page(Bound::Unbounded, Bound::Unbounded, 5)
+        .unwrap();
+    storage.inner.expire_page_leases();
+    assert_eq!(storage.inner.mvcc().ts.lock().1.num_retained_snapshots(), 0);
+    storage.put(&key_of(0), b"2").unwrap();
+    // The versions read by the page are garbage-collected by the next compaction
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    assert!(matches!(
+        storage.scan_next_page(&page.continuation.unwrap(), Bound::Unbounded, 5),
+        Err(Error::InvalidArgument(_))
+    ));
+    storage.close().unwrap();
+}
@@ -0,0 +1,73 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::ops::Bound;
+
+use tempfile::tempdir;
+
+use crate::{
+    iterators::StorageIterator,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+fn scan_count(storage: &MiniLsm, lower: &[u8], upper: &[u8]) -> usize {
+    let mut iter = storage
+        .scan(Bound::Included(lower), Bound::Included(upper))
+        .unwrap();
+    let mut cnt = 0;
+    while iter.is_valid() {
+        assert!(iter.key() <= upper);
+        cnt += 1;
+        iter.next().unwrap();
+    }
+    cnt
+}
+
+#[test]
+fn test_scan_does_not_read_blocks_past_upper_bound() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::builder()
+        .block_size(256)
+        .target_sst_size(4096)
+        .build()
+        .unwrap();
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for i in 0..1000 {
+        storage
+            .put(format!("key{i:04}").as_bytes(), b"value")
+            .unwrap();
+    }
+    storage.force_flush().unwrap();
+
+    // an L0 SST stops at the last key of its first block, without reading the second block
+    let snapshot = storage.inner.snapshot();
+    let sst = snapshot.sstables[&snapshot.l0_sstables[0]].clone();
+    assert!(sst.num_of_blocks() > 2);
+    let upper = sst.block_meta[0].last_key.key_ref().to_vec();
+    assert!(scan_count(&storage, b"key", &upper) > 0);
+    assert!(sst.cached_block(0).is_some());
+    assert!(sst.cached_block(1).is_none());
+
+    // a level stops at the last key of its first SST, without opening the second one
+    storage.force_full_compaction().unwrap();
+    let snapshot = storage.inner.snapshot();
+    let level = &snapshot.levels[0].1;
+    assert!(level.len() > 1);
+    let (first, second) = (&snapshot.sstables[&level[0]], &snapshot.sstables[&level[1]]);
+    let last_block = first.num_of_blocks() - 1;
+    let lower = first.block_meta[last_block].first_key.key_ref().to_vec();
+    let upper = first.last_key().key_ref().to_vec();
+    assert!(scan_count(&storage, &lower, &upper) > 0);
+    assert!(first.cached_block(last_block).is_some());
+    assert!((0..second.num_of_blocks()).all(|idx| second.cached_block(idx).is_none()));
+}
@@ -0,0 +1,99 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::ops::Bound;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    iterators::StorageIterator,
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MiniLsm, ReadOptions},
+};
+
+fn key_of(idx: usize) -> Vec<u8> {
+    format!("key_{:05}", idx).into_bytes()
+}
+
+fn value_of(idx: usize) -> Vec<u8> {
+    format!("value_{:05}", idx).into_bytes()
+}
+
+#[test]
+fn test_scan_releases_iterators_every_max_keys_per_poll() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for idx in 0..100 {
+        storage.put(&key_of(idx), &value_of(idx)).unwrap();
+        if idx % 25 == 24 {
+            storage.force_flush().unwrap();
+        }
+    }
+    storage.put(&key_of(100), &value_of(100)).unwrap();
+
+    let mut iter = storage
+        .scan_with_options(
+            Bound::Unbounded,
+            Bound::Unbounded,
+            &ReadOptions {
+                max_keys_per_poll: Some(10),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    let active_before = iter.num_active_iterators();
+    // changes after the scan started stay invisible to it after its iterators are recreated
+    storage.delete(&key_of(50)).unwrap();
+    storage.put(&key_of(101), &value_of(101)).unwrap();
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+
+    let mut cnt = 0;
+    while iter.is_valid() {
+        assert_eq!(iter.key(), key_of(cnt));
+        assert_eq!(iter.value(), value_of(cnt));
+        cnt += 1;
+        iter.next().unwrap();
+        if cnt == 20 {
+            // the memtables and L0 SSTs the scan started with were replaced by a single compacted SST
+            assert!(iter.num_active_iterators() < active_before);
+        }
+    }
+    assert_eq!(cnt, 101);
+}
+
+#[test]
+fn test_cancel_scan() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for idx in 0..10 {
+        storage.put(&key_of(idx), &value_of(idx)).unwrap();
+    }
+
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    iter.next().unwrap();
+    let handle = iter.cancel_handle();
+    assert!(!handle.is_cancelled());
+    std::thread::spawn(move || handle.cancel()).join().unwrap();
+    assert!(iter.cancel_handle().is_cancelled());
+    assert_eq!(iter.key(), key_of(1));
+    assert_eq!(iter.value(), Bytes::from(value_of(1)));
+    let err = iter.next().unwrap_err();
+    assert!(matches!(Error::from(err), Error::Cancelled));
+    assert!(!iter.is_valid());
+    assert!(iter.next().is_err());
+}
@@ -0,0 +1,112 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::time::{Duration, Instant};
+
+use tempfile::tempdir;
+
+use crate::compact::{CompactionOptions, SimpleLeveledCompactionOptions};
+use crate::lsm_error::Error;
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+
+fn fill_and_corrupt(path: &std::path::Path, options: LsmStorageOptions) -> (usize, usize) {
+    let storage = MiniLsm::open(path, options).unwrap();
+    for i in 0..1000 {
+        storage
+            .put(format!("key{i:04}").as_bytes(), b"value")
+            .unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.put(b"key", b"value").unwrap();
+    storage.force_flush().unwrap();
+    let l0_sstables = storage.inner.state.read().l0_sstables.clone();
+    storage.close().unwrap();
+
+    let sst_path = path.join(format!("{:05}.sst", l0_sstables[1]));
+    let mut data = std::fs::read(&sst_path).unwrap();
+    data[0] ^= 0xff;
+    std::fs::write(&sst_path, data).unwrap();
+    (l0_sstables[1], l0_sstables[0])
+}
+
+#[test]
+fn test_scrub_quarantines_corrupt_sst() {
+    let dir = tempdir().unwrap();
+    let (corrupt_id, good_id) =
+        fill_and_corrupt(dir.path(), LsmStorageOptions::default_for_week1_test());
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+
+    // The damaged first block of the older SST quarantines it, and the rest of the pass goes over the newer one
+    assert!(storage.inner.scrub_next_block().unwrap() > 0);
+    let report = storage.scrub_report();
+    assert!(report.quarantined.contains_key(&corrupt_id));
+    assert!(!report.quarantined.contains_key(&good_id));
+    assert_eq!(report.passes, 0);
+    assert!(storage.inner.scrub_next_block().unwrap() > 0);
+    assert!(storage.inner.scrub_next_block().unwrap() > 0);
+    let report = storage.scrub_report();
+    assert_eq!(report.passes, 1);
+    assert_eq!(report.quarantined.len(), 1);
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_scrub_thread() {
+    let dir = tempdir().unwrap();
+    let (corrupt_id, _) = fill_and_corrupt(dir.path(), LsmStorageOptions::default_for_week1_test());
+    let options = LsmStorageOptions::builder()
+        .scrub_bytes_per_sec(1 << 30)
+        .build()
+        .unwrap();
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let start = Instant::now();
+    while storage.scrub_report().passes < 2 {
+        assert!(start.elapsed() < Duration::from_secs(10), "scrub is stuck");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let report = storage.scrub_report();
+    assert_eq!(
+        report.quarantined.keys().copied().collect::<Vec<_>>(),
+        vec![corrupt_id]
+    );
+    assert!(report.bytes_scrubbed > 0);
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_quarantined_sst_blocks_compaction() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::builder()
+        .compaction_options(CompactionOptions::Simple(SimpleLeveledCompactionOptions {
+            size_ratio_percent: 200,
+            level0_file_num_compaction_trigger: 2,
+            max_levels: 3,
+        }))
+        .deterministic_seed(0)
+        .build()
+        .unwrap();
+    let (corrupt_id, _) = fill_and_corrupt(dir.path(), options.clone());
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.inner.scrub_next_block().unwrap();
+    assert!(storage.scrub_report().quarantined.contains_key(&corrupt_id));
+
+    // The L0 compaction would rewrite the quarantined SST, so it is reported as stuck
+    let Err(Error::Corruption(msg)) = storage.tick() else {
+        panic!("expected compaction to be blocked");
+    };
+    assert!(
+        msg.contains(&format!("quarantined SST {corrupt_id}")),
+        "{msg}"
+    );
+    assert_eq!(storage.inner.state.read().l0_sstables.len(), 2);
+}
@@ -0,0 +1,193 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::time::{Duration, Instant};
+
+use tempfile::tempdir;
+
+use crate::{
+    compact::{CompactionOptions, SimpleLeveledCompactionOptions},
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MiniLsm, OptionsDelta},
+};
+
+fn simple_options() -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::Simple(
+        SimpleLeveledCompactionOptions {
+            size_ratio_percent: 200,
+            level0_file_num_compaction_trigger: 2,
+            max_levels: 2,
+        },
+    ));
+    options.level0_file_num_compaction_trigger = Some(100);
+    options
+}
+
+fn wait_until(what: &str, mut done: impl FnMut() -> bool) {
+    let start = Instant::now();
+    while !done() {
+        assert!(start.elapsed() < Duration::from_secs(10), "{what}");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn test_set_compaction_trigger() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, simple_options()).unwrap();
+    for i in 0..4 {
+        storage.put(format!("key{i}").as_bytes(), b"value").unwrap();
+        storage.force_flush().unwrap();
+    }
+    assert!(storage.compaction_plan().task.is_none());
+    assert_eq!(storage.inner.state.read().l0_sstables.len(), 4);
+
+    storage
+        .set_options(&OptionsDelta {
+            level0_file_num_compaction_trigger: Some(2),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(
+        storage.options().level0_file_num_compaction_trigger,
+        Some(2)
+    );
+    // the compaction thread picks up the new trigger without reopening
+    wait_until("L0 was not compacted", || {
+        storage.inner.state.read().l0_sstables.is_empty()
+    });
+    for i in 0..4 {
+        assert_eq!(
+            storage.get(format!("key{i}").as_bytes()).unwrap().unwrap(),
+            "value"
+        );
+    }
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_set_memtable_limit() {
+    let dir = tempdir().unwrap();
+    let mut options = simple_options();
+    options.num_memtable_limit = 100;
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for i in 0..3 {
+        storage.put(format!("key{i}").as_bytes(), b"value").unwrap();
+        storage
+            .inner
+            .force_freeze_memtable(&storage.inner.state_lock.lock())
+            .unwrap();
+    }
+    std::thread::sleep(Duration::from_millis(200));
+    assert_eq!(storage.inner.state.read().imm_memtables.len(), 3);
+
+    storage
+        .set_options(&OptionsDelta {
+            num_memtable_limit: Some(1),
+            ..Default::default()
+        })
+        .unwrap();
+    wait_until("memtables were not flushed", || {
+        storage.inner.state.read().imm_memtables.is_empty()
+    });
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_set_invalid_options() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, simple_options()).unwrap();
+    let before = storage.options();
+    for delta in [
+        OptionsDelta {
+            num_memtable_limit: Some(5),
+            level0_file_num_compaction_trigger: Some(0),
+            ..Default::default()
+        },
+        OptionsDelta {
+            delete_compaction_ratio: Some(Some(1.5)),
+            ..Default::default()
+        },
+        // scrubbing was not enabled when opening
+        OptionsDelta {
+            scrub_bytes_per_sec: Some(Some(1 << 20)),
+            ..Default::default()
+        },
+    ] {
+        assert!(matches!(
+            storage.set_options(&delta),
+            Err(Error::InvalidArgument(_))
+        ));
+        // nothing of a rejected change is applied
+        assert_eq!(
+            storage.options().num_memtable_limit,
+            before.num_memtable_limit
+        );
+        assert_eq!(
+            storage.options().level0_file_num_compaction_trigger,
+            before.level0_file_num_compaction_trigger
+        );
+    }
+
+    storage
+        .set_options(&OptionsDelta {
+            periodic_compaction_ttl: Some(Some(Duration::from_secs(3600))),
+            delete_compaction_ratio: Some(Some(0.5)),
+            ..Default::default()
+        })
+        .unwrap();
+    let options = storage.options();
+    assert_eq!(
+        options.periodic_compaction_ttl,
+        Some(Duration::from_secs(3600))
+    );
+    assert_eq!(options.delete_compaction_ratio, Some(0.5));
+    storage
+        .set_options(&OptionsDelta {
+            periodic_compaction_ttl: Some(None),
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(storage.options().periodic_compaction_ttl, None);
+    assert_eq!(storage.options().delete_compaction_ratio, Some(0.5));
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_set_scrub_rate() {
+    let dir = tempdir().unwrap();
+    let mut options = simple_options();
+    options.scrub_bytes_per_sec = Some(1);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    // at 1 byte per second, the scrubber would not get past the first block within the test
+    storage
+        .set_options(&OptionsDelta {
+            scrub_bytes_per_sec: Some(Some(1 << 30)),
+            ..Default::default()
+        })
+        .unwrap();
+    for i in 0..3 {
+        storage.put(format!("key{i}").as_bytes(), b"value").unwrap();
+        storage.force_flush().unwrap();
+    }
+    wait_until("the SSTs were not scrubbed", || {
+        storage.scrub_report().passes > 1
+    });
+    storage
+        .set_options(&OptionsDelta {
+            scrub_bytes_per_sec: Some(None),
+            ..Default::default()
+        })
+        .unwrap();
+    storage.close().unwrap();
+}
@@ -0,0 +1,104 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::collections::HashSet;
+use std::ops::Bound;
+use std::sync::Arc;
+
+use tempfile::tempdir;
+
+use crate::{
+    block::BlockBuilder,
+    block_cache::BlockCache,
+    iterators::StorageIterator,
+    key::KeySlice,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+#[test]
+fn test_blocks_spread_across_shards() {
+    let cache = BlockCache::new(1024, 8);
+    assert_eq!(cache.num_shards(), 8);
+    let mut builder = BlockBuilder::new(4096);
+    assert!(builder.add(KeySlice::for_testing_from_slice_no_ts(b"key"), b"value"));
+    let block = Arc::new(builder.build());
+
+    let mut shards = HashSet::new();
+    for sst_id in 0..4 {
+        for block_idx in 0..64 {
+            let shard = cache.shard_of(sst_id, block_idx);
+            assert_eq!(shard, cache.shard_of(sst_id, block_idx));
+            shards.insert(shard);
+            cache.insert((sst_id, block_idx), block.clone());
+        }
+    }
+    assert_eq!(shards.len(), 8);
+    for sst_id in 0..4 {
+        for block_idx in 0..64 {
+            assert!(cache.get(&(sst_id, block_idx)).is_some());
+        }
+    }
+    assert!(cache.get(&(4, 0)).is_none());
+    assert_eq!(cache.iter().count(), 4 * 64);
+
+    let loaded = cache
+        .try_get_with::<()>((4, 0), || Ok(block.clone()))
+        .unwrap();
+    assert!(Arc::ptr_eq(&loaded, &block));
+    assert!(cache.try_get_with((5, 0), || Err(())).is_err());
+    assert!(cache.get(&(5, 0)).is_none());
+}
+
+#[test]
+fn test_storage_reads_through_sharded_cache() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::builder()
+        .block_size(256)
+        .block_cache_shards(4)
+        .build()
+        .unwrap();
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for i in 0..1000 {
+        storage
+            .put(format!("key{i:04}").as_bytes(), b"value")
+            .unwrap();
+    }
+    storage.force_flush().unwrap();
+
+    let cache = &storage.inner.block_cache;
+    assert_eq!(cache.num_shards(), 4);
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    let mut cnt = 0;
+    while iter.is_valid() {
+        cnt += 1;
+        iter.next().unwrap();
+    }
+    assert_eq!(cnt, 1000);
+    let shards = cache
+        .iter()
+        .map(|(key, _)| cache.shard_of(key.0, key.1))
+        .collect::<HashSet<_>>();
+    assert!(shards.len() > 1, "blocks should land in several shards");
+    assert_eq!(
+        storage.get(b"key0500").unwrap(),
+        Some(bytes::Bytes::from("value"))
+    );
+
+    assert!(
+        LsmStorageOptions::builder()
+            .block_cache_shards(0)
+            .build()
+            .is_err()
+    );
+    storage.close().unwrap();
+}
@@ -0,0 +1,263 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Checks that scans and transactions observe a consistent snapshot while flushes and compactions swap the state
+//! under them. Writers move balances between accounts in atomic batches, so every snapshot holds the same total.
+
+use std::ops::Bound;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tempfile::tempdir;
+
+use crate::{
+    compact::{CompactionOptions, LeveledCompactionOptions, TieredCompactionOptions},
+    iterators::StorageIterator,
+    lsm_storage::{LsmStorageOptions, MiniLsm, WriteBatchRecord},
+};
+
+const NUM_ACCOUNTS: usize = 200;
+const INITIAL_BALANCE: u64 = 100;
+
+fn key_of(i: usize) -> Vec<u8> {
+    format!("account{i:04}").into_bytes()
+}
+
+fn balance_of(value: &[u8]) -> u64 {
+    u64::from_be_bytes(value.try_into().unwrap())
+}
+
+/// The threads of a run and how much work each does, all derived from `seed` so that a failure can be replayed.
+struct Harness {
+    seed: u64,
+    writers: usize,
+    transfers_per_writer: usize,
+    scanners: usize,
+    /// Whether a thread keeps flushing the memtables, also forcing full compactions if the options have no background
+    /// compaction.
+    flusher: bool,
+}
+
+impl Harness {
+    fn run(&self, options: LsmStorageOptions) {
+        let dir = tempdir().unwrap();
+        let full_compaction = matches!(options.compaction_options, CompactionOptions::NoCompaction);
+        let storage = MiniLsm::open(&dir, options).unwrap();
+        for i in 0..NUM_ACCOUNTS {
+            storage
+                .put(&key_of(i), &INITIAL_BALANCE.to_be_bytes())
+                .unwrap();
+        }
+        storage.force_flush().unwrap();
+
+        let done = Arc::new(AtomicBool::new(false));
+        let writers = (0..self.writers)
+            .map(|thread| {
+                let storage = storage.clone();
+                let mut rng = StdRng::seed_from_u64(self.seed + thread as u64);
+                let transfers = self.transfers_per_writer;
+                std::thread::spawn(move || {
+                    for _ in 0..transfers {
+                        transfer(&storage, &mut rng);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        let flusher = self.flusher.then(|| {
+            let storage = storage.clone();
+            let done = done.clone();
+            let mut rng = StdRng::seed_from_u64(self.seed + 1000);
+            std::thread::spawn(move || {
+                while !done.load(Ordering::SeqCst) {
+                    storage.force_flush().unwrap();
+                    if full_compaction && rng.gen_bool(0.3) {
+                        storage.force_full_compaction().unwrap();
+                    }
+                    std::thread::sleep(Duration::from_millis(rng.gen_range(1..5)));
+                }
+            })
+        });
+        let scanners = (0..self.scanners)
+            .map(|thread| {
+                let storage = storage.clone();
+                let done = done.clone();
+                let mut rng = StdRng::seed_from_u64(self.seed + 2000 + thread as u64);
+                std::thread::spawn(move || {
+                    let mut rounds = 0;
+                    while !done.load(Ordering::SeqCst) || rounds == 0 {
+                        check_long_scan(&storage, &mut rng);
+                        check_repeatable_reads(&storage, &mut rng);
+                        rounds += 1;
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        done.store(true, Ordering::SeqCst);
+        for thread in scanners.into_iter().chain(flusher) {
+            thread.join().unwrap();
+        }
+        assert_eq!(scan_total(&storage), NUM_ACCOUNTS as u64 * INITIAL_BALANCE);
+        storage.close().unwrap();
+    }
+}
+
+/// Move part of the balance of an account to another one in a batch, deleting the account once it is empty.
+fn transfer(storage: &MiniLsm, rng: &mut StdRng) {
+    // Serialize the read-modify-write with a transaction over the two accounts
+    loop {
+        let from = rng.gen_range(0..NUM_ACCOUNTS);
+        let to = (from + rng.gen_range(1..NUM_ACCOUNTS)) % NUM_ACCOUNTS;
+        let txn = storage.new_txn().unwrap();
+        let balance = |key: &[u8]| txn.get(key).unwrap().map_or(0, |x| balance_of(&x));
+        let (from_balance, to_balance) = (balance(&key_of(from)), balance(&key_of(to)));
+        if from_balance == 0 {
+            continue;
+        }
+        let amount = rng.gen_range(1..=from_balance);
+        let from_record = if amount == from_balance {
+            WriteBatchRecord::Del(key_of(from))
+        } else {
+            WriteBatchRecord::Put(key_of(from), (from_balance - amount).to_be_bytes().to_vec())
+        };
+        let to_record =
+            WriteBatchRecord::Put(key_of(to), (to_balance + amount).to_be_bytes().to_vec());
+        for record in [from_record, to_record] {
+            match record {
+                WriteBatchRecord::Put(key, value) => txn.put(&key, &value),
+                WriteBatchRecord::Del(key) => txn.delete(&key),
+            }
+        }
+        if txn.commit().is_ok() {
+            return;
+        }
+    }
+}
+
+fn scan_total(storage: &MiniLsm) -> u64 {
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    let mut total = 0;
+    while iter.is_valid() {
+        total += balance_of(iter.value());
+        iter.next().unwrap();
+    }
+    total
+}
+
+/// Scan all accounts slowly, so that flushes and compactions finish while the scan is open.
+fn check_long_scan(storage: &MiniLsm, rng: &mut StdRng) {
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    let mut total = 0;
+    let mut last_key: Option<Vec<u8>> = None;
+    while iter.is_valid() {
+        assert!(
+            last_key.as_deref().is_none_or(|last| last < iter.key()),
+            "scan went backwards or repeated a key"
+        );
+        assert!(
+            balance_of(iter.value()) > 0,
+            "scan returned a deleted account"
+        );
+        total += balance_of(iter.value());
+        last_key = Some(iter.key().to_vec());
+        if rng.gen_bool(0.05) {
+            std::thread::sleep(Duration::from_micros(rng.gen_range(100..1000)));
+        }
+        iter.next().unwrap();
+    }
+    assert_eq!(total, NUM_ACCOUNTS as u64 * INITIAL_BALANCE);
+}
+
+/// Read the same accounts twice in a transaction, with the state likely swapped in between.
+fn check_repeatable_reads(storage: &MiniLsm, rng: &mut StdRng) {
+    let txn = storage.new_txn().unwrap();
+    let keys = (0..10)
+        .map(|_| key_of(rng.gen_range(0..NUM_ACCOUNTS)))
+        .collect::<Vec<_>>();
+    let first = keys
+        .iter()
+        .map(|key| txn.get(key).unwrap())
+        .collect::<Vec<_>>();
+    std::thread::sleep(Duration::from_millis(rng.gen_range(1..5)));
+    let second = keys
+        .iter()
+        .map(|key| txn.get(key).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(first, second);
+}
+
+fn small_options(compaction_options: CompactionOptions) -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(compaction_options);
+    options.block_size = 256;
+    options.target_sst_size = 2048;
+    options.num_memtable_limit = 4;
+    options.serializable = true;
+    options
+}
+
+#[test]
+fn test_snapshot_consistency_forced_compaction() {
+    Harness {
+        seed: 421,
+        writers: 4,
+        transfers_per_writer: 300,
+        scanners: 2,
+        flusher: true,
+    }
+    .run(small_options(CompactionOptions::NoCompaction));
+}
+
+#[test]
+fn test_snapshot_consistency_leveled() {
+    Harness {
+        seed: 7,
+        writers: 4,
+        transfers_per_writer: 300,
+        scanners: 2,
+        flusher: false,
+    }
+    .run(small_options(CompactionOptions::Leveled(
+        LeveledCompactionOptions {
+            level0_file_num_compaction_trigger: 2,
+            max_levels: 3,
+            base_level_size_mb: 1,
+            level_size_multiplier: 2,
+        },
+    )));
+}
+
+#[test]
+fn test_snapshot_consistency_tiered() {
+    Harness {
+        seed: 99,
+        writers: 4,
+        transfers_per_writer: 300,
+        scanners: 2,
+        flusher: true,
+    }
+    .run(small_options(CompactionOptions::Tiered(
+        TieredCompactionOptions {
+            num_tiers: 3,
+            max_size_amplification_percent: 200,
+            size_ratio: 1,
+            min_merge_width: 2,
+            max_merge_width: None,
+        },
+    )));
+}
@@ -0,0 +1,76 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::ops::Bound;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+use super::harness::check_lsm_iter_result_by_key;
+
+#[test]
+fn test_snapshot_reads_are_consistent() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.put(b"b", b"1").unwrap();
+
+    let snapshot = storage.snapshot();
+    storage.put(b"a", b"2").unwrap();
+    storage.delete(b"b").unwrap();
+    storage.put(b"c", b"2").unwrap();
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+
+    assert_eq!(snapshot.get(b"a").unwrap(), Some(Bytes::from("1")));
+    assert_eq!(snapshot.get(b"b").unwrap(), Some(Bytes::from("1")));
+    assert!(!snapshot.contains(b"c").unwrap());
+    let mut iter = snapshot.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    check_lsm_iter_result_by_key(
+        &mut iter,
+        vec![
+            (Bytes::from("a"), Bytes::from("1")),
+            (Bytes::from("b"), Bytes::from("1")),
+        ],
+    );
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from("2")));
+    assert_eq!(storage.get(b"b").unwrap(), None);
+}
+
+#[test]
+fn test_snapshot_pins_watermark() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    let mvcc = storage.inner.mvcc();
+
+    let snapshot = storage.snapshot();
+    let read_ts = snapshot.read_ts();
+    assert_eq!(read_ts, mvcc.latest_commit_ts());
+    storage.put(b"a", b"2").unwrap();
+    assert_eq!(mvcc.watermark(), read_ts);
+
+    // A scan keeps the timestamp pinned after the snapshot is gone.
+    let iter = snapshot.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    drop(snapshot);
+    assert_eq!(mvcc.watermark(), read_ts);
+    drop(iter);
+    assert_eq!(mvcc.watermark(), mvcc.latest_commit_ts());
+}
@@ -0,0 +1,69 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use tempfile::tempdir;
+
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+
+#[test]
+fn test_space_report() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    for i in 0..100 {
+        storage
+            .put(format!("key{i:03}").as_bytes(), &[b'v'; 100])
+            .unwrap();
+    }
+    storage.force_flush().unwrap();
+    // With only L0, all of it is considered live
+    let report = storage.space_report().unwrap();
+    assert_eq!(report.levels[0].num_sstables, 1);
+    assert_eq!(report.live_data_size, report.sst_size);
+    assert_eq!(report.garbage_size, 0);
+    assert!(report.total_disk_size >= report.sst_size);
+
+    // Keep the old versions and tombstones across the compaction
+    let txn = storage.new_txn().unwrap();
+    for i in 0..50 {
+        storage.delete(format!("key{i:03}").as_bytes()).unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    let report = storage.space_report().unwrap();
+    assert_eq!(report.levels[0].size, 0);
+    let bottom = &report.levels[1];
+    assert_eq!(bottom.num_sstables, 1);
+    assert_eq!(bottom.tombstones, 50);
+    assert_eq!(report.tombstones, 50);
+    assert!(bottom.obsolete_size > 0);
+    assert_eq!(report.live_data_size, bottom.size - bottom.obsolete_size);
+    assert_eq!(report.garbage_size, bottom.obsolete_size);
+
+    // Overwrites in L0 are garbage until compacted into the bottom level
+    drop(txn);
+    for i in 50..100 {
+        storage
+            .put(format!("key{i:03}").as_bytes(), &[b'w'; 100])
+            .unwrap();
+    }
+    storage.force_flush().unwrap();
+    let report = storage.space_report().unwrap();
+    assert!(report.garbage_size >= report.levels[0].size);
+    assert!(report.space_amplification() > 1.0);
+
+    storage.force_full_compaction().unwrap();
+    let report = storage.space_report().unwrap();
+    assert_eq!(report.tombstones, 0);
+    assert_eq!(report.garbage_size, 0);
+    assert_eq!(report.live_data_size, report.sst_size);
+}
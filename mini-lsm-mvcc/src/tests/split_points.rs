@@ -0,0 +1,95 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+fn key_of(i: usize) -> String {
+    format!("key{i:05}")
+}
+
+/// The number of keys below `split`, among `key_of(0..n)`.
+fn rank(split: &[u8], n: usize) -> usize {
+    (0..n).filter(|&i| key_of(i).as_bytes() < split).count()
+}
+
+#[test]
+fn test_split_points_uniform() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert!(storage.suggest_split_points(4).unwrap().is_empty());
+    assert!(matches!(
+        storage.suggest_split_points(0),
+        Err(Error::InvalidArgument(_))
+    ));
+
+    for i in 0..2000 {
+        storage.put(key_of(i).as_bytes(), b"0123456789").unwrap();
+        if i % 500 == 499 {
+            storage.force_flush().unwrap();
+        }
+    }
+    // the tail stays in the memtable, and is weighed as well
+    for i in 2000..2400 {
+        storage.put(key_of(i).as_bytes(), b"0123456789").unwrap();
+    }
+
+    assert!(storage.suggest_split_points(1).unwrap().is_empty());
+    let split_points = storage.suggest_split_points(4).unwrap();
+    assert_eq!(split_points.len(), 3);
+    assert!(split_points.windows(2).all(|x| x[0] < x[1]));
+    for (idx, split) in split_points.iter().enumerate() {
+        let expected = 600 * (idx + 1);
+        let actual = rank(split, 2400);
+        assert!(
+            actual.abs_diff(expected) < 100,
+            "split point {idx} at key {actual}, expected around {expected}"
+        );
+    }
+
+    // asking for more ranges than blocks and entries gives every distinct first key at most once
+    let split_points = storage.suggest_split_points(100000).unwrap();
+    assert!(split_points.len() < 100000 - 1);
+    assert!(split_points.windows(2).all(|x| x[0] < x[1]));
+}
+
+#[test]
+fn test_split_points_skewed() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    // the first 100 keys hold most of the data
+    for i in 0..1000 {
+        let value = if i < 100 {
+            vec![b'x'; 1000]
+        } else {
+            vec![b'x'; 1]
+        };
+        storage.put(key_of(i).as_bytes(), &value).unwrap();
+    }
+    storage.force_flush().unwrap();
+
+    let split_points = storage.suggest_split_points(2).unwrap();
+    assert_eq!(split_points.len(), 1);
+    let actual = rank(&split_points[0], 1000);
+    assert!(
+        (20..100).contains(&actual),
+        "split point at key {actual}, expected in the large values"
+    );
+}
@@ -0,0 +1,80 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use tempfile::tempdir;
+
+use crate::key::KeySlice;
+use crate::lsm_error::Error;
+use crate::table::SsTableBuilder;
+
+#[test]
+fn test_try_add_rejects_out_of_order_keys() {
+    let mut builder = SsTableBuilder::new(64);
+    builder
+        .try_add(KeySlice::from_slice(b"b", 2), b"1")
+        .unwrap();
+    // Newer versions of the same key come first
+    builder
+        .try_add(KeySlice::from_slice(b"b", 1), b"1")
+        .unwrap();
+    for (key, ts) in [(&b"b"[..], 1), (b"b", 2), (b"a", 3)] {
+        let err = builder
+            .try_add(KeySlice::from_slice(key, ts), b"1")
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::InvalidArgument(_))
+        ));
+    }
+    // Keys across block boundaries are checked too
+    for i in 0..100 {
+        builder
+            .try_add(KeySlice::from_slice(format!("c{i:03}").as_bytes(), 1), b"1")
+            .unwrap();
+    }
+    assert!(builder.meta.len() > 1);
+    assert!(
+        builder
+            .try_add(KeySlice::from_slice(b"c000", 1), b"1")
+            .is_err()
+    );
+
+    let dir = tempdir().unwrap();
+    let sst = builder.build_for_test(dir.path().join("1.sst")).unwrap();
+    assert_eq!(sst.last_key().key_ref(), b"c099");
+}
+
+#[test]
+fn test_add_fails_build_when_checked() {
+    let dir = tempdir().unwrap();
+    let mut builder = SsTableBuilder::new(64);
+    builder.set_check_key_order(true);
+    builder.add(KeySlice::from_slice(b"b", 1), b"1");
+    builder.add(KeySlice::from_slice(b"a", 1), b"1");
+    builder.add(KeySlice::from_slice(b"c", 1), b"1");
+    let err = builder
+        .build_for_test(dir.path().join("1.sst"))
+        .err()
+        .unwrap();
+    assert!(matches!(
+        err.downcast_ref::<Error>(),
+        Some(Error::InvalidArgument(_))
+    ));
+    assert!(!dir.path().join("1.sst").exists());
+
+    let mut builder = SsTableBuilder::new(64);
+    builder.set_check_key_order(false);
+    builder.add(KeySlice::from_slice(b"b", 1), b"1");
+    builder.add(KeySlice::from_slice(b"a", 1), b"1");
+    builder.build_for_test(dir.path().join("2.sst")).unwrap();
+}
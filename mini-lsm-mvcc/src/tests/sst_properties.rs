@@ -0,0 +1,162 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::time::{Duration, SystemTime};
+
+use bytes::BufMut;
+use tempfile::tempdir;
+
+use crate::{
+    key::KeySlice,
+    lsm_error::Error,
+    lsm_storage::{LsmStorageInner, LsmStorageOptions, MiniLsm},
+    table::{BlockMeta, FileObject, SsTable, SsTableBuilder, SstOrigin},
+};
+
+fn all_ssts(storage: &MiniLsm) -> Vec<std::sync::Arc<SsTable>> {
+    let snapshot = storage.inner.state.read().clone();
+    snapshot
+        .l0_sstables
+        .iter()
+        .chain(snapshot.levels.iter().flat_map(|(_, ssts)| ssts))
+        .map(|id| snapshot.sstables[id].clone())
+        .collect()
+}
+
+#[test]
+fn test_sst_properties() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    for round in 0..2 {
+        for i in 0..50 {
+            storage
+                .put(format!("key{round}{i:03}").as_bytes(), b"value")
+                .unwrap();
+        }
+        storage.force_flush().unwrap();
+    }
+    let flushed = all_ssts(&storage);
+    assert_eq!(flushed.len(), 2);
+    assert_ne!(
+        flushed[0].properties().uuid(),
+        flushed[1].properties().uuid()
+    );
+    let properties = flushed[0].properties();
+    assert_eq!(properties.origin, SstOrigin::Flush);
+    assert_eq!(properties.engine_version, env!("CARGO_PKG_VERSION"));
+    let uuid = properties.uuid();
+    assert_eq!(uuid.len(), 36);
+    assert_eq!(&uuid[14..15], "4");
+    assert!(matches!(&uuid[19..20], "8" | "9" | "a" | "b"));
+    let age = SystemTime::now()
+        .duration_since(flushed[0].creation_time().unwrap())
+        .unwrap();
+    assert!(age < Duration::from_secs(60));
+
+    storage.force_full_compaction().unwrap();
+    let compacted = all_ssts(&storage);
+    assert_eq!(compacted.len(), 1);
+    let SstOrigin::Compaction { job_id } = compacted[0].properties().origin else {
+        panic!("unexpected origin {}", compacted[0].properties().origin);
+    };
+    storage.put(b"key", b"value").unwrap();
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    let SstOrigin::Compaction {
+        job_id: next_job_id,
+    } = all_ssts(&storage)[0].properties().origin
+    else {
+        panic!("SST is not written by compaction");
+    };
+    assert_ne!(job_id, next_job_id);
+
+    storage
+        .ingest_sorted([("zzz", "value")].into_iter().map(anyhow::Ok))
+        .unwrap();
+    assert!(
+        all_ssts(&storage)
+            .iter()
+            .any(|sst| sst.properties().origin == SstOrigin::Ingest)
+    );
+
+    // The properties travel with the file
+    let sst = &all_ssts(&storage)[0];
+    let copy_dir = tempdir().unwrap();
+    let copy_path = copy_dir.path().join("copy.sst");
+    std::fs::copy(
+        LsmStorageInner::path_of_sst_static(&dir, sst.sst_id()),
+        &copy_path,
+    )
+    .unwrap();
+    let copy = SsTable::open(0, None, FileObject::open(&copy_path).unwrap()).unwrap();
+    assert_eq!(copy.properties(), sst.properties());
+    assert_eq!(copy.creation_time(), sst.creation_time());
+}
+
+#[test]
+fn test_sst_properties_default_origin() {
+    let dir = tempdir().unwrap();
+    let mut builder = SsTableBuilder::new(128);
+    builder.add(KeySlice::for_testing_from_slice_no_ts(b"key"), b"value");
+    let sst = builder.build_for_test(dir.path().join("1.sst")).unwrap();
+    assert_eq!(sst.properties().origin, SstOrigin::Unknown);
+    assert_eq!(sst.properties().origin.to_string(), "unknown");
+}
+
+/// A meta block of `num` blocks with `body` as its checksummed contents.
+fn sst_meta_of(num: u32, body: &[u8]) -> Vec<u8> {
+    let mut meta = Vec::new();
+    meta.put_u32(num);
+    meta.put_slice(body);
+    meta.put_u32(crc32fast::hash(body));
+    meta
+}
+
+fn assert_truncated(meta: &[u8]) {
+    let err = BlockMeta::decode_block_meta(meta).err().unwrap();
+    assert!(matches!(Error::from(err), Error::Corruption(_)));
+}
+
+#[test]
+fn test_truncated_sst_meta() {
+    // A key longer than the rest of the meta
+    let mut body = Vec::new();
+    body.put_u32(0);
+    body.put_u16(1000);
+    assert_truncated(&sst_meta_of(1, &body));
+    // No time range and entry counts
+    assert_truncated(&sst_meta_of(0, &[]));
+    // An engine version longer than the rest of the meta
+    let mut body = vec![0; 48 + 16 + 8 + 9];
+    body.put_u16(1000);
+    assert_truncated(&sst_meta_of(0, &body));
+    // More user-collected properties than the rest of the meta holds
+    let mut body = vec![0; 48 + 16 + 8 + 9 + 2];
+    body.put_u32(1000);
+    assert_truncated(&sst_meta_of(0, &body));
+    // A block filter longer than the rest of the meta
+    let mut body = vec![0; 48 + 16 + 8 + 9 + 2 + 4];
+    body.put_u32(1);
+    body.put_u32(1000);
+    assert_truncated(&sst_meta_of(0, &body));
+    // A block filter too short to hold its checksum
+    let mut body = vec![0; 48 + 16 + 8 + 9 + 2 + 4];
+    body.put_u32(1);
+    body.put_u32(0);
+    assert_truncated(&sst_meta_of(0, &body));
+    // More value ranges than the rest of the meta holds
+    let mut body = vec![0; 48 + 16 + 8 + 9 + 2 + 4 + 4];
+    body.put_u8(1);
+    body.put_u32(1000);
+    assert_truncated(&sst_meta_of(0, &body));
+}
@@ -0,0 +1,234 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    iterators::StorageIterator,
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MiniLsm, ReadOptions, WriteBatchRecord},
+    state_machine::LsmStateMachine,
+};
+
+fn options() -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.enable_wal = true;
+    options
+}
+
+fn all_entries(storage: &MiniLsm) -> Vec<(Bytes, Bytes)> {
+    let mut iter = storage
+        .scan(std::ops::Bound::Unbounded, std::ops::Bound::Unbounded)
+        .unwrap();
+    let mut entries = Vec::new();
+    while iter.is_valid() {
+        entries.push((
+            Bytes::copy_from_slice(iter.key()),
+            Bytes::copy_from_slice(iter.value()),
+        ));
+        iter.next().unwrap();
+    }
+    entries
+}
+
+#[test]
+fn test_state_machine_apply() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    let state_machine = LsmStateMachine::new(storage).unwrap();
+    assert_eq!(state_machine.applied_index(), 0);
+    assert!(
+        state_machine
+            .apply(
+                1,
+                &[
+                    WriteBatchRecord::Put(b"a", b"1"),
+                    WriteBatchRecord::Put(b"b", b"1")
+                ]
+            )
+            .unwrap()
+    );
+    // An entry without writes still advances the index.
+    assert!(state_machine.apply::<&[u8]>(3, &[]).unwrap());
+    assert!(
+        state_machine
+            .apply(4, &[WriteBatchRecord::Del(b"b".as_slice())])
+            .unwrap()
+    );
+    // Replayed entries are skipped.
+    assert!(
+        !state_machine
+            .apply(4, &[WriteBatchRecord::Put(b"b", b"2")])
+            .unwrap()
+    );
+    assert!(
+        !state_machine
+            .apply(2, &[WriteBatchRecord::Put(b"c", b"2")])
+            .unwrap()
+    );
+    assert_eq!(state_machine.applied_index(), 4);
+    state_machine.lsm().close().unwrap();
+    drop(state_machine);
+
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    let state_machine = LsmStateMachine::new(storage).unwrap();
+    assert_eq!(state_machine.applied_index(), 4);
+    assert_eq!(
+        all_entries(state_machine.lsm()),
+        vec![(Bytes::from("a"), Bytes::from("1"))]
+    );
+
+    let mut options = options();
+    options.enable_wal = false;
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert!(matches!(
+        LsmStateMachine::new(storage),
+        Err(Error::InvalidArgument(_))
+    ));
+}
+
+#[test]
+fn test_state_machine_apply_with_ts() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    let state_machine = LsmStateMachine::new(storage).unwrap();
+    let get_at = |key: &[u8], read_ts: u64| {
+        state_machine
+            .lsm()
+            .inner
+            .get_with_options(key, read_ts, &ReadOptions::default())
+            .unwrap()
+    };
+    state_machine
+        .apply_with_ts(1, &[WriteBatchRecord::Put(b"a", b"1")], 10)
+        .unwrap();
+    // A published timestamp cannot be reused, as a snapshot may read at it
+    let snapshot = state_machine.lsm().new_txn().unwrap();
+    assert!(matches!(
+        state_machine.apply_with_ts(2, &[WriteBatchRecord::Put(b"b", b"1")], 10),
+        Err(Error::InvalidArgument(_))
+    ));
+    state_machine
+        .apply_with_ts(2, &[WriteBatchRecord::Put(b"b", b"1")], 12)
+        .unwrap();
+    assert_eq!(snapshot.get(b"b").unwrap(), None);
+    state_machine
+        .apply_with_ts(3, &[WriteBatchRecord::Put(b"a", b"2")], 15)
+        .unwrap();
+    assert_eq!(snapshot.get(b"a").unwrap(), Some(Bytes::from("1")));
+    drop(snapshot);
+    assert_eq!(state_machine.lsm().inner.mvcc().latest_commit_ts(), 15);
+    assert_eq!(get_at(b"a", 9), None);
+    assert_eq!(get_at(b"a", 10), Some(Bytes::from("1")));
+    assert_eq!(get_at(b"b", 10), None);
+    assert_eq!(get_at(b"b", 12), Some(Bytes::from("1")));
+    assert_eq!(get_at(b"a", 15), Some(Bytes::from("2")));
+
+    // A timestamp below the latest one is rejected without applying the entry
+    assert!(matches!(
+        state_machine.apply_with_ts(4, &[WriteBatchRecord::Put(b"c", b"1")], 12),
+        Err(Error::InvalidArgument(_))
+    ));
+    assert_eq!(state_machine.applied_index(), 3);
+    assert_eq!(get_at(b"c", 15), None);
+
+    // Later writes take timestamps above the forced ones
+    state_machine
+        .apply(4, &[WriteBatchRecord::Put(b"c", b"1")])
+        .unwrap();
+    assert_eq!(state_machine.lsm().inner.mvcc().latest_commit_ts(), 16);
+    state_machine.lsm().close().unwrap();
+    drop(state_machine);
+
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    assert_eq!(storage.inner.mvcc().latest_commit_ts(), 16);
+    assert_eq!(
+        storage
+            .inner
+            .get_with_options(b"a", 10, &ReadOptions::default())
+            .unwrap(),
+        Some(Bytes::from("1"))
+    );
+}
+
+#[test]
+fn test_state_machine_snapshot_restore() {
+    let leader_dir = tempdir().unwrap();
+    let leader = LsmStateMachine::new(MiniLsm::open(&leader_dir, options()).unwrap()).unwrap();
+    for i in 0..100 {
+        let key = format!("key_{:03}", i);
+        leader
+            .apply(
+                i + 1,
+                &[WriteBatchRecord::Put(key.as_bytes(), b"leader".as_slice())],
+            )
+            .unwrap();
+    }
+    leader
+        .apply(101, &[WriteBatchRecord::Del(b"key_050")])
+        .unwrap();
+    let mut snapshot = Vec::new();
+    assert_eq!(leader.snapshot(&mut snapshot).unwrap(), 101);
+    // Writes after the snapshot is taken are not part of it.
+    leader
+        .apply(
+            102,
+            &[WriteBatchRecord::Put(b"key_100".as_slice(), b"leader")],
+        )
+        .unwrap();
+
+    let follower_dir = tempdir().unwrap();
+    let follower = LsmStateMachine::new(MiniLsm::open(&follower_dir, options()).unwrap()).unwrap();
+    follower
+        .apply(
+            1,
+            &[
+                WriteBatchRecord::Put(b"key_000".as_slice(), b"follower".as_slice()),
+                WriteBatchRecord::Put(b"key_050", b"follower"),
+                WriteBatchRecord::Put(b"stale", b"follower"),
+            ],
+        )
+        .unwrap();
+
+    // A corrupted or truncated snapshot does not change anything.
+    let mut corrupted = snapshot.clone();
+    corrupted[20] ^= 1;
+    assert!(matches!(
+        follower.restore(corrupted.as_slice()),
+        Err(Error::Corruption(_))
+    ));
+    assert!(matches!(
+        follower.restore(&snapshot[..snapshot.len() - 1]),
+        Err(Error::Corruption(_))
+    ));
+    assert_eq!(follower.applied_index(), 1);
+    assert_eq!(all_entries(follower.lsm()).len(), 3);
+
+    assert_eq!(follower.restore(snapshot.as_slice()).unwrap(), 101);
+    assert_eq!(follower.applied_index(), 101);
+    let expected = (0..100)
+        .filter(|i| *i != 50)
+        .map(|i| (Bytes::from(format!("key_{:03}", i)), Bytes::from("leader")))
+        .collect::<Vec<_>>();
+    assert_eq!(all_entries(follower.lsm()), expected);
+    follower.lsm().close().unwrap();
+    drop(follower);
+
+    let follower = LsmStateMachine::new(MiniLsm::open(&follower_dir, options()).unwrap()).unwrap();
+    assert_eq!(follower.applied_index(), 101);
+    assert_eq!(all_entries(follower.lsm()), expected);
+}
@@ -0,0 +1,123 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    key::KeySlice,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    table::{SsTableBuilder, TablePropertiesCollector, TablePropertiesCollectorFactory},
+};
+
+/// Counts the entries by the first byte of the key.
+#[derive(Default)]
+struct PrefixCounter {
+    counts: BTreeMap<u8, u64>,
+}
+
+impl TablePropertiesCollector for PrefixCounter {
+    fn add(&mut self, key: KeySlice, _value: &[u8]) {
+        *self.counts.entry(key.key_ref()[0]).or_default() += 1;
+    }
+
+    fn finish(&mut self) -> Vec<(String, Bytes)> {
+        self.counts
+            .iter()
+            .map(|(prefix, count)| {
+                (
+                    format!("prefix.{}", *prefix as char),
+                    Bytes::from(count.to_string()),
+                )
+            })
+            .collect()
+    }
+}
+
+struct PrefixCounterFactory;
+
+impl TablePropertiesCollectorFactory for PrefixCounterFactory {
+    fn create(&self) -> Box<dyn TablePropertiesCollector> {
+        Box::<PrefixCounter>::default()
+    }
+}
+
+fn prefix_count(properties: &BTreeMap<String, Bytes>, prefix: char) -> Option<u64> {
+    let value = properties.get(&format!("prefix.{prefix}"))?;
+    Some(std::str::from_utf8(value).unwrap().parse().unwrap())
+}
+
+#[test]
+fn test_builder_properties_collector() {
+    let dir = tempdir().unwrap();
+    let mut builder = SsTableBuilder::new(16);
+    builder.add_properties_collector(Box::<PrefixCounter>::default());
+    for key in ["a1", "a2", "b1"] {
+        builder.add(
+            KeySlice::for_testing_from_slice_no_ts(key.as_bytes()),
+            b"value",
+        );
+    }
+    let sst = builder.build_for_test(dir.path().join("1.sst")).unwrap();
+    let properties = &sst.properties().user_collected;
+    assert_eq!(properties.len(), 2);
+    assert_eq!(prefix_count(properties, 'a'), Some(2));
+    assert_eq!(prefix_count(properties, 'b'), Some(1));
+}
+
+#[test]
+fn test_table_properties_collector() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    storage.add_table_properties_collector(Arc::new(PrefixCounterFactory));
+    for i in 0..30 {
+        storage.put(format!("a{i}").as_bytes(), b"value").unwrap();
+    }
+    for i in 0..10 {
+        storage.put(format!("b{i}").as_bytes(), b"value").unwrap();
+    }
+    storage.force_flush().unwrap();
+    for i in 0..5 {
+        storage.delete(format!("b{i}").as_bytes()).unwrap();
+    }
+    storage.put(b"c", b"value").unwrap();
+    storage.force_flush().unwrap();
+    {
+        let snapshot = storage.inner.state.read().clone();
+        let l0 = &snapshot.l0_sstables;
+        let newest = &snapshot.sstables[&l0[0]].properties().user_collected;
+        assert_eq!(prefix_count(newest, 'a'), None);
+        assert_eq!(prefix_count(newest, 'b'), Some(5));
+        assert_eq!(prefix_count(newest, 'c'), Some(1));
+        let oldest = &snapshot.sstables[&l0[1]].properties().user_collected;
+        assert_eq!(prefix_count(oldest, 'a'), Some(30));
+        assert_eq!(prefix_count(oldest, 'b'), Some(10));
+    }
+
+    // Compaction outputs are collected over their own entries
+    storage.force_full_compaction().unwrap();
+    storage.close().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    let snapshot = storage.inner.state.read().clone();
+    let mut counts = BTreeMap::new();
+    for id in &snapshot.levels[0].1 {
+        let properties = &snapshot.sstables[id].properties().user_collected;
+        for prefix in ['a', 'b', 'c'] {
+            *counts.entry(prefix).or_default() += prefix_count(properties, prefix).unwrap_or(0);
+        }
+    }
+    assert_eq!(counts, BTreeMap::from([('a', 30), ('b', 5), ('c', 1)]));
+}
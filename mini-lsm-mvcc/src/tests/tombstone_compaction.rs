@@ -0,0 +1,136 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::time::{Duration, Instant};
+
+use tempfile::tempdir;
+
+use crate::{
+    compact::{CompactionOptions, LeveledCompactionOptions},
+    iterators::StorageIterator,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    table::{SsTableIterator, SstEntryCounts},
+};
+
+fn sst_entry_counts(storage: &MiniLsm) -> Vec<SstEntryCounts> {
+    let snapshot = storage.inner.state.read().clone();
+    snapshot
+        .l0_sstables
+        .iter()
+        .chain(snapshot.levels.iter().flat_map(|(_, ssts)| ssts))
+        .map(|id| *snapshot.sstables[id].entry_counts())
+        .collect()
+}
+
+fn num_entries_in_ssts(storage: &MiniLsm) -> u64 {
+    let snapshot = storage.inner.state.read().clone();
+    let mut cnt = 0;
+    for sst in snapshot.sstables.values() {
+        let mut iter = SsTableIterator::create_and_seek_to_first(sst.clone()).unwrap();
+        while iter.is_valid() {
+            cnt += 1;
+            iter.next().unwrap();
+        }
+    }
+    cnt
+}
+
+fn wait_until(what: &str, mut cond: impl FnMut() -> bool) {
+    let start = Instant::now();
+    while !cond() {
+        assert!(start.elapsed() < Duration::from_secs(10), "{what}");
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+#[test]
+fn test_tombstone_compaction() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::Leveled(
+        LeveledCompactionOptions {
+            level0_file_num_compaction_trigger: 2,
+            max_levels: 3,
+            base_level_size_mb: 1,
+            level_size_multiplier: 2,
+        },
+    ));
+    options.tombstone_compaction_ratio = Some(0.3);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for i in 0..100 {
+        storage
+            .put(format!("key{:03}", i).as_bytes(), b"value")
+            .unwrap();
+    }
+    storage.force_flush().unwrap();
+    assert_eq!(
+        sst_entry_counts(&storage),
+        vec![SstEntryCounts {
+            num_entries: 100,
+            num_deletes: 0
+        }]
+    );
+
+    // The snapshot keeps the deletes and the values they shadow across the L0 compaction into the bottom level
+    let txn = storage.new_txn().unwrap();
+    for i in 0..80 {
+        storage.delete(format!("key{:03}", i).as_bytes()).unwrap();
+    }
+    storage.force_flush().unwrap();
+    wait_until("L0 is not compacted", || {
+        storage.inner.state.read().l0_sstables.is_empty()
+    });
+    let counts = sst_entry_counts(&storage);
+    assert_eq!(counts.iter().map(|c| c.num_entries).sum::<u64>(), 180);
+    assert_eq!(counts.iter().map(|c| c.num_deletes).sum::<u64>(), 80);
+    assert!(counts.iter().any(|c| c.tombstone_ratio() > 0.3));
+    std::thread::sleep(Duration::from_millis(300));
+    assert_eq!(num_entries_in_ssts(&storage), 180);
+
+    // Once released, the deletes are dropped by rewriting the SST without reaching any size trigger
+    drop(txn);
+    wait_until("tombstones are not reclaimed", || {
+        num_entries_in_ssts(&storage) == 20
+    });
+    assert!(
+        sst_entry_counts(&storage)
+            .iter()
+            .all(|c| c.num_deletes == 0)
+    );
+    for i in 0..100 {
+        let value = storage.get(format!("key{:03}", i).as_bytes()).unwrap();
+        assert_eq!(value.is_some(), i >= 80);
+    }
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_tombstone_compaction_ratio_validation() {
+    assert!(
+        LsmStorageOptions::builder()
+            .tombstone_compaction_ratio(0.0)
+            .build()
+            .is_err()
+    );
+    assert!(
+        LsmStorageOptions::builder()
+            .tombstone_compaction_ratio(1.5)
+            .build()
+            .is_err()
+    );
+    assert!(
+        LsmStorageOptions::builder()
+            .tombstone_compaction_ratio(0.5)
+            .build()
+            .is_ok()
+    );
+}
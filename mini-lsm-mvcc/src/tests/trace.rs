@@ -0,0 +1,197 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::ops::Bound;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    iterators::StorageIterator,
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MiniLsm, WriteBatchRecord},
+    trace::{ReplaySummary, TraceOp, TraceReader},
+};
+
+fn contents(storage: &MiniLsm) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    let mut contents = Vec::new();
+    while iter.is_valid() {
+        contents.push((iter.key().to_vec(), iter.value().to_vec()));
+        iter.next().unwrap();
+    }
+    contents
+}
+
+fn options() -> LsmStorageOptions {
+    LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction)
+}
+
+#[test]
+fn test_trace_records() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(dir.path().join("db"), options()).unwrap();
+    let trace_path = dir.path().join("trace");
+    storage.put(b"before", b"not traced").unwrap();
+
+    storage.start_trace(&trace_path).unwrap();
+    assert!(matches!(
+        storage.start_trace(dir.path().join("other")),
+        Err(Error::InvalidArgument(_))
+    ));
+    storage.put(b"a", b"1").unwrap();
+    storage
+        .write_batch(&[
+            WriteBatchRecord::Put(b"b", b"2"),
+            WriteBatchRecord::Del(b"a"),
+        ])
+        .unwrap();
+    storage.get(b"b").unwrap();
+    storage
+        .scan(Bound::Included(b"a"), Bound::Excluded(b"c"))
+        .unwrap();
+    std::thread::scope(|s| {
+        s.spawn(|| storage.get(b"other thread").unwrap());
+    });
+    assert_eq!(storage.stop_trace().unwrap(), 6);
+    assert!(matches!(
+        storage.stop_trace(),
+        Err(Error::InvalidArgument(_))
+    ));
+    storage.put(b"after", b"not traced").unwrap();
+
+    let records = TraceReader::open(&trace_path)
+        .unwrap()
+        .collect::<anyhow::Result<Vec<_>>>()
+        .unwrap();
+    let ops = records
+        .iter()
+        .map(|record| record.op.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        ops,
+        vec![
+            TraceOp::Put {
+                key: Bytes::from("a"),
+                value: Bytes::from("1")
+            },
+            TraceOp::Put {
+                key: Bytes::from("b"),
+                value: Bytes::from("2")
+            },
+            TraceOp::Delete {
+                key: Bytes::from("a")
+            },
+            TraceOp::Get {
+                key: Bytes::from("b")
+            },
+            TraceOp::Scan {
+                lower: Bound::Included(Bytes::from("a")),
+                upper: Bound::Excluded(Bytes::from("c"))
+            },
+            TraceOp::Get {
+                key: Bytes::from("other thread")
+            },
+        ]
+    );
+    // the writes of a batch share their commit ts, and reads see the latest commit
+    assert_eq!(records[0].ts + 1, records[1].ts);
+    assert_eq!(records[1].ts, records[2].ts);
+    assert_eq!(records[3].ts, records[2].ts);
+    assert!(records.windows(2).all(|x| x[0].elapsed <= x[1].elapsed));
+    assert!(
+        records[..5]
+            .iter()
+            .all(|x| x.thread_id == records[0].thread_id)
+    );
+    assert_ne!(records[5].thread_id, records[0].thread_id);
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_trace_replay() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(dir.path().join("db"), options()).unwrap();
+    let trace_path = dir.path().join("trace");
+    storage.start_trace(&trace_path).unwrap();
+    for i in 0..200 {
+        storage
+            .put(
+                format!("key{:03}", i % 150).as_bytes(),
+                format!("v{i}").as_bytes(),
+            )
+            .unwrap();
+        if i % 7 == 0 {
+            storage
+                .delete(format!("key{:03}", i / 2).as_bytes())
+                .unwrap();
+        }
+        if i % 5 == 0 {
+            storage.get(format!("key{:03}", i / 3).as_bytes()).unwrap();
+        }
+        if i % 50 == 0 {
+            storage.force_flush().unwrap();
+            storage
+                .scan(Bound::Included(b"key050"), Bound::Unbounded)
+                .unwrap();
+        }
+    }
+    let txn = storage.new_txn().unwrap();
+    txn.put(b"txn", b"value");
+    txn.get(b"key001").unwrap();
+    txn.commit().unwrap();
+    storage.stop_trace().unwrap();
+    let expected = contents(&storage);
+    storage.close().unwrap();
+
+    let mut summaries = Vec::new();
+    for run in 0..2 {
+        let replayed = MiniLsm::open(dir.path().join(format!("replay{run}")), options()).unwrap();
+        let summary = replayed
+            .replay_trace(TraceReader::open(&trace_path).unwrap(), false)
+            .unwrap();
+        assert_eq!(contents(&replayed), expected);
+        replayed.close().unwrap();
+        summaries.push(summary);
+    }
+    assert_eq!(summaries[0], summaries[1]);
+    let summary = &summaries[0];
+    assert_eq!(summary.puts, 201);
+    assert_eq!(summary.deletes, 29);
+    assert_eq!(summary.batches, 201 + 29);
+    assert_eq!(summary.gets, 40 + 1);
+    assert_eq!(summary.scans, 4);
+    assert!(summary.gets_found > 0 && summary.scanned_entries > 0);
+    assert_ne!(summary, &ReplaySummary::default());
+}
+
+#[test]
+fn test_trace_corrupted() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("trace");
+    std::fs::write(&path, b"not a trace").unwrap();
+    assert!(TraceReader::open(&path).is_err());
+
+    let storage = MiniLsm::open(dir.path().join("db"), options()).unwrap();
+    let path = dir.path().join("truncated");
+    storage.start_trace(&path).unwrap();
+    storage.put(b"key", b"value").unwrap();
+    storage.stop_trace().unwrap();
+    storage.close().unwrap();
+    let data = std::fs::read(&path).unwrap();
+    std::fs::write(&path, &data[..data.len() - 2]).unwrap();
+    let records = TraceReader::open(&path).unwrap().collect::<Vec<_>>();
+    assert_eq!(records.len(), 1);
+    assert!(records[0].is_err());
+}
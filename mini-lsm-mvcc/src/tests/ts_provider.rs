@@ -0,0 +1,135 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+use anyhow::Result;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    mvcc::ts_provider::{TsProvider, TsProviderOptions, hlc_physical_millis},
+    table::unix_millis,
+};
+
+/// Hands out the multiples of 10 from its counter, which can be set back to hand out stale timestamps.
+#[derive(Debug, Default)]
+struct Oracle(AtomicU64);
+
+impl TsProvider for Oracle {
+    fn next_ts(&self, _last: u64) -> Result<u64> {
+        Ok(self.0.fetch_add(10, Ordering::SeqCst) + 10)
+    }
+}
+
+fn options(ts_provider: TsProviderOptions) -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.ts_provider = ts_provider;
+    options
+}
+
+fn latest_ts(storage: &MiniLsm) -> u64 {
+    storage.inner.mvcc().latest_commit_ts()
+}
+
+#[test]
+fn test_hybrid_logical_ts() {
+    let dir = tempdir().unwrap();
+    let before = unix_millis(SystemTime::now());
+    let storage = MiniLsm::open(&dir, options(TsProviderOptions::HybridLogical)).unwrap();
+    let mut last = 0;
+    for i in 0..100 {
+        storage.put(format!("key{i}").as_bytes(), b"v").unwrap();
+        let ts = latest_ts(&storage);
+        assert!(ts > last);
+        last = ts;
+    }
+    let after = unix_millis(SystemTime::now());
+    assert!((before..=after).contains(&hlc_physical_millis(last)));
+    storage.close().unwrap();
+    drop(storage);
+
+    // A clock far behind the timestamps handed out before is ignored
+    let storage = MiniLsm::open(&dir, options(TsProviderOptions::HybridLogical)).unwrap();
+    storage.observe_ts(last + (1000 << 16)).unwrap();
+    storage.put(b"key", b"v").unwrap();
+    assert_eq!(latest_ts(&storage), last + (1000 << 16) + 1);
+}
+
+#[test]
+fn test_observed_ts_persisted() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options(TsProviderOptions::Logical)).unwrap();
+    storage.put(b"key", b"v").unwrap();
+    storage.observe_ts(1000).unwrap();
+    storage.close().unwrap();
+    drop(storage);
+
+    // Nothing was committed above the observed ts before closing
+    let storage = MiniLsm::open(&dir, options(TsProviderOptions::Logical)).unwrap();
+    storage.put(b"key", b"v").unwrap();
+    assert_eq!(latest_ts(&storage), 1001);
+}
+
+#[test]
+fn test_external_ts() {
+    let dir = tempdir().unwrap();
+    let oracle = Arc::new(Oracle::default());
+    let storage =
+        MiniLsm::open(&dir, options(TsProviderOptions::External(oracle.clone()))).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    let txn = storage.new_txn().unwrap();
+    txn.put(b"b", b"2");
+    txn.commit().unwrap();
+    assert_eq!(latest_ts(&storage), 20);
+
+    oracle.0.store(0, Ordering::SeqCst);
+    assert!(matches!(
+        storage.put(b"c", b"3"),
+        Err(Error::InvalidArgument(_))
+    ));
+    assert_eq!(storage.get(b"c").unwrap(), None);
+    oracle.0.store(100, Ordering::SeqCst);
+    storage.put(b"c", b"3").unwrap();
+    assert_eq!(latest_ts(&storage), 110);
+    assert_eq!(storage.get(b"c").unwrap().as_deref(), Some(&b"3"[..]));
+}
+
+#[test]
+fn test_commit_ts_persisted() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options(TsProviderOptions::Logical)).unwrap();
+    storage.put(b"kept", b"v").unwrap();
+    for i in 0..10 {
+        storage.put(format!("key{i}").as_bytes(), b"v").unwrap();
+        storage.delete(format!("key{i}").as_bytes()).unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    // The deletes are dropped along with the versions they delete, leaving only the first commit in the SSTs
+    let snapshot = storage.inner.state.read().clone();
+    let max_ts = snapshot.sstables.values().map(|sst| sst.max_ts()).max();
+    assert_eq!(max_ts, Some(1));
+    assert_eq!(latest_ts(&storage), 21);
+    storage.close().unwrap();
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, options(TsProviderOptions::Logical)).unwrap();
+    assert_eq!(latest_ts(&storage), 21);
+    storage.put(b"key", b"v").unwrap();
+    assert_eq!(latest_ts(&storage), 22);
+}
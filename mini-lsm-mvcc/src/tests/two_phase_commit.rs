@@ -0,0 +1,166 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+};
+
+fn options() -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.enable_wal = true;
+    options
+}
+
+#[test]
+fn test_prepare_locks_keys_until_commit() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    let txn = storage.new_txn().unwrap();
+    txn.put(b"a", b"1");
+    txn.delete(b"b");
+    txn.prepare("xid-1").unwrap();
+    assert_eq!(txn.prepared_name().as_deref(), Some("xid-1"));
+    assert!(dir.path().join("xid-1.prepared").exists());
+
+    assert!(matches!(storage.put(b"a", b"2"), Err(Error::Busy(_))));
+    assert!(matches!(storage.delete(b"b"), Err(Error::Busy(_))));
+    storage.put(b"c", b"2").unwrap();
+    let other = storage.new_txn().unwrap();
+    other.put(b"a", b"3");
+    assert!(matches!(other.prepare("xid-2"), Err(Error::Busy(_))));
+    assert!(matches!(other.commit(), Err(Error::Busy(_))));
+    let other = storage.new_txn().unwrap();
+    other.put(b"d", b"3");
+    assert!(matches!(
+        other.prepare("xid-1"),
+        Err(Error::InvalidArgument(_))
+    ));
+    assert!(matches!(
+        other.prepare("../xid"),
+        Err(Error::InvalidArgument(_))
+    ));
+    assert_eq!(storage.get(b"a").unwrap(), None);
+
+    txn.commit().unwrap();
+    assert_eq!(txn.prepared_name(), None);
+    assert!(!dir.path().join("xid-1.prepared").exists());
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from("1")));
+    storage.put(b"a", b"2").unwrap();
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from("2")));
+}
+
+#[test]
+fn test_rollback_prepared() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    let txn = storage.new_txn().unwrap();
+    txn.put(b"a", b"1");
+    txn.prepare("xid").unwrap();
+    txn.rollback().unwrap();
+    assert!(!dir.path().join("xid.prepared").exists());
+    assert_eq!(storage.get(b"a").unwrap(), None);
+    storage.put(b"a", b"2").unwrap();
+
+    // The name is free again once decided.
+    let txn = storage.new_txn().unwrap();
+    txn.put(b"b", b"1");
+    txn.prepare("xid").unwrap();
+    txn.commit().unwrap();
+    assert_eq!(storage.get(b"b").unwrap(), Some(Bytes::from("1")));
+}
+
+#[test]
+fn test_recover_prepared_after_restart() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    storage.put(b"b", b"0").unwrap();
+    for (name, key) in [("xid-1", b"a"), ("xid-2", b"b")] {
+        let txn = storage.new_txn().unwrap();
+        txn.put(key, b"1");
+        txn.prepare(name).unwrap();
+    }
+    storage.close().unwrap();
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    let txns = storage.recovered_prepared_txns();
+    assert_eq!(
+        txns.iter()
+            .map(|txn| txn.prepared_name().unwrap())
+            .collect::<Vec<_>>(),
+        vec!["xid-1", "xid-2"]
+    );
+    assert!(storage.recovered_prepared_txns().is_empty());
+    assert!(matches!(storage.put(b"a", b"2"), Err(Error::Busy(_))));
+    assert_eq!(txns[0].get(b"a").unwrap(), Some(Bytes::from("1")));
+    assert_eq!(storage.get(b"b").unwrap(), Some(Bytes::from("0")));
+
+    txns[0].commit().unwrap();
+    txns[1].rollback().unwrap();
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from("1")));
+    assert_eq!(storage.get(b"b").unwrap(), Some(Bytes::from("0")));
+    storage.put(b"b", b"2").unwrap();
+    storage.close().unwrap();
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    assert!(storage.recovered_prepared_txns().is_empty());
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from("1")));
+    assert_eq!(storage.get(b"b").unwrap(), Some(Bytes::from("2")));
+}
+
+#[test]
+fn test_prepare_locks_read_set() {
+    let dir = tempdir().unwrap();
+    let mut options = options();
+    options.serializable = true;
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"a", b"0").unwrap();
+    storage.put(b"b", b"0").unwrap();
+    // Write skew: each transaction reads one key and writes the other
+    let txn1 = storage.new_txn().unwrap();
+    let txn2 = storage.new_txn().unwrap();
+    assert_eq!(txn1.get(b"a").unwrap(), Some(Bytes::from("0")));
+    txn1.put(b"b", b"1");
+    txn1.prepare("xid-1").unwrap();
+    assert_eq!(txn2.get(b"b").unwrap(), Some(Bytes::from("0")));
+    txn2.put(b"a", b"1");
+    assert!(matches!(txn2.commit(), Err(Error::Busy(_))));
+    assert!(matches!(storage.put(b"a", b"2"), Err(Error::Busy(_))));
+    txn1.commit().unwrap();
+    storage.put(b"a", b"2").unwrap();
+}
+
+#[test]
+fn test_drop_prepared_keeps_locks() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    let txn = storage.new_txn().unwrap();
+    txn.put(b"a", b"1");
+    txn.prepare("xid").unwrap();
+    drop(txn);
+    assert!(dir.path().join("xid.prepared").exists());
+    assert!(matches!(storage.put(b"a", b"2"), Err(Error::Busy(_))));
+
+    let txns = storage.recovered_prepared_txns();
+    assert_eq!(txns.len(), 1);
+    assert_eq!(txns[0].prepared_name().as_deref(), Some("xid"));
+    txns[0].commit().unwrap();
+    assert_eq!(storage.get(b"a").unwrap(), Some(Bytes::from("1")));
+    storage.put(b"a", b"2").unwrap();
+}
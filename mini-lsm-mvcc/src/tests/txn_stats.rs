@@ -0,0 +1,94 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    stats::AbortReason,
+};
+
+#[test]
+fn test_txn_stats_write_conflict() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(storage.txn_stats().commits, 0);
+
+    let txn = storage.new_txn().unwrap();
+    txn.get_for_update(b"a").unwrap();
+    let other = storage.new_txn().unwrap();
+    other.put(b"b", b"1");
+    other.put(b"a", b"1");
+    assert!(matches!(other.commit(), Err(Error::Busy(_))));
+
+    let prepared = storage.new_txn().unwrap();
+    prepared.put(b"c", b"1");
+    prepared.prepare("xid-1").unwrap();
+    let other = storage.new_txn().unwrap();
+    other.put(b"c", b"2");
+    assert!(matches!(other.commit(), Err(Error::Busy(_))));
+
+    txn.put(b"a", b"2");
+    txn.commit().unwrap();
+    prepared.commit().unwrap();
+
+    let stats = storage.txn_stats();
+    assert_eq!(stats.commits, 2);
+    assert_eq!(stats.write_conflicts, 2);
+    assert_eq!(stats.aborts(), 2);
+    assert_eq!(
+        stats.recent_aborts,
+        [
+            AbortReason::WriteConflict {
+                key: Bytes::from("a")
+            },
+            AbortReason::WriteConflict {
+                key: Bytes::from("c")
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_txn_stats_read_validation() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.serializable = true;
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"key1", b"1").unwrap();
+    storage.put(b"key2", b"2").unwrap();
+    // Writes outside transactions commit through one when serializable
+    assert_eq!(storage.txn_stats().commits, 2);
+    let txn1 = storage.new_txn().unwrap();
+    let txn2 = storage.new_txn().unwrap();
+    txn1.put(b"key1", &txn1.get(b"key2").unwrap().unwrap());
+    txn2.put(b"key2", &txn2.get(b"key1").unwrap().unwrap());
+    txn1.commit().unwrap();
+    let commit_ts = storage.inner.mvcc().latest_commit_ts();
+    assert!(matches!(txn2.commit(), Err(Error::Busy(_))));
+
+    let stats = storage.txn_stats();
+    assert_eq!(stats.commits, 3);
+    assert_eq!(stats.read_validation_failures, 1);
+    assert_eq!(
+        stats.recent_aborts,
+        [AbortReason::ReadValidation {
+            read_ts: txn2.read_ts(),
+            commit_ts
+        }]
+    );
+}
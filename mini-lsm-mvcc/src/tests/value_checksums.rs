@@ -0,0 +1,155 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::path::Path;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    checksum::block_checksum,
+    compact::CompactionOptions,
+    iterators::StorageIterator,
+    key::KeySlice,
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MAX_KEY_VALUE_SIZE, MiniLsm, ReadOptions},
+    table::{FileObject, SsTable, SsTableBuilder, SsTableIterator},
+};
+
+fn key_of(i: usize) -> Vec<u8> {
+    format!("key{i:03}").into_bytes()
+}
+
+fn value_of(i: usize) -> Vec<u8> {
+    match i % 3 {
+        0 => format!("value{i:03}").repeat(20).into_bytes(),
+        1 => format!("value{i:03}").into_bytes(),
+        _ => Vec::new(),
+    }
+}
+
+fn build_sst(path: &Path, value_checksums: bool, threshold: Option<usize>) -> Arc<SsTable> {
+    let mut builder = SsTableBuilder::new(256);
+    builder.set_value_checksums(value_checksums);
+    if let Some(threshold) = threshold {
+        builder.set_value_compression_threshold(threshold);
+    }
+    for i in 0..100 {
+        builder.add(
+            KeySlice::for_testing_from_slice_with_ts(&key_of(i), 1),
+            &value_of(i),
+        );
+    }
+    builder.build_for_test(path).unwrap();
+    Arc::new(SsTable::open_for_test(FileObject::open(path).unwrap()).unwrap())
+}
+
+#[test]
+fn test_value_checksums() {
+    let dir = tempdir().unwrap();
+    for threshold in [None, Some(64)] {
+        let sst = build_sst(&dir.path().join("1.sst"), true, threshold);
+        assert!(sst.has_value_checksums());
+        let mut iter = SsTableIterator::create_and_seek_to_first(sst.clone()).unwrap();
+        for i in 0..100 {
+            assert_eq!(iter.key().key_ref(), &key_of(i)[..]);
+            assert_eq!(iter.value(), &value_of(i)[..]);
+            iter.next().unwrap();
+        }
+        assert!(!iter.is_valid());
+    }
+    let sst = build_sst(&dir.path().join("2.sst"), false, None);
+    assert!(!sst.has_value_checksums());
+}
+
+#[test]
+fn test_value_checksum_mismatch() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("1.sst");
+    let sst = build_sst(&path, true, None);
+    let block_end = sst.block_meta[1].offset;
+    drop(sst);
+
+    // Flip a bit of a value in the first block and fix up the block checksum, as if the block was damaged before it
+    // was checksummed.
+    let mut data = std::fs::read(&path).unwrap();
+    let pos = data
+        .windows(8)
+        .position(|window| window == b"value001")
+        .unwrap();
+    assert!(pos < block_end);
+    data[pos + 7] ^= 1;
+    let checksum = block_checksum(&data[..block_end - 4]);
+    data[block_end - 4..block_end].copy_from_slice(&checksum.to_be_bytes());
+    std::fs::write(&path, data).unwrap();
+
+    let sst = Arc::new(SsTable::open_for_test(FileObject::open(&path).unwrap()).unwrap());
+    let key = key_of(1);
+    let key = KeySlice::for_testing_from_slice_with_ts(&key, 1);
+    let err = SsTableIterator::create_and_seek_to_key(sst.clone(), key)
+        .err()
+        .unwrap();
+    let Some(Error::Corruption(msg)) = err.downcast_ref::<Error>() else {
+        panic!("expected a corruption error, got {err}");
+    };
+    assert!(msg.contains("key001\"@1 in block 0"), "{msg}");
+    // A scan fails when it reaches the damaged entry, but not before.
+    let mut iter = SsTableIterator::create_and_seek_to_first(sst.clone()).unwrap();
+    assert_eq!(iter.key().key_ref(), &key_of(0)[..]);
+    assert!(iter.next().is_err());
+
+    let options = ReadOptions {
+        verify_checksums: false,
+        ..Default::default()
+    };
+    let iter = SsTableIterator::create_and_seek_to_key_with_options(sst, key, options).unwrap();
+    assert_eq!(iter.value(), b"value000");
+}
+
+#[test]
+fn test_value_checksums_option() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::builder()
+        .compaction_options(CompactionOptions::NoCompaction)
+        .value_checksums(true)
+        .build()
+        .unwrap();
+    assert!(options.max_value_size < MAX_KEY_VALUE_SIZE - 4);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for i in 0..100 {
+        if i % 3 == 2 {
+            storage.delete(&key_of(i)).unwrap();
+        } else {
+            storage.put(&key_of(i), &value_of(i)).unwrap();
+        }
+    }
+    storage.force_flush().unwrap();
+    let snapshot = storage.inner.state.read().clone();
+    assert!(
+        snapshot
+            .sstables
+            .values()
+            .all(|sst| sst.has_value_checksums())
+    );
+    assert_eq!(storage.get(&key_of(2)).unwrap(), None);
+    assert_eq!(
+        storage.get(&key_of(3)).unwrap(),
+        Some(Bytes::from(value_of(3)))
+    );
+    storage.close().unwrap();
+
+    let mut options = LsmStorageOptions::default_for_week1_test();
+    options.value_checksums = true;
+    assert!(options.validate().is_err());
+}
@@ -0,0 +1,152 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::ops::Bound;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    iterators::StorageIterator,
+    key::KeySlice,
+    lsm_error::Error,
+    lsm_storage::{LsmStorageOptions, MAX_KEY_VALUE_SIZE, MiniLsm},
+    table::{FileObject, SsTable, SsTableBuilder, SsTableIterator},
+};
+
+fn large_value(i: usize) -> Vec<u8> {
+    format!("value{i:05}").repeat(100).into_bytes()
+}
+
+fn build_sst(dir: &std::path::Path, name: &str, threshold: Option<usize>) -> Arc<SsTable> {
+    let mut builder = SsTableBuilder::new(4096);
+    if let Some(threshold) = threshold {
+        builder.set_value_compression_threshold(threshold);
+    }
+    for i in 0..100 {
+        let key = format!("key{i:03}");
+        let value = match i % 3 {
+            0 => large_value(i),
+            1 => b"small".to_vec(),
+            _ => Vec::new(),
+        };
+        builder.add(
+            KeySlice::for_testing_from_slice_with_ts(key.as_bytes(), 1),
+            &value,
+        );
+    }
+    let path = dir.join(name);
+    builder.build_for_test(&path).unwrap();
+    Arc::new(SsTable::open_for_test(FileObject::open(&path).unwrap()).unwrap())
+}
+
+#[test]
+fn test_compressed_values() {
+    let dir = tempdir().unwrap();
+    let compressed = build_sst(dir.path(), "1.sst", Some(64));
+    let uncompressed = build_sst(dir.path(), "2.sst", None);
+    assert!(compressed.has_compressed_values());
+    assert!(!uncompressed.has_compressed_values());
+    assert!(compressed.table_size() * 4 < uncompressed.table_size());
+
+    let mut iter = SsTableIterator::create_and_seek_to_first(compressed.clone()).unwrap();
+    let mut expected = SsTableIterator::create_and_seek_to_first(uncompressed).unwrap();
+    while expected.is_valid() {
+        assert!(iter.is_valid());
+        assert_eq!(iter.key(), expected.key());
+        assert_eq!(iter.value(), expected.value());
+        iter.next().unwrap();
+        expected.next().unwrap();
+    }
+    assert!(!iter.is_valid());
+
+    let iter = SsTableIterator::create_and_seek_to_key(
+        compressed,
+        KeySlice::for_testing_from_slice_with_ts(b"key042", 1),
+    )
+    .unwrap();
+    assert_eq!(iter.value(), &large_value(42)[..]);
+}
+
+#[test]
+fn test_value_compression_option() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::builder()
+        .compaction_options(CompactionOptions::NoCompaction)
+        .value_compression_threshold(64)
+        .build()
+        .unwrap();
+    assert_eq!(options.max_value_size, MAX_KEY_VALUE_SIZE - 1);
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    for i in 0..100 {
+        storage
+            .put(format!("key{i:03}").as_bytes(), &large_value(i))
+            .unwrap();
+    }
+    storage.force_flush().unwrap();
+    storage.delete(b"key005").unwrap();
+    storage.put(b"key006", b"small").unwrap();
+    storage.force_flush().unwrap();
+    storage.force_full_compaction().unwrap();
+    storage.close().unwrap();
+
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    let snapshot = storage.inner.state.read().clone();
+    assert!(
+        snapshot
+            .sstables
+            .values()
+            .all(|sst| sst.has_compressed_values())
+    );
+    assert_eq!(storage.get(b"key005").unwrap(), None);
+    assert_eq!(storage.get(b"key006").unwrap(), Some(Bytes::from("small")));
+    assert_eq!(
+        storage.get(b"key007").unwrap(),
+        Some(Bytes::from(large_value(7)))
+    );
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    let mut count = 0;
+    while iter.is_valid() {
+        count += 1;
+        iter.next().unwrap();
+    }
+    assert_eq!(count, 99);
+
+    let mut options = LsmStorageOptions::default_for_week1_test();
+    options.value_compression_threshold = Some(64);
+    assert!(options.validate().is_err());
+}
+
+#[test]
+fn test_reopen_with_other_value_compression() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::builder()
+        .compaction_options(CompactionOptions::NoCompaction)
+        .value_compression_threshold(64)
+        .build()
+        .unwrap();
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    storage.close().unwrap();
+
+    let mut uncompressed = options;
+    uncompressed.value_compression_threshold = None;
+    let Err(Error::InvalidArgument(msg)) = MiniLsm::open(&dir, uncompressed) else {
+        panic!("expected the reopen to be refused");
+    };
+    assert!(
+        msg.contains("value_compression is `snappy` but opened with `none`"),
+        "{msg}"
+    );
+}
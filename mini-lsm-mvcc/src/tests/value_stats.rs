@@ -0,0 +1,160 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::ops::Bound;
+
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    iterators::StorageIterator,
+    key::KeySlice,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    table::{FileObject, SsTable, SsTableBuilder, ValueSchema},
+};
+
+#[test]
+fn test_block_value_ranges() {
+    let dir = tempdir().unwrap();
+    let mut builder = SsTableBuilder::new(64);
+    builder.set_value_schema(ValueSchema::I64);
+    for i in 0..100i64 {
+        let key = format!("key{i:03}");
+        let value = if i == 50 {
+            b"text".to_vec()
+        } else {
+            (i - 20).to_be_bytes().to_vec()
+        };
+        builder.add(
+            KeySlice::for_testing_from_slice_with_ts(key.as_bytes(), 1),
+            &value,
+        );
+    }
+    let path = dir.path().join("1.sst");
+    builder.build_for_test(&path).unwrap();
+    let table = SsTable::open_for_test(FileObject::open(&path).unwrap()).unwrap();
+    let value_ranges = table.value_ranges().unwrap();
+    assert_eq!(value_ranges.schema, ValueSchema::I64);
+    assert_eq!(value_ranges.ranges.len(), table.num_of_blocks());
+
+    let order_key = |x: i64| ValueSchema::I64.order_key(&x.to_be_bytes()).unwrap();
+    // Negative numbers are ordered before positive ones
+    assert!(order_key(-1) < order_key(0));
+    let (first_min, _) = value_ranges.ranges[0].unwrap();
+    assert_eq!(first_min, order_key(-20));
+    assert!(value_ranges.ranges.iter().filter(|x| x.is_none()).count() == 1);
+    let skipped = (0..table.num_of_blocks())
+        .filter(|idx| !value_ranges.may_overlap(*idx, order_key(70), order_key(75)))
+        .count();
+    // Two entries per block: three blocks hold the range, besides the one holding the text
+    assert_eq!(skipped, table.num_of_blocks() - 4);
+}
+
+fn scan_filtered(storage: &MiniLsm, min: u64, max: u64) -> Vec<(Bytes, Bytes)> {
+    let mut iter = storage.scan(Bound::Unbounded, Bound::Unbounded).unwrap();
+    let mut entries = Vec::new();
+    while iter.is_valid() {
+        let value = u64::from_be_bytes(iter.value().try_into().unwrap());
+        if (min..=max).contains(&value) {
+            entries.push((
+                Bytes::copy_from_slice(iter.key()),
+                Bytes::copy_from_slice(iter.value()),
+            ));
+        }
+        iter.next().unwrap();
+    }
+    entries
+}
+
+#[test]
+fn test_scan_where_value_between() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week1_test();
+    options.block_size = 64;
+    options.value_schema = Some(ValueSchema::U64);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    for i in 0..300u64 {
+        storage
+            .put(format!("key{i:03}").as_bytes(), &(i * 10).to_be_bytes())
+            .unwrap();
+    }
+    storage.force_flush().unwrap();
+    // Move some keys out of the range and some into it, leaving stale versions in the flushed blocks
+    for i in (0..300u64).step_by(7) {
+        storage
+            .put(format!("key{i:03}").as_bytes(), &(5000 + i).to_be_bytes())
+            .unwrap();
+    }
+    storage.delete(b"key105").unwrap();
+    storage.force_flush().unwrap();
+    storage.put(b"key001", &1234u64.to_be_bytes()).unwrap();
+
+    for (min, max) in [(1000, 1500), (5000, 5100), (0, 0), (9000, 9999)] {
+        let entries = storage
+            .scan_where_value_between(
+                Bound::Unbounded,
+                Bound::Unbounded,
+                &u64::to_be_bytes(min),
+                &u64::to_be_bytes(max),
+            )
+            .unwrap();
+        assert_eq!(entries, scan_filtered(&storage, min, max));
+    }
+    let entries = storage
+        .scan_where_value_between(
+            Bound::Included(b"key120"),
+            Bound::Excluded(b"key130"),
+            &u64::to_be_bytes(1000),
+            &u64::to_be_bytes(1500),
+        )
+        .unwrap();
+    let keys: Vec<_> = entries.iter().map(|(key, _)| key.clone()).collect();
+    let expected: Vec<_> = (120..130)
+        .filter(|i| i % 7 != 0)
+        .map(|i| Bytes::from(format!("key{i:03}")))
+        .collect();
+    assert_eq!(keys, expected);
+
+    // Only the blocks of the first SST holding the range may be read
+    let snapshot = storage.inner.state.read().clone();
+    let order_key = |x: u64| ValueSchema::U64.order_key(&x.to_be_bytes()).unwrap();
+    let first_sst = &snapshot.sstables[snapshot.l0_sstables.last().unwrap()];
+    let value_ranges = first_sst.value_ranges().unwrap();
+    let kept = (0..first_sst.num_of_blocks())
+        .filter(|idx| value_ranges.may_overlap(*idx, order_key(1000), order_key(1500)))
+        .count();
+    assert!(kept * 5 < first_sst.num_of_blocks());
+
+    assert!(
+        storage
+            .scan_where_value_between(Bound::Unbounded, Bound::Unbounded, b"short", b"short")
+            .is_err()
+    );
+}
+
+#[test]
+fn test_scan_where_value_between_without_schema() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, LsmStorageOptions::default_for_week1_test()).unwrap();
+    storage.put(b"key", &1u64.to_be_bytes()).unwrap();
+    assert!(
+        storage
+            .scan_where_value_between(
+                Bound::Unbounded,
+                Bound::Unbounded,
+                &0u64.to_be_bytes(),
+                &9u64.to_be_bytes(),
+            )
+            .is_err()
+    );
+}
@@ -0,0 +1,96 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::path::Path;
+
+use tempfile::tempdir;
+
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm};
+
+const SEGMENT_SIZE: usize = 64 << 10;
+
+fn options() -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week1_test();
+    options.enable_wal = true;
+    options.wal_segment_size = Some(SEGMENT_SIZE);
+    options
+}
+
+fn wal_files(path: &Path) -> Vec<String> {
+    let mut files = std::fs::read_dir(path)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name.contains(".wal"))
+        .collect::<Vec<_>>();
+    files.sort();
+    files
+}
+
+#[test]
+fn test_wal_preallocated_and_recycled() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    let wal_path = dir.path().join(format!(
+        "{:05}.wal",
+        storage.inner.state.read().memtable.id()
+    ));
+    assert_eq!(wal_path.metadata().unwrap().len(), SEGMENT_SIZE as u64);
+    for i in 0..100 {
+        storage
+            .put(format!("key{i:03}").as_bytes(), b"value1")
+            .unwrap();
+    }
+    storage.inner.sync().unwrap();
+    assert_eq!(wal_path.metadata().unwrap().len(), SEGMENT_SIZE as u64);
+
+    storage.force_flush().unwrap();
+    assert_eq!(wal_files(dir.path()), vec!["00000.wal.free", "00001.wal"]);
+    storage.put(b"key000", b"value2").unwrap();
+    storage.force_flush().unwrap();
+    // The WAL of the first memtable is reused by the third one
+    assert_eq!(wal_files(dir.path()), vec!["00001.wal.free", "00002.wal"]);
+    let reused = dir.path().join("00002.wal");
+    assert_eq!(reused.metadata().unwrap().len(), SEGMENT_SIZE as u64);
+    assert!(std::fs::read(&reused).unwrap().iter().all(|x| *x == 0));
+
+    storage.put(b"key001", b"value2").unwrap();
+    storage.close().unwrap();
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    assert_eq!(&storage.get(b"key000").unwrap().unwrap()[..], b"value2");
+    assert_eq!(&storage.get(b"key001").unwrap().unwrap()[..], b"value2");
+    assert_eq!(&storage.get(b"key002").unwrap().unwrap()[..], b"value1");
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_recover_preallocated_wal_and_append() {
+    let dir = tempdir().unwrap();
+    let options = options();
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    storage.put(b"a", b"1").unwrap();
+    storage.close().unwrap();
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    assert_eq!(&storage.get(b"a").unwrap().unwrap()[..], b"1");
+    storage.put(b"b", b"2").unwrap();
+    storage.close().unwrap();
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(&storage.get(b"a").unwrap().unwrap()[..], b"1");
+    assert_eq!(&storage.get(b"b").unwrap().unwrap()[..], b"2");
+    storage.close().unwrap();
+}
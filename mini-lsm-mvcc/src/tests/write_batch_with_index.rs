@@ -0,0 +1,96 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use bytes::Bytes;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    lsm_storage::{LsmStorageOptions, MiniLsm},
+    write_batch::WriteBatchWithIndex,
+};
+
+fn open(dir: &tempfile::TempDir) -> std::sync::Arc<MiniLsm> {
+    MiniLsm::open(
+        dir,
+        LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction),
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_read_your_writes() {
+    let dir = tempdir().unwrap();
+    let storage = open(&dir);
+    storage.put(b"a", b"1").unwrap();
+    storage.put(b"b", b"2").unwrap();
+    storage.put(b"c", b"3").unwrap();
+
+    let mut batch = WriteBatchWithIndex::new();
+    batch.put(b"a", b"10");
+    batch.delete(b"b");
+    batch.put(b"d", b"4");
+    batch.put(b"d", b"40");
+    assert_eq!(batch.len(), 3);
+    assert_eq!(
+        batch.get_from_batch(b"a"),
+        Some(Some(Bytes::from_static(b"10")))
+    );
+    assert_eq!(batch.get_from_batch(b"b"), Some(None));
+    assert_eq!(batch.get_from_batch(b"c"), None);
+
+    let get = |key: &[u8]| batch.get_from_batch_and_db(&storage, key).unwrap();
+    assert_eq!(get(b"a").as_deref(), Some(&b"10"[..]));
+    assert_eq!(get(b"b"), None);
+    assert_eq!(get(b"c").as_deref(), Some(&b"3"[..]));
+    assert_eq!(get(b"d").as_deref(), Some(&b"40"[..]));
+    assert_eq!(get(b"e"), None);
+    // nothing is visible before committing
+    assert_eq!(storage.get(b"a").unwrap().as_deref(), Some(&b"1"[..]));
+    assert_eq!(storage.get(b"d").unwrap(), None);
+
+    storage.write_indexed_batch(&batch).unwrap();
+    assert_eq!(storage.get(b"a").unwrap().as_deref(), Some(&b"10"[..]));
+    assert_eq!(storage.get(b"b").unwrap(), None);
+    assert_eq!(storage.get(b"c").unwrap().as_deref(), Some(&b"3"[..]));
+    assert_eq!(storage.get(b"d").unwrap().as_deref(), Some(&b"40"[..]));
+
+    batch.clear();
+    assert!(batch.is_empty());
+    storage.write_indexed_batch(&batch).unwrap();
+}
+
+#[test]
+fn test_build_batch_from_current_state() {
+    let dir = tempdir().unwrap();
+    let storage = open(&dir);
+    storage.put(b"counter", b"0").unwrap();
+
+    // increment the same counter several times within one batch
+    let mut batch = WriteBatchWithIndex::new();
+    for _ in 0..5 {
+        let current = batch
+            .get_from_batch_and_db(&storage, b"counter")
+            .unwrap()
+            .map(|value| {
+                String::from_utf8(value.to_vec())
+                    .unwrap()
+                    .parse::<u64>()
+                    .unwrap()
+            })
+            .unwrap_or_default();
+        batch.put(b"counter", (current + 1).to_string().as_bytes());
+    }
+    storage.write_indexed_batch(&batch).unwrap();
+    assert_eq!(storage.get(b"counter").unwrap().as_deref(), Some(&b"5"[..]));
+}
@@ -0,0 +1,203 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use parking_lot::Mutex;
+use tempfile::tempdir;
+
+use crate::{
+    compact::CompactionOptions,
+    lsm_storage::{LsmStorageOptions, MiniLsm, WriteBatchRecord, WriteCallback},
+};
+
+type Change = (Bytes, Option<Bytes>);
+
+#[derive(Default)]
+struct RecordingCallback {
+    batches: Mutex<Vec<(u64, Vec<Change>)>>,
+}
+
+impl WriteCallback for RecordingCallback {
+    fn on_write(
+        &self,
+        commit_ts: u64,
+        batch: &[WriteBatchRecord<&[u8]>],
+    ) -> Vec<WriteBatchRecord<Bytes>> {
+        let changes = batch
+            .iter()
+            .map(|record| match record {
+                WriteBatchRecord::Put(key, value) => (
+                    Bytes::copy_from_slice(key),
+                    Some(Bytes::copy_from_slice(value)),
+                ),
+                WriteBatchRecord::Del(key) => (Bytes::copy_from_slice(key), None),
+            })
+            .collect();
+        self.batches.lock().push((commit_ts, changes));
+        Vec::new()
+    }
+}
+
+fn test_write_callback(serializable: bool) {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.serializable = serializable;
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.put(b"before", b"1").unwrap();
+    let callback = Arc::new(RecordingCallback::default());
+    storage.add_write_callback(callback.clone());
+
+    storage.put(b"a", b"1").unwrap();
+    storage
+        .write_batch(&[
+            WriteBatchRecord::Put(&b"b"[..], &b"2"[..]),
+            WriteBatchRecord::Del(&b"a"[..]),
+        ])
+        .unwrap();
+    let txn = storage.new_txn().unwrap();
+    txn.put(b"c", b"3");
+    txn.commit().unwrap();
+    // Rejected writes are not reported.
+    assert!(storage.put(b"", b"1").is_err());
+
+    let batches = callback.batches.lock();
+    assert_eq!(batches.len(), 3);
+    assert!(batches.windows(2).all(|w| w[0].0 < w[1].0));
+    assert_eq!(
+        batches[0].1,
+        vec![(Bytes::from("a"), Some(Bytes::from("1")))]
+    );
+    // With `serializable`, the batch is committed through a transaction, which writes in key order.
+    let mut batch = batches[1].1.clone();
+    batch.sort();
+    assert_eq!(
+        batch,
+        vec![
+            (Bytes::from("a"), None),
+            (Bytes::from("b"), Some(Bytes::from("2")))
+        ]
+    );
+    assert_eq!(
+        batches[2].1,
+        vec![(Bytes::from("c"), Some(Bytes::from("3")))]
+    );
+}
+
+#[test]
+fn test_write_callback_snapshot_isolation() {
+    test_write_callback(false);
+}
+
+#[test]
+fn test_write_callback_serializable() {
+    test_write_callback(true);
+}
+
+/// Panics on writes to the key `panic`.
+struct PanickingCallback;
+
+impl WriteCallback for PanickingCallback {
+    fn on_write(
+        &self,
+        _commit_ts: u64,
+        batch: &[WriteBatchRecord<&[u8]>],
+    ) -> Vec<WriteBatchRecord<Bytes>> {
+        if batch
+            .iter()
+            .any(|record| matches!(record, WriteBatchRecord::Put(b"panic", _)))
+        {
+            panic!("write callback failed");
+        }
+        Vec::new()
+    }
+}
+
+#[test]
+fn test_write_callback_panic() {
+    let dir = tempdir().unwrap();
+    let options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    storage.add_write_callback(Arc::new(PanickingCallback));
+    let result = std::panic::catch_unwind(AssertUnwindSafe(|| storage.put(b"panic", b"1")));
+    assert!(result.is_err());
+
+    // The commit ts of the failed write is published, so later writes do not wait for it
+    let (tx, rx) = std::sync::mpsc::channel();
+    let writer = storage.clone();
+    std::thread::spawn(move || tx.send(writer.put(b"after", b"1")).unwrap());
+    rx.recv_timeout(Duration::from_secs(10))
+        .expect("the write is blocked behind the failed one")
+        .unwrap();
+    assert_eq!(storage.get(b"after").unwrap(), Some(Bytes::from("1")));
+}
+
+/// Indexes the keys of the `user/` keyspace by value, under `by_value/<value>/<key>`.
+struct IndexCallback;
+
+impl WriteCallback for IndexCallback {
+    fn on_write(
+        &self,
+        _commit_ts: u64,
+        batch: &[WriteBatchRecord<&[u8]>],
+    ) -> Vec<WriteBatchRecord<Bytes>> {
+        batch
+            .iter()
+            .filter_map(|record| match record {
+                WriteBatchRecord::Put(key, value) if key.starts_with(b"user/") => {
+                    let index_key = [b"by_value/", *value, b"/", &key[5..]].concat();
+                    Some(WriteBatchRecord::Put(index_key.into(), Bytes::from("1")))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn test_write_callback_adds_records() {
+    let dir = tempdir().unwrap();
+    let mut options = LsmStorageOptions::default_for_week2_test(CompactionOptions::NoCompaction);
+    options.enable_wal = true;
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    storage.add_write_callback(Arc::new(IndexCallback));
+    storage.put(b"user/alice", b"admin").unwrap();
+    let ts = storage.inner.mvcc().latest_commit_ts();
+    // The index entry is committed at the timestamp of the batch
+    let get_at = |key: &[u8], read_ts: u64| {
+        storage
+            .inner
+            .get_with_options(key, read_ts, &Default::default())
+            .unwrap()
+    };
+    assert_eq!(get_at(b"by_value/admin/alice", ts - 1), None);
+    assert_eq!(get_at(b"by_value/admin/alice", ts), Some(Bytes::from("1")));
+
+    // A record the callback adds fails the batch if it is invalid
+    let long_value = vec![b'x'; options.max_key_size];
+    assert!(storage.put(b"user/bob", &long_value).is_err());
+    assert_eq!(storage.get(b"user/bob").unwrap(), None);
+    storage.close().unwrap();
+    drop(storage);
+
+    // The index entry is logged with the batch
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(
+        storage.get(b"by_value/admin/alice").unwrap(),
+        Some(Bytes::from("1"))
+    );
+}
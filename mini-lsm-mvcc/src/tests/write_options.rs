@@ -0,0 +1,106 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use tempfile::tempdir;
+
+use crate::lsm_storage::{LsmStorageOptions, MiniLsm, WriteBatchRecord, WriteOptions};
+
+fn options() -> LsmStorageOptions {
+    let mut options = LsmStorageOptions::default_for_week1_test();
+    options.enable_wal = true;
+    options
+}
+
+#[test]
+fn test_write_options_disable_wal() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    let no_wal = WriteOptions {
+        disable_wal: true,
+        ..Default::default()
+    };
+    storage.put_with_options(b"a", b"1", &no_wal).unwrap();
+    storage
+        .write_batch_with_options(
+            &[
+                WriteBatchRecord::Put(b"b", b"1"),
+                WriteBatchRecord::Del(b"c"),
+            ],
+            &no_wal,
+        )
+        .unwrap();
+    storage.put(b"c", b"1").unwrap();
+    storage.put(b"d", b"1").unwrap();
+    storage.delete_with_options(b"d", &no_wal).unwrap();
+    assert_eq!(storage.get(b"a").unwrap(), Some("1".into()));
+    assert_eq!(storage.get(b"d").unwrap(), None);
+    // Closing keeps the memtable in the WAL only, so the writes that skipped it are lost as in a crash
+    storage.close().unwrap();
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    assert_eq!(storage.get(b"a").unwrap(), None);
+    assert_eq!(storage.get(b"b").unwrap(), None);
+    assert_eq!(storage.get(b"c").unwrap(), Some("1".into()));
+    assert_eq!(storage.get(b"d").unwrap(), Some("1".into()));
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_write_options_sync() {
+    let dir = tempdir().unwrap();
+    let storage = MiniLsm::open(&dir, options()).unwrap();
+    let wal_path = dir.path().join(format!(
+        "{:05}.wal",
+        storage.inner.state.read().memtable.id()
+    ));
+    storage.put(b"a", b"1").unwrap();
+    // The WAL is buffered until synced
+    assert_eq!(wal_path.metadata().unwrap().len(), 0);
+    let sync = WriteOptions {
+        sync: true,
+        ..Default::default()
+    };
+    storage.put_with_options(b"b", b"1", &sync).unwrap();
+    let synced_len = wal_path.metadata().unwrap().len();
+    assert!(synced_len > 0);
+
+    storage.delete_with_options(b"a", &sync).unwrap();
+    assert!(wal_path.metadata().unwrap().len() > synced_len);
+    storage.close().unwrap();
+}
+
+#[test]
+fn test_write_options_serializable() {
+    let dir = tempdir().unwrap();
+    let mut options = options();
+    options.serializable = true;
+    let storage = MiniLsm::open(&dir, options.clone()).unwrap();
+    let no_wal = WriteOptions {
+        disable_wal: true,
+        ..Default::default()
+    };
+    storage.put_with_options(b"a", b"1", &no_wal).unwrap();
+    let txn = storage.new_txn().unwrap();
+    txn.put(b"b", b"1");
+    txn.commit_with_options(&no_wal).unwrap();
+    storage.put(b"c", b"1").unwrap();
+    storage.close().unwrap();
+    drop(storage);
+
+    let storage = MiniLsm::open(&dir, options).unwrap();
+    assert_eq!(storage.get(b"a").unwrap(), None);
+    assert_eq!(storage.get(b"b").unwrap(), None);
+    assert_eq!(storage.get(b"c").unwrap(), Some("1".into()));
+    storage.close().unwrap();
+}
@@ -0,0 +1,389 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Recording the operations on the storage into a binary trace, and replaying a trace, e.g., to reproduce a
+//! performance or correctness issue on a fresh instance.
+//!
+//! A trace starts with `TRACE_MAGIC`, followed by one record per operation:
+//!
+//! ```text
+//! | op (u8) | elapsed micros (u64) | thread id (u32) | ts (u64) | payload |
+//! ```
+//!
+//! A put carries its key and value, a delete or a get its key, and a scan its bounds, each as a u8 kind (unbounded,
+//! included, excluded) followed by the key of bounded ones. Keys and values are prefixed with their u16 length. Writes
+//! are recorded with their commit ts in commit order, and reads with their read ts.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, bail};
+use bytes::{BufMut, Bytes};
+use parking_lot::Mutex;
+
+use crate::iterators::StorageIterator;
+use crate::lsm_error::{self, Error};
+use crate::lsm_storage::{MiniLsm, WriteBatchRecord};
+
+pub const TRACE_MAGIC: &[u8; 8] = b"MLSMTRC1";
+
+const OP_PUT: u8 = 0;
+const OP_DELETE: u8 = 1;
+const OP_GET: u8 = 2;
+const OP_SCAN: u8 = 3;
+
+const BOUND_UNBOUNDED: u8 = 0;
+const BOUND_INCLUDED: u8 = 1;
+const BOUND_EXCLUDED: u8 = 2;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TraceOp {
+    Put {
+        key: Bytes,
+        value: Bytes,
+    },
+    Delete {
+        key: Bytes,
+    },
+    Get {
+        key: Bytes,
+    },
+    Scan {
+        lower: Bound<Bytes>,
+        upper: Bound<Bytes>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceRecord {
+    /// The time since tracing started, in microseconds.
+    pub elapsed: Duration,
+    /// Numbers the threads of the process in the order they first recorded an operation.
+    pub thread_id: u32,
+    /// The commit ts of a write or the read ts of a read.
+    pub ts: u64,
+    pub op: TraceOp,
+}
+
+static NEXT_THREAD_ID: AtomicU32 = AtomicU32::new(0);
+
+thread_local! {
+    static THREAD_ID: u32 = NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed);
+}
+
+fn put_bytes(buf: &mut Vec<u8>, data: &[u8]) {
+    buf.put_u16(data.len() as u16);
+    buf.put_slice(data);
+}
+
+fn put_bound(buf: &mut Vec<u8>, bound: Bound<&[u8]>) {
+    match bound {
+        Bound::Unbounded => buf.put_u8(BOUND_UNBOUNDED),
+        Bound::Included(key) => {
+            buf.put_u8(BOUND_INCLUDED);
+            put_bytes(buf, key);
+        }
+        Bound::Excluded(key) => {
+            buf.put_u8(BOUND_EXCLUDED);
+            put_bytes(buf, key);
+        }
+    }
+}
+
+struct TraceWriter {
+    file: BufWriter<File>,
+    start: Instant,
+    num_records: u64,
+    buf: Vec<u8>,
+}
+
+/// Records the operations into a trace file while tracing is started.
+#[derive(Default)]
+pub(crate) struct Tracer {
+    /// Checked before taking the lock, so that the operations skip tracing cheaply while it is stopped.
+    enabled: AtomicBool,
+    writer: Mutex<Option<TraceWriter>>,
+}
+
+impl Tracer {
+    pub fn start(&self, path: &Path) -> Result<()> {
+        let mut writer = self.writer.lock();
+        if writer.is_some() {
+            bail!(Error::InvalidArgument(
+                "tracing is already started".to_string()
+            ));
+        }
+        let mut file = BufWriter::new(
+            File::options()
+                .write(true)
+                .create_new(true)
+                .open(path)
+                .context("failed to create trace file")?,
+        );
+        file.write_all(TRACE_MAGIC)?;
+        *writer = Some(TraceWriter {
+            file,
+            start: Instant::now(),
+            num_records: 0,
+            buf: Vec::new(),
+        });
+        self.enabled.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Flush and close the trace file, returning the number of records written.
+    pub fn stop(&self) -> Result<u64> {
+        let mut writer = self.writer.lock();
+        self.enabled.store(false, Ordering::SeqCst);
+        let Some(mut writer) = writer.take() else {
+            bail!(Error::InvalidArgument("tracing is not started".to_string()));
+        };
+        writer.file.flush()?;
+        writer.file.get_ref().sync_all()?;
+        Ok(writer.num_records)
+    }
+
+    fn record(&self, ts: u64, op: u8, encode_payload: impl Fn(&mut Vec<u8>)) {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+        let mut guard = self.writer.lock();
+        let Some(writer) = guard.as_mut() else {
+            return;
+        };
+        let mut buf = std::mem::take(&mut writer.buf);
+        buf.clear();
+        buf.put_u8(op);
+        buf.put_u64(writer.start.elapsed().as_micros() as u64);
+        buf.put_u32(THREAD_ID.with(|id| *id));
+        buf.put_u64(ts);
+        encode_payload(&mut buf);
+        // Tracing must not fail the operation, so a broken trace file stops tracing instead
+        if let Err(e) = writer.file.write_all(&buf) {
+            eprintln!("failed to write trace, stopping tracing: {}", e);
+            self.enabled.store(false, Ordering::SeqCst);
+            guard.take();
+            return;
+        }
+        writer.num_records += 1;
+        writer.buf = buf;
+    }
+
+    pub fn record_writes<T: AsRef<[u8]>>(&self, ts: u64, batch: &[WriteBatchRecord<T>]) {
+        for record in batch {
+            match record {
+                WriteBatchRecord::Put(key, value) => self.record(ts, OP_PUT, |buf| {
+                    put_bytes(buf, key.as_ref());
+                    put_bytes(buf, value.as_ref());
+                }),
+                WriteBatchRecord::Del(key) => {
+                    self.record(ts, OP_DELETE, |buf| put_bytes(buf, key.as_ref()))
+                }
+            }
+        }
+    }
+
+    pub fn record_get(&self, ts: u64, key: &[u8]) {
+        self.record(ts, OP_GET, |buf| put_bytes(buf, key));
+    }
+
+    pub fn record_scan(&self, ts: u64, lower: Bound<&[u8]>, upper: Bound<&[u8]>) {
+        self.record(ts, OP_SCAN, |buf| {
+            put_bound(buf, lower);
+            put_bound(buf, upper);
+        });
+    }
+}
+
+/// Reads the records of a trace file in order.
+pub struct TraceReader<R: Read> {
+    reader: R,
+}
+
+impl TraceReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::open(path).context("failed to open trace file")?;
+        Self::new(BufReader::new(file))
+    }
+}
+
+impl<R: Read> TraceReader<R> {
+    pub fn new(mut reader: R) -> Result<Self> {
+        let mut magic = [0; TRACE_MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if &magic != TRACE_MAGIC {
+            bail!(Error::Corruption("not a trace file".to_string()));
+        }
+        Ok(Self { reader })
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut data = [0; N];
+        self.reader
+            .read_exact(&mut data)
+            .map_err(|_| Error::Corruption("trace record is truncated".to_string()))?;
+        Ok(data)
+    }
+
+    fn read_bytes(&mut self) -> Result<Bytes> {
+        let len = u16::from_be_bytes(self.read_array()?) as usize;
+        let mut data = vec![0; len];
+        self.reader
+            .read_exact(&mut data)
+            .map_err(|_| Error::Corruption("trace record is truncated".to_string()))?;
+        Ok(data.into())
+    }
+
+    fn read_bound(&mut self) -> Result<Bound<Bytes>> {
+        match self.read_array::<1>()?[0] {
+            BOUND_UNBOUNDED => Ok(Bound::Unbounded),
+            BOUND_INCLUDED => Ok(Bound::Included(self.read_bytes()?)),
+            BOUND_EXCLUDED => Ok(Bound::Excluded(self.read_bytes()?)),
+            kind => bail!(Error::Corruption(format!("unknown bound kind {kind}"))),
+        }
+    }
+
+    fn read_record(&mut self, op: u8) -> Result<TraceRecord> {
+        let elapsed = Duration::from_micros(u64::from_be_bytes(self.read_array()?));
+        let thread_id = u32::from_be_bytes(self.read_array()?);
+        let ts = u64::from_be_bytes(self.read_array()?);
+        let op = match op {
+            OP_PUT => TraceOp::Put {
+                key: self.read_bytes()?,
+                value: self.read_bytes()?,
+            },
+            OP_DELETE => TraceOp::Delete {
+                key: self.read_bytes()?,
+            },
+            OP_GET => TraceOp::Get {
+                key: self.read_bytes()?,
+            },
+            OP_SCAN => TraceOp::Scan {
+                lower: self.read_bound()?,
+                upper: self.read_bound()?,
+            },
+            op => bail!(Error::Corruption(format!("unknown trace op {op}"))),
+        };
+        Ok(TraceRecord {
+            elapsed,
+            thread_id,
+            ts,
+            op,
+        })
+    }
+}
+
+impl<R: Read> Iterator for TraceReader<R> {
+    type Item = Result<TraceRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut op = [0];
+        match self.reader.read(&mut op) {
+            Ok(0) => None,
+            Ok(_) => Some(self.read_record(op[0])),
+            Err(e) => Some(Err(e.into())),
+        }
+    }
+}
+
+/// What a replay did, to compare runs of the same trace.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ReplaySummary {
+    /// The write batches committed, each made of the writes recorded with the same commit ts.
+    pub batches: u64,
+    pub puts: u64,
+    pub deletes: u64,
+    pub gets: u64,
+    /// The gets that found a value.
+    pub gets_found: u64,
+    pub scans: u64,
+    /// The entries read by all scans.
+    pub scanned_entries: u64,
+}
+
+impl MiniLsm {
+    /// Record every put, delete, get and scan into a new trace file at `path` until `stop_trace`, including the reads
+    /// and writes of transactions. Fails with `Error::InvalidArgument` if tracing is already started.
+    pub fn start_trace(&self, path: impl AsRef<Path>) -> lsm_error::Result<()> {
+        Ok(self.inner.tracer.start(path.as_ref())?)
+    }
+
+    /// Stop tracing and flush the trace file. Returns the number of records written.
+    pub fn stop_trace(&self) -> lsm_error::Result<u64> {
+        Ok(self.inner.tracer.stop()?)
+    }
+
+    /// Re-execute the operations of a trace one after another in the order they were recorded, which is
+    /// deterministic for a fresh instance. The writes of each recorded commit are written as one batch. With
+    /// `preserve_timing`, waits until each operation is as far from the start as it was when recorded.
+    pub fn replay_trace(
+        &self,
+        records: impl IntoIterator<Item = Result<TraceRecord>>,
+        preserve_timing: bool,
+    ) -> lsm_error::Result<ReplaySummary> {
+        let start = Instant::now();
+        let mut summary = ReplaySummary::default();
+        let mut batch: Vec<WriteBatchRecord<Bytes>> = Vec::new();
+        let mut batch_ts = None;
+        for record in records {
+            let record = record?;
+            let is_write = matches!(record.op, TraceOp::Put { .. } | TraceOp::Delete { .. });
+            if !batch.is_empty() && (!is_write || batch_ts != Some(record.ts)) {
+                self.write_batch(&batch)?;
+                batch.clear();
+                summary.batches += 1;
+            }
+            if preserve_timing && let Some(wait) = record.elapsed.checked_sub(start.elapsed()) {
+                std::thread::sleep(wait);
+            }
+            match record.op {
+                TraceOp::Put { key, value } => {
+                    batch.push(WriteBatchRecord::Put(key, value));
+                    batch_ts = Some(record.ts);
+                    summary.puts += 1;
+                }
+                TraceOp::Delete { key } => {
+                    batch.push(WriteBatchRecord::Del(key));
+                    batch_ts = Some(record.ts);
+                    summary.deletes += 1;
+                }
+                TraceOp::Get { key } => {
+                    summary.gets += 1;
+                    if self.get(&key)?.is_some() {
+                        summary.gets_found += 1;
+                    }
+                }
+                TraceOp::Scan { lower, upper } => {
+                    summary.scans += 1;
+                    let mut iter = self.scan(
+                        lower.as_ref().map(|key| &key[..]),
+                        upper.as_ref().map(|key| &key[..]),
+                    )?;
+                    while iter.is_valid() {
+                        summary.scanned_entries += 1;
+                        iter.next()?;
+                    }
+                }
+            }
+        }
+        if !batch.is_empty() {
+            self.write_batch(&batch)?;
+            summary.batches += 1;
+        }
+        Ok(summary)
+    }
+}
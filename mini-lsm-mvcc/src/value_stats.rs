@@ -0,0 +1,157 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//! Scans filtering on numeric values, skipping the SST blocks whose value range recorded with
+//! `LsmStorageOptions::value_schema` is out of the range of the scan.
+//!
+//! A key whose latest version is in range is either in a memtable or in a block that cannot be skipped, so the scan
+//! collects the key ranges of those and only reads them, through a transaction to resolve the versions.
+
+use std::ops::{Bound, RangeBounds};
+use std::sync::Arc;
+
+use anyhow::{Result, bail};
+use bytes::Bytes;
+
+use crate::iterators::StorageIterator;
+use crate::key;
+use crate::lsm_error::{self, Error};
+use crate::lsm_storage::{LsmStorageInner, LsmStorageState, MiniLsm, range_overlap};
+use crate::mem_table::map_key_bound_plus_ts;
+use crate::table::ValueSchema;
+
+impl LsmStorageInner {
+    /// Get the latest entries of the range whose value is a number within `min..=max`, both encoded as the values.
+    /// Fails with `Error::InvalidArgument` if the storage has no value schema or `min` or `max` is not a number.
+    pub fn scan_where_value_between(
+        self: &Arc<Self>,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        min: &[u8],
+        max: &[u8],
+    ) -> Result<Vec<(Bytes, Bytes)>> {
+        let Some(schema) = self.options().value_schema else {
+            bail!(Error::InvalidArgument(
+                "value filtering requires a value schema".to_string()
+            ));
+        };
+        let (Some(min), Some(max)) = (schema.order_key(min), schema.order_key(max)) else {
+            bail!(Error::InvalidArgument(format!(
+                "value range bounds must be 8-byte {schema:?} numbers"
+            )));
+        };
+        // the snapshot is taken after the transaction, so that it holds everything the transaction reads
+        let txn = self.new_txn()?;
+        let candidates = candidate_ranges(&self.snapshot(), schema, lower, upper, min, max);
+        let mut entries = Vec::new();
+        for (begin, end) in candidates {
+            let mut iter = txn.scan(Bound::Included(&begin), Bound::Included(&end))?;
+            while iter.is_valid() {
+                let in_range = schema
+                    .order_key(iter.value())
+                    .is_some_and(|value| (min..=max).contains(&value));
+                if in_range && RangeBounds::<[u8]>::contains(&(lower, upper), iter.key()) {
+                    entries.push((
+                        Bytes::copy_from_slice(iter.key()),
+                        Bytes::copy_from_slice(iter.value()),
+                    ));
+                }
+                iter.next()?;
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// The sorted, disjoint and inclusive key ranges that may hold a key of `lower..upper` whose latest value is within
+/// `min..=max`: the keys in the memtables with such a value and the blocks whose value range overlaps it.
+fn candidate_ranges(
+    snapshot: &LsmStorageState,
+    schema: ValueSchema,
+    lower: Bound<&[u8]>,
+    upper: Bound<&[u8]>,
+    min: u64,
+    max: u64,
+) -> Vec<(Bytes, Bytes)> {
+    let mut ranges = Vec::new();
+    let (begin, end) = map_key_bound_plus_ts(lower, upper, key::TS_RANGE_BEGIN);
+    for memtable in std::iter::once(&snapshot.memtable).chain(snapshot.imm_memtables.iter()) {
+        let mut iter = memtable.scan(begin, end);
+        while iter.is_valid() {
+            if schema
+                .order_key(iter.value())
+                .is_some_and(|value| (min..=max).contains(&value))
+            {
+                let key = Bytes::copy_from_slice(iter.key().key_ref());
+                ranges.push((key.clone(), key));
+            }
+            // iterating a memtable does not fail
+            iter.next().unwrap();
+        }
+    }
+    let level_ssts = snapshot.levels.iter().flat_map(|(_, ids)| ids);
+    for id in snapshot.l0_sstables.iter().chain(level_ssts) {
+        let table = &snapshot.sstables[id];
+        if !range_overlap(
+            lower,
+            upper,
+            table.first_key().as_key_slice(),
+            table.last_key().as_key_slice(),
+        ) {
+            continue;
+        }
+        let value_ranges = table.value_ranges().filter(|x| x.schema == schema);
+        for (idx, meta) in table.block_meta().iter().enumerate() {
+            // SSTs written without the schema have nothing to skip blocks by
+            let may_match = value_ranges.is_none_or(|x| x.may_overlap(idx, min, max));
+            if may_match
+                && range_overlap(
+                    lower,
+                    upper,
+                    meta.first_key.as_key_slice(),
+                    meta.last_key.as_key_slice(),
+                )
+            {
+                ranges.push((
+                    Bytes::copy_from_slice(meta.first_key.key_ref()),
+                    Bytes::copy_from_slice(meta.last_key.key_ref()),
+                ));
+            }
+        }
+    }
+    ranges.sort();
+    let mut merged: Vec<(Bytes, Bytes)> = Vec::with_capacity(ranges.len());
+    for (begin, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if begin <= last.1 => last.1 = last.1.clone().max(end),
+            _ => merged.push((begin, end)),
+        }
+    }
+    merged
+}
+
+impl MiniLsm {
+    /// Get the latest entries of the range whose value is a number within `min..=max`, skipping the SST blocks
+    /// recorded to hold no such value. Requires `LsmStorageOptions::value_schema`.
+    pub fn scan_where_value_between(
+        &self,
+        lower: Bound<&[u8]>,
+        upper: Bound<&[u8]>,
+        min: &[u8],
+        max: &[u8],
+    ) -> lsm_error::Result<Vec<(Bytes, Bytes)>> {
+        Ok(self
+            .inner
+            .scan_where_value_between(lower, upper, min, max)?)
+    }
+}
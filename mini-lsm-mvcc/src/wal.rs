@@ -12,10 +12,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::fs::{File, OpenOptions};
 use std::hash::Hasher;
-use std::io::{BufWriter, Read, Write};
-use std::path::Path;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use anyhow::{Context, Result, bail};
@@ -23,80 +22,151 @@ use bytes::{Buf, BufMut, Bytes};
 use crossbeam_skiplist::SkipMap;
 use parking_lot::Mutex;
 
+use crate::file_system::{FileSystem, FileSystemOptions, WriteFile, Writer};
+use crate::io_retry::{IoRetry, IoTarget};
 use crate::key::{KeyBytes, KeySlice};
+use crate::lsm_error::Error;
 
 pub struct Wal {
-    file: Arc<Mutex<BufWriter<File>>>,
+    file: Arc<Mutex<BufWriter<Writer>>>,
+    io_retry: Arc<IoRetry>,
 }
 
 impl Wal {
     pub fn create(path: impl AsRef<Path>) -> Result<Self> {
-        Ok(Self {
-            file: Arc::new(Mutex::new(BufWriter::new(
-                OpenOptions::new()
-                    .read(true)
-                    .create_new(true)
-                    .write(true)
-                    .open(path)
-                    .context("failed to create WAL")?,
-            ))),
-        })
+        Self::create_in(&FileSystemOptions::Std, path)
+    }
+
+    /// Create a WAL like `create`, in `file_system`.
+    pub fn create_in(file_system: &dyn FileSystem, path: impl AsRef<Path>) -> Result<Self> {
+        let file = file_system
+            .create_new(path.as_ref())
+            .context("failed to create WAL")?;
+        Ok(Self::new(file.into()))
+    }
+
+    fn new(file: Arc<dyn WriteFile>) -> Self {
+        Self {
+            file: Arc::new(Mutex::new(BufWriter::new(Writer(file)))),
+            io_retry: Arc::default(),
+        }
+    }
+
+    /// Create a WAL in a file of `segment_size` zero bytes, so that appends overwrite the zeros instead of growing the
+    /// file. A file recycled by `pool` is reused if there is one.
+    pub(crate) fn create_preallocated(
+        path: impl AsRef<Path>,
+        segment_size: usize,
+        pool: &WalPool,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let file_system = &pool.file_system;
+        let file = match pool.take() {
+            Some(recycled) => {
+                file_system
+                    .rename(&recycled, path)
+                    .context("failed to reuse WAL")?;
+                file_system.open_write(path)?
+            }
+            None => {
+                let file = file_system
+                    .create_new(path)
+                    .context("failed to create WAL")?;
+                zero_fill(&*file, segment_size)?;
+                file
+            }
+        };
+        Ok(Self::new(file.into()))
     }
 
     pub fn recover(path: impl AsRef<Path>, skiplist: &SkipMap<KeyBytes, Bytes>) -> Result<Self> {
+        Self::recover_in(&FileSystemOptions::Std, path, skiplist)
+    }
+
+    /// Recover a WAL like `recover`, from `file_system`.
+    pub fn recover_in(
+        file_system: &dyn FileSystem,
+        path: impl AsRef<Path>,
+        skiplist: &SkipMap<KeyBytes, Bytes>,
+    ) -> Result<Self> {
         let path = path.as_ref();
-        let mut file = OpenOptions::new()
-            .read(true)
-            .append(true)
-            .open(path)
+        let buf = file_system
+            .read(path)
             .context("failed to recover from WAL")?;
-        let mut buf = Vec::new();
-        file.read_to_end(&mut buf)?;
         let mut rbuf: &[u8] = buf.as_slice();
         while rbuf.has_remaining() {
-            let batch_size = rbuf.get_u32() as usize;
-            if rbuf.remaining() < batch_size {
-                bail!("incomplete WAL");
-            }
-            let mut batch_buf = &rbuf[..batch_size];
-            let mut kv_pairs = Vec::new();
-            let mut hasher = crc32fast::Hasher::new();
-            // The checksum computed from the individual components should be the same as a direct checksum on the buffer.
-            // Students' implementation only needs to do a single checksum on the buffer. We compute both for verification purpose.
-            let single_checksum = crc32fast::hash(batch_buf);
-            while batch_buf.has_remaining() {
-                let key_len = batch_buf.get_u16() as usize;
-                hasher.write(&(key_len as u16).to_be_bytes());
-                let key = Bytes::copy_from_slice(&batch_buf[..key_len]);
-                hasher.write(&key);
-                batch_buf.advance(key_len);
-                let ts = batch_buf.get_u64();
-                hasher.write(&ts.to_be_bytes());
-                let value_len = batch_buf.get_u16() as usize;
-                hasher.write(&(value_len as u16).to_be_bytes());
-                let value = Bytes::copy_from_slice(&batch_buf[..value_len]);
-                hasher.write(&value);
-                kv_pairs.push((key, ts, value));
-                batch_buf.advance(value_len);
-            }
-            rbuf.advance(batch_size);
-            let expected_checksum = rbuf.get_u32();
-            let component_checksum = hasher.finalize();
-            assert_eq!(component_checksum, single_checksum);
-            if single_checksum != expected_checksum {
-                bail!("checksum mismatch");
-            }
+            let Some(kv_pairs) = Self::decode_batch(&mut rbuf)? else {
+                // A preallocated WAL is zero-filled after the last batch
+                if rbuf.iter().all(|x| *x == 0) {
+                    break;
+                }
+                bail!(Error::Corruption("incomplete WAL".to_string()));
+            };
             for (key, ts, value) in kv_pairs {
                 skiplist.insert(KeyBytes::from_bytes_with_ts(key, ts), value);
             }
         }
-        Ok(Self {
-            file: Arc::new(Mutex::new(BufWriter::new(file))),
-        })
+        let file = file_system
+            .open_write(path)
+            .context("failed to recover from WAL")?;
+        // Append after the last batch rather than at the end of the file
+        file.seek((buf.len() - rbuf.len()) as u64)?;
+        Ok(Self::new(file.into()))
+    }
+
+    pub(crate) fn set_io_retry(&mut self, io_retry: Arc<IoRetry>) {
+        self.io_retry = io_retry;
+    }
+
+    /// Decode the batch at the beginning of `buf` and advance past it, or return `None` without advancing if `buf` does
+    /// not hold a complete batch. Empty batches are never written, so a zero batch size marks the zero-filled end of a
+    /// preallocated WAL.
+    pub(crate) fn decode_batch(buf: &mut &[u8]) -> Result<Option<Vec<(Bytes, u64, Bytes)>>> {
+        let mut rbuf = *buf;
+        if rbuf.remaining() < 4 {
+            return Ok(None);
+        }
+        let batch_size = rbuf.get_u32() as usize;
+        if batch_size == 0 || rbuf.remaining() < batch_size + 4 {
+            return Ok(None);
+        }
+        let mut batch_buf = &rbuf[..batch_size];
+        let mut kv_pairs = Vec::new();
+        let mut hasher = crc32fast::Hasher::new();
+        // The checksum computed from the individual components should be the same as a direct checksum on the buffer.
+        // Students' implementation only needs to do a single checksum on the buffer. We compute both for verification purpose.
+        let single_checksum = crc32fast::hash(batch_buf);
+        while batch_buf.has_remaining() {
+            let key_len = batch_buf.get_u16() as usize;
+            hasher.write(&(key_len as u16).to_be_bytes());
+            let key = Bytes::copy_from_slice(&batch_buf[..key_len]);
+            hasher.write(&key);
+            batch_buf.advance(key_len);
+            let ts = batch_buf.get_u64();
+            hasher.write(&ts.to_be_bytes());
+            let value_len = batch_buf.get_u16() as usize;
+            hasher.write(&(value_len as u16).to_be_bytes());
+            let value = Bytes::copy_from_slice(&batch_buf[..value_len]);
+            hasher.write(&value);
+            kv_pairs.push((key, ts, value));
+            batch_buf.advance(value_len);
+        }
+        rbuf.advance(batch_size);
+        let expected_checksum = rbuf.get_u32();
+        let component_checksum = hasher.finalize();
+        assert_eq!(component_checksum, single_checksum);
+        if single_checksum != expected_checksum {
+            bail!(Error::Corruption("WAL checksum mismatch".to_string()));
+        }
+        *buf = rbuf;
+        Ok(Some(kv_pairs))
     }
 
     /// Implement this in week 3, day 5.
     pub fn put_batch(&self, data: &[(KeySlice, &[u8])]) -> Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
         let mut file = self.file.lock();
         let mut buf = Vec::<u8>::new();
         for (key, value) in data {
@@ -106,12 +176,21 @@ impl Wal {
             buf.put_u16(value.len() as u16);
             buf.put_slice(value);
         }
+        let retry = &self.io_retry;
         // write batch_size header (u32)
-        file.write_all(&(buf.len() as u32).to_be_bytes())?;
+        retry.write_all(
+            IoTarget::WalWrite,
+            &mut *file,
+            &(buf.len() as u32).to_be_bytes(),
+        )?;
         // write key-value pairs body
-        file.write_all(&buf)?;
+        retry.write_all(IoTarget::WalWrite, &mut *file, &buf)?;
         // write checksum (u32)
-        file.write_all(&crc32fast::hash(&buf).to_be_bytes())?;
+        retry.write_all(
+            IoTarget::WalWrite,
+            &mut *file,
+            &crc32fast::hash(&buf).to_be_bytes(),
+        )?;
         Ok(())
     }
 
@@ -120,9 +199,80 @@ impl Wal {
     }
 
     pub fn sync(&self) -> Result<()> {
-        let mut file = self.file.lock();
-        file.flush()?;
-        file.get_mut().sync_all()?;
+        let file = {
+            let mut file = self.file.lock();
+            // BufWriter keeps the bytes it failed to write, so retrying a flush does not duplicate them
+            self.io_retry.run(IoTarget::WalWrite, || file.flush())?;
+            file.get_ref().0.clone()
+        };
+        // fsync without holding the lock, so that later batches are appended meanwhile
+        self.io_retry.run(IoTarget::WalWrite, || file.sync())?;
+        Ok(())
+    }
+}
+
+/// Overwrite the first `size` bytes of the file with zeros, and cut off anything after them.
+fn zero_fill(file: &dyn WriteFile, size: usize) -> Result<()> {
+    const CHUNK_SIZE: usize = 64 << 10;
+    let zeros = vec![0; CHUNK_SIZE.min(size)];
+    file.seek(0)?;
+    let mut remaining = size;
+    while remaining > 0 {
+        let len = remaining.min(CHUNK_SIZE);
+        file.write_all(&zeros[..len])?;
+        remaining -= len;
+    }
+    file.set_len(size as u64)?;
+    file.sync()?;
+    file.seek(0)?;
+    Ok(())
+}
+
+/// WALs of flushed memtables kept for reuse by [`Wal::create_preallocated`]. They are zero-filled and renamed to
+/// `<id>.wal.free`, so that they survive a restart and are never replayed.
+#[derive(Default)]
+pub(crate) struct WalPool {
+    file_system: FileSystemOptions,
+    files: Mutex<Vec<PathBuf>>,
+}
+
+/// The suffix appended to the name of a WAL kept for reuse.
+pub(crate) const RECYCLED_WAL_SUFFIX: &str = ".free";
+
+impl WalPool {
+    /// Collect the WALs left for reuse in `dir` of `file_system`.
+    pub(crate) fn open(file_system: FileSystemOptions, dir: impl AsRef<Path>) -> Result<Self> {
+        let mut files = Vec::new();
+        for path in file_system.list_dir(dir.as_ref())? {
+            if path
+                .to_str()
+                .is_some_and(|name| name.ends_with(&format!(".wal{RECYCLED_WAL_SUFFIX}")))
+            {
+                files.push(path);
+            }
+        }
+        Ok(Self {
+            file_system,
+            files: Mutex::new(files),
+        })
+    }
+
+    fn take(&self) -> Option<PathBuf> {
+        self.files.lock().pop()
+    }
+
+    /// Keep the WAL at `path` for reuse, or remove it if `capacity` WALs are kept already.
+    pub(crate) fn recycle(&self, path: &Path, segment_size: usize, capacity: usize) -> Result<()> {
+        let mut files = self.files.lock();
+        if files.len() >= capacity {
+            self.file_system.remove_file(path)?;
+            return Ok(());
+        }
+        zero_fill(&*self.file_system.open_write(path)?, segment_size)?;
+        let mut free_path = path.as_os_str().to_owned();
+        free_path.push(RECYCLED_WAL_SUFFIX);
+        self.file_system.rename(path, Path::new(&free_path))?;
+        files.push(free_path.into());
         Ok(())
     }
 }
@@ -0,0 +1,97 @@
+// Copyright (c) 2022-2025 Alex Chi Z
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A write batch indexed by key, so that a batch built up from the current state of the storage can read its own
+//! staged writes before it is committed.
+
+use std::collections::BTreeMap;
+
+use bytes::Bytes;
+
+use crate::lsm_error;
+use crate::lsm_storage::{MiniLsm, WriteBatchRecord};
+
+/// Staged puts and deletes, keeping only the latest write of each key.
+#[derive(Clone, Debug, Default)]
+pub struct WriteBatchWithIndex {
+    /// The value staged for each key, or `None` for a delete.
+    writes: BTreeMap<Bytes, Option<Bytes>>,
+}
+
+impl WriteBatchWithIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn put(&mut self, key: &[u8], value: &[u8]) {
+        self.writes.insert(
+            Bytes::copy_from_slice(key),
+            Some(Bytes::copy_from_slice(value)),
+        );
+    }
+
+    pub fn delete(&mut self, key: &[u8]) {
+        self.writes.insert(Bytes::copy_from_slice(key), None);
+    }
+
+    /// The number of keys written by the batch.
+    pub fn len(&self) -> usize {
+        self.writes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.writes.clear();
+    }
+
+    /// The write staged for `key`: `Some(Some(value))` for a put, `Some(None)` for a delete, or `None` if the batch
+    /// does not write the key.
+    pub fn get_from_batch(&self, key: &[u8]) -> Option<Option<Bytes>> {
+        self.writes.get(key).cloned()
+    }
+
+    /// The value of `key` as if the batch were committed: the staged write if there is one, or the value in `storage`
+    /// otherwise.
+    pub fn get_from_batch_and_db(
+        &self,
+        storage: &MiniLsm,
+        key: &[u8],
+    ) -> lsm_error::Result<Option<Bytes>> {
+        match self.get_from_batch(key) {
+            Some(value) => Ok(value),
+            None => storage.get(key),
+        }
+    }
+
+    /// The staged writes in key order.
+    pub fn records(&self) -> Vec<WriteBatchRecord<&[u8]>> {
+        self.writes
+            .iter()
+            .map(|(key, value)| match value {
+                Some(value) => WriteBatchRecord::Put(key.as_ref(), value.as_ref()),
+                None => WriteBatchRecord::Del(key.as_ref()),
+            })
+            .collect()
+    }
+}
+
+impl MiniLsm {
+    /// Commit the writes staged in `batch` atomically. The batch is left as is, e.g., to be cleared and reused.
+    pub fn write_indexed_batch(&self, batch: &WriteBatchWithIndex) -> lsm_error::Result<()> {
+        self.write_batch(&batch.records())
+    }
+}
@@ -17,6 +17,23 @@ use crate::lsm_storage::{LsmStorageInner, MiniLsm};
 impl LsmStorageInner {
     pub fn dump_structure(&self) {
         let snapshot = self.state.read();
+        println!(
+            "Memtable: {} ({} bytes)",
+            snapshot.memtable.id(),
+            snapshot.memtable.approximate_size()
+        );
+        if !snapshot.imm_memtables.is_empty() {
+            let imm_memtables = snapshot
+                .imm_memtables
+                .iter()
+                .map(|memtable| (memtable.id(), memtable.approximate_size()))
+                .collect::<Vec<_>>();
+            println!(
+                "Immutable memtables ({}): {:?}",
+                imm_memtables.len(),
+                imm_memtables,
+            );
+        }
         if !snapshot.l0_sstables.is_empty() {
             println!(
                 "L0 ({}): {:?}",
@@ -14,10 +14,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{
-    collections::BTreeMap, ops::Bound, os::unix::fs::MetadataExt, path::Path, sync::Arc,
-    time::Duration,
-};
+use std::{collections::BTreeMap, ops::Bound, path::Path, sync::Arc, time::Duration};
 
 use anyhow::{Result, bail};
 use bytes::Bytes;
@@ -325,7 +322,6 @@ pub fn check_compaction_ratio(storage: Arc<MiniLsm>) {
         .num_active_iterators();
     let num_memtables = storage.inner.state.read().imm_memtables.len() + 1;
     match compaction_options {
-        CompactionOptions::NoCompaction => unreachable!(),
         CompactionOptions::Simple(SimpleLeveledCompactionOptions {
             size_ratio_percent,
             level0_file_num_compaction_trigger,
@@ -425,6 +421,7 @@ pub fn check_compaction_ratio(storage: Arc<MiniLsm>) {
                 "we found {num_iters} iterators in your implementation, (num_memtables={num_memtables}, num_tiers={num_tiers}) did you use concat iterators?"
             );
         }
+        _ => unreachable!(),
     }
 }
 
@@ -435,7 +432,7 @@ pub fn dump_files_in_dir(path: impl AsRef<Path>) {
         print!("{}", f.path().display());
         println!(
             ", size={:.3}KB",
-            f.metadata().unwrap().size() as f64 / 1024.0
+            f.metadata().unwrap().len() as f64 / 1024.0
         );
     }
 }
@@ -92,6 +92,56 @@ fn check() -> Result<()> {
     Ok(())
 }
 
+fn check_no_std() -> Result<()> {
+    println!(
+        "{}",
+        style("cargo check (mini-lsm-mvcc without std)").bold()
+    );
+    cmd!(
+        "cargo",
+        "check",
+        "-p",
+        "mini-lsm-mvcc",
+        "--lib",
+        "--no-default-features"
+    )
+    .run()?;
+    Ok(())
+}
+
+fn check_wasm() -> Result<()> {
+    println!(
+        "{}",
+        style("cargo build (mini-lsm-mvcc for wasm32 without std)").bold()
+    );
+    cmd!(
+        "cargo",
+        "build",
+        "-p",
+        "mini-lsm-mvcc",
+        "--lib",
+        "--target",
+        "wasm32-unknown-unknown",
+        "--no-default-features"
+    )
+    .run()?;
+    println!(
+        "{}",
+        style("cargo build (mini-lsm-mvcc for wasm32-wasip1)").bold()
+    );
+    cmd!(
+        "cargo",
+        "build",
+        "-p",
+        "mini-lsm-mvcc",
+        "--lib",
+        "--target",
+        "wasm32-wasip1"
+    )
+    .run()?;
+    Ok(())
+}
+
 fn test() -> Result<()> {
     println!("{}", style("cargo nextest run").bold());
     cmd!("cargo", "nextest", "run").run()?;
@@ -223,6 +273,8 @@ fn main() -> Result<()> {
             switch_to_workspace_root()?;
             check_fmt()?;
             check()?;
+            check_no_std()?;
+            check_wasm()?;
             test()?;
             clippy()?;
             build_book()?;